@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use gameroy::consts::CLOCK_SPEED;
+use gameroy::gameboy::accuracy::AccuracyConfig;
 use gameroy::gameboy::cartridge::Cartridge;
 use gameroy::gameboy::GameBoy;
 use gameroy::interpreter::Interpreter;
@@ -7,7 +8,7 @@ use gameroy::interpreter::Interpreter;
 fn criterion_benchmark(c: &mut Criterion) {
     let rom = std::fs::read("../roms/Kirby's Dream Land (USA, Europe).gb").unwrap();
     let cartridge = Cartridge::new(rom).unwrap();
-    let mut gb = GameBoy::new(None, cartridge);
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
 
     let save_state = {
         let mut save_state = Vec::new();
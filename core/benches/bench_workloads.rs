@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use gameroy::interpreter::Interpreter;
+
+mod workloads;
+
+/// The number of frames emulated in each iteration, one second of emulated time.
+const FRAMES: u64 = 60;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let roms = [
+        ("cpu mix 1s", workloads::cpu_rom()),
+        ("ppu heavy 1s", workloads::ppu_rom()),
+        ("apu heavy 1s", workloads::apu_rom()),
+    ];
+    for (name, rom) in roms {
+        let mut gb = workloads::game_boy(rom);
+        let mut save_state = Vec::new();
+        gb.save_state(None, &mut save_state).unwrap();
+
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                gb.load_state(&mut save_state.as_slice()).unwrap();
+                workloads::run_frames(&mut gb, FRAMES, |gb| Interpreter(gb).interpret_op());
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,177 @@
+//! Synthetic workloads for the benchmarks, built in code so they don't depend on external roms.
+//! Also used by the benchmarks of the JIT compiler.
+
+#![allow(dead_code)]
+
+use gameroy::{
+    consts::FRAME_CYCLES,
+    gameboy::{
+        accuracy::AccuracyConfig,
+        cartridge::{Cartridge, CartridgeHeader, RomValidation},
+        GameBoy,
+    },
+    interpreter::Interpreter,
+};
+
+/// A rom that jumps from the entry point to `program`, placed at 0x150.
+fn rom_with(program: &[u8]) -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let entry = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&entry);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+
+    rom[0x14D] = CartridgeHeader::compute_check_sum(&rom);
+    let checksum = RomValidation::compute_global_checksum(&rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+    rom
+}
+
+/// A mix of loads, arithmetic, bit operations, jumps, calls and stack operations, over the WRAM.
+pub fn cpu_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0xC0, // 0x150: ld hl, 0xC000
+        0x06, 0x00,       // 0x153: ld b, 0x00
+        0x78,             // 0x155: ld a, b
+        0x86,             // 0x156: add a, (hl)
+        0x22,             // 0x157: ld (hl+), a
+        0xCB, 0x37,       // 0x158: swap a
+        0xA8,             // 0x15A: xor b
+        0x4F,             // 0x15B: ld c, a
+        0xCD, 0x70, 0x01, // 0x15C: call 0x170
+        0x05,             // 0x15F: dec b
+        0x20, 0xF3,       // 0x160: jr nz, 0x155
+        0x7C,             // 0x162: ld a, h
+        0xFE, 0xD0,       // 0x163: cp 0xD0
+        0x20, 0xEC,       // 0x165: jr nz, 0x153
+        0xC3, 0x50, 0x01, // 0x167: jp 0x150
+        0x76, 0x76, 0x76, // 0x16A: halt (unused)
+        0x76, 0x76, 0x76, // 0x16D: halt (unused)
+        0x00,             // 0x16F: nop (unused)
+        0xC5,             // 0x170: push bc
+        0x79,             // 0x171: ld a, c
+        0x17,             // 0x172: rla
+        0x81,             // 0x173: add a, c
+        0xC1,             // 0x174: pop bc
+        0xC9,             // 0x175: ret
+    ];
+    rom_with(&program)
+}
+
+/// Renders the background, the window and 40 sprites, while changing SCX at each scanline.
+pub fn ppu_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF0, 0x44,       // 0x150: ldh a, (0x44)
+        0xFE, 0x90,       // 0x152: cp 0x90
+        0x20, 0xFA,       // 0x154: jr nz, 0x150
+        0xAF,             // 0x156: xor a
+        0xE0, 0x40,       // 0x157: ldh (0x40), a
+        0x21, 0x00, 0xFE, // 0x159: ld hl, 0xFE00
+        0x0E, 0x28,       // 0x15C: ld c, 40
+        0x79,             // 0x15E: ld a, c
+        0x87,             // 0x15F: add a, a
+        0x87,             // 0x160: add a, a
+        0x22,             // 0x161: ld (hl+), a
+        0x79,             // 0x162: ld a, c
+        0x87,             // 0x163: add a, a
+        0x87,             // 0x164: add a, a
+        0x87,             // 0x165: add a, a
+        0x22,             // 0x166: ld (hl+), a
+        0x71,             // 0x167: ld (hl), c
+        0x23,             // 0x168: inc hl
+        0x79,             // 0x169: ld a, c
+        0xE6, 0x30,       // 0x16A: and 0x30
+        0x22,             // 0x16C: ld (hl+), a
+        0x0D,             // 0x16D: dec c
+        0x20, 0xEE,       // 0x16E: jr nz, 0x15E
+        0x21, 0x00, 0x80, // 0x170: ld hl, 0x8000
+        0x7D,             // 0x173: ld a, l
+        0xAC,             // 0x174: xor h
+        0x22,             // 0x175: ld (hl+), a
+        0x7C,             // 0x176: ld a, h
+        0xFE, 0xA0,       // 0x177: cp 0xA0
+        0x20, 0xF8,       // 0x179: jr nz, 0x173
+        0x3E, 0x40,       // 0x17B: ld a, 0x40
+        0xE0, 0x4A,       // 0x17D: ldh (0x4A), a
+        0x3E, 0x50,       // 0x17F: ld a, 0x50
+        0xE0, 0x4B,       // 0x181: ldh (0x4B), a
+        0x3E, 0xF3,       // 0x183: ld a, 0xF3
+        0xE0, 0x40,       // 0x185: ldh (0x40), a
+        0xF0, 0x44,       // 0x187: ldh a, (0x44)
+        0xE0, 0x43,       // 0x189: ldh (0x43), a
+        0x18, 0xFA,       // 0x18B: jr 0x187
+    ];
+    rom_with(&program)
+}
+
+/// Plays all four sound channels, retriggering them with a new frequency every few hundred
+/// cycles.
+pub fn apu_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x80,       // 0x150: ld a, 0x80
+        0xE0, 0x26,       // 0x152: ldh (0x26), a
+        0x3E, 0x77,       // 0x154: ld a, 0x77
+        0xE0, 0x24,       // 0x156: ldh (0x24), a
+        0x3E, 0xFF,       // 0x158: ld a, 0xFF
+        0xE0, 0x25,       // 0x15A: ldh (0x25), a
+        0x3E, 0xF0,       // 0x15C: ld a, 0xF0
+        0xE0, 0x12,       // 0x15E: ldh (0x12), a
+        0xE0, 0x17,       // 0x160: ldh (0x17), a
+        0xE0, 0x21,       // 0x162: ldh (0x21), a
+        0x3E, 0x80,       // 0x164: ld a, 0x80
+        0xE0, 0x1A,       // 0x166: ldh (0x1A), a
+        0x3E, 0x20,       // 0x168: ld a, 0x20
+        0xE0, 0x1C,       // 0x16A: ldh (0x1C), a
+        0x21, 0x30, 0xFF, // 0x16C: ld hl, 0xFF30
+        0x7D,             // 0x16F: ld a, l
+        0x22,             // 0x170: ld (hl+), a
+        0xFE, 0x3F,       // 0x171: cp 0x3F
+        0x20, 0xFA,       // 0x173: jr nz, 0x16F
+        0x06, 0x00,       // 0x175: ld b, 0x00
+        0x78,             // 0x177: ld a, b
+        0xE0, 0x13,       // 0x178: ldh (0x13), a
+        0xE0, 0x18,       // 0x17A: ldh (0x18), a
+        0xE0, 0x1D,       // 0x17C: ldh (0x1D), a
+        0xE0, 0x22,       // 0x17E: ldh (0x22), a
+        0x3E, 0x87,       // 0x180: ld a, 0x87
+        0xE0, 0x14,       // 0x182: ldh (0x14), a
+        0xE0, 0x19,       // 0x184: ldh (0x19), a
+        0xE0, 0x1E,       // 0x186: ldh (0x1E), a
+        0xE0, 0x23,       // 0x188: ldh (0x23), a
+        0x0E, 0x40,       // 0x18A: ld c, 0x40
+        0x0D,             // 0x18C: dec c
+        0x20, 0xFD,       // 0x18D: jr nz, 0x18C
+        0x05,             // 0x18F: dec b
+        0x20, 0xE5,       // 0x190: jr nz, 0x177
+        0x18, 0xE1,       // 0x192: jr 0x175
+    ];
+    rom_with(&program)
+}
+
+/// Create a GameBoy with the rom, and run it for a few frames, past its setup.
+pub fn game_boy(rom: Vec<u8>) -> GameBoy {
+    let cartridge = Cartridge::new(rom).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    run_frames(&mut gb, 10, |gb| Interpreter(gb).interpret_op());
+    gb
+}
+
+/// Run the given number of frames, calling `step` until the end of each one, and then consuming
+/// the audio output, like the emulator does.
+pub fn run_frames(gb: &mut GameBoy, frames: u64, mut step: impl FnMut(&mut GameBoy)) {
+    for _ in 0..frames {
+        let target_clock = gb.clock_count + FRAME_CYCLES;
+        while gb.clock_count < target_clock {
+            step(gb);
+        }
+        let clock_count = gb.clock_count;
+        let _ = gb.sound.get_mut().get_output(clock_count);
+    }
+}
@@ -1,6 +1,6 @@
 use gameroy::{
     consts::CLOCK_SPEED,
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, boot_rom::BootRom, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 
@@ -43,12 +43,8 @@ fn main() {
     println!("Boot ROM: {}", boot_rom_path.as_deref().unwrap_or("None"));
 
     let rom = std::fs::read(rom_path.expect("No rom path provided")).unwrap();
-    let boot_rom = boot_rom_path.map(|path| {
-        std::fs::read(&path)
-            .unwrap()
-            .try_into()
-            .expect("Boot ROM must be 256 bytes")
-    });
+    let boot_rom = boot_rom_path
+        .map(|path| BootRom::new(std::fs::read(&path).unwrap()).expect("Invalid Boot ROM"));
 
     let cartridge = Cartridge::new(rom).expect("Invalid ROM");
 
@@ -58,7 +54,7 @@ fn main() {
         cartridge.header.cartridge_type
     );
 
-    let mut gameboy = GameBoy::new(boot_rom, cartridge);
+    let mut gameboy = GameBoy::new(boot_rom, cartridge, AccuracyConfig::default());
 
     let mut inter = Interpreter(&mut gameboy);
 
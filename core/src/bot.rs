@@ -0,0 +1,126 @@
+//! Search for the input sequences that reach a goal, for tool-assisted speedruns ("botting").
+//!
+//! A candidate is a sequence of joypad states, one per frame, with the layout of `GameBoy::joypad`
+//! (where a pressed button is a 0 bit). Each candidate is run from the same save state, and then
+//! scored by a callback that inspects the GameBoy, for example reading the memory, so the best
+//! candidate can be chosen. With the `std` feature the candidates can be run in parallel.
+
+use alloc::vec::Vec;
+
+use crate::{
+    consts::FRAME_CYCLES, gameboy::GameBoy, interpreter::Interpreter, save_state::LoadStateError,
+};
+
+/// Run one frame for each joypad state in `inputs`, holding the buttons for the entire frame.
+pub fn run_inputs(gb: &mut GameBoy, inputs: &[u8]) {
+    for &joypad in inputs {
+        gb.joypad = joypad;
+        let target = gb.clock_count + FRAME_CYCLES;
+        while gb.clock_count < target {
+            Interpreter(gb).interpret_op();
+        }
+    }
+}
+
+/// Load `state` in `gb`, run `inputs` and return the score given by `score` to the resulting
+/// state.
+pub fn evaluate<S>(
+    gb: &mut GameBoy,
+    state: &[u8],
+    inputs: &[u8],
+    score: impl FnOnce(&GameBoy) -> S,
+) -> Result<S, LoadStateError> {
+    gb.load_state(&mut &state[..])?;
+    run_inputs(gb, inputs);
+    Ok(score(gb))
+}
+
+/// Evaluate each candidate from `state`, in `threads` threads, and return their scores, in the
+/// order of the candidates.
+///
+/// Each thread runs its own GameBoy, created by `new_game_boy`, which must have the same cartridge
+/// and configuration of the GameBoy that saved `state`.
+#[cfg(feature = "std")]
+pub fn evaluate_all<C, S>(
+    new_game_boy: impl Fn() -> GameBoy + Sync,
+    state: &[u8],
+    candidates: &[C],
+    threads: usize,
+    score: impl Fn(&GameBoy) -> S + Sync,
+) -> Result<Vec<S>, LoadStateError>
+where
+    C: AsRef<[u8]> + Sync,
+    S: Send,
+{
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let next = AtomicUsize::new(0);
+    let worker = || -> Result<Vec<(usize, S)>, LoadStateError> {
+        let mut gb = new_game_boy();
+        let mut scores = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(inputs) = candidates.get(i) else {
+                break Ok(scores);
+            };
+            scores.push((i, evaluate(&mut gb, state, inputs.as_ref(), &score)?));
+        }
+    };
+
+    let results: Vec<_> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..threads.clamp(1, candidates.len().max(1)))
+            .map(|_| s.spawn(worker))
+            .collect();
+        handles.into_iter().map(|x| x.join().unwrap()).collect()
+    });
+
+    let mut scores: Vec<Option<S>> = candidates.iter().map(|_| None).collect();
+    for result in results {
+        for (i, score) in result? {
+            scores[i] = Some(score);
+        }
+    }
+    Ok(scores.into_iter().map(|x| x.unwrap()).collect())
+}
+
+/// Evaluate each candidate, like `evaluate_all`, and return the index and the score of the one with
+/// the highest score, or the first of them in a tie. Returns `None` if there are no candidates.
+#[cfg(feature = "std")]
+pub fn best<C, S>(
+    new_game_boy: impl Fn() -> GameBoy + Sync,
+    state: &[u8],
+    candidates: &[C],
+    threads: usize,
+    score: impl Fn(&GameBoy) -> S + Sync,
+) -> Result<Option<(usize, S)>, LoadStateError>
+where
+    C: AsRef<[u8]> + Sync,
+    S: Ord + Send,
+{
+    let scores = evaluate_all(new_game_boy, state, candidates, threads, score)?;
+    Ok(scores
+        .into_iter()
+        .enumerate()
+        .rev()
+        .max_by(|(_, a), (_, b)| a.cmp(b)))
+}
+
+/// All sequences of `frames` joypad states where each frame is one of `choices`, in
+/// lexicographic order of the indices of the choices. There are `choices.len().pow(frames)` of
+/// them, so this is only feasible for a few frames, or few choices.
+pub fn combinations(choices: &[u8], frames: usize) -> Vec<Vec<u8>> {
+    let mut sequences = alloc::vec![Vec::new()];
+    for _ in 0..frames {
+        sequences = sequences
+            .into_iter()
+            .flat_map(|x| {
+                choices.iter().map(move |&c| {
+                    let mut x = x.clone();
+                    x.push(c);
+                    x
+                })
+            })
+            .collect();
+    }
+    sequences
+}
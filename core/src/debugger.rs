@@ -1,7 +1,18 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 use crate::save_state::SaveStateContext;
-use crate::{gameboy::GameBoy, interpreter::Interpreter, save_state::SaveState};
+use crate::{
+    consts::{SCANLINE_CYCLES, SCREEN_HEIGHT},
+    gameboy::{memory_lock::LockKind, GameBoy},
+    interpreter::Interpreter,
+    save_state::SaveState,
+};
+
+pub mod expression;
+pub mod session;
+pub mod state_diff;
+
+use expression::Expression;
 
 pub mod break_flags {
     pub const WRITE: u8 = 1 << 0;
@@ -10,21 +21,251 @@ pub mod break_flags {
     pub const JUMP: u8 = 1 << 3;
 }
 
+/// The maximum number of tracepoint messages kept in the trace log. Older ones are dropped.
+const TRACE_LOG_CAPACITY: usize = 1000;
+
+/// The number of bytes above SP checked by the stack guard for writes that are not pushes.
+const STACK_GUARD_WINDOW: u16 = 16;
+
+/// A CPU register that can be read by a tracepoint.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TraceRegister {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+impl TraceRegister {
+    fn parse(name: &str) -> Option<Self> {
+        use TraceRegister::*;
+        Some(match name {
+            "a" => A,
+            "f" => F,
+            "b" => B,
+            "c" => C,
+            "d" => D,
+            "e" => E,
+            "h" => H,
+            "l" => L,
+            "af" => AF,
+            "bc" => BC,
+            "de" => DE,
+            "hl" => HL,
+            "sp" => SP,
+            "pc" => PC,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        use TraceRegister::*;
+        match self {
+            A => "a",
+            F => "f",
+            B => "b",
+            C => "c",
+            D => "d",
+            E => "e",
+            H => "h",
+            L => "l",
+            AF => "af",
+            BC => "bc",
+            DE => "de",
+            HL => "hl",
+            SP => "sp",
+            PC => "pc",
+        }
+    }
+
+    fn is_16bit(self) -> bool {
+        use TraceRegister::*;
+        matches!(self, AF | BC | DE | HL | SP | PC)
+    }
+
+    fn read(self, gb: &GameBoy) -> u16 {
+        use TraceRegister::*;
+        let cpu = &gb.cpu;
+        match self {
+            A => cpu.a as u16,
+            F => cpu.f.0 as u16,
+            B => cpu.b as u16,
+            C => cpu.c as u16,
+            D => cpu.d as u16,
+            E => cpu.e as u16,
+            H => cpu.h as u16,
+            L => cpu.l as u16,
+            AF => cpu.af(),
+            BC => cpu.bc(),
+            DE => cpu.de(),
+            HL => cpu.hl(),
+            SP => cpu.sp,
+            PC => cpu.pc,
+        }
+    }
+}
+
+/// A part of the message of a tracepoint.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum TraceSegment {
+    /// Literal text.
+    Text(String),
+    /// The value of a register, in hexadecimal.
+    Register(TraceRegister),
+    /// The byte in memory at the given address, in hexadecimal.
+    Memory(u16),
+    /// The byte in memory at the address in the given 16-bit register, in hexadecimal.
+    MemoryAt(TraceRegister),
+    /// The current clock count, in decimal.
+    Clock,
+}
+
+/// Parse the message of a tracepoint. Each `{expr}` in the message is replaced by the value of
+/// `expr` when the tracepoint is hit, where `expr` is a register (`a`, `f`, `b`, `c`, `d`, `e`,
+/// `h`, `l`, `af`, `bc`, `de`, `hl`, `sp` or `pc`), `clock` for the clock count, or `[x]` for
+/// the byte in memory at `x`, which is a hexadecimal address or a 16-bit register.
+pub fn parse_trace_format(format: &str) -> Result<Vec<TraceSegment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            segments.push(TraceSegment::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{{' in '{}'", format))?
+            + start;
+        let expr = rest[start + 1..end].trim().to_ascii_lowercase();
+        let segment = if expr == "clock" {
+            TraceSegment::Clock
+        } else if let Some(reg) = TraceRegister::parse(&expr) {
+            TraceSegment::Register(reg)
+        } else if let Some(address) = expr.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            match TraceRegister::parse(address) {
+                Some(reg) if reg.is_16bit() => TraceSegment::MemoryAt(reg),
+                Some(_) => return Err(format!("'{}' is not a 16-bit register", address)),
+                None => match u16::from_str_radix(address, 16) {
+                    Ok(x) => TraceSegment::Memory(x),
+                    Err(_) => return Err(format!("'{}' is not a valid address", address)),
+                },
+            }
+        } else {
+            return Err(format!("'{}' is not a valid expression", expr));
+        };
+        segments.push(segment);
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(TraceSegment::Text(rest.to_string()));
+    }
+    Ok(segments)
+}
+
+/// Write the message of a tracepoint back in the format read by `parse_trace_format`.
+pub fn fmt_trace_format(segments: &[TraceSegment]) -> String {
+    let mut format = String::new();
+    for segment in segments {
+        match segment {
+            TraceSegment::Text(text) => format += text,
+            TraceSegment::Register(reg) => format += &format!("{{{}}}", reg.name()),
+            TraceSegment::Memory(address) => format += &format!("{{[{:04x}]}}", address),
+            TraceSegment::MemoryAt(reg) => format += &format!("{{[{}]}}", reg.name()),
+            TraceSegment::Clock => format += "{clock}",
+        }
+    }
+    format
+}
+
+/// Parse a debug message in the format of no$gmb and BGB. Each `%expr%` in the message is replaced
+/// by the value of `expr` when the message is printed, where `expr` is a register, `(reg)` for the
+/// byte in memory at the address in a 16-bit register, or `totalclks` for the clock count. Any
+/// other `%expr%` is kept as it is.
+pub fn parse_debug_message(message: &str) -> Vec<TraceSegment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find('%') {
+        text += &rest[..start];
+        let Some(len) = rest[start + 1..].find('%') else {
+            rest = &rest[start..];
+            break;
+        };
+        let expr = rest[start + 1..start + 1 + len].trim().to_ascii_lowercase();
+        let segment = if expr == "totalclks" {
+            Some(TraceSegment::Clock)
+        } else if let Some(address) = expr.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+            TraceRegister::parse(address)
+                .filter(|x| x.is_16bit())
+                .map(TraceSegment::MemoryAt)
+        } else {
+            TraceRegister::parse(&expr).map(TraceSegment::Register)
+        };
+        match segment {
+            Some(segment) => {
+                if !text.is_empty() {
+                    segments.push(TraceSegment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(segment);
+            }
+            None => text += &rest[start..start + len + 2],
+        }
+        rest = &rest[start + len + 2..];
+    }
+    text += rest;
+    if !text.is_empty() {
+        segments.push(TraceSegment::Text(text));
+    }
+    segments
+}
+
+/// Write the message of a tracepoint, evaluating its expressions in the current state of the
+/// GameBoy.
+pub fn format_trace(gb: &GameBoy, segments: &[TraceSegment]) -> String {
+    let mut message = String::new();
+    for segment in segments {
+        match *segment {
+            TraceSegment::Text(ref text) => message += text,
+            TraceSegment::Register(reg) if reg.is_16bit() => {
+                message += &format!("{:04x}", reg.read(gb))
+            }
+            TraceSegment::Register(reg) => message += &format!("{:02x}", reg.read(gb)),
+            TraceSegment::Memory(address) => message += &format!("{:02x}", gb.peek(address)),
+            TraceSegment::MemoryAt(reg) => message += &format!("{:02x}", gb.peek(reg.read(gb))),
+            TraceSegment::Clock => message += &gb.clock_count.to_string(),
+        }
+    }
+    message
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum RunResult {
     ReachBreakpoint,
     ReachTargetAddress,
     ReachTargetClock,
+    ReachTargetLine,
     TimeOut,
 }
 
 pub enum DebuggerEvent {
     Step,
     StepBack,
+    /// Go back to the last point where a breakpoint was triggered.
+    RunBack,
     Reset,
     Run,
     BreakpointsUpdate,
     WatchsUpdate,
+    /// The labels or the comments were changed.
+    LabelsUpdate,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -40,12 +281,47 @@ pub struct Debugger {
     execute_breakpoints: HashSet<u16>,
     /// Break if a interrupt is flagged and enabled.
     interrupt_breakpoint: bool,
+    /// Break before a write to the MBC registers, in 0000-7FFF.
+    mbc_breakpoint: bool,
+    /// Log the writes to the MBC registers in the trace log.
+    mbc_log: bool,
+    /// Break after a write to the VRAM or the OAM that was ignored, because the PPU was accessing
+    /// it.
+    blocked_write_breakpoint: bool,
+    /// Log the writes to the VRAM or the OAM that were ignored in the trace log.
+    blocked_write_log: bool,
+    /// Log the first access to each I/O register that is not emulated in the trace log.
+    io_log: bool,
+    /// Break immediately before a `ld b,b` opcode, used by homebrew as a software breakpoint.
+    source_breakpoint: bool,
+    /// Print the debug messages of homebrew, marked by a `ld d,d` opcode, in the trace log.
+    debug_message_log: bool,
+    /// A data region, from the first to the last address, that the stack must not grow into. If
+    /// set, also break before a write to the top of the stack that is not a push.
+    stack_guard: Option<(u16, u16)>,
+    /// The I/O registers already logged, by bit of their address in FF00-FF7F, for reads and for
+    /// writes.
+    io_logged: [u128; 2],
     breakpoints: BTreeMap<u16, u8>,
     watchs: BTreeSet<u16>,
+    /// Expressions shown in the watch panel, with the text they were parsed from.
+    watch_expressions: Vec<(String, Expression)>,
+    /// Messages printed, without stopping the emulation, immediately before executing an opcode
+    /// in the address.
+    tracepoints: BTreeMap<u16, Vec<TraceSegment>>,
+    /// Names given by the user to addresses, shown in the disassembly. They apply to the address
+    /// in any bank.
+    labels: BTreeMap<u16, String>,
+    /// Comments written by the user, shown after the instruction at the address, in any bank.
+    comments: BTreeMap<u16, String>,
+    /// The messages printed by tracepoints and by commands like `stats`, that were not taken yet.
+    trace_log: VecDeque<String>,
     /// Address to stop at
     pub target_address: Option<u16>,
     /// Clock to stop at
     pub target_clock: Option<u64>,
+    /// Scanline to stop at, immediately after LY changes to it.
+    pub target_ly: Option<u8>,
     /// The clock_count in the previous instruction, used for stepback.
     pub last_op_clock: Option<u64>,
     /// Callback called when self is mutated
@@ -54,6 +330,10 @@ pub struct Debugger {
     /// Used by StepBack, to ignore breakpoints between the last frame and the target clock.
     pub skip_breakpoints_until_target_clock: bool,
 }
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|x| format!("'{}': {}", path, x))
+}
+
 impl Debugger {
     pub fn execute_command(&mut self, gb: &GameBoy, args: &[&str]) -> Result<(), String> {
         use DebuggerEvent::*;
@@ -66,9 +346,11 @@ impl Debugger {
         };
         self.target_address = None;
         self.target_clock = None;
+        self.target_ly = None;
         match args[0] {
             "step" | "" => callback(self, Step),
             "stepback" => callback(self, StepBack),
+            "runback" => callback(self, RunBack),
 
             "reset" => callback(self, Reset),
             "runto" => {
@@ -90,6 +372,50 @@ impl Debugger {
                 self.target_address = Some(address);
                 callback(self, Run);
             }
+            "runline" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'runline' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let ly = match args[1].parse::<u8>() {
+                    Ok(x) if x <= 153 => x,
+                    _ => {
+                        return Err(format!(
+                            "'runline' expected a scanline from 0 to 153, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                self.target_ly = Some(ly);
+                callback(self, Run);
+            }
+            "runclock" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'runclock' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let clock = match args[1].parse::<u64>() {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'runclock' expected a clock count, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                if clock <= gb.clock_count {
+                    return Err(format!(
+                        "the clock count is already {}, past {}",
+                        gb.clock_count, clock
+                    ));
+                }
+                self.target_clock = Some(clock);
+                callback(self, Run);
+            }
             "run" => {
                 if args.len() == 1 {
                     callback(self, Run);
@@ -128,10 +454,50 @@ impl Debugger {
             }
             "break" => {
                 if args.len() == 2 {
-                    if let "interrupt" = args[1] {
-                        self.interrupt_breakpoint = true;
-                        return Ok(());
+                    match args[1] {
+                        "interrupt" => {
+                            self.interrupt_breakpoint = true;
+                            return Ok(());
+                        }
+                        "mbc" => {
+                            self.mbc_breakpoint = true;
+                            return Ok(());
+                        }
+                        "blocked" => {
+                            self.blocked_write_breakpoint = true;
+                            return Ok(());
+                        }
+                        "source" => {
+                            self.source_breakpoint = true;
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+                }
+                if args.get(1) == Some(&"stack") {
+                    let [_, _, start, end] = args else {
+                        return Err(
+                            "'break stack' expect the first and the last address of a region"
+                                .to_string(),
+                        );
+                    };
+                    let parse = |x: &str| {
+                        u16::from_str_radix(x, 16).map_err(|_| {
+                            format!(
+                                "'break stack' expected a address, '{}' is not a valid one",
+                                x
+                            )
+                        })
+                    };
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start > end {
+                        return Err(format!(
+                            "'break stack' expected a region, but {:04x} is after {:04x}",
+                            start, end
+                        ));
                     }
+                    self.stack_guard = Some((start, end));
+                    return Ok(());
                 }
                 if args.len() != 3 {
                     return Err(format!(
@@ -168,25 +534,343 @@ impl Debugger {
 
                 self.add_break(flags, address);
             }
+            "unbreak" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'unbreak' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                match args[1] {
+                    "interrupt" => self.interrupt_breakpoint = false,
+                    "mbc" => self.mbc_breakpoint = false,
+                    "blocked" => self.blocked_write_breakpoint = false,
+                    "source" => self.source_breakpoint = false,
+                    "stack" => self.stack_guard = None,
+                    arg => {
+                        let address = match u16::from_str_radix(arg, 16) {
+                            Ok(x) => x,
+                            Err(_) => {
+                                return Err(format!(
+                                    "'unbreak' expected a address, '{}' is not a valid one",
+                                    arg
+                                ))
+                            }
+                        };
+                        self.remove_break(address);
+                    }
+                }
+            }
             "watch" => {
+                if args.len() < 2 {
+                    return Err("'watch' expect a address or a expression".to_string());
+                }
+
+                match u16::from_str_radix(args[1], 16) {
+                    Ok(address) if args.len() == 2 => self.add_watch(address),
+                    _ => self.add_watch_expression(&args[1..].join(" "))?,
+                }
+            }
+            "unwatch" => {
+                if args.len() < 2 {
+                    return Err("'unwatch' expect a address or a expression".to_string());
+                }
+
+                let text = args[1..].join(" ");
+                let index = self.watch_expressions.iter().position(|(x, _)| *x == text);
+                match (u16::from_str_radix(&text, 16), index) {
+                    (_, Some(index)) => self.remove_watch_expression(index),
+                    (Ok(address), None) if self.watchs.contains(&address) => {
+                        self.remove_watch(address)
+                    }
+                    _ => return Err(format!("'{}' is not being watched", text)),
+                }
+            }
+            "trace" => {
+                if args.len() == 2 && args[1] == "mbc" {
+                    self.mbc_log = true;
+                    return Ok(());
+                }
+                if args.len() == 2 && args[1] == "io" {
+                    self.io_log = true;
+                    self.io_logged = [0; 2];
+                    return Ok(());
+                }
+                if args.len() == 2 && args[1] == "blocked" {
+                    self.blocked_write_log = true;
+                    return Ok(());
+                }
+                if args.len() == 2 && args[1] == "debug" {
+                    self.debug_message_log = true;
+                    return Ok(());
+                }
+                if args.len() < 3 {
+                    return Err(format!(
+                        "'trace' expect at least 2 arguments, receive {}",
+                        args.len() - 1
+                    ));
+                }
+
+                let address = match u16::from_str_radix(args[1], 16) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'trace' expected a address, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                let segments = parse_trace_format(&args[2..].join(" "))?;
+
+                self.add_tracepoint(address, segments);
+            }
+            "untrace" => {
                 if args.len() != 2 {
                     return Err(format!(
-                        "'watch' expect 1 argument, receive {}",
+                        "'untrace' expect 1 argument, receive {}",
                         args.len() - 1
                     ));
                 }
+                if args[1] == "mbc" {
+                    self.mbc_log = false;
+                    return Ok(());
+                }
+                if args[1] == "io" {
+                    self.io_log = false;
+                    return Ok(());
+                }
+                if args[1] == "blocked" {
+                    self.blocked_write_log = false;
+                    return Ok(());
+                }
+                if args[1] == "debug" {
+                    self.debug_message_log = false;
+                    return Ok(());
+                }
 
                 let address = match u16::from_str_radix(args[1], 16) {
                     Ok(x) => x,
                     Err(_) => {
                         return Err(format!(
-                            "'watch' expected a address, '{}' is not a valid one",
+                            "'untrace' expected a address, '{}' is not a valid one",
                             args[1]
                         ))
                     }
                 };
 
-                self.add_watch(address);
+                self.remove_tracepoint(address);
+            }
+            "label" => {
+                if args.len() != 3 {
+                    return Err(format!(
+                        "'label' expect 2 arguments, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let address = match u16::from_str_radix(args[1], 16) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'label' expected a address, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                // a label that is also a number would be ambiguous in the commands.
+                if u16::from_str_radix(args[2], 16).is_ok() {
+                    return Err(format!("'{}' is a number, not a valid label", args[2]));
+                }
+                self.add_label(address, args[2].to_string());
+            }
+            "unlabel" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'unlabel' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let address = match u16::from_str_radix(args[1], 16) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'unlabel' expected a address, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                self.remove_label(address);
+            }
+            "comment" => {
+                if args.len() < 3 {
+                    return Err(format!(
+                        "'comment' expect at least 2 arguments, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let address = match u16::from_str_radix(args[1], 16) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'comment' expected a address, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                self.add_comment(address, args[2..].join(" "));
+            }
+            "uncomment" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'uncomment' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let address = match u16::from_str_radix(args[1], 16) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(format!(
+                            "'uncomment' expected a address, '{}' is not a valid one",
+                            args[1]
+                        ))
+                    }
+                };
+                self.remove_comment(address);
+            }
+            "stats" => match args.get(1..) {
+                Some([]) => {
+                    let report = gb.counters.get().report(gb.clock_count);
+                    for line in report.lines() {
+                        self.push_log(line.to_string());
+                    }
+                }
+                Some(["reset"]) => {
+                    let mut counters = gb.counters.get();
+                    counters.reset(gb.clock_count);
+                    gb.counters.set(counters);
+                }
+                _ => {
+                    return Err(format!(
+                        "'stats' expect 0 arguments or 'reset', receive '{}'",
+                        args[1..].join(" ")
+                    ))
+                }
+            },
+            "modes" => {
+                let lines = match args[1..] {
+                    [] => 0..SCREEN_HEIGHT as u8,
+                    [ly] => match ly.parse::<u8>() {
+                        Ok(x) if (x as usize) < SCREEN_HEIGHT => x..x + 1,
+                        _ => {
+                            return Err(format!(
+                            "'modes' expected a scanline from 0 to 143, '{}' is not a valid one",
+                            ly
+                        ))
+                        }
+                    },
+                    _ => {
+                        return Err(format!(
+                            "'modes' expect 0 or 1 arguments, receive {}",
+                            args.len() - 1
+                        ))
+                    }
+                };
+                gb.update_ppu();
+                let ppu = gb.ppu.borrow();
+                // group consecutive scanlines with the same timing.
+                let mut groups: Vec<(u8, u8, Option<u16>)> = Vec::new();
+                for ly in lines {
+                    let length = ppu.mode_3_length(ly);
+                    match groups.last_mut() {
+                        Some((_, last, x)) if *x == length => *last = ly,
+                        _ => groups.push((ly, ly, length)),
+                    }
+                }
+                drop(ppu);
+                for (first, last, length) in groups {
+                    let lines = if first == last {
+                        format!("ly {}", first)
+                    } else {
+                        format!("ly {}-{}", first, last)
+                    };
+                    self.push_log(match length {
+                        Some(x) => format!(
+                            "{}: mode 2 80, mode 3 {}, mode 0 {} cycles",
+                            lines,
+                            x,
+                            SCANLINE_CYCLES - 80 - x as u64
+                        ),
+                        None => format!("{}: not traced", lines),
+                    });
+                }
+            }
+            "freeze" | "protect" => {
+                let cmd = args[0];
+                let kind = if cmd == "freeze" {
+                    LockKind::Freeze
+                } else {
+                    LockKind::Protect
+                };
+                let parse = |x: &str| {
+                    u16::from_str_radix(x, 16).map_err(|_| {
+                        format!("'{}' expected a address, '{}' is not a valid one", cmd, x)
+                    })
+                };
+                let (start, end) = match args[1..] {
+                    [] => {
+                        let locks = gb.memory_locks.borrow().clone();
+                        for &(start, end, k) in locks.ranges() {
+                            if k == kind {
+                                self.push_log(format!("{} {:04x}-{:04x}", cmd, start, end));
+                            }
+                        }
+                        return Ok(());
+                    }
+                    [address] => (parse(address)?, parse(address)?),
+                    [start, end] => (parse(start)?, parse(end)?),
+                    _ => {
+                        return Err(format!(
+                            "'{}' expect the first and the last address of a region",
+                            cmd
+                        ))
+                    }
+                };
+                if start > end {
+                    return Err(format!(
+                        "'{}' expected a region, but {:04x} is after {:04x}",
+                        cmd, start, end
+                    ));
+                }
+                gb.memory_locks.borrow_mut().add(kind, start, end);
+            }
+            "unfreeze" | "unprotect" => {
+                let cmd = args[0];
+                let (kind, name) = if cmd == "unfreeze" {
+                    (LockKind::Freeze, "frozen")
+                } else {
+                    (LockKind::Protect, "protected")
+                };
+                let address = match args[1..] {
+                    [] => None,
+                    [address] => Some(u16::from_str_radix(address, 16).map_err(|_| {
+                        format!(
+                            "'{}' expected a address, '{}' is not a valid one",
+                            cmd, address
+                        )
+                    })?),
+                    _ => {
+                        return Err(format!(
+                            "'{}' expect 0 or 1 arguments, receive {}",
+                            cmd,
+                            args.len() - 1
+                        ))
+                    }
+                };
+                if !gb.memory_locks.borrow_mut().remove(kind, address) {
+                    return Err(match address {
+                        Some(x) => format!("'{:04x}' is not in a {} region", x, name),
+                        None => format!("there is no {} region", name),
+                    });
+                }
             }
             "echo" => println!("{}", args[1..].join(" ")),
             // write the currently dissasembly to a file
@@ -203,6 +887,42 @@ impl Debugger {
                 trace.fmt(gb, &mut string).map_err(|x| x.to_string())?;
                 std::fs::write(file, string).map_err(|x| x.to_string())?;
             }
+            "statediff" => {
+                let (before, after) = match args[1..] {
+                    [before] => {
+                        let mut after = Vec::new();
+                        gb.save_state(None, &mut after).map_err(|x| x.to_string())?;
+                        (read_file(before)?, after)
+                    }
+                    [before, after] => (read_file(before)?, read_file(after)?),
+                    _ => {
+                        return Err(format!(
+                            "'statediff' expect 1 or 2 save state files, receive {} arguments",
+                            args.len() - 1
+                        ))
+                    }
+                };
+                let diff = state_diff::diff_states(gb, &before, &after)
+                    .map_err(|x| format!("failed to load save state: {:?}", x))?;
+                for line in diff.to_string().lines() {
+                    self.push_log(line.to_string());
+                }
+            }
+            "callgraph" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'callgraph' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let file = args[1];
+                let trace = gb.trace.borrow();
+                let mut string = String::new();
+                trace
+                    .fmt_call_graph(&mut string)
+                    .map_err(|x| x.to_string())?;
+                std::fs::write(file, string).map_err(|x| x.to_string())?;
+            }
             // save some state to a file (for dev purposes)
             "save" => {
                 if args.len() != 2 {
@@ -331,12 +1051,307 @@ impl Debugger {
         self.callback = take;
     }
 
+    pub fn watch_expressions(&self) -> &[(String, Expression)] {
+        &self.watch_expressions
+    }
+
+    /// Parse the expression and add it to the watch panel.
+    pub fn add_watch_expression(&mut self, text: &str) -> Result<(), String> {
+        let expr = Expression::parse(text)?;
+        self.watch_expressions.push((text.trim().to_string(), expr));
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::WatchsUpdate)
+        }
+        self.callback = take;
+        Ok(())
+    }
+
+    pub fn remove_watch_expression(&mut self, index: usize) {
+        self.watch_expressions.remove(index);
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::WatchsUpdate)
+        }
+        self.callback = take;
+    }
+
+    pub fn tracepoints(&self) -> &BTreeMap<u16, Vec<TraceSegment>> {
+        &self.tracepoints
+    }
+
+    pub fn add_tracepoint(&mut self, address: u16, segments: Vec<TraceSegment>) {
+        self.tracepoints.insert(address, segments);
+    }
+
+    pub fn remove_tracepoint(&mut self, address: u16) {
+        self.tracepoints.remove(&address);
+    }
+
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    /// Name the address, replacing its previous label, if any.
+    pub fn add_label(&mut self, address: u16, name: String) {
+        self.labels.insert(address, name);
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::LabelsUpdate)
+        }
+        self.callback = take;
+    }
+
+    pub fn remove_label(&mut self, address: u16) {
+        self.labels.remove(&address);
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::LabelsUpdate)
+        }
+        self.callback = take;
+    }
+
+    pub fn comments(&self) -> &BTreeMap<u16, String> {
+        &self.comments
+    }
+
+    /// Comment the address, replacing its previous comment, if any.
+    pub fn add_comment(&mut self, address: u16, text: String) {
+        self.comments.insert(address, text);
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::LabelsUpdate)
+        }
+        self.callback = take;
+    }
+
+    pub fn remove_comment(&mut self, address: u16) {
+        self.comments.remove(&address);
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::LabelsUpdate)
+        }
+        self.callback = take;
+    }
+
+    /// The commands that recreate the breakpoints, the watches, the tracepoints, the labels and
+    /// the comments of the debugger, for saving them in a `session::Session`.
+    pub fn session_commands(&self) -> Vec<String> {
+        use break_flags::*;
+        let mut commands = Vec::new();
+        let switches = [
+            (self.interrupt_breakpoint, "break interrupt"),
+            (self.mbc_breakpoint, "break mbc"),
+            (self.blocked_write_breakpoint, "break blocked"),
+            (self.source_breakpoint, "break source"),
+            (self.mbc_log, "trace mbc"),
+            (self.io_log, "trace io"),
+            (self.blocked_write_log, "trace blocked"),
+            (self.debug_message_log, "trace debug"),
+        ];
+        for (enabled, command) in switches {
+            if enabled {
+                commands.push(command.to_string());
+            }
+        }
+        if let Some((start, end)) = self.stack_guard {
+            commands.push(format!("break stack {:04x} {:04x}", start, end));
+        }
+        for (&address, &flags) in &self.breakpoints {
+            let flags: String = [(WRITE, 'w'), (READ, 'r'), (EXECUTE, 'x'), (JUMP, 'j')]
+                .iter()
+                .filter(|(flag, _)| flags & flag != 0)
+                .map(|(_, c)| c)
+                .collect();
+            commands.push(format!("break {} {:04x}", flags, address));
+        }
+        for address in &self.watchs {
+            commands.push(format!("watch {:04x}", address));
+        }
+        for (text, _) in &self.watch_expressions {
+            commands.push(format!("watch {}", text));
+        }
+        for (address, segments) in &self.tracepoints {
+            commands.push(format!(
+                "trace {:04x} {}",
+                address,
+                fmt_trace_format(segments)
+            ));
+        }
+        for (address, name) in &self.labels {
+            commands.push(format!("label {:04x} {}", address, name));
+        }
+        for (address, text) in &self.comments {
+            commands.push(format!("comment {:04x} {}", address, text));
+        }
+        commands
+    }
+
+    /// Replace the breakpoints, the watches, the tracepoints, the labels and the comments by the
+    /// ones created by `commands`, as returned by `session_commands`. Only the commands that
+    /// create them are accepted. Every valid command is executed, even if others fail, and the
+    /// errors are returned together.
+    pub fn restore_session(&mut self, gb: &GameBoy, commands: &[String]) -> Result<(), String> {
+        self.write_breakpoints.clear();
+        self.read_breakpoints.clear();
+        self.jump_breakpoints.clear();
+        self.execute_breakpoints.clear();
+        self.breakpoints.clear();
+        self.interrupt_breakpoint = false;
+        self.mbc_breakpoint = false;
+        self.blocked_write_breakpoint = false;
+        self.source_breakpoint = false;
+        self.mbc_log = false;
+        self.io_log = false;
+        self.blocked_write_log = false;
+        self.debug_message_log = false;
+        self.watchs.clear();
+        self.watch_expressions.clear();
+        self.tracepoints.clear();
+        self.labels.clear();
+        self.comments.clear();
+
+        let mut errors = Vec::new();
+        for command in commands {
+            let args: Vec<&str> = command.split_whitespace().collect();
+            let result = match args.first() {
+                Some(&("break" | "watch" | "trace" | "label" | "comment")) => {
+                    self.execute_command(gb, &args)
+                }
+                _ => Err(format!("'{}' is not allowed in a session", command)),
+            };
+            if let Err(err) = result {
+                errors.push(err);
+            }
+        }
+
+        let mut take = self.callback.take();
+        if let Some(x) = take.as_mut() {
+            x(self, DebuggerEvent::BreakpointsUpdate);
+            x(self, DebuggerEvent::WatchsUpdate);
+            x(self, DebuggerEvent::LabelsUpdate);
+        }
+        self.callback = take;
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Take all messages printed by tracepoints and commands since the last call.
+    pub fn take_trace_log(&mut self) -> Vec<String> {
+        self.trace_log.drain(..).collect()
+    }
+
+    /// Add a message to the trace log, dropping the oldest one if it is full.
+    fn push_log(&mut self, message: String) {
+        if self.trace_log.len() >= TRACE_LOG_CAPACITY {
+            self.trace_log.pop_front();
+        }
+        self.trace_log.push_back(message);
+    }
+
+    /// Print the message of the tracepoint at the current PC, if any.
+    fn check_tracepoint(&mut self, gb: &GameBoy) {
+        let Some(segments) = self.tracepoints.get(&gb.cpu.pc) else {
+            return;
+        };
+        self.push_log(format_trace(gb, segments));
+    }
+
+    /// Print the debug message at the current PC, if any. A message is a `ld d,d` followed by a
+    /// `jr` over the `64 64 00 00` signature and the text of the message.
+    fn check_debug_message(&mut self, gb: &GameBoy) {
+        let pc = gb.cpu.pc;
+        let read = |offset: u16| gb.peek(pc.wrapping_add(offset));
+        if read(0) != 0x52 || read(1) != 0x18 {
+            return;
+        }
+        let len = read(2) as i8;
+        if len < 4 || (3..7).map(read).ne([0x64, 0x64, 0x00, 0x00]) {
+            return;
+        }
+        let message: String = (7..3 + len as u16).map(|x| read(x) as char).collect();
+        self.push_log(format_trace(gb, &parse_debug_message(&message)));
+    }
+
+    /// Log the last write to the MBC registers, if any, made by the instruction at `pc`.
+    fn check_mbc_write(&mut self, gb: &mut GameBoy, pc: u16) {
+        let Some((address, value)) = gb.last_mbc_write.take() else {
+            return;
+        };
+        // the writes were already logged before stepping back.
+        if self.skip_breakpoints_until_target_clock {
+            return;
+        }
+        let (lower, upper) = gb.cartridge.curr_bank();
+        self.push_log(format!(
+            "{:04x}: {} write {:02x} to {:04x}: {}, ROM banks {:02x}/{:02x}",
+            pc,
+            gb.cartridge.kind_name(),
+            value,
+            address,
+            gb.cartridge.describe_mbc_write(address, value),
+            lower,
+            upper,
+        ));
+    }
+
+    /// Log the last access to a I/O register that is not emulated, made by the instruction at
+    /// `pc`, if it is the first one to that register.
+    fn check_unemulated_io(&mut self, gb: &GameBoy, pc: u16) {
+        let Some((address, value)) = gb.last_unemulated_io.take() else {
+            return;
+        };
+        if self.skip_breakpoints_until_target_clock {
+            return;
+        }
+        let logged = &mut self.io_logged[value.is_some() as usize];
+        let bit = 1u128 << (address & 0x7F);
+        if *logged & bit != 0 {
+            return;
+        }
+        *logged |= bit;
+        let access = match value {
+            Some(value) => format!("write {:02x} to", value),
+            None => "read from".to_string(),
+        };
+        self.push_log(format!(
+            "{:04x}: {} unemulated {:04x} ({}) at clock {}",
+            pc,
+            access,
+            address,
+            crate::gameboy::memory_map::unemulated_io_description(address),
+            gb.clock_count,
+        ));
+    }
+
+    /// Log the last write to the VRAM or the OAM that was ignored, if any, made by the instruction
+    /// at `pc`. Returns true if it should break.
+    fn check_blocked_write(&mut self, gb: &mut GameBoy, pc: u16) -> bool {
+        let Some(write) = gb.last_blocked_write.take() else {
+            return false;
+        };
+        if self.skip_breakpoints_until_target_clock {
+            return false;
+        }
+        if self.blocked_write_log {
+            self.push_log(format!("{:04x}: {}", pc, write));
+        }
+        self.blocked_write_breakpoint
+    }
+
     pub fn check_break(&self, inter: &mut Interpreter) -> bool {
         let writes = inter.will_write_to();
         for w in &writes.1[..writes.0 as usize] {
             if self.write_breakpoints.contains(w) {
                 return true;
             }
+            if self.mbc_breakpoint && *w <= 0x7FFF {
+                return true;
+            }
         }
         let reads = inter.will_read_from();
         for r in &reads.1[..reads.0 as usize] {
@@ -355,6 +1370,111 @@ impl Debugger {
         false
     }
 
+    /// Check if the next instruction, or the interrupt dispatched before it, will push into the
+    /// stack guard region, or if the instruction will write to the top of the stack without
+    /// pushing. Returns the message to log if so.
+    fn check_stack_guard(&self, inter: &mut Interpreter) -> Option<String> {
+        let (start, end) = self.stack_guard?;
+        let gb = &*inter.0;
+        let sp = gb.cpu.sp;
+        let pushed = [sp.wrapping_sub(1), sp.wrapping_sub(2)];
+        let in_region = |x: &u16| (start..=end).contains(x);
+
+        let interrupts = gb.interrupt_flag.get() & gb.interrupt_enabled & 0x1F;
+        if interrupts != 0
+            && gb.cpu.ime == crate::gameboy::cpu::ImeState::Enabled
+            && pushed.iter().any(in_region)
+        {
+            return Some(format!(
+                "stack guard: interrupt at {:04x} pushes into {:04x}-{:04x}, SP={:04x}",
+                gb.cpu.pc, start, end, sp
+            ));
+        }
+
+        let (len, writes) = inter.will_write_to();
+        let writes = &writes[..len as usize];
+        let pc = inter.0.cpu.pc;
+        if writes == pushed {
+            let address = writes.iter().copied().find(in_region)?;
+            Some(format!(
+                "stack guard: {:04x} pushes into {:04x}, in {:04x}-{:04x}",
+                pc, address, start, end
+            ))
+        } else {
+            let address = writes
+                .iter()
+                .copied()
+                .find(|x| x.wrapping_sub(sp) < STACK_GUARD_WINDOW)?;
+            Some(format!(
+                "stack guard: {:04x} writes to {:04x}, at SP+{}",
+                pc,
+                address,
+                address.wrapping_sub(sp)
+            ))
+        }
+    }
+
+    /// Log the last write that was ignored because its address was frozen or protected, if any,
+    /// made by the instruction at `pc`. Returns true if it should break.
+    fn check_locked_write(&mut self, gb: &mut GameBoy, pc: u16) -> bool {
+        let Some(write) = gb.last_locked_write.take() else {
+            return false;
+        };
+        if self.skip_breakpoints_until_target_clock || write.kind != LockKind::Protect {
+            return false;
+        }
+        self.push_log(format!("{:04x}: {}", pc, write));
+        true
+    }
+
+    /// Check the source, interrupt and address breakpoints before the next instruction.
+    fn will_break(&self, inter: &mut Interpreter) -> bool {
+        if self.source_breakpoint && inter.0.read(inter.0.cpu.pc) == 0x40 {
+            return true;
+        }
+        if self.check_break(inter) {
+            return true;
+        }
+        if self.interrupt_breakpoint {
+            let interrupts: u8 = inter.0.interrupt_flag.get() & inter.0.interrupt_enabled;
+            if interrupts != 0 && inter.0.cpu.ime == crate::gameboy::cpu::ImeState::Enabled {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Run the gameboy emulator until the clock count reach `clock`, without stopping or logging
+    /// anything, and return the clock count of the last time that `run_until` would have stopped
+    /// at a breakpoint before it, if any.
+    ///
+    /// Used for running backwards: the emulator is replayed from an earlier save state, and then
+    /// loaded again and run until the returned clock count with `target_clock`.
+    pub fn find_last_break(&self, gb: &mut GameBoy, clock: u64) -> Option<u64> {
+        let mut inter = Interpreter(gb);
+        inter.0.last_blocked_write = None;
+        inter.0.last_locked_write = None;
+        let mut last_break = None;
+        while inter.0.clock_count < clock {
+            inter.interpret_op();
+            let blocked = inter.0.last_blocked_write.take().is_some();
+            let protected = inter
+                .0
+                .last_locked_write
+                .take()
+                .is_some_and(|x| x.kind == LockKind::Protect);
+            if inter.0.clock_count < clock
+                && ((blocked && self.blocked_write_breakpoint)
+                    || protected
+                    || self.check_stack_guard(&mut inter).is_some()
+                    || self.will_break(&mut inter))
+            {
+                last_break = Some(inter.0.clock_count);
+            }
+        }
+        last_break
+    }
+
     pub fn step(&mut self, gb: &mut GameBoy) -> RunResult {
         self.run_until(gb, gb.clock_count)
     }
@@ -374,10 +1494,46 @@ impl Debugger {
             timeout_clock
         };
 
+        inter.0.last_mbc_write = None;
+        inter.0.last_unemulated_io.set(None);
+        inter.0.last_blocked_write = None;
+        inter.0.last_locked_write = None;
+
+        let mut last_ly = self.target_ly.map(|_| {
+            inter.0.update_ppu();
+            inter.0.ppu.borrow().ly
+        });
+
         let result = loop {
             self.last_op_clock = Some(inter.0.clock_count);
+            let pc = inter.0.cpu.pc;
             inter.interpret_op();
 
+            if self.mbc_log {
+                self.check_mbc_write(inter.0, pc);
+            }
+            if self.io_log {
+                self.check_unemulated_io(inter.0, pc);
+            }
+            if self.check_blocked_write(inter.0, pc) {
+                break RunResult::ReachBreakpoint;
+            }
+            if self.check_locked_write(inter.0, pc) {
+                break RunResult::ReachBreakpoint;
+            }
+
+            if let Some(target_ly) = self.target_ly {
+                // the PPU is lazily updated, so it must be brought to the current clock to see
+                // the scanline change.
+                inter.0.update_ppu();
+                let ly = inter.0.ppu.borrow().ly;
+                if ly == target_ly && last_ly != Some(target_ly) {
+                    self.target_ly = None;
+                    break RunResult::ReachTargetLine;
+                }
+                last_ly = Some(ly);
+            }
+
             if Some(inter.0.cpu.pc) == self.target_address {
                 self.target_address = None;
                 break RunResult::ReachTargetAddress;
@@ -398,14 +1554,18 @@ impl Debugger {
                 continue;
             }
 
-            if self.check_break(&mut inter) {
+            if !self.tracepoints.is_empty() {
+                self.check_tracepoint(inter.0);
+            }
+            if self.debug_message_log {
+                self.check_debug_message(inter.0);
+            }
+            if let Some(message) = self.check_stack_guard(&mut inter) {
+                self.push_log(message);
                 break RunResult::ReachBreakpoint;
             }
-            if self.interrupt_breakpoint {
-                let interrupts: u8 = inter.0.interrupt_flag.get() & inter.0.interrupt_enabled;
-                if interrupts != 0 && inter.0.cpu.ime == crate::gameboy::cpu::ImeState::Enabled {
-                    break RunResult::ReachBreakpoint;
-                }
+            if self.will_break(&mut inter) {
+                break RunResult::ReachBreakpoint;
             }
         };
 
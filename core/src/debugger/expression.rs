@@ -0,0 +1,292 @@
+use super::TraceRegister;
+use crate::gameboy::GameBoy;
+
+/// A unary operator of a expression.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LogicNot,
+}
+
+/// A binary operator of a expression.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    And,
+    Xor,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicAnd,
+    LogicOr,
+}
+impl BinaryOp {
+    /// The operators of each precedence level, from the lowest to the highest.
+    const LEVELS: &'static [&'static [(&'static str, BinaryOp)]] = {
+        use BinaryOp::*;
+        &[
+            &[("||", LogicOr)],
+            &[("&&", LogicAnd)],
+            &[
+                ("==", Eq),
+                ("!=", Ne),
+                ("<=", Le),
+                (">=", Ge),
+                ("<", Lt),
+                (">", Gt),
+            ],
+            &[("|", Or)],
+            &[("^", Xor)],
+            &[("&", And)],
+            &[("<<", Shl), (">>", Shr)],
+            &[("+", Add), ("-", Sub)],
+            &[("*", Mul), ("/", Div), ("%", Rem)],
+        ]
+    };
+
+    fn apply(self, a: u16, b: u16) -> Result<u16, String> {
+        use BinaryOp::*;
+        Ok(match self {
+            Mul => a.wrapping_mul(b),
+            Div => a.checked_div(b).ok_or("division by zero")?,
+            Rem => a.checked_rem(b).ok_or("division by zero")?,
+            Add => a.wrapping_add(b),
+            Sub => a.wrapping_sub(b),
+            Shl => a.checked_shl(b as u32).unwrap_or(0),
+            Shr => a.checked_shr(b as u32).unwrap_or(0),
+            And => a & b,
+            Xor => a ^ b,
+            Or => a | b,
+            Eq => (a == b) as u16,
+            Ne => (a != b) as u16,
+            Lt => (a < b) as u16,
+            Le => (a <= b) as u16,
+            Gt => (a > b) as u16,
+            Ge => (a >= b) as u16,
+            LogicAnd => (a != 0 && b != 0) as u16,
+            LogicOr => (a != 0 || b != 0) as u16,
+        })
+    }
+}
+
+/// A expression over the registers, the labels and the memory of the GameBoy, evaluated to a
+/// 16-bit value, with wrapping arithmetic.
+///
+/// The operands are registers (`a`, `hl`, `sp`, ..., in any case), numbers, labels, and `[x]` for
+/// the byte in memory at `x`, or `[x]:16` for the little-endian word. Numbers are hexadecimal, like
+/// the addresses in the debugger commands, with an optional `0x` or `$` prefix. A name that is both
+/// a label and a number is a label. The operators are the ones of C, with the same precedence,
+/// where comparisons evaluate to 1 or 0.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Expression {
+    Number(u16),
+    Register(TraceRegister),
+    /// A label, or a hexadecimal number if there is no label with this name.
+    Symbol(String),
+    /// The byte in memory at the address, or the 16-bit word if the flag is true.
+    Memory(Box<Expression>, bool),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_binary(0)?;
+        match parser.tokens.get(parser.pos) {
+            Some(token) => Err(format!("unexpected '{}' in '{}'", token, text)),
+            None => Ok(expr),
+        }
+    }
+
+    pub fn eval(&self, gb: &GameBoy) -> Result<u16, String> {
+        Ok(match self {
+            Expression::Number(x) => *x,
+            Expression::Register(reg) => reg.read(gb),
+            Expression::Symbol(name) => {
+                let trace = gb.trace.borrow();
+                match trace.labels.values().find(|x| x.name == *name) {
+                    Some(label) => label.address.to_pc(),
+                    None => parse_number(name)
+                        .ok_or_else(|| format!("'{}' is not a label or a number", name))?,
+                }
+            }
+            Expression::Memory(address, word) => {
+                let address = address.eval(gb)?;
+                if *word {
+                    u16::from_le_bytes([gb.peek(address), gb.peek(address.wrapping_add(1))])
+                } else {
+                    gb.peek(address) as u16
+                }
+            }
+            Expression::Unary(op, x) => {
+                let x = x.eval(gb)?;
+                match op {
+                    UnaryOp::Neg => x.wrapping_neg(),
+                    UnaryOp::Not => !x,
+                    UnaryOp::LogicNot => (x == 0) as u16,
+                }
+            }
+            Expression::Binary(op, a, b) => op.apply(a.eval(gb)?, b.eval(gb)?)?,
+        })
+    }
+
+    /// If the value of this expression always fits in a byte, so it can be shown with 2 digits.
+    pub fn is_8bit(&self) -> bool {
+        match self {
+            Expression::Register(reg) => !reg.is_16bit(),
+            Expression::Memory(_, word) => !word,
+            _ => false,
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// A name (a register, a label or a number), or a operator or bracket.
+type Token = String;
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    const PUNCTS: [&str; 23] = [
+        "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*",
+        "/", "%", "~", "!", "(", ")", ":",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let len = if let Some(punct) = PUNCTS.iter().find(|x| rest.starts_with(**x)) {
+            punct.len()
+        } else if rest.starts_with('[') || rest.starts_with(']') {
+            1
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.'))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                let c = rest.chars().next().unwrap();
+                return Err(format!("unexpected '{}' in '{}'", c, text));
+            }
+            len
+        };
+        tokens.push(rest[..len].to_string());
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+impl Parser {
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.tokens.get(self.pos).map(String::as_str) == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next()? {
+            x if x == token => Ok(()),
+            x => Err(format!("expected '{}', found '{}'", token, x)),
+        }
+    }
+
+    /// Parse the operators of the given precedence level, and above.
+    fn parse_binary(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(ops) = BinaryOp::LEVELS.get(level) else {
+            return self.parse_unary();
+        };
+        let mut expr = self.parse_binary(level + 1)?;
+        'outer: loop {
+            for &(token, op) in ops.iter() {
+                if self.eat(token) {
+                    let rhs = self.parse_binary(level + 1)?;
+                    expr = Expression::Binary(op, Box::new(expr), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(expr);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("~") {
+            UnaryOp::Not
+        } else if self.eat("!") {
+            UnaryOp::LogicNot
+        } else {
+            return self.parse_primary();
+        };
+        Ok(Expression::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        let token = self.next()?.to_string();
+        match token.as_str() {
+            "(" => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let address = self.parse_binary(0)?;
+                self.expect("]")?;
+                let word = if self.eat(":") {
+                    match self.next()? {
+                        "8" => false,
+                        "16" => true,
+                        x => return Err(format!("expected ':8' or ':16', found ':{}'", x)),
+                    }
+                } else {
+                    false
+                };
+                Ok(Expression::Memory(Box::new(address), word))
+            }
+            name => {
+                let first = name.chars().next().unwrap();
+                if let Some(reg) = TraceRegister::parse(&name.to_ascii_lowercase()) {
+                    Ok(Expression::Register(reg))
+                } else if first.is_ascii_digit() || first == '$' {
+                    parse_number(name)
+                        .map(Expression::Number)
+                        .ok_or_else(|| format!("'{}' is not a valid number", name))
+                } else if first.is_ascii_alphabetic() || first == '_' || first == '.' {
+                    Ok(Expression::Symbol(name.to_string()))
+                } else {
+                    Err(format!("unexpected '{}'", name))
+                }
+            }
+        }
+    }
+}
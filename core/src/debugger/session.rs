@@ -0,0 +1,180 @@
+//! A file that keeps the work of reverse engineering a game, so it can be resumed exactly where it
+//! was left: a save state and the CRC32 of its rom, the commands that recreate the breakpoints,
+//! watches, tracepoints, labels and comments of the debugger, and the layout of the frontend.
+//!
+//! The file starts with lines of text, so it can be read and edited by hand, and ends with the
+//! bytes of the save state:
+//!
+//! ```text
+//! gameroy session 1
+//! rom 1a2b3c4d
+//! break x 0150
+//! label c0a0 wPlayerHP
+//! layout screen_split 0.333
+//! state 38211
+//! <the 38211 bytes of the save state>
+//! ```
+//!
+//! Empty lines, and lines starting with `#`, are ignored.
+
+use std::{fmt, io::Write};
+
+/// The version written in the header of a session file.
+pub const SESSION_VERSION: u32 = 1;
+
+const HEADER: &str = "gameroy session";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    /// The CRC32 of the rom of the save state.
+    pub rom_crc32: u32,
+    /// The commands of the debugger, as returned by `Debugger::session_commands`.
+    pub commands: Vec<String>,
+    /// Pairs of key and value, chosen by the frontend, like the sizes of its panels. The key can't
+    /// contain whitespace, and neither can contain line breaks.
+    pub layout: Vec<(String, String)>,
+    /// The save state, as written by `GameBoy::save_state`.
+    pub state: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    /// The file doesn't start with the session header.
+    InvalidHeader,
+    /// The file is from another version of gameroy.
+    UnsupportedVersion(u32),
+    /// The line, counting from 1, is not valid.
+    InvalidLine(usize),
+    /// There is no save state in the file.
+    MissingState,
+    /// There is no rom CRC32 before the save state.
+    MissingRom,
+    /// The save state is shorter than its declared length.
+    Truncated,
+    /// The session is from another rom, see `Session::check_rom`.
+    RomMismatch { expected: u32, found: u32 },
+}
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::InvalidHeader => write!(f, "not a session file"),
+            SessionError::UnsupportedVersion(x) => {
+                write!(f, "unsupported session version {}", x)
+            }
+            SessionError::InvalidLine(x) => write!(f, "invalid line {} in session file", x),
+            SessionError::MissingState => write!(f, "the session has no save state"),
+            SessionError::MissingRom => write!(f, "the session has no rom CRC32"),
+            SessionError::Truncated => write!(f, "the save state of the session is truncated"),
+            SessionError::RomMismatch { expected, found } => write!(
+                f,
+                "the session is from the rom {:08X}, but the loaded rom is {:08X}",
+                found, expected
+            ),
+        }
+    }
+}
+impl std::error::Error for SessionError {}
+
+impl Session {
+    pub fn write(&self, w: &mut impl Write) -> std::io::Result<()> {
+        writeln!(w, "{} {}", HEADER, SESSION_VERSION)?;
+        writeln!(w, "rom {:08x}", self.rom_crc32)?;
+        for command in &self.commands {
+            debug_assert!(!command.contains('\n'));
+            writeln!(w, "{}", command)?;
+        }
+        for (key, value) in &self.layout {
+            debug_assert!(!key.contains(char::is_whitespace) && !value.contains('\n'));
+            writeln!(w, "layout {} {}", key, value)?;
+        }
+        writeln!(w, "state {}", self.state.len())?;
+        w.write_all(&self.state)
+    }
+
+    pub fn read(data: &[u8]) -> Result<Self, SessionError> {
+        let mut session = Session::default();
+        let mut rom_crc32 = None;
+        let mut rest = data;
+        let mut line_number = 0;
+        let mut next_line = |rest: &mut &[u8]| -> Option<Result<(usize, String), SessionError>> {
+            if rest.is_empty() {
+                return None;
+            }
+            let len = rest.iter().position(|&x| x == b'\n').unwrap_or(rest.len());
+            let line = &rest[..len];
+            *rest = &rest[(len + 1).min(rest.len())..];
+            line_number += 1;
+            Some(match std::str::from_utf8(line) {
+                Ok(line) => Ok((line_number, line.trim().to_string())),
+                Err(_) => Err(SessionError::InvalidLine(line_number)),
+            })
+        };
+
+        let version = match next_line(&mut rest) {
+            Some(Ok((_, line))) => line
+                .strip_prefix(HEADER)
+                .and_then(|x| x.trim().parse::<u32>().ok())
+                .ok_or(SessionError::InvalidHeader)?,
+            _ => return Err(SessionError::InvalidHeader),
+        };
+        if version != SESSION_VERSION {
+            return Err(SessionError::UnsupportedVersion(version));
+        }
+
+        while let Some(line) = next_line(&mut rest) {
+            let (number, line) = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(len) = line.strip_prefix("state ") {
+                let len: usize = len
+                    .trim()
+                    .parse()
+                    .map_err(|_| SessionError::InvalidLine(number))?;
+                if rest.len() < len {
+                    return Err(SessionError::Truncated);
+                }
+                session.rom_crc32 = rom_crc32.ok_or(SessionError::MissingRom)?;
+                session.state = rest[..len].to_vec();
+                return Ok(session);
+            }
+            if let Some(crc32) = line.strip_prefix("rom ") {
+                let crc32 = u32::from_str_radix(crc32.trim(), 16)
+                    .map_err(|_| SessionError::InvalidLine(number))?;
+                rom_crc32 = Some(crc32);
+                continue;
+            }
+            if let Some(layout) = line.strip_prefix("layout ") {
+                let (key, value) = layout
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .ok_or(SessionError::InvalidLine(number))?;
+                session
+                    .layout
+                    .push((key.to_string(), value.trim().to_string()));
+                continue;
+            }
+            session.commands.push(line);
+        }
+        Err(SessionError::MissingState)
+    }
+
+    /// Check that the session is from the rom with the given CRC32.
+    pub fn check_rom(&self, rom_crc32: u32) -> Result<(), SessionError> {
+        if self.rom_crc32 != rom_crc32 {
+            return Err(SessionError::RomMismatch {
+                expected: rom_crc32,
+                found: self.rom_crc32,
+            });
+        }
+        Ok(())
+    }
+
+    /// The value of the given key of the layout, if any.
+    pub fn layout_value(&self, key: &str) -> Option<&str> {
+        self.layout
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
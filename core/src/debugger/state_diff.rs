@@ -0,0 +1,279 @@
+//! A structured comparison of two save states of the same rom, for understanding what changed
+//! between two points in time: the registers that differ, the ranges of addresses that differ in
+//! each memory region, and which components have a different internal state.
+
+use std::fmt::{self, Write};
+
+use crate::{
+    gameboy::GameBoy,
+    save_state::{LoadStateError, SaveState, SaveStateContext},
+};
+
+/// The number of equal bytes that may be between two differences for them to be in the same
+/// range.
+const MAX_RANGE_GAP: usize = 3;
+/// The number of bytes of a range that are shown.
+const MAX_SHOWN_BYTES: usize = 8;
+
+/// A register, or other value of the CPU, that differs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterDiff {
+    pub name: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+/// A range of bytes that differ, possibly with some equal bytes between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeDiff {
+    /// The offset of the range from the start of the region.
+    pub offset: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+/// A memory region with differences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionDiff {
+    /// The name of the region, like "wram".
+    pub name: &'static str,
+    /// The address where the region starts in the memory map.
+    pub address: u16,
+    /// The size of each bank of the region, for regions with more than one bank, like the
+    /// cartridge RAM. The offsets of the ranges are from the start of the first bank.
+    pub bank_size: Option<usize>,
+    pub ranges: Vec<RangeDiff>,
+}
+
+impl RegionDiff {
+    /// The bank and the address in the memory map of the given offset in the region.
+    pub fn location(&self, offset: usize) -> (Option<usize>, u16) {
+        match self.bank_size {
+            Some(size) => (
+                Some(offset / size),
+                self.address.wrapping_add((offset % size) as u16),
+            ),
+            None => (None, self.address.wrapping_add(offset as u16)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The clock count of each state.
+    pub clock_counts: [u64; 2],
+    pub registers: Vec<RegisterDiff>,
+    pub regions: Vec<RegionDiff>,
+    /// The components whose internal state differs, besides their registers and memory, like
+    /// "ppu" for the position of the pixel fetcher.
+    pub internal: Vec<&'static str>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.regions.is_empty() && self.internal.is_empty()
+    }
+}
+
+/// Compare the save states `before` and `after` of the rom in the cartridge of `gb`.
+pub fn diff_states(gb: &GameBoy, before: &[u8], after: &[u8]) -> Result<StateDiff, LoadStateError> {
+    let load = |state: &[u8]| -> Result<GameBoy, LoadStateError> {
+        let mut gb = GameBoy::new(None, gb.cartridge.clone(), gb.accuracy);
+        gb.load_state(&mut &state[..])?;
+        Ok(gb)
+    };
+    let mut a = load(before)?;
+    let mut b = load(after)?;
+
+    let mut registers = Vec::new();
+    {
+        let mut register = |name, before: String, after: String| {
+            if before != after {
+                registers.push(RegisterDiff {
+                    name,
+                    before,
+                    after,
+                });
+            }
+        };
+        let hex8 = |x: u8| format!("{:02x}", x);
+        let u8s = |x: &GameBoy| {
+            let cpu = &x.cpu;
+            [cpu.a, cpu.f.0, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l]
+        };
+        let names = ["a", "f", "b", "c", "d", "e", "h", "l"];
+        for ((name, x), y) in names.into_iter().zip(u8s(&a)).zip(u8s(&b)) {
+            register(name, hex8(x), hex8(y));
+        }
+        let hex16 = |x: u16| format!("{:04x}", x);
+        register("sp", hex16(a.cpu.sp), hex16(b.cpu.sp));
+        register("pc", hex16(a.cpu.pc), hex16(b.cpu.pc));
+        let debug = |x: &dyn fmt::Debug| format!("{:?}", x);
+        register("ime", debug(&a.cpu.ime), debug(&b.cpu.ime));
+        register("state", debug(&a.cpu.state), debug(&b.cpu.state));
+        register("ie", hex8(a.interrupt_enabled), hex8(b.interrupt_enabled));
+    }
+
+    let io = |x: &GameBoy| (0xFF00..0xFF80).map(|i| x.peek(i)).collect::<Vec<u8>>();
+    let regions = [
+        (
+            "vram",
+            0x8000,
+            None,
+            diff_bytes(&a.ppu.get_mut().vram, &b.ppu.get_mut().vram),
+        ),
+        (
+            "sram",
+            0xA000,
+            (a.cartridge.ram.len() > 0x2000).then_some(0x2000),
+            diff_bytes(&a.cartridge.ram, &b.cartridge.ram),
+        ),
+        ("wram", 0xC000, None, diff_bytes(&a.wram, &b.wram)),
+        (
+            "oam",
+            0xFE00,
+            None,
+            diff_bytes(&a.ppu.get_mut().oam, &b.ppu.get_mut().oam),
+        ),
+        ("io", 0xFF00, None, diff_bytes(&io(&a), &io(&b))),
+        ("hram", 0xFF80, None, diff_bytes(&a.hram, &b.hram)),
+    ];
+    let regions = regions
+        .into_iter()
+        .filter(|(_, _, _, ranges)| !ranges.is_empty())
+        .map(|(name, address, bank_size, ranges)| RegionDiff {
+            name,
+            address,
+            bank_size,
+            ranges,
+        })
+        .collect();
+
+    // the memory is already compared, and the screen is not part of the state of the emulation.
+    for gb in [&mut a, &mut b] {
+        let ppu = gb.ppu.get_mut();
+        ppu.vram = [0; 0x2000];
+        ppu.oam = [0; 0xA0];
+        ppu.screen.screen.fill(0);
+        gb.cartridge.ram.clear();
+    }
+    let mut internal = Vec::new();
+    for component in ["timer", "sound", "ppu", "serial", "mbc"] {
+        if serialize(&a, component)? != serialize(&b, component)? {
+            internal.push(component);
+        }
+    }
+
+    Ok(StateDiff {
+        clock_counts: [a.clock_count, b.clock_count],
+        registers,
+        regions,
+        internal,
+    })
+}
+
+/// The save state of the given component of `gb`.
+fn serialize(gb: &GameBoy, component: &str) -> Result<Vec<u8>, LoadStateError> {
+    let ctx = &mut SaveStateContext::new(None, gb.clock_count);
+    let mut data = Vec::new();
+    match component {
+        "timer" => gb.timer.borrow().save_state(ctx, &mut data)?,
+        "sound" => gb.sound.borrow().save_state(ctx, &mut data)?,
+        "ppu" => gb.ppu.borrow().save_state(ctx, &mut data)?,
+        "serial" => gb.serial.borrow().save_state(ctx, &mut data)?,
+        "mbc" => gb.cartridge.save_state(ctx, &mut data)?,
+        _ => unreachable!(),
+    }
+    Ok(data)
+}
+
+/// The ranges where `a` and `b` differ, including the bytes of `b` past the end of `a` and
+/// vice-versa.
+fn diff_bytes(a: &[u8], b: &[u8]) -> Vec<RangeDiff> {
+    let len = a.len().max(b.len());
+    let differs = |i: usize| a.get(i) != b.get(i);
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < len {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        while let Some(next) = (end..(end + MAX_RANGE_GAP + 1).min(len)).find(|&x| differs(x)) {
+            end = next + 1;
+        }
+        let slice = |x: &[u8]| x[start.min(x.len())..end.min(x.len())].to_vec();
+        ranges.push(RangeDiff {
+            offset: start,
+            before: slice(a),
+            after: slice(b),
+        });
+        i = end;
+    }
+    ranges
+}
+
+fn fmt_bytes(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for x in bytes.iter().take(MAX_SHOWN_BYTES) {
+        let _ = write!(text, "{:02x} ", x);
+    }
+    if bytes.len() > MAX_SHOWN_BYTES {
+        text.push_str(".. ");
+    }
+    text.pop();
+    text
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [before, after] = self.clock_counts;
+        writeln!(f, "clock count: {} -> {}", before, after)?;
+        if self.is_empty() {
+            return write!(f, "the states are equal");
+        }
+        for register in &self.registers {
+            writeln!(
+                f,
+                "{}: {} -> {}",
+                register.name, register.before, register.after
+            )?;
+        }
+        for region in &self.regions {
+            let len: usize = region
+                .ranges
+                .iter()
+                .map(|x| x.before.len().max(x.after.len()))
+                .sum();
+            let count = region.ranges.len();
+            let plural = if count == 1 { "range" } else { "ranges" };
+            writeln!(f, "{}: {} bytes in {} {}", region.name, len, count, plural)?;
+            for range in &region.ranges {
+                let len = range.before.len().max(range.after.len());
+                let (bank, start) = region.location(range.offset);
+                let (_, end) = region.location(range.offset + len - 1);
+                write!(f, "  ")?;
+                if let Some(bank) = bank {
+                    write!(f, "{:02x}:", bank)?;
+                }
+                if len == 1 {
+                    write!(f, "{:04x}:", start)?;
+                } else {
+                    write!(f, "{:04x}-{:04x}:", start, end)?;
+                }
+                writeln!(
+                    f,
+                    " {} -> {}",
+                    fmt_bytes(&range.before),
+                    fmt_bytes(&range.after)
+                )?;
+            }
+        }
+        if !self.internal.is_empty() {
+            writeln!(f, "internal state: {}", self.internal.join(", "))?;
+        }
+        Ok(())
+    }
+}
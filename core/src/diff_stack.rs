@@ -1,4 +1,6 @@
-use std::io::Write;
+use alloc::{boxed::Box, vec};
+
+use crate::io::Write;
 
 /// A Stack where in each push, the previous top element is delta-compressed against the new
 /// element. Is used the build a save state stack.
@@ -152,7 +154,7 @@ impl DiffStack {
 // The compression works by writing the out the sequence:
 //
 // (equal_bytes_count:u16 | different_bytes_count:u16 | different_bytes:u8*)*
-pub fn delta_compress(a: &[u8], b: &[u8], out: &mut impl Write) -> std::io::Result<usize> {
+pub fn delta_compress(a: &[u8], b: &[u8], out: &mut impl Write) -> Result<usize, crate::io::Error> {
     let mut i = 0;
     let mut out_len = 0;
 
@@ -230,11 +232,11 @@ pub enum DecompressError {
     /// While reading the next `equal_bytes_count`/`different_bytes_count` pair, or the
     /// `different_bytes` from the diff, the diff ended early.
     MalformedDiff,
-    Io(std::io::Error),
+    Io(crate::io::Error),
 }
 
-impl From<std::io::Error> for DecompressError {
-    fn from(v: std::io::Error) -> Self {
+impl From<crate::io::Error> for DecompressError {
+    fn from(v: crate::io::Error) -> Self {
         Self::Io(v)
     }
 }
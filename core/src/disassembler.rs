@@ -1,13 +1,15 @@
-use std::{
+use alloc::{
     collections::{BTreeMap, BTreeSet},
-    fmt,
-    fmt::Write,
-    ops::Range,
+    format,
+    string::String,
+    vec,
+    vec::Vec,
 };
+use core::{fmt, fmt::Write, ops::Range};
 
 use crate::{
     consts,
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{cartridge::Cartridge, counters::INTERRUPT_NAMES, GameBoy},
 };
 
 struct ReallySigned(i8);
@@ -56,7 +58,8 @@ impl Address {
         }
     }
 
-    fn to_pc(self) -> u16 {
+    /// The address of this in the CPU address space, assuming that its bank is mapped.
+    pub fn to_pc(self) -> u16 {
         if self.bank == 0 {
             self.address
         } else {
@@ -88,12 +91,26 @@ pub struct Label {
     pub name: String,
 }
 impl Label {
-    /// Create a Label in a given address with a generate name, in the format "L<BANK:02x>_<ADDRESS:04x>".
+    /// Create a Label in a given address with a generate name, in the format "L<BANK:02x>_<ADDRESS:04x>",
+    /// or the name of the vector, if it is a RST or interrupt vector in bank 0.
     fn new(address: Address) -> Self {
-        Self {
-            address,
-            name: format!("L{:02x}_{:04x}", address.bank, address.address),
+        let name = match vector_name(address) {
+            Some(name) => name,
+            None => format!("L{:02x}_{:04x}", address.bank, address.address),
+        };
+        Self { address, name }
+    }
+}
+
+/// The name of the RST or interrupt vector in the given address, like "RST_0x28" or "INT_VBLANK".
+fn vector_name(address: Address) -> Option<String> {
+    match (address.bank, address.address) {
+        (0, x @ 0x00..=0x38) if x % 8 == 0 => Some(format!("RST_0x{:02x}", x)),
+        (0, x @ 0x40..=0x60) if x % 8 == 0 => {
+            let name = INTERRUPT_NAMES[(x as usize - 0x40) / 8];
+            Some(format!("INT_{}", name.to_uppercase()))
         }
+        _ => None,
     }
 }
 
@@ -148,6 +165,13 @@ pub struct Trace {
     pub labels: BTreeMap<Address, Label>,
     /// Map from a opcode (like jp or call) to another address
     pub jumps: BTreeMap<Address, Address>,
+    /// Map from a CALL or RST opcode to the called address. A subset of `jumps`, used for building
+    /// the call graph. See `Trace::fmt_call_graph`.
+    pub calls: BTreeMap<Address, Address>,
+    /// Names for the interrupt handlers that live in a switchable bank, by their address in the
+    /// CPU address space. The bank is only know when the handler is reached, so they are labeled
+    /// in any bank they are traced in.
+    pub handler_names: BTreeMap<u16, String>,
 }
 impl Default for Trace {
     fn default() -> Self {
@@ -161,6 +185,48 @@ impl Trace {
             code_ranges: Vec::new(),
             labels: Default::default(),
             jumps: Default::default(),
+            calls: Default::default(),
+            handler_names: Default::default(),
+        }
+    }
+
+    /// Disassemble the code reachable from the entry point and from the interrupt vectors, and
+    /// label the interrupt handlers.
+    pub fn trace_entry_points(&mut self, gameboy: &GameBoy) {
+        let banks = gameboy.cartridge.curr_bank();
+        self.trace_starting_at(gameboy, banks, 0x100, Some("entry point".into()));
+
+        // If there is more than one switchable bank, the bank active when the interrupt happens is
+        // unknown, so code in the switchable bank is not traced from the vectors.
+        let bank = (gameboy.cartridge.num_banks() == 2).then_some(1);
+        for vector in (0x40..=0x60).step_by(8) {
+            let cursor = Cursor {
+                bank0: banks.0,
+                bank,
+                pc: vector,
+                reg_a: None,
+            };
+            let mut cursors = vec![cursor];
+            while !cursors.is_empty() {
+                self.trace_once(gameboy, &mut cursors);
+            }
+            self.add_label(Address::new(banks.0, vector));
+
+            let Some(target) = vector_target(gameboy, banks.0, vector) else {
+                continue;
+            };
+            let address = Address::new(banks.0, vector);
+            let name = format!("{}_handler", self.labels[&address].name);
+            match Address::from_pc((banks.0, bank), target) {
+                Some(address) => {
+                    if let Some(label) = self.add_label(address) {
+                        label.name = name;
+                    }
+                }
+                None => {
+                    self.handler_names.insert(target, name);
+                }
+            }
         }
     }
 
@@ -252,6 +318,7 @@ impl Trace {
     ) {
         if let Some(label) = label {
             let address = Address::from_pc(banks, start).unwrap();
+            let label = self.handler_name(address).unwrap_or(label);
             if let Some(x) = self.add_label(address) {
                 x.name = label
             }
@@ -273,7 +340,7 @@ impl Trace {
     fn get_curr_code_range(&self, address: Address) -> Option<Range<Address>> {
         self.code_ranges
             .binary_search_by(|range| {
-                use std::cmp::Ordering;
+                use core::cmp::Ordering;
                 if address < range.start {
                     Ordering::Greater
                 } else if address >= range.end {
@@ -290,7 +357,7 @@ impl Trace {
     /// Return true if the opcode was not added before.
     fn add_opcode(&mut self, address: Address, op: &[u8], len: u16) -> bool {
         let i = self.code_ranges.binary_search_by(|range| {
-            use std::cmp::Ordering;
+            use core::cmp::Ordering;
             if address < range.start {
                 Ordering::Greater
             } else if address >= range.end {
@@ -332,13 +399,23 @@ impl Trace {
     }
 
     fn add_label(&mut self, address: Address) -> Option<&mut Label> {
+        let handler_name = self.handler_name(address);
         Some(
             self.labels
                 .entry(address)
-                .or_insert_with(|| Label::new(address)),
+                .or_insert_with(|| match handler_name {
+                    Some(name) => Label { address, name },
+                    None => Label::new(address),
+                }),
         )
     }
 
+    /// The name of the label of a interrupt handler in a switchable bank, suffixed by its bank.
+    fn handler_name(&self, address: Address) -> Option<String> {
+        let name = self.handler_names.get(&address.to_pc())?;
+        Some(format!("{}_{:02x}", name, address.bank))
+    }
+
     fn add_jump(&mut self, from: Address, to: Address) {
         if let Some(x) = self.add_label(to) {
             let to = x.address;
@@ -360,17 +437,166 @@ impl Trace {
             return;
         }
 
-        let (step, jump) = compute_step(len, cursor, &op, &rom.cartridge);
+        let bank0 = cursor.bank0;
+        let (mut step, jump) = compute_step(len, cursor, &op, &rom.cartridge);
+        if op[0] & 0b11000111 == 0b11000111 {
+            let vector = (op[0] & 0b00111000) as u16;
+            if is_jump_table_rst(rom, bank0, vector) {
+                // the bytes after the RST are a table of addresses, not code.
+                if let Some(table) = step.take() {
+                    self.trace_jump_table(rom, table, cursors);
+                }
+            }
+        }
         cursors.extend(step);
 
         let Some(jump) = jump else { return };
 
         if let Some(to) = Address::from_cursor(&jump) {
             self.add_jump(address, to);
+            if is_call(op[0]) {
+                self.calls.insert(address, to);
+            }
             cursors.push(jump);
         }
     }
 
+    /// Push a cursor for each entry of the jump table starting at `table`. The table ends at the
+    /// first entry that is not a ROM address, or where the code of a previous entry starts.
+    fn trace_jump_table(&mut self, rom: &GameBoy, table: Cursor, cursors: &mut Vec<Cursor>) {
+        const MAX_ENTRIES: usize = 128;
+
+        let Cursor { bank0, bank, .. } = table;
+        let region_end = if table.pc < 0x4000 { 0x4000 } else { 0x8000 };
+        let mut end = region_end;
+        let mut pc = table.pc;
+        for _ in 0..MAX_ENTRIES {
+            if pc + 2 > end {
+                break;
+            }
+            let entry = Cursor {
+                bank0,
+                bank,
+                pc,
+                reg_a: None,
+            };
+            let (op, _) = entry.get_op(rom);
+            let dest = u16::from_le_bytes([op[0], op[1]]);
+            if dest > 0x7FFF {
+                break;
+            }
+            if dest > table.pc && dest < end {
+                end = dest;
+            }
+            let jump = Cursor {
+                bank0,
+                bank,
+                pc: dest,
+                reg_a: None,
+            };
+            if let Some(to) = Address::from_cursor(&jump) {
+                self.add_label(to);
+                cursors.push(jump);
+            }
+            pc += 2;
+        }
+    }
+
+    /// The name of the label at the address, or the generated one if there is none.
+    fn label_name(&self, address: Address) -> String {
+        match self.labels.get(&address) {
+            Some(label) => label.name.clone(),
+            None => Label::new(address).name,
+        }
+    }
+
+    /// Follow the code of the function starting at `start`, through jumps and branches, but not
+    /// into calls. Insert a edge to `edges` for each call found, and mark the call as visited.
+    fn walk_function(
+        &self,
+        start: Address,
+        functions: &BTreeSet<Address>,
+        ops: &BTreeMap<Address, &Directive>,
+        edges: &mut BTreeSet<(Address, Address)>,
+        visited_calls: &mut BTreeSet<Address>,
+    ) {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![start];
+        while let Some(address) = stack.pop() {
+            if !visited.insert(address) {
+                continue;
+            }
+            let Some(directive) = ops.get(&address) else {
+                continue;
+            };
+            if let Some(&to) = self.calls.get(&address) {
+                edges.insert((start, to));
+                visited_calls.insert(address);
+            } else if let Some(&to) = self.jumps.get(&address) {
+                // a jump to the start of another function is a tail call.
+                if functions.contains(&to) {
+                    edges.insert((start, to));
+                } else {
+                    stack.push(to);
+                }
+            }
+            let ends_flow = matches!(directive.op[0], 0xC3 | 0xE9 | 0x18 | 0xC9 | 0xD9);
+            let next = address.address.wrapping_add(directive.len);
+            if !ends_flow && next != 0x4000 && next != 0x8000 {
+                stack.push(Address::new(address.bank, next));
+            }
+        }
+    }
+
+    /// Write the call graph of the traced code in the Graphviz dot format.
+    ///
+    /// Each node is a function, named by its label: the entry point, the interrupt vectors, and
+    /// each address called by a CALL or RST. Each edge is a call (or a jump to another function),
+    /// from the function whose code reaches the call opcode. Calls in code only reached through a
+    /// jump table are from the start of their code range.
+    pub fn fmt_call_graph(&self, f: &mut impl Write) -> fmt::Result {
+        let mut functions: BTreeSet<Address> = self.calls.values().copied().collect();
+        for &address in self.labels.keys() {
+            let is_entry = address.bank == 0 && address.address == 0x100;
+            let is_vector = address.bank == 0 && (0x40..=0x60).contains(&address.address);
+            if (is_entry || is_vector) && self.is_already_traced(address) {
+                functions.insert(address);
+            }
+        }
+
+        let ops: BTreeMap<Address, &Directive> =
+            self.directives.iter().map(|x| (x.address, x)).collect();
+        let mut edges = BTreeSet::new();
+        let mut visited_calls = BTreeSet::new();
+        for &start in &functions {
+            self.walk_function(start, &functions, &ops, &mut edges, &mut visited_calls);
+        }
+        for (&from, &to) in &self.calls {
+            if !visited_calls.contains(&from) {
+                let range = self.get_curr_code_range(from);
+                edges.insert((range.map_or(from, |x| x.start), to));
+            }
+        }
+
+        let nodes: BTreeSet<Address> = functions
+            .iter()
+            .copied()
+            .chain(edges.iter().map(|&(from, _)| from))
+            .collect();
+
+        let id = |address: Address| format!("\"{:02x}_{:04x}\"", address.bank, address.address);
+        writeln!(f, "digraph callgraph {{")?;
+        writeln!(f, "    node [shape=box];")?;
+        for &address in &nodes {
+            let name = dot_quote(&self.label_name(address));
+            writeln!(f, "    {} [label={}];", id(address), name)?;
+        }
+        for &(from, to) in &edges {
+            writeln!(f, "    {} -> {};", id(from), id(to))?;
+        }
+        writeln!(f, "}}")
+    }
+
     pub fn fmt(&self, rom: &GameBoy, f: &mut impl Write) -> fmt::Result {
         for range in self.code_ranges.iter() {
             let mut pc = range.start;
@@ -404,6 +630,83 @@ impl Trace {
     }
 }
 
+/// The destination of the first JP or CALL in the code at the given vector, if it reaches one
+/// before any other control flow instruction.
+fn vector_target(rom: &GameBoy, bank0: u16, vector: u16) -> Option<u16> {
+    let mut pc = vector;
+    for _ in 0..8 {
+        let cursor = Cursor {
+            bank0,
+            bank: None,
+            pc,
+            reg_a: None,
+        };
+        let (op, len) = cursor.get_op(rom);
+        match op[0] {
+            0xC3 | 0xCD => return Some(u16::from_le_bytes([op[1], op[2]])),
+            x if is_control_flow(x) => return None,
+            _ => pc += len as u16,
+        }
+    }
+    None
+}
+
+/// If the handler of the RST vector dispatches a jump table: it pops the return address, which is
+/// the address of the table right after the RST, and jumps to HL.
+fn is_jump_table_rst(rom: &GameBoy, bank0: u16, vector: u16) -> bool {
+    let mut pc = vector;
+    let mut pop_hl = false;
+    for _ in 0..16 {
+        let cursor = Cursor {
+            bank0,
+            bank: None,
+            pc,
+            reg_a: None,
+        };
+        let (op, len) = cursor.get_op(rom);
+        match op[0] {
+            // POP HL
+            0xE1 => pop_hl = true,
+            // JP (HL)
+            0xE9 => return pop_hl,
+            // JP $aaaa, the handler may be in bank 0
+            0xC3 => {
+                pc = u16::from_le_bytes([op[1], op[2]]);
+                if pc >= 0x4000 {
+                    return false;
+                }
+                continue;
+            }
+            x if is_control_flow(x) => return false,
+            _ => {}
+        }
+        pc += len as u16;
+    }
+    false
+}
+
+/// If the opcode is a jump, call, return or RST.
+fn is_control_flow(op: u8) -> bool {
+    matches!(op, 0xC3 | 0xC9 | 0xCD | 0xD9 | 0xE9 | 0x18)
+        // JR cc, JP cc, CALL cc, RET cc
+        || op & 0b1110_0111 == 0b0010_0000
+        || op & 0b1110_0111 == 0b1100_0010
+        || op & 0b1110_0111 == 0b1100_0100
+        || op & 0b1110_0111 == 0b1100_0000
+        // RST n
+        || op & 0b1100_0111 == 0b1100_0111
+}
+
+/// If the opcode is a CALL, conditional or not, or a RST.
+fn is_call(op: u8) -> bool {
+    op == 0xCD || op & 0b1110_0111 == 0b1100_0100 || op & 0b1100_0111 == 0b1100_0111
+}
+
+/// Quote a name for the Graphviz dot format.
+fn dot_quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 /// Return a (step, jump) pair.
 pub fn compute_step(
     len: u8,
@@ -1177,3 +1480,372 @@ fn disassembly_opcode_cr(op: u8, w: &mut impl Write) -> fmt::Result {
         0xff => write!(w, "SET  7,A "),
     }
 }
+
+/// The mnemonic of a instruction. See `decode_opcode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Mnemonic {
+    Nop,
+    Ld,
+    Ldh,
+    Inc,
+    Dec,
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+    Rlca,
+    Rrca,
+    Rla,
+    Rra,
+    Daa,
+    Cpl,
+    Scf,
+    Ccf,
+    Stop,
+    Halt,
+    Jr,
+    Jp,
+    Call,
+    Ret,
+    Reti,
+    Rst,
+    Push,
+    Pop,
+    Di,
+    Ei,
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+    Bit,
+    Res,
+    Set,
+    /// One of the opcodes that don't exist in the SM83 (0xD3, 0xDB, etc).
+    Invalid,
+}
+impl Mnemonic {
+    /// The mnemonic as written by `disassembly_opcode`, or a empty string if invalid.
+    pub fn as_str(self) -> &'static str {
+        use Mnemonic::*;
+        match self {
+            Nop => "NOP",
+            Ld => "LD",
+            Ldh => "LDH",
+            Inc => "INC",
+            Dec => "DEC",
+            Add => "ADD",
+            Adc => "ADC",
+            Sub => "SUB",
+            Sbc => "SBC",
+            And => "AND",
+            Xor => "XOR",
+            Or => "OR",
+            Cp => "CP",
+            Rlca => "RLCA",
+            Rrca => "RRCA",
+            Rla => "RLA",
+            Rra => "RRA",
+            Daa => "DAA",
+            Cpl => "CPL",
+            Scf => "SCF",
+            Ccf => "CCF",
+            Stop => "STOP",
+            Halt => "HALT",
+            Jr => "JR",
+            Jp => "JP",
+            Call => "CALL",
+            Ret => "RET",
+            Reti => "RETI",
+            Rst => "RST",
+            Push => "PUSH",
+            Pop => "POP",
+            Di => "DI",
+            Ei => "EI",
+            Rlc => "RLC",
+            Rrc => "RRC",
+            Rl => "RL",
+            Rr => "RR",
+            Sla => "SLA",
+            Sra => "SRA",
+            Swap => "SWAP",
+            Srl => "SRL",
+            Bit => "BIT",
+            Res => "RES",
+            Set => "SET",
+            Invalid => "",
+        }
+    }
+
+    /// If this instruction may change the program counter to something other than the next
+    /// instruction.
+    pub fn is_jump(self) -> bool {
+        use Mnemonic::*;
+        matches!(self, Jr | Jp | Call | Ret | Reti | Rst)
+    }
+}
+
+/// A register of the SM83.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+/// The condition of a conditional jump, call or return.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+/// A operand of a instruction. See `decode_opcode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Operand {
+    /// The value of a register.
+    Reg(Register),
+    /// The memory pointed by a register. `(C)` points to `0xFF00 + C`.
+    Indirect(Register),
+    /// The memory pointed by HL, incrementing HL after the access: `(HL+)`.
+    IndirectInc,
+    /// The memory pointed by HL, decrementing HL after the access: `(HL-)`.
+    IndirectDec,
+    /// A 8-bit immediate value.
+    Imm8(u8),
+    /// A 16-bit immediate value.
+    Imm16(u16),
+    /// A signed 8-bit immediate value, added to SP.
+    Offset(i8),
+    /// SP plus a signed offset: `SP+r8`.
+    SpOffset(i8),
+    /// The memory at a immediate address. For LDH, the address is already added to 0xFF00.
+    Address(u16),
+    /// The destination of a jump, call or RST.
+    Target(u16),
+    Condition(Condition),
+    /// The bit index of BIT, RES and SET.
+    Bit(u8),
+}
+
+/// A decoded instruction. See `decode_opcode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    /// The length of the instruction in bytes, including the 0xCB prefix.
+    pub len: u8,
+    pub mnemonic: Mnemonic,
+    operands: [Option<Operand>; 2],
+    /// The destination of the jump, if it is known statically.
+    pub target: Option<u16>,
+    /// The flags that this instruction may change, in the format 0b0000ZNHC.
+    pub flags_written: u8,
+    /// The flags that this instruction depends on, in the format 0b0000ZNHC.
+    pub flags_read: u8,
+}
+impl Instruction {
+    /// The operands, in the same order as written in the disassembly.
+    pub fn operands(&self) -> impl Iterator<Item = Operand> + '_ {
+        self.operands.iter().flatten().copied()
+    }
+}
+
+/// Decode the instruction in `op`, which starts at address `pc`. `op` must contain the entire
+/// instruction, with length given by `consts::LEN`.
+///
+/// This is the structured counterpart of `disassembly_opcode`.
+pub fn decode_opcode(pc: u16, op: &[u8]) -> Instruction {
+    use Mnemonic::*;
+    use Operand::*;
+    use Register::*;
+
+    let len = consts::LEN[op[0] as usize];
+    let byte = |i: usize| op.get(i).copied().unwrap_or(0);
+    let imm8 = byte(1);
+    let imm16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let relative = pc
+        .wrapping_add(len as u16)
+        .wrapping_add_signed(imm8 as i8 as i16);
+
+    let reg8 = |i: u8| match i & 7 {
+        0 => Reg(B),
+        1 => Reg(C),
+        2 => Reg(D),
+        3 => Reg(E),
+        4 => Reg(H),
+        5 => Reg(L),
+        6 => Indirect(HL),
+        _ => Reg(A),
+    };
+    let reg16 = |i: u8| Reg([BC, DE, HL, SP][i as usize & 3]);
+    let reg16_stack = |i: u8| Reg([BC, DE, HL, AF][i as usize & 3]);
+    let cond = |i: u8| {
+        Operand::Condition(
+            [
+                self::Condition::NZ,
+                self::Condition::Z,
+                self::Condition::NC,
+                self::Condition::C,
+            ][i as usize & 3],
+        )
+    };
+    let alu = |i: u8| [Add, Adc, Sub, Sbc, And, Xor, Or, Cp][i as usize & 7];
+
+    let opcode = op[0];
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let (p, q) = (y >> 1, y & 1);
+
+    let (mnemonic, operands): (Mnemonic, [Option<Operand>; 2]) = match (x, z) {
+        (0, 0) => match y {
+            0 => (Nop, [None, None]),
+            1 => (Ld, [Some(Address(imm16)), Some(Reg(SP))]),
+            2 => (Stop, [None, None]),
+            3 => (Jr, [Some(Target(relative)), None]),
+            _ => (Jr, [Some(cond(y - 4)), Some(Target(relative))]),
+        },
+        (0, 1) if q == 0 => (Ld, [Some(reg16(p)), Some(Imm16(imm16))]),
+        (0, 1) => (Add, [Some(Reg(HL)), Some(reg16(p))]),
+        (0, 2) => {
+            let mem = [Indirect(BC), Indirect(DE), IndirectInc, IndirectDec][p as usize];
+            if q == 0 {
+                (Ld, [Some(mem), Some(Reg(A))])
+            } else {
+                (Ld, [Some(Reg(A)), Some(mem)])
+            }
+        }
+        (0, 3) => ([Inc, Dec][q as usize], [Some(reg16(p)), None]),
+        (0, 4) => (Inc, [Some(reg8(y)), None]),
+        (0, 5) => (Dec, [Some(reg8(y)), None]),
+        (0, 6) => (Ld, [Some(reg8(y)), Some(Imm8(imm8))]),
+        (0, _) => (
+            [Rlca, Rrca, Rla, Rra, Daa, Cpl, Scf, Ccf][y as usize],
+            [None, None],
+        ),
+        (1, _) if opcode == 0x76 => (Halt, [None, None]),
+        (1, _) => (Ld, [Some(reg8(y)), Some(reg8(z))]),
+        (2, _) => alu_operands(alu(y), reg8(z)),
+        (3, 0) => match y {
+            0..=3 => (Ret, [Some(cond(y)), None]),
+            4 => (Ldh, [Some(Address(0xFF00 | imm8 as u16)), Some(Reg(A))]),
+            5 => (Add, [Some(Reg(SP)), Some(Offset(imm8 as i8))]),
+            6 => (Ldh, [Some(Reg(A)), Some(Address(0xFF00 | imm8 as u16))]),
+            _ => (Ld, [Some(Reg(HL)), Some(SpOffset(imm8 as i8))]),
+        },
+        (3, 1) if q == 0 => (Pop, [Some(reg16_stack(p)), None]),
+        (3, 1) => match p {
+            0 => (Ret, [None, None]),
+            1 => (Reti, [None, None]),
+            2 => (Jp, [Some(Reg(HL)), None]),
+            _ => (Ld, [Some(Reg(SP)), Some(Reg(HL))]),
+        },
+        (3, 2) => match y {
+            0..=3 => (Jp, [Some(cond(y)), Some(Target(imm16))]),
+            4 => (Ld, [Some(Indirect(C)), Some(Reg(A))]),
+            5 => (Ld, [Some(Address(imm16)), Some(Reg(A))]),
+            6 => (Ld, [Some(Reg(A)), Some(Indirect(C))]),
+            _ => (Ld, [Some(Reg(A)), Some(Address(imm16))]),
+        },
+        (3, 3) => match y {
+            0 => (Jp, [Some(Target(imm16)), None]),
+            1 => return decode_cb_opcode(byte(1)),
+            6 => (Di, [None, None]),
+            7 => (Ei, [None, None]),
+            _ => (Invalid, [None, None]),
+        },
+        (3, 4) if y < 4 => (Call, [Some(cond(y)), Some(Target(imm16))]),
+        (3, 5) if q == 0 => (Push, [Some(reg16_stack(p)), None]),
+        (3, 5) if p == 0 => (Call, [Some(Target(imm16)), None]),
+        (3, 6) => alu_operands(alu(y), Imm8(imm8)),
+        (3, 7) => (Rst, [Some(Target(y as u16 * 8)), None]),
+        _ => (Invalid, [None, None]),
+    };
+
+    let target = operands.iter().find_map(|x| match x {
+        Some(Target(target)) => Some(*target),
+        _ => None,
+    });
+
+    let mut flags_read = match operands[0] {
+        Some(Operand::Condition(self::Condition::NZ | self::Condition::Z)) => 0b1000,
+        Some(Operand::Condition(self::Condition::NC | self::Condition::C)) => 0b0001,
+        _ => 0,
+    };
+    flags_read |= match (mnemonic, operands[0]) {
+        (Adc | Sbc | Rla | Rra | Ccf, _) => 0b0001,
+        (Daa, _) => 0b0111,
+        (Push, Some(Reg(AF))) => 0b1111,
+        _ => 0,
+    };
+
+    Instruction {
+        len,
+        mnemonic,
+        operands,
+        target,
+        flags_written: consts::WRITE_FLAG[opcode as usize],
+        flags_read,
+    }
+}
+
+/// The operands of a arithmetic or logic instruction. ADD, ADC and SBC are written with A as the
+/// first operand, like in `disassembly_opcode`.
+fn alu_operands(mnemonic: Mnemonic, operand: Operand) -> (Mnemonic, [Option<Operand>; 2]) {
+    match mnemonic {
+        Mnemonic::Add | Mnemonic::Adc | Mnemonic::Sbc => {
+            (mnemonic, [Some(Operand::Reg(Register::A)), Some(operand)])
+        }
+        _ => (mnemonic, [Some(operand), None]),
+    }
+}
+
+fn decode_cb_opcode(op: u8) -> Instruction {
+    use Mnemonic::*;
+
+    let reg8 = match op & 7 {
+        0 => Operand::Reg(Register::B),
+        1 => Operand::Reg(Register::C),
+        2 => Operand::Reg(Register::D),
+        3 => Operand::Reg(Register::E),
+        4 => Operand::Reg(Register::H),
+        5 => Operand::Reg(Register::L),
+        6 => Operand::Indirect(Register::HL),
+        _ => Operand::Reg(Register::A),
+    };
+    let y = (op >> 3) & 7;
+    let (mnemonic, operands) = match op >> 6 {
+        0 => (
+            [Rlc, Rrc, Rl, Rr, Sla, Sra, Swap, Srl][y as usize],
+            [Some(reg8), None],
+        ),
+        x => (
+            [Bit, Res, Set][x as usize - 1],
+            [Some(Operand::Bit(y)), Some(reg8)],
+        ),
+    };
+
+    Instruction {
+        len: 2,
+        mnemonic,
+        operands,
+        target: None,
+        flags_written: consts::CB_WRITE_FLAG[op as usize],
+        flags_read: consts::CB_READ_FLAG[op as usize],
+    }
+}
@@ -0,0 +1,205 @@
+//! The DMG-07, the 4-player adapter, which connects up to four Game Boys in a hub topology.
+//!
+//! The adapter is the master of every transfer, and sends one byte to each player at the same
+//! time, receiving their bytes in return. The games select the external clock, and wait for the
+//! adapter. It starts in the ping phase, and goes to the transmission phase when player 1 asks:
+//!
+//! - Ping phase: the adapter sends ping packets of 4 bytes, a `0xFE` header followed by the status
+//!   of the player three times. The status has the player number, from 1 to 4, in bits 0-2, and
+//!   which players are connected in bits 4-7, player 1 in bit 4. A player is connected while it
+//!   answers the header and the first status with `0x88`. The answers to the last two bytes are
+//!   the rate and the size of the packets of the transmission phase, which are taken from player
+//!   1.
+//! - Player 1 asks to start the transmission phase by sending `0xAA` in a ping packet, which also
+//!   counts as `0x88`. The adapter finishes the packet, and sends `0xCC` four times.
+//! - Transmission phase: in each cycle, each player sends its packet of `size` bytes, and
+//!   the rest of the cycle, `3 * size` bytes, is ignored. Meanwhile, the adapter broadcasts the
+//!   packets received in the previous cycle, from player 1 to 4, with zeros for players that are
+//!   not connected. If player 1 sends a packet of only `0xFF`, the adapter goes back to the ping
+//!   phase.
+//!
+//! The rate is not emulated: the bytes are exchanged as fast as the games start the transfers.
+
+use alloc::{vec, vec::Vec};
+
+/// The number of ports of the adapter.
+pub const PLAYERS: usize = 4;
+
+/// The first byte of each ping packet.
+pub const PING_HEADER: u8 = 0xFE;
+/// The answer of a player to the first two bytes of a ping packet.
+pub const ACK: u8 = 0x88;
+/// Sent by player 1 in the ping phase, to start the transmission phase.
+pub const START: u8 = 0xAA;
+/// Sent by the adapter four times before the transmission phase.
+pub const STARTING: u8 = 0xCC;
+/// A packet of only this byte, sent by player 1 in the transmission phase, restarts the ping
+/// phase.
+pub const RESTART: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Ping,
+    /// Sending `STARTING`, before the transmission phase.
+    Starting,
+    Transmission,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dmg07 {
+    phase: Phase,
+    /// The position of the next byte in the current packet, or in the current cycle of the
+    /// transmission phase.
+    position: usize,
+    /// The connected players, by bit, player 1 in bit 0.
+    connected: u8,
+    /// The players that answered with `ACK` to each byte of the current ping packet so far.
+    acks: u8,
+    /// If player 1 sent `START` in the current ping packet.
+    start_requested: bool,
+    /// The rate and the size of the packets, as sent by player 1 in the last ping packet.
+    rate: u8,
+    size: u8,
+    /// The packets received from each player in the current cycle of the transmission phase.
+    receiving: Vec<u8>,
+    /// The packets received in the previous cycle, that are broadcast in the current one.
+    sending: Vec<u8>,
+}
+
+impl Default for Dmg07 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dmg07 {
+    pub fn new() -> Self {
+        Self {
+            phase: Phase::Ping,
+            position: 0,
+            connected: 0,
+            acks: 0,
+            start_requested: false,
+            rate: 0,
+            size: 0,
+            receiving: Vec::new(),
+            sending: Vec::new(),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The connected players, by bit, player 1 in bit 0.
+    pub fn connected(&self) -> u8 {
+        self.connected
+    }
+
+    /// The rate and the size of the packets of the transmission phase, as sent by player 1.
+    pub fn rate_and_size(&self) -> (u8, u8) {
+        (self.rate, self.size)
+    }
+
+    /// The size of each packet of the transmission phase. A size of 0 is taken as 1.
+    fn packet_size(&self) -> usize {
+        self.size.max(1) as usize
+    }
+
+    /// The byte sent to each player in the next transfer.
+    pub fn output(&self) -> [u8; PLAYERS] {
+        match self.phase {
+            Phase::Ping if self.position == 0 => [PING_HEADER; PLAYERS],
+            Phase::Ping => {
+                let mut status = [0; PLAYERS];
+                for (i, x) in status.iter_mut().enumerate() {
+                    *x = (self.connected << 4) | (i as u8 + 1);
+                }
+                status
+            }
+            Phase::Starting => [STARTING; PLAYERS],
+            Phase::Transmission => [self.sending[self.position]; PLAYERS],
+        }
+    }
+
+    /// Finish the transfer started by `output`, with the byte received from each player, or None
+    /// for the players that are not linked, or whose game was not waiting for a transfer.
+    pub fn input(&mut self, received: [Option<u8>; PLAYERS]) {
+        match self.phase {
+            Phase::Ping => {
+                if received[0] == Some(START) {
+                    self.start_requested = true;
+                }
+                match self.position {
+                    0 | 1 => {
+                        let mut acks = 0;
+                        for (i, x) in received.iter().enumerate() {
+                            if *x == Some(ACK) || i == 0 && *x == Some(START) {
+                                acks |= 1 << i;
+                            }
+                        }
+                        self.acks = if self.position == 0 {
+                            acks
+                        } else {
+                            self.acks & acks
+                        };
+                    }
+                    2 => self.rate = received[0].unwrap_or(self.rate),
+                    _ => self.size = received[0].unwrap_or(self.size),
+                }
+                self.position += 1;
+                if self.position == 4 {
+                    self.position = 0;
+                    self.connected = self.acks;
+                    if self.start_requested {
+                        self.start_requested = false;
+                        self.phase = Phase::Starting;
+                    }
+                }
+            }
+            Phase::Starting => {
+                self.position += 1;
+                if self.position == 4 {
+                    let len = PLAYERS * self.packet_size();
+                    self.position = 0;
+                    self.phase = Phase::Transmission;
+                    self.receiving = vec![0; len];
+                    self.sending = vec![0; len];
+                }
+            }
+            Phase::Transmission => {
+                let size = self.packet_size();
+                if self.position < size {
+                    for (i, x) in received.iter().enumerate() {
+                        if self.connected & (1 << i) != 0 {
+                            self.receiving[i * size + self.position] = x.unwrap_or(0);
+                        }
+                    }
+                }
+                self.position += 1;
+                if self.position == self.receiving.len() {
+                    self.position = 0;
+                    if self.receiving[..size].iter().all(|&x| x == RESTART) {
+                        *self = Self {
+                            connected: self.connected,
+                            ..Self::new()
+                        };
+                        return;
+                    }
+                    self.sending = core::mem::replace(&mut self.receiving, vec![0; size * PLAYERS]);
+                }
+            }
+        }
+    }
+
+    /// Do a transfer, calling `exchange` with each player and the byte sent to it, which returns
+    /// the byte received from that player, if any.
+    pub fn transfer(&mut self, mut exchange: impl FnMut(usize, u8) -> Option<u8>) {
+        let output = self.output();
+        let mut received = [None; PLAYERS];
+        for (i, x) in received.iter_mut().enumerate() {
+            *x = exchange(i, output[i]);
+        }
+        self.input(received);
+    }
+}
@@ -1,26 +1,54 @@
-use std::cell::{Cell, RefCell};
+use alloc::boxed::Box;
+use core::cell::{Cell, RefCell};
 
 use crate::{
     disassembler::Trace,
     save_state::{LoadStateError, SaveState, SaveStateContext, SaveStateHeader},
 };
 
+pub mod accuracy;
+pub mod boot_rom;
+pub mod builder;
 pub mod cartridge;
+pub mod colorization;
+pub mod counters;
 pub mod cpu;
+pub mod memory_lock;
+pub mod memory_map;
 pub mod ppu;
+pub mod ram_fill;
 pub mod serial_transfer;
 pub mod sound_controller;
 pub mod timer;
+pub mod unusable_area;
 
 use self::{
-    cartridge::Cartridge, cpu::Cpu, ppu::Ppu, serial_transfer::Serial,
-    sound_controller::SoundController, timer::Timer,
+    accuracy::AccuracyConfig,
+    boot_rom::BootRom,
+    builder::GameBoyBuilder,
+    cartridge::Cartridge,
+    counters::PerfCounters,
+    cpu::Cpu,
+    memory_lock::{LockedWrite, MemoryLocks},
+    ppu::{BlockedWrite, Ppu},
+    ram_fill::RamFill,
+    serial_transfer::Serial,
+    sound_controller::SoundController,
+    timer::Timer,
+    unusable_area::UnusableArea,
 };
 
+/// A callback called after each VBlank. See `GameBoy::v_blank`.
 #[cfg(not(target_arch = "wasm32"))]
-type VBlankCallback = Box<dyn FnMut(&mut GameBoy) + Send>;
+pub type VBlankCallback = Box<dyn FnMut(&mut GameBoy) + Send>;
 #[cfg(target_arch = "wasm32")]
-type VBlankCallback = Box<dyn FnMut(&mut GameBoy)>;
+pub type VBlankCallback = Box<dyn FnMut(&mut GameBoy)>;
+
+/// A callback that receives the cartridge RAM, for persisting it. See `GameBoy::save_ram`.
+#[cfg(not(target_arch = "wasm32"))]
+pub type SaveRamCallback = Box<dyn FnMut(&[u8]) + Send>;
+#[cfg(target_arch = "wasm32")]
+pub type SaveRamCallback = Box<dyn FnMut(&[u8])>;
 
 pub struct GameBoy {
     pub trace: RefCell<Trace>,
@@ -30,7 +58,7 @@ pub struct GameBoy {
     pub wram: [u8; 0x2000],
     /// FF80-FFFE: High RAM
     pub hram: [u8; 0x7F],
-    pub boot_rom: Option<[u8; 0x100]>,
+    pub boot_rom: Option<BootRom>,
     pub boot_rom_active: bool,
     pub clock_count: u64,
     pub timer: RefCell<Timer>,
@@ -59,14 +87,48 @@ pub struct GameBoy {
     /// A callback that is called after a VBlank. This is called when a vblank interrupt is
     /// triggered.
     pub v_blank: Option<VBlankCallback>,
+    /// A callback that persists the cartridge RAM. It is called by `flush_save_ram`, and when the
+    /// game disables the cartridge RAM after writing to it, which games do after saving.
+    pub save_ram: Option<SaveRamCallback>,
+    /// If the cartridge RAM was written since it was last passed to `save_ram`.
+    pub ram_modified: bool,
 
     /// Used to toggle the next interrupt prediction, to be able to test its correctness.
     pub predict_interrupt: bool,
     /// Used to toggle the halt optimization, to allow interpreting with more granuallity.
     pub halt_optimization: bool,
+    /// The initial content of the WRAM, VRAM and OAM, used on `reset`.
+    pub ram_fill: RamFill,
+    /// The behavior of the unusable area, FEA0-FEFF, which depends on the hardware revision.
+    pub unusable_area: UnusableArea,
+    /// Which optimizations that affect the emulation accuracy are enabled.
+    pub accuracy: AccuracyConfig,
 
     /// The clock_count when the next interrupt may happen.
     pub next_interrupt: Cell<u64>,
+    /// A clock_count at which the execution must stop, to allow taking a save state exactly at
+    /// that point. It is included in `next_interrupt`, so the compiled blocks of the JIT exit
+    /// before it, like they would for a interrupt. See `GameBoy::set_stop_clock`.
+    pub stop_clock: Cell<u64>,
+    /// The address and value of the last write to the MBC registers, in 0000-7FFF. Used by the
+    /// debugger for logging bank switches. See `Debugger::execute_command`.
+    pub last_mbc_write: Option<(u16, u8)>,
+    /// The address, in FF00-FF7F, of the last access to a I/O register that is not emulated, and
+    /// the value written, if it was a write. Used by the debugger for reporting them. See
+    /// `Debugger::execute_command`.
+    pub last_unemulated_io: Cell<Option<(u16, Option<u8>)>>,
+    /// The last write to the VRAM or the OAM that was ignored, because the PPU was accessing it.
+    /// Used by the debugger for breaking on them. See `Debugger::execute_command`.
+    pub last_blocked_write: Option<BlockedWrite>,
+    /// The address ranges whose writes are ignored. In a `RefCell` so the debugger can change
+    /// them. See `Debugger::execute_command`.
+    pub memory_locks: RefCell<MemoryLocks>,
+    /// The last write that was ignored because of `memory_locks`. Used by the debugger for
+    /// breaking on the protected ones.
+    pub last_locked_write: Option<LockedWrite>,
+    /// Counters of instructions, interrupts, DMA transfers and bank switches, queried by the
+    /// debugger `stats` command. In a `Cell` so the debugger can reset them.
+    pub counters: Cell<PerfCounters>,
 
     /// trace of reads and writes. (kind | ((clock_count & !3) >> 1), address, value), kind: 0=GameBoy::IO_READ,1=GameBoy::IO_WRITE
     #[cfg(feature = "io_trace")]
@@ -77,8 +139,8 @@ pub struct GameBoy {
     pub vcd_writer: crate::wave_trace::WaveTrace,
 }
 
-impl std::fmt::Debug for GameBoy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for GameBoy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         // TODO: derive Debug for fields when the time arrive.
         f.debug_struct("GameBoy")
             // .field("trace", &self.trace)
@@ -148,6 +210,8 @@ crate::save_state!(GameBoy, self, ctx, data {
 
     bitset [self.boot_rom_active, self.v_blank_trigger];
     // self.v_blank;
+    if ctx.version >= 4 => { self.ram_fill; }
+    if ctx.version >= 5 => { self.accuracy; }
 
     on_load self.update_next_interrupt();
 });
@@ -157,8 +221,27 @@ impl GameBoy {
     #[cfg(feature = "io_trace")]
     pub const IO_WRITE: u8 = 1;
 
-    pub fn new(boot_rom: Option<[u8; 0x100]>, cartridge: Cartridge) -> Self {
-        let mut this = Self {
+    /// Create a GameBoy with the given boot rom and accuracy, and the default for everything else.
+    /// See `GameBoy::builder` for configuring the rest.
+    pub fn new(boot_rom: Option<BootRom>, cartridge: Cartridge, accuracy: AccuracyConfig) -> Self {
+        Self::builder(cartridge)
+            .boot_rom(boot_rom)
+            .accuracy(accuracy)
+            .build()
+    }
+
+    /// Start configuring a GameBoy with the given cartridge. See `GameBoyBuilder`.
+    pub fn builder(cartridge: Cartridge) -> GameBoyBuilder {
+        GameBoyBuilder::new(cartridge)
+    }
+
+    /// Create the GameBoy, before it is reset. Used by `GameBoyBuilder::build`.
+    fn with_parts(
+        boot_rom: Option<BootRom>,
+        cartridge: Cartridge,
+        accuracy: AccuracyConfig,
+    ) -> Self {
+        Self {
             trace: RefCell::new(Trace::new()),
             cpu: Cpu::default(),
             cartridge,
@@ -179,20 +262,28 @@ impl GameBoy {
             interrupt_enabled: 0,
             v_blank_trigger: false.into(),
             v_blank: None,
+            save_ram: None,
+            ram_modified: false,
             predict_interrupt: true,
             halt_optimization: true,
+            ram_fill: RamFill::default(),
+            unusable_area: UnusableArea::default(),
+            accuracy,
             next_interrupt: 0.into(),
+            stop_clock: u64::MAX.into(),
+            last_mbc_write: None,
+            last_unemulated_io: Cell::new(None),
+            last_blocked_write: None,
+            memory_locks: RefCell::default(),
+            last_locked_write: None,
+            counters: Cell::default(),
 
             #[cfg(feature = "io_trace")]
             io_trace: Vec::new().into(),
 
             #[cfg(feature = "wave_trace")]
             vcd_writer: crate::wave_trace::WaveTrace::new().unwrap(),
-        };
-
-        this.reset();
-
-        this
+        }
     }
 
     /// call the `v_blank` callback
@@ -203,21 +294,33 @@ impl GameBoy {
         }
     }
 
+    /// Pass the cartridge RAM to the `save_ram` callback, if it was written since the last call.
+    /// Frontends should call this before the GameBoy is dropped, to not lose unsaved progress.
+    pub fn flush_save_ram(&mut self) {
+        if !self.ram_modified {
+            return;
+        }
+        if let Some(save_ram) = &mut self.save_ram {
+            save_ram(&self.cartridge.ram);
+            self.ram_modified = false;
+        }
+    }
+
     /// Saves the current state of the GameBoy.
     ///
     /// `timestamp` is the instant that this file is being saved, in number of milliseconds since
     /// the UNIX_EPOCH. it may be None if the system could not provide one.
-    pub fn save_state<W: std::io::Write>(
+    pub fn save_state<W: crate::io::Write>(
         &self,
         timestamp: Option<u64>,
         data: &mut W,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         self.update_all();
         let ctx = &mut SaveStateContext::new(timestamp, self.clock_count);
         SaveState::save_state(self, ctx, data)
     }
 
-    pub fn load_state<R: std::io::Read>(&mut self, data: &mut R) -> Result<(), LoadStateError> {
+    pub fn load_state<R: crate::io::Read>(&mut self, data: &mut R) -> Result<(), LoadStateError> {
         let ctx = &mut SaveStateContext::default();
         self.update_all();
         SaveState::load_state(self, ctx, data)
@@ -227,9 +330,22 @@ impl GameBoy {
     pub fn reset(&mut self) {
         if self.boot_rom.is_none() {
             self.reset_after_boot();
-            return;
+        } else {
+            self.reset_at_power_on();
         }
-        self.reset_at_power_on();
+        self.counters.get_mut().reset(self.clock_count);
+    }
+
+    /// Turn the gameboy off and on again, with the RAM refilled following `ram_fill`.
+    ///
+    /// Differently from `reset`, which keeps the cartridge RAM, the RAM of a cartridge without a
+    /// battery loses its content too.
+    pub fn power_cycle(&mut self, ram_fill: RamFill) {
+        self.ram_fill = ram_fill;
+        if !self.cartridge.header.has_battery() {
+            ram_fill.fill(&mut self.cartridge.ram, RamFill::CARTRIDGE_RAM);
+        }
+        self.reset();
     }
 
     /// Reset the gameboy to its state after powering on, before the boot rom is executed, even if
@@ -239,13 +355,22 @@ impl GameBoy {
     pub(crate) fn reset_at_power_on(&mut self) {
         // TODO: Maybe I should reset the cartridge
         self.cpu = Cpu::default();
-        self.wram = [0xFF; 0x2000];
+        self.ram_fill.fill(&mut self.wram, RamFill::WRAM);
         self.hram = [0xFF; 0x7F];
         self.boot_rom_active = true;
         self.clock_count = 0;
         self.timer = Timer::new().into();
-        self.sound = RefCell::new(SoundController::default());
+        self.sound.get_mut().reset();
+        let pixel_sources = self.ppu.get_mut().pixel_sources_enabled();
+        let colorization = self.ppu.get_mut().colorization_enabled();
         self.ppu = Ppu::default().into();
+        self.ppu.get_mut().set_pixel_sources_enabled(pixel_sources);
+        self.ppu.get_mut().set_colorization_enabled(colorization);
+        {
+            let ppu = self.ppu.get_mut();
+            self.ram_fill.fill(&mut ppu.vram, RamFill::VRAM);
+            self.ram_fill.fill(&mut ppu.oam, RamFill::OAM);
+        }
         self.joypad = 0xFF;
         self.joypad_io = 0xCF;
 
@@ -276,13 +401,16 @@ impl GameBoy {
             op: 0,
         };
 
-        self.wram = [0xFF; 0x2000];
+        self.ram_fill.fill(&mut self.wram, RamFill::WRAM);
         self.hram = [0xFF; 0x7F];
         self.hram[0x7a..=0x7c].copy_from_slice(&[0x39, 0x01, 0x2e]);
 
         self.boot_rom_active = false;
         self.clock_count = 23_440_324;
         self.ppu.get_mut().reset_after_boot();
+        // the boot rom clears the VRAM, but does not touch the OAM.
+        self.ram_fill
+            .fill(&mut self.ppu.get_mut().oam, RamFill::OAM);
 
         self.joypad = 0xFF;
 
@@ -300,11 +428,14 @@ impl GameBoy {
     }
 
     pub fn read(&self, mut address: u16) -> u8 {
-        if self.boot_rom_active && address < 0x100 {
+        if self.boot_rom_active && address < 0x8000 {
             let boot_rom = self
                 .boot_rom
+                .as_ref()
                 .expect("the boot rom is only actived when there is one");
-            return boot_rom[address as usize];
+            if let Some(value) = boot_rom.read(address) {
+                return value;
+            }
         }
         if (0xE000..=0xFDFF).contains(&address) {
             address -= 0x2000;
@@ -323,17 +454,39 @@ impl GameBoy {
             // Sprite Attribute table
             0xFE00..=0xFE9F => Ppu::read_oam(self, address),
             // Not Usable
-            0xFEA0..=0xFEFF => 0xff,
+            0xFEA0..=0xFEFF => Ppu::read_unusable(self, address),
             // I/O registers and Hight RAM
             0xFF00..=0xFFFF => self.read_io(address as u8),
         }
     }
 
+    /// Read the value at `address` for inspection, like `read`, but without being reported as a
+    /// access to a unemulated I/O register (see `last_unemulated_io`). Used by the debugger and
+    /// the other tools that look at the memory without the game doing it.
+    pub fn peek(&self, address: u16) -> u8 {
+        let last_unemulated_io = self.last_unemulated_io.get();
+        let value = self.read(address);
+        self.last_unemulated_io.set(last_unemulated_io);
+        value
+    }
+
     pub fn write(&mut self, mut address: u16, value: u8) {
         if (0xE000..=0xFDFF).contains(&address) {
             address -= 0x2000;
         }
 
+        let locks = self.memory_locks.get_mut();
+        if !locks.is_empty() {
+            if let Some(kind) = locks.find(address) {
+                self.last_locked_write = Some(LockedWrite {
+                    address,
+                    value,
+                    kind,
+                });
+                return;
+            }
+        }
+
         // When writing to the ppu, the ppu will already be updated, but with a special timing.
         let will_write_ppu =
             (0xff40..=0xff45).contains(&address) || (0xff47..=0xff4b).contains(&address);
@@ -343,11 +496,34 @@ impl GameBoy {
 
         match address {
             // Cartridge ROM
-            0x0000..=0x7FFF => self.cartridge.write(address, value),
+            0x0000..=0x7FFF => {
+                let banks = self.cartridge.curr_bank();
+                self.cartridge.write(address, value);
+                self.last_mbc_write = Some((address, value));
+                if self.cartridge.curr_bank() != banks {
+                    crate::tracing_event!(
+                        DEBUG,
+                        clock_count = self.clock_count,
+                        from = ?banks,
+                        to = ?self.cartridge.curr_bank(),
+                        "rom bank switch by write {:02x} to {:04x}",
+                        value,
+                        address
+                    );
+                    self.counters.get_mut().bank_switches += 1;
+                }
+                // a write to the RAM enable register, that is not enabling it.
+                if address < 0x2000 && value & 0x0F != 0x0A {
+                    self.flush_save_ram();
+                }
+            }
             // Video RAM
             0x8000..=0x9FFF => Ppu::write_vram(self, address, value),
             // Cartridge RAM
-            0xA000..=0xBFFF => self.cartridge.write(address, value),
+            0xA000..=0xBFFF => {
+                self.cartridge.write(address, value);
+                self.ram_modified |= !self.cartridge.ram.is_empty();
+            }
             // Work RAM
             0xC000..=0xDFFF => self.wram[address as usize - 0xC000] = value,
             // ECHO RAM
@@ -383,7 +559,8 @@ impl GameBoy {
                 .borrow()
                 .next_interrupt
                 .min(self.timer.borrow().next_interrupt)
-                .min(self.serial.borrow().next_interrupt),
+                .min(self.serial.borrow().next_interrupt)
+                .min(self.stop_clock.get()),
         );
 
         // If the interrupt_enabled was modified in the last instructions, the next interrupt may
@@ -395,6 +572,16 @@ impl GameBoy {
         }
     }
 
+    /// Request the execution to stop at the first instruction boundary at or after `clock_count`,
+    /// even when running compiled blocks. None clears the request.
+    ///
+    /// The stop clock must be cleared after it is reached, otherwise the interrupts will be checked
+    /// after every instruction, and the JIT will always fallback to the interpreter.
+    pub fn set_stop_clock(&self, clock_count: Option<u64>) {
+        self.stop_clock.set(clock_count.unwrap_or(u64::MAX));
+        self.update_next_interrupt();
+    }
+
     pub fn update_interrupt(&self) {
         if !self.predict_interrupt {
             self.update_all_interrupts();
@@ -433,13 +620,23 @@ impl GameBoy {
             .unwrap();
     }
 
-    fn update_ppu(&self) {
+    pub(crate) fn update_ppu(&self) {
         let (v_blank_interrupt, stat_interrupt) = Ppu::update(self);
         if stat_interrupt {
+            crate::tracing_event!(
+                DEBUG,
+                clock_count = self.clock_count,
+                "stat interrupt requested"
+            );
             self.interrupt_flag
                 .set(self.interrupt_flag.get() | (1 << 1));
         }
         if v_blank_interrupt {
+            crate::tracing_event!(
+                DEBUG,
+                clock_count = self.clock_count,
+                "vblank interrupt requested"
+            );
             self.interrupt_flag
                 .set(self.interrupt_flag.get() | (1 << 0));
             self.v_blank_trigger.set(true);
@@ -454,6 +651,11 @@ impl GameBoy {
             #[cfg(feature = "wave_trace")]
             &self.vcd_writer,
         ) {
+            crate::tracing_event!(
+                DEBUG,
+                clock_count = self.clock_count,
+                "timer interrupt requested"
+            );
             self.interrupt_flag
                 .set(self.interrupt_flag.get() | (1 << 2));
         }
@@ -479,17 +681,28 @@ impl GameBoy {
         u16::from_le_bytes([self.read(address), self.read(address.wrapping_add(1))])
     }
 
+    /// Record a access to a I/O register that is not emulated, returning the value it reads as.
+    fn unemulated_io(&self, address: u8, value: Option<u8>) -> u8 {
+        self.last_unemulated_io
+            .set(Some((0xFF00 | address as u16, value)));
+        0xff
+    }
+
     fn write_io(&mut self, address: u8, value: u8) {
         match address {
             0x00 => self.joypad_io = 0b1100_1111 | (value & 0x30), // JOYPAD
             0x01..=0x02 => Serial::write(self, address, value),
-            0x03 => {}
+            0x03 => {
+                self.unemulated_io(address, Some(value));
+            }
             0x04..=0x07 => {
                 self.update_timer();
                 self.timer.get_mut().write(address, value);
                 self.update_next_interrupt();
             }
-            0x08..=0x0e => {}
+            0x08..=0x0e => {
+                self.unemulated_io(address, Some(value));
+            }
             0x0f => {
                 *self.interrupt_flag.get_mut() = value & 0x1f;
                 self.update_interrupt();
@@ -497,23 +710,34 @@ impl GameBoy {
             0x10..=0x14 | 0x16..=0x1e | 0x20..=0x26 | 0x30..=0x3f => {
                 self.sound.get_mut().write(self.clock_count, address, value)
             }
-            0x15 => {}
-            0x1f => {}
-            0x27..=0x2f => {}
+            0x15 => {
+                self.unemulated_io(address, Some(value));
+            }
+            0x1f => {
+                self.unemulated_io(address, Some(value));
+            }
+            0x27..=0x2f => {
+                self.unemulated_io(address, Some(value));
+            }
             0x40..=0x45 => Ppu::write(self, address, value),
             0x46 => {
                 // DMA Transfer
                 Ppu::start_dma(self, value);
+                self.counters.get_mut().dma_transfers += 1;
             }
             0x47..=0x4b => Ppu::write(self, address, value),
-            0x4c..=0x4f => {}
+            0x4c..=0x4f => {
+                self.unemulated_io(address, Some(value));
+            }
             0x50 => {
                 if self.boot_rom_active && value & 0b1 != 0 {
                     self.boot_rom_active = false;
                     self.cpu.pc = 0x100;
                 }
             }
-            0x51..=0x7f => {}
+            0x51..=0x7f => {
+                self.unemulated_io(address, Some(value));
+            }
             0x80..=0xfe => self.hram[address as usize - 0x80] = value,
             0xff => {
                 self.interrupt_enabled = value;
@@ -540,12 +764,12 @@ impl GameBoy {
                 r
             }
             0x01..=0x02 => Serial::read(self, address),
-            0x03 => 0xff,
+            0x03 => self.unemulated_io(address, None),
             0x04..=0x07 => {
                 self.update_timer();
                 self.timer.borrow().read(address)
             }
-            0x08..=0x0e => 0xff,
+            0x08..=0x0e => self.unemulated_io(address, None),
             0x0f => {
                 self.update_interrupt();
                 self.interrupt_flag.get() | 0xE0
@@ -553,17 +777,15 @@ impl GameBoy {
             0x10..=0x14 | 0x16..=0x1e | 0x20..=0x26 | 0x30..=0x3f => {
                 self.sound.borrow_mut().read(self.clock_count, address)
             }
-            0x15 => 0xff,
-            0x1f => 0xff,
-            0x27..=0x2f => 0xff,
+            0x15 => self.unemulated_io(address, None),
+            0x1f => self.unemulated_io(address, None),
+            0x27..=0x2f => self.unemulated_io(address, None),
             0x40..=0x45 => Ppu::read(self, address),
             0x46 => self.dma,
             0x47..=0x4b => Ppu::read(self, address),
-            0x4c => 0xff,
-            0x4d => 0xff,
-            0x4e..=0x4f => 0xff,
+            0x4c..=0x4f => self.unemulated_io(address, None),
             0x50 => 0xff,
-            0x51..=0x7F => 0xff,
+            0x51..=0x7F => self.unemulated_io(address, None),
             0x80..=0xfe => self.hram[address as usize - 0x80],
             0xff => self.interrupt_enabled,
         }
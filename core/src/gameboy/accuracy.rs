@@ -0,0 +1,64 @@
+use crate::save_state::{LoadStateError, SaveState};
+
+/// Toggles that trade emulation accuracy for speed.
+///
+/// Most games don't depend on the timing details that these toggles disable, so they can run
+/// faster without any observable difference. The presets `ACCURATE`, `BALANCED` and `FAST` bundle
+/// them in increasing order of speed.
+///
+/// `stat_write_bug` is the exception: it emulates a hardware quirk that some games depend on, and
+/// costs nothing, but is disabled in `FAST` for the games that break with it.
+///
+/// The OAM corruption bug is not emulated yet, so there is no toggle for it: all profiles behave as
+/// if it was disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccuracyConfig {
+    /// Render a whole scanline at once, when it is entirely in the past, instead of running the
+    /// pixel FIFO for each dot. Any access to the PPU in the middle of a scanline falls back to the
+    /// pixel FIFO, so this should not be observable.
+    pub simple_ppu: bool,
+    /// Copy the OAM DMA at once, when it is started, instead of after 160 machine cycles. The OAM
+    /// is never blocked during the transfer.
+    pub instant_dma: bool,
+    /// Draw all sprites of a scanline, instead of only the first 10 ones. Only has effect in
+    /// scanlines rendered by the simple PPU renderer.
+    pub lift_sprite_limit: bool,
+    /// Emulate the DMG quirk where writing to STAT enables all STAT interrupt sources for a cycle,
+    /// which triggers a STAT interrupt if any of the conditions is true at the time of the write.
+    pub stat_write_bug: bool,
+}
+
+impl AccuracyConfig {
+    /// Emulate everything as precisely as possible.
+    pub const ACCURATE: Self = Self {
+        simple_ppu: false,
+        instant_dma: false,
+        lift_sprite_limit: false,
+        stat_write_bug: true,
+    };
+    /// Only enable optimizations that should not be observable by games.
+    pub const BALANCED: Self = Self {
+        simple_ppu: true,
+        instant_dma: false,
+        lift_sprite_limit: false,
+        stat_write_bug: true,
+    };
+    /// Enable all optimizations, even the ones that may break some games.
+    pub const FAST: Self = Self {
+        simple_ppu: true,
+        instant_dma: true,
+        lift_sprite_limit: true,
+        stat_write_bug: false,
+    };
+}
+
+// saved with the state, so it keeps the timing it was saved with.
+crate::save_state!(AccuracyConfig, self, data {
+    bitset [self.simple_ppu, self.instant_dma, self.lift_sprite_limit, self.stat_write_bug];
+});
+
+impl Default for AccuracyConfig {
+    fn default() -> Self {
+        Self::BALANCED
+    }
+}
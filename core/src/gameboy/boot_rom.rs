@@ -0,0 +1,101 @@
+//! The boot ROM, mapped over the start of the cartridge ROM until it is disabled by a write to
+//! FF50.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// A boot ROM image.
+///
+/// The first 0x100 bytes are mapped at 0000-00FF, like the DMG, MGB and SGB boot ROMs. Larger
+/// images follow the layout of the CGB boot ROM: the rest of the image is mapped from 0200 up to
+/// its end, while 0100-01FF keeps showing the cartridge header (the image bytes there are
+/// ignored).
+#[derive(Clone, PartialEq, Eq)]
+pub struct BootRom(Box<[u8]>);
+
+impl BootRom {
+    /// The largest image, which covers the entire cartridge ROM area.
+    pub const MAX_LEN: usize = 0x8000;
+
+    pub fn new(data: Vec<u8>) -> Result<Self, String> {
+        if data.is_empty() {
+            return Err("the boot rom is empty".to_string());
+        }
+        if data.len() > Self::MAX_LEN {
+            return Err(format!(
+                "the boot rom has {} bytes, but at most {} bytes can be mapped",
+                data.len(),
+                Self::MAX_LEN
+            ));
+        }
+        Ok(Self(data.into_boxed_slice()))
+    }
+
+    /// The bytes of the image.
+    pub fn data(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The byte at `address`, or None if the address is not mapped to the boot ROM.
+    #[inline]
+    pub fn read(&self, address: u16) -> Option<u8> {
+        let address = address as usize;
+        if (0x100..0x200).contains(&address) {
+            return None;
+        }
+        self.0.get(address).copied()
+    }
+}
+
+impl From<[u8; 0x100]> for BootRom {
+    fn from(data: [u8; 0x100]) -> Self {
+        Self(Box::new(data))
+    }
+}
+
+impl core::fmt::Debug for BootRom {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "BootRom({} bytes)", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BootRom;
+
+    #[test]
+    fn dmg_layout() {
+        let boot_rom = BootRom::from([0xAB; 0x100]);
+        assert_eq!(boot_rom.read(0x0000), Some(0xAB));
+        assert_eq!(boot_rom.read(0x00FF), Some(0xAB));
+        assert_eq!(boot_rom.read(0x0100), None);
+        assert_eq!(boot_rom.read(0x0200), None);
+    }
+
+    #[test]
+    fn cgb_layout() {
+        let mut data = vec![0; 0x900];
+        data[0x0FF] = 1;
+        data[0x100] = 2;
+        data[0x200] = 3;
+        data[0x8FF] = 4;
+        let boot_rom = BootRom::new(data).unwrap();
+        assert_eq!(boot_rom.read(0x00FF), Some(1));
+        assert_eq!(boot_rom.read(0x0100), None);
+        assert_eq!(boot_rom.read(0x01FF), None);
+        assert_eq!(boot_rom.read(0x0200), Some(3));
+        assert_eq!(boot_rom.read(0x08FF), Some(4));
+        assert_eq!(boot_rom.read(0x0900), None);
+    }
+
+    #[test]
+    fn invalid_size() {
+        assert!(BootRom::new(Vec::new()).is_err());
+        assert!(BootRom::new(vec![0; BootRom::MAX_LEN + 1]).is_err());
+        assert!(BootRom::new(vec![0; BootRom::MAX_LEN]).is_ok());
+    }
+}
@@ -0,0 +1,113 @@
+use super::{
+    accuracy::AccuracyConfig,
+    boot_rom::BootRom,
+    cartridge::Cartridge,
+    ram_fill::RamFill,
+    serial_transfer::{self, SerialCallback},
+    unusable_area::UnusableArea,
+    GameBoy, SaveRamCallback, VBlankCallback,
+};
+
+/// Configures a `GameBoy` before it is created. See `GameBoy::builder`.
+///
+/// The emulated hardware is always a DMG, so the hardware model is chosen by the boot rom that is
+/// run, if any, and by `unusable_area`.
+pub struct GameBoyBuilder {
+    cartridge: Cartridge,
+    boot_rom: Option<BootRom>,
+    accuracy: AccuracyConfig,
+    ram_fill: RamFill,
+    unusable_area: UnusableArea,
+    predict_interrupt: bool,
+    halt_optimization: bool,
+    serial_sink: Option<SerialCallback>,
+    v_blank: Option<VBlankCallback>,
+    save_ram: Option<SaveRamCallback>,
+}
+
+impl GameBoyBuilder {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self {
+            cartridge,
+            boot_rom: None,
+            accuracy: AccuracyConfig::default(),
+            ram_fill: RamFill::default(),
+            unusable_area: UnusableArea::default(),
+            predict_interrupt: true,
+            halt_optimization: true,
+            serial_sink: serial_transfer::default_callback(),
+            v_blank: None,
+            save_ram: None,
+        }
+    }
+
+    /// The boot rom run at power on. Without one, the GameBoy starts in the state left by the DMG
+    /// boot rom.
+    pub fn boot_rom(mut self, boot_rom: Option<BootRom>) -> Self {
+        self.boot_rom = boot_rom;
+        self
+    }
+
+    pub fn accuracy(mut self, accuracy: AccuracyConfig) -> Self {
+        self.accuracy = accuracy;
+        self
+    }
+
+    /// The initial content of the RAM. See `GameBoy::ram_fill`.
+    pub fn ram_fill(mut self, ram_fill: RamFill) -> Self {
+        self.ram_fill = ram_fill;
+        self
+    }
+
+    /// The behavior of FEA0-FEFF. See `UnusableArea`.
+    pub fn unusable_area(mut self, unusable_area: UnusableArea) -> Self {
+        self.unusable_area = unusable_area;
+        self
+    }
+
+    /// See `GameBoy::predict_interrupt`.
+    pub fn predict_interrupt(mut self, predict_interrupt: bool) -> Self {
+        self.predict_interrupt = predict_interrupt;
+        self
+    }
+
+    /// See `GameBoy::halt_optimization`.
+    pub fn halt_optimization(mut self, halt_optimization: bool) -> Self {
+        self.halt_optimization = halt_optimization;
+        self
+    }
+
+    /// The callback that receives each byte sent through the serial port. By default they are
+    /// printed to stderr, with the `std` feature. `None` discards them.
+    pub fn serial_sink(mut self, sink: Option<SerialCallback>) -> Self {
+        self.serial_sink = sink;
+        self
+    }
+
+    /// The callback called after each VBlank. See `GameBoy::v_blank`.
+    pub fn v_blank(mut self, v_blank: VBlankCallback) -> Self {
+        self.v_blank = Some(v_blank);
+        self
+    }
+
+    /// The callback that persists the cartridge RAM. See `GameBoy::save_ram`. Without one, the
+    /// cartridge RAM is only kept in memory.
+    pub fn save_ram(mut self, save_ram: SaveRamCallback) -> Self {
+        self.save_ram = Some(save_ram);
+        self
+    }
+
+    /// Create the GameBoy, already reset to its starting state.
+    pub fn build(self) -> GameBoy {
+        let mut gb = GameBoy::with_parts(self.boot_rom, self.cartridge, self.accuracy);
+        gb.ram_fill = self.ram_fill;
+        gb.unusable_area = self.unusable_area;
+        gb.predict_interrupt = self.predict_interrupt;
+        gb.halt_optimization = self.halt_optimization;
+        gb.serial.get_mut().serial_transfer_callback = self.serial_sink;
+        gb.v_blank = self.v_blank;
+        gb.save_ram = self.save_ram;
+        gb.reset();
+        gb
+    }
+}
@@ -1,6 +1,15 @@
-use std::{convert::TryInto, fmt::Write, io::Read};
-
-use crate::save_state::{LoadStateError, SaveState, SaveStateContext};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{convert::TryInto, fmt::Write};
+
+use crate::{
+    io::Read,
+    save_state::{LoadStateError, SaveState, SaveStateContext},
+};
 
 const NINTENDOO_LOGO: [u8; 48] = [
     0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
@@ -134,6 +143,15 @@ impl CartridgeHeader {
         Self::from_bytes(&bytes[0..len])
     }
 
+    /// Return true if the cartridge type has a battery, which keeps the content of the cartridge
+    /// RAM while the GameBoy is powered off.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFE | 0xFF
+        )
+    }
+
     /// Return true if it has the correct values for the first  0x18  bytes of the Nintendo logo.
     pub fn check_logo(&self) -> bool {
         self.logo[..0x18] == NINTENDOO_LOGO[..0x18]
@@ -341,6 +359,104 @@ impl MbcSpecification {
     }
 }
 
+/// A report of the integrity of a ROM. See `Cartridge::validation`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RomValidation {
+    /// If the Nintendo logo in the header is correct. The boot rom locks up if it is not.
+    pub logo_ok: bool,
+    /// The header checksum stored in the header, and the one computed from the ROM. The boot rom
+    /// locks up if they don't match.
+    pub header_checksum: (u8, u8),
+    /// The global checksum stored in the header, and the one computed from the ROM. It is not
+    /// checked by the hardware, but a mismatch indicates a bad dump or a patched ROM.
+    pub global_checksum: (u16, u16),
+    /// The size of the ROM file, and the size declared in the header, if valid.
+    pub size: (usize, Option<usize>),
+    /// The size of the save file loaded by `Cartridge::load_ram`, and the size of the cartridge
+    /// RAM, if a save file was loaded.
+    pub save_size: Option<(usize, usize)>,
+}
+impl RomValidation {
+    pub fn new(rom: &[u8], header: &CartridgeHeader) -> Self {
+        let declared_size = header.rom_size_in_bytes();
+        let checked = &rom[..declared_size.unwrap_or(rom.len()).min(rom.len())];
+        Self {
+            logo_ok: header.check_logo(),
+            header_checksum: (
+                header.header_checksum,
+                CartridgeHeader::compute_check_sum(rom),
+            ),
+            // the global checksum is stored in big-endian.
+            global_checksum: (
+                u16::from_be_bytes([rom[0x14E], rom[0x14F]]),
+                Self::compute_global_checksum(checked),
+            ),
+            size: (rom.len(), declared_size),
+            save_size: None,
+        }
+    }
+
+    /// The sum of all bytes of the ROM, except the two bytes of the global checksum itself.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        let sum = rom.iter().fold(0u16, |sum, &x| sum.wrapping_add(x as u16));
+        let checksum = rom.get(0x14E..=0x14F).unwrap_or(&[]);
+        checksum
+            .iter()
+            .fold(sum, |sum, &x| sum.wrapping_sub(x as u16))
+    }
+
+    /// Return true if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.warnings().is_empty()
+    }
+
+    /// A description of each problem found in the ROM.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.logo_ok {
+            warnings.push("the Nintendo logo in the header is invalid".to_string());
+        }
+        let (expected, computed) = self.header_checksum;
+        if expected != computed {
+            warnings.push(format!(
+                "header checksum don't match: expected {:02x}, computed {:02x}",
+                expected, computed
+            ));
+        }
+        let (expected, computed) = self.global_checksum;
+        if expected != computed {
+            warnings.push(format!(
+                "global checksum don't match: expected {:04x}, computed {:04x}",
+                expected, computed
+            ));
+        }
+        match self.size {
+            (_, None) => warnings.push("the ROM size in the header is invalid".to_string()),
+            (file, Some(declared)) if file > declared => warnings.push(format!(
+                "the file is bigger than the declared ROM size ({} > {} bytes), it may be a overdump",
+                file, declared
+            )),
+            (file, Some(declared)) if file < declared => warnings.push(format!(
+                "the file is smaller than the declared ROM size ({} < {} bytes), it may be a bad dump",
+                file, declared
+            )),
+            _ => {}
+        }
+        match self.save_size {
+            Some((file, ram)) if file > ram => warnings.push(format!(
+                "the save file is bigger than the cartridge RAM ({} > {} bytes), it was truncated",
+                file, ram
+            )),
+            Some((file, ram)) if file < ram => warnings.push(format!(
+                "the save file is smaller than the cartridge RAM ({} < {} bytes), it was padded with zeros",
+                file, ram
+            )),
+            _ => {}
+        }
+        warnings
+    }
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct Cartridge {
     pub header: CartridgeHeader,
@@ -351,10 +467,40 @@ pub struct Cartridge {
     pub rom: Vec<u8>,
     pub ram: Vec<u8>,
     mbc: Mbc,
+    validation: RomValidation,
+}
+
+/// The registers of the MBC of a cartridge, as returned by `Cartridge::mbc_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbcState {
+    /// The ROM bank mapped in 0000..=3FFF.
+    pub lower_rom_bank: u16,
+    /// The ROM bank mapped in 4000..=7FFF.
+    pub upper_rom_bank: u16,
+    /// The RAM bank mapped in A000..=BFFF. None if the cartridge has no RAM, or if a RTC register
+    /// is mapped instead.
+    pub ram_bank: Option<usize>,
+    pub ram_enabled: bool,
+    /// The banking mode of a MBC1, 0 or 1.
+    pub banking_mode: Option<u8>,
+    /// The RTC of a MBC3.
+    pub rtc: Option<RtcState>,
 }
 
-impl std::fmt::Debug for Cartridge {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// The RTC of a MBC3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtcState {
+    /// The latched registers: seconds, minutes, hours, and the lower and upper bits of the day
+    /// counter.
+    pub registers: [u8; 5],
+    /// The register mapped in A000..=BFFF, from 08 to 0C, if any.
+    pub selected: Option<u8>,
+    /// If 00 was written to 6000..=7FFF, so writing 01 next latches the clock.
+    pub latch_armed: bool,
+}
+
+impl core::fmt::Debug for Cartridge {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Cartridge")
             // .field("header", &self.header)
             .field("lower_bank", &self.lower_bank)
@@ -371,8 +517,8 @@ impl SaveState for Cartridge {
     fn save_state(
         &self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         // self.rom.save_state(data)?;
         self.ram.save_state(ctx, data)?;
         match &self.mbc {
@@ -411,6 +557,16 @@ impl Cartridge {
         Self::new_maybe_with_spec(rom, None)
     }
 
+    /// Create a cartridge from a copy of the given ROM, like one embedded with `include_bytes!`,
+    /// without touching the filesystem. Problems that still allow the ROM to be loaded are ignored,
+    /// see `Cartridge::new` for reporting them.
+    pub fn from_bytes(rom: &[u8]) -> Result<Self, String> {
+        match Self::new(rom.to_vec()) {
+            Ok(x) | Err((_, Some(x))) => Ok(x),
+            Err((err, None)) => Err(err),
+        }
+    }
+
     pub fn new_with_spec_str(
         rom: Vec<u8>,
         spec: Option<&str>,
@@ -449,6 +605,8 @@ impl Cartridge {
             },
         };
 
+        let validation = RomValidation::new(&rom, &header);
+
         let rom_size = spec.rom_size;
 
         // resize the rom in case the header expected a different size
@@ -470,6 +628,7 @@ impl Cartridge {
             rom,
             ram: vec![0; spec.ram_size],
             mbc,
+            validation,
         };
 
         if !error.is_empty() {
@@ -498,6 +657,42 @@ impl Cartridge {
         this
     }
 
+    /// Replace the cartridge RAM with the content of a save file.
+    ///
+    /// A save file of a different size than the RAM declared in the header is padded with zeros or
+    /// truncated, and reported in `validation`. Some emulators append a 48 bytes RTC footer to the
+    /// save, which is truncated in the same way. Returns a error, without changing the RAM, if the
+    /// cartridge has no RAM, or if the save file is bigger than any cartridge RAM could be.
+    pub fn load_ram(&mut self, mut ram: Vec<u8>) -> Result<(), String> {
+        // the largest cartridge RAM, plus a RTC footer.
+        const MAX_SAVE_SIZE: usize = 0x20000 + 48;
+
+        let expected = self.ram.len();
+        if expected == 0 && !ram.is_empty() {
+            return Err(format!(
+                "the cartridge has no RAM, but the save file has {} bytes",
+                ram.len()
+            ));
+        }
+        if ram.len() > MAX_SAVE_SIZE {
+            return Err(format!(
+                "the save file is too big ({} bytes), expected {} bytes",
+                ram.len(),
+                expected
+            ));
+        }
+
+        self.validation.save_size = Some((ram.len(), expected));
+        ram.resize(expected, 0);
+        self.ram = ram;
+        Ok(())
+    }
+
+    /// The integrity report of the ROM, computed when the cartridge was loaded.
+    pub fn validation(&self) -> &RomValidation {
+        &self.validation
+    }
+
     /// The number of banks in this cartridge. A cartridge without bank switching have 2 banks.
     pub fn num_banks(&self) -> u8 {
         (self.rom.len() / 0x4000) as u8
@@ -515,6 +710,123 @@ impl Cartridge {
         }
     }
 
+    /// Set the time, in seconds, that the RTC of the cartridge will report. Does nothing if the
+    /// cartridge does not have a RTC.
+    ///
+    /// The RTC never reads the host clock, so emulation stays deterministic.
+    pub fn set_rtc_time(&mut self, seconds: u64) {
+        if let Mbc::Mbc3(x) = &mut self.mbc {
+            x.rtc_time = seconds;
+        }
+    }
+
+    /// Describe the effect of writing `value` to the MBC register at `address`, in 0000-7FFF,
+    /// decoded according to the kind of the cartridge.
+    pub fn describe_mbc_write(&self, address: u16, value: u8) -> String {
+        let ram_enable = |value: u8| {
+            if value & 0x0F == 0x0A {
+                "RAM enable".to_string()
+            } else {
+                "RAM disable".to_string()
+            }
+        };
+        match (&self.mbc, address) {
+            (Mbc::None(_), _) => "ignored, no MBC".to_string(),
+            (Mbc::Mbc1(_) | Mbc::Mbc1M(_), 0x0000..=0x1FFF) => ram_enable(value),
+            (Mbc::Mbc1(_), 0x2000..=0x3FFF) => format!("ROM bank low bits = {:02x}", value & 0x1F),
+            (Mbc::Mbc1M(_), 0x2000..=0x3FFF) => format!("ROM bank low bits = {:02x}", value & 0x0F),
+            (Mbc::Mbc1(_) | Mbc::Mbc1M(_), 0x4000..=0x5FFF) => {
+                format!("RAM bank / ROM bank high bits = {}", value & 0x03)
+            }
+            (Mbc::Mbc1(_) | Mbc::Mbc1M(_), _) => format!("banking mode = {}", value & 0x01),
+            (Mbc::Mbc2(_), 0x0000..=0x3FFF) if address & 0x100 == 0 => ram_enable(value),
+            (Mbc::Mbc2(_), 0x0000..=0x3FFF) => format!("ROM bank = {:02x}", value & 0x0F),
+            (Mbc::Mbc2(_), _) => "ignored".to_string(),
+            (Mbc::Mbc3(_), 0x0000..=0x1FFF) => ram_enable(value) + " and RTC",
+            (Mbc::Mbc3(_), 0x2000..=0x3FFF) => format!("ROM bank = {:02x}", value & 0x7F),
+            (Mbc::Mbc3(_), 0x4000..=0x5FFF) if value >= 0x08 => {
+                format!("RTC register = {:02x}", value)
+            }
+            (Mbc::Mbc3(_), 0x4000..=0x5FFF) => format!("RAM bank = {}", value),
+            (Mbc::Mbc3(_), _) => format!("latch clock data = {}", value),
+            (Mbc::Mbc5(_), 0x0000..=0x1FFF) if value == 0x0A => "RAM enable".to_string(),
+            (Mbc::Mbc5(_), 0x0000..=0x1FFF) => "RAM disable".to_string(),
+            (Mbc::Mbc5(_), 0x2000..=0x2FFF) => format!("ROM bank low bits = {:02x}", value),
+            (Mbc::Mbc5(_), 0x3000..=0x3FFF) => format!("ROM bank bit 8 = {}", value & 0x01),
+            (Mbc::Mbc5(_), 0x4000..=0x5FFF) => format!("RAM bank = {}", value & 0x0F),
+            (Mbc::Mbc5(_), _) => "ignored".to_string(),
+        }
+    }
+
+    /// The current state of the registers of the MBC.
+    pub fn mbc_state(&self) -> MbcState {
+        let ram_len = self.ram.len();
+        // the bank of the RAM that is mapped when the given bank is selected, wrapping around the
+        // size of the RAM.
+        let ram_bank = |bank: usize| (ram_len != 0).then(|| bank * 0x2000 % ram_len / 0x2000);
+        // the RAM bank selected in mode 1 of the MBC1, that is ignored by large ROMs.
+        let mbc1_ram_bank = |selected_bank: u8, mode: bool| {
+            let large_rom = self.rom.len() >= 0x10_0000;
+            if mode && !large_rom {
+                ((selected_bank >> 5) & 0x03) as usize
+            } else {
+                0
+            }
+        };
+        let mut state = MbcState {
+            lower_rom_bank: self.lower_bank,
+            upper_rom_bank: self.upper_bank,
+            ram_bank: ram_bank(0),
+            ram_enabled: true,
+            banking_mode: None,
+            rtc: None,
+        };
+        match &self.mbc {
+            Mbc::None(_) => {}
+            Mbc::Mbc1(x) => {
+                state.ram_bank = ram_bank(mbc1_ram_bank(x.selected_bank, x.mode));
+                state.ram_enabled = x.ram_enabled;
+                state.banking_mode = Some(x.mode as u8);
+            }
+            Mbc::Mbc1M(x) => {
+                state.ram_bank = ram_bank(mbc1_ram_bank(x.selected_bank, x.mode));
+                state.ram_enabled = x.ram_enabled;
+                state.banking_mode = Some(x.mode as u8);
+            }
+            Mbc::Mbc2(x) => state.ram_enabled = x.ram_enabled,
+            Mbc::Mbc3(x) => {
+                let selected = (0x08..=0x0C).contains(&x.ram_bank).then_some(x.ram_bank);
+                state.ram_bank = match x.ram_bank {
+                    0x0..=0x3 => ram_bank(x.ram_bank as usize),
+                    _ => None,
+                };
+                state.ram_enabled = x.ram_enabled;
+                state.rtc = Some(RtcState {
+                    registers: x.rtc,
+                    selected,
+                    latch_armed: x.latch_clock_data == 1,
+                });
+            }
+            Mbc::Mbc5(x) => {
+                state.ram_bank = ram_bank(x.selected_ram_bank as usize);
+                state.ram_enabled = x.ram_enabled;
+            }
+        }
+        state
+    }
+
+    /// The number of banks of 8 KiB in the RAM of the cartridge. A RAM smaller than a bank, like
+    /// the one of the MBC2, counts as one bank.
+    pub fn num_ram_banks(&self) -> usize {
+        self.ram.len().div_ceil(0x2000)
+    }
+
+    /// The content of the given RAM bank, independent of the bank that is mapped in A000..=BFFF.
+    /// Returns None if the bank does not exist.
+    pub fn ram_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.ram.chunks(0x2000).nth(bank)
+    }
+
     /// The current pair of ROM banks beign mapped to 0..=3FFF and 4000..=7FFF, respectvely.
     pub fn curr_bank(&self) -> (u16, u16) {
         (self.lower_bank, self.upper_bank)
@@ -547,6 +859,14 @@ impl Cartridge {
             Mbc::Mbc5(x) => x.write(address, value, &self.rom, &mut self.ram),
         }
         self.update_banks();
+        crate::tracing_event!(
+            TRACE,
+            lower_bank = self.lower_bank,
+            upper_bank = self.upper_bank,
+            "mbc write {:02x} to {:04x}",
+            value,
+            address
+        );
     }
 
     fn update_banks(&mut self) {
@@ -1168,6 +1488,10 @@ struct Mbc3 {
     // 0 is the intial state
     // 1 means that 0 was written
     latch_clock_data: u8,
+    // the time, in seconds, that is latched into the rtc registers. The rtc does not count time
+    // yet, so this never changes during emulation. It is not part of the save state, it is
+    // configured by the frontend instead.
+    rtc_time: u64,
 }
 crate::save_state!(Mbc3, self, data {
     self.selected_bank;
@@ -1184,6 +1508,7 @@ impl Mbc3 {
             ram_bank: 0,
             rtc: [0; 5],
             latch_clock_data: 0,
+            rtc_time: 0,
         }
     }
 
@@ -1230,7 +1555,7 @@ impl Mbc3 {
                         if !self.ram_enabled || ram.is_empty() {
                             return 0xff;
                         }
-                        let start_address = 0x2000 * ((self.selected_bank >> 5) & 0x3) as usize;
+                        let start_address = (0x2000 * self.ram_bank as usize) % ram.len();
                         ram[address as usize - 0xA000 + start_address]
                     }
                     // RTC registers
@@ -1283,7 +1608,7 @@ impl Mbc3 {
                     self.latch_clock_data = 0;
                     // TODO: complete the rtc implementation
 
-                    let now = std::time::Duration::ZERO;
+                    let now = core::time::Duration::from_secs(self.rtc_time);
                     let seconds = now.as_secs();
                     let secs = (seconds % 60) as u8;
                     let mins = ((seconds / 60) % 60) as u8;
@@ -1314,7 +1639,7 @@ impl Mbc3 {
                         if !self.ram_enabled || ram.is_empty() {
                             return;
                         }
-                        let start_address = 0x2000 * ((self.selected_bank >> 5) & 0x3) as usize;
+                        let start_address = (0x2000 * self.ram_bank as usize) % ram.len();
                         ram[address as usize - 0xA000 + start_address] = value;
                     }
                     // RTC registers
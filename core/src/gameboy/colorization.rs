@@ -0,0 +1,114 @@
+//! Colorization of DMG games, giving them colors without emulating the CGB, like the SGB and the
+//! CGB boot rom do for older games.
+//!
+//! When enabled with `Ppu::set_colorization_enabled`, each pixel of the screen holds, besides its
+//! shade in the bits 0 and 1, the `ColorSource` it was drawn with in the bits 2 and 3. A
+//! `LinePalette` maps each of these 16 values to a RGB color, and can be chosen for each scanline,
+//! for example to give a status bar its own colors.
+
+use alloc::vec::Vec;
+
+use crate::consts::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::ppu::Screen;
+
+/// Which layer and palette register a screen pixel was drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSource {
+    /// The background, with BGP. Also used when the background is disabled.
+    Background = 0,
+    /// The window, with BGP.
+    Window = 1,
+    /// A sprite, with OBP0.
+    Obj0 = 2,
+    /// A sprite, with OBP1.
+    Obj1 = 3,
+}
+
+impl ColorSource {
+    /// The source of a pixel of a colorized screen.
+    pub fn of_pixel(pixel: u8) -> Self {
+        match (pixel >> 2) & 0b11 {
+            0 => ColorSource::Background,
+            1 => ColorSource::Window,
+            2 => ColorSource::Obj0,
+            _ => ColorSource::Obj1,
+        }
+    }
+
+    /// The value of a pixel with this source and the given shade, from 0 (lightest) to 3.
+    pub fn pixel(self, shade: u8) -> u8 {
+        debug_assert!(shade < 4);
+        (self as u8) << 2 | shade
+    }
+}
+
+/// The RGB color of each pixel value of a colorized scanline, indexed by `ColorSource::pixel`.
+pub type LinePalette = [[u8; 3]; 16];
+
+/// The colors of each `ColorSource`, from the lightest to the darkest shade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePalettes {
+    pub background: [[u8; 3]; 4],
+    pub window: [[u8; 3]; 4],
+    pub obj0: [[u8; 3]; 4],
+    pub obj1: [[u8; 3]; 4],
+}
+
+impl SourcePalettes {
+    /// The same colors for every source, like the DMG screen does with `colors` as its shades.
+    pub fn uniform(colors: [[u8; 3]; 4]) -> Self {
+        Self {
+            background: colors,
+            window: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+
+    pub fn line_palette(&self) -> LinePalette {
+        let mut palette = [[0; 3]; 16];
+        let sources = [self.background, self.window, self.obj0, self.obj1];
+        for (i, color) in palette.iter_mut().enumerate() {
+            *color = sources[i >> 2][i & 0b11];
+        }
+        palette
+    }
+}
+
+/// The colors used by a game: the palettes of all scanlines, except the ones in `lines`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorizationProfile {
+    pub palettes: SourcePalettes,
+    /// The palettes of the scanlines in the given inclusive range, like a status bar. Later entries
+    /// take precedence over earlier ones.
+    pub lines: Vec<(u8, u8, SourcePalettes)>,
+}
+
+impl ColorizationProfile {
+    /// The palette of the scanline `ly`.
+    pub fn line_palette(&self, ly: u8) -> LinePalette {
+        self.lines
+            .iter()
+            .rev()
+            .find(|(start, end, _)| (*start..=*end).contains(&ly))
+            .map_or(&self.palettes, |(_, _, palettes)| palettes)
+            .line_palette()
+    }
+}
+
+/// Convert a colorized screen to RGBA, with the palette given by `line_palette` for each scanline.
+/// `rgba` must have 4 bytes for each pixel of the screen.
+pub fn colorize(screen: &Screen, mut line_palette: impl FnMut(u8) -> LinePalette, rgba: &mut [u8]) {
+    assert_eq!(rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+    let rows = screen
+        .row_iter()
+        .zip(rgba.chunks_exact_mut(SCREEN_WIDTH * 4));
+    for (ly, (pixels, rgba)) in rows.enumerate() {
+        let palette = line_palette(ly as u8);
+        for (color, &pixel) in rgba.chunks_exact_mut(4).zip(pixels) {
+            let [r, g, b] = palette[pixel as usize & 0xF];
+            color.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+}
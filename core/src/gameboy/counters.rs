@@ -0,0 +1,168 @@
+use alloc::{format, string::String};
+use core::fmt::Write;
+
+use crate::consts::{CLOCK_SPEED, FRAME_CYCLES};
+
+/// The names of the interrupts, in the order of their bits in IF and IE.
+pub const INTERRUPT_NAMES: [&str; 5] = ["vblank", "stat", "timer", "serial", "joypad"];
+
+/// The maximum number of nested interrupt handlers that are timed. The handlers dispatched past
+/// this depth are not timed.
+const MAX_NESTED_HANDLERS: usize = 8;
+
+/// A interrupt handler being executed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Handler {
+    interrupt: u8,
+    /// The stack pointer after the return address was pushed by the dispatch.
+    sp: u16,
+    /// The clock count at the start of the dispatch.
+    start: u64,
+}
+
+/// Counters of what the emulated game is doing, for finding out why it is slow or stuttering.
+///
+/// They are not part of the save state, and are reset together with the GameBoy, or on demand
+/// through the debugger `stats reset` command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerfCounters {
+    /// The clock count when the counters were last reset.
+    pub since_clock: u64,
+    /// The number of opcodes executed by the interpreter. The instructions run by the JIT
+    /// compiled blocks are not counted, see `jit_cycles`.
+    pub instructions: u64,
+    /// The cycles run by JIT compiled blocks, which don't count their instructions. Updated by the
+    /// JIT compiler.
+    pub jit_cycles: u64,
+    /// The number of interrupts dispatched, for each kind, in the order of `INTERRUPT_NAMES`.
+    pub interrupts: [u64; 5],
+    /// The cycles spent in the handlers of each kind of interrupt, from the start of the dispatch
+    /// until the return address is popped from the stack, by a `reti` or otherwise. Include the
+    /// cycles of nested handlers.
+    pub interrupt_cycles: [u64; 5],
+    /// The most cycles spent in a single call of the handler of each kind of interrupt.
+    pub interrupt_max_cycles: [u64; 5],
+    /// The number of OAM DMA transfers started.
+    pub dma_transfers: u64,
+    /// The number of writes to the MBC registers that changed the mapped ROM banks.
+    pub bank_switches: u64,
+    /// The handlers being executed, from the outermost to the innermost.
+    handlers: [Handler; MAX_NESTED_HANDLERS],
+    handler_depth: u8,
+}
+impl PerfCounters {
+    /// Set all counters to zero, starting to count the cycles from `clock_count`.
+    pub fn reset(&mut self, clock_count: u64) {
+        *self = Self {
+            since_clock: clock_count,
+            ..Self::default()
+        };
+    }
+
+    /// Start timing the handler of `interrupt`, dispatched at `clock_count`, that pushed its
+    /// return address to the stack at `sp`.
+    pub fn enter_handler(&mut self, interrupt: usize, sp: u16, clock_count: u64) {
+        let Some(handler) = self.handlers.get_mut(self.handler_depth as usize) else {
+            return;
+        };
+        *handler = Handler {
+            interrupt: interrupt as u8,
+            sp,
+            start: clock_count,
+        };
+        self.handler_depth += 1;
+    }
+
+    /// Stop timing the handlers that returned, given the current stack pointer and clock count.
+    /// Must be called after each instruction, or at least after each one that pops from the
+    /// stack.
+    #[inline(always)]
+    pub fn leave_handlers(&mut self, sp: u16, clock_count: u64) {
+        while self.handler_depth != 0 {
+            let handler = self.handlers[self.handler_depth as usize - 1];
+            if sp <= handler.sp {
+                break;
+            }
+            self.handler_depth -= 1;
+            let interrupt = handler.interrupt as usize;
+            let cycles = clock_count.saturating_sub(handler.start);
+            self.interrupt_cycles[interrupt] += cycles;
+            self.interrupt_max_cycles[interrupt] = self.interrupt_max_cycles[interrupt].max(cycles);
+        }
+    }
+
+    /// A human readable report of the counters, given the current clock count.
+    pub fn report(&self, clock_count: u64) -> String {
+        let cycles = clock_count.saturating_sub(self.since_clock);
+        let seconds = cycles as f64 / CLOCK_SPEED as f64;
+        let per_second = |count: u64| {
+            if cycles == 0 {
+                0.0
+            } else {
+                count as f64 / seconds
+            }
+        };
+
+        let mut text = String::new();
+        let _ = writeln!(text, "cycles:        {:12} ({:.2} s)", cycles, seconds);
+        if self.jit_cycles == 0 {
+            let _ = writeln!(
+                text,
+                "instructions:  {:12} ({:.0}/s)",
+                self.instructions,
+                per_second(self.instructions)
+            );
+        } else {
+            // the count would be missing the instructions of the compiled blocks.
+            let _ = writeln!(
+                text,
+                "instructions:  {:>12} ({:.1}% of the cycles run by the JIT)",
+                "n/a",
+                100.0 * self.jit_cycles as f64 / cycles.max(1) as f64
+            );
+        }
+        for (name, count) in INTERRUPT_NAMES.iter().zip(self.interrupts) {
+            let _ = writeln!(
+                text,
+                "{:14} {:12} ({:.1}/s)",
+                format!("{} int:", name),
+                count,
+                per_second(count)
+            );
+        }
+        let frames = cycles as f64 / FRAME_CYCLES as f64;
+        for (i, name) in INTERRUPT_NAMES.iter().enumerate() {
+            let total = self.interrupt_cycles[i];
+            if self.interrupts[i] == 0 {
+                continue;
+            }
+            let per_frame = if cycles == 0 {
+                0.0
+            } else {
+                total as f64 / frames
+            };
+            let _ = writeln!(
+                text,
+                "{:14} {:12} ({:.0}/frame, {:.1}% of a frame, max {})",
+                format!("{} cycles:", name),
+                total,
+                per_frame,
+                100.0 * per_frame / FRAME_CYCLES as f64,
+                self.interrupt_max_cycles[i]
+            );
+        }
+        let _ = writeln!(
+            text,
+            "dma transfers: {:12} ({:.1}/s)",
+            self.dma_transfers,
+            per_second(self.dma_transfers)
+        );
+        let _ = write!(
+            text,
+            "bank switches: {:12} ({:.1}/s)",
+            self.bank_switches,
+            per_second(self.bank_switches)
+        );
+        text
+    }
+}
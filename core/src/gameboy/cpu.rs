@@ -1,4 +1,4 @@
-use std::fmt;
+use core::fmt;
 
 use crate::save_state::{LoadStateError, SaveState, SaveStateContext};
 
@@ -13,15 +13,15 @@ impl SaveState for CpuState {
     fn save_state(
         &self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         (*self as u8).save_state(ctx, data)
     }
 
     fn load_state(
         &mut self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Read,
+        data: &mut impl crate::io::Read,
     ) -> Result<(), LoadStateError> {
         let mut value = 0u8;
         value.load_state(ctx, data)?;
@@ -54,15 +54,15 @@ impl SaveState for ImeState {
     fn save_state(
         &self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         (*self as u8).save_state(ctx, data)
     }
 
     fn load_state(
         &mut self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Read,
+        data: &mut impl crate::io::Read,
     ) -> Result<(), LoadStateError> {
         let mut value = 0u8;
         value.load_state(ctx, data)?;
@@ -84,7 +84,7 @@ impl Default for ImeState {
 // Each pair of registers is in the reverse order to allow addressing them as a single 16 bit
 // registers in little-endian machines.
 #[repr(C, align(2))]
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
 pub struct Cpu {
     pub f: Flags,
     pub a: u8,
@@ -176,7 +176,7 @@ impl Cpu {
 /// 5 - H: Half Carry flag (BCD)
 /// 4 - C: Carry flag
 /// Remaning bits are read/writeable, but are not flags.
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(transparent)]
 pub struct Flags(pub u8);
 impl Flags {
@@ -0,0 +1,87 @@
+use alloc::vec::Vec;
+
+/// What happens to a write to a locked address range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// The write is ignored, so the range keeps the values it had when it was frozen.
+    Freeze,
+    /// The write is ignored, and the debugger breaks on it.
+    Protect,
+}
+
+/// A write that was ignored because its address was locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockedWrite {
+    pub address: u16,
+    pub value: u8,
+    pub kind: LockKind,
+}
+impl core::fmt::Display for LockedWrite {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let kind = match self.kind {
+            LockKind::Freeze => "frozen",
+            LockKind::Protect => "protected",
+        };
+        write!(
+            f,
+            "write {:02x} to {:04x} ignored, {}",
+            self.value, self.address, kind
+        )
+    }
+}
+
+/// The address ranges whose writes by the CPU are ignored, set by the debugger `freeze` and
+/// `protect` commands. Checked by `GameBoy::write`, so the writes by the OAM DMA, or by the
+/// hardware itself, are not affected.
+///
+/// They are not part of the save state.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryLocks {
+    /// The first and the last address of each range, and its kind.
+    ranges: Vec<(u16, u16, LockKind)>,
+}
+impl MemoryLocks {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The locked ranges, in the order they were added.
+    pub fn ranges(&self) -> &[(u16, u16, LockKind)] {
+        &self.ranges
+    }
+
+    /// Lock the range from `start` to `end`, inclusive. The addresses in E000-FDFF are mapped to
+    /// the Work RAM they mirror.
+    pub fn add(&mut self, kind: LockKind, start: u16, end: u16) {
+        let (start, end) = if (0xE000..=0xFDFF).contains(&start) && end <= 0xFDFF {
+            (start - 0x2000, end - 0x2000)
+        } else {
+            (start, end)
+        };
+        self.ranges.push((start, end, kind));
+    }
+
+    /// Unlock the ranges of the given kind that contain `address`, or all of them if None. Return
+    /// false if there was none.
+    pub fn remove(&mut self, kind: LockKind, address: Option<u16>) -> bool {
+        let len = self.ranges.len();
+        self.ranges.retain(|&(start, end, k)| {
+            k != kind || address.is_some_and(|x| !(start..=end).contains(&x))
+        });
+        self.ranges.len() != len
+    }
+
+    /// The kind of the lock of `address`, if any. Protect takes precedence over freeze.
+    pub fn find(&self, address: u16) -> Option<LockKind> {
+        let mut found = None;
+        for &(start, end, kind) in &self.ranges {
+            if (start..=end).contains(&address) {
+                if kind == LockKind::Protect {
+                    return Some(kind);
+                }
+                found = Some(kind);
+            }
+        }
+        found
+    }
+}
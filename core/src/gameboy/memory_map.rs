@@ -0,0 +1,146 @@
+//! What is mapped at each address of the memory map, for annotating addresses in the debugger.
+
+use core::fmt;
+
+/// A region of the memory map, and which part of it an address belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// 0000-3FFF: the lower ROM bank, with the given bank number.
+    RomBank0(u16),
+    /// 4000-7FFF: the switchable ROM bank, with the given bank number.
+    RomBankN(u16),
+    /// 8000-97FF: tile data, with the index of the tile, counting from 8000.
+    TileData(u16),
+    /// 9800-9FFF: tile maps, with the index of the map (0 for 9800, 1 for 9C00).
+    TileMap(u8),
+    /// A000-BFFF: the cartridge RAM.
+    ExternalRam,
+    /// C000-DFFF: the work RAM.
+    WorkRam,
+    /// E000-FDFF: a mirror of C000-DDFF.
+    EchoRam,
+    /// FE00-FE9F: the object attribute memory, with the index of the object.
+    Oam(u8),
+    /// FEA0-FEFF: not usable.
+    Unusable,
+    /// FF00-FF7F: a I/O register, with its name, if the address is used.
+    Io(Option<&'static str>),
+    /// FF80-FFFE: the high RAM.
+    HighRam,
+    /// FFFF: the Interrupt Enabled register.
+    InterruptEnable,
+}
+
+impl Region {
+    /// The region of `address`, given the current lower and upper ROM banks, as returned by
+    /// `Cartridge::curr_bank`.
+    pub fn of(address: u16, banks: (u16, u16)) -> Self {
+        match address {
+            0x0000..=0x3FFF => Region::RomBank0(banks.0),
+            0x4000..=0x7FFF => Region::RomBankN(banks.1),
+            0x8000..=0x97FF => Region::TileData((address - 0x8000) / 16),
+            0x9800..=0x9BFF => Region::TileMap(0),
+            0x9C00..=0x9FFF => Region::TileMap(1),
+            0xA000..=0xBFFF => Region::ExternalRam,
+            0xC000..=0xDFFF => Region::WorkRam,
+            0xE000..=0xFDFF => Region::EchoRam,
+            0xFE00..=0xFE9F => Region::Oam(((address - 0xFE00) / 4) as u8),
+            0xFEA0..=0xFEFF => Region::Unusable,
+            0xFF00..=0xFF7F => Region::Io(io_register_name(address)),
+            0xFF80..=0xFFFE => Region::HighRam,
+            0xFFFF => Region::InterruptEnable,
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::RomBank0(bank) | Region::RomBankN(bank) => write!(f, "ROM bank {:02x}", bank),
+            Region::TileData(tile) => write!(f, "VRAM tile {:03x}", tile),
+            Region::TileMap(map) => write!(f, "VRAM tile map {}", map),
+            Region::ExternalRam => write!(f, "cartridge RAM"),
+            Region::WorkRam => write!(f, "WRAM"),
+            Region::EchoRam => write!(f, "echo RAM"),
+            Region::Oam(object) => write!(f, "OAM object {}", object),
+            Region::Unusable => write!(f, "unusable"),
+            Region::Io(Some(name)) => write!(f, "IO {}", name),
+            Region::Io(None) => write!(f, "IO"),
+            Region::HighRam => write!(f, "HRAM"),
+            Region::InterruptEnable => write!(f, "IO IE"),
+        }
+    }
+}
+
+/// The name of the I/O register at `address`, if any.
+pub fn io_register_name(address: u16) -> Option<&'static str> {
+    Some(match address {
+        0xFF00 => "P1",
+        0xFF01 => "SB",
+        0xFF02 => "SC",
+        0xFF04 => "DIV",
+        0xFF05 => "TIMA",
+        0xFF06 => "TMA",
+        0xFF07 => "TAC",
+        0xFF0F => "IF",
+        0xFF10 => "NR10",
+        0xFF11 => "NR11",
+        0xFF12 => "NR12",
+        0xFF13 => "NR13",
+        0xFF14 => "NR14",
+        0xFF16 => "NR21",
+        0xFF17 => "NR22",
+        0xFF18 => "NR23",
+        0xFF19 => "NR24",
+        0xFF1A => "NR30",
+        0xFF1B => "NR31",
+        0xFF1C => "NR32",
+        0xFF1D => "NR33",
+        0xFF1E => "NR34",
+        0xFF20 => "NR41",
+        0xFF21 => "NR42",
+        0xFF22 => "NR43",
+        0xFF23 => "NR44",
+        0xFF24 => "NR50",
+        0xFF25 => "NR51",
+        0xFF26 => "NR52",
+        0xFF30..=0xFF3F => "WAVE",
+        0xFF40 => "LCDC",
+        0xFF41 => "STAT",
+        0xFF42 => "SCY",
+        0xFF43 => "SCX",
+        0xFF44 => "LY",
+        0xFF45 => "LYC",
+        0xFF46 => "DMA",
+        0xFF47 => "BGP",
+        0xFF48 => "OBP0",
+        0xFF49 => "OBP1",
+        0xFF4A => "WY",
+        0xFF4B => "WX",
+        0xFF50 => "BOOT",
+        0xFFFF => "IE",
+        _ => return None,
+    })
+}
+
+/// What the I/O register at `address`, that is not emulated, is used for. Most of them are only
+/// present in the Game Boy Color, and read back as `ff` in the DMG.
+pub fn unemulated_io_description(address: u16) -> &'static str {
+    match address {
+        0xFF4C => "CGB KEY0, compatibility mode",
+        0xFF4D => "CGB KEY1, speed switch",
+        0xFF4F => "CGB VBK, VRAM bank",
+        0xFF51..=0xFF54 => "CGB HDMA source and destination",
+        0xFF55 => "CGB HDMA5, VRAM DMA",
+        0xFF56 => "CGB RP, infrared port",
+        0xFF68 => "CGB BCPS, background palette index",
+        0xFF69 => "CGB BCPD, background palette data",
+        0xFF6A => "CGB OCPS, object palette index",
+        0xFF6B => "CGB OCPD, object palette data",
+        0xFF6C => "CGB OPRI, object priority mode",
+        0xFF70 => "CGB SVBK, WRAM bank",
+        0xFF76 => "CGB PCM12, audio digital output",
+        0xFF77 => "CGB PCM34, audio digital output",
+        _ => "unused",
+    }
+}
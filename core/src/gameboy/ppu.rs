@@ -1,6 +1,8 @@
+use alloc::{boxed::Box, format, string::ToString, vec, vec::Vec};
+
 use crate::{
     consts::{FRAME_CYCLES, SCANLINE_CYCLES, SCANLINE_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH},
-    gameboy::GameBoy,
+    gameboy::{colorization::ColorSource, GameBoy},
     save_state::{LoadStateError, SaveState, SaveStateContext},
 };
 
@@ -16,8 +18,8 @@ impl SaveState for PixelFifo {
     fn save_state(
         &self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         self.queue.save_state(ctx, data)?;
         self.head.save_state(ctx, data)?;
         self.tail.save_state(ctx, data)?;
@@ -28,7 +30,7 @@ impl SaveState for PixelFifo {
     fn load_state(
         &mut self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Read,
+        data: &mut impl crate::io::Read,
     ) -> Result<(), LoadStateError> {
         self.queue.load_state(ctx, data)?;
         self.head.load_state(ctx, data)?;
@@ -38,6 +40,8 @@ impl SaveState for PixelFifo {
     }
 }
 impl PixelFifo {
+    /// The pixels in the FIFO, starting from the next one to be popped. Each pixel has its color
+    /// in bits 0-1, and for sprites, the background priority in bit 3 and the palette in bit 4.
     pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
         let tail = self.tail as usize;
         let head = self.head as usize;
@@ -102,7 +106,9 @@ impl PixelFifo {
 
         let mut cursor = self.tail;
         let mut x = 8u8;
-        // overwrite pixels in fifo, but only if 0
+        // Overwrite pixels in fifo, but only if 0. Sprites are pushed in decreasing order of
+        // priority, so any opaque pixel already in the fifo belongs to a sprite with greater
+        // priority.
         while cursor != self.head && x != 0 {
             x -= 1;
             let color = self.queue[cursor as usize] & 0b11;
@@ -140,15 +146,15 @@ impl SaveState for Sprite {
     fn save_state(
         &self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         [self.sx, self.sy, self.tile, self.flags].save_state(ctx, data)
     }
 
     fn load_state(
         &mut self,
         ctx: &mut SaveStateContext,
-        data: &mut impl std::io::Read,
+        data: &mut impl crate::io::Read,
     ) -> Result<(), LoadStateError> {
         let mut t = [0u8; 4];
         t.load_state(ctx, data)?;
@@ -175,8 +181,8 @@ pub struct Screen {
     pub screen: [u8; Self::STRIDE * SCREEN_HEIGHT],
 }
 
-impl std::hash::Hash for Screen {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+impl core::hash::Hash for Screen {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         self.row_iter().for_each(|row| row.hash(state));
     }
 }
@@ -209,7 +215,8 @@ impl Screen {
         self.screen[ly * Self::STRIDE + Self::LEFT_PAD + lx] = color;
     }
 
-    fn row_iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+    /// Iterate over the visible pixels of each scanline.
+    pub fn row_iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
         self.screen
             .chunks_exact(Screen::STRIDE)
             .map(|x| &x[Screen::LEFT_PAD..][..SCREEN_WIDTH])
@@ -229,8 +236,8 @@ impl SaveState for Screen {
     fn save_state(
         &self,
         _: &mut SaveStateContext,
-        data: &mut impl std::io::Write,
-    ) -> Result<(), std::io::Error> {
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
         for i in 0..SCREEN_HEIGHT {
             data.write_all(&self.screen[i * Self::STRIDE + Self::LEFT_PAD..][..SCREEN_WIDTH])?;
         }
@@ -240,7 +247,7 @@ impl SaveState for Screen {
     fn load_state(
         &mut self,
         _: &mut SaveStateContext,
-        data: &mut impl std::io::Read,
+        data: &mut impl crate::io::Read,
     ) -> Result<(), LoadStateError> {
         for i in 0..SCREEN_HEIGHT {
             data.read_exact(&mut self.screen[i * Self::STRIDE + Self::LEFT_PAD..][..SCREEN_WIDTH])?;
@@ -249,6 +256,106 @@ impl SaveState for Screen {
     }
 }
 
+/// The layer that produced a screen pixel, as recorded by the PPU debug pass.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum PixelLayer {
+    /// The pixel was not rendered since the debug pass was enabled.
+    #[default]
+    None,
+    /// The background and window are disabled, and no sprite is drawn over the pixel.
+    Blank,
+    Background,
+    Window,
+    /// A sprite, with its index in the OAM.
+    Sprite(u8),
+}
+
+/// Where a screen pixel came from, as recorded by the PPU debug pass. See
+/// [`Ppu::set_pixel_sources_enabled`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct PixelSource {
+    pub layer: PixelLayer,
+    /// The tile number, as read from the tile map or the OAM.
+    pub tile: u8,
+    /// The address of the row of the tile data that contains the pixel.
+    pub tile_address: u16,
+    /// The value of the palette register used for the pixel.
+    pub palette: u8,
+    /// The color of the pixel in the tile, before applying the palette.
+    pub color: u8,
+    /// The attributes of the sprite, or 0 for the background and the window.
+    pub attributes: u8,
+}
+impl core::fmt::Display for PixelSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (layer, palette) = match self.layer {
+            PixelLayer::None => return write!(f, "not rendered by the debug pass yet"),
+            PixelLayer::Blank => return write!(f, "blank (background disabled)"),
+            PixelLayer::Background => ("background".to_string(), "BGP"),
+            PixelLayer::Window => ("window".to_string(), "BGP"),
+            PixelLayer::Sprite(i) if self.attributes & 0x10 != 0 => {
+                (format!("sprite {}", i), "OBP1")
+            }
+            PixelLayer::Sprite(i) => (format!("sprite {}", i), "OBP0"),
+        };
+        write!(
+            f,
+            "{}, tile {:02x} at {:04x}, {} {:02x}, color {}",
+            layer, self.tile, self.tile_address, palette, self.palette, self.color
+        )?;
+        if let PixelLayer::Sprite(_) = self.layer {
+            write!(f, ", attributes {:02x}", self.attributes)?;
+        }
+        Ok(())
+    }
+}
+
+/// A write to the VRAM or the OAM that was ignored, because the PPU or the OAM DMA was accessing
+/// it.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct BlockedWrite {
+    pub address: u16,
+    pub value: u8,
+    /// The PPU mode at the write, or `None` if the OAM was blocked by a OAM DMA transfer.
+    pub mode: Option<u8>,
+}
+impl core::fmt::Display for BlockedWrite {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "write {:02x} to {:04x} ignored ",
+            self.value, self.address
+        )?;
+        match self.mode {
+            Some(mode) => write!(f, "in PPU mode {}", mode),
+            None => write!(f, "during OAM DMA"),
+        }
+    }
+}
+
+/// The state of the background pixel fetcher and of the window in the current scanline, for
+/// inspecting the pixel pipeline. See `Ppu::pipeline`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct PipelineState {
+    /// The step of the fetcher, from 0 to 7: 1 fetches the tile number, 3 and 5 the low and high
+    /// bytes of the tile data, and 6 and 7 wait for the background FIFO to be empty to push them.
+    pub fetcher_step: u8,
+    /// The x position of the tile being fetched in the window.
+    pub fetcher_x: u8,
+    pub tile_number: u8,
+    pub tile_data_low: u8,
+    pub tile_data_high: u8,
+    /// How many pixels still need to be discarded at the start of the scanline: the 8 pixels
+    /// that are never shown, and the ones scrolled out by SCX.
+    pub discarding: u8,
+    /// If WY matched LY in the current frame, so the window can be shown.
+    pub reach_window: bool,
+    /// If the pixels being output are from the window.
+    pub is_in_window: bool,
+    /// If the window was just activated, and its first pixel was not output yet.
+    pub is_window_being_fetched: bool,
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct Ppu {
     /// 8000-9FFF: Video RAM
@@ -269,7 +376,8 @@ pub struct Ppu {
     vram_write_block: bool,
 
     /// The current screen been render.
-    /// Each pixel is a shade of gray, from 0 to 3
+    /// Each pixel is a shade of gray, from 0 to 3, plus its source in the bits 2 and 3 if the
+    /// colorization is enabled. See `colorization`.
     pub screen: Screen,
     /// sprites that will be rendered in the next mode 3 scanline
     pub sprite_buffer: [Sprite; 10],
@@ -375,10 +483,21 @@ pub struct Ppu {
     /// (represented by positives between 241 and 255) are use for detecting sprites that starts
     /// to the left of the screen, and for discarding pixels for scrolling.
     pub scanline_x: u8,
+
+    /// Where each screen pixel came from, in the same order as `screen`, if the debug pass is
+    /// enabled. Not part of the save state.
+    pixel_sources: Option<Box<[PixelSource]>>,
+    /// If the `ColorSource` of each pixel is stored in `screen`. Not part of the save state.
+    colorization: bool,
+    /// The clock count in which STAT last changed to mode 3. Not part of the save state.
+    mode_3_start_clock_count: u64,
+    /// How many clock cycles STAT was in mode 3 in each scanline, or 0 if the scanline was not
+    /// traced. Not part of the save state.
+    mode_3_lengths: [u16; SCREEN_HEIGHT],
 }
 
-fn dbg_fmt_hash<T: std::hash::Hash>(value: &T) -> impl std::fmt::Debug {
-    use std::hash::Hasher;
+fn dbg_fmt_hash<T: core::hash::Hash>(value: &T) -> impl core::fmt::Debug {
+    use core::hash::Hasher;
 
     struct MyHasher(u64);
     impl Hasher for MyHasher {
@@ -391,8 +510,8 @@ fn dbg_fmt_hash<T: std::hash::Hash>(value: &T) -> impl std::fmt::Debug {
             }
         }
     }
-    impl std::fmt::Debug for MyHasher {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    impl core::fmt::Debug for MyHasher {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
             write!(f, "hash:{:08x}", self.0 as u32)
         }
     }
@@ -402,8 +521,8 @@ fn dbg_fmt_hash<T: std::hash::Hash>(value: &T) -> impl std::fmt::Debug {
     hasher
 }
 
-impl std::fmt::Debug for Ppu {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for Ppu {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Ppu")
             .field("vram", &dbg_fmt_hash(&self.vram))
             .field("oam", &dbg_fmt_hash(&self.oam))
@@ -532,6 +651,7 @@ crate::save_state!(Ppu, self, ctx, data {
     ];
 
     on_load self.next_interrupt = self.estimate_next_interrupt();
+    on_load self.mode_3_start_clock_count = 0;
 });
 
 impl Default for Ppu {
@@ -588,6 +708,10 @@ impl Default for Ppu {
             wx_just_changed: false,
             screen_x: 0,
             scanline_x: 0,
+            pixel_sources: None,
+            colorization: false,
+            mode_3_start_clock_count: 0,
+            mode_3_lengths: [0; SCREEN_HEIGHT],
         }
     }
 }
@@ -682,8 +806,86 @@ impl Ppu {
 
             screen_x: 0xa0,
             scanline_x: 0xA0,
+
+            pixel_sources: self.pixel_sources.take(),
+            colorization: self.colorization,
+            mode_3_start_clock_count: 0,
+            mode_3_lengths: [0; SCREEN_HEIGHT],
+        }
+    }
+
+    /// Enable or disable the debug pass, that records where each pixel of the screen came from
+    /// while rendering. It slows down the rendering, so it is only enabled while debugging.
+    ///
+    /// The sources of each scanline are computed from the state of the PPU at the end of the
+    /// scanline, so they may not match the screen if the game changes the PPU registers in the
+    /// middle of it.
+    pub fn set_pixel_sources_enabled(&mut self, enabled: bool) {
+        if enabled != self.pixel_sources.is_some() {
+            self.pixel_sources =
+                enabled.then(|| vec![PixelSource::default(); SCREEN_WIDTH * SCREEN_HEIGHT].into());
         }
     }
+
+    /// Where the given screen pixel came from, if the debug pass is enabled.
+    pub fn pixel_source(&self, x: u8, y: u8) -> Option<PixelSource> {
+        if x as usize >= SCREEN_WIDTH {
+            return None;
+        }
+        let sources = self.pixel_sources.as_ref()?;
+        sources.get(y as usize * SCREEN_WIDTH + x as usize).copied()
+    }
+
+    pub fn pixel_sources_enabled(&self) -> bool {
+        self.pixel_sources.is_some()
+    }
+
+    /// Enable or disable storing the `ColorSource` of each pixel of the screen in its bits 2 and
+    /// 3, for colorizing it. See the `colorization` module.
+    pub fn set_colorization_enabled(&mut self, enabled: bool) {
+        self.colorization = enabled;
+    }
+
+    /// How many clock cycles STAT reported mode 3 in the scanline `ly`, the last time it was
+    /// drawn. This is when the pixels are pushed to the screen, and takes longer the more pixels
+    /// are discarded by SCX, and the more sprites and window there are in the scanline. Mode 2
+    /// always takes 80 cycles, and mode 0 the remaining of the 456 cycles of the scanline.
+    ///
+    /// Returns None if the scanline was not drawn yet, or was drawn at once by the simple PPU
+    /// renderer (see `AccuracyConfig::simple_ppu`), which doesn't compute its timing.
+    pub fn mode_3_length(&self, ly: u8) -> Option<u16> {
+        match self.mode_3_lengths.get(ly as usize) {
+            Some(&x) if x != 0 => Some(x),
+            _ => None,
+        }
+    }
+
+    /// The state of the pixel fetcher and of the window. Together with `background_fifo`,
+    /// `sprite_fifo`, `screen_x` and `scanline_x`, they show where the pixel pipeline is in the
+    /// current scanline. Only meaningful in mode 3, and only updated dot by dot when the simple
+    /// PPU renderer is not being used (see `AccuracyConfig::simple_ppu`).
+    pub fn pipeline(&self) -> PipelineState {
+        PipelineState {
+            fetcher_step: self.fetcher_step,
+            fetcher_x: self.fetcher_x,
+            tile_number: self.fetch_tile_number,
+            tile_data_low: self.fetch_tile_data_low,
+            tile_data_high: self.fetch_tile_data_hight,
+            // negative values of `scanline_x` are pixels to be discarded.
+            discarding: if self.scanline_x > 240 {
+                self.scanline_x.wrapping_neg()
+            } else {
+                0
+            },
+            reach_window: self.reach_window,
+            is_in_window: self.is_in_window,
+            is_window_being_fetched: self.is_window_being_fetched,
+        }
+    }
+
+    pub fn colorization_enabled(&self) -> bool {
+        self.colorization
+    }
     pub fn write(gb: &mut GameBoy, address: u8, value: u8) {
         match address {
             0x40 => {
@@ -732,6 +934,17 @@ impl Ppu {
             }
             0x41 => {
                 gb.update_ppu();
+                if gb.accuracy.stat_write_bug && gb.ppu.borrow().lcdc & 0x80 != 0 {
+                    // On DMG, the write sets all bits of STAT for a cycle, before the written
+                    // value, so it can trigger a STAT interrupt. Road Rash depends on this.
+                    let this = &mut *gb.ppu.get_mut();
+                    this.stat |= 0x78;
+                    let mut stat_interrupt = false;
+                    this.update_stat(&mut stat_interrupt);
+                    if stat_interrupt {
+                        gb.interrupt_flag.set(gb.interrupt_flag.get() | (1 << 1));
+                    }
+                }
                 {
                     let this = &mut *gb.ppu.get_mut();
                     this.stat = 0x80 | (value & !0b111) | (this.stat & 0b111);
@@ -823,33 +1036,9 @@ impl Ppu {
     }
 
     fn search_objects(&mut self) {
-        self.sprite_buffer_len = 0;
-        let sprite_height = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
-        for i in 0..40 {
-            let i = i as usize * 4;
-            let data = &self.oam[i..i + 4];
-            let sy = data[0];
-            let sx = data[1];
-            let t = data[2];
-            let flags = data[3];
-
-            if self.ly as u16 + 16 >= sy as u16 && self.ly as u16 + 16 < sy as u16 + sprite_height {
-                self.sprite_buffer[self.sprite_buffer_len as usize] = Sprite {
-                    sy,
-                    sx,
-                    tile: t,
-                    flags,
-                };
-                self.sprite_buffer_len += 1;
-            }
-            if self.sprite_buffer_len == 10 {
-                break;
-            }
-        }
-        // sort buffer by priority, in increasing order
-        // lower x position, has greater priority
-        self.sprite_buffer[0..self.sprite_buffer_len as usize].reverse();
-        self.sprite_buffer[0..self.sprite_buffer_len as usize].sort_by_key(|x| !x.sx);
+        let len = find_objects(&self.oam, self.lcdc, self.ly, &mut self.sprite_buffer);
+        self.sprite_buffer_len = len as u8;
+        sort_by_priority(&mut self.sprite_buffer[0..len]);
     }
 
     fn update_dma(gb: &GameBoy, ppu: &mut Ppu, clock_count: u64) {
@@ -864,27 +1053,36 @@ impl Ppu {
                 ppu.dma_block_oam = false;
                 ppu.dma_running = false;
 
-                // copy memory
-                let mut value = gb.dma;
-                if value >= 0xFE {
-                    value -= 0x20;
-                }
-                let start = (value as u16) << 8;
-                for (i, j) in (0x00..=0x9F).zip(start..=start + 0x9F) {
-                    // avoid borrowing the ppu twice
-                    let value = match j {
-                        0x8000..=0x9FFF => ppu.vram[j as usize - 0x8000],
-                        j => gb.read(j),
-                    };
-                    ppu.oam[i] = value;
-                }
+                Self::copy_dma(gb, ppu);
             }
         }
     }
 
+    /// Copy the 160 bytes starting at the address in the DMA register to the OAM.
+    fn copy_dma(gb: &GameBoy, ppu: &mut Ppu) {
+        let mut value = gb.dma;
+        if value >= 0xFE {
+            value -= 0x20;
+        }
+        let start = (value as u16) << 8;
+        for (i, j) in (0x00..=0x9F).zip(start..=start + 0x9F) {
+            // avoid borrowing the ppu twice
+            let value = match j {
+                0x8000..=0x9FFF => ppu.vram[j as usize - 0x8000],
+                j => gb.read(j),
+            };
+            ppu.oam[i] = value;
+        }
+    }
+
     pub fn start_dma(gb: &mut GameBoy, value: u8) {
         gb.update_ppu();
         gb.dma = value;
+        if gb.accuracy.instant_dma {
+            let ppu = &mut *gb.ppu.borrow_mut();
+            Self::copy_dma(gb, ppu);
+            return;
+        }
         let ppu = &mut *gb.ppu.get_mut();
         ppu.dma_started = gb.clock_count;
         if ppu.dma_running {
@@ -907,11 +1105,29 @@ impl Ppu {
         }
     }
 
+    /// Read from the unusable area, FEA0-FEFF, which is blocked together with the OAM.
+    pub fn read_unusable(gb: &GameBoy, address: u16) -> u8 {
+        gb.update_ppu();
+        let ppu = &*gb.ppu.borrow();
+        if ppu.dma_block_oam || ppu.oam_read_block {
+            0xff
+        } else {
+            gb.unusable_area.read(address)
+        }
+    }
+
     pub fn write_oam(gb: &mut GameBoy, address: u16, value: u8) {
         gb.update_ppu();
         let ppu = &mut *gb.ppu.get_mut();
         if !ppu.dma_block_oam && !ppu.oam_write_block {
             ppu.oam[address as usize - 0xFE00] = value;
+        } else {
+            let mode = (!ppu.dma_block_oam).then_some(ppu.stat & 0b11);
+            gb.last_blocked_write = Some(BlockedWrite {
+                address,
+                value,
+                mode,
+            });
         }
     }
 
@@ -930,6 +1146,12 @@ impl Ppu {
         let ppu = &mut *gb.ppu.get_mut();
         if !ppu.vram_write_block {
             ppu.vram[address as usize - 0x8000] = value;
+        } else {
+            gb.last_blocked_write = Some(BlockedWrite {
+                address,
+                value,
+                mode: Some(ppu.stat & 0b11),
+            });
         }
     }
 
@@ -939,6 +1161,13 @@ impl Ppu {
 
         let ppu = &mut *gb.ppu.borrow_mut();
 
+        crate::tracing_span!(
+            TRACE,
+            "ppu_update",
+            from = ppu.last_clock_count,
+            to = gb.clock_count
+        );
+
         // Writing to wx do some time traveling shenanigans. Make sure they are not observable.
         debug_assert!(ppu.last_clock_count <= gb.clock_count);
 
@@ -972,7 +1201,13 @@ impl Ppu {
             let curr_ppu_clock_count = ppu.next_clock_count;
 
             Self::update_dma(gb, ppu, ppu.next_clock_count);
-            // println!("state: {}", state);
+            crate::tracing_event!(
+                TRACE,
+                state = ppu.state,
+                ly = ppu.ly,
+                clock_count = ppu.next_clock_count,
+                "ppu state"
+            );
             match ppu.state {
                 // turn on
                 0 => {
@@ -1016,6 +1251,7 @@ impl Ppu {
                     ppu.set_stat_mode(3);
                     ppu.stat_mode_for_interrupt = 3;
                     ppu.update_stat(&mut stat_interrupt);
+                    ppu.mode_3_start_clock_count = ppu.next_clock_count;
 
                     ppu.next_clock_count += 2;
                     ppu.state = 4;
@@ -1036,7 +1272,7 @@ impl Ppu {
                     ppu.line_start_clock_count = ppu.next_clock_count;
                     ppu.screen_x = 0;
 
-                    let use_optimization = !cfg!(feature = "wave_trace");
+                    let use_optimization = !cfg!(feature = "wave_trace") && gb.accuracy.simple_ppu;
 
                     if use_optimization && gb.clock_count > ppu.next_clock_count + 456 {
                         if ppu.wy == ppu.ly {
@@ -1053,7 +1289,23 @@ impl Ppu {
 
                         Self::update_dma(gb, ppu, ppu.next_clock_count + 4);
                         ppu.search_objects();
-                        draw_scan_line(ppu);
+                        if gb.accuracy.lift_sprite_limit {
+                            let mut sprites = [Sprite::default(); 40];
+                            let len = find_objects(&ppu.oam, ppu.lcdc, ppu.ly, &mut sprites);
+                            sort_by_priority(&mut sprites[0..len]);
+                            draw_scan_line(ppu, &sprites[0..len]);
+                        } else {
+                            let sprites = ppu.sprite_buffer;
+                            draw_scan_line(ppu, &sprites[0..ppu.sprite_buffer_len as usize]);
+                        }
+                        if ppu.pixel_sources.is_some() {
+                            let sprite_limit = if gb.accuracy.lift_sprite_limit {
+                                40
+                            } else {
+                                10
+                            };
+                            trace_scan_line(ppu, sprite_limit);
+                        }
 
                         // TODO: I think only LY=LYC flag is observable here? So don't need all this
                         // code.
@@ -1092,6 +1344,10 @@ impl Ppu {
                             debug_assert!(!stat_interrupt);
                         }
 
+                        if let Some(x) = ppu.mode_3_lengths.get_mut(ppu.ly as usize) {
+                            *x = 0;
+                        }
+
                         ppu.next_clock_count += 456;
                         // goto end_line
                         ppu.state = 14;
@@ -1152,6 +1408,7 @@ impl Ppu {
                     ppu.set_stat_mode(3);
                     ppu.stat_mode_for_interrupt = 3;
                     ppu.update_stat(&mut stat_interrupt);
+                    ppu.mode_3_start_clock_count = ppu.next_clock_count;
 
                     ppu.oam_read_block = true;
                     ppu.oam_write_block = true;
@@ -1391,6 +1648,10 @@ impl Ppu {
                 }
                 // exit_mode_3
                 11 => {
+                    if ppu.pixel_sources.is_some() {
+                        trace_scan_line(ppu, 10);
+                    }
+
                     ppu.oam_read_block = false;
                     ppu.oam_write_block = false;
                     ppu.vram_read_block = false;
@@ -1400,6 +1661,14 @@ impl Ppu {
                     ppu.stat_mode_for_interrupt = 0;
                     ppu.update_stat(&mut stat_interrupt);
 
+                    // after loading a save state in the middle of mode 3, its start is unknown.
+                    if ppu.mode_3_start_clock_count > ppu.line_start_clock_count {
+                        let length = ppu.next_clock_count - ppu.mode_3_start_clock_count;
+                        if let Some(x) = ppu.mode_3_lengths.get_mut(ppu.ly as usize) {
+                            *x = length as u16;
+                        }
+                    }
+
                     ppu.next_clock_count += 1;
                     ppu.state = 12;
                 }
@@ -1543,6 +1812,7 @@ impl Ppu {
     }
 
     fn set_stat_mode(&mut self, mode: u8) {
+        crate::tracing_event!(TRACE, mode, ly = self.ly, "ppu mode");
         self.stat = (self.stat & !0b11) | mode;
     }
 
@@ -1766,28 +2036,23 @@ impl Ppu {
 
         next_interrupt = next_interrupt.min(next_vblank);
 
-        // println!(
-        //     "\
-        //     next_interrupt {next_interrupt}
-        //         ly {}, lyc {}
-        //         clock_count {}
-        //         line_start {}
-        //         next_frame {next_frame}
-        //         stat_mode {}
-        //         stat_signal {}
-        //         mode0 {next_mode0}
-        //         mode1 {next_mode1}
-        //         mode2 {next_mode2}
-        //         next_lyc {next_lyc}
-        //         next_vblank {next_vblank}
-        //     ",
-        //     self.ly,
-        //     self.lyc,
-        //     self.last_clock_count,
-        //     self.line_start_clock_count,
-        //     self.stat_mode_for_interrupt,
-        //     self.stat_signal
-        // );
+        crate::tracing_event!(
+            TRACE,
+            next_interrupt,
+            ly = self.ly,
+            lyc = self.lyc,
+            clock_count = self.last_clock_count,
+            line_start = self.line_start_clock_count,
+            next_frame,
+            stat_mode = self.stat_mode_for_interrupt,
+            stat_signal = self.stat_signal,
+            next_mode0,
+            next_mode1,
+            next_mode2,
+            next_lyc,
+            next_vblank,
+            "estimated next ppu interrupt"
+        );
 
         next_interrupt
     }
@@ -1854,6 +2119,46 @@ fn write_pallete_conflict<F: Fn(&mut Ppu) -> &mut u8>(gb: &mut GameBoy, value: u
     gb.clock_count += 1;
 }
 
+/// Find the sprites in the OAM that overlap the line `ly`, in OAM order, until `buffer` is full.
+/// Returns the number of sprites found.
+fn find_objects(oam: &[u8; 0xA0], lcdc: u8, ly: u8, buffer: &mut [Sprite]) -> usize {
+    let mut len = 0;
+    let sprite_height = if lcdc & 0x04 != 0 { 16 } else { 8 };
+    for data in oam.chunks_exact(4) {
+        if len == buffer.len() {
+            break;
+        }
+        let sy = data[0];
+        let sx = data[1];
+        let t = data[2];
+        let flags = data[3];
+
+        if ly as u16 + 16 >= sy as u16 && (ly as u16 + 16) < sy as u16 + sprite_height {
+            buffer[len] = Sprite {
+                sy,
+                sx,
+                tile: t,
+                flags,
+            };
+            len += 1;
+        }
+    }
+    len
+}
+
+/// Sort the sprites found in the OAM search by priority, in increasing order. The last sprite is
+/// the one with the highest priority, which is the first to be fetched, and the last to be drawn
+/// by `draw_scan_line`.
+///
+/// On DMG, a sprite with a lower X position has greater priority. If two sprites have the same X
+/// position, the one that comes first in the OAM has greater priority. `sprites` must be in OAM
+/// order.
+fn sort_by_priority(sprites: &mut [Sprite]) {
+    sprites.reverse();
+    // `sort_by_key` is stable, so sprites with the same X are kept in reverse OAM order.
+    sprites.sort_by_key(|x| core::cmp::Reverse(x.sx));
+}
+
 fn tick_pixel_fetcher(ppu: &mut Ppu, ly: u8) {
     let is_in_window = ppu.is_in_window;
 
@@ -1971,6 +2276,11 @@ fn output_pixel(ppu: &mut Ppu) {
         // background color, with pallete applied
         let palette = ppu.bgp;
         let mut color = (palette >> (bcolor * 2)) & 0b11;
+        let mut source = if ppu.is_in_window {
+            ColorSource::Window
+        } else {
+            ColorSource::Background
+        };
 
         if let Some(sprite_pixel) = sprite_pixel {
             let scolor = sprite_pixel & 0b11;
@@ -1980,11 +2290,15 @@ fn output_pixel(ppu: &mut Ppu) {
             } else {
                 // use sprite color
                 let palette = (sprite_pixel >> 4) & 0x1;
+                source = [ColorSource::Obj0, ColorSource::Obj1][palette as usize];
                 let palette = [ppu.obp0, ppu.obp1][palette as usize];
                 color = (palette >> (scolor * 2)) & 0b11;
             }
         }
         debug_assert!(color < 4);
+        if ppu.colorization {
+            color = source.pixel(color);
+        }
         ppu.screen.set(ppu.screen_x, ppu.ly, color);
         ppu.screen_x += 1;
         ppu.scanline_x += 1;
@@ -1992,6 +2306,155 @@ fn output_pixel(ppu: &mut Ppu) {
     }
 }
 
+/// Record where each pixel of the current scanline came from, in the PPU debug pass. Follows the
+/// same rules as `draw_scan_line`, considering the first `sprite_limit` sprites in the scanline.
+fn trace_scan_line(ppu: &mut Ppu, sprite_limit: usize) {
+    let mut sources = match ppu.pixel_sources.take() {
+        Some(x) => x,
+        None => return,
+    };
+    let line = &mut sources[ppu.ly as usize * SCREEN_WIDTH..][..SCREEN_WIDTH];
+
+    // the address of the given row of the tile, in the current addressing method.
+    let tile_address = |tile: u8, row: u8| -> u16 {
+        let base = if ppu.lcdc & 0x10 == 0 && tile < 0x80 {
+            0x9000
+        } else {
+            0x8000
+        };
+        base + tile as u16 * 0x10 + row as u16 * 2
+    };
+    let tile_color = |address: u16, column: u8| -> u8 {
+        let a = ppu.vram[address as usize - 0x8000];
+        let b = ppu.vram[address as usize + 1 - 0x8000];
+        let x = 7 - column;
+        (((b >> x) & 0b1) << 1) | ((a >> x) & 0b1)
+    };
+
+    let window_enabled = ppu.is_in_window && ppu.lcdc & 0x01 != 0;
+    let dx = if ppu.wx != 0 {
+        7
+    } else {
+        let cmp = [7u8, 9, 10, 11, 12, 13, 14, 14];
+        cmp[(ppu.scx % 8) as usize]
+    };
+    let wxs = ppu.wx.saturating_sub(dx);
+    let window_scx = wxs + dx - ppu.wx;
+
+    // the sprites in the scanline, with their OAM index, in OAM order.
+    let height = if ppu.lcdc & 0x04 != 0 { 16 } else { 8 };
+    let sprites: Vec<(u8, Sprite)> = (0..40u8)
+        .map(|i| {
+            let data = &ppu.oam[i as usize * 4..][..4];
+            let sprite = Sprite {
+                sy: data[0],
+                sx: data[1],
+                tile: data[2],
+                flags: data[3],
+            };
+            (i, sprite)
+        })
+        .filter(|(_, s)| {
+            let ly = ppu.ly as u16 + 16;
+            ly >= s.sy as u16 && ly < s.sy as u16 + height as u16
+        })
+        .take(sprite_limit)
+        .collect();
+
+    for (x, source) in line.iter_mut().enumerate() {
+        let x = x as u8;
+        *source = if ppu.lcdc & 0x01 == 0 {
+            PixelSource {
+                layer: PixelLayer::Blank,
+                palette: ppu.bgp,
+                ..PixelSource::default()
+            }
+        } else {
+            let (layer, tile_map, px, py) = if window_enabled && x >= wxs {
+                let tile_map = if ppu.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+                (PixelLayer::Window, tile_map, x - wxs + window_scx, ppu.wyc)
+            } else {
+                let tile_map = if ppu.lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+                let py = ppu.ly.wrapping_add(ppu.scy);
+                (
+                    PixelLayer::Background,
+                    tile_map,
+                    x.wrapping_add(ppu.scx),
+                    py,
+                )
+            };
+            let offset = (py as usize / 8) * 32 + (px as usize / 8) % 32;
+            let tile = ppu.vram[tile_map - 0x8000 + offset];
+            let address = tile_address(tile, py % 8);
+            PixelSource {
+                layer,
+                tile,
+                tile_address: address,
+                palette: ppu.bgp,
+                color: tile_color(address, px % 8),
+                attributes: 0,
+            }
+        };
+
+        if ppu.lcdc & 0x02 == 0 {
+            continue;
+        }
+
+        // the opaque sprite with the highest priority: the lowest X, and then the first in OAM.
+        let mut sprite_source: Option<(u8, PixelSource)> = None;
+        for &(i, sprite) in &sprites {
+            let column = (x + 8).wrapping_sub(sprite.sx);
+            if column >= 8 || matches!(sprite_source, Some((sx, _)) if sx <= sprite.sx) {
+                continue;
+            }
+            let mut row = ppu.ly + 16 - sprite.sy;
+            if sprite.flags & 0x40 != 0 {
+                row = height - 1 - row;
+            }
+            let tile = if height == 16 {
+                (sprite.tile & !1) + row / 8
+            } else {
+                sprite.tile
+            };
+            let address = 0x8000 + tile as u16 * 0x10 + (row % 8) as u16 * 2;
+            let column = if sprite.flags & 0x20 != 0 {
+                7 - column
+            } else {
+                column
+            };
+            let color = tile_color(address, column);
+            if color == 0 {
+                continue;
+            }
+            let palette = if sprite.flags & 0x10 != 0 {
+                ppu.obp1
+            } else {
+                ppu.obp0
+            };
+            sprite_source = Some((
+                sprite.sx,
+                PixelSource {
+                    layer: PixelLayer::Sprite(i),
+                    tile,
+                    tile_address: address,
+                    palette,
+                    color,
+                    attributes: sprite.flags,
+                },
+            ));
+        }
+
+        if let Some((_, sprite_source)) = sprite_source {
+            let background_priority = sprite_source.attributes & 0x80 != 0;
+            if !(background_priority && source.color != 0) {
+                *source = sprite_source;
+            }
+        }
+    }
+
+    ppu.pixel_sources = Some(sources);
+}
+
 pub fn draw_tile(
     ppu: &Ppu,
     draw_pixel: &mut impl FnMut(i32, i32, u8),
@@ -2146,7 +2609,13 @@ pub fn draw_screen(ppu: &Ppu, draw_pixel: &mut impl FnMut(i32, i32, u8)) {
     }
 }
 
-pub fn draw_scan_line(ppu: &mut Ppu) {
+// The flags stored in the unused bits of the screen pixels by `draw_scan_line`, while drawing the
+// sprites.
+const BACKGROUND_PRIORITY_FLAG: u8 = 0b001_0000;
+const SPRITE_DRAW_FLAG: u8 = 0b010_0000;
+const OBP1_FLAG: u8 = 0b100_0000;
+
+pub fn draw_scan_line(ppu: &mut Ppu, sprites: &[Sprite]) {
     let scanline = &mut ppu.screen.screen[ppu.ly as usize * Screen::STRIDE..][..Screen::STRIDE];
 
     let window_enabled = ppu.is_in_window && ppu.lcdc & 0x01 != 0;
@@ -2238,12 +2707,8 @@ pub fn draw_scan_line(ppu: &mut Ppu) {
         }
     }
 
-    const BACKGROUND_PRIORITY_FLAG: u8 = 0b01_0000;
-    const SPRITE_DRAW_FLAG: u8 = 0b10_0000;
-
     // Draw Sprites, if enabled
-    if ppu.lcdc & 0x02 != 0 && ppu.sprite_buffer_len != 0 {
-        let sprites = &&ppu.sprite_buffer[0..ppu.sprite_buffer_len as usize];
+    if ppu.lcdc & 0x02 != 0 && !sprites.is_empty() {
         for &Sprite {
             sy,
             sx,
@@ -2319,9 +2784,14 @@ pub fn draw_scan_line(ppu: &mut Ppu) {
                 *p = (*p & 0b11)
                     | (c << 2)
                     | ((flags & 0x80 != 0) as u8 * BACKGROUND_PRIORITY_FLAG)
+                    | ((flags & 0x10 != 0) as u8 * OBP1_FLAG)
                     | SPRITE_DRAW_FLAG;
             }
         }
+        if ppu.colorization {
+            colorize_scan_line(scanline, ppu.bgp, window_enabled.then_some(wxs));
+            return;
+        }
         // write sprite pixels to the screen, or apply the background pallete.
         for x in scanline[Screen::LEFT_PAD..][..SCREEN_WIDTH].iter_mut() {
             let background_color = *x & 0b11;
@@ -2333,6 +2803,8 @@ pub fn draw_scan_line(ppu: &mut Ppu) {
                 *x = (ppu.bgp >> ((background_color) * 2)) & 0b11;
             }
         }
+    } else if ppu.colorization {
+        colorize_scan_line(scanline, ppu.bgp, window_enabled.then_some(wxs));
     } else {
         // apply the background pallete.
         for x in scanline[Screen::LEFT_PAD..][..SCREEN_WIDTH].iter_mut() {
@@ -2342,9 +2814,35 @@ pub fn draw_scan_line(ppu: &mut Ppu) {
     }
 }
 
+/// The last step of `draw_scan_line`, when the colorization is enabled: the same as writing the
+/// sprite pixels or applying the background palette, but also storing the source of each pixel.
+/// `window_start` is the first screen x of the window, if it is in the scanline.
+fn colorize_scan_line(scanline: &mut [u8], bgp: u8, window_start: Option<u8>) {
+    let pixels = scanline[Screen::LEFT_PAD..][..SCREEN_WIDTH].iter_mut();
+    for (x, p) in pixels.enumerate() {
+        let background_color = *p & 0b11;
+        *p = if *p & SPRITE_DRAW_FLAG != 0
+            && !(*p & BACKGROUND_PRIORITY_FLAG != 0 && background_color != 0)
+        {
+            let source = if *p & OBP1_FLAG != 0 {
+                ColorSource::Obj1
+            } else {
+                ColorSource::Obj0
+            };
+            source.pixel((*p >> 2) & 0b11)
+        } else {
+            let source = match window_start {
+                Some(start) if x as u8 >= start => ColorSource::Window,
+                _ => ColorSource::Background,
+            };
+            source.pixel((bgp >> (background_color * 2)) & 0b11)
+        };
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::gameboy::cartridge::Cartridge;
+    use crate::gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge};
 
     use super::*;
     use rand::Rng;
@@ -2365,7 +2863,7 @@ mod test {
     fn fuzz() {
         let start_time = std::time::Instant::now();
 
-        let mut gb = GameBoy::new(None, Cartridge::halt_filled());
+        let mut gb = GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default());
         gb.predict_interrupt = true;
         let mut rng = rand::thread_rng();
 
@@ -2427,7 +2925,7 @@ mod test {
 
     #[test]
     fn case1() {
-        let mut gb = GameBoy::new(None, Cartridge::halt_filled());
+        let mut gb = GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default());
         gb.predict_interrupt = true;
         *gb.ppu.get_mut() = Ppu {
             dma_started: 0x7FFFFFFFFFFFFFFF,
@@ -2510,4 +3008,139 @@ mod test {
             panic!("interrupt is on early?");
         }
     }
+
+    fn sprite(sx: u8, tile: u8) -> Sprite {
+        Sprite {
+            sx,
+            sy: 16,
+            tile,
+            flags: 0,
+        }
+    }
+
+    fn ppu_with_oam(sprites: &[Sprite]) -> Ppu {
+        let mut ppu = Ppu {
+            lcdc: 0x93,
+            ly: 0,
+            ..Ppu::default()
+        };
+        // place all unused sprites outside of the scanline
+        ppu.oam = [0; 0xA0];
+        for (i, s) in sprites.iter().enumerate() {
+            ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[s.sy, s.sx, s.tile, s.flags]);
+        }
+        ppu
+    }
+
+    /// The sprites in `sprite_buffer`, in the order they are fetched.
+    fn fetch_order(ppu: &Ppu) -> Vec<u8> {
+        ppu.sprite_buffer[..ppu.sprite_buffer_len as usize]
+            .iter()
+            .rev()
+            .map(|x| x.tile)
+            .collect()
+    }
+
+    #[test]
+    fn sprite_priority_by_x() {
+        let mut ppu = ppu_with_oam(&[sprite(30, 0), sprite(10, 1), sprite(20, 2)]);
+        ppu.search_objects();
+        assert_eq!(fetch_order(&ppu), [1, 2, 0]);
+    }
+
+    #[test]
+    fn sprite_priority_by_oam_index() {
+        let mut ppu = ppu_with_oam(&[
+            sprite(20, 0),
+            sprite(20, 1),
+            sprite(10, 2),
+            sprite(20, 3),
+            sprite(10, 4),
+        ]);
+        ppu.search_objects();
+        assert_eq!(fetch_order(&ppu), [2, 4, 0, 1, 3]);
+    }
+
+    #[test]
+    fn sprite_priority_only_first_ten() {
+        let sprites: Vec<Sprite> = (0..12).map(|i| sprite(50 - i, i)).collect();
+        let mut ppu = ppu_with_oam(&sprites);
+        ppu.search_objects();
+        // the last two sprites in OAM are not selected, even if they have lower X.
+        assert_eq!(fetch_order(&ppu), [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn sprite_fifo_keeps_higher_priority_pixels() {
+        let mut fifo = PixelFifo::default();
+        // first sprite: left half is transparent, right half is color 1.
+        fifo.push_sprite(0x0F, 0x00, false, false);
+        // second sprite: all color 2, with palette 1.
+        fifo.push_sprite(0x00, 0xFF, true, false);
+        let pixels: Vec<u8> = fifo.iter().collect();
+        assert_eq!(pixels, [0x12, 0x12, 0x12, 0x12, 0x01, 0x01, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn sprite_fifo_background_priority_hides_lower_sprite() {
+        let mut fifo = PixelFifo::default();
+        // A opaque pixel of a higher priority sprite wins, even if it has background priority.
+        fifo.push_sprite(0xFF, 0x00, false, true);
+        fifo.push_sprite(0x00, 0xFF, false, false);
+        assert!(fifo.iter().all(|x| x == 0x09));
+    }
+
+    /// Render a frame with overlapping sprites, using both the pixel FIFO and the `draw_scan_line`
+    /// optimization, and check that both give the same result.
+    fn render_sprites(sprites: &[Sprite], step: u64) -> [u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        let mut gb = GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default());
+        {
+            let ppu = gb.ppu.get_mut();
+            ppu.vram = [0; 0x2000];
+            // tile 1: color 1 on the left half; tile 2: color 2; tile 3: color 3.
+            for y in 0..8 {
+                ppu.vram[0x10 + y * 2] = 0xF0;
+                ppu.vram[0x20 + y * 2 + 1] = 0xFF;
+                ppu.vram[0x30 + y * 2] = 0xFF;
+                ppu.vram[0x30 + y * 2 + 1] = 0xFF;
+            }
+            let oam = ppu_with_oam(sprites).oam;
+            ppu.oam = oam;
+            ppu.lcdc |= 0x02;
+            ppu.bgp = 0b11_10_01_00;
+            ppu.obp0 = 0b11_10_01_00;
+        }
+        let target = gb.clock_count + 2 * FRAME_CYCLES;
+        while gb.clock_count < target {
+            gb.clock_count = (gb.clock_count + step).min(target);
+            gb.update_ppu();
+        }
+        let screen = gb.ppu.borrow().screen.packed();
+        screen
+    }
+
+    #[test]
+    fn sprite_priority_render() {
+        let sprites = [
+            sprite(20, 2),
+            sprite(20, 1),
+            sprite(16, 3),
+            sprite(24, 1),
+            sprite(24, 3),
+        ];
+
+        let fifo = render_sprites(&sprites, 4);
+        let fast = render_sprites(&sprites, FRAME_CYCLES);
+        assert!(fifo == fast, "pixel fifo and draw_scan_line differ");
+
+        let line = &fifo[..SCREEN_WIDTH];
+        assert_eq!(line[0..8], [0; 8]);
+        // sprite 2 has the lowest x, so it has priority over everything.
+        assert_eq!(line[8..16], [3; 8]);
+        // sprite 0 has priority over sprites 1, 3 and 4, even where they are opaque.
+        assert_eq!(line[16..20], [2; 4]);
+        // sprite 4 is visible where sprite 3 is transparent.
+        assert_eq!(line[20..24], [3; 4]);
+        assert_eq!(line[24..32], [0; 8]);
+    }
 }
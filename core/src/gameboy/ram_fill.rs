@@ -0,0 +1,128 @@
+use crate::save_state::{LoadStateError, SaveState, SaveStateContext};
+
+/// The initial content of the WRAM, VRAM and OAM when the GameBoy is powered on, and of the
+/// cartridge RAM without a battery after a power cycle.
+///
+/// In real hardware the RAM powers on with semi-random content, and some games (and bugs) depend
+/// on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamFill {
+    /// All bytes are 0x00.
+    Zeros,
+    /// All bytes are 0xFF.
+    #[default]
+    Ones,
+    /// An approximation of the pattern commonly seen in DMG units: runs of 8 bytes alternating
+    /// between 0x00 and 0xFF, with the phase inverted every 0x100 bytes.
+    DmgPattern,
+    /// Pseudo-random bytes, generated from the given seed.
+    Random(u64),
+}
+
+impl RamFill {
+    /// The address of each memory region, used to make each region have a different random
+    /// content.
+    pub(crate) const WRAM: u64 = 0xC000;
+    pub(crate) const VRAM: u64 = 0x8000;
+    pub(crate) const OAM: u64 = 0xFE00;
+    pub(crate) const CARTRIDGE_RAM: u64 = 0xA000;
+
+    /// Fill `ram` following this pattern. `region` is the start address of the memory being
+    /// filled.
+    pub fn fill(self, ram: &mut [u8], region: u64) {
+        match self {
+            RamFill::Zeros => ram.fill(0x00),
+            RamFill::Ones => ram.fill(0xFF),
+            RamFill::DmgPattern => {
+                for (i, x) in ram.iter_mut().enumerate() {
+                    let run = (i / 8) % 2;
+                    let phase = (i / 0x100) % 2;
+                    *x = if run == phase { 0x00 } else { 0xFF };
+                }
+            }
+            RamFill::Random(seed) => {
+                let mut state = seed ^ region.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                for chunk in ram.chunks_mut(8) {
+                    let bytes = splitmix64(&mut state).to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+/// SplitMix64 pseudo-random number generator. It is used because it is tiny, and its output is
+/// the same in every platform.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl SaveState for RamFill {
+    fn save_state(
+        &self,
+        ctx: &mut SaveStateContext,
+        data: &mut impl crate::io::Write,
+    ) -> Result<(), crate::io::Error> {
+        let (kind, seed): (u8, u64) = match *self {
+            RamFill::Zeros => (0, 0),
+            RamFill::Ones => (1, 0),
+            RamFill::DmgPattern => (2, 0),
+            RamFill::Random(seed) => (3, seed),
+        };
+        kind.save_state(ctx, data)?;
+        seed.save_state(ctx, data)
+    }
+
+    fn load_state(
+        &mut self,
+        ctx: &mut SaveStateContext,
+        data: &mut impl crate::io::Read,
+    ) -> Result<(), LoadStateError> {
+        let mut kind = 0u8;
+        let mut seed = 0u64;
+        kind.load_state(ctx, data)?;
+        seed.load_state(ctx, data)?;
+        *self = match kind {
+            0 => RamFill::Zeros,
+            1 => RamFill::Ones,
+            2 => RamFill::DmgPattern,
+            3 => RamFill::Random(seed),
+            x => return Err(LoadStateError::InvalidRamFill(x)),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RamFill;
+
+    #[test]
+    fn random_is_reproducible() {
+        let mut a = [0; 0x2000];
+        let mut b = [0; 0x2000];
+        RamFill::Random(42).fill(&mut a, RamFill::WRAM);
+        RamFill::Random(42).fill(&mut b, RamFill::WRAM);
+        assert_eq!(a, b);
+
+        RamFill::Random(43).fill(&mut b, RamFill::WRAM);
+        assert_ne!(a, b);
+
+        RamFill::Random(42).fill(&mut b, RamFill::VRAM);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn dmg_pattern() {
+        let mut ram = [0x55; 0x200];
+        RamFill::DmgPattern.fill(&mut ram, RamFill::WRAM);
+        assert_eq!(ram[0..8], [0x00; 8]);
+        assert_eq!(ram[8..16], [0xFF; 8]);
+        assert_eq!(ram[0x100..0x108], [0xFF; 8]);
+        assert_eq!(ram[0x108..0x110], [0x00; 8]);
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::{boxed::Box, collections::VecDeque};
+
 use crate::save_state::{LoadStateError, SaveState};
 
 use super::GameBoy;
@@ -6,6 +8,26 @@ use super::GameBoy;
 /// arbitrarily, in a way that pass the serial_boot_sclk_align_dmg_abc_mgb test.
 const SERIAL_OFFSET: u64 = 8;
 
+/// A callback that receives each byte sent through the serial port.
+#[cfg(not(target_arch = "wasm32"))]
+pub type SerialCallback = Box<dyn FnMut(u8) + Send>;
+#[cfg(target_arch = "wasm32")]
+pub type SerialCallback = Box<dyn FnMut(u8)>;
+
+/// The serial callback of a new GameBoy: print the bytes to stderr.
+#[cfg(feature = "std")]
+pub(crate) fn default_callback() -> Option<SerialCallback> {
+    Some(Box::new(|c| {
+        eprint!("{}", c as char);
+    }))
+}
+
+/// The serial callback of a new GameBoy: without `std`, there is nowhere to print to.
+#[cfg(not(feature = "std"))]
+pub(crate) fn default_callback() -> Option<SerialCallback> {
+    None
+}
+
 pub struct Serial {
     /// FF01: SB
     pub serial_data: u8,
@@ -16,13 +38,14 @@ pub struct Serial {
     /// serial transfer was send. It is 0 if there is no transfer happening.
     pub serial_transfer_started: u64,
 
-    #[cfg(not(target_arch = "wasm32"))]
-    pub serial_transfer_callback: Option<Box<dyn FnMut(u8) + Send>>,
-    #[cfg(target_arch = "wasm32")]
-    pub serial_transfer_callback: Option<Box<dyn FnMut(u8)>>,
+    pub serial_transfer_callback: Option<SerialCallback>,
 
     /// The estimated time where the next interrupt may happen.
     pub next_interrupt: u64,
+
+    /// The bytes sent by a stubbed link partner. At the end of each transfer, the next byte is
+    /// received in SB. If empty, SB is left unchanged, as if there was no partner.
+    pub link_partner: VecDeque<u8>,
 }
 
 impl Eq for Serial {}
@@ -51,10 +74,9 @@ impl Serial {
             serial_data: 0,
             serial_control: 0x7E,
             serial_transfer_started: 0,
-            serial_transfer_callback: Some(Box::new(|c| {
-                eprint!("{}", c as char);
-            })),
+            serial_transfer_callback: default_callback(),
             next_interrupt: 0,
+            link_partner: VecDeque::new(),
         }
     }
 
@@ -73,6 +95,10 @@ impl Serial {
             self.serial_control &= !0x80;
             self.serial_transfer_started = 0;
 
+            if let Some(data) = self.link_partner.pop_front() {
+                self.serial_data = data;
+            }
+
             // interrupt
             return true;
         }
@@ -107,6 +133,31 @@ impl Serial {
         gb.update_next_interrupt();
     }
 
+    /// Complete a transfer clocked by the link partner, which sends `data`, if the game is waiting
+    /// for one, by having started a transfer with the external clock selected in SC. Return the
+    /// byte that was in SB, received by the partner, or None if the game was not waiting for a
+    /// transfer, in which case nothing is exchanged.
+    ///
+    /// The transfer completes immediately, instead of over the 8 clocks sent by the partner.
+    pub fn external_transfer(gb: &mut GameBoy, data: u8) -> Option<u8> {
+        gb.update_serial();
+        let this = &mut *gb.serial.get_mut();
+        if this.serial_control & 0x81 != 0x80 {
+            return None;
+        }
+        let sent = this.serial_data;
+        this.serial_data = data;
+        this.serial_control &= !0x80;
+        if let Some(x) = this.serial_transfer_callback.as_mut() {
+            x(sent)
+        }
+
+        // interrupt
+        gb.interrupt_flag.set(gb.interrupt_flag.get() | (1 << 3));
+        gb.update_next_interrupt();
+        Some(sent)
+    }
+
     pub fn read(gb: &GameBoy, address: u8) -> u8 {
         match address {
             0x01 => gb.serial.borrow().serial_data,
@@ -1,3 +1,5 @@
+use alloc::{collections::VecDeque, vec::Vec};
+
 use crate::{
     consts::CLOCK_SPEED,
     save_state::{LoadStateError, SaveState},
@@ -102,7 +104,8 @@ pub struct SoundController {
     ch4_lfsr: u16,
     ch4_frequency_timer: u16,
 
-    /// Audio buffer with generated samples. Each frame has two samples: left and right.
+    /// Audio buffer with generated samples. Each frame has two samples: left and right, each one
+    /// being the sum of the channels routed to that side by NR51, scaled by the volume in NR50.
     output: Vec<u16>,
     /// Clock count at the last sound update
     pub last_clock_count: u64,
@@ -110,8 +113,15 @@ pub struct SoundController {
     /// means that there will be no sampling.
     pub sample_frequency: u64,
 
-    /// The remainder of `curr_clock * sample_frequency / CLOCK_SPEED`, used for timing the samples.
-    sample_mod: u64,
+    /// If the amplitude of each channel is recorded in `channels_history` at each sample.
+    pub record_channels: bool,
+    /// The amplitude of each channel, from 0 to 15, in the last `CHANNELS_HISTORY_LEN` samples.
+    /// Used for visualizing the waveforms.
+    channels_history: VecDeque<[u8; 4]>,
+
+    /// The filters applied to the samples returned by `get_filtered_output`.
+    pub filter_mode: FilterMode,
+    filter_state: FilterState,
 }
 
 impl PartialEq for SoundController {
@@ -170,7 +180,10 @@ impl PartialEq for SoundController {
             // && self.output == other.output
             && self.last_clock_count == other.last_clock_count
         // && self.sample_frequency == other.sample_frequency
-        // && self.sample_mod == other.sample_mod
+        // && self.record_channels == other.record_channels
+        // && self.channels_history == other.channels_history
+        // && self.filter_mode == other.filter_mode
+        // && self.filter_state == other.filter_state
     }
 }
 crate::save_state!(SoundController, self, data {
@@ -240,7 +253,10 @@ crate::save_state!(SoundController, self, data {
     // self.output;
     self.last_clock_count;
     // self.sample_frequency;
-    // self.sample_mod;
+    // self.record_channels;
+    // self.channels_history;
+    // self.filter_mode;
+    // self.filter_state;
 });
 impl Default for SoundController {
     fn default() -> Self {
@@ -302,18 +318,218 @@ impl Default for SoundController {
             output: Vec::default(),
             last_clock_count: 0,
             sample_frequency: 0,
-            sample_mod: 0,
+            record_channels: false,
+            channels_history: VecDeque::new(),
+            filter_mode: FilterMode::default(),
+            filter_state: FilterState::default(),
         }
     }
 }
 
+/// The analog filters applied to the output of the sound controller, by
+/// `SoundController::get_filtered_output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    /// The high-pass filter of the output, and the roll off of the highs of the DMG speaker.
+    #[default]
+    Hardware,
+    /// Only the high-pass filter, which removes the DC offset of the output.
+    HighPass,
+    /// No filtering. The output keeps the DC offset, as in older versions.
+    Raw,
+}
+
+/// The charge factor of the high-pass capacitor of the DMG, per clock cycle.
+const HIGH_PASS_CHARGE: f32 = 0.999958;
+/// The cutoff frequency, in Hertz, of the low-pass filter that approximates the DMG speaker.
+const LOW_PASS_CUTOFF: f32 = 10_000.0;
+/// The gain from the sound controller output to the 16-bit samples.
+const OUTPUT_GAIN: f32 = 30.0;
+
+/// `f32::powf`, that is only available with `std`.
+#[cfg(feature = "std")]
+fn powf(x: f32, y: f32) -> f32 {
+    x.powf(y)
+}
+
+/// `f32::exp`, that is only available with `std`.
+#[cfg(feature = "std")]
+fn exp(x: f32) -> f32 {
+    x.exp()
+}
+
+/// A approximation of `f32::powf`, for positive `x`, precise enough for the filter coefficients.
+#[cfg(not(feature = "std"))]
+fn powf(x: f32, y: f32) -> f32 {
+    use core::f64::consts::LN_2;
+    // x = m * 2^e, with m in [1, 2), and ln(m) = 2 * atanh((m - 1) / (m + 1)).
+    let bits = x.to_bits();
+    let e = ((bits >> 23) & 0xff) as i32 - 127;
+    let m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000) as f64;
+    let s = (m - 1.0) / (m + 1.0);
+    let mut term = s;
+    let mut atanh = 0.0;
+    for i in 0..12 {
+        atanh += term / (2 * i + 1) as f64;
+        term *= s * s;
+    }
+    exp(((2.0 * atanh + e as f64 * LN_2) * y as f64) as f32)
+}
+
+/// A approximation of `f32::exp`, precise enough for the filter coefficients.
+#[cfg(not(feature = "std"))]
+fn exp(x: f32) -> f32 {
+    use core::f64::consts::LN_2;
+    // x = k * ln(2) + r, with |r| <= ln(2) / 2, and exp(r) by its Taylor series.
+    let x = x.clamp(-87.0, 88.0) as f64;
+    let k = (x / LN_2 + if x < 0.0 { -0.5 } else { 0.5 }) as i32;
+    let r = x - k as f64 * LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for i in 1..14 {
+        term *= r / i as f64;
+        sum += term;
+    }
+    (sum * f64::from_bits(((k + 1023) as u64) << 52)) as f32
+}
+
+/// The state of the output filters, for the left and right channels.
+#[derive(Debug, Clone, Default)]
+struct FilterState {
+    high_pass_charge: [f32; 2],
+    low_pass: [f32; 2],
+}
+impl PartialEq for FilterState {
+    fn eq(&self, other: &Self) -> bool {
+        self.high_pass_charge.map(f32::to_bits) == other.high_pass_charge.map(f32::to_bits)
+            && self.low_pass.map(f32::to_bits) == other.low_pass.map(f32::to_bits)
+    }
+}
+impl Eq for FilterState {}
+impl FilterState {
+    /// Filter the interleaved left and right `samples`, sampled at `sample_frequency`.
+    fn apply(&mut self, mode: FilterMode, sample_frequency: u64, samples: &[u16]) -> Vec<i16> {
+        if mode == FilterMode::Raw || sample_frequency == 0 {
+            return samples
+                .iter()
+                .map(|&x| (x as i16 - 128) * OUTPUT_GAIN as i16)
+                .collect();
+        }
+
+        let clocks_per_sample = CLOCK_SPEED as f32 / sample_frequency as f32;
+        let charge_factor = powf(HIGH_PASS_CHARGE, clocks_per_sample);
+        let low_pass = mode == FilterMode::Hardware;
+        let alpha =
+            1.0 - exp(-2.0 * core::f32::consts::PI * LOW_PASS_CUTOFF / sample_frequency as f32);
+
+        samples
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let c = i % 2;
+                let input = x as f32;
+                let mut out = input - self.high_pass_charge[c];
+                self.high_pass_charge[c] = input - out * charge_factor;
+                if low_pass {
+                    self.low_pass[c] += alpha * (out - self.low_pass[c]);
+                    out = self.low_pass[c];
+                }
+                (out * OUTPUT_GAIN).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            })
+            .collect()
+    }
+}
+
 const WAVE_DUTY_TABLE: [u8; 4] = [0b0000_0001, 0b0000_0011, 0b0000_1111, 0b1111_1100];
 
+/// The number of samples kept in `SoundController::channels_history`.
+pub const CHANNELS_HISTORY_LEN: usize = 4096;
+
+/// The remainder of `clock * sample_frequency / CLOCK_SPEED`, used for timing the samples.
+fn sample_phase(sample_frequency: u64, clock: u64) -> u64 {
+    ((clock % CLOCK_SPEED) * sample_frequency) % CLOCK_SPEED
+}
+
+/// The number of samples, at `sample_frequency`, that the sound controller generates between the
+/// clock counts `start` (inclusive) and `end` (exclusive). Each sample produces two values in the
+/// output, the left and right channels.
+///
+/// A sample is taken at the first even clock count after each multiple of `CLOCK_SPEED /
+/// sample_frequency`, counting from clock 0. So the samples of a span only depend on its start and
+/// end, and the fractional remainder of a span is carried to the next one: the samples of
+/// consecutive spans, like the frames of a recording, always add up to the samples of the entire
+/// span, without drifting.
+pub fn sample_count(sample_frequency: u64, start: u64, end: u64) -> u64 {
+    // the number of samples at the even clock counts before `clock`.
+    let samples_before = |clock: u64| {
+        if clock == 0 {
+            return 0;
+        }
+        // the last even clock count before `clock`.
+        let last = (clock - 1) & !1;
+        // last * fs / fc, without overflowing for large clock counts.
+        let n = (last / CLOCK_SPEED) * sample_frequency
+            + (last % CLOCK_SPEED) * sample_frequency / CLOCK_SPEED;
+        n + 1
+    };
+    if end <= start {
+        return 0;
+    }
+    samples_before(end) - samples_before(start)
+}
+
 impl SoundController {
+    /// Reset the sound controller to its power on state, but keep the configuration of the
+    /// frontend (`sample_frequency`, `record_channels` and `filter_mode`).
+    pub fn reset(&mut self) {
+        *self = Self {
+            sample_frequency: self.sample_frequency,
+            record_channels: self.record_channels,
+            filter_mode: self.filter_mode,
+            ..Self::default()
+        };
+    }
+
+    /// The amplitude of each channel in the last samples, from the oldest to the newest. Is empty
+    /// unless `record_channels` is set.
+    pub fn channels_history(&self) -> &VecDeque<[u8; 4]> {
+        &self.channels_history
+    }
+
+    fn push_channels(&mut self, amplitudes: [u8; 4]) {
+        if self.channels_history.len() == CHANNELS_HISTORY_LEN {
+            self.channels_history.pop_front();
+        }
+        self.channels_history.push_back(amplitudes);
+    }
+
+    /// The number of samples that updating to `clock_count` will add to the output. See
+    /// `sample_count`.
+    pub fn samples_until(&self, clock_count: u64) -> u64 {
+        sample_count(self.sample_frequency, self.last_clock_count, clock_count)
+    }
+
     /// Updates itself and return the currently generated audio output. The buffer is cleared.
     pub fn get_output(&mut self, clock_count: u64) -> Vec<u16> {
         self.update(clock_count);
-        std::mem::take(&mut self.output)
+        core::mem::take(&mut self.output)
+    }
+
+    /// Same as `get_output`, but return signed 16-bit samples, filtered according to
+    /// `filter_mode`.
+    pub fn get_filtered_output(&mut self, clock_count: u64) -> Vec<i16> {
+        let output = self.get_output(clock_count);
+        self.filter_state
+            .apply(self.filter_mode, self.sample_frequency, &output)
+    }
+
+    /// Same as `get_filtered_output`, but with the left and right samples of each frame
+    /// separated, as `[left, right]`.
+    pub fn get_stereo_output(&mut self, clock_count: u64) -> Vec<[i16; 2]> {
+        self.get_filtered_output(clock_count)
+            .chunks_exact(2)
+            .map(|x| [x[0], x[1]])
+            .collect()
     }
 
     /// Emulate the sound controller until to the currently `clock_count`, since the `clock_count`
@@ -327,28 +543,17 @@ impl SoundController {
         // if it is off, there is no need for audio generation
         if !self.on {
             if self.sample_frequency != 0 {
-                // compute the number of samples (multiples of k) beetween l (inclusive) and r (exclusive)
-                //  k = fc/fs
-                //  n = r/k - l/k + (l%k == 0) <- for r, l and k integers
-                //  => n = r*fs/fc - l*fs/fc + (l*fs % fc < fs) <- for r, l, fs and fc integers
-
-                // map clock_count to a smaller value, to avoid multiplication overflows in the
-                // distance future
-                let anchor = self.last_clock_count - (self.last_clock_count % CLOCK_SPEED);
-                let l = self.last_clock_count - anchor;
-                let r = clock_count - anchor;
-
-                let n = r * self.sample_frequency / CLOCK_SPEED
-                    - l * self.sample_frequency / CLOCK_SPEED
-                    + ((l * self.sample_frequency) % CLOCK_SPEED < self.sample_frequency) as u64;
+                let n = sample_count(self.sample_frequency, self.last_clock_count, clock_count);
                 // for each sample, there is two values (left and right channels)
                 self.output.extend((0..2 * n).map(|_| 0));
+                if self.record_channels {
+                    for _ in 0..n.min(CHANNELS_HISTORY_LEN as u64) {
+                        self.push_channels([0; 4]);
+                    }
+                }
             }
 
             self.last_clock_count = clock_count;
-            let elapsed_clock = clock_count - self.last_clock_count;
-            self.sample_mod =
-                (self.sample_mod + elapsed_clock * self.sample_frequency) % CLOCK_SPEED;
             return;
         }
 
@@ -379,6 +584,7 @@ impl SoundController {
         let ch4_divisor: u16 = [8, 16, 32, 48, 64, 80, 96, 112][self.nr43 as usize & 0x07];
 
         // mixing
+        // bits 7 and 3 of NR50 route the VIN signal of the cartridge, which no cartridge uses.
         let volume_left = (self.nr50 & 0x70) >> 4;
         let ch1_left = (self.nr51 & 0x10) != 0;
         let ch2_left = (self.nr51 & 0x20) != 0;
@@ -406,24 +612,18 @@ impl SoundController {
                 u64::MAX
             } else {
                 let fs = self.sample_frequency;
-                let next_sample = clock + (CLOCK_SPEED - self.sample_mod).div_ceil(fs);
+                let next_sample = clock + (CLOCK_SPEED - sample_phase(fs, clock)).div_ceil(fs);
                 next_sample + (next_sample % 2 != 0) as u64
             };
             debug_assert!(clock < next_step);
             debug_assert!(clock < next_sample);
 
-            let previous_clock = clock;
             clock = next_step.min(next_sample);
 
             if clock >= clock_count {
-                let delta = (r - 2) - previous_clock;
-                self.sample_mod = (self.sample_mod + delta * self.sample_frequency) % CLOCK_SPEED;
                 break;
             }
 
-            let delta = clock - previous_clock;
-            self.sample_mod = (self.sample_mod + delta * self.sample_frequency) % CLOCK_SPEED;
-
             if next_step == clock {
                 // TODO: a step should happens in a falling edge of the bit 13 of the Timer's DIV
                 // register. We only need to keep track of the DIV's phase, and update it when need.
@@ -608,8 +808,19 @@ impl SoundController {
                     }
                 }
 
-                self.output.push(left * volume_left as u16);
-                self.output.push(right * volume_right as u16);
+                // the master volume goes from 1/8 to 8/8, so 0 doesn't mute a side.
+                self.output.push(left * (volume_left as u16 + 1));
+                self.output.push(right * (volume_right as u16 + 1));
+
+                if self.record_channels {
+                    let ch3_on = self.ch3_channel_enable && self.nr30 & 0x80 != 0;
+                    self.push_channels([
+                        if self.ch1_channel_enable { ch1_amp } else { 0 },
+                        if self.ch2_channel_enable { ch2_amp } else { 0 },
+                        if ch3_on { ch3_amp } else { 0 },
+                        if self.ch4_channel_enable { ch4_amp } else { 0 },
+                    ]);
+                }
             }
         }
         if clock_count > last_run + 1 {
@@ -702,28 +913,12 @@ impl SoundController {
         // if it is off, there is no need for audio generation
         if !self.on {
             if self.sample_frequency != 0 {
-                // compute the number of samples (multiples of k) beetween l (inclusive) and r (exclusive)
-                //  k = fc/fs
-                //  n = r/k - l/k + (l%k == 0) <- for r, l and k integers
-                //  => n = r*fs/fc - l*fs/fc + (l*fs % fc < fs) <- for r, l, fs and fc integers
-
-                // map clock_count to a smaller value, to avoid multiplication overflows in the
-                // distance future
-                let anchor = self.last_clock_count - (self.last_clock_count % CLOCK_SPEED);
-                let l = self.last_clock_count - anchor;
-                let r = clock_count - anchor;
-
-                let n = r * self.sample_frequency / CLOCK_SPEED
-                    - l * self.sample_frequency / CLOCK_SPEED
-                    + ((l * self.sample_frequency) % CLOCK_SPEED < self.sample_frequency) as u64;
+                let n = sample_count(self.sample_frequency, self.last_clock_count, clock_count);
                 // for each sample, there is two values (left and right channels)
                 self.output.extend((0..2 * n).map(|_| 0));
             }
 
             self.last_clock_count = clock_count;
-            let elapsed_clock = clock_count - self.last_clock_count;
-            self.sample_mod =
-                (self.sample_mod + elapsed_clock * self.sample_frequency) % CLOCK_SPEED;
             return;
         }
 
@@ -754,6 +949,7 @@ impl SoundController {
         let ch4_divisor: u16 = [8, 16, 32, 48, 64, 80, 96, 112][self.nr43 as usize & 0x07];
 
         // mixing
+        // bits 7 and 3 of NR50 route the VIN signal of the cartridge, which no cartridge uses.
         let volume_left = (self.nr50 & 0x70) >> 4;
         let ch1_left = (self.nr51 & 0x10) != 0;
         let ch2_left = (self.nr51 & 0x20) != 0;
@@ -934,12 +1130,9 @@ impl SoundController {
                 // collect a sample
 
                 // c % (fc/fs) == 0 ~> c % (fc/fs) < 1 => (c*fs) % fc < fs
-                // => ( (c-1)*fs)%fc + fs) % fc < fs
-                // => (    last      + fs) % fc < fs
 
                 // I multiple fs by 2, because the clock increase in two by two
-                self.sample_mod = (self.sample_mod + 2 * self.sample_frequency) % CLOCK_SPEED;
-                if self.sample_mod < 2 * self.sample_frequency {
+                if sample_phase(self.sample_frequency, clock) < 2 * self.sample_frequency {
                     let ch1_amp =
                         ((WAVE_DUTY_TABLE[ch1_duty as usize] >> self.ch1_wave_duty_position) & 0x1)
                             * self.ch1_current_volume;
@@ -985,8 +1178,9 @@ impl SoundController {
                         }
                     }
 
-                    self.output.push(left * volume_left as u16);
-                    self.output.push(right * volume_right as u16);
+                    // the master volume goes from 1/8 to 8/8, so 0 doesn't mute a side.
+                    self.output.push(left * (volume_left as u16 + 1));
+                    self.output.push(right * (volume_right as u16 + 1));
                 }
             }
         }
@@ -1326,10 +1520,13 @@ impl SoundController {
                         nr41: self.nr41 & 0x3F,
                         ch4_length_timer: self.ch4_length_timer,
 
-                        output: std::mem::take(&mut self.output),
+                        output: core::mem::take(&mut self.output),
                         last_clock_count: self.last_clock_count,
                         sample_frequency: self.sample_frequency,
-                        sample_mod: self.sample_mod,
+                        record_channels: self.record_channels,
+                        channels_history: core::mem::take(&mut self.channels_history),
+                        filter_mode: self.filter_mode,
+                        filter_state: self.filter_state.clone(),
                         ..Self::default()
                     };
                 } else if value & 0x80 != 0 && !self.on {
@@ -1501,7 +1698,7 @@ mod test {
     #[test]
     fn case1() {
         #[rustfmt::skip]
-        let mut sound = SoundController { nr10: 0, nr11: 5, nr12: 0, nr13: 0, nr14: 0, nr21: 0, nr22: 0, nr23: 0, nr24: 0, nr30: 0, nr31: 99, nr32: 0, nr33: 0, nr34: 0, ch3_wave_pattern: [240, 214, 67, 163, 199, 10, 6, 197, 14, 228, 70, 146, 52, 77, 129, 74], nr41: 2, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 59, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 0, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 157, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 62, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: [0, 0].to_vec(), last_clock_count: 100, sample_frequency: 10843, sample_mod: 21686, };
+        let mut sound = SoundController { nr10: 0, nr11: 5, nr12: 0, nr13: 0, nr14: 0, nr21: 0, nr22: 0, nr23: 0, nr24: 0, nr30: 0, nr31: 99, nr32: 0, nr33: 0, nr34: 0, ch3_wave_pattern: [240, 214, 67, 163, 199, 10, 6, 197, 14, 228, 70, 146, 52, 77, 129, 74], nr41: 2, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 59, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 0, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 157, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 62, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: [0, 0].to_vec(), last_clock_count: 100, sample_frequency: 10843, ..SoundController::default() };
         let mut clock_count = sound.last_clock_count;
 
         let timer_start = sound.clone();
@@ -1517,7 +1714,7 @@ mod test {
     #[test]
     fn case2() {
         #[rustfmt::skip]
-        let mut sound = SoundController { nr10: 0, nr11: 0, nr12: 0, nr13: 0, nr14: 0, nr21: 0, nr22: 0, nr23: 0, nr24: 0, nr30: 0, nr31: 0, nr32: 0, nr33: 0, nr34: 0, ch3_wave_pattern: [65, 64, 67, 170, 45, 120, 208, 60, 225, 11, 239, 176, 52, 184, 46, 74], nr41: 0, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 0, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 0, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 0, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 0, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: [0, 0, 0, 0].to_vec(), last_clock_count: 100, sample_frequency: 97408, sample_mod: 0 };
+        let mut sound = SoundController { nr10: 0, nr11: 0, nr12: 0, nr13: 0, nr14: 0, nr21: 0, nr22: 0, nr23: 0, nr24: 0, nr30: 0, nr31: 0, nr32: 0, nr33: 0, nr34: 0, ch3_wave_pattern: [65, 64, 67, 170, 45, 120, 208, 60, 225, 11, 239, 176, 52, 184, 46, 74], nr41: 0, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 0, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 0, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 0, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 0, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: [0, 0, 0, 0].to_vec(), last_clock_count: 100, sample_frequency: 97408, ..SoundController::default() };
         let mut clock_count = sound.last_clock_count;
 
         let timer_start = sound.clone();
@@ -1533,7 +1730,7 @@ mod test {
     #[test]
     fn case3() {
         #[rustfmt::skip]
-           let mut sound = SoundController { nr10: 0, nr11: 37, nr12: 0, nr13: 40, nr14: 0, nr21: 6, nr22: 0, nr23: 0, nr24: 0, nr30: 184, nr31: 148, nr32: 0, nr33: 91, nr34: 0, ch3_wave_pattern: [187, 26, 80, 4, 215, 120, 80, 50, 7, 255, 7, 52, 52, 67, 13, 15], nr41: 10, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 27, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 58, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 108, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 54, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: Vec::new(), last_clock_count: 65536, sample_frequency: 111537, sample_mod: 80512 };
+           let mut sound = SoundController { nr10: 0, nr11: 37, nr12: 0, nr13: 40, nr14: 0, nr21: 6, nr22: 0, nr23: 0, nr24: 0, nr30: 184, nr31: 148, nr32: 0, nr33: 91, nr34: 0, ch3_wave_pattern: [187, 26, 80, 4, 215, 120, 80, 50, 7, 255, 7, 52, 52, 67, 13, 15], nr41: 10, nr42: 0, nr43: 0, nr44: 0, nr50: 0, nr51: 0, on: true, frame_sequencer_step: 0, ch1_channel_enable: false, ch1_length_timer: 27, ch1_sweep_enabled: false, ch1_shadow_freq: 0, ch1_sweep_timer: 0, ch1_has_done_sweep_calculation: false, ch1_frequency_timer: 0, ch1_wave_duty_position: 0, ch1_current_volume: 0, ch1_env_period_timer: 0, ch2_channel_enable: false, ch2_length_timer: 58, ch2_frequency_timer: 0, ch2_wave_duty_position: 0, ch2_current_volume: 0, ch2_env_period_timer: 0, ch3_channel_enable: false, ch3_length_timer: 108, ch3_frequency_timer: 0, ch3_wave_position: 0, ch3_sample_buffer: 0, ch3_wave_just_read: false, ch4_channel_enable: false, ch4_length_timer: 54, ch4_current_volume: 0, ch4_env_period_timer: 0, ch4_lfsr: 0, ch4_frequency_timer: 0, output: Vec::new(), last_clock_count: 65536, sample_frequency: 111537, ..SoundController::default() };
         let mut clock_count = sound.last_clock_count;
 
         let timer_start = sound.clone();
@@ -1545,4 +1742,26 @@ mod test {
         sound.update(clock_count);
         check_with_ref(&timer_start, &mut sound);
     }
+
+    #[test]
+    fn filters() {
+        // a constant output, like a channel with a DAC enabled but silent.
+        let samples = vec![300u16; 2 * 44100];
+
+        let mut state = FilterState::default();
+        let raw = state.apply(FilterMode::Raw, 44100, &samples);
+        assert!(raw.iter().all(|&x| x == (300 - 128) * 30));
+
+        for mode in [FilterMode::HighPass, FilterMode::Hardware] {
+            let mut state = FilterState::default();
+            let out = state.apply(mode, 44100, &samples);
+            // the DC offset is removed in less than a second.
+            assert!(out[0] > 0);
+            assert!(
+                out[out.len() - 2..].iter().all(|&x| x.abs() < 16),
+                "{:?}",
+                mode
+            );
+        }
+    }
 }
@@ -254,21 +254,33 @@ impl Timer {
 
     pub fn write(&mut self, address: u8, value: u8) {
         match address {
-            0x04 => self.div = 0,
+            0x04 => {
+                crate::tracing_event!(TRACE, div = self.div, "DIV reset");
+                self.div = 0
+            }
             0x05 => {
                 if self.loading > 4 {
                     // cancel reloading
+                    crate::tracing_event!(
+                        DEBUG,
+                        loading = self.loading,
+                        "TIMA write cancels reload"
+                    );
                     self.loading = 0;
                     self.tima = value
                 } else if self.loading == 0 {
                     self.tima = value
                 } else {
                     // ignored while reloading
+                    crate::tracing_event!(DEBUG, loading = self.loading, "TIMA write ignored");
                 }
             }
             0x06 => self.tma = value,
 
-            0x07 => self.tac = value,
+            0x07 => {
+                crate::tracing_event!(TRACE, old = self.tac, new = value, "TAC write");
+                self.tac = value
+            }
             _ => unreachable!("out of Timer memory map"),
         }
         self.next_interrupt = self.estimate_next_interrupt();
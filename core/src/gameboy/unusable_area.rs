@@ -0,0 +1,29 @@
+/// How the unusable area of the memory map, FEA0-FEFF, behaves. It differs between hardware
+/// revisions, and some games read it by accident, for example through a off-by-one OAM loop.
+///
+/// In all revisions the area reads as 0xFF while the OAM is blocked, by the PPU or by a OAM DMA,
+/// and writes are ignored. The OAM corruption that accesses to this area trigger in the DMG is not
+/// emulated, and neither is the RAM that CGB revisions 0 to D have in this area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnusableArea {
+    /// DMG, MGB and SGB: reads return 0x00.
+    #[default]
+    Dmg,
+    /// CGB revision E, AGB and AGS: reads return the high nibble of the lower byte of the address,
+    /// twice. For example, FEAx reads as 0xAA and FEBx as 0xBB.
+    Cgb,
+}
+
+impl UnusableArea {
+    /// The value read from `address`, in FEA0-FEFF, when the OAM is not blocked.
+    pub fn read(self, address: u16) -> u8 {
+        debug_assert!((0xFEA0..=0xFEFF).contains(&address));
+        match self {
+            UnusableArea::Dmg => 0x00,
+            UnusableArea::Cgb => {
+                let nibble = (address as u8) >> 4;
+                (nibble << 4) | nibble
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Feature-gated instrumentation with the `tracing` crate.
+//!
+//! With the `tracing` feature, these macros forward to `tracing`, so the timing interactions
+//! between the CPU, the PPU, the timer and the MBC can be followed with any `tracing` subscriber.
+//! Without it they expand to nothing, and their arguments are not even evaluated.
+
+/// Emit a event at the given level (`TRACE`, `DEBUG`, `INFO`, `WARN` or `ERROR`). The rest of the
+/// arguments are the ones of `tracing::event!`.
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! tracing_event {
+    ($level:ident, $($arg:tt)+) => {
+        $crate::tracing::event!($crate::tracing::Level::$level, $($arg)+)
+    };
+}
+
+/// Emit a event at the given level (`TRACE`, `DEBUG`, `INFO`, `WARN` or `ERROR`). The rest of the
+/// arguments are the ones of `tracing::event!`.
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! tracing_event {
+    ($level:ident, $($arg:tt)+) => {};
+}
+
+/// Enter a span at the given level, until the end of the current block. The rest of the arguments
+/// are the ones of `tracing::span!`.
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! tracing_span {
+    ($level:ident, $($arg:tt)+) => {
+        let _span = $crate::tracing::span!($crate::tracing::Level::$level, $($arg)+).entered();
+    };
+}
+
+/// Enter a span at the given level, until the end of the current block. The rest of the arguments
+/// are the ones of `tracing::span!`.
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! tracing_span {
+    ($level:ident, $($arg:tt)+) => {};
+}
@@ -1,4 +1,5 @@
-use std::ops::ControlFlow;
+use alloc::format;
+use core::ops::ControlFlow;
 
 use crate::{
     consts,
@@ -68,6 +69,10 @@ fn add(a: u8, b: u8) -> u8 {
 pub struct Interpreter<'a>(pub &'a mut GameBoy);
 impl Interpreter<'_> {
     pub fn interpret_op(&mut self) {
+        // stop timing the interrupt handlers that returned in the previous instruction.
+        let (sp, clock_count) = (self.0.cpu.sp, self.0.clock_count);
+        self.0.counters.get_mut().leave_handlers(sp, clock_count);
+
         if let ControlFlow::Break(_) = self.handle_interrupt() {
             return;
         }
@@ -80,6 +85,8 @@ impl Interpreter<'_> {
             return;
         }
 
+        self.0.counters.get_mut().instructions += 1;
+
         #[cfg(feature = "wave_trace")]
         {
             const MB: usize = 1024 * 1024;
@@ -94,23 +101,21 @@ impl Interpreter<'_> {
 
         let op = self.read_next_pc();
 
-        let trace = false;
-        if trace {
-            println!(
-                "{:04x}: {:02x} {:04x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x}",
-                self.0.cpu.pc,
-                op,
-                self.0.cpu.sp,
-                self.0.cpu.a,
-                self.0.cpu.f.0,
-                self.0.cpu.b,
-                self.0.cpu.c,
-                self.0.cpu.d,
-                self.0.cpu.e,
-                self.0.cpu.h,
-                self.0.cpu.l,
-            );
-        }
+        crate::tracing_event!(
+            TRACE,
+            "{:04x}: {:02x} {:04x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x}",
+            self.0.cpu.pc,
+            op,
+            self.0.cpu.sp,
+            self.0.cpu.a,
+            self.0.cpu.f.0,
+            self.0.cpu.b,
+            self.0.cpu.c,
+            self.0.cpu.d,
+            self.0.cpu.e,
+            self.0.cpu.h,
+            self.0.cpu.l,
+        );
 
         use Condition::*;
         match op {
@@ -674,6 +679,7 @@ impl Interpreter<'_> {
             self.0.cpu.state = CpuState::Running;
 
             if self.0.cpu.ime == ImeState::Enabled {
+                let dispatch_clock = self.0.clock_count;
                 self.0.cpu.ime = ImeState::Disabled;
                 self.0.tick(4);
                 let mut interrupt = 8;
@@ -716,12 +722,28 @@ impl Interpreter<'_> {
                 };
 
                 if interrupt != 8 {
+                    crate::tracing_event!(
+                        DEBUG,
+                        interrupt = crate::gameboy::counters::INTERRUPT_NAMES[interrupt],
+                        clock_count = self.0.clock_count,
+                        "interrupt dispatched to {:04x}",
+                        address
+                    );
+                    let sp = self.0.cpu.sp;
+                    let counters = self.0.counters.get_mut();
+                    counters.interrupts[interrupt] += 1;
+                    counters.enter_handler(interrupt, sp, dispatch_clock);
                     self.0.update_interrupt();
                     self.0
                         .interrupt_flag
                         .set(self.0.interrupt_flag.get() & !(1 << interrupt));
                     self.jump_to(address);
                 } else {
+                    crate::tracing_event!(
+                        DEBUG,
+                        clock_count = self.0.clock_count,
+                        "interrupt canceled by the push, jumping to 0000"
+                    );
                     self.jump_to(0x0000);
                 }
                 self.0.tick(4);
@@ -1256,6 +1278,7 @@ impl Interpreter<'_> {
     /// Print the instruction around the current running instruction
     /// and the current state of the CPU.
     /// Wait for command in the stdin.
+    #[cfg(feature = "std")]
     pub fn debug(&mut self) {
         let mut input = String::new();
         input.clear();
@@ -1954,8 +1977,10 @@ impl Interpreter<'_> {
         if self.0.interrupt_flag.get() & self.0.interrupt_enabled != 0
             && self.0.cpu.ime != ImeState::Enabled
         {
+            crate::tracing_event!(DEBUG, clock_count = self.0.clock_count, "halt bug");
             self.0.cpu.halt_bug = true;
         } else {
+            crate::tracing_event!(TRACE, clock_count = self.0.clock_count, "halt");
             self.0.cpu.state = CpuState::Halt;
         }
     }
@@ -2045,6 +2070,7 @@ impl Interpreter<'_> {
 
     #[inline(always)]
     pub fn stop(&mut self) {
+        crate::tracing_event!(DEBUG, clock_count = self.0.clock_count, "stop");
         self.0.cpu.state = CpuState::Stopped;
         self.0.cpu.pc = add16(self.0.cpu.pc, 1);
     }
@@ -2069,7 +2095,7 @@ impl Interpreter<'_> {
 
     #[inline(always)]
     pub fn invalid_opcode(&mut self, _opcode: u8) {
-        // println!("executed invalid instructions: {_opcode:02x}");
+        crate::tracing_event!(WARN, "executed invalid instruction {:02x}", _opcode);
     }
 
     #[inline(always)]
@@ -2175,8 +2201,8 @@ impl Interpreter<'_> {
                 // LD (HL), .. or INC (HL) or etc.
                 some(self.0.cpu.hl())
             }
-            0xc4 | 0xc5 | 0xcd | 0xcf | 0xd4 | 0xd5 | 0xd7 | 0xd8 | 0xd9 | 0xe5 | 0xe7 | 0xef
-            | 0xf5 | 0xf7 | 0xff | 0xdc => {
+            0xc4 | 0xc5 | 0xc7 | 0xcc | 0xcd | 0xcf | 0xd4 | 0xd5 | 0xd7 | 0xdc | 0xdf | 0xe5
+            | 0xe7 | 0xef | 0xf5 | 0xf7 | 0xff => {
                 // PUSH .. or CALL .. or RST
                 (2, [sub16(self.0.cpu.sp, 1), sub16(self.0.cpu.sp, 2)])
             }
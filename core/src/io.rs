@@ -0,0 +1,100 @@
+//! The reader and writer traits used by the save states and the other binary formats of the crate.
+//!
+//! With the `std` feature these are the ones of `std::io`. Without it, this is a minimal
+//! replacement, implemented for byte slices and `Vec<u8>`, so the emulation core can be used with
+//! only `core` and `alloc`.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, Read, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::{Error, Read, Write};
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    #[derive(Debug)]
+    pub enum Error {
+        /// The reader ended before the buffer was filled.
+        UnexpectedEof,
+        /// The writer stopped accepting bytes before the buffer was written.
+        WriteZero,
+    }
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::UnexpectedEof => write!(f, "failed to fill whole buffer"),
+                Error::WriteZero => write!(f, "failed to write whole buffer"),
+            }
+        }
+    }
+
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(Error::UnexpectedEof),
+                    n => buf = &mut buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let len = buf.len().min(self.len());
+            let (a, b) = self.split_at(len);
+            buf[..len].copy_from_slice(a);
+            *self = b;
+            Ok(len)
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            (**self).read(buf)
+        }
+    }
+
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(Error::WriteZero),
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    impl Write for &mut [u8] {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            let len = buf.len().min(self.len());
+            let (a, b) = core::mem::take(self).split_at_mut(len);
+            a.copy_from_slice(&buf[..len]);
+            *self = b;
+            Ok(len)
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            (**self).write(buf)
+        }
+    }
+}
@@ -1,11 +1,42 @@
+//! The emulation core of GameRoy: a Game Boy (DMG) emulator, with no dependency on any frontend.
+//!
+//! A `GameBoy` is created from a `Cartridge` with `GameBoy::builder`, and is run by an
+//! `interpreter::Interpreter`, one instruction at a time, while the frontend reads the screen in the
+//! `GameBoy::v_blank` callback, the audio from `GameBoy::sound`, and writes the buttons to
+//! `GameBoy::joypad`.
+//!
+//! # Features
+//!
+//! - `std` (default): save states read from and written to `std::io`, the VBM movie parser, and
+//!   the serial output printed to stderr. Without it, only `core` and `alloc` are used.
+//! - `debugger` (default): the `debugger` module.
+//! - `io_trace`: record every memory access, for comparing the interpreter to the JIT compiler.
+//! - `wave_trace`: dump the internal signals of the emulator to a VCD file.
+//! - `tracing`: instrument the CPU, PPU, timer and MBC with `tracing` spans and events.
+//!
+//! The public API follows semantic versioning, see the CHANGELOG.md of this crate.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod bot;
 pub mod consts;
+#[cfg(feature = "debugger")]
 pub mod debugger;
 pub mod diff_stack;
 pub mod disassembler;
+pub mod dmg07;
 pub mod gameboy;
+mod instrument;
 pub mod interpreter;
+pub mod io;
 pub mod parser;
 pub mod save_state;
 
 #[cfg(feature = "wave_trace")]
 mod wave_trace;
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing;
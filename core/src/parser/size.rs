@@ -1,4 +1,4 @@
-use std::num::ParseIntError;
+use core::num::ParseIntError;
 
 #[derive(Debug, PartialEq)]
 pub enum ParseSizeError {
@@ -0,0 +1,416 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+fn read_u32(file: &mut impl Read) -> Result<u32, std::io::Error> {
+    let mut value = [0; 4];
+    file.read_exact(&mut value)?;
+    Ok(u32::from_le_bytes(value))
+}
+
+fn read_u16(file: &mut impl Read) -> Result<u16, std::io::Error> {
+    let mut value = [0; 2];
+    file.read_exact(&mut value)?;
+    Ok(u16::from_le_bytes(value))
+}
+
+fn read_u8(file: &mut impl Read) -> Result<u8, std::io::Error> {
+    let mut value = 0;
+    file.read_exact(std::slice::from_mut(&mut value))?;
+    Ok(value)
+}
+
+/// The movie starts from the embedded save state, in `start_data`.
+pub const VBM_START_FROM_SAVE_STATE: u8 = 0x01;
+/// The movie starts from a reset, with the cartridge RAM embedded in `start_data`.
+pub const VBM_START_FROM_SRAM: u8 = 0x02;
+
+/// The magic that starts the `start_data` of a movie that starts from a save state that is not
+/// embedded, but referenced by the CRC32 of the save state file, stored after it.
+pub const VBM_STATE_REFERENCE_MAGIC: [u8; 4] = *b"GRSR";
+
+/// Where a movie starts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VbmStart<'a> {
+    /// From the power on, or from the end of the boot rom.
+    PowerOn,
+    /// From a reset, with the given cartridge RAM.
+    Sram(&'a [u8]),
+    /// From the given save state, in the format of `GameBoy::save_state`.
+    SaveState(&'a [u8]),
+    /// From the save state file with the given CRC32.
+    SaveStateReference(u32),
+}
+
+/// VBM is the movie capture format of Visual Boy Advance
+pub struct Vbm {
+    pub magic: u32,
+    pub version: u32,
+    pub uid: u32,
+    pub length_frames: u32,
+    pub rerecord_count: u32,
+    pub start_flags: u8,
+    pub controller_flags: u8,
+    pub type_flags: u8,
+    pub options_flags: u8,
+    pub save_type: u32,
+    pub flash_size: u32,
+    pub gb_emulator_type: u32,
+    pub rom_title: [u8; 12],
+    pub vbm_version: u8,
+    pub rom_crc: u8,
+    pub rom_or_bios_checksum: u16,
+    pub rom_game_code: u32,
+    pub name: String,
+    pub description: String,
+    pub start_data: Vec<u8>,
+    pub controller_data: Vec<u16>,
+}
+
+impl Vbm {
+    /// Where the movie starts from, given by its start flags and its start data.
+    ///
+    /// Movies recorded by VBA from a save state embed a VBA quicksave, which can't be loaded,
+    /// so only save states in the format of GameRoy are supported.
+    pub fn start(&self) -> VbmStart<'_> {
+        match self.start_flags {
+            VBM_START_FROM_SAVE_STATE => {
+                match self.start_data.strip_prefix(&VBM_STATE_REFERENCE_MAGIC) {
+                    Some(&[a, b, c, d]) => {
+                        VbmStart::SaveStateReference(u32::from_le_bytes([a, b, c, d]))
+                    }
+                    _ => VbmStart::SaveState(&self.start_data),
+                }
+            }
+            VBM_START_FROM_SRAM => VbmStart::Sram(&self.start_data),
+            _ => VbmStart::PowerOn,
+        }
+    }
+}
+
+impl std::fmt::Debug for Vbm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vbm")
+            .field("signature", &self.magic)
+            .field("verson", &self.version)
+            .field("uid", &self.uid)
+            .field("num_frames", &self.length_frames)
+            .field("rerecord_count", &self.rerecord_count)
+            .field("movie_start_flags", &self.start_flags)
+            .field("controller_flags", &self.controller_flags)
+            .field("system_flags", &self.type_flags)
+            .field("emulator_flags", &self.options_flags)
+            .field("win_save_type", &self.save_type)
+            .field("win_flash_size", &self.flash_size)
+            .field("gb_emulator_type", &self.gb_emulator_type)
+            .field("game_title", &self.rom_title)
+            .field("vbm_version", &self.vbm_version)
+            .field("rom_crc", &self.rom_crc)
+            .field("rom_checksum", &self.rom_or_bios_checksum)
+            .field("game_code", &self.rom_game_code)
+            .field("name", &self.name)
+            .field("description", &self.description)
+            // .field("start_data", &self.start_data)
+            // .field("controller_data", &self.controller_data)
+            .finish()
+    }
+}
+
+/// The latest minor version (revision) of the VBM format.
+pub const VBM_LATEST_REVISION: u8 = 1;
+
+/// The size of the header, including the name and the description of the movie.
+const VBM_HEADER_SIZE: usize = 0x100;
+
+#[derive(Debug)]
+pub enum VbmParseError {
+    /// The file doesn't start with "VBM\x1A".
+    InvalidSignature(u32),
+    /// The major version is not 1.
+    InvalidVersion(u32),
+    /// The revision is newer than `VBM_LATEST_REVISION`.
+    UnsupportedRevision(u8),
+    /// No controller is in use, or the reserved bits are set.
+    InvalidControllerFlags(u8),
+    /// More than one controller is in use.
+    UnsupportedControllerFlags(u8),
+    /// More than one system is set, or the reserved bits are set.
+    InvalidSystemFlags(u8),
+    /// The movie is for the GBA, GBC or SGB.
+    UnsupportedSystemFlags(u8),
+    /// Both start flags are set, or the reserved bits are set.
+    InvalidStartFlags(u8),
+    /// The file ends before the given part of the movie, or the controller data has less than one
+    /// input per frame.
+    Truncated(&'static str),
+    IoError(std::io::Error),
+}
+impl From<std::io::Error> for VbmParseError {
+    fn from(v: std::io::Error) -> Self {
+        Self::IoError(v)
+    }
+}
+impl std::fmt::Display for VbmParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSignature(x) => write!(f, "not a VBM file (signature is {:08x})", x),
+            Self::InvalidVersion(x) => write!(f, "unknown VBM version {}", x),
+            Self::UnsupportedRevision(x) => write!(
+                f,
+                "unsupported VBM revision {} (latest is {})",
+                x, VBM_LATEST_REVISION
+            ),
+            Self::InvalidControllerFlags(x) => write!(f, "invalid controller flags {:02x}", x),
+            Self::UnsupportedControllerFlags(x) => write!(
+                f,
+                "unsupported controller flags {:02x}: only a single controller is supported",
+                x
+            ),
+            Self::InvalidSystemFlags(x) => write!(f, "invalid system flags {:02x}", x),
+            Self::UnsupportedSystemFlags(x) => write!(
+                f,
+                "unsupported system flags {:02x}: only GB movies are supported",
+                x
+            ),
+            Self::InvalidStartFlags(x) => write!(f, "invalid start flags {:02x}", x),
+            Self::Truncated(part) => write!(f, "the movie is truncated in the {}", part),
+            Self::IoError(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl std::error::Error for VbmParseError {}
+
+#[test]
+#[ignore]
+fn parse_vbm() {
+    let mut file = std::fs::File::open("../roms/mugg-kirbysdreamland.vbm").unwrap();
+    let vbm = vbm(&mut file).unwrap();
+    println!("{:#x?}", vbm);
+    assert_eq!(vbm.name, "mugg");
+}
+
+/// Parse a movie in the VBM format, from any revision up to `VBM_LATEST_REVISION`.
+///
+/// Since revision 1 the controller data has the input of one extra frame, after the last one. Older
+/// revisions don't, so their controller data is extended with a frame with no button pressed.
+pub fn vbm(file: &mut (impl Read + Seek)) -> Result<Vbm, VbmParseError> {
+    let file_len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut header = Vec::with_capacity(VBM_HEADER_SIZE);
+    file.take(VBM_HEADER_SIZE as u64).read_to_end(&mut header)?;
+    // the signature is checked first, so a file that is not a movie is not reported as truncated.
+    if let [a, b, c, d, ..] = header[..] {
+        let magic = u32::from_le_bytes([a, b, c, d]);
+        if magic != 0x1A4D4256 {
+            return Err(VbmParseError::InvalidSignature(magic));
+        }
+    }
+    if header.len() < VBM_HEADER_SIZE {
+        return Err(VbmParseError::Truncated("header"));
+    }
+    let header = &mut &header[..];
+
+    // from https://tasvideos.org/EmulatorResources/VBA/VBM and VBA-rerecord source code.
+    // 000	4-byte signature	56 42 4D 1A "VBM\x1A"
+    let magic = read_u32(header)?;
+    // 004	4-byte little-endian unsigned int	major version number, must be "1"
+    let version = read_u32(header)?;
+    if version != 1 {
+        return Err(VbmParseError::InvalidVersion(version));
+    }
+    // 008	4-byte little-endian integer	movie "uid" - identifies the movie-savestate relationship, also used as the recording time in Unix epoch format
+    let uid = read_u32(header)?;
+    // 00C	4-byte little-endian unsigned int	number of frames
+    let length_frames = read_u32(header)?;
+    // 010	4-byte little-endian unsigned int	rerecord count
+    let rerecord_count = read_u32(header)?;
+    // 014	1-byte flags	(movie start flags)
+    // bit 0	if "1", movie starts from an embedded "quicksave" snapshot
+    // bit 1	if "1", movie starts from reset with an embedded SRAM
+    // If both bits 0 and 1 are "1", the movie file is invalid
+    // other	reserved, set to 0
+    let start_flags = read_u8(header)?;
+    match start_flags {
+        0x00 | VBM_START_FROM_SAVE_STATE | VBM_START_FROM_SRAM => {}
+        x => return Err(VbmParseError::InvalidStartFlags(x)),
+    }
+    // 015	1-byte flags	controller flags
+    // bit 0	controller 1 in use
+    // bit 1	controller 2 in use (SGB games can be 2-player multiplayer)
+    // bit 2	controller 3 in use (SGB games can be 3- or 4-player multiplayer with multitap)
+    // bit 3	controller 4 in use (SGB games can be 3- or 4-player multiplayer with multitap)
+    // other	reserved
+    let controller_flags = read_u8(header)?;
+    match controller_flags {
+        0x01 => {}
+        x if x & 0xF0 == 0 && x & 0x0E != 0 => {
+            return Err(VbmParseError::UnsupportedControllerFlags(x))
+        }
+        x => return Err(VbmParseError::InvalidControllerFlags(x)),
+    }
+    // 016	1-byte flags	system flags (game always runs at 60 frames/sec)
+    // bit 0	if "1", movie is for the GBA system
+    // bit 1	if "1", movie is for the GBC system
+    // bit 2	if "1", movie is for the SGB system
+    // If all 3 of these bits are "0", it is for regular GB.
+    // At most one of bits 0, 1, 2 can be "1"
+    // other	reserved, set to 0
+    let type_flags = read_u8(header)?;
+    match type_flags {
+        0x00 => {}
+        0x01 | 0x02 | 0x04 => return Err(VbmParseError::UnsupportedSystemFlags(type_flags)),
+        x => return Err(VbmParseError::InvalidSystemFlags(x)),
+    }
+    // 017	1-byte flags	(values of some boolean emulator options)
+    // bit 0: useBiosFile	if "1" and the movie is of a GBA game, the movie was made using a GBA BIOS file.
+    // bit 1: skipBiosFile	if "0" and the movie was made with a GBA BIOS file, the BIOS intro is included in the movie.
+    // bit 2: rtcEnable	if "1", the emulator "real time clock" feature was enabled.
+    // bit 3: gbInputHack	if "1" and the movie is of a GB, GBC, or SGB game, the movie was made with the Null Input Kludge on, otherwise it was not.
+    // bit 4: lagReduction	if "0" and the movie is of a GBA game, the movie was made using the old excessively laggy GBA timing.
+    // bit 5: gbcHdma5Fix	if "0" and the movie is of a GBC game, the movie was made using the old buggy HDMA5 timing.
+    // bit 6: echoRAMFix	if "1" and the movie is of a GB, GBC, or SGB game, the movie was made with Echo RAM Fix on, otherwise it was made with Echo RAM Fix off.
+    // bit 7: sramInitFix	if "1" and the movie is of a GBA game, the movie was made with SRAM Init Fix on, otherwise it was not.
+    let options_flags = read_u8(header)?;
+    // 018	4-byte little-endian unsigned int	theApp.winSaveType (value of that emulator option)
+    let save_type = read_u32(header)?;
+    // 01C	4-byte little-endian unsigned int	theApp.winFlashSize (value of that emulator option)
+    let flash_size = read_u32(header)?;
+    // 020	4-byte little-endian unsigned int	gbEmulatorType (value of that emulator option)
+    let gb_emulator_type = read_u32(header)?;
+    // 024	12-byte character array	the internal game title of the ROM used while recording, not necessarily null-terminated (ASCII?)
+    let mut rom_title = [0; 12];
+    header.read_exact(&mut rom_title)?;
+    // 030	1-byte unsigned char	minor version/revision number of current VBM version, the latest is "1"
+    let vbm_version = read_u8(header)?;
+    if vbm_version > VBM_LATEST_REVISION {
+        return Err(VbmParseError::UnsupportedRevision(vbm_version));
+    }
+    // 031	1-byte unsigned char	the internal CRC of the ROM used while recording
+    let rom_crc = read_u8(header)?;
+    // 032	2-byte little-endian unsigned short	the internal Checksum of the ROM used while recording, or a calculated CRC16 of the BIOS if GBA
+    let rom_or_bios_checksum = read_u16(header)?;
+    // 034	4-byte little-endian unsigned int	the Game Code of the ROM used while recording, or the Unit Code if not GBA
+    let rom_game_code = read_u32(header)?;
+    // 038	4-byte little-endian unsigned int	offset to the savestate or SRAM inside file, set to 0 if unused
+    let offset_to_savestate = read_u32(header)?;
+    // 03C	4-byte little-endian unsigned int	offset to the controller data inside file
+    let offset_to_controller_data = read_u32(header)?;
+
+    // 040	64-byte character array	the author of the movie, null-terminated unless it fills the array
+    // 080	128-byte character array	the description of the movie, null-terminated unless it fills the array
+    let mut read_str = |len: usize| -> Result<String, VbmParseError> {
+        let mut buffer = vec![0; len];
+        header.read_exact(&mut buffer)?;
+        let null_terminate = buffer.iter().position(|&x| x == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&buffer[0..null_terminate]).into_owned())
+    };
+    let name = read_str(64)?;
+    let description = read_str(128)?;
+
+    let start_data = if start_flags == 0x00 {
+        Vec::new()
+    } else {
+        if offset_to_savestate as u64 > file_len {
+            return Err(VbmParseError::Truncated("start data"));
+        }
+        // the start data goes until the controller data, or until the end of the file.
+        file.seek(SeekFrom::Start(offset_to_savestate as u64))?;
+        let mut data = Vec::new();
+        if offset_to_controller_data > offset_to_savestate {
+            let len = offset_to_controller_data - offset_to_savestate;
+            file.take(len as u64).read_to_end(&mut data)?;
+        } else {
+            file.read_to_end(&mut data)?;
+        }
+        data
+    };
+    let mut controller_data: Vec<u16> = {
+        if offset_to_controller_data as u64 > file_len {
+            return Err(VbmParseError::Truncated("controller data"));
+        }
+        file.seek(SeekFrom::Start(offset_to_controller_data as u64))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        data.chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect()
+    };
+    if controller_data.len() < length_frames as usize {
+        return Err(VbmParseError::Truncated("controller data"));
+    }
+    if controller_data.len() == length_frames as usize {
+        controller_data.push(0);
+    }
+
+    // TODO: implement the rest
+    Ok(Vbm {
+        magic,
+        version,
+        uid,
+        length_frames,
+        rerecord_count,
+        start_flags,
+        controller_flags,
+        type_flags,
+        options_flags,
+        save_type,
+        flash_size,
+        gb_emulator_type,
+        rom_title,
+        vbm_version,
+        rom_crc,
+        rom_or_bios_checksum,
+        rom_game_code,
+        name,
+        description,
+        start_data,
+        controller_data,
+    })
+}
+
+/// Write a movie in the VBM format, with the start data placed before the controller data.
+pub fn write_vbm(vbm: &Vbm, file: &mut impl Write) -> Result<(), std::io::Error> {
+    // the header has 0x40 bytes, followed by the name and the description.
+    let offset_to_savestate = 0x100u32;
+    let offset_to_controller_data = offset_to_savestate + vbm.start_data.len() as u32;
+
+    file.write_all(&vbm.magic.to_le_bytes())?;
+    file.write_all(&vbm.version.to_le_bytes())?;
+    file.write_all(&vbm.uid.to_le_bytes())?;
+    file.write_all(&vbm.length_frames.to_le_bytes())?;
+    file.write_all(&vbm.rerecord_count.to_le_bytes())?;
+    file.write_all(&[
+        vbm.start_flags,
+        vbm.controller_flags,
+        vbm.type_flags,
+        vbm.options_flags,
+    ])?;
+    file.write_all(&vbm.save_type.to_le_bytes())?;
+    file.write_all(&vbm.flash_size.to_le_bytes())?;
+    file.write_all(&vbm.gb_emulator_type.to_le_bytes())?;
+    file.write_all(&vbm.rom_title)?;
+    file.write_all(&[vbm.vbm_version, vbm.rom_crc])?;
+    file.write_all(&vbm.rom_or_bios_checksum.to_le_bytes())?;
+    file.write_all(&vbm.rom_game_code.to_le_bytes())?;
+    if vbm.start_data.is_empty() {
+        file.write_all(&0u32.to_le_bytes())?;
+    } else {
+        file.write_all(&offset_to_savestate.to_le_bytes())?;
+    }
+    file.write_all(&offset_to_controller_data.to_le_bytes())?;
+
+    let mut write_str = |text: &str, len: usize| {
+        // keep at least one null terminator.
+        let mut buffer = vec![0; len];
+        let bytes = &text.as_bytes()[..text.len().min(len - 1)];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        file.write_all(&buffer)
+    };
+    write_str(&vbm.name, 64)?;
+    write_str(&vbm.description, 128)?;
+
+    file.write_all(&vbm.start_data)?;
+    for x in &vbm.controller_data {
+        file.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
-use std::cell::Cell;
-use std::io::{Read, Write};
+use alloc::{string::String, vec::Vec};
+use core::cell::Cell;
+
+use crate::io::{Read, Write};
 
 #[derive(Debug)]
 pub enum LoadStateError {
@@ -10,12 +12,13 @@ pub enum LoadStateError {
     InvalidBoolBitArray(u8, u8),
     SoundControllerDesync(u64, u64),
     ConstMismatch(String, String),
-    IoError(std::io::Error),
+    IoError(crate::io::Error),
     InvalidMagicConst([u8; 4]),
     UnknownVersion(u32),
+    InvalidRamFill(u8),
 }
-impl From<std::io::Error> for LoadStateError {
-    fn from(error: std::io::Error) -> Self {
+impl From<crate::io::Error> for LoadStateError {
+    fn from(error: crate::io::Error) -> Self {
         Self::IoError(error)
     }
 }
@@ -56,7 +59,7 @@ impl Default for SaveStateContext {
 pub struct SaveStateHeader;
 impl SaveStateHeader {
     /// The current version of the save state format
-    const SAVE_STATE_VERSION: u32 = 3;
+    const SAVE_STATE_VERSION: u32 = 5;
 
     /// "GameRoy Save State" magic contant.
     const MAGIC_CONST: [u8; 4] = *b"GRST";
@@ -66,7 +69,7 @@ impl SaveState for SaveStateHeader {
         &self,
         ctx: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         Self::MAGIC_CONST.save_state(ctx, data)?;
         Self::SAVE_STATE_VERSION.save_state(ctx, data)?;
         if let Some(time) = ctx.time {
@@ -115,7 +118,7 @@ pub trait SaveState {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error>;
+    ) -> Result<(), crate::io::Error>;
     fn load_state(
         &mut self,
         _: &mut SaveStateContext,
@@ -128,7 +131,7 @@ impl SaveState for u8 {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         data.write_all(&[*self])?;
         Ok(())
     }
@@ -138,7 +141,7 @@ impl SaveState for u8 {
         _: &mut SaveStateContext,
         data: &mut impl Read,
     ) -> Result<(), LoadStateError> {
-        data.read_exact(std::slice::from_mut(self))?;
+        data.read_exact(core::slice::from_mut(self))?;
         Ok(())
     }
 }
@@ -148,13 +151,13 @@ macro_rules! save_state {
     // end
     (@accum ($n:ident, $s:ident, $ctx:ident, $d:ident,) -> ($($save:tt)*) -> ($($load:tt)*)) => {
         impl SaveState for $n {
-            fn save_state(&$s, $ctx: &mut $crate::save_state::SaveStateContext, $d: &mut impl std::io::Write) -> Result<(), std::io::Error> {
+            fn save_state(&$s, $ctx: &mut $crate::save_state::SaveStateContext, $d: &mut impl $crate::io::Write) -> Result<(), $crate::io::Error> {
                 $($save)*
                 let _ = $d;
                 Ok(())
             }
 
-            fn load_state(&mut $s, $ctx: &mut $crate::save_state::SaveStateContext, $d: &mut impl std::io::Read) -> Result<(), LoadStateError> {
+            fn load_state(&mut $s, $ctx: &mut $crate::save_state::SaveStateContext, $d: &mut impl $crate::io::Read) -> Result<(), LoadStateError> {
                 $($load)*
                 let _ = $d;
                 Ok(())
@@ -262,7 +265,7 @@ impl SaveState for u16 {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         data.write_all(&self.to_be_bytes())?;
         Ok(())
     }
@@ -284,7 +287,7 @@ impl SaveState for u32 {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         data.write_all(&self.to_be_bytes())?;
         Ok(())
     }
@@ -306,7 +309,7 @@ impl SaveState for u64 {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         data.write_all(&self.to_be_bytes())?;
         Ok(())
     }
@@ -328,7 +331,7 @@ impl<T: SaveState, const N: usize> SaveState for [T; N] {
         &self,
         ctx: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         for x in self {
             x.save_state(ctx, data)?;
         }
@@ -352,7 +355,7 @@ impl SaveState for Vec<u8> {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         data.write_all(&(self.len() as u32).to_be_bytes())?;
         data.write_all(self)?;
         Ok(())
@@ -377,7 +380,7 @@ impl<const N: usize> SaveState for [&bool; N] {
         &self,
         _: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         if N <= 8 {
             let mut flags = 0;
             for &&b in self {
@@ -404,7 +407,7 @@ impl<const N: usize> SaveState for [&mut bool; N] {
         &self,
         _: &mut SaveStateContext,
         _data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         unimplemented!()
     }
 
@@ -415,7 +418,7 @@ impl<const N: usize> SaveState for [&mut bool; N] {
     ) -> Result<(), LoadStateError> {
         if N <= 8 {
             let mut flags = 0;
-            data.read_exact(std::slice::from_mut(&mut flags))?;
+            data.read_exact(core::slice::from_mut(&mut flags))?;
             for b in self.iter_mut().rev() {
                 **b = flags & 0x1 != 0;
                 flags >>= 1;
@@ -435,7 +438,7 @@ impl<T: SaveState + Default + Copy> SaveState for Cell<T> {
         &self,
         ctx: &mut SaveStateContext,
         data: &mut impl Write,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), crate::io::Error> {
         self.get().save_state(ctx, data)?;
         Ok(())
     }
@@ -0,0 +1,89 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// A rom that waits for VBlank, when the OAM is not blocked by the PPU, start a OAM DMA from
+/// 0xC000, read the first byte of the OAM right after it, and write it to 0xC100.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x55,       // 0x150: ld a, 0x55
+        0xEA, 0x00, 0xC0, // 0x152: ld (0xC000), a
+        0xF0, 0x44,       // 0x155: ldh a, (0x44)   ; LY
+        0xFE, 0x90,       // 0x157: cp 0x90
+        0x20, 0xFA,       // 0x159: jr nz, 0x155
+        0x3E, 0xC0,       // 0x15B: ld a, 0xC0
+        0xE0, 0x46,       // 0x15D: ldh (0x46), a   ; DMA
+        0xFA, 0x00, 0xFE, // 0x15F: ld a, (0xFE00)
+        0xEA, 0x00, 0xC1, // 0x162: ld (0xC100), a
+        0x18, 0xFE,       // 0x165: jr 0x165
+    ];
+    rom_with_program(&program)
+}
+
+/// Run the test rom with the given accuracy, and returns the byte read from the OAM.
+fn run(accuracy: AccuracyConfig) -> u8 {
+    let cartridge = Cartridge::new(test_rom()).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, accuracy);
+
+    let target = gb.clock_count + 200_000;
+    while gb.clock_count < target {
+        Interpreter(&mut gb).interpret_op();
+    }
+
+    gb.read(0xC100)
+}
+
+#[test]
+fn dma_blocks_oam() {
+    assert_eq!(run(AccuracyConfig::ACCURATE), 0xFF);
+    assert_eq!(run(AccuracyConfig::BALANCED), 0xFF);
+}
+
+#[test]
+fn instant_dma() {
+    assert_eq!(run(AccuracyConfig::FAST), 0x55);
+}
+
+/// A rom that waits for VBlank, clears IF, writes 0 to STAT, and write IF to 0xC100.
+fn stat_write_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF3,             // 0x150: di
+        0xF0, 0x44,       // 0x151: ldh a, (0x44)   ; LY
+        0xFE, 0x90,       // 0x153: cp 0x90
+        0x20, 0xFA,       // 0x155: jr nz, 0x151
+        0xAF,             // 0x157: xor a
+        0xE0, 0x0F,       // 0x158: ldh (0x0F), a   ; IF
+        0xE0, 0x41,       // 0x15A: ldh (0x41), a   ; STAT
+        0xF0, 0x0F,       // 0x15C: ldh a, (0x0F)
+        0xEA, 0x00, 0xC1, // 0x15E: ld (0xC100), a
+        0x18, 0xFE,       // 0x161: jr 0x161
+    ];
+    rom_with_program(&program)
+}
+
+/// Run the STAT write rom with the given accuracy, and returns if a STAT interrupt was requested.
+fn stat_write_interrupt(accuracy: AccuracyConfig) -> bool {
+    let cartridge = Cartridge::new(stat_write_rom()).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, accuracy);
+
+    let target = gb.clock_count + 200_000;
+    while gb.clock_count < target {
+        Interpreter(&mut gb).interpret_op();
+    }
+
+    gb.read(0xC100) & 0x02 != 0
+}
+
+#[test]
+fn stat_write_bug() {
+    // writing to STAT in VBlank triggers a STAT interrupt, even with all sources disabled.
+    assert!(stat_write_interrupt(AccuracyConfig::ACCURATE));
+    assert!(stat_write_interrupt(AccuracyConfig::BALANCED));
+    assert!(!stat_write_interrupt(AccuracyConfig::FAST));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::{
+    consts::FRAME_CYCLES,
+    debugger::{Debugger, RunResult},
+};
+
+/// A rom that waits for the PPU mode 3, and then writes to VRAM, which is ignored.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF0, 0x41,       // 0x150: ldh a, (0x41)
+        0xE6, 0x03,       // 0x152: and 0x03
+        0xFE, 0x03,       // 0x154: cp 0x03
+        0x20, 0xF8,       // 0x156: jr nz, 0x150
+        0x3E, 0x42,       // 0x158: ld a, 0x42
+        0xEA, 0x10, 0x80, // 0x15A: ld (0x8010), a
+        0x18, 0xFE,       // 0x15D: jr 0x15D
+    ];
+    rom_with_program(&program)
+}
+
+#[test]
+fn log_blocked_write() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&gb, &["trace", "blocked"])
+        .unwrap();
+
+    let target = gb.clock_count + 2 * FRAME_CYCLES;
+    let result = debugger.run_until(&mut gb, target);
+    assert_eq!(result, RunResult::TimeOut);
+    assert_eq!(
+        debugger.take_trace_log(),
+        ["015a: write 42 to 8010 ignored in PPU mode 3"]
+    );
+    assert_ne!(gb.ppu.borrow().vram[0x10], 0x42);
+
+    debugger
+        .execute_command(&gb, &["untrace", "blocked"])
+        .unwrap();
+}
+
+#[test]
+fn break_on_blocked_write() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&gb, &["break", "blocked"])
+        .unwrap();
+
+    let target = gb.clock_count + 2 * FRAME_CYCLES;
+    let result = debugger.run_until(&mut gb, target);
+    assert_eq!(result, RunResult::ReachBreakpoint);
+    // it breaks after the write.
+    assert_eq!(gb.cpu.pc, 0x15D);
+    assert!(debugger.take_trace_log().is_empty());
+
+    let mut gb = game_boy(test_rom());
+    debugger
+        .execute_command(&gb, &["unbreak", "blocked"])
+        .unwrap();
+    let target = gb.clock_count + 2 * FRAME_CYCLES;
+    assert_eq!(debugger.run_until(&mut gb, target), RunResult::TimeOut);
+}
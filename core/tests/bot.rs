@@ -0,0 +1,99 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    bot::{best, combinations, evaluate, evaluate_all, run_inputs},
+    gameboy::GameBoy,
+};
+
+/// The joypad state with only the A button pressed.
+const A: u8 = !0x10;
+/// The joypad state with no button pressed.
+const NONE: u8 = 0xFF;
+
+/// A rom that counts how many times the A button was pressed in 0xC000.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xAF,             // 0x150: xor a
+        0xEA, 0x00, 0xC0, // 0x151: ld (0xC000), a
+        0x3E, 0x10,       // 0x154: ld a, 0x10
+        0xE0, 0x00,       // 0x156: ldh (0x00), a  ; select the action buttons
+        // released:
+        0xF0, 0x00,       // 0x158: ldh a, (0x00)
+        0xE6, 0x01,       // 0x15A: and 0x01
+        0x20, 0xFA,       // 0x15C: jr nz, released
+        0x21, 0x00, 0xC0, // 0x15E: ld hl, 0xC000
+        0x34,             // 0x161: inc (hl)
+        // pressed:
+        0xF0, 0x00,       // 0x162: ldh a, (0x00)
+        0xE6, 0x01,       // 0x164: and 0x01
+        0x28, 0xFA,       // 0x166: jr z, pressed
+        0x18, 0xEE,       // 0x168: jr released
+    ];
+    rom_with_program(&program)
+}
+
+fn game_boy() -> GameBoy {
+    common::game_boy(test_rom())
+}
+
+/// A save state after the program started.
+fn start_state() -> Vec<u8> {
+    let mut gb = game_boy();
+    run_inputs(&mut gb, &[NONE]);
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+    state
+}
+
+fn presses(gb: &GameBoy) -> u8 {
+    gb.read(0xC000)
+}
+
+#[test]
+fn evaluate_from_state() {
+    let state = start_state();
+    let mut gb = game_boy();
+    assert_eq!(
+        evaluate(&mut gb, &state, &[A, A, NONE], presses).unwrap(),
+        1
+    );
+    assert_eq!(
+        evaluate(&mut gb, &state, &[A, NONE, A], presses).unwrap(),
+        2
+    );
+    // the state is reloaded, so the previous run does not count.
+    assert_eq!(evaluate(&mut gb, &state, &[NONE], presses).unwrap(), 0);
+}
+
+#[test]
+fn all_combinations() {
+    assert_eq!(
+        combinations(&[1, 2], 2),
+        [vec![1, 1], vec![1, 2], vec![2, 1], vec![2, 2]]
+    );
+    assert_eq!(combinations(&[A, NONE], 4).len(), 16);
+}
+
+#[test]
+fn parallel_search() {
+    let state = start_state();
+    let candidates = combinations(&[A, NONE], 4);
+
+    let scores = evaluate_all(game_boy, &state, &candidates, 4, presses).unwrap();
+    let mut gb = game_boy();
+    for (inputs, &score) in candidates.iter().zip(&scores) {
+        assert_eq!(evaluate(&mut gb, &state, inputs, presses).unwrap(), score);
+    }
+
+    let (index, score) = best(game_boy, &state, &candidates, 4, presses)
+        .unwrap()
+        .unwrap();
+    // the first of the candidates with 2 presses.
+    assert_eq!(candidates[index], [A, A, NONE, A]);
+    assert_eq!(score, 2);
+
+    let empty: &[Vec<u8>] = &[];
+    assert!(best(game_boy, &state, empty, 4, presses).unwrap().is_none());
+}
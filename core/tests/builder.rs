@@ -0,0 +1,69 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use gameroy::{
+    consts::FRAME_CYCLES,
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, ram_fill::RamFill, GameBoy},
+    interpreter::Interpreter,
+};
+
+#[test]
+fn builder_configures_before_reset() {
+    let frames = Arc::new(AtomicU32::new(0));
+    let mut gb = GameBoy::builder(Cartridge::halt_filled())
+        .accuracy(AccuracyConfig::FAST)
+        .ram_fill(RamFill::Zeros)
+        .predict_interrupt(false)
+        .halt_optimization(false)
+        .serial_sink(None)
+        .v_blank(Box::new({
+            let frames = frames.clone();
+            move |_| {
+                frames.fetch_add(1, Ordering::Relaxed);
+            }
+        }))
+        .build();
+
+    assert_eq!(gb.accuracy, AccuracyConfig::FAST);
+    assert!(!gb.predict_interrupt);
+    assert!(!gb.halt_optimization);
+    assert!(gb.serial.borrow().serial_transfer_callback.is_none());
+    // the RAM was filled with the configured pattern when the GameBoy was reset.
+    assert!(gb.wram.iter().all(|&x| x == 0x00));
+
+    let target = gb.clock_count + 3 * FRAME_CYCLES;
+    while gb.clock_count < target {
+        Interpreter(&mut gb).interpret_op();
+    }
+    assert!(frames.load(Ordering::Relaxed) >= 2);
+}
+
+#[test]
+fn new_is_the_default_builder() {
+    let a = GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default());
+    let b = GameBoy::builder(Cartridge::halt_filled()).build();
+    let state = |gb: &GameBoy| {
+        let mut state = Vec::new();
+        gb.save_state(None, &mut state).unwrap();
+        state
+    };
+    assert_eq!(state(&a), state(&b));
+    assert!(b.predict_interrupt && b.halt_optimization);
+}
+
+#[test]
+fn load_state_restores_accuracy() {
+    let a = GameBoy::builder(Cartridge::halt_filled())
+        .accuracy(AccuracyConfig::FAST)
+        .build();
+    let mut state = Vec::new();
+    a.save_state(None, &mut state).unwrap();
+
+    let mut b = GameBoy::builder(Cartridge::halt_filled())
+        .accuracy(AccuracyConfig::ACCURATE)
+        .build();
+    b.load_state(&mut &state[..]).unwrap();
+    assert_eq!(b.accuracy, AccuracyConfig::FAST);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::{fix_checksums, write_program};
+use gameroy::{
+    debugger::Debugger,
+    disassembler::Address,
+    gameboy::{cartridge::Cartridge, GameBoy},
+};
+
+/// A rom where the entry point calls two functions, one of them calling the other, and the VBlank
+/// handler calls a RST. The other interrupt handlers only return.
+fn game_boy() -> GameBoy {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    rom[0x40..0x42].copy_from_slice(&[0xEF, 0xD9]); // 0x40: rst 0x28; reti
+    rom[0x28] = 0xC9; // 0x28: ret
+    for vector in [0x48, 0x50, 0x58, 0x60] {
+        rom[vector] = 0xD9; // reti
+    }
+
+    #[rustfmt::skip]
+    let program = [
+        0xCD, 0x00, 0x02, // 0x150: call 0x200
+        0xCD, 0x00, 0x03, // 0x153: call 0x300
+        0x18, 0xFE,       // 0x156: jr 0x156
+    ];
+    write_program(&mut rom, &program);
+    rom[0x200..0x204].copy_from_slice(&[0xC4, 0x00, 0x03, 0xC9]); // 0x200: call nz, 0x300; ret
+    rom[0x300] = 0xC9; // 0x300: ret
+
+    fix_checksums(&mut rom);
+
+    let gb = common::game_boy(rom);
+    gb.trace.borrow_mut().trace_entry_points(&gb);
+    gb
+}
+
+#[test]
+fn calls_are_recorded() {
+    let gb = game_boy();
+    let trace = gb.trace.borrow();
+    let a = |x| Address::new(0, x);
+    let calls: Vec<_> = trace.calls.iter().map(|(&x, &y)| (x, y)).collect();
+    assert_eq!(
+        calls,
+        [
+            (a(0x0040), a(0x0028)),
+            (a(0x0150), a(0x0200)),
+            (a(0x0153), a(0x0300)),
+            (a(0x0200), a(0x0300)),
+        ]
+    );
+}
+
+#[test]
+fn export_call_graph() {
+    let gb = game_boy();
+    let mut dot = String::new();
+    gb.trace.borrow().fmt_call_graph(&mut dot).unwrap();
+
+    assert!(dot.starts_with("digraph callgraph {\n"));
+    assert!(dot.contains("\"00_0100\" [label=\"entry point\"];"));
+    assert!(dot.contains("\"00_0040\" [label=\"INT_VBLANK\"];"));
+    assert!(dot.contains("\"00_0200\" [label=\"L00_0200\"];"));
+    // the calls at 0x150 and 0x153 are part of the entry point function.
+    assert!(dot.contains("\"00_0100\" -> \"00_0200\";"));
+    assert!(dot.contains("\"00_0100\" -> \"00_0300\";"));
+    assert!(dot.contains("\"00_0200\" -> \"00_0300\";"));
+    assert!(dot.contains("\"00_0040\" -> \"00_0028\";"));
+    assert_eq!(dot.matches("->").count(), 4);
+
+    let path = std::env::temp_dir().join("gameroy_call_graph_test.dot");
+    let path = path.to_str().unwrap();
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["callgraph", path]).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), dot);
+    assert!(debugger.execute_command(&gb, &["callgraph"]).is_err());
+}
@@ -1,11 +1,15 @@
 use gameroy::{
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, boot_rom::BootRom, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 
 // const BOOT_ROM: Option<[u8; 256]> = Some(*include_bytes!("../../boot/dmg_boot.bin"));
 const BOOT_ROM: Option<[u8; 256]> = None;
 
+fn boot_rom() -> Option<BootRom> {
+    BOOT_ROM.map(BootRom::from)
+}
+
 #[test]
 #[ignore]
 fn test_boot_state() {
@@ -16,8 +20,8 @@ fn test_boot_state() {
 
     let cartridge = Cartridge::halt_filled();
 
-    let mut a = GameBoy::new(BOOT_ROM, cartridge.clone());
-    let b = GameBoy::new(None, cartridge.clone());
+    let mut a = GameBoy::new(boot_rom(), cartridge.clone(), AccuracyConfig::default());
+    let b = GameBoy::new(None, cartridge.clone(), AccuracyConfig::default());
 
     while a.cpu.pc < 0x100 {
         Interpreter(&mut a).interpret_op();
@@ -108,39 +108,41 @@ fn test_interrupt_prediction(rom: &str, timeout: u64) -> bool {
 
     let vblank = Arc::new(Mutex::new(VBlank::default()));
 
-    let mut game_boy_a = GameBoy::new(None, cartridge.clone());
-    game_boy_a.predict_interrupt = true;
-    game_boy_a.v_blank = Some(Box::new({
-        let vblank = vblank.clone();
-        move |gb| {
-            let mut vblank = vblank.lock().unwrap();
-            vblank.screen_a = Some(gb.ppu.borrow().screen.packed());
-            vblank.clock_count = Some(gb.clock_count);
-            if gb.clock_count == 81230904 {
-                println!("{:?}", *gb.ppu.borrow());
-            }
-        }
-    }));
-
-    let mut game_boy_b = GameBoy::new(None, cartridge);
-    game_boy_b.predict_interrupt = false;
-    game_boy_b.v_blank = Some(Box::new({
-        let vblank = vblank.clone();
-        move |gb| {
-            let mut vblank = vblank.lock().unwrap();
-
-            // The vblank shoud have been set by `gameboy_a`.
-            match vblank.clock_count {
-                Some(clock) if clock == gb.clock_count => {}
-                _ => panic!("Clock count don't match?!"),
+    let mut game_boy_a = GameBoy::builder(cartridge.clone())
+        .predict_interrupt(true)
+        .v_blank(Box::new({
+            let vblank = vblank.clone();
+            move |gb| {
+                let mut vblank = vblank.lock().unwrap();
+                vblank.screen_a = Some(gb.ppu.borrow().screen.packed());
+                vblank.clock_count = Some(gb.clock_count);
+                if gb.clock_count == 81230904 {
+                    println!("{:?}", *gb.ppu.borrow());
+                }
             }
+        }))
+        .build();
+
+    let mut game_boy_b = GameBoy::builder(cartridge)
+        .predict_interrupt(false)
+        .v_blank(Box::new({
+            let vblank = vblank.clone();
+            move |gb| {
+                let mut vblank = vblank.lock().unwrap();
+
+                // The vblank shoud have been set by `gameboy_a`.
+                match vblank.clock_count {
+                    Some(clock) if clock == gb.clock_count => {}
+                    _ => panic!("Clock count don't match?!"),
+                }
 
-            vblank.screen_b = Some(gb.ppu.borrow().screen.packed());
-            if gb.clock_count == 81230904 {
-                println!("{:?}", *gb.ppu.borrow());
+                vblank.screen_b = Some(gb.ppu.borrow().screen.packed());
+                if gb.clock_count == 81230904 {
+                    println!("{:?}", *gb.ppu.borrow());
+                }
             }
-        }
-    }));
+        }))
+        .build();
 
     while game_boy_b.clock_count < timeout {
         // print!("\u{001b}[37m");
@@ -0,0 +1,112 @@
+use gameroy::{
+    consts::{FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH},
+    gameboy::{
+        accuracy::AccuracyConfig,
+        cartridge::Cartridge,
+        colorization::{colorize, ColorSource, ColorizationProfile, SourcePalettes},
+        GameBoy,
+    },
+    interpreter::Interpreter,
+};
+
+/// Create a GameBoy that loops forever, with a background that is all tile 0, a window from
+/// (80, 100) to the bottom right corner, also of tile 0, sprite 3 with OBP1 at x = 16 and sprite 5
+/// with OBP0 at x = 40, both of the opaque tile 2, at line 8.
+fn setup(accuracy: AccuracyConfig) -> GameBoy {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // 0x100: jr 0x100
+    let cartridge = Cartridge::new(rom).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, accuracy);
+
+    let ppu = gb.ppu.get_mut();
+    ppu.vram[..0x10].fill(0x00);
+    ppu.vram[0x20..0x30].fill(0xFF);
+    ppu.vram[0x1800..0x1C00].fill(0);
+
+    ppu.oam.fill(0);
+    ppu.oam[3 * 4..][..4].copy_from_slice(&[24, 24, 2, 0x10]);
+    ppu.oam[5 * 4..][..4].copy_from_slice(&[24, 48, 2, 0x00]);
+
+    ppu.lcdc = 0xB3;
+    ppu.wy = 100;
+    ppu.wx = 87;
+    ppu.bgp = 0xE4;
+    ppu.obp0 = 0xE4;
+    ppu.obp1 = 0xE4;
+    gb
+}
+
+fn run_frames(gb: &mut GameBoy) {
+    let target = gb.clock_count + 2 * FRAME_CYCLES;
+    while gb.clock_count < target {
+        Interpreter(gb).interpret_op();
+    }
+    gb.update_all();
+}
+
+fn pixel(gb: &GameBoy, x: usize, y: usize) -> u8 {
+    gb.ppu.borrow().screen.row_iter().nth(y).unwrap()[x]
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut gb = setup(AccuracyConfig::default());
+    run_frames(&mut gb);
+    let ppu = gb.ppu.borrow();
+    assert!(ppu.screen.row_iter().flatten().all(|&x| x < 4));
+}
+
+#[test]
+fn pixel_sources() {
+    for accuracy in [AccuracyConfig::ACCURATE, AccuracyConfig::BALANCED] {
+        let mut gb = setup(accuracy);
+        gb.ppu.get_mut().set_colorization_enabled(true);
+        run_frames(&mut gb);
+
+        assert_eq!(pixel(&gb, 0, 0), ColorSource::Background.pixel(0));
+        assert_eq!(pixel(&gb, 17, 9), ColorSource::Obj1.pixel(3));
+        assert_eq!(pixel(&gb, 41, 9), ColorSource::Obj0.pixel(3));
+        assert_eq!(pixel(&gb, 79, 120), ColorSource::Background.pixel(0));
+        assert_eq!(pixel(&gb, 80, 120), ColorSource::Window.pixel(0));
+        assert_eq!(pixel(&gb, 159, 143), ColorSource::Window.pixel(0));
+        assert_eq!(ColorSource::of_pixel(pixel(&gb, 17, 9)), ColorSource::Obj1);
+    }
+}
+
+#[test]
+fn kept_after_reset() {
+    let mut gb = setup(AccuracyConfig::default());
+    gb.ppu.get_mut().set_colorization_enabled(true);
+    gb.reset();
+    assert!(gb.ppu.borrow().colorization_enabled());
+}
+
+#[test]
+fn colorize_screen() {
+    let mut gb = setup(AccuracyConfig::default());
+    gb.ppu.get_mut().set_colorization_enabled(true);
+    run_frames(&mut gb);
+
+    let white = [[255; 3]; 4];
+    let red = [[255, 0, 0]; 4];
+    let blue = [[0, 0, 255]; 4];
+    let profile = ColorizationProfile {
+        palettes: SourcePalettes {
+            obj1: red,
+            ..SourcePalettes::uniform(white)
+        },
+        lines: vec![(100, 143, SourcePalettes::uniform(blue))],
+    };
+
+    let mut rgba = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+    colorize(
+        &gb.ppu.borrow().screen,
+        |ly| profile.line_palette(ly),
+        &mut rgba,
+    );
+    let color = |x: usize, y: usize| rgba[(y * SCREEN_WIDTH + x) * 4..][..4].to_vec();
+    assert_eq!(color(0, 0), [255, 255, 255, 255]);
+    assert_eq!(color(17, 9), [255, 0, 0, 255]);
+    assert_eq!(color(41, 9), [255, 255, 255, 255]);
+    assert_eq!(color(10, 120), [0, 0, 255, 255]);
+}
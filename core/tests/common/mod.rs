@@ -0,0 +1,42 @@
+//! The test rom shared by the integration tests: a halt filled rom with a valid header, whose
+//! entry point jumps to a program at 0x150.
+
+#![allow(dead_code)]
+
+use gameroy::gameboy::{
+    accuracy::AccuracyConfig,
+    cartridge::{Cartridge, CartridgeHeader, RomValidation},
+    GameBoy,
+};
+
+/// A 32 KiB rom that runs `program` at 0x150.
+pub fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+    write_program(&mut rom, program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+/// Write `program` at 0x150 of `rom`, and a jump to it at the entry point.
+pub fn write_program(rom: &mut [u8], program: &[u8]) {
+    #[rustfmt::skip]
+    let entry = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&entry);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+}
+
+/// Recompute the header and global checksums, after the header or the program of `rom` changed.
+pub fn fix_checksums(rom: &mut [u8]) {
+    rom[0x14D] = CartridgeHeader::compute_check_sum(rom);
+    let checksum = RomValidation::compute_global_checksum(rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// A Game Boy running `rom`, with the default accuracy.
+pub fn game_boy(rom: Vec<u8>) -> GameBoy {
+    let cartridge = Cartridge::new(rom).unwrap();
+    GameBoy::new(None, cartridge, AccuracyConfig::default())
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::debugger::{parse_debug_message, Debugger, RunResult, TraceRegister, TraceSegment};
+
+/// A rom that prints a debug message, and then reaches a software breakpoint.
+fn test_rom() -> Vec<u8> {
+    let message = b"a=%A% %x%";
+    #[rustfmt::skip]
+    let print = [
+        0x3E, 0x42,                        // 0x150: ld a, 0x42
+        0x52,                              // 0x152: ld d,d
+        0x18, 4 + message.len() as u8,     // 0x153: jr over the message
+        0x64, 0x64, 0x00, 0x00,            // 0x155: signature
+    ];
+    #[rustfmt::skip]
+    let breakpoint = [
+        0x40,             // ld b,b
+        0x18, 0xFE,       // jr -2
+    ];
+    rom_with_program(&[&print[..], message, &breakpoint].concat())
+}
+
+#[test]
+fn parse_message() {
+    use TraceSegment::*;
+    assert_eq!(
+        parse_debug_message("hl=%HL% [hl]=%(hl)% at %TOTALCLKS% %foo% 100%"),
+        [
+            Text("hl=".to_string()),
+            Register(TraceRegister::HL),
+            Text(" [hl]=".to_string()),
+            MemoryAt(TraceRegister::HL),
+            Text(" at ".to_string()),
+            Clock,
+            Text(" %foo% 100%".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+
+    assert_eq!(debugger.run_for(&mut gb, 10_000), RunResult::TimeOut);
+    assert!(debugger.take_trace_log().is_empty());
+}
+
+#[test]
+fn debug_message_and_source_breakpoint() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+
+    debugger.execute_command(&gb, &["trace", "debug"]).unwrap();
+    debugger.execute_command(&gb, &["break", "source"]).unwrap();
+    assert_eq!(
+        debugger.run_for(&mut gb, 10_000),
+        RunResult::ReachBreakpoint
+    );
+    assert_eq!(gb.cpu.pc, 0x162);
+    assert_eq!(debugger.take_trace_log(), ["a=42 %x%"]);
+
+    // continues after the breakpoint.
+    assert_eq!(debugger.run_for(&mut gb, 10_000), RunResult::TimeOut);
+
+    debugger
+        .execute_command(&gb, &["unbreak", "source"])
+        .unwrap();
+    debugger
+        .execute_command(&gb, &["untrace", "debug"])
+        .unwrap();
+    gb.reset();
+    assert_eq!(debugger.run_for(&mut gb, 10_000), RunResult::TimeOut);
+    assert!(debugger.take_trace_log().is_empty());
+}
@@ -0,0 +1,160 @@
+use gameroy::{
+    debugger::{
+        fmt_trace_format, parse_trace_format,
+        session::{Session, SessionError},
+        Debugger,
+    },
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+};
+
+fn game_boy() -> GameBoy {
+    GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default())
+}
+
+fn run(debugger: &mut Debugger, gb: &GameBoy, command: &str) {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    debugger.execute_command(gb, &args).unwrap();
+}
+
+#[test]
+fn trace_format_round_trip() {
+    let format = "a={a} hl={hl} [c000]={[c000]} [hl]={[hl]} at {clock}";
+    let segments = parse_trace_format(format).unwrap();
+    assert_eq!(fmt_trace_format(&segments), format);
+}
+
+#[test]
+fn labels_and_comments() {
+    let gb = game_boy();
+    let mut debugger = Debugger::default();
+    run(&mut debugger, &gb, "label c0a0 wPlayerHP");
+    run(&mut debugger, &gb, "comment 0150 wait for   vblank");
+    assert_eq!(debugger.labels()[&0xC0A0], "wPlayerHP");
+    assert_eq!(debugger.comments()[&0x0150], "wait for vblank");
+
+    assert!(debugger
+        .execute_command(&gb, &["label", "c0a1", "beef"])
+        .is_err());
+    assert!(debugger.execute_command(&gb, &["label", "c0a1"]).is_err());
+
+    run(&mut debugger, &gb, "unlabel c0a0");
+    run(&mut debugger, &gb, "uncomment 0150");
+    assert!(debugger.labels().is_empty());
+    assert!(debugger.comments().is_empty());
+}
+
+#[test]
+fn restore_commands() {
+    let gb = game_boy();
+    let mut debugger = Debugger::default();
+    for command in [
+        "break rw c000",
+        "break x 0150",
+        "break interrupt",
+        "trace io",
+        "watch ff44",
+        "watch [hl] + 1",
+        "trace 0150 a={a} [c000]={[c000]}",
+        "label c0a0 wPlayerHP",
+        "comment 0150 main loop",
+    ] {
+        run(&mut debugger, &gb, command);
+    }
+    let commands = debugger.session_commands();
+
+    let mut restored = Debugger::default();
+    run(&mut restored, &gb, "break w d000");
+    run(&mut restored, &gb, "label d000 old");
+    restored.restore_session(&gb, &commands).unwrap();
+
+    assert_eq!(restored.breakpoints(), debugger.breakpoints());
+    assert_eq!(restored.watchs(), debugger.watchs());
+    assert_eq!(restored.watch_expressions()[0].0, "[hl] + 1");
+    assert_eq!(restored.tracepoints(), debugger.tracepoints());
+    assert_eq!(restored.labels(), debugger.labels());
+    assert_eq!(restored.comments(), debugger.comments());
+    assert_eq!(restored.session_commands(), commands);
+}
+
+#[test]
+fn restore_rejects_other_commands() {
+    let gb = game_boy();
+    let mut debugger = Debugger::default();
+    let commands = [
+        "dump /tmp/disassembly.txt".to_string(),
+        "break x 0150".to_string(),
+        "label 0150".to_string(),
+    ];
+    let err = debugger.restore_session(&gb, &commands).unwrap_err();
+    assert_eq!(err.lines().count(), 2);
+    // the valid commands are still executed.
+    assert!(debugger.breakpoints().contains_key(&0x0150));
+}
+
+#[test]
+fn file_round_trip() {
+    let mut gb = game_boy();
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+
+    let session = Session {
+        rom_crc32: 0x1a2b3c4d,
+        commands: vec![
+            "break x 0150".to_string(),
+            "label c0a0 wPlayerHP".to_string(),
+        ],
+        layout: vec![("screen_split".to_string(), "0.333".to_string())],
+        state,
+    };
+    let mut file = Vec::new();
+    session.write(&mut file).unwrap();
+    assert!(file.starts_with(b"gameroy session 1\nrom 1a2b3c4d\nbreak x 0150\n"));
+
+    let read = Session::read(&file).unwrap();
+    assert_eq!(read, session);
+    assert!(read.check_rom(0x1a2b3c4d).is_ok());
+    assert!(matches!(
+        read.check_rom(0x12345678),
+        Err(SessionError::RomMismatch {
+            expected: 0x12345678,
+            found: 0x1a2b3c4d
+        })
+    ));
+    assert_eq!(read.layout_value("screen_split"), Some("0.333"));
+    gb.load_state(&mut &read.state[..]).unwrap();
+}
+
+#[test]
+fn malformed_files() {
+    assert!(matches!(
+        Session::read(b"not a session\nstate 0\n"),
+        Err(SessionError::InvalidHeader)
+    ));
+    assert!(matches!(
+        Session::read(b"gameroy session 99\nrom 1a2b3c4d\nstate 0\n"),
+        Err(SessionError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        Session::read(b"gameroy session 1\nrom 1a2b3c4d\nbreak x 0150\n"),
+        Err(SessionError::MissingState)
+    ));
+    assert!(matches!(
+        Session::read(b"gameroy session 1\nbreak x 0150\nstate 0\n"),
+        Err(SessionError::MissingRom)
+    ));
+    assert!(matches!(
+        Session::read(b"gameroy session 1\nrom 1a2b3c4d\nlayout key\nstate 0\n"),
+        Err(SessionError::InvalidLine(3))
+    ));
+    assert!(matches!(
+        Session::read(b"gameroy session 1\nrom 1a2b3c4d\nstate 4\nab"),
+        Err(SessionError::Truncated)
+    ));
+
+    let session =
+        Session::read(b"gameroy session 1\n\n# comment\nrom 1a2b3c4d\nbreak x 0150\nstate 2\nab")
+            .unwrap();
+    assert_eq!(session.rom_crc32, 0x1a2b3c4d);
+    assert_eq!(session.commands, ["break x 0150"]);
+    assert_eq!(session.state, b"ab");
+}
@@ -0,0 +1,80 @@
+use gameroy::{
+    consts::LEN,
+    disassembler::{decode_opcode, disassembly_opcode, Condition, Mnemonic, Operand, Register},
+};
+
+/// The first word of the disassembly of the given opcode.
+fn disassembly_mnemonic(pc: u16, op: &[u8]) -> String {
+    let mut string = String::new();
+    disassembly_opcode(pc, op, |x| format!("${:04x}", x), &mut string).unwrap();
+    string.split_whitespace().next().unwrap_or("").to_string()
+}
+
+#[test]
+fn mnemonics_match_disassembly() {
+    for opcode in 0..=0xFF {
+        let op = [opcode, 0x12, 0x34];
+        let op = &op[..LEN[opcode as usize] as usize];
+        let instr = decode_opcode(0x150, op);
+        assert_eq!(instr.len as usize, op.len(), "{:02x}", opcode);
+        assert_eq!(
+            instr.mnemonic.as_str(),
+            disassembly_mnemonic(0x150, op),
+            "{:02x}",
+            opcode
+        );
+    }
+    for opcode in 0..=0xFF {
+        let op = [0xCB, opcode];
+        let instr = decode_opcode(0x150, &op);
+        assert_eq!(instr.len, 2);
+        assert_eq!(
+            instr.mnemonic.as_str(),
+            disassembly_mnemonic(0x150, &op),
+            "cb {:02x}",
+            opcode
+        );
+    }
+}
+
+#[test]
+fn jump_targets() {
+    // JR -2
+    let instr = decode_opcode(0x200, &[0x18, 0xFE]);
+    assert_eq!(instr.target, Some(0x200));
+
+    // JP NZ, $1234
+    let instr = decode_opcode(0x200, &[0xC2, 0x34, 0x12]);
+    assert_eq!(instr.target, Some(0x1234));
+    assert_eq!(instr.flags_read, 0b1000);
+    assert_eq!(
+        instr.operands().collect::<Vec<_>>(),
+        [Operand::Condition(Condition::NZ), Operand::Target(0x1234)]
+    );
+
+    // RST 38H
+    assert_eq!(decode_opcode(0x200, &[0xFF]).target, Some(0x38));
+
+    // JP (HL) and RET have no static target
+    assert_eq!(decode_opcode(0x200, &[0xE9]).target, None);
+    assert_eq!(decode_opcode(0x200, &[0xC9]).target, None);
+}
+
+#[test]
+fn operands() {
+    // LDH ($ff44), A
+    let instr = decode_opcode(0x200, &[0xE0, 0x44]);
+    assert_eq!(instr.mnemonic, Mnemonic::Ldh);
+    assert_eq!(
+        instr.operands().collect::<Vec<_>>(),
+        [Operand::Address(0xFF44), Operand::Reg(Register::A)]
+    );
+
+    // BIT 7, (HL)
+    let instr = decode_opcode(0x200, &[0xCB, 0x7E]);
+    assert_eq!(
+        instr.operands().collect::<Vec<_>>(),
+        [Operand::Bit(7), Operand::Indirect(Register::HL)]
+    );
+    assert_eq!(instr.flags_written, 0b1110);
+}
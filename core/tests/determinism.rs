@@ -0,0 +1,111 @@
+//! The emulation must only depend on the rom, the boot rom, the initial cartridge ram and the
+//! joypad input of each frame. Nothing in the core may depend on the host time or in a
+//! nondeterministic iteration order, because that would break the playback of movies (and the
+//! frontend `--deterministic` mode).
+
+mod common;
+
+use common::{fix_checksums, write_program};
+use gameroy::{
+    consts::CLOCK_SPEED,
+    gameboy::{cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// A MBC3+TIMER+RAM+BATTERY rom, that keep writing the DIV register and the joypad state to the
+/// work ram, and latching the RTC.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    // MBC3+TIMER+RAM+BATTERY, with 8 KiB of ram
+    rom[0x147] = 0x10;
+    rom[0x149] = 0x02;
+
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0xC0, // 0x150: ld hl, 0xC000
+        0x3E, 0x0A,       // 0x153: ld a, 0x0A
+        0xEA, 0x00, 0x00, // 0x155: ld (0x0000), a  ; enable ram and rtc
+        0x3E, 0x08,       // 0x158: ld a, 0x08
+        0xEA, 0x00, 0x40, // 0x15A: ld (0x4000), a  ; select rtc seconds
+        // loop:
+        0xF0, 0x04,       // 0x15D: ldh a, (0x04)   ; DIV
+        0x22,             // 0x15F: ld (hl+), a
+        0xF0, 0x00,       // 0x160: ldh a, (0x00)   ; P1
+        0x22,             // 0x162: ld (hl+), a
+        0xAF,             // 0x163: xor a
+        0xEA, 0x00, 0x60, // 0x164: ld (0x6000), a  ; latch clock data
+        0x3C,             // 0x167: inc a
+        0xEA, 0x00, 0x60, // 0x168: ld (0x6000), a
+        0xFA, 0x00, 0xA0, // 0x16B: ld a, (0xA000)  ; rtc seconds
+        0x22,             // 0x16E: ld (hl+), a
+        0x7C,             // 0x16F: ld a, h
+        0xFE, 0xDF,       // 0x170: cp 0xDF
+        0x20, 0xE9,       // 0x172: jr nz, loop
+        0x21, 0x00, 0xC0, // 0x174: ld hl, 0xC000
+        0x18, 0xE4,       // 0x177: jr loop
+    ];
+    write_program(&mut rom, &program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+/// Run the test rom for some frames, with a fixed joypad input per frame, and return the final
+/// save state.
+fn run(rtc_time: u64) -> Vec<u8> {
+    let mut cartridge = Cartridge::new(test_rom()).unwrap();
+    cartridge.set_rtc_time(rtc_time);
+
+    let mut frame = 0u8;
+    let mut gb = GameBoy::builder(cartridge)
+        .v_blank(Box::new(move |gb| {
+            frame = frame.wrapping_add(1);
+            // press and release a different set of buttons each frame
+            gb.joypad = !frame.wrapping_mul(37);
+        }))
+        .build();
+
+    let target = gb.clock_count + CLOCK_SPEED;
+    while gb.clock_count < target {
+        Interpreter(&mut gb).interpret_op();
+    }
+
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+    state
+}
+
+#[test]
+fn save_states_are_byte_identical() {
+    let a = run(0);
+    let b = run(0);
+    assert!(
+        a == b,
+        "two runs of the same input produced different save states"
+    );
+}
+
+#[test]
+fn rtc_is_sourced_from_rtc_time() {
+    let seconds = 24 * 60 * 60 + 2 * 60 * 60 + 3 * 60 + 4;
+    let a = run(seconds);
+    let b = run(seconds);
+    assert!(
+        a == b,
+        "two runs of the same input produced different save states"
+    );
+    assert!(a != run(0), "the rtc time was not used by the rom");
+
+    let mut cartridge = Cartridge::new(test_rom()).unwrap();
+    cartridge.set_rtc_time(seconds);
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x6000, 0x00);
+    cartridge.write(0x6000, 0x01);
+    let rtc: Vec<u8> = (0x08..=0x0C)
+        .map(|register| {
+            cartridge.write(0x4000, register);
+            cartridge.read(0xA000)
+        })
+        .collect();
+    assert_eq!(rtc, [4, 3, 2, 1, 0]);
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{fix_checksums, game_boy, write_program};
+use gameroy::{disassembler::Address, gameboy::GameBoy};
+
+/// A MBC1 rom with 4 banks, with a jump table dispatcher at RST 0x28, a VBlank handler in a
+/// switchable bank, a STAT handler in bank 0, and a entry point that jumps through a table.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0xFF; 4 * 0x4000];
+    rom[0x134..0x14D].fill(0);
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 4 banks
+
+    #[rustfmt::skip]
+    let rst_28 = [
+        0x87,       // 0x28: add a
+        0xE1,       // 0x29: pop hl
+        0x5F,       // 0x2A: ld e, a
+        0x16, 0x00, // 0x2B: ld d, 0x00
+        0x19,       // 0x2D: add hl, de
+        0x2A,       // 0x2E: ld a, (hl+)
+        0x66,       // 0x2F: ld h, (hl)
+        0x6F,       // 0x30: ld l, a
+        0xE9,       // 0x31: jp hl
+    ];
+    rom[0x28..0x28 + rst_28.len()].copy_from_slice(&rst_28);
+
+    rom[0x40..0x44].copy_from_slice(&[0xF5, 0xC3, 0x00, 0x42]); // 0x40: push af; jp 0x4200
+    rom[0x48..0x4B].copy_from_slice(&[0xC3, 0x00, 0x03]); // 0x48: jp 0x0300
+    for vector in [0x50, 0x58, 0x60] {
+        rom[vector] = 0xD9; // reti
+    }
+    rom[0x300] = 0xD9; // 0x300: reti
+
+    #[rustfmt::skip]
+    let program = [
+        0xAF,       // 0x150: xor a
+        0xEF,       // 0x151: rst 0x28
+        0x60, 0x01, // 0x152: dw 0x0160
+        0x70, 0x01, // 0x154: dw 0x0170
+    ];
+    write_program(&mut rom, &program);
+    rom[0x160..0x162].copy_from_slice(&[0x18, 0xFE]); // 0x160: jr 0x160
+    rom[0x170..0x172].copy_from_slice(&[0x18, 0xFE]); // 0x170: jr 0x170
+
+    fix_checksums(&mut rom);
+    rom
+}
+
+fn label(gb: &GameBoy, bank: u16, address: u16) -> Option<String> {
+    let trace = gb.trace.borrow();
+    let label = trace.labels.get(&Address::new(bank, address))?;
+    Some(label.name.clone())
+}
+
+#[test]
+fn label_vectors_and_handlers() {
+    let gb = game_boy(test_rom());
+    gb.trace.borrow_mut().trace_entry_points(&gb);
+
+    assert_eq!(label(&gb, 0, 0x100).as_deref(), Some("entry point"));
+    assert_eq!(label(&gb, 0, 0x28).as_deref(), Some("RST_0x28"));
+    assert_eq!(label(&gb, 0, 0x40).as_deref(), Some("INT_VBLANK"));
+    assert_eq!(label(&gb, 0, 0x48).as_deref(), Some("INT_STAT"));
+    assert_eq!(label(&gb, 0, 0x300).as_deref(), Some("INT_STAT_handler"));
+
+    // the handler in the switchable bank is not traced until its bank is known.
+    assert_eq!(label(&gb, 1, 0x200), None);
+    gb.trace
+        .borrow_mut()
+        .trace_starting_at(&gb, (0, 2), 0x4200, Some("L02_4200".into()));
+    assert_eq!(
+        label(&gb, 2, 0x200).as_deref(),
+        Some("INT_VBLANK_handler_02")
+    );
+}
+
+#[test]
+fn follow_rst_jump_table() {
+    let gb = game_boy(test_rom());
+    gb.trace.borrow_mut().trace_entry_points(&gb);
+
+    let trace = gb.trace.borrow();
+    assert!(trace.is_already_traced(Address::new(0, 0x160)));
+    assert!(trace.is_already_traced(Address::new(0, 0x170)));
+    assert!(trace.labels.contains_key(&Address::new(0, 0x160)));
+    // the table is not disassembled as code
+    assert!(!trace.is_already_traced(Address::new(0, 0x152)));
+    assert!(!trace.is_already_traced(Address::new(0, 0x156)));
+}
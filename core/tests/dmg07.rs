@@ -0,0 +1,102 @@
+use gameroy::{
+    dmg07::{Dmg07, Phase, ACK, PING_HEADER, RESTART, START, STARTING},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, serial_transfer::Serial, GameBoy},
+};
+
+/// Run a ping packet, where each player answers with the given bytes, or is not linked. Return
+/// the bytes sent to each player.
+fn ping(dmg07: &mut Dmg07, answers: [Option<[u8; 4]>; 4]) -> [[u8; 4]; 4] {
+    let mut sent = [[0; 4]; 4];
+    for i in 0..4 {
+        dmg07.transfer(|player, byte| {
+            sent[player][i] = byte;
+            answers[player].map(|x| x[i])
+        });
+    }
+    sent
+}
+
+#[test]
+fn ping_phase() {
+    let mut dmg07 = Dmg07::new();
+    let answer = Some([ACK, ACK, 0x00, 0x04]);
+
+    let sent = ping(&mut dmg07, [answer, None, answer, None]);
+    // nobody was connected during the first packet.
+    assert_eq!(sent[0], [PING_HEADER, 0x01, 0x01, 0x01]);
+    assert_eq!(sent[3], [PING_HEADER, 0x04, 0x04, 0x04]);
+    assert_eq!(dmg07.connected(), 0b0101);
+    assert_eq!(dmg07.rate_and_size(), (0x00, 0x04));
+
+    // player 2 connects, and player 3 stops answering.
+    let sent = ping(&mut dmg07, [answer, answer, Some([0; 4]), None]);
+    assert_eq!(sent[1], [PING_HEADER, 0x52, 0x52, 0x52]);
+    assert_eq!(dmg07.connected(), 0b0011);
+    assert_eq!(dmg07.phase(), Phase::Ping);
+}
+
+#[test]
+fn transmission_phase() {
+    let mut dmg07 = Dmg07::new();
+    let answer = Some([ACK, ACK, 0x00, 0x02]);
+    ping(&mut dmg07, [answer, answer, None, None]);
+    ping(
+        &mut dmg07,
+        [Some([START, ACK, 0x00, 0x02]), answer, None, None],
+    );
+    assert_eq!(dmg07.phase(), Phase::Starting);
+    assert_eq!(dmg07.connected(), 0b0011);
+
+    for _ in 0..4 {
+        assert_eq!(dmg07.output(), [STARTING; 4]);
+        dmg07.input([Some(0); 4]);
+    }
+    assert_eq!(dmg07.phase(), Phase::Transmission);
+
+    // each cycle has 4 packets of 2 bytes, and the players send theirs in the first 2 bytes.
+    let cycle = |dmg07: &mut Dmg07, packets: [[u8; 2]; 4]| {
+        let mut sent = Vec::new();
+        for i in 0..8 {
+            dmg07.transfer(|player, byte| {
+                if player == 0 {
+                    sent.push(byte);
+                }
+                Some(packets[player].get(i).copied().unwrap_or(0x55))
+            });
+        }
+        sent
+    };
+    let first = cycle(&mut dmg07, [[1, 2], [3, 4], [5, 6], [7, 8]]);
+    assert_eq!(first, [0; 8]);
+    let second = cycle(&mut dmg07, [[9, 10], [11, 12], [0, 0], [0, 0]]);
+    // players 3 and 4 are not connected.
+    assert_eq!(second, [1, 2, 3, 4, 0, 0, 0, 0]);
+    let third = cycle(&mut dmg07, [[RESTART, RESTART], [0, 0], [0, 0], [0, 0]]);
+    assert_eq!(third, [9, 10, 11, 12, 0, 0, 0, 0]);
+
+    assert_eq!(dmg07.phase(), Phase::Ping);
+    assert_eq!(dmg07.output(), [PING_HEADER; 4]);
+}
+
+#[test]
+fn external_transfer() {
+    let cartridge = Cartridge::halt_filled();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    gb.serial.get_mut().serial_transfer_callback = None;
+    gb.interrupt_flag.set(0);
+
+    // the game is not waiting for a transfer.
+    assert_eq!(Serial::external_transfer(&mut gb, 0x99), None);
+
+    gb.write(0xFF01, 0x42);
+    // start a transfer with the external clock.
+    gb.write(0xFF02, 0x80);
+    assert_eq!(Serial::external_transfer(&mut gb, 0x99), Some(0x42));
+    assert_eq!(gb.read(0xFF01), 0x99);
+    assert_eq!(gb.read(0xFF02) & 0x80, 0);
+    assert_eq!(gb.interrupt_flag.get() & 0x08, 0x08);
+
+    // a transfer with the internal clock is not clocked by the partner.
+    gb.write(0xFF02, 0x81);
+    assert_eq!(Serial::external_transfer(&mut gb, 0x99), None);
+}
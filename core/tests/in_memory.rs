@@ -0,0 +1,80 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{fix_checksums, write_program};
+use gameroy::{
+    gameboy::{cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// A MBC1 cartridge with 8 KiB of battery backed RAM, that enables the RAM, writes 0x42 to A000
+/// and disables the RAM again, like a game saving its progress.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x02; // 8 KiB
+
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x0A,       // 0x150: ld a, 0x0A
+        0xEA, 0x00, 0x00, // 0x152: ld (0x0000), a
+        0x3E, 0x42,       // 0x155: ld a, 0x42
+        0xEA, 0x00, 0xA0, // 0x157: ld (0xA000), a
+        0xAF,             // 0x15A: xor a
+        0xEA, 0x00, 0x00, // 0x15B: ld (0x0000), a
+        0x18, 0xFE,       // 0x15E: jr 0x15E
+    ];
+    write_program(&mut rom, &program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+#[test]
+fn cartridge_from_bytes() {
+    let rom = test_rom();
+    let cartridge = Cartridge::from_bytes(&rom).unwrap();
+    assert_eq!(cartridge.rom, rom);
+    assert_eq!(cartridge.ram.len(), 0x2000);
+
+    // a bad checksum is only a warning.
+    let mut bad_checksum = rom.clone();
+    bad_checksum[0x14D] ^= 0xFF;
+    assert!(Cartridge::new(bad_checksum.clone()).is_err());
+    assert!(Cartridge::from_bytes(&bad_checksum).is_ok());
+
+    assert!(Cartridge::from_bytes(&[0; 0x10]).is_err());
+}
+
+#[test]
+fn save_ram_callback() {
+    let saves = Arc::new(Mutex::new(Vec::new()));
+    let saves_ = saves.clone();
+    let mut gb = GameBoy::builder(Cartridge::from_bytes(&test_rom()).unwrap())
+        .save_ram(Box::new(move |ram| {
+            saves_.lock().unwrap().push(ram.to_vec())
+        }))
+        .build();
+
+    while gb.cpu.pc != 0x15E {
+        Interpreter(&mut gb).interpret_op();
+    }
+    {
+        let saves = saves.lock().unwrap();
+        assert_eq!(saves.len(), 1);
+        assert_eq!(saves[0][0], 0x42);
+    }
+    assert!(!gb.ram_modified);
+
+    // nothing changed since the last save.
+    gb.flush_save_ram();
+    assert_eq!(saves.lock().unwrap().len(), 1);
+
+    gb.write(0x0000, 0x0A);
+    gb.write(0xA001, 0x43);
+    assert!(gb.ram_modified);
+    gb.flush_save_ram();
+    let saves = saves.lock().unwrap();
+    assert_eq!(saves.len(), 2);
+    assert_eq!(saves[1][..2], [0x42, 0x43]);
+}
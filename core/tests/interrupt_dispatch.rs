@@ -0,0 +1,76 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// A rom that sets SP to `sp`, requests and enables the timer interrupt, and then executes `ei`
+/// followed by two `inc b`. The interrupt vectors are filled with `halt`.
+fn test_rom(sp: u16) -> Vec<u8> {
+    let [sp_lsb, sp_msb] = sp.to_le_bytes();
+    #[rustfmt::skip]
+    let program = [
+        0x31, sp_lsb, sp_msb, // 0x150: ld sp, $sp
+        0x06, 0x00,           // 0x153: ld b, 0
+        0x3E, 0x04,           // 0x155: ld a, 0x04
+        0xE0, 0xFF,           // 0x157: ldh (0xFF), a   ; IE
+        0xE0, 0x0F,           // 0x159: ldh (0x0F), a   ; IF
+        0xFB,                 // 0x15B: ei
+        0x04,                 // 0x15C: inc b
+        0x04,                 // 0x15D: inc b
+        0x18, 0xFE,           // 0x15E: jr 0x15E
+    ];
+    rom_with_program(&program)
+}
+
+fn game_boy(sp: u16) -> GameBoy {
+    let cartridge = Cartridge::new(test_rom(sp)).unwrap();
+    GameBoy::new(None, cartridge, AccuracyConfig::ACCURATE)
+}
+
+/// Run until the interrupt is dispatched, and return the number of cycles the dispatch took.
+fn run_until_dispatch(gb: &mut GameBoy) -> u64 {
+    let timeout = gb.clock_count + 10_000;
+    while gb.clock_count < timeout {
+        let pc = gb.cpu.pc;
+        let start = gb.clock_count;
+        Interpreter(gb).interpret_op();
+        if (0x0000..0x0100).contains(&gb.cpu.pc) && pc >= 0x0100 {
+            return gb.clock_count - start;
+        }
+    }
+    panic!("the interrupt was not dispatched");
+}
+
+#[test]
+fn dispatch_takes_20_cycles() {
+    let mut gb = game_boy(0xD000);
+    assert_eq!(run_until_dispatch(&mut gb), 20);
+    assert_eq!(gb.cpu.pc, 0x50);
+    assert_eq!(gb.interrupt_flag.get() & 0x04, 0);
+}
+
+#[test]
+fn ei_is_delayed_by_one_instruction() {
+    let mut gb = game_boy(0xD000);
+    run_until_dispatch(&mut gb);
+    // the interrupt is dispatched after the first `inc b`.
+    assert_eq!(gb.cpu.b, 1);
+    assert_eq!(gb.cpu.sp, 0xCFFE);
+    assert_eq!(gb.read(0xCFFE), 0x5D);
+    assert_eq!(gb.read(0xCFFF), 0x01);
+}
+
+#[test]
+fn ie_push_cancels_dispatch() {
+    // the push of the high byte of PC (0x01) overwrites IE, disabling the timer interrupt in the
+    // middle of the dispatch, which jumps to 0x0000 instead.
+    let mut gb = game_boy(0x0000);
+    run_until_dispatch(&mut gb);
+    assert_eq!(gb.cpu.pc, 0x0000);
+    assert_eq!(gb.interrupt_enabled, 0x01);
+    // the interrupt was not acknowledged.
+    assert_eq!(gb.interrupt_flag.get() & 0x04, 0x04);
+}
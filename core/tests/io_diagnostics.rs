@@ -0,0 +1,70 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::debugger::Debugger;
+
+/// A rom that loops reading and writing to I/O registers that are not emulated.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF0, 0x4D, // 0x150: ldh a, (0x4D)
+        0xF0, 0x4D, // 0x152: ldh a, (0x4D)
+        0x3E, 0x01, // 0x154: ld a, 0x01
+        0xE0, 0x4F, // 0x156: ldh (0x4F), a
+        0xE0, 0x4F, // 0x158: ldh (0x4F), a
+        0xF0, 0x03, // 0x15A: ldh a, (0x03)
+        0x18, 0xF2, // 0x15C: jr 0x150
+    ];
+    rom_with_program(&program)
+}
+
+#[test]
+fn log_first_access() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["trace", "io"]).unwrap();
+
+    // a read from outside the emulation, like the memory viewer, is not logged.
+    assert_eq!(gb.peek(0xFF56), 0xff);
+
+    let target = gb.clock_count + 1000;
+    debugger.run_until(&mut gb, target);
+
+    let log = debugger.take_trace_log();
+    let log: Vec<&str> = log
+        .iter()
+        .map(|x| x.split(" at clock ").next().unwrap())
+        .collect();
+    assert_eq!(
+        log,
+        [
+            "0150: read from unemulated ff4d (CGB KEY1, speed switch)",
+            "0156: write 01 to unemulated ff4f (CGB VBK, VRAM bank)",
+            "015a: read from unemulated ff03 (unused)",
+        ]
+    );
+
+    // enabling it again reports the registers again.
+    debugger.execute_command(&gb, &["trace", "io"]).unwrap();
+    let target = gb.clock_count + 100;
+    debugger.run_until(&mut gb, target);
+    assert_eq!(debugger.take_trace_log().len(), 3);
+
+    debugger.execute_command(&gb, &["untrace", "io"]).unwrap();
+    debugger.execute_command(&gb, &["trace", "io"]).unwrap();
+    debugger.execute_command(&gb, &["untrace", "io"]).unwrap();
+    let target = gb.clock_count + 100;
+    debugger.run_until(&mut gb, target);
+    assert!(debugger.take_trace_log().is_empty());
+}
+
+#[test]
+fn peek_is_not_recorded() {
+    let gb = game_boy(test_rom());
+    assert_eq!(gb.peek(0xFF4D), 0xff);
+    assert_eq!(gb.last_unemulated_io.get(), None);
+
+    assert_eq!(gb.read(0xFF4D), 0xff);
+    assert_eq!(gb.peek(0xFF03), 0xff);
+    assert_eq!(gb.last_unemulated_io.get(), Some((0xFF4D, None)));
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{fix_checksums, game_boy, write_program};
+use gameroy::{
+    debugger::{Debugger, RunResult},
+    gameboy::cartridge::{Cartridge, MbcState, RtcState},
+};
+
+/// A MBC1 rom with 4 banks, that switches to ROM bank 2 and enables the RAM.
+fn test_rom() -> Vec<u8> {
+    let mut rom = vec![0x76; 4 * 0x4000];
+    rom[..0x8000].copy_from_slice(&Cartridge::halt_filled().rom);
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 4 banks
+
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x02,       // 0x150: ld a, 0x02
+        0xEA, 0x00, 0x20, // 0x152: ld (0x2000), a
+        0x3E, 0x0A,       // 0x155: ld a, 0x0A
+        0xEA, 0x00, 0x00, // 0x157: ld (0x0000), a
+        0x18, 0xFE,       // 0x15A: jr 0x15A
+    ];
+    write_program(&mut rom, &program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+#[test]
+fn log_mbc_writes() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["trace", "mbc"]).unwrap();
+
+    let target = gb.clock_count + 1000;
+    debugger.run_until(&mut gb, target);
+
+    assert_eq!(
+        debugger.take_trace_log(),
+        [
+            "0152: MBC1 write 02 to 2000: ROM bank low bits = 02, ROM banks 00/02",
+            "0157: MBC1 write 0a to 0000: RAM enable, ROM banks 00/02",
+        ]
+    );
+}
+
+#[test]
+fn break_on_mbc_write() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["break", "mbc"]).unwrap();
+
+    let target = gb.clock_count + 1000;
+    assert_eq!(
+        debugger.run_until(&mut gb, target),
+        RunResult::ReachBreakpoint
+    );
+    assert_eq!(gb.cpu.pc, 0x152);
+    assert_eq!(gb.cartridge.curr_bank(), (0, 1));
+
+    debugger.execute_command(&gb, &["unbreak", "mbc"]).unwrap();
+    assert_eq!(debugger.run_until(&mut gb, target), RunResult::TimeOut);
+    assert_eq!(gb.cartridge.curr_bank(), (0, 2));
+}
+
+/// A cartridge with 4 ROM banks, 32 KiB of RAM, and the given kind of MBC.
+fn cartridge_with_ram(kind: u8) -> Cartridge {
+    let mut rom = test_rom();
+    rom[0x147] = kind;
+    rom[0x149] = 0x03; // 4 RAM banks
+    fix_checksums(&mut rom);
+    let mut cartridge = Cartridge::new(rom).unwrap();
+    for (i, x) in cartridge.ram.iter_mut().enumerate() {
+        *x = (i / 0x2000) as u8;
+    }
+    cartridge
+}
+
+#[test]
+fn mbc1_state() {
+    let mut cartridge = cartridge_with_ram(0x03); // MBC1+RAM+BATTERY
+    assert_eq!(cartridge.num_ram_banks(), 4);
+    assert_eq!(cartridge.mbc_state().ram_bank, Some(0));
+
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x4000, 0x02);
+    cartridge.write(0x6000, 0x01);
+    assert_eq!(
+        cartridge.mbc_state(),
+        MbcState {
+            lower_rom_bank: 0,
+            upper_rom_bank: 1,
+            ram_bank: Some(2),
+            ram_enabled: true,
+            banking_mode: Some(1),
+            rtc: None,
+        }
+    );
+    assert_eq!(cartridge.read(0xA000), 2);
+    assert_eq!(cartridge.ram_bank(3).unwrap()[0], 3);
+    assert_eq!(cartridge.ram_bank(4), None);
+}
+
+#[test]
+fn mbc3_state() {
+    let mut cartridge = cartridge_with_ram(0x10); // MBC3+TIMER+RAM+BATTERY
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x2000, 0x03);
+    cartridge.write(0x4000, 0x02);
+    let state = cartridge.mbc_state();
+    assert_eq!(state.upper_rom_bank, 3);
+    assert_eq!(state.ram_bank, Some(2));
+    assert_eq!(cartridge.read(0xA000), 2);
+
+    cartridge.set_rtc_time(90);
+    cartridge.write(0x6000, 0x00);
+    assert!(cartridge.mbc_state().rtc.unwrap().latch_armed);
+    cartridge.write(0x6000, 0x01);
+    cartridge.write(0x4000, 0x09);
+    let state = cartridge.mbc_state();
+    assert_eq!(state.ram_bank, None);
+    assert_eq!(
+        state.rtc,
+        Some(RtcState {
+            registers: [30, 1, 0, 0, 0],
+            selected: Some(0x09),
+            latch_armed: false,
+        })
+    );
+    assert_eq!(cartridge.read(0xA000), 1);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    debugger::{Debugger, RunResult},
+    gameboy::GameBoy,
+};
+
+/// A rom that keeps incrementing the byte at c000.
+fn game_boy() -> GameBoy {
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0xC0, // 0x150: ld hl, 0xC000
+        0x34,             // 0x153: inc (hl)
+        0x18, 0xFD,       // 0x154: jr 0x153
+    ];
+    let mut gb = common::game_boy(rom_with_program(&program));
+    gb.wram[0] = 0x42;
+    gb
+}
+
+#[test]
+fn freeze() {
+    let mut gb = game_boy();
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&gb, &["freeze", "c000", "c0ff"])
+        .unwrap();
+
+    assert_eq!(debugger.run_for(&mut gb, 10_000), RunResult::TimeOut);
+    assert_eq!(gb.read(0xC000), 0x42);
+    // the writes through the echo RAM are ignored too.
+    gb.write(0xE000, 0x00);
+    assert_eq!(gb.read(0xC000), 0x42);
+    gb.write(0xC100, 0x55);
+    assert_eq!(gb.read(0xC100), 0x55);
+
+    debugger.execute_command(&gb, &["freeze"]).unwrap();
+    assert_eq!(debugger.take_trace_log(), ["freeze c000-c0ff"]);
+
+    debugger
+        .execute_command(&gb, &["unfreeze", "c080"])
+        .unwrap();
+    assert!(gb.memory_locks.borrow().is_empty());
+    assert!(debugger.execute_command(&gb, &["unfreeze"]).is_err());
+    debugger.run_for(&mut gb, 100);
+    assert_ne!(gb.read(0xC000), 0x42);
+}
+
+#[test]
+fn protect() {
+    let mut gb = game_boy();
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["protect", "c000"]).unwrap();
+
+    assert_eq!(
+        debugger.run_for(&mut gb, 10_000),
+        RunResult::ReachBreakpoint
+    );
+    // stops after the write, which was ignored.
+    assert_eq!(gb.cpu.pc, 0x154);
+    assert_eq!(gb.read(0xC000), 0x42);
+    assert_eq!(
+        debugger.take_trace_log(),
+        ["0153: write 43 to c000 ignored, protected"]
+    );
+
+    debugger.execute_command(&gb, &["unprotect"]).unwrap();
+    assert_eq!(debugger.run_for(&mut gb, 100), RunResult::TimeOut);
+    assert_ne!(gb.read(0xC000), 0x42);
+}
+
+#[test]
+fn invalid_regions() {
+    let gb = game_boy();
+    let mut debugger = Debugger::default();
+    assert!(debugger
+        .execute_command(&gb, &["freeze", "c0ff", "c000"])
+        .is_err());
+    assert!(debugger.execute_command(&gb, &["protect", "xyz"]).is_err());
+    assert!(debugger
+        .execute_command(&gb, &["protect", "c000", "c001", "c002"])
+        .is_err());
+    assert!(debugger
+        .execute_command(&gb, &["unprotect", "c000"])
+        .is_err());
+    assert!(gb.memory_locks.borrow().is_empty());
+}
@@ -0,0 +1,26 @@
+use gameroy::gameboy::memory_map::{io_register_name, Region};
+
+#[test]
+fn regions() {
+    let banks = (0, 5);
+    assert_eq!(Region::of(0x0150, banks), Region::RomBank0(0));
+    assert_eq!(Region::of(0x4000, banks), Region::RomBankN(5));
+    assert_eq!(Region::of(0x8010, banks), Region::TileData(1));
+    assert_eq!(Region::of(0x97FF, banks), Region::TileData(0x17F));
+    assert_eq!(Region::of(0x9800, banks), Region::TileMap(0));
+    assert_eq!(Region::of(0x9C00, banks), Region::TileMap(1));
+    assert_eq!(Region::of(0xFE07, banks), Region::Oam(1));
+    assert_eq!(Region::of(0xFE9F, banks), Region::Oam(39));
+    assert_eq!(Region::of(0xFF40, banks), Region::Io(Some("LCDC")));
+    assert_eq!(Region::of(0xFF03, banks), Region::Io(None));
+    assert_eq!(Region::of(0xFF80, banks), Region::HighRam);
+    assert_eq!(Region::of(0xFFFF, banks), Region::InterruptEnable);
+}
+
+#[test]
+fn display() {
+    assert_eq!(Region::of(0x4000, (0, 0x12)).to_string(), "ROM bank 12");
+    assert_eq!(Region::of(0xFE10, (0, 1)).to_string(), "OAM object 4");
+    assert_eq!(Region::of(0xFF0F, (0, 1)).to_string(), "IO IF");
+    assert_eq!(io_register_name(0xFF26), Some("NR52"));
+}
@@ -0,0 +1,153 @@
+use gameroy::{
+    consts::{FRAME_CYCLES, SCANLINE_CYCLES},
+    debugger::Debugger,
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+};
+
+/// A halted GameBoy, with the PPU emulated dot by dot.
+fn gameboy(accuracy: AccuracyConfig) -> GameBoy {
+    let cartridge = Cartridge::halt_filled();
+    GameBoy::new(None, cartridge, accuracy)
+}
+
+/// Draw an entire frame with the current PPU registers.
+fn run_frame(gb: &mut GameBoy) {
+    let mut debugger = Debugger::default();
+    let target = gb.clock_count + FRAME_CYCLES;
+    debugger.run_until(gb, target);
+    gb.update_all();
+}
+
+/// Place sprite `i` in OAM, at the screen position `x`, `y`.
+fn set_sprite(gb: &mut GameBoy, i: usize, x: u8, y: u8) {
+    let oam = &mut gb.ppu.get_mut().oam;
+    oam[i * 4..i * 4 + 4].copy_from_slice(&[y + 16, x + 8, 0, 0]);
+}
+
+#[test]
+fn scx_and_sprites() {
+    let mut gb = gameboy(AccuracyConfig::ACCURATE);
+    gb.ppu.get_mut().oam = [0; 0xA0];
+    run_frame(&mut gb);
+
+    let base = gb.ppu.borrow().mode_3_length(0).unwrap();
+    assert_eq!(base, BASE_LENGTH);
+    for ly in 0..144 {
+        assert_eq!(gb.ppu.borrow().mode_3_length(ly), Some(base), "ly {}", ly);
+    }
+
+    // the pixels discarded by SCX % 8 take a cycle each.
+    for scx in [1, 4, 7, 8, 13] {
+        gb.write(0xFF43, scx);
+        run_frame(&mut gb);
+        let length = gb.ppu.borrow().mode_3_length(50).unwrap();
+        assert_eq!(length, base + (scx % 8) as u16, "scx {}", scx);
+    }
+    gb.write(0xFF43, 0);
+
+    // a sprite aligned to the background tiles, in the scanlines 16 to 23.
+    set_sprite(&mut gb, 0, 16, 16);
+    // enable the sprites.
+    gb.ppu.get_mut().lcdc |= 0x02;
+    run_frame(&mut gb);
+    let ppu = gb.ppu.borrow();
+    assert_eq!(ppu.mode_3_length(15), Some(base));
+    assert_eq!(ppu.mode_3_length(16), Some(base + ALIGNED_SPRITE_PENALTY));
+    assert_eq!(ppu.mode_3_length(23), Some(base + ALIGNED_SPRITE_PENALTY));
+    assert_eq!(ppu.mode_3_length(24), Some(base));
+    drop(ppu);
+
+    // each sprite adds its own penalty.
+    set_sprite(&mut gb, 1, 40, 16);
+    set_sprite(&mut gb, 2, 0, 20);
+    run_frame(&mut gb);
+    let ppu = gb.ppu.borrow();
+    assert_eq!(
+        ppu.mode_3_length(16),
+        Some(base + 2 * ALIGNED_SPRITE_PENALTY)
+    );
+    assert_eq!(
+        ppu.mode_3_length(20),
+        Some(base + 3 * ALIGNED_SPRITE_PENALTY)
+    );
+    drop(ppu);
+
+    // a sprite that starts in the middle of a background tile waits less for its fetch, one cycle
+    // less for each pixel into the tile, up to 5.
+    gb.ppu.get_mut().oam = [0; 0xA0];
+    set_sprite(&mut gb, 0, 19, 16);
+    run_frame(&mut gb);
+    assert_eq!(
+        gb.ppu.borrow().mode_3_length(16),
+        Some(base + ALIGNED_SPRITE_PENALTY - 3)
+    );
+}
+
+/// The length of mode 3 with SCX = 0 and no sprites or window.
+const BASE_LENGTH: u16 = 172;
+/// The penalty of a sprite whose left border is aligned to a background tile.
+const ALIGNED_SPRITE_PENALTY: u16 = 11;
+
+#[test]
+fn stat_polling() {
+    let mut gb = gameboy(AccuracyConfig::ACCURATE);
+    gb.ppu.get_mut().oam = [0; 0xA0];
+    set_sprite(&mut gb, 0, 21, 30);
+    set_sprite(&mut gb, 1, 77, 30);
+    // enable the sprites.
+    gb.ppu.get_mut().lcdc |= 0x02;
+    gb.write(0xFF43, 3);
+    run_frame(&mut gb);
+
+    // go to the start of the scanline 30, and count the cycles in which STAT reads mode 3, like a
+    // game that polls it.
+    while gb.read(0xFF44) != 30 {
+        gb.clock_count += 1;
+    }
+    let mut mode_3_cycles = 0;
+    for _ in 0..SCANLINE_CYCLES {
+        if gb.read(0xFF41) & 0b11 == 3 {
+            mode_3_cycles += 1;
+        }
+        gb.clock_count += 1;
+    }
+    let length = gb.ppu.borrow().mode_3_length(30).unwrap();
+    assert_eq!(mode_3_cycles, length);
+    assert!(length > BASE_LENGTH + 3);
+}
+
+#[test]
+fn simple_ppu_is_not_traced() {
+    let mut gb = gameboy(AccuracyConfig::BALANCED);
+    run_frame(&mut gb);
+    run_frame(&mut gb);
+    // the CPU is halted and never reads the PPU, so all scanlines are rendered at once.
+    assert_eq!(gb.ppu.borrow().mode_3_length(40), None);
+
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["modes", "40"]).unwrap();
+    assert_eq!(debugger.take_trace_log(), ["ly 40: not traced"]);
+}
+
+#[test]
+fn modes_command() {
+    let mut gb = gameboy(AccuracyConfig::ACCURATE);
+    gb.ppu.get_mut().oam = [0; 0xA0];
+    gb.write(0xFF43, 2);
+    run_frame(&mut gb);
+
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["modes", "10"]).unwrap();
+    let base = BASE_LENGTH + 2;
+    assert_eq!(
+        debugger.take_trace_log(),
+        [format!(
+            "ly 10: mode 2 80, mode 3 {}, mode 0 {} cycles",
+            base,
+            376 - base
+        )]
+    );
+
+    assert!(debugger.execute_command(&gb, &["modes", "144"]).is_err());
+    assert!(debugger.execute_command(&gb, &["modes", "1", "2"]).is_err());
+}
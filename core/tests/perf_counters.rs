@@ -0,0 +1,113 @@
+mod common;
+
+use common::{fix_checksums, game_boy, write_program};
+use gameroy::{consts::FRAME_CYCLES, debugger::Debugger, gameboy::cartridge::Cartridge};
+
+/// A MBC1 rom that switches to ROM bank 2, starts a OAM DMA, and loops with the VBlank interrupt
+/// enabled, handled by `handler`.
+fn test_rom(handler: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x76; 4 * 0x4000];
+    rom[..0x8000].copy_from_slice(&Cartridge::halt_filled().rom);
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 4 banks
+
+    rom[0x40..0x40 + handler.len()].copy_from_slice(handler);
+
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x02,       // 0x150: ld a, 0x02
+        0xEA, 0x00, 0x20, // 0x152: ld (0x2000), a
+        0x3E, 0xC0,       // 0x155: ld a, 0xC0
+        0xE0, 0x46,       // 0x157: ldh (0x46), a
+        0x3E, 0x01,       // 0x159: ld a, 0x01
+        0xE0, 0xFF,       // 0x15B: ldh (0xFF), a
+        0xFB,             // 0x15D: ei
+        0x18, 0xFE,       // 0x15E: jr 0x15E
+    ];
+    write_program(&mut rom, &program);
+    fix_checksums(&mut rom);
+    rom
+}
+
+#[test]
+fn count_and_reset() {
+    let mut gb = game_boy(test_rom(&[0xD9])); // 0x40: reti
+    let mut debugger = Debugger::default();
+
+    let start = gb.clock_count;
+    debugger.run_until(&mut gb, start + 3 * FRAME_CYCLES);
+
+    let counters = gb.counters.get();
+    assert_eq!(counters.since_clock, start);
+    assert_eq!(counters.bank_switches, 1);
+    assert_eq!(counters.dma_transfers, 1);
+    assert!(counters.instructions > 3 * FRAME_CYCLES / 16);
+    assert!((2..=4).contains(&counters.interrupts[0]));
+    assert_eq!(counters.interrupts[1..], [0; 4]);
+
+    debugger.execute_command(&gb, &["stats"]).unwrap();
+    let log = debugger.take_trace_log();
+    assert!(log[0].starts_with("cycles:"));
+    assert!(log.iter().any(|x| x.starts_with("bank switches:")));
+
+    debugger.execute_command(&gb, &["stats", "reset"]).unwrap();
+    let counters = gb.counters.get();
+    assert_eq!(counters.since_clock, gb.clock_count);
+    assert_eq!(counters.instructions, 0);
+    assert_eq!(counters.interrupts, [0; 5]);
+    assert_eq!(counters.interrupt_cycles, [0; 5]);
+
+    assert!(debugger.execute_command(&gb, &["stats", "foo"]).is_err());
+}
+
+#[test]
+fn instructions_not_available_with_jit() {
+    let mut gb = game_boy(test_rom(&[0xD9])); // 0x40: reti
+    let mut debugger = Debugger::default();
+    let target = gb.clock_count + FRAME_CYCLES;
+    debugger.run_until(&mut gb, target);
+
+    // what the JIT compiler records after running a block.
+    let mut counters = gb.counters.get();
+    counters.jit_cycles = (gb.clock_count - counters.since_clock) / 4;
+    gb.counters.set(counters);
+
+    debugger.execute_command(&gb, &["stats"]).unwrap();
+    let log = debugger.take_trace_log();
+    let line = log.iter().find(|x| x.starts_with("instructions:")).unwrap();
+    assert!(line.contains("n/a") && line.contains("25.0%"), "{}", line);
+}
+
+#[test]
+fn interrupt_handler_cycles() {
+    #[rustfmt::skip]
+    let handler = [
+        0x06, 0x0A, // 0x40: ld b, 10
+        0x05,       // 0x42: dec b
+        0x20, 0xFD, // 0x43: jr nz, 0x42
+        0xD9,       // 0x45: reti
+    ];
+    let mut gb = game_boy(test_rom(&handler));
+    let mut debugger = Debugger::default();
+
+    let start = gb.clock_count;
+    debugger.run_until(&mut gb, start + 10 * FRAME_CYCLES);
+
+    // the dispatch (20), ld (8), 10 dec (40), 9 taken jr (108), 1 not taken jr (8) and reti (16).
+    const HANDLER_CYCLES: u64 = 200;
+    let counters = gb.counters.get();
+    let calls = counters.interrupts[0];
+    assert!((9..=11).contains(&calls));
+    assert_eq!(counters.interrupt_cycles[0], calls * HANDLER_CYCLES);
+    assert_eq!(counters.interrupt_max_cycles[0], HANDLER_CYCLES);
+    assert_eq!(counters.interrupt_cycles[1..], [0; 4]);
+
+    debugger.execute_command(&gb, &["stats"]).unwrap();
+    let log = debugger.take_trace_log();
+    let line = log
+        .iter()
+        .find(|x| x.starts_with("vblank cycles:"))
+        .unwrap();
+    assert!(line.contains("max 200"), "{}", line);
+    assert!(!log.iter().any(|x| x.starts_with("stat cycles:")));
+}
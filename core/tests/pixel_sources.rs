@@ -0,0 +1,118 @@
+use gameroy::{
+    consts::FRAME_CYCLES,
+    gameboy::{
+        accuracy::AccuracyConfig,
+        cartridge::Cartridge,
+        ppu::{PixelLayer, PixelSource},
+        GameBoy,
+    },
+    interpreter::Interpreter,
+};
+
+/// Create a GameBoy that loops forever, with a background that is all tile 0, except for tile 1 at
+/// the tile (3, 1), and two sprites of tile 2 at line 8: sprite 3 at x = 16, and sprite 4, with
+/// background priority, at x = 20.
+fn setup(accuracy: AccuracyConfig) -> GameBoy {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // 0x100: jr 0x100
+    let cartridge = Cartridge::new(rom).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, accuracy);
+
+    let ppu = gb.ppu.get_mut();
+    ppu.vram[..0x10].fill(0x00);
+    ppu.vram[0x10..0x20].copy_from_slice(&[0xFF, 0x00].repeat(8));
+    ppu.vram[0x20..0x30].fill(0xFF);
+    ppu.vram[0x1800..0x1C00].fill(0);
+    ppu.vram[0x1800 + 32 + 3] = 1;
+
+    ppu.oam.fill(0);
+    ppu.oam[3 * 4..][..4].copy_from_slice(&[24, 24, 2, 0x10]);
+    ppu.oam[4 * 4..][..4].copy_from_slice(&[24, 28, 2, 0x80]);
+
+    ppu.lcdc = 0x93;
+    ppu.obp1 = 0xE4;
+    gb
+}
+
+fn run_frames(gb: &mut GameBoy) {
+    let target = gb.clock_count + 2 * FRAME_CYCLES;
+    while gb.clock_count < target {
+        Interpreter(gb).interpret_op();
+    }
+    gb.update_all();
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut gb = setup(AccuracyConfig::default());
+    run_frames(&mut gb);
+    assert_eq!(gb.ppu.borrow().pixel_source(0, 0), None);
+}
+
+#[test]
+fn record_pixel_sources() {
+    for accuracy in [AccuracyConfig::ACCURATE, AccuracyConfig::BALANCED] {
+        let mut gb = setup(accuracy);
+        gb.ppu.get_mut().set_pixel_sources_enabled(true);
+        run_frames(&mut gb);
+
+        let ppu = gb.ppu.borrow();
+        let bgp = ppu.bgp;
+        assert_eq!(
+            ppu.pixel_source(0, 0),
+            Some(PixelSource {
+                layer: PixelLayer::Background,
+                tile: 0,
+                tile_address: 0x8000,
+                palette: bgp,
+                color: 0,
+                attributes: 0,
+            })
+        );
+
+        // sprite 4 is behind the opaque background
+        assert_eq!(
+            ppu.pixel_source(25, 9),
+            Some(PixelSource {
+                layer: PixelLayer::Background,
+                tile: 1,
+                tile_address: 0x8012,
+                palette: bgp,
+                color: 1,
+                attributes: 0,
+            })
+        );
+
+        let sprite_3 = PixelSource {
+            layer: PixelLayer::Sprite(3),
+            tile: 2,
+            tile_address: 0x8022,
+            palette: 0xE4,
+            color: 3,
+            attributes: 0x10,
+        };
+        assert_eq!(ppu.pixel_source(17, 9), Some(sprite_3));
+        // sprite 3 has priority over sprite 4, for having a lower x
+        assert_eq!(ppu.pixel_source(21, 9), Some(sprite_3));
+        assert_eq!(
+            ppu.pixel_source(20, 9).unwrap().to_string(),
+            "sprite 3, tile 02 at 8022, OBP1 e4, color 3, attributes 10"
+        );
+
+        assert_eq!(
+            ppu.pixel_source(27, 8).unwrap().layer,
+            PixelLayer::Background
+        );
+        assert_eq!(ppu.pixel_source(160, 0), None);
+    }
+}
+
+#[test]
+fn kept_after_reset() {
+    let mut gb = setup(AccuracyConfig::default());
+    gb.ppu.get_mut().set_pixel_sources_enabled(true);
+    gb.reset();
+    assert!(gb.ppu.borrow().pixel_sources_enabled());
+    gb.ppu.get_mut().set_pixel_sources_enabled(false);
+    assert_eq!(gb.ppu.borrow().pixel_source(0, 0), None);
+}
@@ -0,0 +1,58 @@
+use gameroy::gameboy::{
+    accuracy::AccuracyConfig,
+    cartridge::{Cartridge, CartridgeHeader, RomValidation},
+    ram_fill::RamFill,
+    GameBoy,
+};
+
+/// A MBC1 cartridge with 8 KiB of RAM, of the given cartridge type.
+fn game_boy(cartridge_type: u8) -> GameBoy {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x147] = cartridge_type;
+    rom[0x149] = 0x02; // 8 KiB
+    rom[0x14D] = CartridgeHeader::compute_check_sum(&rom);
+    let checksum = RomValidation::compute_global_checksum(&rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+    let mut cartridge = Cartridge::new(rom).unwrap();
+    cartridge.ram.fill(0x55);
+    GameBoy::new(None, cartridge, AccuracyConfig::default())
+}
+
+#[test]
+fn reset_keeps_cartridge_ram() {
+    let mut gb = game_boy(0x02); // MBC1+RAM
+    gb.reset();
+    assert_eq!(gb.cartridge.ram, vec![0x55; 0x2000]);
+}
+
+#[test]
+fn power_cycle_clears_ram_without_battery() {
+    let mut gb = game_boy(0x02); // MBC1+RAM
+    gb.power_cycle(RamFill::Zeros);
+    assert_eq!(gb.ram_fill, RamFill::Zeros);
+    assert_eq!(gb.cartridge.ram, vec![0x00; 0x2000]);
+    assert!(gb.wram.iter().all(|&x| x == 0x00));
+}
+
+#[test]
+fn power_cycle_keeps_battery_backed_ram() {
+    let mut gb = game_boy(0x03); // MBC1+RAM+BATTERY
+    gb.power_cycle(RamFill::Random(1));
+    assert_eq!(gb.cartridge.ram, vec![0x55; 0x2000]);
+
+    // a new seed gives a different content.
+    let wram = gb.wram;
+    gb.power_cycle(RamFill::Random(2));
+    assert_ne!(gb.wram, wram);
+}
+
+#[test]
+fn huc3_has_battery() {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x147] = 0xFE; // HuC3
+    rom[0x14D] = CartridgeHeader::compute_check_sum(&rom);
+    let header = CartridgeHeader::from_bytes(&rom)
+        .map_err(|(_, err)| err)
+        .unwrap();
+    assert!(header.has_battery());
+}
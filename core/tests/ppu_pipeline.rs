@@ -0,0 +1,95 @@
+use gameroy::{
+    consts::FRAME_CYCLES,
+    debugger::Debugger,
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+};
+
+/// A halted GameBoy, with the PPU emulated dot by dot, after drawing a frame with `scx`.
+fn gameboy(scx: u8) -> GameBoy {
+    let cartridge = Cartridge::halt_filled();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::ACCURATE);
+    gb.ppu.get_mut().oam = [0; 0xA0];
+    gb.write(0xFF43, scx);
+    let mut debugger = Debugger::default();
+    debugger.run_for(&mut gb, FRAME_CYCLES);
+    gb
+}
+
+/// Advance the clock until the pixel pipeline starts in the scanline `ly`, a few cycles after
+/// STAT changes to mode 3.
+fn run_to_mode_3(gb: &mut GameBoy, ly: u8) {
+    while gb.read(0xFF44) != ly || gb.read(0xFF41) & 0b11 != 3 {
+        gb.clock_count += 1;
+    }
+    gb.update_all();
+    while gb.ppu.borrow().pipeline().discarding == 0 {
+        gb.clock_count += 1;
+        gb.update_all();
+    }
+}
+
+#[test]
+fn discarding() {
+    let mut gb = gameboy(5);
+    run_to_mode_3(&mut gb, 40);
+
+    let ppu = gb.ppu.borrow();
+    assert_eq!(ppu.screen_x, 0);
+    // the first pixel is discarded in the same cycle that the pipeline starts.
+    assert_eq!(ppu.pipeline().discarding, 8 + 5 - 1);
+    assert!(!ppu.pipeline().is_in_window);
+    drop(ppu);
+
+    // the pixels are discarded one by one, before the first one is drawn.
+    let mut last = 8 + 5;
+    while gb.ppu.borrow().screen_x == 0 {
+        gb.clock_count += 1;
+        gb.update_all();
+        let discarding = gb.ppu.borrow().pipeline().discarding;
+        assert!(discarding <= last);
+        last = discarding;
+    }
+    assert_eq!(last, 0);
+
+    // the fetcher keeps the background FIFO filled, with the tile it fetched last.
+    let ppu = gb.ppu.borrow();
+    assert!(!ppu.background_fifo.is_empty());
+    assert!(ppu.pipeline().fetcher_step < 8);
+    assert!(ppu.background_fifo.iter().all(|x| x < 4));
+}
+
+#[test]
+fn window() {
+    let mut gb = gameboy(0);
+    {
+        let ppu = gb.ppu.get_mut();
+        // enable the window, at the scanline 0 and from the screen x 80.
+        ppu.lcdc |= 0x20;
+        ppu.wy = 0;
+        ppu.wx = 80 + 7;
+    }
+    let mut debugger = Debugger::default();
+    debugger.run_for(&mut gb, FRAME_CYCLES);
+    run_to_mode_3(&mut gb, 30);
+
+    let pipeline = gb.ppu.borrow().pipeline();
+    assert!(pipeline.reach_window);
+    assert!(!pipeline.is_in_window);
+    assert!(!pipeline.is_window_being_fetched);
+
+    // the window is activated at WX, and the fetcher restarts at its first tile.
+    let mut activated_at = None;
+    while gb.ppu.borrow().screen_x < 90 {
+        gb.clock_count += 1;
+        gb.update_all();
+        let ppu = gb.ppu.borrow();
+        if ppu.pipeline().is_window_being_fetched && activated_at.is_none() {
+            activated_at = Some(ppu.screen_x);
+        }
+    }
+    assert_eq!(activated_at, Some(80));
+    let pipeline = gb.ppu.borrow().pipeline();
+    assert!(pipeline.is_in_window);
+    assert!(!pipeline.is_window_being_fetched);
+    assert_eq!(pipeline.fetcher_x, 2);
+}
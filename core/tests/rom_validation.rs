@@ -0,0 +1,54 @@
+use gameroy::gameboy::cartridge::{Cartridge, CartridgeHeader, RomValidation};
+
+/// A valid rom, with correct header and global checksums.
+fn valid_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x14D] = CartridgeHeader::compute_check_sum(&rom);
+    let checksum = RomValidation::compute_global_checksum(&rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+    rom
+}
+
+#[test]
+fn valid_rom_has_no_warnings() {
+    let cartridge = Cartridge::new(valid_rom()).unwrap();
+    let validation = cartridge.validation();
+    assert!(validation.is_ok(), "{:?}", validation.warnings());
+}
+
+#[test]
+fn patched_rom() {
+    let mut rom = valid_rom();
+    rom[0x200] ^= 0xFF;
+    let cartridge = Cartridge::new(rom).unwrap();
+    let validation = cartridge.validation();
+    assert_ne!(validation.global_checksum.0, validation.global_checksum.1);
+    assert_eq!(validation.warnings().len(), 1);
+}
+
+#[test]
+fn bad_header_checksum() {
+    let mut rom = valid_rom();
+    rom[0x14D] = rom[0x14D].wrapping_add(1);
+    let Err((_, Some(cartridge))) = Cartridge::new(rom) else {
+        panic!("expected a error with a cartridge");
+    };
+    let validation = cartridge.validation();
+    assert_ne!(validation.header_checksum.0, validation.header_checksum.1);
+    assert!(!validation.is_ok());
+}
+
+#[test]
+fn overdump() {
+    let mut rom = valid_rom();
+    rom.resize(rom.len() + 0x4000, 0xFF);
+    // the size mismatch is also reported as a loading error.
+    let Err((_, Some(cartridge))) = Cartridge::new(rom) else {
+        panic!("expected a error with a cartridge");
+    };
+    let validation = cartridge.validation();
+    assert_eq!(validation.size, (3 * 0x4000, Some(2 * 0x4000)));
+    // the global checksum only covers the declared size.
+    assert_eq!(validation.global_checksum.0, validation.global_checksum.1);
+    assert_eq!(validation.warnings().len(), 1);
+}
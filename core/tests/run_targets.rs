@@ -0,0 +1,108 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::{
+    consts::FRAME_CYCLES,
+    debugger::{Debugger, RunResult},
+};
+
+/// A rom that loops forever, with the LCD on.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x18, 0xFE,       // 0x150: jr 0x150
+    ];
+    rom_with_program(&program)
+}
+
+#[test]
+fn run_to_scanline() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+
+    debugger.execute_command(&gb, &["runline", "100"]).unwrap();
+    let timeout = gb.clock_count + 2 * FRAME_CYCLES;
+    assert_eq!(
+        debugger.run_until(&mut gb, timeout),
+        RunResult::ReachTargetLine
+    );
+    assert_eq!(gb.read(0xFF44), 100);
+    let first = gb.clock_count;
+
+    // already in the line, so it stops when it is reached again, in the next frame.
+    debugger.execute_command(&gb, &["runline", "100"]).unwrap();
+    let timeout = gb.clock_count + 2 * FRAME_CYCLES;
+    assert_eq!(
+        debugger.run_until(&mut gb, timeout),
+        RunResult::ReachTargetLine
+    );
+    assert_eq!(gb.read(0xFF44), 100);
+    // `jr` takes 12 cycles, so the stop can be up to one instruction after the line start.
+    let elapsed = gb.clock_count - first;
+    assert!((FRAME_CYCLES - 12..=FRAME_CYCLES + 12).contains(&elapsed));
+
+    assert!(debugger.execute_command(&gb, &["runline", "154"]).is_err());
+    assert!(debugger.execute_command(&gb, &["runline"]).is_err());
+}
+
+#[test]
+fn run_to_clock() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+
+    let target = gb.clock_count + 1000;
+    debugger
+        .execute_command(&gb, &["runclock", &target.to_string()])
+        .unwrap();
+    assert_eq!(
+        debugger.run_until(&mut gb, u64::MAX),
+        RunResult::ReachTargetClock
+    );
+    assert!((target..target + 12).contains(&gb.clock_count));
+
+    let past = (gb.clock_count - 1).to_string();
+    assert!(debugger.execute_command(&gb, &["runclock", &past]).is_err());
+    assert!(debugger.execute_command(&gb, &["runclock", "foo"]).is_err());
+}
+
+#[test]
+fn find_last_break() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+
+    // `jr 0x150` jumps to itself, so the breakpoint is hit every 12 cycles.
+    debugger
+        .execute_command(&gb, &["break", "x", "0150"])
+        .unwrap();
+    assert_eq!(
+        debugger.run_until(&mut gb, u64::MAX),
+        RunResult::ReachBreakpoint
+    );
+    let first = gb.clock_count;
+    debugger.step(&mut gb);
+    assert_eq!(
+        debugger.run_until(&mut gb, u64::MAX),
+        RunResult::ReachBreakpoint
+    );
+    let current = gb.clock_count;
+    assert_eq!(current, first + 24);
+
+    gb.load_state(&mut &state[..]).unwrap();
+    assert_eq!(debugger.find_last_break(&mut gb, first), None);
+    gb.load_state(&mut &state[..]).unwrap();
+    let last = debugger.find_last_break(&mut gb, current);
+    assert_eq!(last, Some(first + 12));
+
+    // replay to the breakpoint, ignoring the ones before it.
+    gb.load_state(&mut &state[..]).unwrap();
+    debugger.target_clock = last;
+    debugger.skip_breakpoints_until_target_clock = true;
+    assert_eq!(
+        debugger.run_until(&mut gb, u64::MAX),
+        RunResult::ReachTargetClock
+    );
+    assert_eq!(gb.clock_count, first + 12);
+    assert_eq!(gb.cpu.pc, 0x150);
+}
@@ -0,0 +1,51 @@
+use gameroy::gameboy::cartridge::{Cartridge, CartridgeHeader, RomValidation};
+
+/// A MBC1 cartridge with 8 KiB of battery backed RAM.
+fn cartridge_with_ram() -> Cartridge {
+    let mut rom = Cartridge::halt_filled().rom;
+    rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x149] = 0x02; // 8 KiB
+    rom[0x14D] = CartridgeHeader::compute_check_sum(&rom);
+    let checksum = RomValidation::compute_global_checksum(&rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+    Cartridge::new(rom).unwrap()
+}
+
+#[test]
+fn exact_size() {
+    let mut cartridge = cartridge_with_ram();
+    cartridge.load_ram(vec![0x55; 0x2000]).unwrap();
+    assert_eq!(cartridge.ram, vec![0x55; 0x2000]);
+    assert!(cartridge.validation().warnings().is_empty());
+}
+
+#[test]
+fn small_save_is_padded() {
+    let mut cartridge = cartridge_with_ram();
+    cartridge.load_ram(vec![0x55; 0x800]).unwrap();
+    assert_eq!(cartridge.ram.len(), 0x2000);
+    assert_eq!(cartridge.ram[0x7FF], 0x55);
+    assert_eq!(cartridge.ram[0x800], 0x00);
+    assert_eq!(cartridge.validation().save_size, Some((0x800, 0x2000)));
+    assert_eq!(cartridge.validation().warnings().len(), 1);
+}
+
+#[test]
+fn rtc_footer_is_truncated() {
+    let mut cartridge = cartridge_with_ram();
+    cartridge.load_ram(vec![0x55; 0x2000 + 48]).unwrap();
+    assert_eq!(cartridge.ram, vec![0x55; 0x2000]);
+    assert_eq!(cartridge.validation().warnings().len(), 1);
+}
+
+#[test]
+fn wrong_files_are_refused() {
+    let mut cartridge = cartridge_with_ram();
+    assert!(cartridge.load_ram(vec![0x55; 0x100000]).is_err());
+    assert_eq!(cartridge.ram, vec![0x00; 0x2000]);
+    assert_eq!(cartridge.validation().save_size, None);
+
+    let mut cartridge = Cartridge::halt_filled();
+    assert!(cartridge.load_ram(vec![0x55; 0x2000]).is_err());
+    assert!(cartridge.load_ram(Vec::new()).is_ok());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{game_boy, rom_with_program};
+use gameroy::interpreter::Interpreter;
+
+/// A rom that send 0x42 through the serial port, and write the received byte to 0xC000.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x42,       // 0x150: ld a, 0x42
+        0xE0, 0x01,       // 0x152: ldh (0x01), a   ; SB
+        0x3E, 0x81,       // 0x154: ld a, 0x81
+        0xE0, 0x02,       // 0x156: ldh (0x02), a   ; SC, start transfer with internal clock
+        // wait:
+        0xF0, 0x02,       // 0x158: ldh a, (0x02)
+        0xCB, 0x7F,       // 0x15A: bit 7, a
+        0x20, 0xFA,       // 0x15C: jr nz, wait
+        0xF0, 0x01,       // 0x15E: ldh a, (0x01)
+        0xEA, 0x00, 0xC0, // 0x160: ld (0xC000), a
+        0x18, 0xFE,       // 0x163: jr 0x163
+    ];
+    rom_with_program(&program)
+}
+
+/// Run the test rom, with the given bytes in the stubbed link partner. Returns the bytes sent
+/// and the byte received.
+fn run(link_partner: &[u8]) -> (Vec<u8>, u8) {
+    let mut gb = game_boy(test_rom());
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    {
+        let serial = gb.serial.get_mut();
+        serial.link_partner.extend(link_partner);
+        let sent = sent.clone();
+        serial.serial_transfer_callback = Some(Box::new(move |c| sent.lock().unwrap().push(c)));
+    }
+
+    let target = gb.clock_count + 20_000;
+    while gb.clock_count < target {
+        Interpreter(&mut gb).interpret_op();
+    }
+
+    let sent = sent.lock().unwrap().clone();
+    (sent, gb.read(0xC000))
+}
+
+#[test]
+fn no_link_partner() {
+    assert_eq!(run(&[]), (vec![0x42], 0x42));
+}
+
+#[test]
+fn receive_from_link_partner() {
+    assert_eq!(run(&[0x99, 0x11]), (vec![0x42], 0x99));
+}
@@ -0,0 +1,100 @@
+use gameroy::{
+    consts::{CLOCK_SPEED, FRAME_CYCLES},
+    gameboy::sound_controller::{sample_count, FilterMode, SoundController},
+};
+
+/// The clock count when the sound starts being played.
+const START: u64 = 100;
+
+/// A sound controller playing a square wave on channel 2, or powered off if `on` is false.
+fn sound(sample_frequency: u64, on: bool) -> SoundController {
+    let mut sound = SoundController::default();
+    sound.sample_frequency = sample_frequency;
+    sound.filter_mode = FilterMode::Raw;
+    // the sound controller is never updated to clock 0 in a GameBoy.
+    sound.last_clock_count = START;
+    if on {
+        let writes = [
+            (0x26, 0x80), // NR52: power on
+            (0x24, 0x77), // NR50: max volume
+            (0x25, 0x22), // NR51: channel 2 in both sides
+            (0x16, 0x80), // NR21: 50% duty
+            (0x17, 0xF0), // NR22: volume 15, no envelope
+            (0x18, 0x00), // NR23: frequency low
+            (0x19, 0x87), // NR24: trigger, frequency 0x700 (512 Hz)
+        ];
+        for (i, (address, value)) in writes.into_iter().enumerate() {
+            sound.write(START + 4 * i as u64, address, value);
+        }
+    }
+    sound
+}
+
+#[test]
+fn count_of_one_second() {
+    for fs in [8000, 22050, 44100, 48000, 96000] {
+        assert_eq!(sample_count(fs, 0, CLOCK_SPEED), fs);
+        assert_eq!(sample_count(fs, 5 * CLOCK_SPEED, 6 * CLOCK_SPEED), fs);
+    }
+    assert_eq!(sample_count(48000, 10, 10), 0);
+    assert_eq!(sample_count(48000, 10, 5), 0);
+}
+
+#[test]
+fn frames_add_up() {
+    let fs = 44100;
+    let frames = 60 * 60;
+    let start = 12345;
+    let counts = (0..frames)
+        .map(|i| {
+            let frame_start = start + i * FRAME_CYCLES;
+            sample_count(fs, frame_start, frame_start + FRAME_CYCLES)
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        counts.iter().sum::<u64>(),
+        sample_count(fs, start, start + frames * FRAME_CYCLES)
+    );
+    // 44100 Hz at 59.73 frames per second is between 738 and 739 samples per frame.
+    assert!(counts.iter().all(|&x| x == 738 || x == 739));
+}
+
+#[test]
+fn output_matches_count() {
+    for on in [true, false] {
+        for fs in [22050, 44100, 48000] {
+            let mut sound = sound(fs, on);
+            let mut clock_count = sound.last_clock_count;
+            // frames with a odd length, to not always end in a even clock count.
+            for _ in 0..120 {
+                let next = clock_count + FRAME_CYCLES + 1;
+                let expected = sample_count(fs, clock_count, next);
+                assert_eq!(sound.samples_until(next), expected);
+                assert_eq!(sound.get_output(next).len() as u64, 2 * expected);
+                clock_count = next;
+            }
+        }
+    }
+}
+
+#[test]
+fn output_independent_of_updates() {
+    let end = START + 30 * FRAME_CYCLES;
+
+    let mut whole = sound(48000, true);
+    let whole = whole.get_output(end);
+
+    let mut split = sound(48000, true);
+    let mut output = Vec::new();
+    let mut clock_count = split.last_clock_count;
+    let mut step = 1;
+    while clock_count < end {
+        clock_count = (clock_count + step).min(end);
+        output.extend(split.get_output(clock_count));
+        step = (step * 7 + 3) % 5000;
+    }
+
+    assert_eq!(output.len(), whole.len());
+    assert_eq!(output, whole);
+}
@@ -0,0 +1,88 @@
+use gameroy::{
+    consts::CLOCK_SPEED,
+    gameboy::sound_controller::{FilterMode, SoundController},
+};
+
+/// The amplitude of channel 2 at full volume.
+const CH2_HIGH: u16 = 15;
+
+/// The clock count when the sound starts being played.
+const START: u64 = 100;
+
+/// Play a square wave on channel 2, with the given NR50 and NR51, and return the raw output.
+fn sound_with(nr50: u8, nr51: u8) -> SoundController {
+    let mut sound = SoundController::default();
+    sound.sample_frequency = 48000;
+    sound.filter_mode = FilterMode::Raw;
+    // the sound controller is never updated to clock 0 in a GameBoy.
+    sound.last_clock_count = START;
+    let writes = [
+        (0x26, 0x80), // NR52: power on
+        (0x24, nr50),
+        (0x25, nr51),
+        (0x16, 0x80), // NR21: 50% duty
+        (0x17, 0xF0), // NR22: volume 15, no envelope
+        (0x18, 0x00), // NR23: frequency low
+        (0x19, 0x87), // NR24: trigger, frequency 0x700 (512 Hz)
+    ];
+    for (i, (address, value)) in writes.into_iter().enumerate() {
+        sound.write(START + 4 * i as u64, address, value);
+    }
+    sound
+}
+
+fn play(nr50: u8, nr51: u8) -> Vec<(u16, u16)> {
+    let output = sound_with(nr50, nr51).get_output(START + CLOCK_SPEED / 10);
+    assert!(!output.is_empty());
+    output.chunks_exact(2).map(|x| (x[0], x[1])).collect()
+}
+
+/// Assert that every sample is either silent or `high`, and that the wave is not silent.
+fn assert_square(samples: impl Iterator<Item = u16> + Clone, high: u16) {
+    assert!(samples.clone().all(|x| x == 0 || x == high));
+    assert!(samples.clone().any(|x| x == high));
+}
+
+#[test]
+fn left_only() {
+    let samples = play(0x77, 0x20);
+    assert_square(samples.iter().map(|x| x.0), CH2_HIGH * 8);
+    assert!(samples.iter().all(|x| x.1 == 0));
+}
+
+#[test]
+fn right_only() {
+    let samples = play(0x77, 0x02);
+    assert!(samples.iter().all(|x| x.0 == 0));
+    assert_square(samples.iter().map(|x| x.1), CH2_HIGH * 8);
+}
+
+#[test]
+fn unrouted_channel_is_silent() {
+    let samples = play(0x77, 0xDD);
+    assert!(samples.iter().all(|&x| x == (0, 0)));
+}
+
+#[test]
+fn master_volume() {
+    // the volume of each side goes from 1/8 to 8/8, so a volume of 0 is not silent.
+    let samples = play(0x30, 0x22);
+    assert_square(samples.iter().map(|x| x.0), CH2_HIGH * 4);
+    assert_square(samples.iter().map(|x| x.1), CH2_HIGH);
+}
+
+#[test]
+fn vin_is_ignored() {
+    assert_eq!(play(0xF5, 0x22), play(0x75, 0x22));
+}
+
+#[test]
+fn stereo_output() {
+    let raw = play(0x72, 0x22);
+    let stereo = sound_with(0x72, 0x22).get_stereo_output(START + CLOCK_SPEED / 10);
+    assert_eq!(raw.len(), stereo.len());
+    for ((left, right), [l, r]) in raw.into_iter().zip(stereo) {
+        assert_eq!(l, (left as i16 - 128) * 30);
+        assert_eq!(r, (right as i16 - 128) * 30);
+    }
+}
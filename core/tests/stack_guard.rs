@@ -0,0 +1,84 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    debugger::{Debugger, RunResult},
+    gameboy::GameBoy,
+};
+
+/// A rom that runs `program` at 0x150.
+fn game_boy(program: &[u8]) -> GameBoy {
+    common::game_boy(rom_with_program(program))
+}
+
+#[test]
+fn push_into_region() {
+    #[rustfmt::skip]
+    let mut gb = game_boy(&[
+        0x31, 0x10, 0xC0, // 0x150: ld sp, 0xC010
+        0xC5,             // 0x153: push bc
+        0x18, 0xFD,       // 0x154: jr 0x153
+    ]);
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&gb, &["break", "stack", "c000", "c007"])
+        .unwrap();
+
+    assert_eq!(
+        debugger.run_for(&mut gb, 10_000),
+        RunResult::ReachBreakpoint
+    );
+    // stops before the fifth push, which would write to c007.
+    assert_eq!(gb.cpu.pc, 0x153);
+    assert_eq!(gb.cpu.sp, 0xC008);
+    let log = debugger.take_trace_log();
+    assert_eq!(log, ["stack guard: 0153 pushes into c007, in c000-c007"]);
+
+    debugger
+        .execute_command(&gb, &["unbreak", "stack"])
+        .unwrap();
+    assert_eq!(debugger.run_for(&mut gb, 1000), RunResult::TimeOut);
+}
+
+#[test]
+fn write_to_top_of_stack() {
+    #[rustfmt::skip]
+    let mut gb = game_boy(&[
+        0x31, 0x10, 0xC0, // 0x150: ld sp, 0xC010
+        0xC5,             // 0x153: push bc
+        0x21, 0x00, 0xC0, // 0x154: ld hl, 0xC000
+        0x77,             // 0x157: ld (hl), a
+        0x21, 0x0F, 0xC0, // 0x158: ld hl, 0xC00F
+        0x77,             // 0x15B: ld (hl), a
+        0x18, 0xFE,       // 0x15C: jr 0x15C
+    ]);
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&gb, &["break", "stack", "c000", "c007"])
+        .unwrap();
+
+    // the write to c000 is not a push, and is far from SP.
+    assert_eq!(
+        debugger.run_for(&mut gb, 10_000),
+        RunResult::ReachBreakpoint
+    );
+    assert_eq!(gb.cpu.pc, 0x15B);
+    let log = debugger.take_trace_log();
+    assert_eq!(log, ["stack guard: 015b writes to c00f, at SP+1"]);
+}
+
+#[test]
+fn commands() {
+    let gb = game_boy(&[0x18, 0xFE]);
+    let mut debugger = Debugger::default();
+    assert!(debugger
+        .execute_command(&gb, &["break", "stack", "c007", "c000"])
+        .is_err());
+    assert!(debugger
+        .execute_command(&gb, &["break", "stack", "c000"])
+        .is_err());
+    debugger
+        .execute_command(&gb, &["break", "stack", "c000", "c0ff"])
+        .unwrap();
+    assert_eq!(debugger.session_commands(), ["break stack c000 c0ff"]);
+}
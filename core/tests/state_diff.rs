@@ -0,0 +1,90 @@
+mod common;
+
+use common::rom_with_program;
+use gameroy::{
+    debugger::{state_diff::diff_states, Debugger},
+    gameboy::GameBoy,
+};
+
+/// A rom that writes 0x42 to c0a0, c0a1 and c0a5, and then loops forever.
+fn game_boy() -> GameBoy {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x42,       // 0x150: ld a, 0x42
+        0xEA, 0xA0, 0xC0, // 0x152: ld (0xC0A0), a
+        0xEA, 0xA1, 0xC0, // 0x155: ld (0xC0A1), a
+        0xEA, 0xA5, 0xC0, // 0x158: ld (0xC0A5), a
+        0x18, 0xFE,       // 0x15B: jr 0x15B
+    ];
+    let mut gb = common::game_boy(rom_with_program(&program));
+    gb.wram.fill(0);
+    gb
+}
+
+fn save_state(gb: &GameBoy) -> Vec<u8> {
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+    state
+}
+
+#[test]
+fn diff_memory_and_registers() {
+    let mut gb = game_boy();
+    let before = save_state(&gb);
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["runto", "015b"]).unwrap();
+    debugger.run_until(&mut gb, u64::MAX);
+    let after = save_state(&gb);
+
+    let diff = diff_states(&gb, &before, &before).unwrap();
+    assert!(diff.is_empty());
+
+    let diff = diff_states(&gb, &before, &after).unwrap();
+    let names: Vec<_> = diff.registers.iter().map(|x| x.name).collect();
+    assert!(names.contains(&"a"));
+    assert!(names.contains(&"pc"));
+
+    let wram = diff.regions.iter().find(|x| x.name == "wram").unwrap();
+    // the equal bytes between c0a1 and c0a5 are in the same range.
+    assert_eq!(wram.ranges.len(), 1);
+    assert_eq!(wram.location(wram.ranges[0].offset), (None, 0xC0A0));
+    assert_eq!(wram.ranges[0].before, [0; 6]);
+    assert_eq!(wram.ranges[0].after, [0x42, 0x42, 0, 0, 0, 0x42]);
+
+    let text = diff.to_string();
+    assert!(text.contains("pc: 0100 -> 015b"));
+    assert!(text.contains("wram: 6 bytes in 1 range\n"));
+    assert!(text.contains("  c0a0-c0a5: 00 00 00 00 00 00 -> 42 42 00 00 00 42"));
+}
+
+#[test]
+fn statediff_command() {
+    let mut gb = game_boy();
+    let path = std::env::temp_dir().join("gameroy_statediff_test.state");
+    std::fs::write(&path, save_state(&gb)).unwrap();
+
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["runto", "015b"]).unwrap();
+    debugger.run_until(&mut gb, u64::MAX);
+
+    let path_str = path.to_str().unwrap();
+    debugger
+        .execute_command(&gb, &["statediff", path_str])
+        .unwrap();
+    let log = debugger.take_trace_log();
+    assert!(log.iter().any(|x| x == "pc: 0100 -> 015b"));
+
+    debugger
+        .execute_command(&gb, &["statediff", path_str, path_str])
+        .unwrap();
+    assert_eq!(
+        debugger.take_trace_log().last().unwrap(),
+        "the states are equal"
+    );
+
+    assert!(debugger
+        .execute_command(&gb, &["statediff", "/nonexistent/file"])
+        .is_err());
+    assert!(debugger.execute_command(&gb, &["statediff"]).is_err());
+    std::fs::remove_file(path).unwrap();
+}
@@ -8,7 +8,7 @@ use std::{
 
 use gameroy::{
     consts::{CLOCK_SPEED, SCREEN_HEIGHT, SCREEN_WIDTH},
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, boot_rom::BootRom, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 use rand::{Rng, SeedableRng};
@@ -21,6 +21,10 @@ const TEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/gameboy-
 // const BOOT_ROM: Option<[u8; 256]> = Some(*include_bytes!("../../boot/dmg_boot.bin"));
 const BOOT_ROM: Option<[u8; 256]> = None;
 
+fn boot_rom() -> Option<BootRom> {
+    BOOT_ROM.map(BootRom::from)
+}
+
 macro_rules! log {
     ($rom:expr, $str:literal $($t:tt)*) => {
         println!(concat!("\"{}\" ", $str), $rom $($t)*);
@@ -74,7 +78,7 @@ fn test_screen(romstr: &str, reference: &str, timeout: u64) {
 
     let cartridge = Cartridge::new(rom).unwrap();
 
-    let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
     let screen = Arc::new(Mutex::new([0u8; SCREEN_WIDTH * SCREEN_HEIGHT]));
     let matched = Arc::new(AtomicBool::new(false));
@@ -160,7 +164,7 @@ fn test_registers(romstr: &str, timeout: u64) {
 
     let cartridge = Cartridge::new(rom).unwrap();
 
-    let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
     let screen: Arc<Mutex<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>> =
         Arc::new(Mutex::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]));
     game_boy.v_blank = Some(Box::new(move |gb| {
@@ -279,7 +283,7 @@ mod blargg {
 
         let cartridge = Cartridge::new(rom).unwrap();
 
-        let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+        let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
         let string = Arc::new(Mutex::new(String::new()));
         let string_clone = string.clone();
@@ -321,7 +325,7 @@ mod blargg {
 
         let cartridge = Cartridge::new(rom).unwrap();
 
-        let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+        let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
         let mut inter = Interpreter(&mut game_boy);
         while inter.0.clock_count < timeout {
@@ -378,7 +382,7 @@ fn save_state1() {
     let rom = std::fs::read(&romstr).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
-    let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
     let mut inter = Interpreter(&mut game_boy);
     let timeout = 250_400_000;
@@ -402,7 +406,7 @@ fn save_state1() {
 
         // load state
         let cartridge = Cartridge::new(rom.clone()).unwrap();
-        let mut gb = GameBoy::new(BOOT_ROM, cartridge);
+        let mut gb = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
         gb.load_state(&mut Cursor::new(&mut vec)).unwrap();
 
         // compare
@@ -424,7 +428,7 @@ fn save_state2() {
     let rom = std::fs::read(&romstr).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
-    let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
     let mut inter = Interpreter(&mut game_boy);
     let timeout = 250_400_000;
@@ -448,7 +452,7 @@ fn save_state2() {
         // load state
         use std::io::Cursor;
         let cartridge = Cartridge::new(rom.clone()).unwrap();
-        let mut gb = GameBoy::new(BOOT_ROM, cartridge);
+        let mut gb = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
         gb.load_state(&mut Cursor::new(&mut save_state)).unwrap();
 
         // run same number of instructions
@@ -473,7 +477,7 @@ fn save_state3() {
     let rom = std::fs::read(&romstr).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
-    let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
 
     let v_blank_state = Arc::new(Mutex::new(None));
 
@@ -508,7 +512,7 @@ fn save_state3() {
         if let Some(save_state) = v_blank_state.lock().unwrap().take() {
             use std::io::Cursor;
             let cartridge = Cartridge::new(rom.clone()).unwrap();
-            let mut gb = GameBoy::new(BOOT_ROM, cartridge);
+            let mut gb = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
             gb.load_state(&mut Cursor::new(save_state)).unwrap();
 
             // run to the current state
@@ -908,7 +912,7 @@ mod age {
 
         let cartridge = Cartridge::new(rom).unwrap();
 
-        let mut game_boy = GameBoy::new(BOOT_ROM, cartridge);
+        let mut game_boy = GameBoy::new(boot_rom(), cartridge, AccuracyConfig::default());
         let screen: Arc<Mutex<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>> =
             Arc::new(Mutex::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]));
         game_boy.v_blank = Some(Box::new(move |gb| {
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::debugger::{parse_trace_format, Debugger, TraceRegister, TraceSegment};
+
+/// A rom that keep incrementing the A register and writing it to 0xC000.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xAF,             // 0x150: xor a
+        // loop:
+        0x3C,             // 0x151: inc a
+        0xEA, 0x00, 0xC0, // 0x152: ld (0xC000), a
+        0x18, 0xFA,       // 0x155: jr loop
+    ];
+    rom_with_program(&program)
+}
+
+#[test]
+fn parse_format() {
+    use TraceSegment::*;
+    assert_eq!(
+        parse_trace_format("a={a} hl={HL} [c000]={[c000]} [hl]={[hl]} at {clock}").unwrap(),
+        [
+            Text("a=".to_string()),
+            Register(TraceRegister::A),
+            Text(" hl=".to_string()),
+            Register(TraceRegister::HL),
+            Text(" [c000]=".to_string()),
+            Memory(0xC000),
+            Text(" [hl]=".to_string()),
+            MemoryAt(TraceRegister::HL),
+            Text(" at ".to_string()),
+            Clock,
+        ]
+    );
+    assert!(parse_trace_format("{a").is_err());
+    assert!(parse_trace_format("{x}").is_err());
+    assert!(parse_trace_format("{[a]}").is_err());
+}
+
+#[test]
+fn tracepoints_do_not_stop() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+
+    debugger
+        .execute_command(&gb, &["trace", "155", "a={a}", "[c000]={[c000]}"])
+        .unwrap();
+
+    let target = gb.clock_count + 1000;
+    debugger.run_until(&mut gb, target);
+    assert!(
+        gb.clock_count >= target,
+        "the tracepoint stopped the emulation"
+    );
+
+    let log = debugger.take_trace_log();
+    assert!(log.len() > 3);
+    for (i, message) in log.iter().enumerate() {
+        let a = i + 1;
+        assert_eq!(*message, format!("a={:02x} [c000]={:02x}", a, a));
+    }
+    assert!(debugger.take_trace_log().is_empty());
+
+    debugger.execute_command(&gb, &["untrace", "155"]).unwrap();
+    let target = gb.clock_count + 1000;
+    debugger.run_until(&mut gb, target);
+    assert!(debugger.take_trace_log().is_empty());
+}
@@ -0,0 +1,52 @@
+use gameroy::gameboy::{cartridge::Cartridge, unusable_area::UnusableArea, GameBoy};
+
+fn game_boy(unusable_area: UnusableArea) -> GameBoy {
+    let cartridge = Cartridge::halt_filled();
+    let mut gb = GameBoy::builder(cartridge)
+        .unusable_area(unusable_area)
+        .build();
+    // turn the LCD off, so the OAM is not blocked by the PPU. The PPU is synchronized on the
+    // write, so it must be made after the last update.
+    gb.tick(4);
+    gb.write(0xFF40, 0x00);
+    gb
+}
+
+#[test]
+fn dmg_reads_zero() {
+    let mut gb = game_boy(UnusableArea::Dmg);
+    for address in 0xFEA0..=0xFEFF {
+        assert_eq!(gb.read(address), 0x00);
+    }
+    gb.write(0xFEA0, 0x12);
+    assert_eq!(gb.read(0xFEA0), 0x00);
+}
+
+#[test]
+fn cgb_reads_address_nibble() {
+    let gb = game_boy(UnusableArea::Cgb);
+    assert_eq!(gb.read(0xFEA0), 0xAA);
+    assert_eq!(gb.read(0xFEAF), 0xAA);
+    assert_eq!(gb.read(0xFEB5), 0xBB);
+    assert_eq!(gb.read(0xFEFF), 0xFF);
+}
+
+#[test]
+fn blocked_by_oam_dma() {
+    let mut gb = game_boy(UnusableArea::Dmg);
+    gb.write(0xFF46, 0xC0);
+    gb.tick(8);
+    assert_eq!(gb.read(0xFEA0), 0xFF);
+}
+
+#[test]
+fn blocked_by_ppu() {
+    let mut gb = game_boy(UnusableArea::Cgb);
+    gb.tick(4);
+    gb.write(0xFF40, 0x80);
+    // run until the PPU is in OAM scan (mode 2).
+    while gb.read(0xFF41) & 0b11 != 2 {
+        gb.tick(4);
+    }
+    assert_eq!(gb.read(0xFEB0), 0xFF);
+}
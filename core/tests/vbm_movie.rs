@@ -0,0 +1,215 @@
+mod common;
+
+use common::{game_boy, rom_with_program};
+use gameroy::{
+    consts::FRAME_CYCLES,
+    debugger::Debugger,
+    gameboy::GameBoy,
+    parser::{
+        vbm, write_vbm, Vbm, VbmParseError, VbmStart, VBM_START_FROM_SAVE_STATE,
+        VBM_START_FROM_SRAM, VBM_STATE_REFERENCE_MAGIC,
+    },
+};
+
+/// A rom that counts the frames where any button is pressed at C000.
+fn test_rom() -> Vec<u8> {
+    #[rustfmt::skip]
+    let program = [
+        0xF0, 0x44,       // 0x150: ldh a, (0x44)
+        0xFE, 0x90,       // 0x152: cp 0x90
+        0x20, 0xFA,       // 0x154: jr nz, 0x150
+        0x3E, 0x10,       // 0x156: ld a, 0x10
+        0xE0, 0x00,       // 0x158: ldh (0x00), a
+        0xF0, 0x00,       // 0x15A: ldh a, (0x00)
+        0xE6, 0x0F,       // 0x15C: and 0x0F
+        0xFE, 0x0F,       // 0x15E: cp 0x0F
+        0x28, 0x04,       // 0x160: jr z, 0x166
+        0x21, 0x00, 0xC0, // 0x162: ld hl, 0xC000
+        0x34,             // 0x165: inc (hl)
+        0xF0, 0x44,       // 0x166: ldh a, (0x44)
+        0xFE, 0x90,       // 0x168: cp 0x90
+        0x28, 0xFA,       // 0x16A: jr z, 0x166
+        0x18, 0xE2,       // 0x16C: jr 0x150
+    ];
+    rom_with_program(&program)
+}
+
+fn movie(start_flags: u8, start_data: Vec<u8>, controller_data: Vec<u16>) -> Vbm {
+    Vbm {
+        magic: 0x1A4D4256,
+        version: 1,
+        uid: 1234,
+        length_frames: controller_data.len() as u32 - 1,
+        rerecord_count: 5,
+        start_flags,
+        controller_flags: 0x01,
+        type_flags: 0x00,
+        options_flags: 0x00,
+        save_type: 0,
+        flash_size: 0,
+        gb_emulator_type: 0,
+        rom_title: *b"TEST ROM\0\0\0\0",
+        vbm_version: 1,
+        rom_crc: 0,
+        rom_or_bios_checksum: 0,
+        rom_game_code: 0,
+        name: "tester".to_string(),
+        description: "a test movie".to_string(),
+        start_data,
+        controller_data,
+    }
+}
+
+fn round_trip(movie: &Vbm) -> Vbm {
+    let mut file = Vec::new();
+    write_vbm(movie, &mut file).unwrap();
+    vbm(&mut std::io::Cursor::new(file)).unwrap()
+}
+
+#[test]
+fn power_on_round_trip() {
+    let movie = round_trip(&movie(0, Vec::new(), vec![0, 1, 2, 3]));
+    assert_eq!(movie.start(), VbmStart::PowerOn);
+    assert_eq!(movie.uid, 1234);
+    assert_eq!(movie.rerecord_count, 5);
+    assert_eq!(movie.name, "tester");
+    assert_eq!(movie.description, "a test movie");
+    assert_eq!(movie.controller_data, [0, 1, 2, 3]);
+}
+
+#[test]
+fn start_data() {
+    let ram = vec![0xAB; 0x2000];
+    let parsed = round_trip(&movie(VBM_START_FROM_SRAM, ram.clone(), vec![0; 3]));
+    assert_eq!(parsed.start(), VbmStart::Sram(&ram));
+    assert_eq!(parsed.controller_data, [0; 3]);
+
+    let mut reference = VBM_STATE_REFERENCE_MAGIC.to_vec();
+    reference.extend_from_slice(&0xDEADBEEFu32.to_le_bytes());
+    let parsed = round_trip(&movie(VBM_START_FROM_SAVE_STATE, reference, vec![0; 3]));
+    assert_eq!(parsed.start(), VbmStart::SaveStateReference(0xDEADBEEF));
+
+    let mut file = Vec::new();
+    write_vbm(&movie(0x03, vec![0; 4], vec![0; 2]), &mut file).unwrap();
+    assert!(vbm(&mut std::io::Cursor::new(file)).is_err());
+}
+
+fn parse(file: Vec<u8>) -> Result<Vbm, VbmParseError> {
+    vbm(&mut std::io::Cursor::new(file))
+}
+
+fn write(movie: &Vbm) -> Vec<u8> {
+    let mut file = Vec::new();
+    write_vbm(movie, &mut file).unwrap();
+    file
+}
+
+#[test]
+fn malformed_movies() {
+    let file = write(&movie(0, Vec::new(), vec![0; 4]));
+
+    let mut wrong_magic = file.clone();
+    wrong_magic[..4].copy_from_slice(b"GMV\x1A");
+    assert!(matches!(
+        parse(wrong_magic),
+        Err(VbmParseError::InvalidSignature(0x1A564D47))
+    ));
+    // a short file that is not a movie is reported as such, not as truncated.
+    assert!(matches!(
+        parse(b"PK".to_vec()),
+        Err(VbmParseError::Truncated("header"))
+    ));
+    assert!(matches!(
+        parse(b"PK\x03\x04".to_vec()),
+        Err(VbmParseError::InvalidSignature(_))
+    ));
+
+    let mut multiplayer = movie(0, Vec::new(), vec![0; 4]);
+    multiplayer.controller_flags = 0x03;
+    assert!(matches!(
+        parse(write(&multiplayer)),
+        Err(VbmParseError::UnsupportedControllerFlags(0x03))
+    ));
+    let mut gbc = movie(0, Vec::new(), vec![0; 4]);
+    gbc.type_flags = 0x02;
+    assert!(matches!(
+        parse(write(&gbc)),
+        Err(VbmParseError::UnsupportedSystemFlags(0x02))
+    ));
+    let mut future = movie(0, Vec::new(), vec![0; 4]);
+    future.vbm_version = 2;
+    assert!(matches!(
+        parse(write(&future)),
+        Err(VbmParseError::UnsupportedRevision(2))
+    ));
+
+    assert!(matches!(
+        parse(file[..0x80].to_vec()),
+        Err(VbmParseError::Truncated("header"))
+    ));
+    // 4 inputs for 3 frames, missing 2 of them.
+    assert!(matches!(
+        parse(file[..file.len() - 4].to_vec()),
+        Err(VbmParseError::Truncated("controller data"))
+    ));
+    let sram = write(&movie(VBM_START_FROM_SRAM, vec![0; 0x2000], vec![0; 4]));
+    assert!(matches!(
+        parse(sram[..0x180].to_vec()),
+        Err(VbmParseError::Truncated("controller data"))
+    ));
+}
+
+/// Revision 0 movies have no input for the frame after the last one.
+#[test]
+fn old_revision() {
+    let mut old = movie(0, Vec::new(), vec![1, 2, 3]);
+    old.vbm_version = 0;
+    old.length_frames = 3;
+    let parsed = parse(write(&old)).unwrap();
+    assert_eq!(parsed.vbm_version, 0);
+    assert_eq!(parsed.length_frames, 3);
+    assert_eq!(parsed.controller_data, [1, 2, 3, 0]);
+
+    // the name can fill the entire field, without a null terminator.
+    old.name = "a".repeat(64);
+    let mut file = write(&old);
+    file[0x40 + 63] = b'a';
+    assert_eq!(parse(file).unwrap().name, "a".repeat(64));
+}
+
+/// A movie recorded from a save state is played back from the same state, and reproduces the
+/// recording.
+#[test]
+fn start_from_save_state() {
+    let mut gb = game_boy(test_rom());
+    let mut debugger = Debugger::default();
+    let target = gb.clock_count + 10 * FRAME_CYCLES;
+    debugger.run_until(&mut gb, target);
+
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+    let start_clock = gb.clock_count;
+
+    // the movie has one input per frame, in the VBM format, where 1 is the A button.
+    let inputs: Vec<u16> = (0..30).map(|i| (i % 3 == 0) as u16).collect();
+    let run_movie = |gb: &mut GameBoy| {
+        for &input in &inputs {
+            gb.joypad = !(((input as u8) & 0x0F) << 4 | (input as u8) >> 4);
+            let target = gb.clock_count + FRAME_CYCLES;
+            Debugger::default().run_until(gb, target);
+        }
+        gb.read(0xC000)
+    };
+    let recorded = run_movie(&mut gb);
+    assert!(recorded > 0);
+
+    let movie = round_trip(&movie(VBM_START_FROM_SAVE_STATE, state, inputs.clone()));
+    let VbmStart::SaveState(state) = movie.start() else {
+        panic!("expected a embedded save state, found {:?}", movie.start());
+    };
+
+    let mut gb = game_boy(test_rom());
+    gb.load_state(&mut &state[..]).unwrap();
+    assert_eq!(gb.clock_count, start_clock);
+    assert_eq!(run_movie(&mut gb), recorded);
+}
@@ -0,0 +1,79 @@
+use gameroy::{
+    debugger::{expression::Expression, Debugger},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+};
+
+fn game_boy() -> GameBoy {
+    let mut gb = GameBoy::new(None, Cartridge::halt_filled(), AccuracyConfig::default());
+    gb.cpu.h = 0xC3;
+    gb.cpu.l = 0x40;
+    gb.cpu.a = 0x12;
+    gb.write(0xC345, 0x34);
+    gb.write(0xC346, 0x56);
+    gb
+}
+
+fn eval(gb: &GameBoy, text: &str) -> Result<u16, String> {
+    Expression::parse(text)?.eval(gb)
+}
+
+#[test]
+fn evaluate() {
+    let gb = game_boy();
+    assert_eq!(eval(&gb, "HL+2"), Ok(0xC342));
+    assert_eq!(eval(&gb, "[0xC345]"), Ok(0x34));
+    assert_eq!(eval(&gb, "[0xC345]:16"), Ok(0x5634));
+    assert_eq!(eval(&gb, "[hl + 5]:8"), Ok(0x34));
+    assert_eq!(eval(&gb, "[$c346]"), Ok(0x56));
+    assert_eq!(eval(&gb, "c345"), Ok(0xC345));
+    assert_eq!(eval(&gb, "a * 2 + 1"), Ok(0x25));
+    assert_eq!(eval(&gb, "(a + 1) << 4"), Ok(0x130));
+    assert_eq!(eval(&gb, "-1"), Ok(0xFFFF));
+    assert_eq!(eval(&gb, "a == 12 && [c345] != 0"), Ok(1));
+    assert_eq!(eval(&gb, "!a | ~0 & 0f"), Ok(0x0F));
+    assert!(eval(&gb, "a / 0").is_err());
+    assert!(eval(&gb, "wPlayerHP").is_err());
+}
+
+#[test]
+fn parse_errors() {
+    for text in ["", "[c345", "(a", "a +", "hl ]", "[a]:4", "a # 2", "0xgg"] {
+        assert!(Expression::parse(text).is_err(), "{}", text);
+    }
+    assert!(Expression::parse("[a]").unwrap().is_8bit());
+    assert!(!Expression::parse("[a]:16").unwrap().is_8bit());
+    assert!(!Expression::parse("hl").unwrap().is_8bit());
+}
+
+#[test]
+fn labels() {
+    let gb = game_boy();
+    gb.trace.borrow_mut().trace_entry_points(&gb);
+    assert_eq!(eval(&gb, "INT_VBLANK + 1"), Ok(0x41));
+}
+
+#[test]
+fn watch_command() {
+    let gb = game_boy();
+    let mut debugger = Debugger::default();
+    debugger.execute_command(&gb, &["watch", "c345"]).unwrap();
+    debugger
+        .execute_command(&gb, &["watch", "[c345]:16", "+", "1"])
+        .unwrap();
+    assert!(debugger.execute_command(&gb, &["watch", "[c345"]).is_err());
+    assert!(debugger.watchs().contains(&0xC345));
+
+    let [(text, expr)] = debugger.watch_expressions() else {
+        panic!("expected one watch expression");
+    };
+    assert_eq!(text, "[c345]:16 + 1");
+    assert_eq!(expr.eval(&gb), Ok(0x5635));
+
+    debugger
+        .execute_command(&gb, &["unwatch", "[c345]:16", "+", "1"])
+        .unwrap();
+    debugger.execute_command(&gb, &["unwatch", "c345"]).unwrap();
+    assert!(debugger.watch_expressions().is_empty());
+    assert!(debugger.watchs().is_empty());
+    assert!(debugger.execute_command(&gb, &["unwatch", "c345"]).is_err());
+}
@@ -6,7 +6,7 @@ use libfuzzer_sys::fuzz_target;
 
 use gameroy::{
     consts::{CLOCK_SPEED, SCREEN_HEIGHT, SCREEN_WIDTH},
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 
@@ -77,7 +77,7 @@ struct VBlank {
 fn test_interrupt_prediction(cartridge: Cartridge, target: u64) -> bool {
     let vblank = Arc::new(Mutex::new(VBlank::default()));
 
-    let mut game_boy_a = GameBoy::new(None, cartridge.clone());
+    let mut game_boy_a = GameBoy::new(None, cartridge.clone(), AccuracyConfig::default());
     game_boy_a.predict_interrupt = true;
     game_boy_a.serial.borrow_mut().serial_transfer_callback = None;
     game_boy_a.sound.get_mut().sample_frequency = 44100;
@@ -90,7 +90,7 @@ fn test_interrupt_prediction(cartridge: Cartridge, target: u64) -> bool {
         }
     }));
 
-    let mut game_boy_b = GameBoy::new(None, cartridge);
+    let mut game_boy_b = GameBoy::new(None, cartridge, AccuracyConfig::default());
     game_boy_b.predict_interrupt = false;
     game_boy_b.serial.borrow_mut().serial_transfer_callback = None;
     game_boy_b.sound.get_mut().sample_frequency = 44100;
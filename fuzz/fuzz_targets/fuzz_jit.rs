@@ -6,7 +6,7 @@ use libfuzzer_sys::fuzz_target;
 
 use gameroy::{
     consts::{CLOCK_SPEED, SCREEN_HEIGHT, SCREEN_WIDTH},
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 
@@ -79,7 +79,7 @@ fn test_jit(cartridge: Cartridge, target: u64) -> bool {
 
     let mut jit_compiler = gameroy_jit::JitCompiler::new();
 
-    let mut game_boy_a = GameBoy::new(None, cartridge.clone());
+    let mut game_boy_a = GameBoy::new(None, cartridge.clone(), AccuracyConfig::default());
     game_boy_a.predict_interrupt = true;
     game_boy_a.serial.borrow_mut().serial_transfer_callback = None;
     game_boy_a.v_blank = Some(Box::new({
@@ -93,7 +93,7 @@ fn test_jit(cartridge: Cartridge, target: u64) -> bool {
         }
     }));
 
-    let mut game_boy_b = GameBoy::new(None, cartridge);
+    let mut game_boy_b = GameBoy::new(None, cartridge, AccuracyConfig::default());
     game_boy_b.predict_interrupt = true;
     game_boy_b.halt_optimization = false;
     game_boy_b.serial.borrow_mut().serial_transfer_callback = None;
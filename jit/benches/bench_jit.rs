@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use gameroy_jit::JitCompiler;
+
+#[path = "../../core/benches/workloads/mod.rs"]
+mod workloads;
+
+/// The number of frames emulated in each iteration, one second of emulated time.
+const FRAMES: u64 = 60;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let roms = [
+        ("cpu mix", workloads::cpu_rom()),
+        ("ppu heavy", workloads::ppu_rom()),
+        ("apu heavy", workloads::apu_rom()),
+    ];
+    for (name, rom) in roms {
+        let mut gb = workloads::game_boy(rom);
+        let mut save_state = Vec::new();
+        gb.save_state(None, &mut save_state).unwrap();
+
+        // compiling all blocks from scratch, and running them.
+        c.bench_function(&format!("jit compile+run {} 1s", name), |b| {
+            b.iter(|| {
+                gb.load_state(&mut save_state.as_slice()).unwrap();
+                let mut jit = JitCompiler::new();
+                workloads::run_frames(&mut gb, FRAMES, |gb| jit.interpret_block(gb));
+            })
+        });
+
+        // only running the blocks, already compiled.
+        let mut jit = JitCompiler::new();
+        gb.load_state(&mut save_state.as_slice()).unwrap();
+        workloads::run_frames(&mut gb, FRAMES, |gb| jit.interpret_block(gb));
+        c.bench_function(&format!("jit run {} 1s", name), |b| {
+            b.iter(|| {
+                gb.load_state(&mut save_state.as_slice()).unwrap();
+                workloads::run_frames(&mut gb, FRAMES, |gb| jit.interpret_block(gb));
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
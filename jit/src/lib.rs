@@ -1,12 +1,13 @@
 use dynasmrt::ExecutableBuffer;
 use gameroy::{
     consts::{self, CB_CLOCK, CLOCK, CLOCK_SPEED, LEN},
-    disassembler::{Address, Cursor},
+    disassembler::{disassembly_opcode, Address, Cursor},
     gameboy::{cpu::CpuState, GameBoy},
     interpreter::Interpreter,
 };
 use std::{
-    collections::HashMap,
+    cell::Cell,
+    collections::{BTreeSet, HashMap},
     hash::{BuildHasher, Hasher},
 };
 
@@ -32,6 +33,14 @@ pub struct Block {
     ///
     /// Must not be called after `_compiled_code` is dropped.
     fn_ptr: unsafe extern "sysv64" fn(&mut GameBoy),
+    /// The address of the code right after the prolog, where linked blocks jump to.
+    entry: usize,
+    /// The exits of this block to known addresses. They are boxed, because the compiled code
+    /// points to them.
+    links: Vec<Box<BlockLink>>,
+    /// The value of `JitCompiler::use_count` the last time this block was fetched, or was jumped
+    /// to by a linked block. Boxed, because the compiled code of the linked blocks writes to it.
+    last_used: Box<Cell<u64>>,
 
     pub _compiled_code: ExecutableBuffer,
     _bytes: usize,
@@ -45,6 +54,12 @@ pub struct Block {
 }
 
 impl Block {
+    /// The value of `JitCompiler::use_count` the last time this block was used. The least
+    /// recently used blocks are the first to be evicted.
+    pub fn last_used(&self) -> u64 {
+        self.last_used.get()
+    }
+
     #[inline(never)]
     fn call(&self, gb: &mut GameBoy) {
         // SAFETY: `_compiled_code` is still a valid executable buffer.
@@ -52,6 +67,30 @@ impl Block {
     }
 }
 
+/// A exit of a compiled block to a known address. When the block for that address is compiled,
+/// the exit jumps directly to it, instead of returning to `interpret_block`.
+struct BlockLink {
+    /// The `Block::entry` of the target block, or 0 if it was not compiled yet.
+    entry: Cell<usize>,
+    /// The target block can only run if the next interrupt is more than this number of cycles
+    /// away. Same check as in `interpret_block`.
+    min_cycles: Cell<u64>,
+    /// The address of the `Block::last_used` of the target block. The exit refreshes it before
+    /// jumping, so the blocks only reached through links are not evicted as if they were unused.
+    last_used: Cell<usize>,
+    target: Address,
+}
+
+impl BlockLink {
+    fn link_to(&self, block: &Block) {
+        self.entry.set(block.entry);
+        self.min_cycles
+            .set(block.initial_block_clock_cycles as u64 + 4);
+        self.last_used
+            .set(&*block.last_used as *const Cell<u64> as usize);
+    }
+}
+
 /// A block of instruction to be compiled.
 struct BlockTrace {
     instrs: Vec<Instr>,
@@ -64,12 +103,34 @@ struct BlockTrace {
 #[derive(Clone)]
 pub struct CompilerOpts {
     pub flag_optimization: bool,
+    /// If true, the GameBoy registers are kept in host registers between instructions of a block,
+    /// and only written back to the GameBoy struct when needed. Can be disabled for debugging.
+    pub register_allocation: bool,
     /// If true, the compiler will emit a `/tmp/perf-$PID.map` file, that allows `perf` to
     /// identify JIT code.
     #[cfg(target_os = "linux")]
     pub emit_perf_map: bool,
+    /// If true, each executed block is also run through the interpreter, starting from a clone of
+    /// the same state, and the results are compared. Panics on the first mismatch, reporting the
+    /// address and disassembly of the block. This is very slow, only use it for debugging the
+    /// compiler.
+    pub check_against_interpreter: bool,
+    /// If true, blocks that exit to a known address jump directly to the block compiled for that
+    /// address, instead of returning to `interpret_block`.
+    pub block_linking: bool,
+    /// The maximum number of bytes of compiled code to keep. When exceeded, the least recently
+    /// used blocks are discarded. If None, blocks are never discarded. Defaults to
+    /// `DEFAULT_MAX_CODE_SIZE`.
+    pub max_code_size: Option<usize>,
 }
 
+/// The default `CompilerOpts::max_code_size`: 32 MiB.
+pub const DEFAULT_MAX_CODE_SIZE: usize = 32 << 20;
+
+/// The maximum number of cycles that linked blocks can run before returning to
+/// `interpret_block`.
+const MAX_LINKED_CYCLES: u64 = CLOCK_SPEED / 600;
+
 struct Instr {
     op: [u8; 3],
     pc: u16,
@@ -285,12 +346,28 @@ impl Drop for JitCompiler {
 }
 
 pub struct JitCompiler {
+    /// The compiled blocks. `NoHashHasher` uses the address itself as the hash, so a lookup is
+    /// only a couple of instructions.
     pub blocks: HashMap<Address, Block, NoHashHasher>,
+    /// The sum of the size of the code of all blocks.
+    code_size: usize,
+    /// The blocks ordered by their `Block::last_used`, as it was when they were inserted. It is
+    /// only brought up to date when a block is about to be evicted, because the compiled code
+    /// also updates `last_used`.
+    lru: BTreeSet<(u64, Address)>,
+    /// The addresses of the blocks with a link to each address, compiled or not.
+    incoming_links: HashMap<Address, Vec<Address>, NoHashHasher>,
+    /// Incremented on each call to `get_block`, used for finding the least recently used block.
+    /// Boxed, because the compiled code reads it when jumping to a linked block.
+    use_count: Box<Cell<u64>>,
     #[cfg(feature = "statistics")]
     stats: Stats,
     pub opts: CompilerOpts,
     /// A VecAssembler, reused for each block compilation
     assembler: x64::Assembler,
+    /// The clock count at which linked blocks stop jumping to each other. Boxed, because the
+    /// compiled code points to it.
+    link_deadline: Box<Cell<u64>>,
 }
 
 impl Default for JitCompiler {
@@ -303,17 +380,39 @@ impl JitCompiler {
     pub fn new() -> Self {
         Self {
             blocks: HashMap::with_hasher(NoHashHasher(0)),
+            code_size: 0,
+            lru: BTreeSet::new(),
+            incoming_links: HashMap::with_hasher(NoHashHasher(0)),
+            use_count: Box::new(Cell::new(0)),
             #[cfg(feature = "statistics")]
             stats: Stats::default(),
             opts: CompilerOpts {
                 flag_optimization: true,
+                register_allocation: true,
                 #[cfg(target_os = "linux")]
                 emit_perf_map: false,
+                check_against_interpreter: false,
+                block_linking: true,
+                max_code_size: Some(DEFAULT_MAX_CODE_SIZE),
             },
             assembler: x64::Assembler::new(0),
+            link_deadline: Box::new(Cell::new(0)),
         }
     }
 
+    /// Discard all compiled blocks. The options and statistics are kept.
+    ///
+    /// The blocks only depend on the code in the ROM, and check the banks at runtime, so they
+    /// stay valid after loading a save state or resetting the same game. This must be called when
+    /// the cartridge is replaced.
+    pub fn flush(&mut self) {
+        // the links only point to other blocks, so all of them can be dropped together.
+        self.blocks.clear();
+        self.code_size = 0;
+        self.lru.clear();
+        self.incoming_links.clear();
+    }
+
     pub fn get_block(&mut self, gb: &GameBoy) -> Option<&Block> {
         let pc = gb.cpu.pc;
         let bank = gb.cartridge.curr_bank();
@@ -323,6 +422,11 @@ impl JitCompiler {
             return None;
         }
 
+        // the boot rom is mapped over the cartridge, so don't compile it.
+        if gb.boot_rom_active {
+            return None;
+        }
+
         let op = gb.cartridge.read(pc);
 
         // if STOP or HALT, fallback to interpreter
@@ -340,17 +444,106 @@ impl JitCompiler {
         }
 
         let address = Address::from_pc(bank, pc)?;
-        Some(self.blocks.entry(address).or_insert_with(|| {
-            BlockCompiler::new(gb).compile_block(&self.opts, &mut self.assembler)
-        }))
+        self.use_count.set(self.use_count.get() + 1);
+        if !self.blocks.contains_key(&address) {
+            let block = BlockCompiler::new(gb).compile_block(
+                &self.opts,
+                &self.link_deadline,
+                &self.use_count,
+                &mut self.assembler,
+            );
+            self.code_size += block._bytes;
+            for link in &block.links {
+                self.incoming_links
+                    .entry(link.target)
+                    .or_default()
+                    .push(address);
+            }
+            self.lru.insert((self.use_count.get(), address));
+            self.blocks.insert(address, block);
+            self.evict_blocks(address);
+            self.link_block(address);
+        }
+        let block = self.blocks.get(&address)?;
+        block.last_used.set(self.use_count.get());
+        Some(block)
+    }
+
+    /// The sum of the size of the code of all compiled blocks, in bytes.
+    pub fn code_size(&self) -> usize {
+        self.code_size
+    }
+
+    /// While the code size is over `opts.max_code_size`, discard the least recently used block,
+    /// except the one at `keep`.
+    fn evict_blocks(&mut self, keep: Address) {
+        let Some(max_code_size) = self.opts.max_code_size else {
+            return;
+        };
+        let mut kept = None;
+        while self.code_size > max_code_size {
+            let Some((last_used, lru)) = self.lru.pop_first() else {
+                break;
+            };
+            if lru == keep {
+                kept = Some((last_used, lru));
+                continue;
+            }
+
+            // `last_used` may have increased since it was inserted. If so, only the block's place
+            // in the order is updated. Otherwise, all other blocks were used after it.
+            let curr_last_used = self.blocks[&lru].last_used.get();
+            if curr_last_used != last_used {
+                self.lru.insert((curr_last_used, lru));
+                continue;
+            }
+
+            let block = self.blocks.remove(&lru).unwrap();
+            self.code_size -= block._bytes;
+
+            // the code of the block is freed, so nothing can jump to it anymore. The block itself
+            // may be one of the sources, but it was already removed.
+            let sources = self.incoming_links.get(&lru).into_iter().flatten();
+            for source in sources.filter_map(|x| self.blocks.get(x)) {
+                for link in source.links.iter().filter(|x| x.target == lru) {
+                    link.entry.set(0);
+                }
+            }
+            for link in &block.links {
+                if let Some(sources) = self.incoming_links.get_mut(&link.target) {
+                    sources.retain(|x| *x != lru);
+                }
+            }
+        }
+        self.lru.extend(kept);
+    }
+
+    /// Link the exits of the block at `address` to the blocks already compiled, and the exits of
+    /// the blocks already compiled to it.
+    fn link_block(&self, address: Address) {
+        let new_block = &self.blocks[&address];
+        for link in &new_block.links {
+            if let Some(target) = self.blocks.get(&link.target) {
+                link.link_to(target);
+            }
+        }
+        for source in self.incoming_links.get(&address).into_iter().flatten() {
+            let links = self.blocks[source].links.iter();
+            for link in links.filter(|x| x.target == address) {
+                link.link_to(new_block);
+            }
+        }
     }
 
     pub fn interpret_block(&mut self, gb: &mut GameBoy) {
         let on_ram = gb.cpu.pc >= 0x8000;
+        let check_against_interpreter = self.opts.check_against_interpreter;
 
         #[cfg(feature = "statistics")]
         let mut stats = std::mem::take(&mut self.stats);
 
+        self.link_deadline.set(gb.clock_count + MAX_LINKED_CYCLES);
+
         let block = self.get_block(gb);
         let next_interrupt = gb.next_interrupt.get();
         let start_clock = gb.clock_count;
@@ -383,18 +576,24 @@ impl JitCompiler {
 
         match block {
             Some(block) => {
-                // let bank = gb.cartridge.curr_bank();
-                // println!(
-                //     "running {:02x} {:04x} ({})",
-                //     if block._start_address <= 0x3FFF {
-                //         bank.0
-                //     } else {
-                //         bank.1
-                //     },
-                //     block._start_address,
-                //     gb.clock_count,
-                // );
+                gameroy::tracing_event!(
+                    TRACE,
+                    banks = ?gb.cartridge.curr_bank(),
+                    clock_count = gb.clock_count,
+                    "running block {:04x}",
+                    block._start_address
+                );
+                let reference = check_against_interpreter.then(|| clone_game_boy(gb));
                 block.call(gb);
+                // a block ends on any return, so a interrupt handler only returns in its last
+                // instruction.
+                let (sp, clock_count) = (gb.cpu.sp, gb.clock_count);
+                let counters = gb.counters.get_mut();
+                counters.leave_handlers(sp, clock_count);
+                counters.jit_cycles += clock_count - start_clock;
+                if let Some(reference) = reference {
+                    check_block(reference, gb);
+                }
                 debug_assert!(gb.clock_count != start_clock);
 
                 stat!(stats.cycles_compiled += gb.clock_count - start_clock);
@@ -409,7 +608,12 @@ impl JitCompiler {
                 );
             }
             _ => {
-                // println!("interpr {:04x} ({})", gb.cpu.pc, gb.clock_count);
+                gameroy::tracing_event!(
+                    TRACE,
+                    clock_count = gb.clock_count,
+                    "interpreting from {:04x}",
+                    gb.cpu.pc
+                );
 
                 // avoid being stuck here for to long
                 let timeout = gb.clock_count + CLOCK_SPEED / 60;
@@ -437,7 +641,11 @@ impl JitCompiler {
 
                     let is_interrupt = [0x40, 0x48, 0x50, 0x58, 0x60].contains(&inter.0.cpu.pc);
 
+                    // stop at `GameBoy::stop_clock`, so the state can be captured exactly there.
+                    let is_stop = inter.0.clock_count >= inter.0.stop_clock.get();
+
                     if is_interrupt
+                        || is_stop
                         || is_jump && inter.0.cpu.pc < 0x8000
                         || inter.0.clock_count > timeout
                     {
@@ -459,3 +667,89 @@ impl JitCompiler {
         stat!(self.stats = stats);
     }
 }
+
+/// Clone the state of the GameBoy, through a save state. The callbacks are not cloned.
+fn clone_game_boy(gb: &GameBoy) -> GameBoy {
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+
+    let mut clone = GameBoy::new(gb.boot_rom.clone(), gb.cartridge.clone(), gb.accuracy);
+    clone.predict_interrupt = gb.predict_interrupt;
+    clone.halt_optimization = gb.halt_optimization;
+    clone.load_state(&mut state.as_slice()).unwrap();
+    clone
+}
+
+/// Run `reference`, a clone of the state before the execution of a compiled block, with the
+/// interpreter until it reaches the same clock count of `gb`, and compare the two. Panics if they
+/// differ.
+fn check_block(mut reference: GameBoy, gb: &GameBoy) {
+    let bank = reference.cartridge.curr_bank();
+    let start = reference.cpu.pc;
+    let instrs = trace_a_block(&reference).instrs;
+
+    while reference.clock_count < gb.clock_count {
+        Interpreter(&mut reference).interpret_op();
+    }
+    // The v_blank callback was not cloned, and it may change the joypad.
+    reference.joypad = gb.joypad;
+
+    gb.update_all();
+    reference.update_all();
+
+    let mut diffs = Vec::new();
+    macro_rules! compare {
+        ($($field:ident),*) => {
+            $(if reference.$field != gb.$field {
+                diffs.push(stringify!($field));
+            })*
+        };
+    }
+    compare!(
+        cpu,
+        cartridge,
+        wram,
+        hram,
+        boot_rom_active,
+        clock_count,
+        timer,
+        sound,
+        ppu,
+        joypad_io,
+        joypad,
+        serial,
+        interrupt_flag,
+        interrupt_enabled
+    );
+
+    if diffs.is_empty() {
+        return;
+    }
+
+    let mut disassembly = String::new();
+    for instr in instrs {
+        use std::fmt::Write;
+        let len = LEN[instr.op[0] as usize] as usize;
+        let _ = write!(disassembly, "    {:02x}_{:04x}: ", instr.bank, instr.pc);
+        let _ = disassembly_opcode(
+            instr.pc,
+            &instr.op[..len],
+            |x| format!("${:04x}", x),
+            &mut disassembly,
+        );
+        disassembly.push('\n');
+    }
+
+    panic!(
+        "compiled block at {:02x}_{:04x} diverged from the interpreter, in: {}\n\
+         jit: {:x?}\n\
+         int: {:x?}\n\
+         block:\n{}",
+        if start <= 0x3FFF { bank.0 } else { bank.1 },
+        start,
+        diffs.join(", "),
+        gb.cpu,
+        reference.cpu,
+        disassembly,
+    );
+}
@@ -7,6 +7,7 @@ pub type Assembler = VecAssembler<X64Relocation>;
 
 use gameroy::{
     consts,
+    disassembler::Address,
     gameboy::{
         cartridge::Cartridge,
         cpu::{Cpu, CpuState, ImeState},
@@ -15,7 +16,9 @@ use gameroy::{
     interpreter::{Condition, Interpreter, Reg, Reg16},
 };
 
-use crate::{trace_a_block, Block, BlockTrace};
+use std::cell::Cell;
+
+use crate::{trace_a_block, Block, BlockLink, BlockTrace};
 
 macro_rules! offset {
     (@ $parent:path, $field:tt) => {
@@ -60,6 +63,53 @@ struct Instr {
     /// Bitmask of flags set by this isntruction that will be observed by future instructions. In
     /// the format 0b0000ZNHC.
     used_flags: u8,
+    /// If a relative jump in this block targets this instruction. The register cache must be
+    /// empty at its label, because it can be reached from multiple paths.
+    jump_target: bool,
+}
+
+/// The host registers used to cache GameBoy registers inside a block (r8 to r11). They are
+/// caller-saved, so they don't need to be pushed in the prolog, but the cache must be invalidated
+/// before any call.
+const HOST_REGS: [u8; 4] = [8, 9, 10, 11];
+
+/// The carry flag, in the format used by `Instr::used_flags`.
+const FLAG_C: u8 = 0b0001;
+
+/// A 8-bit arithmetic or logic operation between A and another operand.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Alu {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
+
+/// How a opcode interacts with the register cache.
+enum RegCacheUse {
+    /// The opcode is compiled using the register cache, or don't access the GameBoy registers.
+    Cached,
+    /// The opcode may exit the block, so the dirty registers must be written back before it.
+    Flush,
+    /// The opcode access these register pairs directly in memory, but don't call any function.
+    Pairs(&'static [Reg16]),
+    /// The opcode calls a function, or uses the host registers as scratch.
+    Invalidate,
+}
+
+/// A GameBoy register that is currently held in a host register.
+#[derive(Clone, Copy)]
+struct CachedReg {
+    /// The offset of the GameBoy register in the GameBoy struct.
+    offset: usize,
+    /// If the host register has a value that was not written back to the GameBoy struct yet.
+    dirty: bool,
+    /// The value of `BlockCompiler::reg_cache_uses` the last time this register was used.
+    last_use: u32,
 }
 
 pub struct BlockCompiler<'gb> {
@@ -87,6 +137,22 @@ pub struct BlockCompiler<'gb> {
     /// The ime_state of the current instruction, if known.
     previous_ime_state: Option<ImeState>,
 
+    /// If GameBoy registers can be kept in host registers between instructions.
+    reg_allocation: bool,
+    /// The GameBoy register cached in each one of `HOST_REGS`.
+    reg_cache: [Option<CachedReg>; HOST_REGS.len()],
+    /// Incremented on each use of the register cache, for picking the least recently used one.
+    reg_cache_uses: u32,
+
+    /// If exits to known addresses should try to jump directly to the next block.
+    block_linking: bool,
+    /// The address of `JitCompiler::link_deadline`.
+    link_deadline: usize,
+    /// The address of `JitCompiler::use_count`.
+    use_count: usize,
+    /// The exits of this block to known addresses.
+    links: Vec<Box<BlockLink>>,
+
     #[cfg(feature = "statistics")]
     cleared_flags: usize,
     #[cfg(feature = "statistics")]
@@ -128,6 +194,13 @@ impl<'a> BlockCompiler<'a> {
             did_write: false,
             ime_state: None,
             previous_ime_state: None,
+            reg_allocation: false,
+            reg_cache: [None; HOST_REGS.len()],
+            reg_cache_uses: 0,
+            block_linking: false,
+            link_deadline: 0,
+            use_count: 0,
+            links: Vec::new(),
             #[cfg(feature = "statistics")]
             cleared_flags: 0,
             #[cfg(feature = "statistics")]
@@ -137,15 +210,20 @@ impl<'a> BlockCompiler<'a> {
         }
     }
 
-    pub fn compile_block(mut self, opts: &super::CompilerOpts, ops: &mut Assembler) -> Block {
-        // let bank = self.gb.cartridge.curr_bank();
-        // println!(
-        //     "compiling {:02x}_{:04x} (len: {})",
-        //     if self.pc <= 0x3FFF { bank.0 } else { bank.1 },
-        //     self.pc,
-        //     self.block_trace.length,
-        //     // self.max_clock_cycles,
-        // );
+    pub fn compile_block(
+        mut self,
+        opts: &super::CompilerOpts,
+        link_deadline: &Cell<u64>,
+        use_count: &Cell<u64>,
+        ops: &mut Assembler,
+    ) -> Block {
+        gameroy::tracing_span!(
+            DEBUG,
+            "compile_block",
+            banks = ?self.gb.cartridge.curr_bank(),
+            address = self.pc,
+            length = self.block_trace.length
+        );
 
         self.instrs = std::mem::take(&mut self.block_trace.instrs)
             .into_iter()
@@ -163,6 +241,7 @@ impl<'a> BlockCompiler<'a> {
                     curr_clock_count,
                     accum_clock_count: 0,
                     used_flags: 0xf,
+                    jump_target: false,
                 },
             )
             .collect();
@@ -171,10 +250,20 @@ impl<'a> BlockCompiler<'a> {
             self.flags_analysis();
         }
 
+        self.reg_allocation = opts.register_allocation;
+        if self.reg_allocation {
+            self.find_jump_targets();
+        }
+
+        self.block_linking = opts.block_linking;
+        self.link_deadline = link_deadline as *const Cell<u64> as usize;
+        self.use_count = use_count as *const Cell<u64> as usize;
+
         let push_rbp_offset;
         let push_rbx_offset;
         let push_r12_offset;
         let prolog_len;
+        let entry_offset;
 
         dynasm!(ops
             ; .arch x64
@@ -187,6 +276,7 @@ impl<'a> BlockCompiler<'a> {
             ;; prolog_len = ops.offset().0 as u8
             ; mov rbp, rsp
             ; mov rbx, rdi
+            ;; entry_offset = ops.offset().0
         );
 
         let start_address = self.pc;
@@ -208,7 +298,7 @@ impl<'a> BlockCompiler<'a> {
             self.op = instr.op;
             let op = instr.op[0];
 
-            // println!("{:04x}: {:02x?}", self.pc, instr.op);
+            gameroy::tracing_event!(TRACE, "{:04x}: {:02x?}", self.pc, instr.op);
 
             let ime_enabled = self.ime_state == Some(ImeState::ToBeEnable);
             if ime_enabled {
@@ -218,6 +308,9 @@ impl<'a> BlockCompiler<'a> {
 
             self.pc += 1;
 
+            if instr.jump_target {
+                self.invalidate_reg_cache(ops);
+            }
             if instr.accum_clock_count == u32::MAX {
                 self.update_clock_count(ops);
             }
@@ -263,11 +356,14 @@ impl<'a> BlockCompiler<'a> {
             if ime_enabled {
                 let next_check = self.block_trace.interrupt_checks[curr_check].1;
                 let curr_clock_count = self.curr_clock_count;
+                self.flush_reg_cache(ops);
                 self.update_pc(ops);
                 self.check_interrupt(ops, curr_clock_count, next_check);
             }
         }
 
+        self.invalidate_reg_cache(ops);
+
         dynasm!(ops
             ; .arch x64
             ; ->exit:
@@ -338,6 +434,10 @@ impl<'a> BlockCompiler<'a> {
                 )
             },
 
+            entry: compiled_code.as_ptr() as usize + entry_offset,
+            links: self.links,
+            last_used: Box::new(Cell::new(0)),
+
             _compiled_code: compiled_code,
             _bytes: bytes,
             #[cfg(feature = "statistics")]
@@ -487,7 +587,7 @@ impl<'a> BlockCompiler<'a> {
             .position(|x| x.pc == address && x.bank == curr_bank);
 
         let Some(target) = target else {
-            return self.exit_block(ops);
+            return self.exit_block_to(ops, address);
         };
 
         let target_was_compiled = self.curr_instr >= target;
@@ -500,7 +600,7 @@ impl<'a> BlockCompiler<'a> {
             .find(|x| *x > self.instrs[target].curr_clock_count);
 
         let Some(next_check) = next_check else {
-            return self.exit_block(ops);
+            return self.exit_block_to(ops, address);
         };
 
         self.check_interrupt(ops, self.instrs[target].curr_clock_count, next_check);
@@ -516,6 +616,105 @@ impl<'a> BlockCompiler<'a> {
         dynasm!(ops; jmp =>self.instrs[target].label)
     }
 
+    /// Mark each instruction that is the target of a relative jump inside the block.
+    fn find_jump_targets(&mut self) {
+        for i in 0..self.instrs.len() {
+            let Instr { op, pc, bank, .. } = self.instrs[i];
+            if !matches!(op[0], 0x18 | 0x20 | 0x28 | 0x30 | 0x38) {
+                continue;
+            }
+            let address = pc.wrapping_add(2).wrapping_add_signed(op[1] as i8 as i16);
+            for instr in self.instrs.iter_mut() {
+                if instr.pc == address && instr.bank == bank {
+                    instr.jump_target = true;
+                }
+            }
+        }
+    }
+
+    /// Return the host register that holds the GameBoy register at the given offset, allocating
+    /// one if necessary. If `load` is true, the current value of the register is loaded into it.
+    /// If `write` is true, the register is marked as dirty.
+    fn cached_reg(&mut self, ops: &mut Assembler, offset: usize, load: bool, write: bool) -> u8 {
+        self.reg_cache_uses += 1;
+        let uses = self.reg_cache_uses;
+
+        let position = self
+            .reg_cache
+            .iter()
+            .position(|x| x.map_or(false, |x| x.offset == offset));
+        let i = match position {
+            Some(i) => i,
+            None => {
+                // pick a free register, or the least recently used one.
+                let i = (0..HOST_REGS.len())
+                    .min_by_key(|&i| self.reg_cache[i].map_or(0, |x| x.last_use))
+                    .unwrap();
+                self.flush_cached_reg(ops, i);
+                if load {
+                    dynasm!(ops
+                        ; movzx Rd(HOST_REGS[i]), BYTE [rbx + offset as i32]
+                    );
+                }
+                self.reg_cache[i] = Some(CachedReg {
+                    offset,
+                    dirty: false,
+                    last_use: uses,
+                });
+                i
+            }
+        };
+
+        let cached = self.reg_cache[i].as_mut().unwrap();
+        cached.last_use = uses;
+        cached.dirty |= write;
+        HOST_REGS[i]
+    }
+
+    /// Write back the i-th host register to the GameBoy struct, if it is dirty.
+    fn flush_cached_reg(&mut self, ops: &mut Assembler, i: usize) {
+        if let Some(cached) = self.reg_cache[i].as_mut() {
+            if cached.dirty {
+                dynasm!(ops
+                    ; mov BYTE [rbx + cached.offset as i32], Rb(HOST_REGS[i])
+                );
+                cached.dirty = false;
+            }
+        }
+    }
+
+    /// Write back all dirty host registers, but keep them cached. Must be called before any code
+    /// that may exit the block.
+    fn flush_reg_cache(&mut self, ops: &mut Assembler) {
+        for i in 0..HOST_REGS.len() {
+            self.flush_cached_reg(ops, i);
+        }
+    }
+
+    /// Write back all dirty host registers and forget them. Must be called before any code that
+    /// access the GameBoy registers in memory or calls a function.
+    fn invalidate_reg_cache(&mut self, ops: &mut Assembler) {
+        self.flush_reg_cache(ops);
+        self.reg_cache = [None; HOST_REGS.len()];
+    }
+
+    /// Write back and forget only the cached registers that are part of the given register pairs.
+    fn invalidate_cached_pairs(&mut self, ops: &mut Assembler, pairs: &[Reg16]) {
+        for i in 0..HOST_REGS.len() {
+            let Some(cached) = self.reg_cache[i] else {
+                continue;
+            };
+            let in_pairs = pairs.iter().any(|&pair| {
+                let offset = reg_offset16(pair);
+                cached.offset == offset || cached.offset == offset + 1
+            });
+            if in_pairs {
+                self.flush_cached_reg(ops, i);
+                self.reg_cache[i] = None;
+            }
+        }
+    }
+
     fn exit_block(&mut self, ops: &mut Assembler) {
         // self.update_pc(ops);
         self.emit_update_clock_count(ops);
@@ -537,6 +736,85 @@ impl<'a> BlockCompiler<'a> {
         );
     }
 
+    /// Same as `exit_block`, but the next PC is known to be `address`. If the block for that
+    /// address is already compiled and linked, the interrupts permit, and the bank is still the
+    /// same, jump directly into it.
+    fn exit_block_to(&mut self, ops: &mut Assembler, address: u16) {
+        if !self.block_linking || address >= 0x8000 {
+            return self.exit_block(ops);
+        }
+
+        // If the target is in the same bank region of this instruction, its bank is known.
+        // Otherwise assume that the bank will not change, and check it at runtime.
+        let instr = self.instrs[self.curr_instr];
+        let curr_bank = self.gb.cartridge.curr_bank();
+        let (bank_offset, bank) = if address <= 0x3FFF {
+            let bank = if instr.pc <= 0x3FFF {
+                instr.bank
+            } else {
+                curr_bank.0
+            };
+            (offset!(GameBoy, cartridge: Cartridge, lower_bank), bank)
+        } else {
+            let bank = if instr.pc >= 0x4000 {
+                instr.bank
+            } else {
+                curr_bank.1
+            };
+            (offset!(GameBoy, cartridge: Cartridge, upper_bank), bank)
+        };
+        let Some(target) = Address::from_pc((bank, bank), address) else {
+            return self.exit_block(ops);
+        };
+
+        let link = Box::new(BlockLink {
+            entry: Cell::new(0),
+            min_cycles: Cell::new(0),
+            last_used: Cell::new(0),
+            target,
+        });
+        let link_ptr = &*link as *const BlockLink as i64;
+        self.links.push(link);
+
+        let clock_count = offset!(GameBoy, clock_count);
+        let next_interrupt = offset!(GameBoy, next_interrupt);
+        let entry = offset!(BlockLink, entry);
+        let min_cycles = offset!(BlockLink, min_cycles);
+        let last_used = offset!(BlockLink, last_used);
+
+        self.emit_update_clock_count(ops);
+        self.update_ime_state(ops);
+
+        dynasm!(ops
+            ; mov	rdx, QWORD link_ptr
+            ; mov	rcx, QWORD [rdx + entry as i32]
+            ; test	rcx, rcx
+            ; jz	>no_link
+            ; cmp	WORD [rbx + bank_offset as i32], bank as i16
+            ; jne	>no_link
+            ; mov	rax, QWORD self.link_deadline as i64
+            ; mov	rax, QWORD [rax]
+            ; cmp	QWORD [rbx + clock_count as i32], rax
+            ; jae	>no_link
+            ; mov	rax, QWORD [rbx + next_interrupt as i32]
+            ; sub	rax, QWORD [rbx + clock_count as i32]
+            ; jb	>no_link
+            ; cmp	rax, QWORD [rdx + min_cycles as i32]
+            ; jbe	>no_link
+            // the target block is used now: `*link.last_used = *use_count`.
+            ; mov	rax, QWORD [rdx + last_used as i32]
+            ; mov	rdx, QWORD self.use_count as i64
+            ; mov	rdx, QWORD [rdx]
+            ; mov	QWORD [rax], rdx
+            ; jmp	rcx
+            ; no_link:
+            ; pop r12
+            ; pop rbx
+            ; pop rbp
+            ; ret
+        );
+    }
+
     fn tick(&mut self, count: i32) {
         self.accum_clock_count = self.accum_clock_count.checked_add_signed(count).unwrap();
         self.curr_clock_count = self.curr_clock_count.checked_add_signed(count).unwrap();
@@ -547,6 +825,12 @@ impl<'a> BlockCompiler<'a> {
     fn compile_opcode(&mut self, ops: &mut Assembler, op: u8) -> bool {
         self.tick(4);
         self.did_write = false;
+        match reg_cache_use(op) {
+            RegCacheUse::Cached => {}
+            RegCacheUse::Flush => self.flush_reg_cache(ops),
+            RegCacheUse::Pairs(pairs) => self.invalidate_cached_pairs(ops, pairs),
+            RegCacheUse::Invalidate => self.invalidate_reg_cache(ops),
+        }
         use Condition::*;
         match op {
             // NOP 1:4 - - - -
@@ -1610,10 +1894,13 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn invalid_opcode(&mut self, _ops: &mut Assembler, _opcode: u8) {
-        // println!("compiled invalid instructions: {_opcode:02x}");
+        gameroy::tracing_event!(WARN, "compiled invalid instruction {:02x}", _opcode);
     }
 
     pub fn load_reg_reg(&mut self, ops: &mut Assembler, dst: Reg, src: Reg) {
+        if self.reg_allocation {
+            return self.load_reg_reg_cached(ops, dst, src);
+        }
         let dst = reg_offset(dst);
         match src {
             Reg::Im8 => {
@@ -1633,6 +1920,31 @@ impl<'a> BlockCompiler<'a> {
         }
     }
 
+    fn load_reg_reg_cached(&mut self, ops: &mut Assembler, dst: Reg, src: Reg) {
+        let dst = reg_offset(dst);
+        match src {
+            Reg::Im8 => {
+                let value = self.get_immediate();
+                let dst = self.cached_reg(ops, dst, false, true);
+                dynasm!(ops
+                    ; mov Rb(dst), value as i8
+                );
+            }
+            Reg::A | Reg::B | Reg::C | Reg::D | Reg::E | Reg::H | Reg::L => {
+                let src = reg_offset(src);
+                if src == dst {
+                    return;
+                }
+                let src = self.cached_reg(ops, src, true, false);
+                let dst = self.cached_reg(ops, dst, false, true);
+                dynasm!(ops
+                    ; mov Rd(dst), Rd(src)
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub fn load16(&mut self, ops: &mut Assembler, dst: Reg16, src: Reg16) {
         match dst {
             Reg16::Im16 => {
@@ -2109,6 +2421,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn inc(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation {
+            return self.inc_dec_cached(ops, reg, false);
+        }
         let reg = reg_offset(reg);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
@@ -2169,6 +2484,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn dec(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation {
+            return self.inc_dec_cached(ops, reg, true);
+        }
         let reg = reg_offset(reg);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
@@ -2196,6 +2514,50 @@ impl<'a> BlockCompiler<'a> {
         );
     }
 
+    fn inc_dec_cached(&mut self, ops: &mut Assembler, reg: Reg, dec: bool) {
+        let f = offset!(GameBoy, cpu: Cpu, f);
+
+        let flags = self.instrs[self.curr_instr].used_flags != 0;
+
+        let reg = self.cached_reg(ops, reg_offset(reg), true, true);
+        if !flags {
+            if dec {
+                dynasm!(ops; dec Rb(reg));
+            } else {
+                dynasm!(ops; inc Rb(reg));
+            }
+            return;
+        }
+
+        // the value of the lower nibble that causes a half carry.
+        let half = if dec { 0xF } else { 0x0 };
+        let f = self.cached_reg(ops, f, true, true);
+        dynasm!(ops
+            ; movzx	edx, Rb(reg)
+            ;; if dec {
+                dynasm!(ops; dec dl);
+            } else {
+                dynasm!(ops; inc dl);
+            }
+            ; mov	Rb(reg), dl
+            ; movzx	edi, Rb(f)
+            ; and	edi, 0x1F // clear Z, N, H
+            ; xor	eax, eax
+            ; test	dl, dl
+            ; sete	al // Z flag
+            ; shl	eax, 7
+            ; or	edi, eax
+            ; mov	eax, edx
+            ; and	eax, 0xF
+            ; cmp	eax, half
+            ; sete	al // H flag
+            ; shl	eax, 5
+            ; or	edi, eax
+            ;; dynasm_if!(dec, ops; or edi, 0x40)
+            ; mov	Rd(f), edi
+        );
+    }
+
     pub fn dec16(&mut self, ops: &mut Assembler, reg: Reg) {
         self.tick(4);
         let reg = reg_offset(reg);
@@ -2302,10 +2664,17 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn add(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Add, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
-        let flags = self.instrs[self.curr_instr].used_flags != 0;
+        let used_flags = self.instrs[self.curr_instr].used_flags;
+        // If only the carry is observed (like in the low byte of a 16-bit addition), it is
+        // computed by the host flags, and the dead flags are written as zero.
+        let carry_only = used_flags == FLAG_C;
+        let flags = used_flags != 0 && !carry_only;
 
         if !flags {
             match reg {
@@ -2329,6 +2698,7 @@ impl<'a> BlockCompiler<'a> {
                     );
                 }
             }
+            self.carry_to_flags(ops, carry_only);
             return;
         }
 
@@ -2371,14 +2741,20 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn add_imm(&mut self, ops: &mut Assembler) {
+        if self.reg_allocation {
+            return self.alu_cached(ops, Alu::Add, Reg::Im8);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
-        let flags = self.instrs[self.curr_instr].used_flags != 0;
+        let used_flags = self.instrs[self.curr_instr].used_flags;
+        let carry_only = used_flags == FLAG_C;
+        let flags = used_flags != 0 && !carry_only;
 
         let value = self.get_immediate();
         if !flags {
             dynasm!(ops; add	BYTE [rbx + a as i32], value as i8);
+            self.carry_to_flags(ops, carry_only);
             return;
         }
 
@@ -2449,17 +2825,22 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn sub(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Sub, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
-        let flags = self.instrs[self.curr_instr].used_flags != 0;
+        let used_flags = self.instrs[self.curr_instr].used_flags;
+        let carry_only = used_flags == FLAG_C;
+        let flags = used_flags != 0 && !carry_only;
 
         if !flags {
             match reg {
                 Reg::Im8 => {
                     let value = self.get_immediate();
                     dynasm!(ops
-                        ; add	BYTE [rbx + a as i32], value.wrapping_neg() as i8
+                        ; sub	BYTE [rbx + a as i32], value as i8
                     );
                 }
                 Reg::HL => {
@@ -2476,6 +2857,7 @@ impl<'a> BlockCompiler<'a> {
                     );
                 }
             }
+            self.carry_to_flags(ops, carry_only);
             return;
         }
 
@@ -2517,10 +2899,15 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn adc(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Adc, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
-        let flags = self.instrs[self.curr_instr].used_flags != 0;
+        let used_flags = self.instrs[self.curr_instr].used_flags;
+        let carry_only = used_flags == FLAG_C;
+        let flags = used_flags != 0 && !carry_only;
 
         let immediate = if reg == Reg::Im8 {
             self.get_immediate()
@@ -2558,6 +2945,7 @@ impl<'a> BlockCompiler<'a> {
                     );
                 }
             }
+            self.carry_to_flags(ops, carry_only);
         }
 
         dynasm_if!(flags, ops
@@ -2629,6 +3017,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn sbc(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Sbc, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
@@ -2728,6 +3119,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn and(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::And, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
         dynasm!(ops
@@ -2768,6 +3162,19 @@ impl<'a> BlockCompiler<'a> {
     pub fn xor_a(&mut self, ops: &mut Assembler) {
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
+        if self.reg_allocation {
+            let flags = self.instrs[self.curr_instr].used_flags != 0;
+            let a = self.cached_reg(ops, a, false, true);
+            dynasm!(ops; xor Rd(a), Rd(a));
+            if flags {
+                let f = self.cached_reg(ops, f, true, true);
+                dynasm!(ops
+                    ; and	Rd(f), 15
+                    ; or	Rd(f), 0x80
+                );
+            }
+            return;
+        }
         dynasm!(ops
             ; mov	BYTE [rbx + a as i32], 0
             ;; dynasm_if!(self.instrs[self.curr_instr].used_flags != 0, ops
@@ -2780,6 +3187,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn xor(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Xor, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
         dynasm!(ops
@@ -2816,6 +3226,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn or(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Or, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
         dynasm!(ops
@@ -2852,6 +3265,9 @@ impl<'a> BlockCompiler<'a> {
     }
 
     pub fn cp(&mut self, ops: &mut Assembler, reg: Reg) {
+        if self.reg_allocation && reg != Reg::HL {
+            return self.alu_cached(ops, Alu::Cp, reg);
+        }
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
 
@@ -2910,6 +3326,88 @@ impl<'a> BlockCompiler<'a> {
         )
     }
 
+    /// Compile a operation between A and a register or a immediate, using the register cache.
+    fn alu_cached(&mut self, ops: &mut Assembler, alu: Alu, src: Reg) {
+        let a = reg_offset(Reg::A);
+        let f = offset!(GameBoy, cpu: Cpu, f);
+
+        let flags = self.instrs[self.curr_instr].used_flags != 0;
+        let carry_in = matches!(alu, Alu::Adc | Alu::Sbc);
+
+        let value = if src == Reg::Im8 {
+            self.get_immediate()
+        } else {
+            0
+        };
+        if alu == Alu::Cp && !flags {
+            return;
+        }
+
+        let a = self.cached_reg(ops, a, true, alu != Alu::Cp);
+        match src {
+            Reg::Im8 => dynasm!(ops; mov ecx, value as i32),
+            _ => {
+                let src = self.cached_reg(ops, reg_offset(src), true, false);
+                dynasm!(ops; movzx ecx, Rb(src));
+            }
+        }
+        let f = (flags || carry_in).then(|| self.cached_reg(ops, f, true, flags));
+
+        dynasm!(ops; movzx eax, Rb(a));
+        if let (true, Some(f)) = (carry_in, f) {
+            dynasm!(ops
+                ; movzx	esi, Rb(f)
+                ; shr	esi, 4
+                ; and	esi, 1
+            );
+        }
+        dynasm!(ops
+            ;; match alu {
+                Alu::Add => dynasm!(ops; lea edx, [rax + rcx]),
+                Alu::Adc => dynasm!(ops; lea edx, [rax + rcx]; add edx, esi),
+                Alu::Sub | Alu::Cp => dynasm!(ops; mov edx, eax; sub edx, ecx),
+                Alu::Sbc => dynasm!(ops; mov edx, eax; sub edx, ecx; sub edx, esi),
+                Alu::And => dynasm!(ops; mov edx, eax; and edx, ecx),
+                Alu::Xor => dynasm!(ops; mov edx, eax; xor edx, ecx),
+                Alu::Or => dynasm!(ops; mov edx, eax; or edx, ecx),
+            }
+            ;; dynasm_if!(alu != Alu::Cp, ops; mov Rb(a), dl)
+        );
+
+        let Some(f) = f.filter(|_| flags) else {
+            return;
+        };
+
+        dynasm!(ops
+            ; movzx	edi, Rb(f)
+            ; and	edi, 15
+            ;; match alu {
+                Alu::Add | Alu::Adc | Alu::Sub | Alu::Sbc | Alu::Cp => dynasm!(ops
+                    // the bit 4 of a ^ src ^ result is the carry from bit 3.
+                    ; xor	eax, ecx
+                    ; xor	eax, edx
+                    ; and	eax, 0x10
+                    ; shl	eax, 1
+                    ; or	edi, eax // H flag
+                    // the bit 8 of the result is the carry from bit 7, or the borrow.
+                    ; mov	eax, edx
+                    ; shr	eax, 4
+                    ; and	eax, 0x10
+                    ; or	edi, eax // C flag
+                ),
+                Alu::And => dynasm!(ops; or edi, 0x20),
+                Alu::Xor | Alu::Or => {}
+            }
+            ;; dynasm_if!(matches!(alu, Alu::Sub | Alu::Sbc | Alu::Cp), ops; or edi, 0x40)
+            ; xor	eax, eax
+            ; test	dl, dl
+            ; sete	al // Z flag
+            ; shl	eax, 7
+            ; or	edi, eax
+            ; mov	Rd(f), edi
+        );
+    }
+
     pub fn rlca(&mut self, ops: &mut Assembler) {
         let a = reg_offset(Reg::A);
         let f = offset!(GameBoy, cpu: Cpu, f);
@@ -3276,7 +3774,7 @@ impl<'a> BlockCompiler<'a> {
         dynasm!(ops
             ;; self.tick(4)
             ; mov WORD [rbx + pc as i32], address as i16
-            ;; self.exit_block(ops)
+            ;; self.exit_block_to(ops, address)
             ; skip_jump:
         );
 
@@ -3332,7 +3830,7 @@ impl<'a> BlockCompiler<'a> {
 
             ; mov WORD [rbx + pc_offset as i32], address as i16
 
-            ;; self.exit_block(ops)
+            ;; self.exit_block_to(ops, address)
             ; skip_jump:
         );
 
@@ -3371,7 +3869,7 @@ impl<'a> BlockCompiler<'a> {
             ; add	WORD [rbx + sp_offset as i32], -2
             ; mov WORD [rbx + pc_offset as i32], address as i16
 
-            ;; self.exit_block(ops)
+            ;; self.exit_block_to(ops, address)
         );
     }
 
@@ -4101,6 +4599,17 @@ impl<'a> BlockCompiler<'a> {
         );
     }
 
+    /// Write the host carry flag to the F register, clearing the other flags. Only valid if the
+    /// other flags are not observed.
+    fn carry_to_flags(&mut self, ops: &mut Assembler, carry_only: bool) {
+        let f = offset!(GameBoy, cpu: Cpu, f);
+        dynasm_if!(carry_only, ops
+            ; setb	dl
+            ; shl	dl, 4
+            ; mov	BYTE [rbx + f as i32], dl
+        );
+    }
+
     fn read_mem_reg(&mut self, ops: &mut Assembler, src: Reg, preserve_in_r12: bool) {
         dynasm!(ops
             ; .arch x64
@@ -4278,6 +4787,45 @@ fn reg_offset16(reg: Reg16) -> usize {
     }
 }
 
+/// How the opcode interacts with the register cache. Opcodes not listed here are compiled to
+/// function calls, which clobber the host registers, so the cache is invalidated before them.
+fn reg_cache_use(op: u8) -> RegCacheUse {
+    use RegCacheUse::*;
+    match op {
+        // NOP, DI, EI
+        0x00 | 0xf3 | 0xfb => Cached,
+        // LD r,d8
+        0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x3e => Cached,
+        // INC r, DEC r
+        0x04 | 0x0c | 0x14 | 0x1c | 0x24 | 0x2c | 0x3c => Cached,
+        0x05 | 0x0d | 0x15 | 0x1d | 0x25 | 0x2d | 0x3d => Cached,
+        // LD r,r', except from or to (HL), and HALT
+        0x40..=0x7f if op & 0x07 != 0x06 && (op >> 3) & 0x07 != 0x06 => Cached,
+        // ADD, ADC, SUB, SBC, AND, XOR, OR and CP with a register, except (HL)
+        0x80..=0xbf if op & 0x07 != 0x06 => Cached,
+        // ADD, ADC, SUB, SBC, AND, XOR, OR and CP with a immediate
+        0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe => Cached,
+        // LD SP,d16, INC SP, DEC SP
+        0x31 | 0x33 | 0x3b => Cached,
+        // JR, JP, JP (HL)
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Flush,
+        0xc2 | 0xc3 | 0xca | 0xd2 | 0xda | 0xe9 => Flush,
+        // LD rr,d16, INC rr, DEC rr
+        0x01 | 0x03 | 0x0b => Pairs(&[Reg16::BC]),
+        0x11 | 0x13 | 0x1b => Pairs(&[Reg16::DE]),
+        0x21 | 0x23 | 0x2b => Pairs(&[Reg16::HL]),
+        // ADD HL,rr
+        0x09 => Pairs(&[Reg16::HL, Reg16::BC, Reg16::AF]),
+        0x19 => Pairs(&[Reg16::HL, Reg16::DE, Reg16::AF]),
+        0x29 | 0x39 => Pairs(&[Reg16::HL, Reg16::AF]),
+        // LD SP,HL
+        0xf9 => Pairs(&[Reg16::HL]),
+        // RLCA, RRCA, RLA, RRA, CPL, SCF, CCF
+        0x07 | 0x0f | 0x17 | 0x1f | 0x2f | 0x37 | 0x3f => Pairs(&[Reg16::AF]),
+        _ => Invalidate,
+    }
+}
+
 fn reg_offset(reg: Reg) -> usize {
     match reg {
         Reg::A => offset!(GameBoy, cpu: Cpu, a),
@@ -0,0 +1,93 @@
+use gameroy::{
+    disassembler::Address,
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+};
+
+/// A rom with a loop made of two blocks, at 0x150 and 0x158, that jump to each other.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let program = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&program);
+
+    #[rustfmt::skip]
+    let program = [
+        0x04,             // 0x150: inc b
+        0x80,             // 0x151: add a, b
+        0xC3, 0x58, 0x01, // 0x152: jp 0x158
+        0x00, 0x00, 0x00, // 0x155: nop (unreachable)
+        0x0C,             // 0x158: inc c
+        0x89,             // 0x159: adc a, c
+        0xC3, 0x50, 0x01, // 0x15A: jp 0x150
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+
+    rom
+}
+
+fn run(jit_compiler: &mut gameroy_jit::JitCompiler, gb: &mut GameBoy, cycles: u64) {
+    let target = gb.clock_count + cycles;
+    while gb.clock_count < target {
+        jit_compiler.interpret_block(gb);
+    }
+}
+
+fn address(gb: &GameBoy, pc: u16) -> Address {
+    Address::from_pc(gb.cartridge.curr_bank(), pc).unwrap()
+}
+
+/// A block that is only entered through a link must count as used, or the least recently used
+/// eviction would discard the hottest blocks.
+#[test]
+fn linked_blocks_are_used() {
+    let cartridge = Cartridge::new(test_rom()).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+    run(&mut jit_compiler, &mut gb, 10_000);
+
+    let (a, b) = (address(&gb, 0x150), address(&gb, 0x158));
+    for _ in 0..10 {
+        jit_compiler.interpret_block(&mut gb);
+        let last_used = |x: Address| jit_compiler.blocks[&x].last_used();
+        assert_eq!(last_used(a), last_used(b));
+    }
+}
+
+/// Evicting a block must unlink the blocks that jump to it.
+#[test]
+fn eviction_unlinks_blocks() {
+    let cartridge = Cartridge::new(test_rom()).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+    // only the block being compiled is kept.
+    jit_compiler.opts.max_code_size = Some(0);
+    jit_compiler.opts.check_against_interpreter = true;
+    run(&mut jit_compiler, &mut gb, 10_000);
+    assert_eq!(jit_compiler.blocks.len(), 1);
+
+    // with room for all blocks, nothing is evicted.
+    jit_compiler.flush();
+    jit_compiler.opts.max_code_size = Some(usize::MAX);
+    run(&mut jit_compiler, &mut gb, 10_000);
+    assert_eq!(jit_compiler.blocks.len(), 2);
+}
+
+/// Each combination of the compiler optimizations must produce the same result as the
+/// interpreter.
+#[test]
+fn optimizations_match_interpreter() {
+    for opts in 0..8 {
+        let cartridge = Cartridge::new(test_rom()).unwrap();
+        let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+        let mut jit_compiler = gameroy_jit::JitCompiler::new();
+        jit_compiler.opts.register_allocation = opts & 1 != 0;
+        jit_compiler.opts.flag_optimization = opts & 2 != 0;
+        jit_compiler.opts.block_linking = opts & 4 != 0;
+        jit_compiler.opts.check_against_interpreter = true;
+        run(&mut jit_compiler, &mut gb, 10_000);
+    }
+}
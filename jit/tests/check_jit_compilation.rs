@@ -6,7 +6,7 @@ use std::{
 
 use gameroy::{
     consts::{CLOCK_SPEED, SCREEN_HEIGHT, SCREEN_WIDTH},
-    gameboy::{cartridge::Cartridge, GameBoy},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
 use rayon::prelude::*;
@@ -148,6 +148,30 @@ fn test_four() {
     }
 }
 
+/// EI, DI and RETI change IME in the middle of compiled blocks, and the interrupt dispatch must
+/// happen exactly one instruction after EI.
+#[test]
+fn test_interrupt_timing() {
+    let roms = [
+        "ei_sequence",
+        "ei_timing",
+        "di_timing-GS",
+        "rapid_di_ei",
+        "reti_timing",
+        "reti_intr_timing",
+        "intr_timing",
+        "interrupts/ie_push",
+    ];
+    for rom in roms {
+        let rom = format!("{}mooneye-test-suite/acceptance/{}.gb", TEST_ROM_PATH, rom);
+        let timeout = 10 * CLOCK_SPEED;
+        let ok = test_interrupt_prediction(&rom, timeout);
+        if !ok {
+            panic!("CPU desync in {}!", rom);
+        }
+    }
+}
+
 #[derive(Default)]
 struct VBlank {
     screen_a: Option<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
@@ -172,7 +196,7 @@ fn test_interrupt_prediction(rom: &str, timeout: u64) -> bool {
 
     let mut jit_compiler = gameroy_jit::JitCompiler::new();
 
-    let mut game_boy_a = GameBoy::new(None, cartridge.clone());
+    let mut game_boy_a = GameBoy::new(None, cartridge.clone(), AccuracyConfig::default());
     game_boy_a.predict_interrupt = true;
     game_boy_a.v_blank = Some(Box::new({
         let vblank = vblank.clone();
@@ -186,7 +210,7 @@ fn test_interrupt_prediction(rom: &str, timeout: u64) -> bool {
     }));
     game_boy_a.serial.borrow_mut().serial_transfer_callback = None;
 
-    let mut game_boy_b = GameBoy::new(None, cartridge);
+    let mut game_boy_b = GameBoy::new(None, cartridge, AccuracyConfig::default());
     game_boy_b.predict_interrupt = true;
     game_boy_b.v_blank = Some(Box::new({
         let vblank = vblank.clone();
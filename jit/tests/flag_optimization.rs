@@ -0,0 +1,64 @@
+use gameroy::gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy};
+
+/// A rom with a loop of arithmetic operations of which only the carry is observed, by a rotate or
+/// a conditional jump.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let program = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&program);
+
+    #[rustfmt::skip]
+    let program = [
+        0x06, 0x35,       // 0x150: ld b, 0x35
+        0x0E, 0x9A,       // 0x152: ld c, 0x9A
+        0x80,             // 0x154: add a, b
+        0x17,             // 0x155: rla
+        0x89,             // 0x156: adc a, c
+        0x17,             // 0x157: rla
+        0x90,             // 0x158: sub b
+        0x17,             // 0x159: rla
+        0x99,             // 0x15A: sbc a, c
+        0x17,             // 0x15B: rla
+        0xC6, 0xF1,       // 0x15C: add a, 0xF1
+        0x17,             // 0x15E: rla
+        0xD6, 0x2B,       // 0x15F: sub 0x2B
+        0x38, 0x01,       // 0x161: jr c, 0x164
+        0x0C,             // 0x163: inc c
+        0x91,             // 0x164: sub c
+        0x30, 0x01,       // 0x165: jr nc, 0x168
+        0x04,             // 0x167: inc b
+        0xB7,             // 0x168: or a
+        0x18, 0xE9,       // 0x169: jr 0x154
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+
+    rom
+}
+
+fn run(jit_compiler: &mut gameroy_jit::JitCompiler, gb: &mut GameBoy, cycles: u64) {
+    let target = gb.clock_count + cycles;
+    while gb.clock_count < target {
+        jit_compiler.interpret_block(gb);
+    }
+}
+
+/// ADD, ADC, SUB and SBC followed by a carry consumer only compute the carry flag, which must
+/// match the interpreter, with and without register allocation.
+#[test]
+fn carry_only_matches_interpreter() {
+    for register_allocation in [false, true] {
+        let cartridge = Cartridge::new(test_rom()).unwrap();
+        let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+        let mut jit_compiler = gameroy_jit::JitCompiler::new();
+        jit_compiler.opts.flag_optimization = true;
+        jit_compiler.opts.register_allocation = register_allocation;
+        jit_compiler.opts.check_against_interpreter = true;
+        run(&mut jit_compiler, &mut gb, 100_000);
+        assert!(!jit_compiler.blocks.is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use gameroy::gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy};
+
+/// A rom that loops forever, adding `step` to `b`.
+fn test_rom(step: u8) -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let program = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&program);
+
+    #[rustfmt::skip]
+    let program = [
+        0x3E, step,       // 0x150: ld a, step
+        // loop:
+        0x80,             // 0x152: add a, b
+        0x47,             // 0x153: ld b, a
+        0x3E, step,       // 0x154: ld a, step
+        0x18, 0xFA,       // 0x156: jr loop
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+
+    rom
+}
+
+fn run(jit_compiler: &mut gameroy_jit::JitCompiler, gb: &mut GameBoy) {
+    let target = gb.clock_count + 10_000;
+    while gb.clock_count < target {
+        jit_compiler.interpret_block(gb);
+    }
+}
+
+/// The blocks only depend on the rom, so they are kept after loading a state or resetting.
+#[test]
+fn load_state_keeps_blocks() {
+    let cartridge = Cartridge::new(test_rom(1)).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+    jit_compiler.opts.check_against_interpreter = true;
+
+    let mut state = Vec::new();
+    gb.save_state(None, &mut state).unwrap();
+    run(&mut jit_compiler, &mut gb);
+    assert!(!jit_compiler.blocks.is_empty());
+    let code_size = jit_compiler.code_size();
+
+    gb.load_state(&mut state.as_slice()).unwrap();
+    run(&mut jit_compiler, &mut gb);
+    gb.reset();
+    run(&mut jit_compiler, &mut gb);
+    assert_eq!(jit_compiler.code_size(), code_size);
+}
+
+#[test]
+fn flush_discards_blocks() {
+    let cartridge = Cartridge::new(test_rom(1)).unwrap();
+    let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+    run(&mut jit_compiler, &mut gb);
+
+    // same addresses and banks, but different code.
+    gb.cartridge = Cartridge::new(test_rom(3)).unwrap();
+    gb.reset();
+    jit_compiler.flush();
+    assert!(jit_compiler.blocks.is_empty());
+    assert_eq!(jit_compiler.code_size(), 0);
+
+    // run the new code, and compare with the interpreter.
+    jit_compiler.opts.check_against_interpreter = true;
+    run(&mut jit_compiler, &mut gb);
+}
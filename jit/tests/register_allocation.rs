@@ -0,0 +1,78 @@
+use gameroy::gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy};
+
+/// A rom with a loop that mixes cached register operations with memory accesses, 16-bit
+/// operations and relative jumps.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let program = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&program);
+
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0xC0, // 0x150: ld hl, 0xC000
+        0x0E, 0x0F,       // 0x153: ld c, 0x0F
+        0x3E, 0x01,       // 0x155: ld a, 0x01
+        0x06, 0x10,       // 0x157: ld b, 0x10
+        0x80,             // 0x159: add a, b
+        0x89,             // 0x15A: adc a, c
+        0x0C,             // 0x15B: inc c
+        0x91,             // 0x15C: sub c
+        0x98,             // 0x15D: sbc a, b
+        0xA1,             // 0x15E: and c
+        0xA8,             // 0x15F: xor b
+        0xB1,             // 0x160: or c
+        0xB8,             // 0x161: cp b
+        0x17,             // 0x162: rla
+        0xCE, 0x35,       // 0x163: adc a, 0x35
+        0xDE, 0x17,       // 0x165: sbc a, 0x17
+        0x77,             // 0x167: ld (hl), a
+        0x23,             // 0x168: inc hl
+        0x54,             // 0x169: ld d, h
+        0x5D,             // 0x16A: ld e, l
+        0x1C,             // 0x16B: inc e
+        0x83,             // 0x16C: add a, e
+        0x2F,             // 0x16D: cpl
+        0x05,             // 0x16E: dec b
+        0x20, 0xE8,       // 0x16F: jr nz, 0x159
+        0x38, 0x01,       // 0x171: jr c, 0x174
+        0xAF,             // 0x173: xor a
+        0x8F,             // 0x174: adc a, a
+        0x7D,             // 0x175: ld a, l
+        0xE6, 0x7F,       // 0x176: and 0x7F
+        0x6F,             // 0x178: ld l, a
+        0x26, 0xC0,       // 0x179: ld h, 0xC0
+        0x7A,             // 0x17B: ld a, d
+        0x18, 0xD9,       // 0x17C: jr 0x157
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+
+    rom
+}
+
+fn run(jit_compiler: &mut gameroy_jit::JitCompiler, gb: &mut GameBoy, cycles: u64) {
+    let target = gb.clock_count + cycles;
+    while gb.clock_count < target {
+        jit_compiler.interpret_block(gb);
+    }
+}
+
+/// Registers kept in host registers must be written back before any memory access or block exit,
+/// with and without register allocation.
+#[test]
+fn register_allocation_matches_interpreter() {
+    for opts in 0..4 {
+        let cartridge = Cartridge::new(test_rom()).unwrap();
+        let mut gb = GameBoy::new(None, cartridge, AccuracyConfig::default());
+        let mut jit_compiler = gameroy_jit::JitCompiler::new();
+        jit_compiler.opts.register_allocation = opts & 1 != 0;
+        jit_compiler.opts.flag_optimization = opts & 2 != 0;
+        jit_compiler.opts.check_against_interpreter = true;
+        run(&mut jit_compiler, &mut gb, 100_000);
+        assert!(!jit_compiler.blocks.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use gameroy::{
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// A rom that loops forever, incrementing a counter in memory. The loop is long enough to be
+/// compiled in a single block.
+fn test_rom() -> Vec<u8> {
+    let mut rom = Cartridge::halt_filled().rom;
+
+    #[rustfmt::skip]
+    let program = [
+        0x00,             // 0x100: nop
+        0xC3, 0x50, 0x01, // 0x101: jp 0x150
+    ];
+    rom[0x100..0x104].copy_from_slice(&program);
+
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0xC0, // 0x150: ld hl, 0xC000
+        // loop:
+        0x34,             // 0x153: inc (hl)
+        0x04,             // 0x154: inc b
+        0x0C,             // 0x155: inc c
+        0x14,             // 0x156: inc d
+        0x1C,             // 0x157: inc e
+        0x78,             // 0x158: ld a, b
+        0x81,             // 0x159: add a, c
+        0x82,             // 0x15A: add a, d
+        0x83,             // 0x15B: add a, e
+        0x77,             // 0x15C: ld (hl), a
+        0x18, 0xF4,       // 0x15D: jr loop
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+
+    rom
+}
+
+/// With a stop clock, the JIT must stop at the same instruction as the interpreter.
+#[test]
+fn jit_stops_at_stop_clock() {
+    let cartridge = Cartridge::new(test_rom()).unwrap();
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+
+    let mut game_boy_a = GameBoy::new(None, cartridge.clone(), AccuracyConfig::default());
+    let mut game_boy_b = GameBoy::new(None, cartridge, AccuracyConfig::default());
+
+    let start = game_boy_a.clock_count;
+    for target in (1..100).map(|x| start + x * 1001) {
+        game_boy_a.set_stop_clock(Some(target));
+        while game_boy_a.clock_count < target {
+            jit_compiler.interpret_block(&mut game_boy_a);
+        }
+        game_boy_a.set_stop_clock(None);
+
+        while game_boy_b.clock_count < target {
+            Interpreter(&mut game_boy_b).interpret_op();
+        }
+
+        assert_eq!(game_boy_a.clock_count, game_boy_b.clock_count);
+        assert_eq!(game_boy_a.cpu, game_boy_b.cpu);
+        assert_eq!(game_boy_a.read(0xC000), game_boy_b.read(0xC000));
+    }
+}
@@ -6,6 +6,7 @@ use std::{
 };
 
 use gameroy::{
+    consts::{CLOCK_SPEED, FRAME_CYCLES},
     gameboy::{cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
 };
@@ -178,7 +179,7 @@ pub extern "C" fn retro_get_system_av_info(info: &mut MaybeUninit<retro_system_a
             aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
         },
         timing: retro_system_timing {
-            fps: 60.0,
+            fps: CLOCK_SPEED as f64 / FRAME_CYCLES as f64,
             sample_rate: SAMPLE_RATE as f64,
         },
     };
@@ -204,11 +205,12 @@ extern "C" fn retro_load_game(info: Option<&retro_game_info>) -> bool {
         }
     };
 
-    let mut gb = GameBoy::new(None, cartridge);
+    let mut gb = GameBoy::builder(cartridge)
+        .v_blank(Box::new(|gb| {
+            *core().screen_buffer.borrow_mut() = gb.ppu.get_mut().screen.packed();
+        }))
+        .build();
     gb.sound.get_mut().sample_frequency = SAMPLE_RATE;
-    gb.v_blank = Some(Box::new(|gb| {
-        *core().screen_buffer.borrow_mut() = gb.ppu.get_mut().screen.packed();
-    }));
 
     *core().state.borrow_mut() = Some(gb);
 
@@ -250,7 +252,10 @@ pub extern "C" fn retro_run() {
         }
     }
 
-    let target = core.state_mut().clock_count + gameroy::consts::FRAME_CYCLES;
+    // Run until the end of the current frame. The frames have fixed boundaries, so the cycles that
+    // an instruction overshoots are not added to every frame, and the audio don't drift from the
+    // frame rate reported to the frontend.
+    let target = (core.state_mut().clock_count / FRAME_CYCLES + 1) * FRAME_CYCLES;
     while core.state_mut().clock_count < target {
         Interpreter(&mut core.state_mut()).interpret_op();
     }
@@ -280,9 +285,12 @@ pub extern "C" fn retro_run() {
 
     if let Some(callback) = core.audio_callback.get() {
         unsafe {
-            let clock_count = core.state_mut().clock_count;
-            let buffer = core.state_mut().sound.get_mut().get_output(clock_count);
-            let buffer: Vec<i16> = buffer.into_iter().map(|x| (x as i16 - 128) * 30).collect();
+            let mut gb = core.state_mut();
+            let clock_count = gb.clock_count;
+            let sound = gb.sound.get_mut();
+            let samples = sound.samples_until(clock_count);
+            let buffer = sound.get_filtered_output(clock_count);
+            debug_assert_eq!(buffer.len() as u64, 2 * samples);
             (callback)(buffer.as_ptr(), buffer.len() as u64 / 2);
         }
     }
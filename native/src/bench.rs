@@ -53,7 +53,11 @@ pub fn benchmark(
         interpreter,
         mut jit,
         flag_optimization,
+        register_allocation,
+        block_linking,
+        max_code_size,
         emit_perf_map,
+        check_jit,
     }: Bench,
 ) {
     let predict_interrupt = !no_prediction;
@@ -73,13 +77,11 @@ pub fn benchmark(
     };
 
     let cartridge = Cartridge::new(rom).unwrap();
-    let mut game_boy = GameBoy::new(None, cartridge);
-    game_boy.predict_interrupt = predict_interrupt;
-
-    // remove serial transfer console output
-    game_boy.serial.get_mut().serial_transfer_callback = None;
-
-    game_boy.reset();
+    let mut game_boy = GameBoy::builder(cartridge)
+        .predict_interrupt(predict_interrupt)
+        // remove serial transfer console output
+        .serial_sink(None)
+        .build();
     let start_clock_count = game_boy.clock_count;
     let timeout = start_clock_count + frames * gameroy_lib::gameroy::consts::FRAME_CYCLES;
 
@@ -105,8 +107,12 @@ pub fn benchmark(
             timeout,
             CompilerOpts {
                 flag_optimization,
+                register_allocation,
                 #[cfg(target_os = "linux")]
                 emit_perf_map,
+                check_against_interpreter: check_jit,
+                block_linking,
+                max_code_size,
             },
         );
 
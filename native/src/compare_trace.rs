@@ -0,0 +1,233 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use gameroy_lib::{
+    gameroy::{gameboy::GameBoy, interpreter::Interpreter},
+    rom_loading::load_gameboy_with_spec,
+};
+
+/// The state of the CPU in a single line of a trace.
+///
+/// All fields are optional, because each emulator logs a different set of registers.
+#[derive(Default, Debug)]
+struct TraceLine {
+    a: Option<u8>,
+    f: Option<u8>,
+    b: Option<u8>,
+    c: Option<u8>,
+    d: Option<u8>,
+    e: Option<u8>,
+    h: Option<u8>,
+    l: Option<u8>,
+    sp: Option<u16>,
+    pc: Option<u16>,
+    /// The clock count, in T-cycles.
+    cycles: Option<u64>,
+}
+
+/// Parse the value of the F register. It can be given in hexadecimal (`B0`), or as a list of set
+/// flags (`Z-HC`, `znhc`, etc).
+fn parse_flags(value: &str) -> Option<u8> {
+    if value.len() == 4 {
+        let mut f = 0;
+        for (c, bit) in value.chars().zip([7, 6, 5, 4]) {
+            if c.is_ascii_uppercase() {
+                f |= 1 << bit;
+            }
+        }
+        return Some(f);
+    }
+    u8::from_str_radix(value, 16).ok()
+}
+
+/// Parse a line of a CPU trace.
+///
+/// This understands any line composed of `KEY:VALUE` pairs, like the ones emitted by BGB, binjgb
+/// or the gameboy-doctor format, for example:
+///
+/// ```text
+/// A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+/// A:01 F:Z-HC BC:0013 DE:00d8 HL:014d SP:fffe PC:0100 (cy: 0) ppu:+0 |[00]0x0100: 00 nop
+/// ```
+///
+/// Unknown keys are ignored. Returns None if the line has no known key.
+fn parse_line(line: &str) -> Option<TraceLine> {
+    let line = line.replace(['(', ')'], " ").replace(": ", ":");
+    let mut trace = TraceLine::default();
+    let mut any = false;
+    for token in line.split_whitespace() {
+        let Some((key, value)) = token.split_once(':') else {
+            continue;
+        };
+        let u8_value = || u8::from_str_radix(value, 16).ok();
+        let u16_value = || u16::from_str_radix(value, 16).ok();
+        let pair = || u16_value().map(|x| ((x >> 8) as u8, x as u8));
+        match key.to_ascii_uppercase().as_str() {
+            "A" => trace.a = u8_value(),
+            "F" => trace.f = parse_flags(value),
+            "B" => trace.b = u8_value(),
+            "C" => trace.c = u8_value(),
+            "D" => trace.d = u8_value(),
+            "E" => trace.e = u8_value(),
+            "H" => trace.h = u8_value(),
+            "L" => trace.l = u8_value(),
+            "AF" => (trace.a, trace.f) = pair().map_or((None, None), |(a, f)| (Some(a), Some(f))),
+            "BC" => (trace.b, trace.c) = pair().map_or((None, None), |(b, c)| (Some(b), Some(c))),
+            "DE" => (trace.d, trace.e) = pair().map_or((None, None), |(d, e)| (Some(d), Some(e))),
+            "HL" => (trace.h, trace.l) = pair().map_or((None, None), |(h, l)| (Some(h), Some(l))),
+            "SP" => trace.sp = u16_value(),
+            "PC" => trace.pc = u16_value(),
+            "CY" | "CYC" | "CYCLES" => trace.cycles = value.parse().ok(),
+            _ => continue,
+        }
+        any = true;
+    }
+    any.then_some(trace)
+}
+
+/// Compare the state of the GameBoy against a line of the trace. Return a description of each
+/// mismatch.
+fn compare(gb: &GameBoy, trace: &TraceLine, cycle_offset: Option<i128>) -> Vec<String> {
+    let cpu = &gb.cpu;
+    let mut diffs = Vec::new();
+    let mut check = |name: &str, expected: Option<u16>, actual: u16| {
+        if let Some(expected) = expected {
+            if expected != actual {
+                diffs.push(format!(
+                    "{name}: expected {expected:04x}, found {actual:04x}"
+                ));
+            }
+        }
+    };
+    check("A", trace.a.map(u16::from), cpu.a as u16);
+    check("F", trace.f.map(u16::from), cpu.f.0 as u16);
+    check("B", trace.b.map(u16::from), cpu.b as u16);
+    check("C", trace.c.map(u16::from), cpu.c as u16);
+    check("D", trace.d.map(u16::from), cpu.d as u16);
+    check("E", trace.e.map(u16::from), cpu.e as u16);
+    check("H", trace.h.map(u16::from), cpu.h as u16);
+    check("L", trace.l.map(u16::from), cpu.l as u16);
+    check("SP", trace.sp, cpu.sp);
+    check("PC", trace.pc, cpu.pc);
+
+    if let (Some(cycles), Some(offset)) = (trace.cycles, cycle_offset) {
+        let expected = cycles as i128 + offset;
+        if expected != gb.clock_count as i128 {
+            diffs.push(format!(
+                "cycles: expected {}, found {} (relative to the start of the trace)",
+                cycles,
+                gb.clock_count as i128 - offset
+            ));
+        }
+    }
+
+    diffs
+}
+
+/// Step the interpreter while reading a reference CPU trace, and stop at the first divergence.
+///
+/// Each line of the trace is expected to contain the state of the CPU before the execution of
+/// each instruction. The cycle count of the trace is compared relative to the first line, because
+/// each emulator starts counting at a different point.
+pub fn compare_trace(rom_path: &str, trace_path: &str, mbc: Option<&str>) -> Result<(), String> {
+    let rom =
+        std::fs::read(rom_path).map_err(|e| format!("failed to load '{}': {}", rom_path, e))?;
+    let trace = std::fs::File::open(trace_path)
+        .map_err(|e| format!("failed to open '{}': {}", trace_path, e))?;
+
+    let file_name = Path::new(rom_path)
+        .file_name()
+        .map_or("".into(), |x| x.to_string_lossy());
+    let mut gb = load_gameboy_with_spec(rom, None, mbc, &file_name)?;
+
+    let lines = BufReader::new(trace)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match line {
+            Ok(line) => parse_line(&line).map(|x| Ok((i + 1, line, x))),
+            Err(e) => Some(Err(e.to_string())),
+        });
+
+    let mut cycle_offset = None;
+    let mut last_line = None;
+    let mut count = 0u64;
+    for line in lines {
+        let (number, text, trace) = line?;
+
+        if count == 0 {
+            // traces usually start after the boot rom, so skip it if necessary.
+            if trace.pc == Some(0x0100) {
+                while gb.boot_rom_active {
+                    Interpreter(&mut gb).interpret_op();
+                }
+            }
+            cycle_offset = trace
+                .cycles
+                .map(|cycles| gb.clock_count as i128 - cycles as i128);
+        }
+
+        let diffs = compare(&gb, &trace, cycle_offset);
+        if !diffs.is_empty() {
+            println!("divergence at line {} (instruction {}):", number, count);
+            if let Some((number, text)) = last_line {
+                println!("  {:>6}: {}", number, text);
+            }
+            println!("> {:>6}: {}", number, text);
+            for diff in diffs {
+                println!("    {}", diff);
+            }
+            println!("gameroy state:");
+            print!("{}", gb.cpu);
+            println!("clock_count {}", gb.clock_count);
+            return Err("traces diverged".to_string());
+        }
+
+        Interpreter(&mut gb).interpret_op();
+        last_line = Some((number, text));
+        count += 1;
+    }
+
+    println!("no divergence found after {} instructions", count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_line;
+
+    #[test]
+    fn parse_gameboy_doctor() {
+        let line = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+        let trace = parse_line(line).unwrap();
+        assert_eq!(trace.a, Some(0x01));
+        assert_eq!(trace.f, Some(0xB0));
+        assert_eq!(trace.c, Some(0x13));
+        assert_eq!(trace.l, Some(0x4D));
+        assert_eq!(trace.sp, Some(0xFFFE));
+        assert_eq!(trace.pc, Some(0x0100));
+        assert_eq!(trace.cycles, None);
+    }
+
+    #[test]
+    fn parse_binjgb() {
+        let line =
+            "A:01 F:Z-HC BC:0013 DE:00d8 HL:014d SP:fffe PC:0100 (cy: 52) ppu:+0 |[00]0x0100: 00 nop";
+        let trace = parse_line(line).unwrap();
+        assert_eq!(trace.a, Some(0x01));
+        assert_eq!(trace.f, Some(0xB0));
+        assert_eq!(trace.b, Some(0x00));
+        assert_eq!(trace.c, Some(0x13));
+        assert_eq!(trace.e, Some(0xD8));
+        assert_eq!(trace.h, Some(0x01));
+        assert_eq!(trace.pc, Some(0x0100));
+        assert_eq!(trace.cycles, Some(52));
+    }
+
+    #[test]
+    fn ignore_other_lines() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("; some comment").is_none());
+    }
+}
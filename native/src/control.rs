@@ -0,0 +1,238 @@
+//! A headless control server, for driving the emulator from external programs, like test drivers
+//! or agents.
+//!
+//! The server listens on a local TCP address (or a Unix socket, with the `unix:` prefix), and
+//! serves one client at a time. The emulated GameBoy is kept between connections. Each request is
+//! a JSON object in a single line, and each one is answered by a single line:
+//!
+//! ```text
+//! {"cmd":"load_rom","path":"game.gb"}            -> {"ok":true}
+//! {"cmd":"press","buttons":["a","start"]}        -> {"ok":true}
+//! {"cmd":"run_frames","frames":60}               -> {"ok":true,"clock_count":1053696}
+//! {"cmd":"read_memory","address":49152,"length":2} -> {"ok":true,"data":[0,255]}
+//! {"cmd":"screen_hash"}                          -> {"ok":true,"hash":"1a2b3c4d"}
+//! {"cmd":"branch","name":"before boss"}          -> {"ok":true,"id":3}
+//! {"cmd":"load_branch","id":3}                   -> {"ok":true,"clock_count":1053696}
+//! ```
+//!
+//! Errors are answered with `{"ok":false,"error":"<message>"}`. The buttons given to `press` are
+//! held until the next `press`. Each frame runs a fixed number of clock cycles, so the same
+//! requests always produce the same results. `branch` and `load_branch` use the same state tree
+//! of the rom as the emulator UI.
+
+use std::io::{BufRead, BufReader, Write};
+
+use gameroy_lib::{
+    game_database::crc32,
+    gameroy::{consts::FRAME_CYCLES, gameboy::GameBoy, interpreter::Interpreter},
+    rom_loading::load_gameboy,
+    state_tree::StateTree,
+    RomFile,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    LoadRom {
+        path: String,
+    },
+    Press {
+        buttons: Vec<String>,
+    },
+    RunFrames {
+        frames: u32,
+    },
+    ReadMemory {
+        address: u16,
+        length: u16,
+    },
+    ScreenHash,
+    Branch {
+        #[serde(default)]
+        name: String,
+    },
+    LoadBranch {
+        id: u32,
+    },
+}
+
+struct Server {
+    gb: Option<Box<GameBoy>>,
+    /// The pressed buttons, with the same layout of `GameBoy::joypad`, but active-high.
+    buttons: u8,
+    /// The file of the loaded rom, and its state tree.
+    states: Option<(RomFile, StateTree)>,
+}
+impl Server {
+    fn gb(&mut self) -> Result<&mut GameBoy, String> {
+        self.gb
+            .as_deref_mut()
+            .ok_or_else(|| "no rom loaded".to_string())
+    }
+
+    fn gb_and_states(&mut self) -> Result<(&mut GameBoy, &mut (RomFile, StateTree)), String> {
+        match (self.gb.as_deref_mut(), self.states.as_mut()) {
+            (Some(gb), Some(states)) => Ok((gb, states)),
+            _ => Err("no rom loaded".to_string()),
+        }
+    }
+
+    fn handle(&mut self, request: Request) -> Result<Value, String> {
+        match request {
+            Request::LoadRom { path } => {
+                let rom = std::fs::read(&path)
+                    .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                let file = RomFile::from_path(path.into());
+                self.gb = Some(load_gameboy(rom, None, &file.file_name())?);
+                self.buttons = 0;
+                let tree = StateTree::load(&file);
+                self.states = Some((file, tree));
+                Ok(json!({ "ok": true }))
+            }
+            Request::Press { buttons } => {
+                let mut pressed = 0;
+                for button in &buttons {
+                    pressed |= 1 << button_bit(button)?;
+                }
+                self.buttons = pressed;
+                Ok(json!({ "ok": true }))
+            }
+            Request::RunFrames { frames } => {
+                let buttons = self.buttons;
+                let gb = self.gb()?;
+                gb.joypad = !buttons;
+                run_frames(gb, frames as u64);
+                Ok(json!({ "ok": true, "clock_count": gb.clock_count }))
+            }
+            Request::ReadMemory { address, length } => {
+                let gb = self.gb()?;
+                let data: Vec<u8> = (0..length)
+                    .map(|i| gb.peek(address.wrapping_add(i)))
+                    .collect();
+                Ok(json!({ "ok": true, "data": data }))
+            }
+            Request::ScreenHash => {
+                let gb = self.gb()?;
+                Ok(json!({ "ok": true, "hash": screen_hash(gb) }))
+            }
+            Request::Branch { name } => {
+                let (gb, (file, tree)) = self.gb_and_states()?;
+                let mut state = Vec::new();
+                gb.save_state(None, &mut state)
+                    .map_err(|e| format!("failed to save state: {}", e))?;
+                let id = tree.branch(file, name, gb.clock_count, &state)?;
+                Ok(json!({ "ok": true, "id": id }))
+            }
+            Request::LoadBranch { id } => {
+                let (gb, (file, tree)) = self.gb_and_states()?;
+                let state = tree.checkout(file, id)?;
+                gb.load_state(&mut state.as_slice())
+                    .map_err(|e| format!("failed to load state: {:?}", e))?;
+                Ok(json!({ "ok": true, "clock_count": gb.clock_count }))
+            }
+        }
+    }
+
+    /// Answer the requests of a client, until it disconnects.
+    fn serve(&mut self, stream: impl std::io::Read + Write) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = serde_json::from_str::<Request>(&line)
+                .map_err(|e| format!("invalid request: {}", e))
+                .and_then(|request| self.handle(request))
+                .unwrap_or_else(|error| json!({ "ok": false, "error": error }));
+            let stream = reader.get_mut();
+            writeln!(stream, "{}", response)?;
+            stream.flush()?;
+        }
+    }
+}
+
+/// Run the given number of frames, each one of a fixed number of clock cycles.
+pub fn run_frames(gb: &mut GameBoy, frames: u64) {
+    let target = gb.clock_count + frames * FRAME_CYCLES;
+    while gb.clock_count < target {
+        Interpreter(gb).interpret_op();
+    }
+}
+
+/// The CRC32 of the current screen, in hexadecimal.
+pub fn screen_hash(gb: &GameBoy) -> String {
+    let ppu = gb.ppu.borrow();
+    let screen: Vec<u8> = ppu.screen.row_iter().flatten().copied().collect();
+    format!("{:08x}", crc32(&screen))
+}
+
+/// The bit of a button in `GameBoy::joypad`.
+pub fn button_bit(button: &str) -> Result<u8, String> {
+    Ok(match button {
+        "right" => 0,
+        "left" => 1,
+        "up" => 2,
+        "down" => 3,
+        "a" => 4,
+        "b" => 5,
+        "select" => 6,
+        "start" => 7,
+        _ => return Err(format!("unknown button '{}'", button)),
+    })
+}
+
+/// Run the control server at the given address, until the process is killed. If `rom_path` is
+/// given, the rom is loaded before accepting connections.
+pub fn run(address: &str, rom_path: Option<&str>) -> Result<(), String> {
+    let mut server = Server {
+        gb: None,
+        buttons: 0,
+        states: None,
+    };
+    if let Some(path) = rom_path {
+        server.handle(Request::LoadRom { path: path.into() })?;
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        use std::os::unix::fs::FileTypeExt;
+
+        let path = std::path::Path::new(path);
+        // remove the socket left by a previous run.
+        if std::fs::metadata(path).is_ok_and(|x| x.file_type().is_socket()) {
+            let _ = std::fs::remove_file(path);
+        }
+        let listener = std::os::unix::net::UnixListener::bind(path)
+            .map_err(|e| format!("failed to bind '{}': {}", path.display(), e))?;
+        eprintln!("control server listening on {}", path.display());
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| e.to_string())?;
+            if let Err(err) = server.serve(stream) {
+                log::error!("control connection closed: {}", err);
+            }
+        }
+        return Ok(());
+    }
+
+    let listener = std::net::TcpListener::bind(address)
+        .map_err(|e| format!("failed to bind '{}': {}", address, e))?;
+    let local_address = listener.local_addr().map_err(|e| e.to_string())?;
+    if !local_address.ip().is_loopback() {
+        log::warn!("the control server is reachable from other machines");
+    }
+    eprintln!("control server listening on {}", local_address);
+    for stream in listener.incoming() {
+        let stream = stream.map_err(|e| e.to_string())?;
+        if let Err(err) = server.serve(stream) {
+            log::error!("control connection closed: {}", err);
+        }
+    }
+    Ok(())
+}
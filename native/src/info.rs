@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use gameroy_lib::{
+    game_database,
+    gameroy::{
+        gameboy::cartridge::Cartridge,
+        parser::{vbm, Vbm, VbmStart},
+    },
+};
+
+/// Print the information in the rom header, the entry of the game database, and any problem found
+/// while validating the rom.
+pub fn print_info(rom_path: &str, mbc: Option<&str>) -> Result<(), String> {
+    let rom =
+        std::fs::read(rom_path).map_err(|e| format!("failed to load '{}': {}", rom_path, e))?;
+    let crc32 = game_database::crc32(&rom);
+
+    let cartridge = match Cartridge::new_with_spec_str(rom, mbc) {
+        Ok(x) | Err((_, Some(x))) => x,
+        Err((err, None)) => return Err(format!("failed to load rom: {}", err)),
+    };
+    let header = &cartridge.header;
+
+    println!("file:          {}", rom_path);
+    println!("header title:  {}", header.title_as_string());
+    println!("cartridge:     {}", cartridge.kind_name());
+    println!("rom size:      {} bytes", cartridge.rom.len());
+    println!("ram size:      {} bytes", cartridge.ram.len());
+    println!("version:       {}", header.version);
+    println!("crc32:         {:08X}", crc32);
+
+    match game_database::lookup_hash(crc32) {
+        Some(game) => {
+            println!("title:         {}", game.title);
+            println!("region:        {}", game.region);
+            if !game.revision.is_empty() {
+                println!("revision:      {}", game.revision);
+            }
+            for note in &game.notes {
+                println!("note:          {}", note);
+            }
+        }
+        None => println!("title:         (not in the game database)"),
+    }
+
+    for warning in cartridge.validation().warnings() {
+        println!("warning:       {}", warning);
+    }
+
+    Ok(())
+}
+
+/// Open and parse the movie file at `path`.
+pub fn load_movie(path: &Path) -> Result<Vbm, String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("failed to open '{}': {}", path.display(), e))?;
+    vbm(&mut file).map_err(|e| format!("failed to parse '{}': {}", path.display(), e))
+}
+
+/// Print the header fields of the movie.
+pub fn print_movie_info(path: &Path, movie: &Vbm) {
+    let start = match movie.start() {
+        VbmStart::PowerOn => "power on".to_string(),
+        VbmStart::Sram(x) => format!("reset, with {} bytes of SRAM", x.len()),
+        VbmStart::SaveState(x) => format!("embedded save state, of {} bytes", x.len()),
+        VbmStart::SaveStateReference(crc) => format!("save state with CRC32 {:08X}", crc),
+    };
+    let rom_title = movie.rom_title.split(|&x| x == 0).next().unwrap_or(&[]);
+
+    println!("file:          {}", path.display());
+    println!("revision:      {}.{}", movie.version, movie.vbm_version);
+    println!("author:        {}", movie.name);
+    println!("description:   {}", movie.description);
+    println!("uid:           {}", movie.uid);
+    println!("frames:        {}", movie.length_frames);
+    println!("rerecords:     {}", movie.rerecord_count);
+    println!("start:         {}", start);
+    println!("controllers:   {:02x}", movie.controller_flags);
+    println!("system:        {:02x}", movie.type_flags);
+    println!("options:       {:02x}", movie.options_flags);
+    println!("rom title:     {}", String::from_utf8_lossy(rom_title));
+    println!("rom checksum:  {:04X}", movie.rom_or_bios_checksum);
+}
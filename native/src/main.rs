@@ -7,13 +7,22 @@
     windows_subsystem = "console"
 )]
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{ArgAction, Args, Parser, Subcommand};
 use gameroy_lib::config::parse_screen_size;
-use gameroy_lib::{config, gameroy, rom_loading::load_gameboy_with_spec, RomFile};
+use gameroy_lib::{
+    config, gameroy,
+    rom_loading::{load_gameboy_with_spec, start_movie, MovieAnnotations, MovieSync},
+    RomFile,
+};
 
 mod bench;
+mod compare_trace;
+mod control;
+mod info;
+mod stdin_input;
+mod sync_movie;
 
 // this struct is a mirror of gameroy_lib::Config.
 #[derive(Parser)]
@@ -38,18 +47,129 @@ pub struct Cli {
     #[arg(long, requires("rom_path"))]
     disassembly: bool,
 
+    /// Output to stdout information about the rom
+    ///
+    /// Shows the cartridge header, the canonical title, region and emulation notes of the game
+    /// (if it is in the game database), and any problem found while validating the rom.
+    #[arg(long, requires("rom_path"))]
+    info: bool,
+
+    /// Compare the execution of the rom against a reference CPU trace
+    ///
+    /// Steps the interpreter while reading a trace generated by other emulator (like BGB or
+    /// binjgb), one instruction per line, and stops at the first divergence in the registers or
+    /// in the cycle count.
+    #[arg(long, value_name = "FILE", requires("rom_path"))]
+    compare_trace: Option<String>,
+
+    /// Run headless, driven through a local control server at the given address
+    ///
+    /// The address is either a TCP address, like "127.0.0.1:9473", or a Unix socket path prefixed
+    /// with "unix:". Clients send one JSON request per line, to load roms, press buttons, run
+    /// frames, read memory and hash the screen.
+    #[arg(long, value_name = "ADDRESS")]
+    control: Option<String>,
+
+    /// Run headless, with the buttons read from stdin, and write the hash of each frame to stdout
+    ///
+    /// Each line of the input is a frame number followed by the buttons held from that frame on,
+    /// like "120 a start", or only the frame number to release all buttons. After each frame, its
+    /// number and the CRC32 of the screen are written, until the frame of the last line.
+    #[arg(long, requires("rom_path"), conflicts_with("control"))]
+    stdin_input: bool,
+
+    /// Run all the given roms at the same time, showing their screens in a grid
+    ///
+    /// Each rom runs in its own thread, at real speed. Only the selected game receives the input of
+    /// the keyboard and is heard, and the selection is changed with Tab or by clicking a screen.
+    /// Each game loads and saves its cartridge RAM like when played alone.
+    #[arg(
+        long,
+        value_name = "ROM",
+        num_args = 1..,
+        conflicts_with_all(["rom_path", "control", "stdin_input"])
+    )]
+    wall: Vec<String>,
+
+    /// Connect to the DMG-07 4-player adapter hub at the given address, like "127.0.0.1:5207"
+    #[arg(long, value_name = "ADDRESS")]
+    link: Option<String>,
+
+    /// Run headless a DMG-07 4-player adapter hub, listening for players at the given address
+    ///
+    /// Each player runs a instance with `--link <ADDRESS>`. The players are numbered in the order
+    /// they connect.
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with_all(["rom_path", "control", "stdin_input", "wall", "link"])
+    )]
+    dmg07_hub: Option<String>,
+
+    /// Open the N-th most recently opened rom (1 is the last one)
+    #[arg(long, value_name = "N", conflicts_with("rom_path"))]
+    recent: Option<usize>,
+
     /// Play the given .vbm file
+    ///
+    /// If there is a '<movie>.sync.toml' file next to it, its DIV offset and RAM seed are applied
+    /// to the start of the movie. See --sync-movie.
     #[arg(long)]
     movie: Option<String>,
 
+    /// The annotations shown over the screen while the movie given by --movie is played
+    ///
+    /// Each line is a frame of the movie and a text, like "120 skipping the intro". Defaults to
+    /// '<movie>.annotations.txt'. Annotations can also be added with the `annotate` debugger
+    /// command.
+    #[arg(long, value_name = "FILE", requires("movie"))]
+    annotations: Option<PathBuf>,
+
+    /// Output to stdout the header of the movie given by --movie, without playing it
+    ///
+    /// Shows the revision, rerecord count, author, description, frame count, start and controller
+    /// flags of the movie.
+    #[arg(long, requires("movie"))]
+    movie_info: bool,
+
+    /// Search the initial conditions that make the movie end with the given screen hash
+    ///
+    /// Replays the movie given by --movie headlessly, for each DIV offset up to
+    /// --sync-max-div-offset and each RAM seed counted by --sync-seeds, until the CRC32 of the
+    /// screen at the end of the movie (the same of the `screen_hash` request of --control) is
+    /// HASH. The conditions found are saved to '<movie>.sync.toml'. Useful for repairing movies
+    /// that desync.
+    #[arg(long, value_name = "HASH", requires_all(["rom_path", "movie"]))]
+    sync_movie: Option<String>,
+
+    /// The largest DIV offset tried by --sync-movie, in clock cycles
+    #[arg(long, value_name = "CYCLES", default_value_t = 64)]
+    sync_max_div_offset: u16,
+
+    /// The number of random RAM seeds tried by --sync-movie, counting from the movie uid
+    ///
+    /// The configured initial RAM is always tried first.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    sync_seeds: u64,
+
     /// Specify the path of the folder for listing .gb roms
     #[arg(long = "rom_folder", value_name = "PATH")]
     rom_folder: Option<String>,
 
     /// Dump of the bootrom to be used
+    ///
+    /// Can be larger than 256 bytes: the bytes after 0x1FF are mapped from 0x200 on, like the CGB
+    /// boot rom. Overrides the boot rom selected by --model.
     #[arg(long = "boot_rom", value_name = "PATH")]
     boot_rom: Option<String>,
 
+    /// The hardware model whose boot rom, from `boot_roms` in gameroy.toml, is run
+    ///
+    /// Either "dmg", "mgb", "sgb" or "cgb". Only the boot rom changes, the emulation is always of
+    /// a DMG.
+    #[arg(long, value_name = "MODEL")]
+    model: Option<config::Model>,
+
     /// Enables/disables rewinding
     #[arg(long, action = ArgAction::Set, value_name = "BOOL")]
     rewinding: Option<bool>,
@@ -58,6 +178,12 @@ pub struct Cli {
     #[arg(long, action = ArgAction::Set, value_name = "BOOL")]
     interrupt_prediction: Option<bool>,
 
+    /// Listen on the given port for connections from gdb
+    ///
+    /// Only available when built with the `gdb` feature.
+    #[arg(long, value_name = "PORT")]
+    gdb: Option<u16>,
+
     /// If the emulation will start running at max speed
     #[arg(long)]
     frame_skip: bool,
@@ -70,6 +196,58 @@ pub struct Cli {
     #[arg(long)]
     interpreter: bool,
 
+    /// Make the emulation independent of the host
+    ///
+    /// Save states don't include the save time, the saved cartridge ram is not loaded, the RTC
+    /// time is taken from the movie, and each frame always emulate the same amount of clock
+    /// cycles. Useful for TAS and testing.
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Reload the rom each time its file changes
+    ///
+    /// Useful for homebrew development: rebuilding the rom restarts the game, without touching
+    /// the emulator. What is kept between reloads is selected with --reload-mode.
+    #[arg(long, requires("rom_path"))]
+    watch: bool,
+
+    /// What to keep when the rom is reloaded by --watch
+    ///
+    /// Either "restart" (start from a clean state), "keep-ram" (keep the cartridge RAM) or
+    /// "load-state" (load the save state of the rom after restarting).
+    #[arg(long, value_name = "MODE")]
+    reload_mode: Option<config::ReloadMode>,
+
+    /// Exit after emulating the given number of frames, like "600f", or seconds, like "10s"
+    ///
+    /// The time is the one of the emulated Game Boy, so it does not depend on the speed of the
+    /// emulation. Together with --exit-screenshot and --exit-save-state, it supports kiosk demos
+    /// and scripted validation, without --control.
+    #[arg(long, value_name = "DURATION", requires("rom_path"))]
+    exit_after: Option<config::RunDuration>,
+
+    /// Exit when the CPU is about to execute the instruction at the given address, in hexadecimal
+    ///
+    /// The JIT compiler is disabled, because it only stops between blocks.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_address, requires("rom_path"))]
+    exit_at_pc: Option<u16>,
+
+    /// Exit when the game sends the given text through the serial port
+    ///
+    /// Useful for test roms that report their result through the serial port, like "Passed".
+    #[arg(long, value_name = "TEXT", requires("rom_path"))]
+    exit_on_serial: Option<String>,
+
+    /// Write a PNG screenshot of the last frame to the given file, when exiting by --exit-after,
+    /// --exit-at-pc or --exit-on-serial
+    #[arg(long, value_name = "FILE")]
+    exit_screenshot: Option<PathBuf>,
+
+    /// Write a save state to the given file, when exiting by --exit-after, --exit-at-pc or
+    /// --exit-on-serial
+    #[arg(long, value_name = "FILE")]
+    exit_save_state: Option<PathBuf>,
+
     /// The initial size of the window
     #[arg(long, value_name = "WIDTHxHEIGHT")]
     screen_size: Option<String>,
@@ -124,9 +302,26 @@ pub struct Bench {
     #[arg(long, action = ArgAction::Set, value_name = "BOOL", default_value = "true")]
     flag_optimization: bool,
 
+    /// Enables/disables keeping the GameBoy registers in host registers inside a compiled block
+    #[arg(long, action = ArgAction::Set, value_name = "BOOL", default_value = "true")]
+    register_allocation: bool,
+
+    /// Enables/disables jumping directly between compiled blocks
+    #[arg(long, action = ArgAction::Set, value_name = "BOOL", default_value = "true")]
+    block_linking: bool,
+
+    /// The maximum number of bytes of compiled code to keep, discarding the least recently used
+    /// blocks
+    #[arg(long, value_name = "BYTES")]
+    max_code_size: Option<usize>,
+
     /// Emmit symbol information to `/tmp/perf-$PID.map`, enabling `perf`'s JIT support.
     #[arg(long)]
     emit_perf_map: bool,
+
+    /// Compare each compiled block against the interpreter, panicking on the first mismatch
+    #[arg(long)]
+    check_jit: bool,
 }
 
 pub fn main() {
@@ -161,18 +356,36 @@ pub fn main() {
 
         config.start_in_debug |= args.debug;
 
+        config.movie_annotations = args.movie.as_ref().map(|movie| {
+            args.annotations
+                .clone()
+                .unwrap_or_else(|| MovieAnnotations::path(Path::new(movie)))
+        });
+
         config.rom_folder = args.rom_folder.or(config.rom_folder);
 
         config.boot_rom = args.boot_rom.or(config.boot_rom);
 
+        config.model = args.model.unwrap_or(config.model);
+
         config.rewinding = args.rewinding.unwrap_or(config.rewinding);
 
         config.interrupt_prediction = args
             .interrupt_prediction
             .unwrap_or(config.interrupt_prediction);
 
+        config.gdb_port = args.gdb.or(config.gdb_port);
+
+        config.link = args.link.or(config.link);
+
         config.frame_skip |= args.frame_skip;
 
+        config.deterministic |= args.deterministic;
+
+        config.watch_rom |= args.watch;
+
+        config.reload_mode = args.reload_mode.unwrap_or(config.reload_mode);
+
         config.screen_size = args
             .screen_size
             .map(|x| {
@@ -183,6 +396,23 @@ pub fn main() {
             })
             .or(config.screen_size);
 
+        config.auto_exit = config::AutoExit {
+            after: args.exit_after,
+            at_pc: args.exit_at_pc,
+            on_serial: args.exit_on_serial,
+            screenshot: args.exit_screenshot,
+            save_state: args.exit_save_state,
+        };
+        if !config.auto_exit.is_enabled()
+            && (config.auto_exit.screenshot.is_some() || config.auto_exit.save_state.is_some())
+        {
+            eprintln!(
+                "--exit-screenshot and --exit-save-state require --exit-after, --exit-at-pc or \
+                 --exit-on-serial"
+            );
+            std::process::exit(1)
+        }
+
         match (args.interpreter, args.jit) {
             (true, true) => {
                 eprintln!("interpreter and jit are mutually exclusive");
@@ -197,11 +427,33 @@ pub fn main() {
     }
 
     let diss = args.disassembly;
-    let rom_path = args.rom_path;
-    let movie = args.movie.map(|path| {
-        let mut file = std::fs::File::open(path).unwrap();
-        gameroy::parser::vbm(&mut file).unwrap()
-    });
+    let rom_path = match args.recent {
+        Some(n) => {
+            let recent = gameroy_lib::rom_loading::recent_roms();
+            match n.checked_sub(1).and_then(|i| recent.get(i)) {
+                Some(path) => Some(path.to_string_lossy().into_owned()),
+                None => return eprintln!("there is no recent rom number {}", n),
+            }
+        }
+        None => args.rom_path,
+    };
+    let movie_path = args.movie.map(PathBuf::from);
+    let movie = match movie_path.as_ref().map(|path| info::load_movie(path)) {
+        Some(Ok(x)) => Some(x),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    if args.movie_info {
+        let (Some(movie_path), Some(movie)) = (&movie_path, &movie) else {
+            unreachable!("the --movie-info flag already requires --movie")
+        };
+        info::print_movie_info(movie_path, movie);
+        return;
+    }
 
     // dissasembly and return early
     if diss {
@@ -213,7 +465,8 @@ pub fn main() {
                 Err(e) => return eprintln!("failed to load '{}': {}", rom_path, e),
             };
 
-            let gb = load_gameboy_with_spec(rom, None, args.mbc.as_deref());
+            let file = RomFile::from_path(PathBuf::from(rom_path));
+            let gb = load_gameboy_with_spec(rom, None, args.mbc.as_deref(), &file.file_name());
             let mut gb = match gb {
                 Ok(x) => x,
                 Err(e) => return eprintln!("failed to load rom: {}", e),
@@ -230,6 +483,114 @@ pub fn main() {
         }
     }
 
+    if args.info {
+        let rom_path = rom_path
+            .as_ref()
+            .expect("the --info flag already requires <ROM_PATH>");
+        if let Err(e) = info::print_info(rom_path, args.mbc.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(trace_path) = &args.compare_trace {
+        let rom_path = rom_path
+            .as_ref()
+            .expect("the --compare-trace flag already requires <ROM_PATH>");
+        if let Err(e) = compare_trace::compare_trace(rom_path, trace_path, args.mbc.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(hash) = &args.sync_movie {
+        let rom_path = rom_path
+            .as_ref()
+            .expect("the --sync-movie flag already requires <ROM_PATH>");
+        let (Some(movie_path), Some(movie)) = (&movie_path, &movie) else {
+            unreachable!("the --sync-movie flag already requires --movie")
+        };
+        if let Err(e) = sync_movie::sync_movie(
+            rom_path,
+            movie_path,
+            movie,
+            hash,
+            args.sync_max_div_offset,
+            args.sync_seeds,
+            args.mbc.as_deref(),
+        ) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.stdin_input {
+        let rom_path = rom_path
+            .as_ref()
+            .expect("the --stdin-input flag already requires <ROM_PATH>");
+        if let Err(e) = stdin_input::run(rom_path, args.mbc.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(address) = &args.control {
+        if let Err(e) = control::run(address, rom_path.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(address) = &args.dmg07_hub {
+        #[cfg(feature = "threads")]
+        let result = gameroy_lib::link::run_hub(address);
+        #[cfg(not(feature = "threads"))]
+        let result = Err::<(), _>(format!(
+            "--dmg07-hub {} is only available when built with the `threads` feature",
+            address
+        ));
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if !args.wall.is_empty() {
+        let mut games = Vec::new();
+        for rom_path in &args.wall {
+            let rom = match std::fs::read(rom_path) {
+                Ok(x) => x,
+                Err(e) => return eprintln!("failed to load '{}': {}", rom_path, e),
+            };
+            let file = RomFile::from_path(PathBuf::from(rom_path)).with_mbc(args.mbc.clone());
+            let ram = match file.load_ram_data_blocking() {
+                Ok(x) => Some(x),
+                Err(err) => {
+                    log::error!("{}", err);
+                    None
+                }
+            };
+            match load_gameboy_with_spec(rom, ram, file.mbc(), &file.file_name()) {
+                Ok(gb) => games.push((file, gb)),
+                Err(e) => return eprintln!("failed to load '{}': {}", rom_path, e),
+            }
+        }
+        #[cfg(feature = "threads")]
+        gameroy_lib::wall::main(games);
+        #[cfg(not(feature = "threads"))]
+        {
+            let _ = games;
+            eprintln!("--wall is only available when built with the `threads` feature");
+            std::process::exit(1);
+        }
+    }
+
     // load rom if necesary
     let gb = if let Some(rom_path) = &rom_path {
         let rom = std::fs::read(rom_path);
@@ -239,9 +600,9 @@ pub fn main() {
             Err(e) => return eprintln!("failed to load '{}': {}", rom_path, e),
         };
 
-        let file = RomFile::from_path(PathBuf::from(rom_path));
+        let file = RomFile::from_path(PathBuf::from(rom_path)).with_mbc(args.mbc.clone());
 
-        let gb = load_gameboy_with_spec(rom, None, args.mbc.as_deref());
+        let gb = load_gameboy_with_spec(rom, None, file.mbc(), &file.file_name());
         match gb {
             Ok(x) => Some((file, x)),
             Err(e) => return eprintln!("failed to load rom: {}", e),
@@ -250,5 +611,25 @@ pub fn main() {
         None
     };
 
+    let mut gb = gb;
+    if let (Some((_, gb)), Some(movie), Some(movie_path)) = (&mut gb, &movie, &movie_path) {
+        let sync = MovieSync::load(movie_path)
+            .unwrap_or_else(|err| {
+                log::error!("{}", err);
+                None
+            })
+            .unwrap_or_default();
+        // playing the movie from the wrong start would silently desync.
+        if let Err(err) = start_movie(gb, movie, movie_path, sync) {
+            return eprintln!("failed to start the movie: {}", err);
+        }
+    }
+
     gameroy_lib::main(gb, movie);
 }
+
+/// Parse a address in hexadecimal, with or without a "0x" prefix.
+fn parse_address(value: &str) -> Result<u16, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", value))
+}
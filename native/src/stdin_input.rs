@@ -0,0 +1,107 @@
+//! A headless mode driven by the standard input, for regression runs from shell scripts.
+//!
+//! Each line of the input is a frame number followed by the buttons held from that frame on, like
+//! `120 a start`. A line with only the frame number releases all buttons. Empty lines, and lines
+//! starting with `#`, are ignored. The frames are run as the lines are read, and after each one
+//! its number and the CRC32 of the screen are written to the standard output:
+//!
+//! ```text
+//! $ printf '0\n60 start\n64\n120\n' | gameroy game.gb --stdin-input
+//! 1 5b3c21f0
+//! 2 5b3c21f0
+//! ...
+//! 120 0d1e44a2
+//! ```
+//!
+//! The frame numbers must not decrease, and the emulation stops at the frame of the last line.
+//! Like in `--control`, each frame runs a fixed number of clock cycles.
+
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use gameroy_lib::{gameroy::gameboy::GameBoy, rom_loading::load_gameboy_with_spec};
+
+use crate::control::{button_bit, run_frames, screen_hash};
+
+/// Parse a line of input into its frame and the pressed buttons, with the same layout of
+/// `GameBoy::joypad`, but active-high. Returns None for empty lines and comments.
+fn parse_line(line: &str) -> Option<Result<(u64, u8), String>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut words = line.split_whitespace();
+    let frame = words.next()?;
+    let parse = || {
+        let frame = frame
+            .parse::<u64>()
+            .map_err(|_| format!("'{}' is not a frame number", frame))?;
+        let mut buttons = 0;
+        for button in words {
+            buttons |= 1 << button_bit(button)?;
+        }
+        Ok((frame, buttons))
+    };
+    Some(parse())
+}
+
+/// Run the rom, with the inputs read from `input`, writing the hash of each frame to `output`.
+fn run_with(gb: &mut GameBoy, input: impl BufRead, mut output: impl Write) -> Result<(), String> {
+    let mut frame = 0;
+    let mut buttons = 0;
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| format!("failed to read input: {}", e))?;
+        let Some(parsed) = parse_line(&line) else {
+            continue;
+        };
+        let (next_frame, next_buttons) =
+            parsed.map_err(|e| format!("line {}: {}", number + 1, e))?;
+        if next_frame < frame {
+            return Err(format!(
+                "line {}: frame {} is before the current frame {}",
+                number + 1,
+                next_frame,
+                frame
+            ));
+        }
+        gb.joypad = !buttons;
+        while frame < next_frame {
+            run_frames(gb, 1);
+            frame += 1;
+            writeln!(output, "{} {}", frame, screen_hash(gb))
+                .and_then(|_| output.flush())
+                .map_err(|e| format!("failed to write output: {}", e))?;
+        }
+        buttons = next_buttons;
+    }
+    Ok(())
+}
+
+/// Load the rom, and run it with the inputs read from the standard input.
+pub fn run(rom_path: &str, mbc: Option<&str>) -> Result<(), String> {
+    let rom =
+        std::fs::read(rom_path).map_err(|e| format!("failed to load '{}': {}", rom_path, e))?;
+    let file_name = Path::new(rom_path)
+        .file_name()
+        .map_or("".into(), |x| x.to_string_lossy());
+    let mut gb = load_gameboy_with_spec(rom, None, mbc, &file_name)?;
+
+    run_with(&mut gb, std::io::stdin().lock(), std::io::stdout().lock())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_line;
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(parse_line("120 a start"), Some(Ok((120, 0b1001_0000))));
+        assert_eq!(parse_line("  60 "), Some(Ok((60, 0))));
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("# wait for the title"), None);
+        assert!(matches!(parse_line("a 60"), Some(Err(_))));
+        assert!(matches!(parse_line("60 turbo"), Some(Err(_))));
+    }
+}
@@ -0,0 +1,106 @@
+//! Search for the initial conditions that make a desynced movie reach its expected end.
+//!
+//! Each candidate replays the entire movie headlessly, running frames the same way as the control
+//! server, and compares the hash of the screen at the end of the movie with the expected one. The
+//! candidates are each DIV offset up to a maximum, first with the configured initial RAM, and then
+//! with each of the given number of random RAM seeds.
+
+use std::path::Path;
+
+use gameroy_lib::{
+    gameroy::{
+        consts::FRAME_CYCLES,
+        gameboy::GameBoy,
+        parser::{Vbm, VbmStart},
+    },
+    rom_loading::{load_gameboy_with_spec, movie_timeline, start_movie, MovieSync},
+};
+
+use crate::control::{run_frames, screen_hash};
+
+/// Replay the movie from the start, and return the hash of the screen at its end.
+fn replay(
+    gb: &mut GameBoy,
+    movie: &Vbm,
+    movie_path: &Path,
+    timeline: &[u8],
+    sync: MovieSync,
+) -> Result<String, String> {
+    start_movie(gb, movie, movie_path, sync)?;
+    gb.cartridge.set_rtc_time(movie.uid as u64);
+
+    let start_frame = (gb.clock_count / FRAME_CYCLES) as usize;
+    let mut frame = start_frame;
+    let inputs = timeline.to_vec();
+    gb.v_blank = Some(Box::new(move |gb| {
+        gb.joypad = inputs.get(frame).copied().unwrap_or(0xFF);
+        frame += 1;
+    }));
+    run_frames(gb, timeline.len().saturating_sub(start_frame) as u64);
+
+    Ok(screen_hash(gb))
+}
+
+/// Try the initial conditions of the movie until its ends with the screen hash `expected_hash`,
+/// and save the ones found to the sync file of the movie.
+pub fn sync_movie(
+    rom_path: &str,
+    movie_path: &Path,
+    movie: &Vbm,
+    expected_hash: &str,
+    max_div_offset: u16,
+    seeds: u64,
+    mbc: Option<&str>,
+) -> Result<(), String> {
+    let rom =
+        std::fs::read(rom_path).map_err(|e| format!("failed to load '{}': {}", rom_path, e))?;
+    let file_name = Path::new(rom_path)
+        .file_name()
+        .map_or("".into(), |x| x.to_string_lossy());
+
+    if let VbmStart::SaveState(_) | VbmStart::SaveStateReference(_) = movie.start() {
+        return Err(
+            "the movie starts from a save state, which has no initial conditions to sync".into(),
+        );
+    }
+
+    let expected_hash = expected_hash.trim().to_ascii_lowercase();
+
+    let ram_seeds =
+        std::iter::once(None).chain((0..seeds).map(|i| Some((movie.uid as u64).wrapping_add(i))));
+    for ram_seed in ram_seeds {
+        match ram_seed {
+            Some(seed) => eprintln!("trying ram seed {}", seed),
+            None => eprintln!("trying the configured initial ram"),
+        }
+        for div_offset in 0..=max_div_offset {
+            let sync = MovieSync {
+                div_offset,
+                ram_seed,
+            };
+            // a new GameBoy each time, to not keep the cartridge RAM of the previous replay.
+            let mut gb = load_gameboy_with_spec(rom.clone(), None, mbc, &file_name)?;
+            let timeline = movie_timeline(movie, &gb);
+            let hash = replay(&mut gb, movie, movie_path, &timeline, sync)?;
+            log::debug!("{:?}: {}", sync, hash);
+            if hash == expected_hash {
+                println!(
+                    "movie synced with div offset {} and {}",
+                    div_offset,
+                    ram_seed.map_or("the configured initial ram".to_string(), |seed| format!(
+                        "ram seed {}",
+                        seed
+                    ))
+                );
+                sync.save(movie_path)?;
+                println!("saved to '{}'", MovieSync::path(movie_path).display());
+                return Ok(());
+            }
+        }
+    }
+
+    Err(format!(
+        "no initial condition ended the movie with screen hash {}",
+        expected_hash
+    ))
+}
@@ -0,0 +1,128 @@
+//! Colorization profiles of DMG games, keyed by the CRC32 of the entire rom, like the game database.
+//!
+//! The profiles are in `assets/colorization.ron`, and are only applied if `colorization` is
+//! enabled in the config. The file is optional: if it is missing, no game is colorized. See
+//! `gameroy::gameboy::colorization`.
+
+use std::collections::HashMap;
+
+use gameroy::gameboy::colorization::{ColorizationProfile, SourcePalettes};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Deserializer};
+
+/// A color, written as "#rrggbb".
+#[derive(Debug, Clone, Copy)]
+struct Color([u8; 3]);
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse_color(&text)
+            .map(Color)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text
+        .strip_prefix('#')
+        .filter(|x| x.len() == 6)
+        .ok_or_else(|| format!("expected a color like \"#rrggbb\", found {:?}", text))?;
+    let value = u32::from_str_radix(hex, 16).map_err(|err| format!("{:?}: {}", text, err))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok([r, g, b])
+}
+
+/// The colors of each source, from the lightest to the darkest shade. The window and the sprites
+/// have the colors of the background if not given.
+#[derive(Deserialize, Debug)]
+struct PalettesEntry {
+    background: [Color; 4],
+    #[serde(default)]
+    window: Option<[Color; 4]>,
+    #[serde(default)]
+    obj0: Option<[Color; 4]>,
+    #[serde(default)]
+    obj1: Option<[Color; 4]>,
+}
+impl PalettesEntry {
+    fn palettes(&self) -> SourcePalettes {
+        let colors = |x: [Color; 4]| x.map(|x| x.0);
+        let background = colors(self.background);
+        let or_background = |x: Option<[Color; 4]>| x.map_or(background, colors);
+        SourcePalettes {
+            background,
+            window: or_background(self.window),
+            obj0: or_background(self.obj0),
+            obj1: or_background(self.obj1),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ProfileEntry {
+    palettes: PalettesEntry,
+    /// The palettes of the scanlines from the first to the second number, inclusive.
+    #[serde(default)]
+    lines: Vec<(u8, u8, PalettesEntry)>,
+}
+
+type Profiles = HashMap<u32, ProfileEntry>;
+
+static PROFILES: OnceCell<Profiles> = OnceCell::new();
+
+#[cfg(not(feature = "static"))]
+fn read_profiles() -> Option<String> {
+    let mut path = crate::config::base_folder()?;
+    path.push("assets/colorization.ron");
+    std::fs::read_to_string(&path)
+        .map_err(|err| log::debug!("no colorization profiles at '{}': {}", path.display(), err))
+        .ok()
+}
+
+#[cfg(feature = "static")]
+fn read_profiles() -> Option<String> {
+    Some(include_str!("../assets/colorization.ron").to_string())
+}
+
+fn profiles() -> &'static Profiles {
+    PROFILES.get_or_init(|| {
+        read_profiles()
+            .and_then(|file| {
+                ron::from_str(&file)
+                    .map_err(|err| log::error!("failed to parse colorization profiles: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// The colorization profile of the rom with the given CRC32, if there is one.
+pub fn lookup_hash(crc32: u32) -> Option<ColorizationProfile> {
+    let entry = profiles().get(&crc32)?;
+    Some(ColorizationProfile {
+        palettes: entry.palettes.palettes(),
+        lines: entry
+            .lines
+            .iter()
+            .map(|(start, end, x)| (*start, *end, x.palettes()))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn parse_color() {
+        assert_eq!(super::parse_color("#ff8000"), Ok([0xFF, 0x80, 0x00]));
+        assert!(super::parse_color("ff8000").is_err());
+        assert!(super::parse_color("#ff80").is_err());
+        assert!(super::parse_color("#gg8000").is_err());
+    }
+
+    #[test]
+    fn bundled_profiles() {
+        let file = include_str!("../assets/colorization.ron");
+        let profiles: super::Profiles = ron::from_str(file).unwrap();
+        assert!(!profiles.is_empty());
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
 use std::eprintln;
 use std::path::{Path, PathBuf};
 
 use cfg_if::cfg_if;
+use gameroy::consts::{CLOCK_SPEED, FRAME_CYCLES};
+use gameroy::gameboy::{
+    accuracy::AccuracyConfig, sound_controller::FilterMode, unusable_area::UnusableArea,
+};
 use once_cell::sync::OnceCell;
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
@@ -81,7 +86,7 @@ cfg_if! {
         }
     } else {
         pub fn load_config() -> Result<Config, String> {
-            let config_path = normalize_config_path("gameroy.toml");
+            let config_path = crate::dirs::config_path();
             log::info!("using '{}' as config path", config_path.display());
             let config = std::fs::read_to_string(config_path).map_err(|e| e.to_string())?;
             let config: Config = toml::from_str(&config).map_err(|e| e.to_string())?;
@@ -98,17 +103,353 @@ cfg_if! {
 #[serde(default)]
 pub struct Config {
     pub start_in_debug: bool,
+    /// When started without a rom, open a file dialog for choosing one, over the rom list.
+    pub start_with_file_dialog: bool,
     pub rom_folder: Option<String>,
+    /// The boot rom run for any model. Takes precedence over `boot_roms`.
     pub boot_rom: Option<String>,
+    /// The hardware model whose boot rom, from `boot_roms`, is run, and whose behavior of the
+    /// unusable area of the memory map is emulated.
+    pub model: Model,
+    pub boot_roms: BootRoms,
     pub sort_list: Option<String>,
     pub rewinding: bool,
     pub interrupt_prediction: bool,
     pub frame_skip: bool,
     pub jit: bool,
+    /// The maximum size of the code compiled by the JIT compiler, in MiB. When exceeded, the least
+    /// recently used blocks are discarded.
+    pub jit_max_code_size: usize,
+    pub deterministic: bool,
+    pub initial_ram: InitialRam,
+    pub initial_ram_seed: Option<u64>,
+    pub accuracy: Accuracy,
+    /// The accuracy profile of each rom, keyed by the rom file name. Overrides `accuracy`.
+    pub game_accuracy: BTreeMap<String, Accuracy>,
+    pub gdb_port: Option<u16>,
+    /// The address of a DMG-07 hub to connect to, for local multiplayer. See `crate::link`.
+    pub link: Option<String>,
+    pub serial_log: bool,
+    /// Also print the bytes sent through the serial port to stderr, as they are sent. Enabled by
+    /// default, for running test roms from the command line.
+    pub serial_stderr: bool,
+    /// The folder of the save files. If not set, they are kept next to the rom.
+    pub save_folder: Option<String>,
+    /// The folder of the save states. If not set, they are kept next to the rom.
+    pub state_folder: Option<String>,
+    /// How many older versions of each save file are kept, as '<rom>.sav.1' (the newest) up to
+    /// '<rom>.sav.N'. Only the first change in each session is backed up. 0 disables the backups.
+    pub save_backups: u32,
+    /// How often, in seconds, the cartridge RAM is written to the save file, if the game changed
+    /// it. 0 disables the autosave, and the save file is only written when the emulation is closed.
+    pub autosave_interval: u32,
+    /// Never write the save files, for roms in a shared folder. Any progress is lost when the
+    /// emulation is closed.
+    pub read_only_saves: bool,
+    /// The folder where screenshots are written. If not set, a folder in the data folder is used.
+    pub screenshot_folder: Option<String>,
+    /// Reload the rom when its file changes. See `ReloadMode`.
+    pub watch_rom: bool,
+    pub reload_mode: ReloadMode,
+    /// Save a state, as a new branch of the state tree, before resetting from the menu.
+    pub reset_save_state: bool,
+    /// Save a state when the emulation is closed, including by a termination signal.
+    pub save_state_on_exit: bool,
+    pub audio_filter: AudioFilter,
+    /// What happens when opposite directions of the D-pad are pressed at the same time.
+    pub dpad_policy: DpadPolicy,
     #[serde(deserialize_with = "screen_size_deser")]
     pub screen_size: Option<(u32, u32)>,
     pub only_integer_scaling: bool,
+    pub lcd_ghosting: f32,
+    /// Open a borderless window that only shows the game screen, for capture software.
+    pub spectator_window: bool,
+    /// The integer scale of the spectator window.
+    pub spectator_scale: u32,
+    /// The colors of the spectator window, from lightest to darkest. If not set, or the game is
+    /// colorized, the colors of the main screen are used.
+    pub spectator_palette: Option<[[u8; 3]; 4]>,
+    /// Colorize the games that have a profile in `assets/colorization.ron`.
+    pub colorization: bool,
+    /// Measure the time from a key event to the emulated joypad and to the screen, and show it
+    /// over the screen.
+    pub input_latency_overlay: bool,
+    /// Show the controls of the game pad over the screen, so the game can be played with the mouse.
+    /// Always shown on Android.
+    pub gamepad_overlay: bool,
+    pub theme: Theme,
+    pub language: Option<String>,
     pub keymap: KeyMap,
+    /// The input macros of each rom, keyed by the rom file name. The macros recorded in the
+    /// emulator are kept apart, see `input_macros`.
+    pub macros: BTreeMap<String, Vec<InputMacro>>,
+    /// When the emulator exits by itself. Only set by the command line options, like
+    /// `--exit-after`.
+    #[serde(skip)]
+    pub auto_exit: AutoExit,
+    /// The file of the annotations of the movie being played, shown over the screen. Only set
+    /// with `--movie`. See `rom_loading::MovieAnnotations`.
+    #[serde(skip)]
+    pub movie_annotations: Option<PathBuf>,
+}
+
+/// A recorded sequence of button presses, replayed when `key` is pressed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InputMacro {
+    pub key: VirtualKeyCode,
+    /// A list of (frames, joypad) pairs: each joypad state is held for the given number of
+    /// frames.
+    pub inputs: Vec<(u32, u8)>,
+}
+
+/// The initial content of the RAM when the GameBoy is powered on. See
+/// `gameroy::gameboy::ram_fill::RamFill`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialRam {
+    Zeros,
+    Ones,
+    Dmg,
+    Random,
+}
+
+/// A named set of accuracy toggles. See `gameroy::gameboy::accuracy::AccuracyConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accuracy {
+    Accurate,
+    Balanced,
+    Fast,
+}
+impl Accuracy {
+    pub fn config(self) -> AccuracyConfig {
+        match self {
+            Accuracy::Accurate => AccuracyConfig::ACCURATE,
+            Accuracy::Balanced => AccuracyConfig::BALANCED,
+            Accuracy::Fast => AccuracyConfig::FAST,
+        }
+    }
+}
+
+/// A GameBoy hardware model, for choosing its boot rom. Only the boot rom and the behavior of the
+/// unusable area, FEA0-FEFF, change: the rest of the hardware is always emulated as a DMG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Model {
+    Dmg,
+    Mgb,
+    Sgb,
+    Cgb,
+}
+impl std::str::FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dmg" => Ok(Self::Dmg),
+            "mgb" => Ok(Self::Mgb),
+            "sgb" => Ok(Self::Sgb),
+            "cgb" => Ok(Self::Cgb),
+            _ => Err(format!(
+                "invalid model '{}', expected 'dmg', 'mgb', 'sgb' or 'cgb'",
+                s
+            )),
+        }
+    }
+}
+impl Model {
+    /// The behavior of FEA0-FEFF in this model. The CGB is emulated as its last revision, E.
+    pub fn unusable_area(self) -> UnusableArea {
+        match self {
+            Model::Dmg | Model::Mgb | Model::Sgb => UnusableArea::Dmg,
+            Model::Cgb => UnusableArea::Cgb,
+        }
+    }
+}
+
+/// The path of the boot rom of each hardware model. See `Config::model`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BootRoms {
+    pub dmg: Option<String>,
+    pub mgb: Option<String>,
+    pub sgb: Option<String>,
+    pub cgb: Option<String>,
+}
+impl BootRoms {
+    pub fn get(&self, model: Model) -> Option<&String> {
+        match model {
+            Model::Dmg => self.dmg.as_ref(),
+            Model::Mgb => self.mgb.as_ref(),
+            Model::Sgb => self.sgb.as_ref(),
+            Model::Cgb => self.cgb.as_ref(),
+        }
+    }
+}
+
+/// What is kept when the rom is reloaded after its file changes. See `Config::watch_rom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReloadMode {
+    /// Restart the game from the start, with the cartridge RAM cleared.
+    Restart,
+    /// Restart the game from the start, keeping the current cartridge RAM.
+    KeepRam,
+    /// Restart the game and load its save state.
+    LoadState,
+}
+impl std::str::FromStr for ReloadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restart" => Ok(Self::Restart),
+            "keep-ram" => Ok(Self::KeepRam),
+            "load-state" => Ok(Self::LoadState),
+            _ => Err(format!(
+                "invalid reload mode '{}', expected 'restart', 'keep-ram' or 'load-state'",
+                s
+            )),
+        }
+    }
+}
+
+/// When the emulator exits by itself, for kiosk demos and scripted validation, and what is saved
+/// before it. The cartridge RAM is saved as usual.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AutoExit {
+    /// Exit after emulating this long, counting from when the rom is loaded.
+    pub after: Option<RunDuration>,
+    /// Exit when the CPU is about to execute the instruction at this address. The JIT compiler
+    /// is disabled, because it only stops between blocks.
+    pub at_pc: Option<u16>,
+    /// Exit when the game sends this text through the serial port, like the result of a test rom.
+    pub on_serial: Option<String>,
+    /// Write a screenshot of the last frame to this PNG file, before exiting.
+    pub screenshot: Option<PathBuf>,
+    /// Write a save state to this file, before exiting.
+    pub save_state: Option<PathBuf>,
+}
+impl AutoExit {
+    /// If any of the conditions to exit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.after.is_some() || self.at_pc.is_some() || self.on_serial.is_some()
+    }
+}
+
+/// A span of emulated time, in frames or in seconds. The time is the one of the emulated Game
+/// Boy, so it is independent of the speed of the emulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunDuration {
+    Frames(u64),
+    Seconds(f64),
+}
+impl RunDuration {
+    /// The number of clock cycles emulated in this span.
+    pub fn clock_cycles(self) -> u64 {
+        match self {
+            RunDuration::Frames(x) => x * FRAME_CYCLES,
+            RunDuration::Seconds(x) => (x * CLOCK_SPEED as f64) as u64,
+        }
+    }
+}
+impl std::str::FromStr for RunDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "invalid duration '{}', expected a number of frames, like '600f', or of seconds, \
+                 like '10s'",
+                s
+            )
+        };
+        if let Some(frames) = s.strip_suffix('f') {
+            frames.parse().map(Self::Frames).map_err(|_| err())
+        } else if let Some(seconds) = s.strip_suffix('s') {
+            match seconds.parse::<f64>() {
+                Ok(x) if x.is_finite() && x >= 0.0 => Ok(Self::Seconds(x)),
+                _ => Err(err()),
+            }
+        } else {
+            Err(err())
+        }
+    }
+}
+
+/// The filters applied to the audio output. See `gameroy::gameboy::sound_controller::FilterMode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AudioFilter {
+    Hardware,
+    HighPass,
+    Raw,
+}
+impl AudioFilter {
+    pub fn filter_mode(self) -> FilterMode {
+        match self {
+            AudioFilter::Hardware => FilterMode::Hardware,
+            AudioFilter::HighPass => FilterMode::HighPass,
+            AudioFilter::Raw => FilterMode::Raw,
+        }
+    }
+}
+
+/// What happens when opposite directions of the D-pad (Left and Right, or Up and Down) are pressed
+/// at the same time, which is impossible on the real hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DpadPolicy {
+    /// Neither direction is pressed.
+    Block,
+    /// Both directions are pressed, as some TAS inputs rely on.
+    Allow,
+    /// Only the direction pressed last is pressed, until it is released.
+    LastWins,
+}
+impl DpadPolicy {
+    /// Apply the policy to the keys pressed by the user, in the format of `AppState::joypad`,
+    /// where bits 0 to 3 are Right, Left, Up and Down, and 0 means pressed. `old` is the keys
+    /// pressed before, and `last` is the result of the previous call.
+    pub fn apply(self, old: u8, new: u8, last: u8) -> u8 {
+        let mut joypad = new;
+        for pair in [0b0011, 0b1100] {
+            if new & pair != 0 {
+                // at most one of the directions is pressed.
+                continue;
+            }
+            let newly_pressed = old & pair;
+            let pair_state = match self {
+                DpadPolicy::Allow => 0,
+                DpadPolicy::Block => pair,
+                DpadPolicy::LastWins if newly_pressed == 0 => last & pair,
+                DpadPolicy::LastWins if newly_pressed == pair => pair,
+                DpadPolicy::LastWins => pair & !newly_pressed,
+            };
+            joypad = (joypad & !pair) | pair_state;
+        }
+        joypad
+    }
+}
+
+/// The color theme of the UI. Each theme has its own style file in the assets folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Dark,
+    Light,
+    /// White and yellow over black, with the keyboard focus clearly highlighted.
+    HighContrast,
+}
+impl Theme {
+    /// The name of the style file of this theme, relative to the assets folder.
+    pub fn style_file(self) -> &'static str {
+        match self {
+            Theme::Dark => "style.ron",
+            Theme::Light => "style_light.ron",
+            Theme::HighContrast => "style_high_contrast.ron",
+        }
+    }
 }
 
 pub fn parse_screen_size(value: &str) -> Result<(u32, u32), &'static str> {
@@ -161,6 +502,19 @@ impl Config {
     pub fn save(&self) -> Result<(), String> {
         save_config(self)
     }
+
+    /// The accuracy profile used for the rom with the given file name.
+    /// The accuracy profile of the rom with the given file name. In deterministic mode it is always
+    /// `Accurate`, so runs don't depend on the profile of the host.
+    pub fn accuracy_for(&self, file_name: &str) -> Accuracy {
+        if self.deterministic {
+            return Accuracy::Accurate;
+        }
+        self.game_accuracy
+            .get(file_name)
+            .copied()
+            .unwrap_or(self.accuracy)
+    }
 }
 
 /// Transform a path relative to the executable folder to a absolute path.
@@ -226,6 +580,8 @@ pub struct KeyMap {
     pub rewind: VirtualKeyCode,
     pub save_state: VirtualKeyCode,
     pub load_state: VirtualKeyCode,
+    pub record_macro: VirtualKeyCode,
+    pub toggle_jit: VirtualKeyCode,
 
     pub open_debugger: VirtualKeyCode,
     pub debug_step: VirtualKeyCode,
@@ -255,6 +611,8 @@ const DEFAULT_KEYMAP: KeyMap = {
         rewind: R,
         save_state: F5,
         load_state: F6,
+        record_macro: F2,
+        toggle_jit: F10,
 
         open_debugger: F12,
         debug_stepback: F7,
@@ -265,16 +623,64 @@ const DEFAULT_KEYMAP: KeyMap = {
 
 const DEFAULT_CONFIG: Config = Config {
     start_in_debug: false,
+    start_with_file_dialog: false,
     rom_folder: None,
     boot_rom: None,
+    model: Model::Dmg,
+    boot_roms: BootRoms {
+        dmg: None,
+        mgb: None,
+        sgb: None,
+        cgb: None,
+    },
     sort_list: None,
     rewinding: true,
     interrupt_prediction: true,
     frame_skip: false,
     jit: true,
+    jit_max_code_size: 32,
+    deterministic: false,
+    initial_ram: InitialRam::Ones,
+    initial_ram_seed: None,
+    accuracy: Accuracy::Balanced,
+    game_accuracy: BTreeMap::new(),
+    gdb_port: None,
+    link: None,
+    serial_log: false,
+    serial_stderr: true,
+    save_folder: None,
+    state_folder: None,
+    save_backups: 3,
+    autosave_interval: 60,
+    read_only_saves: false,
+    screenshot_folder: None,
+    watch_rom: false,
+    reload_mode: ReloadMode::Restart,
+    reset_save_state: true,
+    save_state_on_exit: false,
+    audio_filter: AudioFilter::Hardware,
+    dpad_policy: DpadPolicy::Block,
     screen_size: None,
     only_integer_scaling: false,
+    lcd_ghosting: 0.0,
+    spectator_window: false,
+    spectator_scale: 3,
+    spectator_palette: None,
+    colorization: false,
+    input_latency_overlay: false,
+    gamepad_overlay: false,
+    theme: Theme::Dark,
+    language: None,
     keymap: DEFAULT_KEYMAP,
+    macros: BTreeMap::new(),
+    auto_exit: AutoExit {
+        after: None,
+        at_pc: None,
+        on_serial: None,
+        screenshot: None,
+        save_state: None,
+    },
+    movie_annotations: None,
 };
 
 static CONFIG: Mutex<Config> = parking_lot::const_mutex(DEFAULT_CONFIG);
@@ -286,3 +692,52 @@ pub fn config() -> MutexGuard<'static, Config> {
 pub fn init_config(config: Config) {
     *CONFIG.lock() = config
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DpadPolicy, RunDuration};
+
+    /// The joypad state with the given keys (bits 0 to 3 are Right, Left, Up and Down) pressed.
+    fn pressed(keys: u8) -> u8 {
+        !keys
+    }
+
+    #[test]
+    fn dpad_policy() {
+        let none = pressed(0);
+        let right = pressed(0b0001);
+        let left = pressed(0b0010);
+        let both = pressed(0b0011);
+        let up_down = pressed(0b1100);
+
+        assert_eq!(DpadPolicy::Allow.apply(right, both, right), both);
+        assert_eq!(DpadPolicy::Block.apply(right, both, right), none);
+        assert_eq!(DpadPolicy::Block.apply(none, up_down, none), none);
+        assert_eq!(DpadPolicy::Block.apply(both, right, none), right);
+
+        // Right is held, then Left is pressed, and then released.
+        let last = DpadPolicy::LastWins.apply(none, right, none);
+        assert_eq!(last, right);
+        let last = DpadPolicy::LastWins.apply(right, both, last);
+        assert_eq!(last, left);
+        let last = DpadPolicy::LastWins.apply(both, both, last);
+        assert_eq!(last, left);
+        let last = DpadPolicy::LastWins.apply(both, right, last);
+        assert_eq!(last, right);
+
+        // A button in the other pair does not change the winner.
+        let a = 0b1_0000;
+        assert_eq!(DpadPolicy::LastWins.apply(both, both & !a, left), left & !a);
+    }
+
+    #[test]
+    fn run_duration() {
+        assert_eq!("600f".parse(), Ok(RunDuration::Frames(600)));
+        assert_eq!("2.5s".parse(), Ok(RunDuration::Seconds(2.5)));
+        assert_eq!(RunDuration::Frames(2).clock_cycles(), 2 * 70224);
+        assert_eq!(RunDuration::Seconds(0.5).clock_cycles(), 4194304 / 2);
+        assert!("600".parse::<RunDuration>().is_err());
+        assert!("-1s".parse::<RunDuration>().is_err());
+        assert!("1.5f".parse::<RunDuration>().is_err());
+    }
+}
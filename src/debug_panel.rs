@@ -0,0 +1,114 @@
+//! The layout of the debug panel, and if the emulator was in debug mode, kept between sessions.
+//!
+//! The layout is kept in '<data folder>/debug_panel.toml' (see `dirs::data_folder`). Changes are
+//! kept in memory, and only written to disk when the debug panel is opened or closed, and when
+//! the emulator exits.
+//!
+//! On the web there is no data folder, and the default layout is always used.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// The file, relative to the data folder, that contains the layout.
+const DEBUG_PANEL_FILE: &str = "debug_panel.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DebugPanelLayout {
+    /// If the emulator was in debug mode. The next session also starts in debug mode.
+    pub debug: bool,
+    /// The splits between the screen and the debug panel, between the tabs and the side panel,
+    /// and between the tab page and the console, from 0.0 to 1.0.
+    pub splits: [f32; 3],
+    /// The tab shown when the debug panel is opened.
+    pub tab: DebugTab,
+}
+
+impl Default for DebugPanelLayout {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            splits: [0.333, 1.0, 0.9],
+            tab: DebugTab::Disassembly,
+        }
+    }
+}
+
+/// The current layout, loaded from the data folder on first use, and if it changed since it was
+/// loaded or saved.
+static LAYOUT: Mutex<Option<(DebugPanelLayout, bool)>> = parking_lot::const_mutex(None);
+
+fn load() -> DebugPanelLayout {
+    if cfg!(target_arch = "wasm32") {
+        return DebugPanelLayout::default();
+    }
+    let path = crate::dirs::data_folder().join(DEBUG_PANEL_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(err) => {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                log::error!("failed reading '{}': {}", path.display(), err);
+            }
+            return DebugPanelLayout::default();
+        }
+    };
+    toml::from_str(&text)
+        .map_err(|err| log::error!("failed parsing '{}': {}", path.display(), err))
+        .unwrap_or_default()
+}
+
+/// The current layout of the debug panel. In the first call it is loaded from the data folder.
+pub fn layout() -> DebugPanelLayout {
+    LAYOUT.lock().get_or_insert_with(|| (load(), false)).0
+}
+
+/// Apply `f` to the current layout. It is only written to disk by `save`.
+pub fn update(f: impl FnOnce(&mut DebugPanelLayout)) {
+    let mut guard = LAYOUT.lock();
+    let (layout, changed) = guard.get_or_insert_with(|| (load(), false));
+    let old = *layout;
+    f(layout);
+    *changed |= *layout != old;
+}
+
+/// Write the current layout to the data folder, if it changed.
+pub fn save() {
+    let mut guard = LAYOUT.lock();
+    let Some((layout, changed)) = guard.as_mut() else {
+        return;
+    };
+    if !*changed || cfg!(target_arch = "wasm32") {
+        return;
+    }
+    *changed = false;
+    let path = crate::dirs::data_path(DEBUG_PANEL_FILE);
+    let result = toml::to_string(layout)
+        .map_err(|err| err.to_string())
+        .and_then(|text| std::fs::write(&path, text).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        log::error!("failed writing '{}': {}", path.display(), err);
+    }
+}
+
+/// The tabs of the debug panel, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugTab {
+    Disassembly,
+    Ppu,
+    Serial,
+    States,
+    Audio,
+    Cartridge,
+}
+impl DebugTab {
+    pub const ALL: [DebugTab; 6] = [
+        DebugTab::Disassembly,
+        DebugTab::Ppu,
+        DebugTab::Serial,
+        DebugTab::States,
+        DebugTab::Audio,
+        DebugTab::Cartridge,
+    ];
+}
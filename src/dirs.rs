@@ -0,0 +1,160 @@
+//! The folders where the config, and the files written by the emulator, are kept.
+//!
+//! If there is a `gameroy.toml` in the base folder (see `config::base_folder`), the emulator runs
+//! in portable mode: the config, the thumbnails and the recent roms list are kept in the base
+//! folder. Otherwise they are kept in the platform folders:
+//!
+//! | Platform | Config                                  | Data                             |
+//! |----------|-----------------------------------------|----------------------------------|
+//! | Linux    | `$XDG_CONFIG_HOME/gameroy`              | `$XDG_DATA_HOME/gameroy`         |
+//! | Windows  | `%APPDATA%\gameroy`                     | `%APPDATA%\gameroy`              |
+//! | macOS    | `~/Library/Application Support/gameroy` | same as config                   |
+//!
+//! Saves and save states are kept next to the rom, unless `save_folder` or `state_folder` are set
+//! in the config, or the folder of the rom is read-only, in which case they are kept in the `saves`
+//! and `states` subfolders of the data folder.
+
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::config::{base_folder, config, normalize_config_path};
+
+const APP_NAME: &str = "gameroy";
+
+#[allow(dead_code)]
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Read a XDG base directory variable, ignoring relative paths as the specification requires, and
+/// fallback to `fallback`, relative to the home folder.
+#[allow(dead_code)]
+fn xdg_folder(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|x| x.is_absolute())
+        .or_else(|| Some(home()?.join(fallback)))
+}
+
+/// The platform folder for configuration files, if any.
+pub fn platform_config_folder() -> Option<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "wasm32", target_os = "android"))] {
+            let folder: Option<PathBuf> = None;
+        } else if #[cfg(target_os = "windows")] {
+            let folder = std::env::var_os("APPDATA").map(PathBuf::from);
+        } else if #[cfg(target_os = "macos")] {
+            let folder = home().map(|x| x.join("Library/Application Support"));
+        } else {
+            let folder = xdg_folder("XDG_CONFIG_HOME", ".config");
+        }
+    }
+    folder.map(|x| x.join(APP_NAME))
+}
+
+/// The platform folder for data files, if any.
+pub fn platform_data_folder() -> Option<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "wasm32", target_os = "android"))] {
+            let folder: Option<PathBuf> = None;
+        } else if #[cfg(any(target_os = "windows", target_os = "macos"))] {
+            let folder = platform_config_folder();
+        } else {
+            let folder = xdg_folder("XDG_DATA_HOME", ".local/share").map(|x| x.join(APP_NAME));
+        }
+    }
+    folder
+}
+
+/// If the config, thumbnails and recent roms list are kept in the base folder.
+pub fn is_portable() -> bool {
+    static PORTABLE: OnceCell<bool> = OnceCell::new();
+    *PORTABLE.get_or_init(|| {
+        let portable = match (platform_config_folder(), base_folder()) {
+            (Some(_), Some(base)) => base.join("gameroy.toml").exists(),
+            _ => true,
+        };
+        log::info!("portable mode: {}", portable);
+        portable
+    })
+}
+
+/// The path of the config file.
+pub fn config_path() -> PathBuf {
+    match platform_config_folder() {
+        Some(folder) if !is_portable() => folder.join("gameroy.toml"),
+        _ => normalize_config_path("gameroy.toml"),
+    }
+}
+
+/// The folder where the thumbnails, the recent roms list, the play stats, the debug panel layout
+/// and the recorded input macros are kept.
+pub fn data_folder() -> PathBuf {
+    match platform_data_folder() {
+        Some(folder) if !is_portable() => folder,
+        _ => base_folder().unwrap_or_default(),
+    }
+}
+
+/// The path of the given file in the data folder. The data folder is created if it doesn't exist.
+pub fn data_path(file_name: impl AsRef<Path>) -> PathBuf {
+    let folder = data_folder();
+    create_folder(&folder);
+    folder.join(file_name)
+}
+
+/// The folder where the save file of the rom at `rom_path` is kept.
+pub fn save_folder(rom_path: &Path) -> PathBuf {
+    let configured = config().save_folder.clone();
+    rom_file_folder(configured, "saves", rom_path)
+}
+
+/// The folder where the save states of the rom at `rom_path` are kept.
+pub fn state_folder(rom_path: &Path) -> PathBuf {
+    let configured = config().state_folder.clone();
+    rom_file_folder(configured, "states", rom_path)
+}
+
+/// The folder where screenshots and exported images are written, if not chosen by the user.
+pub fn screenshot_folder() -> PathBuf {
+    let folder = match config().screenshot_folder.clone() {
+        Some(folder) => normalize_config_path(folder),
+        None => data_folder().join("screenshots"),
+    };
+    create_folder(&folder);
+    folder
+}
+
+fn rom_file_folder(configured: Option<String>, subfolder: &str, rom_path: &Path) -> PathBuf {
+    let folder = if let Some(folder) = configured {
+        normalize_config_path(folder)
+    } else {
+        let rom_folder = rom_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if is_writable(&rom_folder) {
+            return rom_folder;
+        }
+        data_folder().join(subfolder)
+    };
+    create_folder(&folder);
+    folder
+}
+
+/// If files can be created in the given folder. Only checks the permissions of the folder itself,
+/// so a read-only mount is only detected when the folder is also marked read-only.
+fn is_writable(folder: &Path) -> bool {
+    let folder = if folder.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        folder
+    };
+    std::fs::metadata(folder).is_ok_and(|x| !x.permissions().readonly())
+}
+
+fn create_folder(folder: &Path) {
+    if let Err(err) = std::fs::create_dir_all(folder) {
+        log::error!("failed to create folder '{}': {}", folder.display(), err);
+    }
+}
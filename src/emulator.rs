@@ -1,21 +1,38 @@
-use std::{io::Write, sync::Arc};
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 #[cfg(feature = "audio-engine")]
 use audio_engine::{AudioEngine, SoundSource};
 use gameroy::{
-    consts::CLOCK_SPEED,
+    consts::{CLOCK_SPEED, FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH},
     debugger::{Debugger, RunResult},
     diff_stack::DiffStack,
-    gameboy::GameBoy,
+    disassembler::Trace,
+    gameboy::{cartridge::Cartridge, serial_transfer::Serial, GameBoy},
     interpreter::Interpreter,
-    parser::Vbm,
+    parser::{Vbm, VbmStart},
 };
-use instant::{Instant, SystemTime};
+use instant::{Duration, Instant, SystemTime};
 use parking_lot::Mutex as ParkMutex;
-use winit::event_loop::EventLoopProxy;
+use winit::{event::VirtualKeyCode, event_loop::EventLoopProxy};
 
 use super::UserEvent;
-use crate::{config::config, rom_loading::RomFile};
+use crate::{
+    config::{config, Config, InputMacro, ReloadMode},
+    input_latency::InputLatency,
+    localization::{tr, tr_arg},
+    rom_loading::{
+        movie_timeline, power_cycle_ram_fill, trace_entry_points, MovieAnnotations, RomFile,
+    },
+    snapshot::Snapshot,
+    state_tree::StateTree,
+};
 
 #[derive(Debug)]
 pub enum EmulatorEvent {
@@ -27,13 +44,79 @@ pub enum EmulatorEvent {
     Debug(bool),
     Step,
     StepBack,
+    /// Go back to the last breakpoint hit, replaying the frames saved for rewinding.
+    RunBack,
     Run,
-    Reset,
+    Reset(ResetOptions),
     SaveState,
     LoadState,
+    /// Save a state with the given name, as a new branch of the state tree. `SaveState` also saves
+    /// a unnamed one.
+    BranchState(String),
+    /// Load the state of the given node of the state tree.
+    LoadBranch(u32),
+    /// Rename the given node of the state tree.
+    RenameBranch(u32, String),
+    /// Load the given save state, like the one of a debugger session. It is not added to the
+    /// state tree.
+    LoadStateData(Vec<u8>),
     SaveRam,
+    /// Replace the save file by its newest backup, and restart the game with it.
+    RollbackRam,
     Pause,
     Resume,
+    /// Restart the emulator after it has crashed.
+    Restart,
+    /// Start or stop recording a input macro.
+    RecordMacro(bool),
+    /// Bind the last recorded input macro to the given key, for the current rom.
+    BindMacro(VirtualKeyCode),
+    /// Replay the input macro bound to the given key, if any.
+    PlayMacro(VirtualKeyCode),
+    /// Replace the cartridge by a rebuilt one, and restart the game. See `Config::reload_mode`.
+    ReloadRom(Box<Cartridge>),
+    /// Run the game with the JIT compiler if true, or with the interpreter if false. None toggles
+    /// between them.
+    SetJit(Option<bool>),
+    /// Show the given text from the current frame of the movie on, or remove the annotation of the
+    /// frame if None, and save the annotations. See `Config::movie_annotations`.
+    Annotate(Option<String>),
+    /// A byte received from the DMG-07 hub. It is exchanged with the game before the next run of
+    /// the emulator in which the game is waiting for a transfer, and the byte sent by the game is
+    /// answered, or None if the game was not ready within a frame. See `crate::link`.
+    LinkTransfer(u8, flume::Sender<Option<u8>>),
+    /// Output silence instead of the sound of the game if true. Used by the wall mode, where only
+    /// the selected game is heard.
+    Mute(bool),
+}
+
+/// A byte received from the DMG-07 hub, waiting for the game. See `EmulatorEvent::LinkTransfer`.
+struct PendingTransfer {
+    byte: u8,
+    /// The clock count when the byte was received.
+    clock: u64,
+    answer: flume::Sender<Option<u8>>,
+}
+
+/// How `EmulatorEvent::Reset` restarts the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResetOptions {
+    /// Turn the GameBoy off and on again: the RAM is refilled with random bytes from a new seed,
+    /// and the cartridge RAM is lost if the cartridge has no battery. Otherwise, the cartridge RAM
+    /// is always kept.
+    pub power_cycle: bool,
+    /// Save a state before resetting, as a new branch of the state tree, so the progress before
+    /// the reset is not lost.
+    pub save_state: bool,
+}
+impl ResetOptions {
+    /// The options of a reset requested by the user, following `Config::reset_save_state`.
+    pub fn from_config(power_cycle: bool) -> Self {
+        Self {
+            power_cycle,
+            save_state: config().reset_save_state,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -233,8 +316,10 @@ struct Timeline {
     /// TODO: replace this by implementing a CircularBuffer in the DiffStack.
     save_states2: DiffStack,
 
-    /// Current pressed keys by the user
+    /// Current pressed keys by the user, after applying the `dpad_policy`.
     current_joypad: u8,
+    /// Current pressed keys by the user, as received.
+    keys_joypad: u8,
     /// Current frame being emulated
     current_frame: u32,
     /// The state of the joypad for each frame
@@ -242,6 +327,12 @@ struct Timeline {
 
     /// If the emulator is currently rewinding.
     rewinding: bool,
+
+    /// The input macro being recorded, as a list of (frames, joypad) pairs.
+    recording: Option<Vec<(u32, u8)>>,
+    /// The input macro being replayed, the index of the current pair, and the number of frames
+    /// already replayed of it.
+    playing: Option<(Vec<(u32, u8)>, usize, u32)>,
 }
 impl Timeline {
     fn new(current_frame: u32, joypad_timeline: Vec<u8>, capacity: usize) -> Self {
@@ -253,7 +344,10 @@ impl Timeline {
             save_states: DiffStack::new(capacity / 2),
             save_states2: DiffStack::new(capacity / 2),
             current_joypad: 0xff,
+            keys_joypad: 0xff,
             rewinding: false,
+            recording: None,
+            playing: None,
         }
     }
 
@@ -301,6 +395,11 @@ impl Timeline {
         true
     }
 
+    /// If there is a saved frame before the last one.
+    fn has_earlier_frame(&self) -> bool {
+        self.save_states.count() > 1 || !self.save_states2.is_empty()
+    }
+
     /// Remove the save state of the last frame
     fn pop_last_frame(&mut self) -> bool {
         let Some(last_frame) = self.save_states.top() else {
@@ -318,6 +417,24 @@ impl Timeline {
         true
     }
 
+    /// The joypad state of the input macro being replayed in the next frame, or 0xff if there is
+    /// none.
+    fn next_macro_joypad(&mut self) -> u8 {
+        let Some((inputs, index, frame)) = &mut self.playing else {
+            return 0xff;
+        };
+        let Some(&(frames, joypad)) = inputs.get(*index) else {
+            self.playing = None;
+            return 0xff;
+        };
+        *frame += 1;
+        if *frame >= frames {
+            *index += 1;
+            *frame = 0;
+        }
+        joypad
+    }
+
     /// Get next joypad and increase the current frame.
     fn next_frame(&mut self, gb: &GameBoy) -> u8 {
         let joy = if (self.current_frame as usize) < self.joypad_timeline.len() {
@@ -327,9 +444,17 @@ impl Timeline {
             let diff = self.current_frame as usize - self.joypad_timeline.len();
             self.joypad_timeline.extend((0..diff).map(|_| 0xff));
 
-            self.joypad_timeline.push(self.current_joypad);
-            self.current_joypad
+            // a button is pressed if either the user or the macro is pressing it.
+            let joy = self.current_joypad & self.next_macro_joypad();
+            self.joypad_timeline.push(joy);
+            joy
         };
+        if let Some(recording) = &mut self.recording {
+            match recording.last_mut() {
+                Some((frames, last)) if *last == joy => *frames += 1,
+                _ => recording.push((1, joy)),
+            }
+        }
         self.save_state(gb);
         self.current_frame += 1;
         if self.current_frame % 30 == 0 {
@@ -343,6 +468,61 @@ impl Timeline {
     }
 }
 
+/// The annotations of the movie being played, shown over the screen.
+struct AnnotationTrack {
+    annotations: MovieAnnotations,
+    /// The file where the annotations are saved when edited.
+    path: PathBuf,
+    /// The frame of the timeline where the movie starts. The annotations count the frames from it.
+    start_frame: u32,
+    /// The text on the screen.
+    shown: String,
+}
+impl AnnotationTrack {
+    /// The frame of the movie emulated after the given frame of the timeline was reached, or None
+    /// if the movie didn't start yet.
+    fn movie_frame(&self, current_frame: u32) -> Option<u32> {
+        current_frame.checked_sub(self.start_frame + 1)
+    }
+
+    /// Send the annotation of the current frame to the UI, if it is not the one on the screen.
+    fn update(&mut self, current_frame: u32, proxy: &EventLoopProxy<UserEvent>) {
+        let text = self
+            .movie_frame(current_frame)
+            .map_or("", |x| self.annotations.text_at(x));
+        if text != self.shown {
+            self.shown = text.to_string();
+            let _ = proxy.send_event(UserEvent::Annotation(self.shown.clone()));
+        }
+    }
+
+    /// Set the annotation of the current frame, or remove it if `text` is None, and save the
+    /// annotations. Returns a message for the user.
+    fn edit(
+        &mut self,
+        current_frame: u32,
+        text: Option<String>,
+        proxy: &EventLoopProxy<UserEvent>,
+    ) -> String {
+        let Some(frame) = self.movie_frame(current_frame) else {
+            return tr("notification.annotation_before_movie").to_string();
+        };
+        match text {
+            Some(text) => self.annotations.set(frame, text),
+            None if self.annotations.remove(frame) => {}
+            None => return tr_arg("notification.no_annotation", frame),
+        }
+        self.update(current_frame, proxy);
+        match self.annotations.save(&self.path) {
+            Ok(()) => tr_arg("notification.annotation_saved", frame),
+            Err(err) => {
+                log::error!("{}", err);
+                err
+            }
+        }
+    }
+}
+
 #[cfg(feature = "audio-engine")]
 struct SoundBackend {
     _audio_engine: AudioEngine,
@@ -365,15 +545,51 @@ pub struct Emulator {
     state: EmulatorState,
     // When true, the program will sync the time that passed, and the time that is emulated.
     frame_limit: bool,
+    /// When true, each frame emulates a fixed number of clock cycles, instead of syncing with the
+    /// time that passed, or running for a fixed time at max speed. See `Config::deterministic`.
+    deterministic: bool,
     rewind: bool,
     /// The instant when the gameboy emulation was unpaused. Used in combination with
     /// `last_start_clock` to calculate the ammount of clocks to emulate.
     last_start_time: Instant,
     /// The clock_count when the gameboy emulation was unpaused. See `last_start_time`.
     last_start_clock: u64,
+    /// When the cartridge RAM was last checked for changes to save. See
+    /// `Config::autosave_interval`.
+    last_autosave: Instant,
+    /// The clock count at which the emulator exits by itself. See `Config::auto_exit`.
+    exit_clock: Option<u64>,
+    /// The address at which the emulator exits by itself. See `Config::auto_exit`.
+    exit_pc: Option<u16>,
+    /// Set when the game sends the text of `AutoExit::on_serial`.
+    exit_serial: Arc<AtomicBool>,
+    /// If the emulator already reached a exit condition, and is waiting to be killed.
+    exiting: bool,
+    /// The CRC32 of the rom file, under which the play time is recorded. See `play_stats`.
+    play_hash: Option<u32>,
+    /// When the emulator last started running, if it is running.
+    play_start: Option<Instant>,
+    /// The annotations of the movie, if a movie is being played. Also updated by the
+    /// `GameBoy::v_blank` callback.
+    annotation_track: Option<Arc<ParkMutex<AnnotationTrack>>>,
+    /// The byte from the DMG-07 hub waiting for the game to start a transfer, if any.
+    link_transfer: Option<PendingTransfer>,
+    /// See `EmulatorEvent::Mute`.
+    muted: bool,
 
     debugger: Arc<ParkMutex<Debugger>>,
 
+    /// Where a copy of the GameBoy state is published after each frame and each pause, for the UI.
+    snapshot: Snapshot,
+
+    /// The last recorded input macro, waiting to be bound to a key.
+    recorded_macro: Option<Vec<(u32, u8)>>,
+    /// The input macros of the rom. See `input_macros`.
+    macros: Vec<InputMacro>,
+
+    /// The save states of the rom, and the branches between them.
+    state_tree: StateTree,
+
     #[cfg(feature = "audio-engine")]
     /// The sound backend.
     sound: Option<SoundBackend>,
@@ -390,6 +606,8 @@ impl Emulator {
     pub fn new(
         gb: Arc<ParkMutex<GameBoy>>,
         debugger: Arc<ParkMutex<Debugger>>,
+        snapshot: Snapshot,
+        input_latency: InputLatency,
         proxy: EventLoopProxy<UserEvent>,
         movie: Option<Vbm>,
         rom: RomFile,
@@ -410,7 +628,11 @@ impl Emulator {
                 std::mem::forget(sound);
 
                 let mut gb = gb.lock();
-                gb.sound.get_mut().sample_frequency = audio_engine.sample_rate() as u64;
+                let sound = gb.sound.get_mut();
+                sound.sample_frequency = audio_engine.sample_rate() as u64;
+                // for the oscilloscope in the debugger.
+                sound.record_channels = true;
+                sound.filter_mode = config().audio_filter.filter_mode();
 
                 Some(SoundBackend {
                     _audio_engine: audio_engine,
@@ -429,20 +651,44 @@ impl Emulator {
         };
         let frame_clock_count = 154 * 456;
         let current_frame = (clock_count / frame_clock_count) as u32;
-        const BOOT_FRAMES: u64 = 23_384_580 / (154 * 456);
-        let joypad_timeline = movie.map_or(Vec::new(), |m| {
-            (0..BOOT_FRAMES)
-                .map(|_| 0)
-                .chain(m.controller_data)
-                .map(|x| {
-                    let joy = !(x as u8);
-                    ((joy & 0x0F) << 4) | (joy >> 4)
-                })
-                .collect()
+        // the movie unique id is the time when its recording started, in seconds since the UNIX
+        // epoch. A movie that starts from a save state keeps the clock of the state.
+        let from_state = movie.as_ref().is_some_and(|m| {
+            matches!(
+                m.start(),
+                VbmStart::SaveState(_) | VbmStart::SaveStateReference(_)
+            )
         });
+        if !from_state {
+            let rtc_time = movie.as_ref().map_or(0, |m| m.uid as u64);
+            gb.lock().cartridge.set_rtc_time(rtc_time);
+        }
+        let joypad_timeline = movie
+            .as_ref()
+            .map_or(Vec::new(), |movie| movie_timeline(movie, &gb.lock()));
+
+        // loaded before locking the config, which `input_macros::load` also locks.
+        let macros = crate::input_macros::load(&rom.file_name());
 
         let config = config();
 
+        let annotation_track = match (&movie, config.movie_annotations.clone()) {
+            (Some(movie), Some(path)) => {
+                let annotations = MovieAnnotations::load(&path).unwrap_or_else(|err| {
+                    log::error!("{}", err);
+                    MovieAnnotations::default()
+                });
+                Some(Arc::new(ParkMutex::new(AnnotationTrack {
+                    annotations,
+                    path,
+                    // see `movie_timeline`.
+                    start_frame: (joypad_timeline.len() - movie.controller_data.len()) as u32,
+                    shown: String::new(),
+                })))
+            }
+            _ => None,
+        };
+
         let mib = 2usize.pow(20);
         let capacity = if config.rewinding { 32 * mib } else { 0 };
         let joypad = Arc::new(ParkMutex::new(Timeline::new(
@@ -454,35 +700,86 @@ impl Emulator {
             let game_boy = &mut gb.lock();
             let mut old = game_boy.v_blank.take();
             let joypad = joypad.clone();
+            let snapshot = snapshot.clone();
+            let annotation_track = annotation_track.clone();
+            let proxy = proxy.clone();
             game_boy.v_blank = Some(Box::new(move |gb| {
                 if let Some(x) = old.as_mut() {
                     x(gb)
                 }
+                snapshot.publish(gb);
                 let joypad = &mut *joypad.lock();
                 if !joypad.rewinding {
                     gb.joypad = joypad.next_frame(gb);
+                    input_latency.v_blank(gb.joypad);
+                }
+                if let Some(track) = &annotation_track {
+                    track.lock().update(joypad.current_frame, &proxy);
+                }
+            }));
+        }
+
+        let exit_serial = Arc::new(AtomicBool::new(false));
+        if let Some(text) = config.auto_exit.on_serial.clone() {
+            let game_boy = &mut gb.lock();
+            let serial = game_boy.serial.get_mut();
+            let mut old = serial.serial_transfer_callback.take();
+            let exit_serial = exit_serial.clone();
+            let mut received = Vec::new();
+            serial.serial_transfer_callback = Some(Box::new(move |c| {
+                if let Some(x) = old.as_mut() {
+                    x(c)
+                }
+                // only the end of the output is kept, as long as the text.
+                received.push(c);
+                if received.len() > text.len() {
+                    received.remove(0);
+                }
+                if received == text.as_bytes() {
+                    exit_serial.store(true, Ordering::Relaxed);
                 }
             }));
         }
 
         let last_start_time = Instant::now();
         let last_start_clock = gb.lock().clock_count;
+        let state_tree = StateTree::load(&rom);
         Self {
             gb,
             proxy,
             #[cfg(target_arch = "x86_64")]
-            jit_compiler: config.jit.then(gameroy_jit::JitCompiler::new),
+            // the JIT compiler would not stop at `exit_pc`.
+            jit_compiler: (config.jit && config.auto_exit.at_pc.is_none())
+                .then(|| new_jit_compiler(&config)),
             joypad,
             rom,
             debug: false,
             state: EmulatorState::Idle,
             frame_limit: !config.frame_skip,
+            deterministic: config.deterministic,
             rewind: false,
 
             last_start_time,
             last_start_clock,
+            last_autosave: Instant::now(),
+            exit_clock: config
+                .auto_exit
+                .after
+                .map(|x| last_start_clock + x.clock_cycles()),
+            exit_pc: config.auto_exit.at_pc,
+            exit_serial,
+            exiting: false,
+            play_hash: None,
+            play_start: None,
+            annotation_track,
+            link_transfer: None,
+            muted: false,
 
             debugger,
+            snapshot,
+            recorded_macro: None,
+            macros,
+            state_tree,
             #[cfg(feature = "audio-engine")]
             sound,
         }
@@ -491,15 +788,138 @@ impl Emulator {
     fn set_state(&mut self, new_state: EmulatorState) {
         if self.state == EmulatorState::Idle {
             self.proxy.send_event(UserEvent::EmulatorStarted).unwrap();
+            self.play_start.get_or_insert_with(Instant::now);
         }
         if new_state == EmulatorState::Idle {
+            self.snapshot.publish(&self.gb.lock());
             self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
+            self.record_play_time();
         }
         self.state = new_state;
     }
 
+    /// Add the time since the emulator started running to the play time of the rom.
+    fn record_play_time(&mut self) {
+        if let (Some(hash), Some(start)) = (self.play_hash, self.play_start.take()) {
+            crate::play_stats::add_play_time(hash, start.elapsed());
+        }
+    }
+
+    /// Run the emulator until receiving a `EmulatorEvent::Kill`.
+    ///
+    /// If the emulator panics, the panic is caught and reported to the UI by a
+    /// `UserEvent::EmulatorCrashed`. The thread then keeps handling events: `SaveRam` and
+    /// `SaveState` save the data of the crashed emulator (the state is saved as a crash state), and
+    /// `Restart` creates a new emulator from the current state of the GameBoy.
     #[cfg(feature = "threads")]
-    pub fn event_loop(&mut self, recv: flume::Receiver<EmulatorEvent>) {
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        gb: Arc<ParkMutex<GameBoy>>,
+        debugger: Arc<ParkMutex<Debugger>>,
+        snapshot: Snapshot,
+        input_latency: InputLatency,
+        proxy: EventLoopProxy<UserEvent>,
+        mut movie: Option<Vbm>,
+        rom: RomFile,
+        recv: flume::Receiver<EmulatorEvent>,
+    ) {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // counted once, even if the emulator is restarted after a crash.
+        let play_hash = rom
+            .get_hash()
+            .map_err(|err| log::error!("error hashing rom, play time not recorded: {}", err))
+            .ok();
+        if let Some(hash) = play_hash {
+            crate::play_stats::record_launch(hash, &rom.file_name());
+        }
+
+        loop {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                let mut emulator = Emulator::new(
+                    gb.clone(),
+                    debugger.clone(),
+                    snapshot.clone(),
+                    input_latency.clone(),
+                    proxy.clone(),
+                    movie.take(),
+                    rom.clone(),
+                );
+                emulator.play_hash = play_hash;
+                emulator.event_loop(&recv)
+            }));
+
+            let payload = match result {
+                Ok(()) => return,
+                Err(payload) => payload,
+            };
+
+            let message = if let Some(s) = payload.downcast_ref::<&'static str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "Box<Any>".to_string()
+            };
+            let (pc, clock_count) = {
+                let gb = gb.lock();
+                (gb.cpu.pc, gb.clock_count)
+            };
+            log::error!(
+                "emulator crashed at pc {:04x}, clock {}: {}",
+                pc,
+                clock_count,
+                message
+            );
+            let _ = proxy.send_event(UserEvent::EmulatorCrashed {
+                message,
+                pc,
+                clock_count,
+            });
+
+            // keep handling events, to not block the UI thread.
+            loop {
+                match recv.recv() {
+                    Ok(EmulatorEvent::Restart) => {
+                        log::info!("restarting emulator");
+                        break;
+                    }
+                    Ok(EmulatorEvent::SaveRam) if config().read_only_saves => {
+                        log::info!("saves are read-only, not saving game ram data");
+                    }
+                    Ok(EmulatorEvent::SaveRam) => {
+                        log::info!("saving game ram data... ");
+                        match rom.save_ram_data(&gb.lock().cartridge.ram) {
+                            Ok(_) => log::info!("save success"),
+                            Err(x) => log::error!("saving failed: {}", x),
+                        }
+                    }
+                    Ok(EmulatorEvent::SaveState) => {
+                        log::info!("save crash state");
+                        let mut state = Vec::new();
+                        let result = catch_unwind(AssertUnwindSafe(|| {
+                            gb.lock().save_state(timestamp(), &mut state)
+                        }));
+                        match result {
+                            Ok(Ok(())) => match rom.save_crash_state(&state) {
+                                Ok(_) => {}
+                                Err(e) => log::error!("error saving crash state: {}", e),
+                            },
+                            _ => log::error!("error saving crash state"),
+                        }
+                    }
+                    Ok(EmulatorEvent::Kill) | Err(_) => {
+                        log::info!("exiting emulator thread");
+                        return;
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    pub fn event_loop(&mut self, recv: &flume::Receiver<EmulatorEvent>) {
         'event_loop: while let Ok(mut event) = recv.recv() {
             'handle_event: loop {
                 if self.handle_event(event) {
@@ -525,68 +945,240 @@ impl Emulator {
 
         log::info!("exiting emulator thread");
 
+        self.record_play_time();
+        self.save_ram();
+
+        if config().save_state_on_exit {
+            self.handle_event(EmulatorEvent::SaveState);
+        }
+    }
+
+    /// Write the cartridge RAM to the save file, unless `Config::read_only_saves` is set.
+    fn save_ram(&mut self) {
+        if config().read_only_saves {
+            log::info!("saves are read-only, not saving game ram data");
+            return;
+        }
         log::info!("saving game ram data... ");
-        match self.rom.save_ram_data(&self.gb.lock().cartridge.ram) {
-            Ok(_) => log::info!("save success"),
+        let mut gb = self.gb.lock();
+        match self.rom.save_ram_data(&gb.cartridge.ram) {
+            Ok(_) => {
+                log::info!("save success");
+                gb.ram_modified = false;
+                // the UI may be already closed, when saving on exit.
+                let _ = self.proxy.send_event(UserEvent::RamSaved);
+            }
             Err(x) => log::error!("saving failed: {}", x),
         }
     }
 
+    /// Save the cartridge RAM, if the game wrote to it and the autosave interval has passed since
+    /// the last check.
+    fn autosave(&mut self) {
+        let interval = config().autosave_interval;
+        if interval == 0 || self.last_autosave.elapsed() < Duration::from_secs(interval as u64) {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        if self.gb.lock().ram_modified {
+            self.save_ram();
+        }
+    }
+
+    /// Check the conditions of `Config::auto_exit`. When one is reached, write the screenshot and
+    /// the save state it asks for, and ask the UI to quit, which kills the emulator. Returns true
+    /// if the emulator is exiting.
+    fn check_auto_exit(&mut self) -> bool {
+        if self.exiting {
+            return true;
+        }
+        let reason = {
+            let gb = self.gb.lock();
+            if self.exit_clock.map_or(false, |x| gb.clock_count >= x) {
+                format!("ran until clock {}", gb.clock_count)
+            } else if Some(gb.cpu.pc) == self.exit_pc {
+                format!("reached pc {:04x}", gb.cpu.pc)
+            } else if self.exit_serial.load(Ordering::Relaxed) {
+                "received the serial text".to_string()
+            } else {
+                return false;
+            }
+        };
+        log::info!("exiting: {}", reason);
+        println!("exiting: {}", reason);
+        self.exiting = true;
+
+        let auto_exit = config().auto_exit.clone();
+        let gb = self.gb.lock();
+        if let Some(path) = &auto_exit.screenshot {
+            let rgba: Vec<u8> = gb
+                .ppu
+                .borrow()
+                .screen
+                .packed()
+                .iter()
+                .flat_map(|&x| {
+                    let [r, g, b] = crate::DMG_PALETTE[x as usize % 4];
+                    [r, g, b, 255]
+                })
+                .collect();
+            let result =
+                crate::image_pool::encode_png(&rgba, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+                    .and_then(|png| std::fs::write(path, png).map_err(|e| e.to_string()));
+            match result {
+                Ok(()) => log::info!("saved screenshot to '{}'", path.display()),
+                Err(e) => log::error!("error saving screenshot: {}", e),
+            }
+        }
+        if let Some(path) = &auto_exit.save_state {
+            let mut state = Vec::new();
+            gb.save_state(timestamp(), &mut state).unwrap();
+            match std::fs::write(path, state) {
+                Ok(()) => log::info!("saved state to '{}'", path.display()),
+                Err(e) => log::error!("error saving state: {}", e),
+            }
+        }
+        drop(gb);
+
+        self.set_state(EmulatorState::Idle);
+        let _ = self.proxy.send_event(UserEvent::Quit);
+        true
+    }
+
     fn update_start_time(&mut self, clock_count: u64) {
         self.last_start_time = Instant::now();
         self.last_start_clock = clock_count;
     }
 
+    /// Save the current state as a new node of the state tree, and return it.
+    fn branch_state(&mut self, name: String) -> Vec<u8> {
+        let mut state = Vec::new();
+        let clock_count = {
+            let gb = self.gb.lock();
+            gb.save_state(timestamp(), &mut state).unwrap();
+            gb.clock_count
+        };
+        match self.state_tree.branch(&self.rom, name, clock_count, &state) {
+            Ok(id) => log::info!("saved branch state #{}", id),
+            Err(e) => log::error!("error saving branch state: {}", e),
+        }
+        self.send_state_tree();
+        state
+    }
+
+    /// Load a save state. If it fails, the current state is kept and false is returned.
+    fn load_state(&mut self, state: &[u8]) -> bool {
+        let mut gb = self.gb.lock();
+
+        let mut old_state = Vec::new();
+        gb.save_state(timestamp(), &mut old_state).unwrap();
+
+        let loaded = match gb.load_state(&mut &state[..]) {
+            Ok(_) => {
+                log::info!("load state");
+                true
+            }
+            Err(_) => {
+                log::error!("error loading save state: save state is malformatted");
+                // restore current state
+                gb.load_state(&mut old_state.as_slice()).unwrap();
+                false
+            }
+        };
+        let clock_count = gb.clock_count;
+        self.snapshot.publish(&gb);
+        drop(gb);
+        self.update_start_time(clock_count);
+        // send EmulatorPaused to trigger the EmulatorUpdated event.
+        self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
+        // and send Started again, because the emulation is not paused.
+        self.proxy.send_event(UserEvent::EmulatorStarted).unwrap();
+        loaded
+    }
+
+    /// Send a copy of the state tree to the UI.
+    fn send_state_tree(&self) {
+        let tree = Box::new(self.state_tree.clone());
+        // the UI may be already closed, when a state is saved on exit.
+        let _ = self.proxy.send_event(UserEvent::StateTreeUpdated(tree));
+    }
+
     /// Return true if should terminate event_loop.
     pub fn handle_event(&mut self, event: EmulatorEvent) -> bool {
         use EmulatorEvent::*;
         match event {
-            SaveRam => {
-                log::info!("saving game ram data... ");
-                match self.rom.save_ram_data(&self.gb.lock().cartridge.ram) {
-                    Ok(_) => log::info!("save success"),
-                    Err(x) => log::error!("saving failed: {}", x),
+            SaveRam => self.save_ram(),
+            RollbackRam => {
+                if config().read_only_saves {
+                    log::error!("saves are read-only, not rolling back the save");
+                    return false;
                 }
+                let ram = match self.rom.rollback_ram_data() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        log::error!("error rolling back save: {}", e);
+                        return false;
+                    }
+                };
+                let mut gb = self.gb.lock();
+                if let Err(e) = gb.cartridge.load_ram(ram) {
+                    log::error!("error loading save backup: {}", e);
+                    return false;
+                }
+                gb.reset();
+                let clock_count = gb.clock_count;
+                self.snapshot.publish(&gb);
+                drop(gb);
+                log::info!("rolled back save");
+                self.update_start_time(clock_count);
             }
             SaveState => {
                 log::info!("save state");
-                let mut state = Vec::new();
-                self.gb.lock().save_state(timestamp(), &mut state).unwrap();
+                let state = self.branch_state(String::new());
                 match self.rom.save_state(&state) {
                     Ok(_) => {}
                     Err(e) => log::error!("error saving state: {}", e),
                 }
+                if let Err(e) = self.state_tree.set_saved(&self.rom) {
+                    log::error!("error saving state tree: {}", e);
+                }
             }
             LoadState => {
                 match self.rom.load_state() {
                     Ok(state) => {
-                        let mut gb = self.gb.lock();
-
-                        let mut old_state = Vec::new();
-                        gb.save_state(timestamp(), &mut old_state).unwrap();
-
-                        match gb.load_state(&mut state.as_slice()) {
-                            Ok(_) => {
-                                log::info!("load state")
-                            }
-                            Err(_) => {
-                                log::error!("error loading save state: save state is malformatted");
-                                // restore current state
-                                gb.load_state(&mut old_state.as_slice()).unwrap();
+                        if self.load_state(&state) {
+                            if let Err(e) = self.state_tree.checkout_saved(&self.rom) {
+                                log::error!("error saving state tree: {}", e);
                             }
+                            self.send_state_tree();
                         }
-                        let clock_count = gb.clock_count;
-                        drop(gb);
-                        self.update_start_time(clock_count);
-                        // send EmulatorPaused to trigger the EmulatorUpdated event.
-                        self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
-                        // and send Started again, because the emulation is not paused.
-                        self.proxy.send_event(UserEvent::EmulatorStarted).unwrap();
                     }
                     Err(e) => log::error!("error loading saved state: {}", e),
                 };
             }
+            BranchState(name) => {
+                log::info!("save branch state");
+                self.branch_state(name);
+            }
+            LoadBranch(id) => match self.state_tree.checkout(&self.rom, id) {
+                Ok(state) => {
+                    self.load_state(&state);
+                    self.send_state_tree();
+                }
+                Err(e) => log::error!("error loading branch state: {}", e),
+            },
+            LoadStateData(state) => {
+                self.load_state(&state);
+            }
+            RenameBranch(id, name) => {
+                if let Err(e) = self.state_tree.rename(&self.rom, id, name) {
+                    log::error!("error renaming branch state: {}", e);
+                }
+                self.send_state_tree();
+            }
             Kill => return true,
+            // the emulator is only restarted after a crash, see `Emulator::run`.
+            Restart => {}
             RunFrame => {
                 if !self.debug {
                     self.set_state(EmulatorState::RunNoBreak);
@@ -621,13 +1213,25 @@ impl Emulator {
                 }
             }
             SetJoypad(joypad) => {
-                self.joypad.lock().current_joypad = joypad;
+                let timeline = &mut *self.joypad.lock();
+                timeline.current_joypad = config().dpad_policy.apply(
+                    timeline.keys_joypad,
+                    joypad,
+                    timeline.current_joypad,
+                );
+                timeline.keys_joypad = joypad;
             }
             Debug(value) => {
                 if self.debug == value {
                     return false;
                 }
                 self.debug = value;
+                // record where each pixel came from, for inspecting the screen while debugging.
+                self.gb
+                    .lock()
+                    .ppu
+                    .get_mut()
+                    .set_pixel_sources_enabled(value);
                 if self.debug {
                     self.debugger.lock().last_op_clock = None;
                     self.set_state(EmulatorState::Idle);
@@ -681,6 +1285,11 @@ impl Emulator {
                     }
                 }
             }
+            RunBack => {
+                if self.debug {
+                    self.run_back();
+                }
+            }
             Run => {
                 if self.debug {
                     self.set_state(EmulatorState::Run);
@@ -689,22 +1298,226 @@ impl Emulator {
                     self.debugger.lock().step(gb);
                 }
             }
-            Reset => {
-                self.gb.lock().reset();
-                log::info!("reset");
+            Reset(options) => {
+                if options.save_state {
+                    self.branch_state(tr("state_tree.before_reset").to_string());
+                }
+                let mut gb = self.gb.lock();
+                let message = if options.power_cycle {
+                    let ram_fill = power_cycle_ram_fill(gb.ram_fill);
+                    gb.power_cycle(ram_fill);
+                    log::info!("power cycle");
+                    tr("notification.power_cycle")
+                } else {
+                    gb.reset();
+                    log::info!("reset");
+                    tr("notification.reset")
+                };
+                drop(gb);
+                let message = if options.save_state {
+                    format!("{} {}", message, tr("notification.state_saved"))
+                } else {
+                    message.to_string()
+                };
+                self.proxy.send_event(UserEvent::Notify(message)).unwrap();
                 self.set_state(EmulatorState::Idle);
             }
+            ReloadRom(cartridge) => {
+                let mut cartridge = *cartridge;
+                let reload_mode = config().reload_mode;
+                let mut gb = self.gb.lock();
+                if reload_mode == ReloadMode::KeepRam {
+                    let ram = std::mem::take(&mut gb.cartridge.ram);
+                    if let Err(err) = cartridge.load_ram(ram) {
+                        log::warn!("could not keep the cartridge ram: {}", err);
+                    }
+                }
+                gb.cartridge = cartridge;
+                gb.reset();
+                *gb.trace.borrow_mut() = Trace::new();
+                trace_entry_points(&gb);
+                let clock_count = gb.clock_count;
+                self.snapshot.publish(&gb);
+                drop(gb);
+
+                // the compiled blocks are from the old rom.
+                #[cfg(target_arch = "x86_64")]
+                if let Some(jit_compiler) = &mut self.jit_compiler {
+                    jit_compiler.flush();
+                }
+
+                log::info!("reloaded rom");
+                self.update_start_time(clock_count);
+                if reload_mode == ReloadMode::LoadState {
+                    return self.handle_event(LoadState);
+                }
+            }
             Pause => {
                 self.debug = true;
             }
             Resume => {
                 self.debug = false;
             }
+            RecordMacro(true) => {
+                log::info!("recording input macro");
+                self.joypad.lock().recording = Some(Vec::new());
+            }
+            RecordMacro(false) => {
+                let Some(mut inputs) = self.joypad.lock().recording.take() else {
+                    return false;
+                };
+                // ignore the frames before the first and after the last button press.
+                while inputs.last().map_or(false, |&(_, joypad)| joypad == 0xff) {
+                    inputs.pop();
+                }
+                let start = inputs.iter().position(|&(_, joypad)| joypad != 0xff);
+                inputs.drain(..start.unwrap_or(inputs.len()));
+                log::info!("recorded input macro with {} inputs", inputs.len());
+                self.recorded_macro = (!inputs.is_empty()).then_some(inputs);
+            }
+            BindMacro(key) => {
+                let Some(inputs) = self.recorded_macro.take() else {
+                    return false;
+                };
+                log::info!("binding input macro to {:?}", key);
+                let input_macro = InputMacro { key, inputs };
+                crate::input_macros::save(&self.rom.file_name(), input_macro.clone());
+                crate::input_macros::insert(&mut self.macros, input_macro);
+            }
+            PlayMacro(key) => {
+                let input_macro = self.macros.iter().find(|x| x.key == key);
+                if let Some(input_macro) = input_macro {
+                    log::info!("replaying input macro bound to {:?}", key);
+                    self.joypad.lock().playing = Some((input_macro.inputs.clone(), 0, 0));
+                }
+            }
+            SetJit(enable) => {
+                let message = self.set_jit(enable);
+                log::info!("{}", message);
+                self.proxy.send_event(UserEvent::Notify(message)).unwrap();
+            }
+            Annotate(text) => {
+                let message = match &self.annotation_track {
+                    Some(track) => {
+                        let current_frame = self.joypad.lock().current_frame;
+                        track.lock().edit(current_frame, text, &self.proxy)
+                    }
+                    None => tr("notification.no_movie").to_string(),
+                };
+                self.proxy.send_event(UserEvent::Notify(message)).unwrap();
+            }
+            LinkTransfer(byte, answer) => {
+                let clock = self.gb.lock().clock_count;
+                self.link_transfer = Some(PendingTransfer {
+                    byte,
+                    clock,
+                    answer,
+                });
+            }
+            Mute(value) => self.muted = value,
         }
         false
     }
 
+    /// Exchange the byte received from the DMG-07 hub with the game, if it is waiting for a
+    /// transfer, or answer that it is not ready if it did not start one for a frame. Called before
+    /// each run of the emulator, so the exchange always happens between two runs, at the clock
+    /// where the last one stopped.
+    fn update_link_transfer(&mut self) {
+        let Some(transfer) = &self.link_transfer else {
+            return;
+        };
+        // the link thread gave up waiting, and the hub moved on.
+        if transfer.answer.is_disconnected() {
+            self.link_transfer = None;
+            return;
+        }
+        let mut gb = self.gb.lock();
+        let sent = Serial::external_transfer(&mut gb, transfer.byte);
+        if sent.is_none() && gb.clock_count < transfer.clock + FRAME_CYCLES {
+            return;
+        }
+        let _ = transfer.answer.send(sent);
+        self.link_transfer = None;
+    }
+
+    /// Switch between the JIT compiler and the interpreter, and return a message for the user.
+    ///
+    /// Events are only handled between the calls to `poll`, after the last JIT block returned, so
+    /// the switch always happens between two instructions, and no compiled code is running. The
+    /// compiled blocks are discarded when switching to the interpreter.
+    #[cfg(target_arch = "x86_64")]
+    fn set_jit(&mut self, enable: Option<bool>) -> String {
+        let enable = enable.unwrap_or(self.jit_compiler.is_none());
+        if !enable {
+            self.jit_compiler = None;
+            return tr("notification.interpreter").to_string();
+        }
+        // the JIT compiler would not stop at `exit_pc`.
+        if self.exit_pc.is_some() {
+            return tr("notification.jit_exit_at_pc").to_string();
+        }
+        self.jit_compiler
+            .get_or_insert_with(|| new_jit_compiler(&config()));
+        tr("notification.jit").to_string()
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn set_jit(&mut self, _enable: Option<bool>) -> String {
+        tr("notification.jit_unavailable").to_string()
+    }
+
+    /// Go back to the last point before the current one where a breakpoint was triggered. Each
+    /// saved frame, from the newest to the oldest, is replayed until the start of the next one,
+    /// looking for a breakpoint, and then the emulator runs from the frame to the breakpoint. The
+    /// frames after it are discarded, like in StepBack. If there is none, stop at the oldest frame.
+    fn run_back(&mut self) {
+        let mut gb = self.gb.lock();
+        let mut joypad = self.joypad.lock();
+        // the replay never crosses a frame, and the callback would lock the joypad.
+        let v_blank = gb.v_blank.take();
+        let mut end_clock = gb.clock_count;
+        let mut found = None;
+        while let Some(frame_clock) = joypad.last_frame_clock_count() {
+            if frame_clock >= end_clock {
+                joypad.pop_last_frame();
+                continue;
+            }
+            assert!(joypad.load_last_frame(&mut gb));
+            found = self.debugger.lock().find_last_break(&mut gb, end_clock);
+            if found.is_some() || !joypad.has_earlier_frame() {
+                assert!(joypad.load_last_frame(&mut gb));
+                break;
+            }
+            end_clock = frame_clock;
+            joypad.pop_last_frame();
+        }
+        gb.v_blank = v_blank;
+        drop(joypad);
+        drop(gb);
+
+        let Some(clock) = found else {
+            log::warn!("there is no earlier breakpoint");
+            self.debugger.lock().last_op_clock = None;
+            self.set_state(EmulatorState::Idle);
+            return;
+        };
+        {
+            let debugger = &mut *self.debugger.lock();
+            debugger.target_clock = Some(clock);
+            debugger.skip_breakpoints_until_target_clock = true;
+        }
+        self.set_state(EmulatorState::Run);
+    }
+
     pub fn poll(&mut self) -> Control {
+        self.autosave();
+        if self.check_auto_exit() {
+            return Control::Wait;
+        }
+        if matches!(self.state, EmulatorState::Run | EmulatorState::RunNoBreak) && !self.rewind {
+            self.update_link_transfer();
+        }
         match self.state {
             EmulatorState::Idle | EmulatorState::WaitNextFrame => {}
             EmulatorState::Run => {
@@ -714,7 +1527,8 @@ impl Emulator {
                     let mut debugger = self.debugger.lock();
                     use RunResult::*;
                     match debugger.run_for(&mut gb, CLOCK_SPEED / 600) {
-                        ReachBreakpoint | ReachTargetAddress | ReachTargetClock => {
+                        ReachBreakpoint | ReachTargetAddress | ReachTargetClock
+                        | ReachTargetLine => {
                             drop(gb);
                             drop(debugger);
                             self.set_state(EmulatorState::Idle);
@@ -743,6 +1557,38 @@ impl Emulator {
                             gb.v_blank = c;
                         }
                     }
+                    self.set_state(EmulatorState::WaitNextFrame);
+                } else if self.deterministic {
+                    // emulate exactly one frame worth of clock cycles, independent of how much
+                    // time has passed, with or without frame limit.
+                    let mut gb = self.gb.lock();
+                    let target_clock = self.last_start_clock.max(gb.clock_count) + 154 * 456;
+                    self.last_start_clock = target_clock;
+
+                    // make the JIT stop at the same instruction as the interpreter would.
+                    gb.set_stop_clock(Some(target_clock));
+                    while gb.clock_count < target_clock && Some(gb.cpu.pc) != self.exit_pc {
+                        #[cfg(target_arch = "x86_64")]
+                        if let Some(jit_compiler) = &mut self.jit_compiler {
+                            jit_compiler.interpret_block(&mut gb);
+                        } else {
+                            Interpreter(&mut gb).interpret_op();
+                        }
+                        #[cfg(not(target_arch = "x86_64"))]
+                        Interpreter(&mut gb).interpret_op();
+                    }
+                    gb.set_stop_clock(None);
+
+                    if !self.frame_limit {
+                        // clear the audio output
+                        let clock_count = gb.clock_count;
+                        let _ = gb.sound.get_mut().get_output(clock_count);
+                        return Control::Poll;
+                    }
+
+                    drop(gb);
+                    self.update_audio();
+
                     self.set_state(EmulatorState::WaitNextFrame);
                 } else if self.frame_limit {
                     let mut gb = self.gb.lock();
@@ -759,7 +1605,7 @@ impl Emulator {
                         self.last_start_clock = gb.clock_count;
                     }
 
-                    while gb.clock_count < target_clock {
+                    while gb.clock_count < target_clock && Some(gb.cpu.pc) != self.exit_pc {
                         #[cfg(target_arch = "x86_64")]
                         if let Some(jit_compiler) = &mut self.jit_compiler {
                             jit_compiler.interpret_block(&mut gb);
@@ -779,7 +1625,7 @@ impl Emulator {
                     let mut gb = self.gb.lock();
                     let target_clock = gb.clock_count + CLOCK_SPEED / 600;
 
-                    while gb.clock_count < target_clock {
+                    while gb.clock_count < target_clock && Some(gb.cpu.pc) != self.exit_pc {
                         #[cfg(target_arch = "x86_64")]
                         if let Some(jit_compiler) = &mut self.jit_compiler {
                             jit_compiler.interpret_block(&mut gb);
@@ -812,22 +1658,40 @@ impl Emulator {
         {
             let mut gb = self.gb.lock();
             let clock_count = gb.clock_count;
-            let buffer = gb.sound.get_mut().get_output(clock_count);
+            let buffer = gb.sound.get_mut().get_filtered_output(clock_count);
 
             let mut lock = audio_buffer.lock();
             if lock.len() == 0 {
                 // if the buffer is empty, add zeros to increase it
                 lock.extend((0..1600 * 5).map(|_| 0));
             }
-            lock.extend(buffer.iter().map(|&x| (x as i16 - 128) * 30));
+            if self.muted {
+                // keep the timing of the sound, so unmuting don't add latency.
+                lock.extend(buffer.iter().map(|_| 0));
+            } else {
+                lock.extend(buffer);
+            }
 
             *last_buffer_len = lock.len();
         }
     }
 }
 
+/// Create a JIT compiler with the options from the config. Takes the config, because the callers
+/// may already be holding its lock.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn new_jit_compiler(config: &Config) -> gameroy_jit::JitCompiler {
+    let mut jit_compiler = gameroy_jit::JitCompiler::new();
+    jit_compiler.opts.max_code_size = Some(config.jit_max_code_size << 20);
+    jit_compiler
+}
+
 /// The number of milliseconds since UNIX_EPOCH.
-fn timestamp() -> Option<u64> {
+pub(crate) fn timestamp() -> Option<u64> {
+    // the save time would make the save states of identical runs differ.
+    if config().deterministic {
+        return None;
+    }
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         // cannot handle times before year 1970.
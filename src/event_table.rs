@@ -10,10 +10,17 @@ use giui::{Context, Id};
 
 pub trait Event: Clone + 'static {}
 
+/// The screen changed in the last emulated frame. Not sent for static screens.
 #[derive(Clone, Copy)]
 pub struct FrameUpdated;
 impl Event for FrameUpdated {}
 
+/// A frame was emulated, even if the screen didn't change. For the panels that show the state of
+/// the emulator.
+#[derive(Clone, Copy)]
+pub struct FrameTick;
+impl Event for FrameTick {}
+
 #[derive(Clone, Copy)]
 pub struct EmulatorUpdated;
 impl Event for EmulatorUpdated {}
@@ -26,6 +33,37 @@ impl Event for BreakpointsUpdated {}
 pub struct WatchsUpdated;
 impl Event for WatchsUpdated {}
 
+#[derive(Clone, Copy)]
+pub struct LabelsUpdated;
+impl Event for LabelsUpdated {}
+
+#[derive(Clone, Copy)]
+pub struct StateTreeUpdated;
+impl Event for StateTreeUpdated {}
+
+/// A short message to be shown to the user over the screen, like the confirmation of a reset.
+#[derive(Clone)]
+pub struct Notification(pub String);
+impl Event for Notification {}
+
+/// The annotation of the movie being played changed. See `AppState::annotation`.
+#[derive(Clone)]
+pub struct Annotation(pub String);
+impl Event for Annotation {}
+
+/// The cartridge RAM was written to the save file.
+#[derive(Clone, Copy)]
+pub struct RamSaved;
+impl Event for RamSaved {}
+
+/// Go back or forward in the history of jumps of the disassembly view.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DisassemblyNavigation {
+    Back,
+    Forward,
+}
+impl Event for DisassemblyNavigation {}
+
 pub struct UpdatedRomList;
 
 /// A handle to a registered event callback. When this is dropped, the callback is unregistered.
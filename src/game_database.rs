@@ -0,0 +1,104 @@
+//! A small database of known games, keyed by the CRC32 of the entire rom.
+//!
+//! The entries are in `assets/game_database.ron`, a map from the CRC32 of the rom (the same hash
+//! used by No-Intro DAT files) to its canonical title, region and known emulation notes. The file
+//! is optional: if it is missing, no rom is recognized.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GameInfo {
+    /// The canonical title of the game.
+    pub title: String,
+    /// The region of the release, like "USA", "Japan" or "World".
+    #[serde(default)]
+    pub region: String,
+    /// The revision of the release, like "Rev 1", if it is not the first one.
+    #[serde(default)]
+    pub revision: String,
+    /// Known issues or quirks when emulating this game.
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+impl GameInfo {
+    /// The title followed by the region and revision, in the format used by No-Intro.
+    pub fn full_title(&self) -> String {
+        let mut title = self.title.clone();
+        for tag in [&self.region, &self.revision] {
+            if !tag.is_empty() {
+                title += &format!(" ({})", tag);
+            }
+        }
+        title
+    }
+}
+
+type Database = HashMap<u32, GameInfo>;
+
+static DATABASE: OnceCell<Database> = OnceCell::new();
+
+#[cfg(not(feature = "static"))]
+fn read_database() -> Option<String> {
+    let mut path = crate::config::base_folder()?;
+    path.push("assets/game_database.ron");
+    std::fs::read_to_string(&path)
+        .map_err(|err| log::debug!("no game database at '{}': {}", path.display(), err))
+        .ok()
+}
+
+#[cfg(feature = "static")]
+fn read_database() -> Option<String> {
+    Some(include_str!("../assets/game_database.ron").to_string())
+}
+
+fn database() -> &'static Database {
+    DATABASE.get_or_init(|| {
+        read_database()
+            .and_then(|file| {
+                ron::from_str(&file)
+                    .map_err(|err| log::error!("failed to parse game database: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default()
+    })
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC32 (IEEE) of the given data.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Find the game with the given rom CRC32.
+pub fn lookup_hash(crc32: u32) -> Option<&'static GameInfo> {
+    database().get(&crc32)
+}
+
+/// Find the game of the given rom.
+pub fn lookup(rom: &[u8]) -> Option<&'static GameInfo> {
+    lookup_hash(crc32(rom))
+}
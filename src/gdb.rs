@@ -0,0 +1,518 @@
+//! A server for the GDB Remote Serial Protocol, allowing gdb (or any frontend that speaks the
+//! protocol) to debug the emulated CPU.
+//!
+//! The server runs in its own thread, and drives the emulator through the same channels used by
+//! the debugger UI: breakpoints are added to the `Debugger`, and execution is controlled by sending
+//! `EmulatorEvent`s. The emulator reports that it stopped through `UserEvent::EmulatorPaused`,
+//! which the `EmulatorApp` forwards to the server with `GdbServer::notify_stop`.
+//!
+//! The registers are sent in the order AF, BC, DE, HL, SP, PC, each as a 16-bit little-endian
+//! value, matching the first registers of gdb's Z80 target.
+
+use std::{
+    collections::BTreeMap,
+    net::{Shutdown, TcpListener, TcpStream},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use gameroy::{
+    debugger::{break_flags, Debugger},
+    gameboy::GameBoy,
+};
+use gdbstub::{
+    arch::{Arch, RegId, Registers, SingleStepGdbBehavior},
+    common::Signal,
+    conn::ConnectionExt,
+    stub::{run_blocking, DisconnectReason, GdbStub, SingleThreadStopReason},
+    target::{
+        ext::{
+            base::{
+                singlethread::{
+                    SingleThreadBase, SingleThreadResume, SingleThreadResumeOps,
+                    SingleThreadSingleStep, SingleThreadSingleStepOps,
+                },
+                BaseOps,
+            },
+            breakpoints::{
+                Breakpoints, BreakpointsOps, HwWatchpoint, HwWatchpointOps, SwBreakpoint,
+                SwBreakpointOps, WatchKind,
+            },
+        },
+        Target, TargetResult,
+    },
+};
+use parking_lot::Mutex;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{emulator::EmulatorEvent, UserEvent};
+
+/// The Sharp SM83, the CPU of the Game Boy.
+pub enum Sm83 {}
+impl Arch for Sm83 {
+    type Usize = u16;
+    type Registers = Sm83Registers;
+    type BreakpointKind = usize;
+    type RegId = Sm83RegId;
+
+    fn single_step_gdb_behavior() -> SingleStepGdbBehavior {
+        SingleStepGdbBehavior::Required
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Sm83Registers {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+}
+impl Registers for Sm83Registers {
+    type ProgramCounter = u16;
+
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        for reg in [self.af, self.bc, self.de, self.hl, self.sp, self.pc] {
+            for byte in reg.to_le_bytes() {
+                write_byte(Some(byte));
+            }
+        }
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        if bytes.len() < 12 {
+            return Err(());
+        }
+        let mut regs = bytes
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]));
+        for reg in [
+            &mut self.af,
+            &mut self.bc,
+            &mut self.de,
+            &mut self.hl,
+            &mut self.sp,
+            &mut self.pc,
+        ] {
+            *reg = regs.next().ok_or(())?;
+        }
+        Ok(())
+    }
+}
+
+/// The index of a register, in the order they are serialized.
+#[derive(Debug, Clone, Copy)]
+pub struct Sm83RegId(usize);
+impl RegId for Sm83RegId {
+    fn from_raw_id(id: usize) -> Option<(Self, Option<NonZeroUsize>)> {
+        (id < 6).then(|| (Sm83RegId(id), NonZeroUsize::new(2)))
+    }
+}
+
+/// What the emulator is doing, from the point of view of the debugger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExecState {
+    Stopped,
+    Running,
+    Stepping,
+    Interrupted,
+}
+
+/// A breakpoint added by gdb.
+#[derive(Default)]
+struct GdbBreak {
+    /// The flags gdb asked for.
+    flags: u8,
+    /// The flags that were not set before, by the user, and are removed with the breakpoint.
+    added: u8,
+}
+
+struct GdbTarget {
+    gb: Arc<Mutex<GameBoy>>,
+    debugger: Arc<Mutex<Debugger>>,
+    emu_channel: flume::Sender<EmulatorEvent>,
+    stopped: flume::Receiver<()>,
+    state: ExecState,
+    /// The breakpoints added by gdb, by address.
+    breakpoints: BTreeMap<u16, GdbBreak>,
+}
+impl GdbTarget {
+    fn add_break(&mut self, address: u16, flags: u8) {
+        let debugger = &mut *self.debugger.lock();
+        let old_flags = debugger.breakpoints().get(&address).copied().unwrap_or(0);
+        let entry = self.breakpoints.entry(address).or_default();
+        entry.flags |= flags;
+        entry.added |= flags & !old_flags;
+        debugger.add_break(flags, address);
+    }
+
+    /// Remove the given flags from a breakpoint added by gdb. The flags that were already set
+    /// when gdb added it are kept. Returns false if gdb has no such breakpoint.
+    fn remove_break(&mut self, address: u16, flags: u8) -> bool {
+        let Some(entry) = self.breakpoints.get_mut(&address) else {
+            return false;
+        };
+        if entry.flags & flags == 0 {
+            return false;
+        }
+        let removed = entry.added & flags;
+        entry.flags &= !flags;
+        entry.added &= !flags;
+        if entry.flags == 0 {
+            self.breakpoints.remove(&address);
+        }
+        if removed != 0 {
+            remove_break_flags(&mut self.debugger.lock(), address, removed);
+        }
+        true
+    }
+
+    /// Remove all breakpoints added by gdb, when the session ends.
+    fn clear_breakpoints(&mut self) {
+        let debugger = &mut *self.debugger.lock();
+        for (address, entry) in std::mem::take(&mut self.breakpoints) {
+            if entry.added != 0 {
+                remove_break_flags(debugger, address, entry.added);
+            }
+        }
+    }
+
+    fn resume_with(&mut self, event: EmulatorEvent, state: ExecState) -> Result<(), &'static str> {
+        // discard stops reported before this resume.
+        while self.stopped.try_recv().is_ok() {}
+        self.state = state;
+        self.emu_channel
+            .send(event)
+            .map_err(|_| "emulator channel is closed")
+    }
+
+    /// The reason the emulator stopped, given that it was running in the given state.
+    fn stop_reason(&self, state: ExecState) -> SingleThreadStopReason<u16> {
+        let pc = self.gb.lock().cpu.pc;
+        let at_breakpoint = self
+            .debugger
+            .lock()
+            .breakpoints()
+            .get(&pc)
+            .map_or(false, |flags| flags & break_flags::EXECUTE != 0);
+        match state {
+            ExecState::Stepping => SingleThreadStopReason::DoneStep,
+            ExecState::Interrupted => SingleThreadStopReason::Signal(Signal::SIGINT),
+            _ if at_breakpoint => SingleThreadStopReason::SwBreak(()),
+            _ => SingleThreadStopReason::Signal(Signal::SIGTRAP),
+        }
+    }
+}
+
+impl Target for GdbTarget {
+    type Arch = Sm83;
+    type Error = &'static str;
+
+    fn base_ops(&mut self) -> BaseOps<'_, Self::Arch, Self::Error> {
+        BaseOps::SingleThread(self)
+    }
+
+    fn support_breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadBase for GdbTarget {
+    fn read_registers(&mut self, regs: &mut Sm83Registers) -> TargetResult<(), Self> {
+        let cpu = &self.gb.lock().cpu;
+        let pair = |h: u8, l: u8| u16::from_be_bytes([h, l]);
+        *regs = Sm83Registers {
+            af: pair(cpu.a, cpu.f.0),
+            bc: pair(cpu.b, cpu.c),
+            de: pair(cpu.d, cpu.e),
+            hl: pair(cpu.h, cpu.l),
+            sp: cpu.sp,
+            pc: cpu.pc,
+        };
+        Ok(())
+    }
+
+    fn write_registers(&mut self, regs: &Sm83Registers) -> TargetResult<(), Self> {
+        let cpu = &mut self.gb.lock().cpu;
+        [cpu.a, cpu.f.0] = regs.af.to_be_bytes();
+        // the lower nibble of F is always zero.
+        cpu.f.0 &= 0xF0;
+        [cpu.b, cpu.c] = regs.bc.to_be_bytes();
+        [cpu.d, cpu.e] = regs.de.to_be_bytes();
+        [cpu.h, cpu.l] = regs.hl.to_be_bytes();
+        cpu.sp = regs.sp;
+        cpu.pc = regs.pc;
+        Ok(())
+    }
+
+    fn read_addrs(&mut self, start_addr: u16, data: &mut [u8]) -> TargetResult<usize, Self> {
+        let gb = self.gb.lock();
+        let mut len = 0;
+        for (byte, address) in data.iter_mut().zip(start_addr..=0xFFFF) {
+            *byte = gb.peek(address);
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    fn write_addrs(&mut self, start_addr: u16, data: &[u8]) -> TargetResult<(), Self> {
+        let mut gb = self.gb.lock();
+        for (&byte, address) in data.iter().zip(start_addr..=0xFFFF) {
+            gb.write(address, byte);
+        }
+        Ok(())
+    }
+
+    fn support_resume(&mut self) -> Option<SingleThreadResumeOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadResume for GdbTarget {
+    fn resume(&mut self, _signal: Option<Signal>) -> Result<(), Self::Error> {
+        self.resume_with(EmulatorEvent::Run, ExecState::Running)
+    }
+
+    fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadSingleStep for GdbTarget {
+    fn step(&mut self, _signal: Option<Signal>) -> Result<(), Self::Error> {
+        self.resume_with(EmulatorEvent::Step, ExecState::Stepping)
+    }
+}
+
+impl Breakpoints for GdbTarget {
+    fn support_sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_hw_watchpoint(&mut self) -> Option<HwWatchpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+/// Remove the given flags from the breakpoint at `address`, keeping the other ones.
+fn remove_break_flags(debugger: &mut Debugger, address: u16, flags: u8) {
+    let Some(&old_flags) = debugger.breakpoints().get(&address) else {
+        return;
+    };
+    debugger.remove_break(address);
+    if old_flags & !flags != 0 {
+        debugger.add_break(old_flags & !flags, address);
+    }
+}
+
+fn watch_flags(kind: WatchKind) -> u8 {
+    match kind {
+        WatchKind::Write => break_flags::WRITE,
+        WatchKind::Read => break_flags::READ,
+        WatchKind::ReadWrite => break_flags::READ | break_flags::WRITE,
+    }
+}
+
+impl SwBreakpoint for GdbTarget {
+    fn add_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        self.add_break(addr, break_flags::EXECUTE);
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(&mut self, addr: u16, _kind: usize) -> TargetResult<bool, Self> {
+        Ok(self.remove_break(addr, break_flags::EXECUTE))
+    }
+}
+
+impl HwWatchpoint for GdbTarget {
+    fn add_hw_watchpoint(
+        &mut self,
+        addr: u16,
+        len: u16,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        for address in (addr..=0xFFFF).take(len as usize) {
+            self.add_break(address, watch_flags(kind));
+        }
+        Ok(true)
+    }
+
+    fn remove_hw_watchpoint(
+        &mut self,
+        addr: u16,
+        len: u16,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        let mut removed = false;
+        for address in (addr..=0xFFFF).take(len as usize) {
+            removed |= self.remove_break(address, watch_flags(kind));
+        }
+        Ok(removed)
+    }
+}
+
+enum GdbEventLoop {}
+impl run_blocking::BlockingEventLoop for GdbEventLoop {
+    type Target = GdbTarget;
+    type Connection = TcpStream;
+    type StopReason = SingleThreadStopReason<u16>;
+
+    fn wait_for_stop_reason(
+        target: &mut GdbTarget,
+        conn: &mut TcpStream,
+    ) -> Result<
+        run_blocking::Event<Self::StopReason>,
+        run_blocking::WaitForStopReasonError<&'static str, std::io::Error>,
+    > {
+        loop {
+            match target.stopped.recv_timeout(Duration::from_millis(10)) {
+                Ok(()) => {
+                    let state = std::mem::replace(&mut target.state, ExecState::Stopped);
+                    return Ok(run_blocking::Event::TargetStopped(
+                        target.stop_reason(state),
+                    ));
+                }
+                Err(flume::RecvTimeoutError::Timeout) => {}
+                Err(flume::RecvTimeoutError::Disconnected) => {
+                    return Err(run_blocking::WaitForStopReasonError::Target(
+                        "emulator was closed",
+                    ))
+                }
+            }
+
+            if conn
+                .peek()
+                .map_err(run_blocking::WaitForStopReasonError::Connection)?
+                .is_some()
+            {
+                let byte = conn
+                    .read()
+                    .map_err(run_blocking::WaitForStopReasonError::Connection)?;
+                return Ok(run_blocking::Event::IncomingData(byte));
+            }
+        }
+    }
+
+    fn on_interrupt(target: &mut GdbTarget) -> Result<Option<Self::StopReason>, &'static str> {
+        // Stop at the next instruction, in the same way that step back stops at a target clock.
+        let clock_count = target.gb.lock().clock_count;
+        target.debugger.lock().target_clock = Some(clock_count);
+        target.state = ExecState::Interrupted;
+        Ok(None)
+    }
+}
+
+/// A handle to the gdb server thread. The server, and the current session, are closed when
+/// dropped.
+pub struct GdbServer {
+    port: u16,
+    stopped: flume::Sender<()>,
+    closed: Arc<AtomicBool>,
+    /// The connection of the current session, shut down to end it.
+    session: Arc<Mutex<Option<TcpStream>>>,
+}
+impl GdbServer {
+    /// Listen for gdb connections at the given port, in a new thread. Only one connection is
+    /// handled at a time.
+    pub fn start(
+        port: u16,
+        gb: Arc<Mutex<GameBoy>>,
+        debugger: Arc<Mutex<Debugger>>,
+        emu_channel: flume::Sender<EmulatorEvent>,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("failed to listen on port {}: {}", port, e))?;
+        log::info!("gdb server listening on port {}", port);
+
+        let (stopped, stop_recv) = flume::unbounded();
+        let closed = Arc::new(AtomicBool::new(false));
+        let session = Arc::new(Mutex::new(None));
+        let mut target = GdbTarget {
+            gb,
+            debugger,
+            emu_channel,
+            stopped: stop_recv,
+            state: ExecState::Stopped,
+            breakpoints: BTreeMap::new(),
+        };
+
+        std::thread::Builder::new()
+            .name("gdb server".to_string())
+            .spawn({
+                let closed = closed.clone();
+                let session = session.clone();
+                move || {
+                    for stream in listener.incoming() {
+                        if closed.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        let stream = match stream {
+                            Ok(x) => x,
+                            Err(e) => {
+                                log::error!("gdb connection failed: {}", e);
+                                continue;
+                            }
+                        };
+                        log::info!("gdb connected from {:?}", stream.peer_addr());
+                        *session.lock() = stream.try_clone().ok();
+                        // the server may have been closed before the session was stored.
+                        if closed.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        // enter debug mode, which pauses the emulator.
+                        if proxy.send_event(UserEvent::Debug(true)).is_err() {
+                            break;
+                        }
+                        target.state = ExecState::Stopped;
+
+                        match GdbStub::new(stream).run_blocking::<GdbEventLoop>(&mut target) {
+                            Ok(DisconnectReason::Disconnect) => log::info!("gdb disconnected"),
+                            Ok(reason) => log::info!("gdb session ended: {:?}", reason),
+                            Err(e) => log::error!("gdb session failed: {}", e),
+                        }
+                        *session.lock() = None;
+                        target.clear_breakpoints();
+                        if closed.load(Ordering::Relaxed) {
+                            break;
+                        }
+                    }
+                    log::info!("exiting gdb server thread");
+                }
+            })
+            .map_err(|e| format!("failed to spawn gdb server thread: {}", e))?;
+
+        Ok(Self {
+            port,
+            stopped,
+            closed,
+            session,
+        })
+    }
+
+    /// Notify the server that the emulator has stopped running.
+    pub fn notify_stop(&self) {
+        let _ = self.stopped.send(());
+    }
+}
+impl Drop for GdbServer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        // end the current session, if any.
+        if let Some(stream) = self.session.lock().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        // wake up the thread if it is waiting for a connection.
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+    }
+}
@@ -0,0 +1,113 @@
+//! A small pool of worker threads that encode PNG images, so that exporting images or generating
+//! thumbnails never stalls the emulator or the UI threads.
+//!
+//! The jobs go through a bounded queue. Images saved from the UI are refused when the queue is
+//! full, instead of blocking, while background threads that wait for the encoded image are slowed
+//! down to the speed of the pool. Without the `threads` feature the images are encoded in place.
+
+use std::path::PathBuf;
+
+use image::{codecs::png::PngEncoder, ImageEncoder};
+
+/// The number of worker threads.
+#[cfg(feature = "threads")]
+const WORKERS: usize = 2;
+/// The number of jobs that can wait for a free worker.
+#[cfg(feature = "threads")]
+const QUEUE_SIZE: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[cfg(feature = "threads")]
+static QUEUE: once_cell::sync::OnceCell<flume::Sender<Job>> = once_cell::sync::OnceCell::new();
+
+/// The sender of the job queue. The workers are spawned on the first call.
+#[cfg(feature = "threads")]
+fn queue() -> &'static flume::Sender<Job> {
+    QUEUE.get_or_init(|| {
+        let (sender, recv) = flume::bounded::<Job>(QUEUE_SIZE);
+        for i in 0..WORKERS {
+            let recv = recv.clone();
+            std::thread::Builder::new()
+                .name(format!("image encoder {}", i))
+                .spawn(move || {
+                    while let Ok(job) = recv.recv() {
+                        job();
+                    }
+                })
+                .unwrap();
+        }
+        sender
+    })
+}
+
+/// Encode a RGBA image as PNG, in the current thread.
+pub fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    PngEncoder::new(&mut buffer)
+        .write_image(rgba, width, height, image::ExtendedColorType::Rgba8)
+        .map_err(|err| format!("failed to png encode image: {}", err))?;
+    Ok(buffer)
+}
+
+/// Encode a RGBA image as PNG in a worker thread, and wait for the result. If the queue is full,
+/// this blocks until there is space in it.
+#[cfg(feature = "threads")]
+pub fn encode_png_blocking(rgba: Vec<u8>, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let (sender, recv) = flume::bounded(1);
+    let job = move || {
+        let _ = sender.send(encode_png(&rgba, width, height));
+    };
+    queue()
+        .send(Box::new(job))
+        .map_err(|_| "the image encoder stopped".to_string())?;
+    recv.recv()
+        .map_err(|_| "the image encoder stopped".to_string())?
+}
+
+#[cfg(not(feature = "threads"))]
+pub fn encode_png_blocking(rgba: Vec<u8>, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    encode_png(&rgba, width, height)
+}
+
+/// Encode a RGBA image as PNG, and write it to `path`, in a worker thread. Never blocks: returns an
+/// error if the queue is full. Errors of the encoding or writing are only logged.
+pub fn save_png(path: PathBuf, rgba: Vec<u8>, width: u32, height: u32) -> Result<(), String> {
+    let job = move || {
+        let result = encode_png(&rgba, width, height).and_then(|png| {
+            std::fs::write(&path, png)
+                .map_err(|err| format!("failed to save '{}': {}", path.display(), err))
+        });
+        match result {
+            Ok(()) => log::info!("saved '{}'", path.display()),
+            Err(err) => log::error!("{}", err),
+        }
+    };
+    try_run(Box::new(job))
+}
+
+/// Queue the job, or return a error if the queue is full.
+#[cfg(feature = "threads")]
+fn try_run(job: Job) -> Result<(), String> {
+    queue().try_send(job).map_err(|err| match err {
+        flume::TrySendError::Full(_) => "too many images are being saved, try again".to_string(),
+        flume::TrySendError::Disconnected(_) => "the image encoder stopped".to_string(),
+    })
+}
+
+#[cfg(not(feature = "threads"))]
+fn try_run(job: Job) -> Result<(), String> {
+    job();
+    Ok(())
+}
+
+/// If the queue is full, so new images saved by `save_png` are refused until a worker is free.
+#[cfg(feature = "threads")]
+pub fn is_saturated() -> bool {
+    QUEUE.get().is_some_and(|x| x.is_full())
+}
+
+#[cfg(not(feature = "threads"))]
+pub fn is_saturated() -> bool {
+    false
+}
@@ -0,0 +1,101 @@
+//! Measure the input latency: the time from a key event on the host to the VBlank where the
+//! emulated joypad is updated with it, and to the presentation of the first frame emulated with
+//! the new joypad.
+
+use std::sync::Arc;
+
+use instant::{Duration, Instant};
+use parking_lot::Mutex;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    /// From the key event to the VBlank where the emulated joypad took its state.
+    pub to_joypad: Duration,
+    /// From the key event to the presentation of the first frame emulated with the new joypad.
+    pub to_present: Duration,
+}
+
+#[derive(Default)]
+struct State {
+    /// If key events are measured. See `Config::input_latency_overlay`.
+    enabled: bool,
+    /// The joypad after the last key event, and when the event happened.
+    pressed: Option<(u8, Instant)>,
+    /// When the emulated joypad was updated to the pressed one.
+    applied: Option<Instant>,
+    /// If the frame emulated with the new joypad was finished.
+    emulated: bool,
+    last: Option<Measurement>,
+    /// The sum of `to_present` of all measurements, and their number.
+    total: (Duration, u32),
+}
+
+/// Shared between the main thread, which receives the key events and presents the frames, and the
+/// emulator. Only one key event is measured at a time: a new one restarts the measurement.
+#[derive(Clone)]
+pub struct InputLatency(Arc<Mutex<State>>);
+impl InputLatency {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            enabled,
+            ..State::default()
+        })))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.0.lock().enabled
+    }
+
+    /// Called when a key event changes the joypad to `joypad`.
+    pub fn key_event(&self, joypad: u8) {
+        let state = &mut *self.0.lock();
+        if !state.enabled {
+            return;
+        }
+        state.pressed = Some((joypad, Instant::now()));
+        state.applied = None;
+        state.emulated = false;
+    }
+
+    /// Called by the emulator at each VBlank, after updating the emulated joypad to `joypad`.
+    pub fn v_blank(&self, joypad: u8) {
+        let state = &mut *self.0.lock();
+        let Some((pressed, _)) = state.pressed else {
+            return;
+        };
+        if state.applied.is_none() {
+            if joypad == pressed {
+                state.applied = Some(Instant::now());
+            }
+        } else {
+            state.emulated = true;
+        }
+    }
+
+    /// Called by the main thread after presenting a frame.
+    pub fn presented(&self) {
+        let state = &mut *self.0.lock();
+        let (Some((_, pressed)), Some(applied), true) =
+            (state.pressed, state.applied, state.emulated)
+        else {
+            return;
+        };
+        let measurement = Measurement {
+            to_joypad: applied - pressed,
+            to_present: pressed.elapsed(),
+        };
+        state.last = Some(measurement);
+        state.total.0 += measurement.to_present;
+        state.total.1 += 1;
+        state.pressed = None;
+        state.applied = None;
+        state.emulated = false;
+    }
+
+    /// The last measurement, and the average time to presentation of all of them.
+    pub fn stats(&self) -> Option<(Measurement, Duration)> {
+        let state = self.0.lock();
+        let last = state.last?;
+        Some((last, state.total.0 / state.total.1))
+    }
+}
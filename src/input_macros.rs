@@ -0,0 +1,91 @@
+//! The input macros recorded in the emulator.
+//!
+//! The recorded macros are kept in '<data folder>/macros.toml' (see `dirs::data_folder`), indexed
+//! by the file name of the rom. Macros can also be written by hand in the `macros` table of the
+//! config. If a key is bound in both, the recorded macro is used.
+//!
+//! On the web there is no data folder, and the recorded macros are lost when the emulator closes.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use std::collections::BTreeMap;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{config, InputMacro};
+
+/// The file, relative to the data folder, that contains the recorded macros.
+const MACROS_FILE: &str = "macros.toml";
+
+/// Held while the file is read and written back, so concurrent updates are not lost.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct MacroStore {
+    /// The macros of each rom, indexed by the rom file name.
+    macros: BTreeMap<String, Vec<InputMacro>>,
+}
+impl MacroStore {
+    fn load() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return Self::default();
+        }
+        let path = crate::dirs::data_folder().join(MACROS_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    log::error!("failed reading '{}': {}", path.display(), err);
+                }
+                return Self::default();
+            }
+        };
+        toml::from_str(&text)
+            .map_err(|err| log::error!("failed parsing '{}': {}", path.display(), err))
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if cfg!(target_arch = "wasm32") {
+            return Ok(());
+        }
+        let path = crate::dirs::data_path(MACROS_FILE);
+        let text = toml::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(&path, text)
+            .map_err(|err| format!("failed writing '{}': {}", path.display(), err))
+    }
+}
+
+/// Add `input_macro` to `macros`, replacing the one bound to the same key.
+pub fn insert(macros: &mut Vec<InputMacro>, input_macro: InputMacro) {
+    macros.retain(|x| x.key != input_macro.key);
+    macros.push(input_macro);
+}
+
+/// The macros of the rom with the given file name: the ones in the config, and the recorded ones.
+pub fn load(rom_name: &str) -> Vec<InputMacro> {
+    let mut macros = config().macros.get(rom_name).cloned().unwrap_or_default();
+    let recorded = {
+        let _lock = LOCK.lock();
+        MacroStore::load().macros.remove(rom_name)
+    };
+    for input_macro in recorded.unwrap_or_default() {
+        insert(&mut macros, input_macro);
+    }
+    macros
+}
+
+/// Save a recorded macro of the rom with the given file name, replacing the one bound to the same
+/// key.
+pub fn save(rom_name: &str, input_macro: InputMacro) {
+    let _lock = LOCK.lock();
+    let mut store = MacroStore::load();
+    insert(
+        store.macros.entry(rom_name.to_string()).or_default(),
+        input_macro,
+    );
+    if let Err(err) = store.save() {
+        log::error!("error saving input macros: {}", err);
+    }
+}
@@ -8,12 +8,43 @@ pub static RESIZE: parking_lot::Mutex<Option<(u32, u32)>> = parking_lot::const_m
 
 mod waker_fn;
 
+mod colorization;
+mod debug_panel;
+mod dirs;
 mod emulator;
 mod event_table;
 pub mod executor;
+pub mod game_database;
+#[cfg(feature = "gdb")]
+mod gdb;
+mod image_pool;
+mod input_latency;
+mod input_macros;
+#[cfg(all(
+    feature = "threads",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+pub mod link;
+mod localization;
+mod play_stats;
+mod render;
 pub mod rom_loading;
+#[cfg(all(
+    feature = "threads",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+mod rom_watcher;
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+mod signal;
+mod snapshot;
+pub mod state_tree;
 mod style;
 mod ui;
+#[cfg(all(
+    feature = "threads",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+pub mod wall;
 mod widget {
     pub mod fold_view;
     pub mod menu;
@@ -26,16 +57,21 @@ mod widget {
 }
 pub mod config;
 
-use std::{any::Any, rc::Rc, sync::Arc, thread};
+use std::{any::Any, ops::Range, rc::Rc, sync::Arc, thread, time::Duration};
 
-use emulator::{Emulator, EmulatorEvent};
+use emulator::{Emulator, EmulatorEvent, ResetOptions};
 pub use gameroy;
 use gameroy::{
     consts::{SCREEN_HEIGHT, SCREEN_WIDTH, VERSION},
     debugger::{Debugger, DebuggerEvent},
-    gameboy::GameBoy,
+    gameboy::{
+        colorization::{ColorizationProfile, SourcePalettes},
+        ppu::Screen,
+        GameBoy,
+    },
     parser::Vbm,
 };
+use instant::Instant;
 use parking_lot::Mutex;
 #[cfg(feature = "rfd")]
 pub use rfd;
@@ -47,7 +83,7 @@ use winit::{
     window::{Icon, Window, WindowBuilder},
 };
 
-use crate::config::config;
+use crate::{config::config, input_latency::InputLatency, snapshot::Snapshot};
 
 use self::ui::RomEntries;
 
@@ -90,6 +126,9 @@ pub fn log_panic() {
 pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
     log::info!("GameRoy {}", VERSION);
 
+    let language = config().language.clone();
+    localization::init(language.as_deref().unwrap_or("en"));
+
     #[allow(unused_assignments, unused_mut)]
     let mut icon: Option<Icon> = None;
     #[cfg(target_os = "windows")]
@@ -128,12 +167,26 @@ pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
 
     let window = wb.build(&event_loop).unwrap();
 
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    signal::handle_termination(event_loop.create_proxy());
+
     let proxy = event_loop.create_proxy();
     let mut ui = ui::Ui::new(&window, proxy);
 
     let window = Rc::new(window);
     ui.gui.set(window.clone());
 
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    if config().spectator_window {
+        ui.open_spectator(&event_loop);
+    }
+
+    #[cfg(not(any(feature = "static", target_arch = "wasm32", target_os = "android")))]
+    style::watch_style_file(config().theme, event_loop.create_proxy());
+
     // initiate in the apropriated screen
     match gb {
         Some((file, gb)) => {
@@ -145,7 +198,7 @@ pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
             let emu = EmulatorApp::new(
                 gb,
                 proxy,
-                config::config().start_in_debug,
+                config::config().start_in_debug || debug_panel::layout().debug,
                 &mut ui,
                 movie,
                 file,
@@ -154,6 +207,10 @@ pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
         }
         _ => {
             let rom_loading = RomLoadingApp::new(&mut ui.gui, event_loop.create_proxy());
+            #[cfg(feature = "rfd")]
+            if config().start_with_file_dialog {
+                ui::open_rom_dialog(&mut ui.gui.get_context());
+            }
             start_event_loop(event_loop, window, ui, Box::new(rom_loading))
         }
     };
@@ -164,12 +221,33 @@ pub struct AppState {
     pub joypad: u8,
     /// If the emulation is in debug mode.
     pub debug: bool,
+    /// If the emulation was paused by the user, outside of debug mode.
+    pub paused: bool,
+    /// If the emulation is running without frame limit.
+    pub fast_forward: bool,
+    /// If the emulator thread is currently running the emulation.
+    pub running: bool,
+    /// If a Ctrl key is currently pressed, as reported by the modifiers of the window.
+    pub ctrl: bool,
+    /// If a input macro is being recorded.
+    pub recording_macro: bool,
+    /// If the next key pressed will be bound to the last recorded input macro.
+    pub binding_macro: bool,
+    /// The annotation of the movie shown over the screen. Empty if there is none.
+    pub annotation: String,
 }
 impl AppState {
     fn new(debug: bool) -> Self {
         Self {
             debug,
             joypad: 0xFF,
+            paused: false,
+            fast_forward: false,
+            running: false,
+            ctrl: false,
+            recording_macro: false,
+            binding_macro: false,
+            annotation: String::new(),
         }
     }
 }
@@ -205,7 +283,22 @@ fn start_event_loop(
             Event::NewEvents(_) => {
                 ui.new_events(control, &window);
             }
-            Event::WindowEvent { ref event, .. } => {
+            Event::WindowEvent {
+                window_id,
+                ref event,
+            } => {
+                #[cfg(all(
+                    feature = "threads",
+                    not(any(target_arch = "wasm32", target_os = "android"))
+                ))]
+                if ui.spectator_window_event(window_id, event) {
+                    return;
+                }
+                #[cfg(not(all(
+                    feature = "threads",
+                    not(any(target_arch = "wasm32", target_os = "android"))
+                )))]
+                let _ = window_id;
                 ui.window_event(event, &window);
                 match event {
                     WindowEvent::CloseRequested => {
@@ -214,6 +307,12 @@ fn start_event_loop(
                     WindowEvent::Resized(size) => {
                         ui.resize(*size, &window);
                     }
+                    // Reload the style for the new scale factor
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        if ui.style_is_outdated(&window) {
+                            rebuild_ui(&mut ui, &window, &**last(app));
+                        }
+                    }
                     // Rebuild the UI
                     #[cfg(not(feature = "static"))]
                     WindowEvent::KeyboardInput {
@@ -225,11 +324,7 @@ fn start_event_loop(
                             },
                         ..
                     } => {
-                        log::debug!("clear ui");
-                        ui.clear();
-                        ui.reload_graphics(&window);
-                        log::debug!("build ui");
-                        last(app).build_ui(&mut ui);
+                        rebuild_ui(&mut ui, &window, &**last(app));
                     }
                     // Load Dropped File
                     #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
@@ -247,11 +342,17 @@ fn start_event_loop(
                                     None
                                 }
                             };
+                            let game_boy =
+                                match rom_loading::load_gameboy(rom, ram, &file.file_name()) {
+                                    Ok(x) => x,
+                                    Err(err) => {
+                                        log::error!("failed to load rom: {}", err);
+                                        proxy.send_event(UserEvent::LoadFailed(err)).unwrap();
+                                        return;
+                                    }
+                                };
                             proxy
-                                .send_event(UserEvent::LoadRom {
-                                    file,
-                                    game_boy: rom_loading::load_gameboy(rom, ram).unwrap(),
-                                })
+                                .send_event(UserEvent::LoadRom { file, game_boy })
                                 .unwrap();
                         };
                         executor::Executor::spawn_task(task, &mut ui.gui.get_context());
@@ -261,6 +362,11 @@ fn start_event_loop(
             }
             Event::MainEventsCleared => {}
             Event::RedrawRequested(window_id) => {
+                // the spectator window only shows the screen, and must not drive the emulation.
+                if window_id != window.id() {
+                    ui.render(window_id);
+                    return;
+                }
                 #[cfg(target_arch = "wasm32")]
                 {
                     if let Some((width, height)) = RESIZE.lock().take() {
@@ -276,6 +382,13 @@ fn start_event_loop(
                     *control = ControlFlow::Poll;
                 }
             }
+            Event::UserEvent(UserEvent::ReloadStyle) => {
+                rebuild_ui(&mut ui, &window, &**last(app));
+                return;
+            }
+            Event::UserEvent(UserEvent::Quit) => {
+                *control = ControlFlow::Exit;
+            }
             Event::UserEvent(UserEvent::PopApp) => {
                 log::trace!("popping app");
                 app.pop();
@@ -284,12 +397,13 @@ fn start_event_loop(
             }
             Event::UserEvent(UserEvent::LoadRom { file, game_boy }) => {
                 let gb = game_boy;
+                let warnings = gb.cartridge.validation().warnings();
                 window.set_title(&format!("{} - gameroy", file.file_name()));
                 log::trace!("create emu!!");
                 let emu = EmulatorApp::new(
                     gb,
                     proxy.clone(),
-                    config::config().start_in_debug,
+                    config::config().start_in_debug || debug_panel::layout().debug,
                     &mut ui,
                     None,
                     file,
@@ -298,8 +412,14 @@ fn start_event_loop(
                 log::trace!("rebuilding ui for emulator");
                 ui.clear();
                 last(app).build_ui(&mut ui);
+                if !warnings.is_empty() {
+                    ui::open_rom_warnings(&mut ui, &warnings);
+                }
                 return;
             }
+            Event::UserEvent(UserEvent::LoadFailed(message)) => {
+                ui::open_load_error(&mut ui.gui.get_context(), &message);
+            }
             Event::UserEvent(UserEvent::SpawnTask(task_id)) => {
                 use std::future::Future;
                 let p = Arc::new(Mutex::new(proxy.clone()));
@@ -341,6 +461,15 @@ fn start_event_loop(
     })
 }
 
+/// Reload the style and all graphics, and build the UI again.
+fn rebuild_ui(ui: &mut ui::Ui, window: &Window, app: &dyn App) {
+    log::debug!("clear ui");
+    ui.clear();
+    ui.reload_graphics(window);
+    log::debug!("build ui");
+    app.build_ui(ui);
+}
+
 trait App: Any {
     fn handle_event(
         &mut self,
@@ -397,11 +526,258 @@ impl App for RomLoadingApp {
     }
 }
 
+/// The colors of each shade of the DMG screen, from lightest to darkest.
+pub(crate) const DMG_PALETTE: [[u8; 3]; 4] =
+    [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]];
+
+/// The duration of a frame of the GameBoy, 70224 clock cycles.
+const FRAME_DURATION: Duration = Duration::from_nanos(70224 * 1_000_000_000 / 4_194_304);
+
+/// The maximum number of bytes kept in `SerialOutput`. Newer bytes are dropped, with a warning in
+/// the log.
+const SERIAL_OUTPUT_CAPACITY: usize = 0x10000;
+
+/// All bytes sent through the serial port. Each viewer keeps the length it already shown.
+#[derive(Clone, Default)]
+pub struct SerialOutput(pub Arc<Mutex<Vec<u8>>>);
+
+/// A frame converted to RGBA.
+struct Frame {
+    rgba: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4]>,
+    /// The `LcdScreen::generation` of the frame in `rgba`.
+    generation: u64,
+}
+
+/// The frame shared between the emulator and the main thread, in a triple buffering scheme: the
+/// emulator writes each frame to its own back buffer, and swaps it with the one here. The main
+/// thread swaps the one here with its own front buffer. Only the boxes are swapped, so frames are
+/// never copied between threads.
+struct FrameSlot {
+    frame: Frame,
+    /// If `frame` was not taken by the main thread yet.
+    fresh: bool,
+    /// The rows that changed since the last frame taken by the main thread.
+    dirty_rows: Range<usize>,
+}
+impl FrameSlot {
+    /// Swap the front buffer with the last frame, if there is a new one. Returns the rows that
+    /// changed since the last frame taken.
+    #[cfg_attr(not(feature = "threads"), allow(dead_code))]
+    fn take(&mut self, front: &mut Frame) -> Range<usize> {
+        if !std::mem::take(&mut self.fresh) {
+            return 0..0;
+        }
+        std::mem::swap(front, &mut self.frame);
+        std::mem::replace(&mut self.dirty_rows, 0..0)
+    }
+}
+
+/// Converts the frames produced by the emulator to RGBA, and publishes them to a `FrameSlot`. Owned
+/// by the emulator thread.
+struct LcdScreen {
+    /// The last published frame, as shades of gray.
+    screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// The colors of the last published frame, when frames are blended.
+    blended: Option<Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4]>>,
+    /// Maps the value of a pixel of `screen` to its RGBA color, for each row. It has an entry for
+    /// each byte value so that the lookup don't need bounds checks. The rows only differ if the
+    /// screen is colorized.
+    lut: Box<[[[u8; 4]; 256]]>,
+    /// How much of the previous color is kept in each frame, out of 256. If 0, frames are not
+    /// blended.
+    persistence: i32,
+    /// Incremented on each published frame.
+    generation: u64,
+    /// The generation in which each row last changed.
+    row_generation: [u64; SCREEN_HEIGHT],
+    /// The frame being written.
+    back: Frame,
+    slot: Arc<Mutex<FrameSlot>>,
+}
+impl LcdScreen {
+    /// Returns the screen and the front buffer, to be used by the main thread.
+    ///
+    /// If `colorization` is given, the screen must be colorized, see
+    /// `Ppu::set_colorization_enabled`, and `palette` is ignored.
+    fn new(
+        palette: [[u8; 3]; 4],
+        colorization: Option<&ColorizationProfile>,
+        ghosting: f32,
+    ) -> (Self, Frame) {
+        let lut: Box<[[[u8; 4]; 256]]> = (0..SCREEN_HEIGHT as u8)
+            .map(|ly| {
+                let palette = match colorization {
+                    Some(profile) => profile.line_palette(ly),
+                    None => SourcePalettes::uniform(palette).line_palette(),
+                };
+                let mut lut = [[0, 0, 0, 255]; 256];
+                for (i, color) in lut.iter_mut().enumerate() {
+                    let [r, g, b] = palette[i % 16];
+                    *color = [r, g, b, 255];
+                }
+                lut
+            })
+            .collect();
+        // all buffers start with a blank screen.
+        let blank_colors: Vec<[u8; 4]> = lut.iter().map(|x| x[0]).collect();
+        let blank = || {
+            let mut rgba = Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT * 4]);
+            let rows = rgba.chunks_exact_mut(SCREEN_WIDTH * 4).zip(&blank_colors);
+            for (row, blank_color) in rows {
+                for color in row.chunks_exact_mut(4) {
+                    color.copy_from_slice(blank_color);
+                }
+            }
+            rgba
+        };
+        let frame = || Frame {
+            rgba: blank(),
+            generation: 0,
+        };
+        let persistence = (ghosting.clamp(0.0, 1.0) * 255.0) as i32;
+        let slot = FrameSlot {
+            frame: frame(),
+            fresh: true,
+            dirty_rows: 0..SCREEN_HEIGHT,
+        };
+        let this = Self {
+            screen: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            blended: (persistence != 0).then(blank),
+            lut,
+            persistence,
+            generation: 0,
+            row_generation: [0; SCREEN_HEIGHT],
+            back: frame(),
+            slot: Arc::new(Mutex::new(slot)),
+        };
+        (this, frame())
+    }
+
+    /// Same as `new`, with the palette and the options from the config. The screen of `gb` is
+    /// colorized if `Config::colorization` is set and the game has a profile.
+    fn for_game(gb: &mut GameBoy) -> (Self, Frame) {
+        let colorization = config()
+            .colorization
+            .then(|| colorization::lookup_hash(game_database::crc32(&gb.cartridge.rom)))
+            .flatten();
+        gb.ppu
+            .get_mut()
+            .set_colorization_enabled(colorization.is_some());
+        Self::new(DMG_PALETTE, colorization.as_ref(), config().lcd_ghosting)
+    }
+
+    /// Compare the new frame with the last one, and publish it if it changed. Returns false if
+    /// nothing changed.
+    fn update(&mut self, screen: &Screen) -> bool {
+        let changed = if self.persistence != 0 {
+            self.update_blended(screen)
+        } else {
+            self.update_rows(screen)
+        };
+        if changed.is_empty() {
+            return false;
+        }
+        self.write_back();
+
+        let slot = &mut *self.slot.lock();
+        std::mem::swap(&mut slot.frame, &mut self.back);
+        slot.fresh = true;
+        slot.dirty_rows = if slot.dirty_rows.is_empty() {
+            changed
+        } else {
+            slot.dirty_rows.start.min(changed.start)..slot.dirty_rows.end.max(changed.end)
+        };
+        true
+    }
+
+    /// Copy the rows that changed, and returns their range.
+    fn update_rows(&mut self, screen: &Screen) -> Range<usize> {
+        let generation = self.generation + 1;
+        let mut changed = 0..0;
+        let rows = screen
+            .row_iter()
+            .zip(self.screen.chunks_exact_mut(SCREEN_WIDTH));
+        for (y, (new, old)) in rows.enumerate() {
+            if new != old {
+                old.copy_from_slice(new);
+                self.row_generation[y] = generation;
+                if changed.is_empty() {
+                    changed.start = y;
+                }
+                changed.end = y + 1;
+            }
+        }
+        if !changed.is_empty() {
+            self.generation = generation;
+        }
+        changed
+    }
+
+    /// Same as `update_rows`, but mix the color of each pixel with its previous color, emulating
+    /// the slow response of the LCD. A row keeps changing for some frames after the screen stops
+    /// changing, until it converges.
+    fn update_blended(&mut self, screen: &Screen) -> Range<usize> {
+        let generation = self.generation + 1;
+        let blended = self.blended.as_mut().unwrap();
+        let mut changed = 0..0;
+        for (y, pixels) in screen.row_iter().enumerate() {
+            let rgba = &mut blended[y * SCREEN_WIDTH * 4..][..SCREEN_WIDTH * 4];
+            let lut = &self.lut[y];
+            let mut row_changed = false;
+            for (color, &pixel) in rgba.chunks_exact_mut(4).zip(pixels) {
+                let target = lut[pixel as usize];
+                for (prev, &curr) in color.iter_mut().zip(target.iter()) {
+                    // rounds towards `curr`, so it always converges.
+                    let next = curr as i32 + (*prev as i32 - curr as i32) * self.persistence / 256;
+                    row_changed |= next != *prev as i32;
+                    *prev = next as u8;
+                }
+            }
+            if row_changed {
+                self.row_generation[y] = generation;
+                if changed.is_empty() {
+                    changed.start = y;
+                }
+                changed.end = y + 1;
+            }
+        }
+        if !changed.is_empty() {
+            self.generation = generation;
+        }
+        changed
+    }
+
+    /// Bring the back buffer up to date, by writing only the rows that changed since the frame it
+    /// holds.
+    fn write_back(&mut self) {
+        let stride = SCREEN_WIDTH * 4;
+        for y in 0..SCREEN_HEIGHT {
+            if self.row_generation[y] <= self.back.generation {
+                continue;
+            }
+            let rgba = &mut self.back.rgba[y * stride..][..stride];
+            match &self.blended {
+                Some(blended) => rgba.copy_from_slice(&blended[y * stride..][..stride]),
+                None => {
+                    let pixels = &self.screen[y * SCREEN_WIDTH..][..SCREEN_WIDTH];
+                    let lut = &self.lut[y];
+                    for (color, &pixel) in rgba.chunks_exact_mut(4).zip(pixels) {
+                        color.copy_from_slice(&lut[pixel as usize]);
+                    }
+                }
+            }
+        }
+        self.back.generation = self.generation;
+    }
+}
+
 struct EmulatorApp {
+    /// Where the emulator publishes its frames.
+    #[cfg(feature = "threads")]
+    frame_slot: Arc<Mutex<FrameSlot>>,
+    /// The frame being shown.
     #[cfg(feature = "threads")]
-    lcd_screen: Arc<
-        parking_lot::lock_api::Mutex<parking_lot::RawMutex, [u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
-    >,
+    front_frame: Frame,
     emu_channel: flume::Sender<EmulatorEvent>,
     #[cfg(feature = "threads")]
     emu_thread: Option<thread::JoinHandle<()>>,
@@ -409,7 +785,26 @@ struct EmulatorApp {
     emulator: Emulator,
     #[cfg(not(feature = "threads"))]
     recv: flume::Receiver<emulator::EmulatorEvent>,
+    #[cfg(feature = "gdb")]
+    gdb_server: Option<gdb::GdbServer>,
+    /// The connection to a DMG-07 hub, if `link` is set in the config.
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    _link: Option<link::LinkClient>,
+    /// If the screen changed since the last `event_table::FrameUpdated`.
     update_frame: bool,
+    /// If a frame was emulated since the last `event_table::FrameTick`.
+    update_tick: bool,
+    /// When the last `EmulatorEvent::RunFrame` was sent.
+    last_run_frame: Instant,
+    /// When to run the next frame, if the last one didn't change the screen. A static screen is not
+    /// redrawn, so the next frame is not requested by `Event::RedrawRequested`.
+    next_frame: Option<Instant>,
+    rom: RomFile,
+    serial_output: SerialOutput,
+    input_latency: InputLatency,
 }
 impl EmulatorApp {
     fn new(
@@ -420,17 +815,42 @@ impl EmulatorApp {
         movie: Option<Vbm>,
         rom: RomFile,
     ) -> EmulatorApp {
-        let lcd_screen: Arc<Mutex<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>> =
-            Arc::new(Mutex::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]));
+        let (mut lcd_screen, front_frame) = LcdScreen::for_game(&mut gb);
+        ui.set_screen_colorized(gb.ppu.get_mut().colorization_enabled());
+        #[cfg(feature = "threads")]
+        let frame_slot = lcd_screen.slot.clone();
+        #[cfg(not(feature = "threads"))]
+        let _ = front_frame;
         gb.v_blank = Some(Box::new({
-            let lcd_screen = lcd_screen.clone();
             let proxy = proxy.clone();
             move |gb| {
-                {
-                    let img_data = &mut lcd_screen.lock();
-                    img_data.copy_from_slice(&gb.ppu.borrow().screen.packed());
+                let changed = lcd_screen.update(&gb.ppu.borrow().screen);
+                // a static screen don't need to be uploaded or redrawn again.
+                if changed {
+                    let _ = proxy.send_event(UserEvent::FrameUpdated);
+                }
+                let _ = proxy.send_event(UserEvent::FrameTick);
+            }
+        }));
+        let serial_output = SerialOutput::default();
+        let serial_stderr = config().serial_stderr;
+        gb.serial.get_mut().serial_transfer_callback = Some(Box::new({
+            let serial_output = serial_output.clone();
+            let mut full = false;
+            move |c| {
+                if serial_stderr {
+                    eprint!("{}", c as char);
+                }
+                let mut output = serial_output.0.lock();
+                if output.len() < SERIAL_OUTPUT_CAPACITY {
+                    output.push(c);
+                } else if !full {
+                    full = true;
+                    log::warn!(
+                        "the serial output reached {} bytes, the next bytes are not kept",
+                        SERIAL_OUTPUT_CAPACITY
+                    );
                 }
-                let _ = proxy.send_event(UserEvent::FrameUpdated);
             }
         }));
         let gb = Arc::new(Mutex::new(*gb));
@@ -449,24 +869,93 @@ impl EmulatorApp {
                 match event {
                     Step => emu_channel.send(EmulatorEvent::Step).unwrap(),
                     StepBack => emu_channel.send(EmulatorEvent::StepBack).unwrap(),
-                    Reset => emu_channel.send(EmulatorEvent::Reset).unwrap(),
+                    RunBack => emu_channel.send(EmulatorEvent::RunBack).unwrap(),
+                    Reset => emu_channel
+                        .send(EmulatorEvent::Reset(ResetOptions::default()))
+                        .unwrap(),
                     Run => emu_channel.send(EmulatorEvent::Run).unwrap(),
                     BreakpointsUpdate => proxy.send_event(UserEvent::BreakpointsUpdated).unwrap(),
                     WatchsUpdate => proxy.send_event(UserEvent::WatchsUpdated).unwrap(),
+                    LabelsUpdate => proxy.send_event(UserEvent::LabelsUpdated).unwrap(),
                 }
             }));
         }
+        #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+        rom_loading::add_recent_rom(&rom);
+        #[cfg(all(
+            feature = "threads",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        ))]
+        if config().watch_rom {
+            rom_watcher::watch_rom(
+                rom.path().to_path_buf(),
+                rom.mbc().map(str::to_string),
+                emu_channel.clone(),
+            );
+        }
+
         ui.gui.set::<Arc<Mutex<GameBoy>>>(gb.clone());
         ui.gui.set::<Arc<Mutex<Debugger>>>(debugger.clone());
         ui.gui.set(emu_channel.clone());
         ui.gui.set(AppState::new(debug));
+        ui.gui.set(serial_output.clone());
+        let snapshot = Snapshot::default();
+        snapshot.publish(&gb.lock());
+        ui.gui.set(snapshot.clone());
+        let input_latency = InputLatency::new(config().input_latency_overlay);
+        ui.gui.set(input_latency.clone());
+        ui.gui.set(state_tree::StateTree::load(&rom));
+
+        #[cfg(feature = "gdb")]
+        let gdb_server = config().gdb_port.and_then(|port| {
+            gdb::GdbServer::start(
+                port,
+                gb.clone(),
+                debugger.clone(),
+                emu_channel.clone(),
+                proxy.clone(),
+            )
+            .map_err(|e| log::error!("{}", e))
+            .ok()
+        });
+
+        #[cfg(all(
+            feature = "threads",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        ))]
+        let link = {
+            let config = config();
+            config.link.as_deref().and_then(|address| {
+                // the bytes from the hub arrive at network dependent times.
+                if config.deterministic || movie.is_some() {
+                    log::warn!(
+                        "the link is disabled while playing a movie or in deterministic mode"
+                    );
+                    return None;
+                }
+                link::LinkClient::start(address, emu_channel.clone())
+                    .map_err(|e| log::error!("{}", e))
+                    .ok()
+            })
+        };
 
         #[cfg(feature = "threads")]
         let emu_thread = {
+            let rom = rom.clone();
+            let input_latency = input_latency.clone();
             let join_handle = thread::Builder::new()
                 .name("emulator".to_string())
                 .spawn(move || {
-                    Emulator::new(gb, debugger, proxy, movie, rom).event_loop(recv);
+                    Emulator::run(
+                        gb,
+                        debugger,
+                        snapshot,
+                        input_latency,
+                        proxy,
+                        movie,
+                        rom,
+                        recv,
+                    );
                 })
                 .unwrap();
             Some(join_handle)
@@ -474,23 +963,97 @@ impl EmulatorApp {
 
         EmulatorApp {
             #[cfg(feature = "threads")]
-            lcd_screen,
+            frame_slot,
+            #[cfg(feature = "threads")]
+            front_frame,
 
             emu_channel,
             #[cfg(feature = "threads")]
             emu_thread,
             #[cfg(not(feature = "threads"))]
-            emulator: Emulator::new(gb, debugger, proxy, movie, rom),
+            emulator: Emulator::new(
+                gb,
+                debugger,
+                snapshot,
+                input_latency.clone(),
+                proxy,
+                movie,
+                rom.clone(),
+            ),
             #[cfg(not(feature = "threads"))]
             recv,
+            #[cfg(feature = "gdb")]
+            gdb_server,
+            #[cfg(all(
+                feature = "threads",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            _link: link,
             update_frame: true,
+            update_tick: true,
+            last_run_frame: Instant::now(),
+            next_frame: None,
+            rom,
+            serial_output,
+            input_latency,
+        }
+    }
+
+    /// Send the joypad state and request the next frame to the emulator.
+    fn run_frame(&mut self, ui: &mut ui::Ui) {
+        self.last_run_frame = Instant::now();
+        self.next_frame = None;
+        let joypad = ui.get::<AppState>().joypad;
+        self.emu_channel
+            .send(EmulatorEvent::SetJoypad(joypad))
+            .unwrap();
+        self.emu_channel.send(EmulatorEvent::RunFrame).unwrap();
+    }
+
+    /// Notify the panels of the last emulated frame, and run the next frame if the screen is static
+    /// and it is its time.
+    fn frame_tick(&mut self, ui: &mut ui::Ui, window: &Window, control: &mut ControlFlow) {
+        if std::mem::take(&mut self.update_tick) {
+            ui.notify(event_table::FrameTick);
+            // the panels changed, so the window is redrawn anyway, running the next frame.
+            if ui.render_is_dirty() {
+                window.request_redraw();
+                self.next_frame = None;
+            }
+        }
+        let Some(next_frame) = self.next_frame else {
+            return;
+        };
+        if Instant::now() >= next_frame {
+            self.run_frame(ui);
+            return;
+        }
+        match *control {
+            ControlFlow::Wait => *control = ControlFlow::WaitUntil(next_frame),
+            ControlFlow::WaitUntil(time) if next_frame < time => {
+                *control = ControlFlow::WaitUntil(next_frame)
+            }
+            _ => {}
         }
     }
 
     fn kill_emulator(&mut self) {
-        self.emu_channel.send(EmulatorEvent::Kill).unwrap();
+        if self.emu_channel.send(EmulatorEvent::Kill).is_err() {
+            log::error!("emulator channel is closed");
+        }
         #[cfg(feature = "threads")]
-        self.emu_thread.take().unwrap().join().unwrap();
+        if let Some(emu_thread) = self.emu_thread.take() {
+            if emu_thread.join().is_err() {
+                log::error!("emulator thread panicked");
+            }
+        }
+        let serial_output = self.serial_output.0.lock();
+        if config().serial_log && !serial_output.is_empty() {
+            log::info!("saving serial log...");
+            if let Err(err) = self.rom.save_serial_log(&serial_output) {
+                log::error!("error saving serial log: {}", err);
+            }
+        }
     }
 }
 impl Drop for EmulatorApp {
@@ -514,15 +1077,20 @@ impl App for EmulatorApp {
     ) {
         match event {
             Event::RedrawRequested(_) => {
-                let joypad = ui.get::<AppState>().joypad;
-                self.emu_channel
-                    .send(EmulatorEvent::SetJoypad(joypad))
-                    .unwrap();
-                self.emu_channel.send(EmulatorEvent::RunFrame).unwrap();
+                // the ui is rendered before this, so the current frame was already presented.
+                self.input_latency.presented();
+                self.run_frame(ui);
             }
             Event::Suspended => {
                 self.emu_channel.send(EmulatorEvent::SaveRam).unwrap();
             }
+            Event::LoopDestroyed => ui::save_debug_layout(ui),
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => {
+                ui.get::<AppState>().ctrl = modifiers.ctrl();
+            }
             #[cfg(not(feature = "threads"))]
             Event::MainEventsCleared => {
                 let mut poll = true;
@@ -549,8 +1117,6 @@ impl App for EmulatorApp {
                 }
 
                 if poll {
-                    use instant::{Duration, Instant};
-
                     // This assumes that every frame has 10 ms of slack time. Should estimate this
                     // somehow.
                     let next_frame = Instant::now() + Duration::from_micros(10_000);
@@ -568,29 +1134,21 @@ impl App for EmulatorApp {
                         }
                     }
                 }
+                // without threads, the screen is not uploaded here.
+                self.update_frame = false;
+                self.frame_tick(ui, window, _control);
             }
             #[cfg(feature = "threads")]
             Event::MainEventsCleared => {
+                if self.update_frame || ui.screen_texture_outdated() {
+                    let rows = self.frame_slot.lock().take(&mut self.front_frame);
+                    ui.update_screen_texture(&self.front_frame.rgba[..], rows);
+                }
                 if self.update_frame {
                     self.update_frame = false;
-                    let screen: &[u8] = &{
-                        let lock = self.lcd_screen.lock();
-                        *lock
-                    };
-                    const COLOR: [[u8; 3]; 4] =
-                        [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]];
-                    let mut img_data = vec![255; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
-                    for y in 0..SCREEN_HEIGHT {
-                        for x in 0..SCREEN_WIDTH {
-                            let i = (x + y * SCREEN_WIDTH) * 4;
-                            let c = screen[i / 4];
-                            img_data[i..i + 3].copy_from_slice(&COLOR[c as usize]);
-                        }
-                    }
-                    ui.update_screen_texture(&img_data);
-
                     ui.notify(event_table::FrameUpdated);
                 }
+                self.frame_tick(ui, window, _control);
             }
             Event::UserEvent(event) => {
                 use UserEvent::*;
@@ -599,21 +1157,57 @@ impl App for EmulatorApp {
                         self.update_frame = true;
                         window.request_redraw();
                     }
+                    FrameTick => {
+                        self.update_tick = true;
+                        // the screen is static, and not redrawn.
+                        if !self.update_frame {
+                            self.next_frame = Some(self.last_run_frame + FRAME_DURATION);
+                        }
+                    }
                     EmulatorStarted => {
                         log::debug!("emulator started");
+                        ui.get::<AppState>().running = true;
                         ui.force_render = true;
                         window.request_redraw();
                     }
                     EmulatorPaused => {
                         log::debug!("emulator paused");
+                        ui.get::<AppState>().running = false;
                         ui.notify(event_table::EmulatorUpdated);
                         ui.force_render = false;
+                        #[cfg(feature = "gdb")]
+                        if let Some(gdb_server) = &self.gdb_server {
+                            gdb_server.notify_stop();
+                        }
                     }
                     BreakpointsUpdated => ui.notify(event_table::BreakpointsUpdated),
                     WatchsUpdated => ui.notify(event_table::WatchsUpdated),
+                    LabelsUpdated => ui.notify(event_table::LabelsUpdated),
+                    StateTreeUpdated(tree) => {
+                        ui.gui.set(*tree);
+                        ui.notify(event_table::StateTreeUpdated);
+                    }
                     Debug(value) => {
                         ui.get::<AppState>().debug = value;
                         self.emu_channel.send(EmulatorEvent::Debug(value)).unwrap();
+                        // start the next session in the same mode.
+                        ui::save_debug_layout(ui);
+                    }
+                    Notify(message) => ui.notify(event_table::Notification(message)),
+                    Annotation(text) => {
+                        ui.get::<AppState>().annotation = text.clone();
+                        ui.notify(event_table::Annotation(text));
+                    }
+                    RamSaved => ui.notify(event_table::RamSaved),
+                    ImportSession(path) => ui::import_session(ui, &path),
+                    EmulatorCrashed {
+                        message,
+                        pc,
+                        clock_count,
+                    } => {
+                        ui.get::<AppState>().running = false;
+                        ui.force_render = false;
+                        ui::open_crash_menu(ui, &message, pc, clock_count);
                     }
                     _ => {}
                 }
@@ -624,32 +1218,64 @@ impl App for EmulatorApp {
 }
 
 pub enum UserEvent {
+    /// The screen changed in the last frame, and need to be uploaded and redrawn. Not sent for
+    /// static screens.
     FrameUpdated,
+    /// A frame was emulated. Sent every frame, even if the screen didn't change.
+    FrameTick,
     EmulatorPaused,
     EmulatorStarted,
     BreakpointsUpdated,
     WatchsUpdated,
+    /// The labels or the comments of the debugger changed.
+    LabelsUpdated,
+    /// The state tree of the rom changed. Contains a copy of it.
+    StateTreeUpdated(Box<state_tree::StateTree>),
     Debug(bool),
     UpdateTexture(u32, Box<[u8]>),
     NewTexture(u32, Box<dyn Fn() -> (u32, u32, Vec<u8>) + Send + 'static>),
     PopApp,
+    /// Exit gameroy, the same way as closing the window. Sent on a termination signal.
+    Quit,
     LoadRom {
         file: RomFile,
         game_boy: Box<GameBoy>,
     },
+    /// A ROM, or its save file, failed to load. Contains the reason.
+    LoadFailed(String),
     SpawnTask(u32),
     UpdateRomList,
     UpdatedRomList,
+    /// The style file was modified, and the UI needs to be rebuilt.
+    ReloadStyle,
+    /// A short message to show to the user over the screen.
+    Notify(String),
+    /// The annotation of the movie to show over the screen, from now on. Empty hides it.
+    Annotation(String),
+    /// The cartridge RAM was written to the save file.
+    RamSaved,
+    /// Import the debugger session in the given file, chosen in a file dialog.
+    ImportSession(std::path::PathBuf),
+    /// The emulator thread panicked. Contains the panic message, and the program counter and clock
+    /// count of the GameBoy at the moment of the crash.
+    EmulatorCrashed {
+        message: String,
+        pc: u16,
+        clock_count: u64,
+    },
 }
 
 impl std::fmt::Debug for UserEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::FrameUpdated => write!(f, "FrameUpdated"),
+            Self::FrameTick => write!(f, "FrameTick"),
             Self::EmulatorPaused => write!(f, "EmulatorPaused"),
             Self::EmulatorStarted => write!(f, "EmulatorStarted"),
             Self::BreakpointsUpdated => write!(f, "BreakpointsUpdated"),
             Self::WatchsUpdated => write!(f, "WatchsUpdated"),
+            Self::LabelsUpdated => write!(f, "LabelsUpdated"),
+            Self::StateTreeUpdated(_) => write!(f, "StateTreeUpdated"),
             Self::Debug(arg0) => f.debug_tuple("Debug").field(arg0).finish(),
             Self::UpdateTexture(arg0, arg1) => f
                 .debug_tuple("UpdateTexture")
@@ -658,14 +1284,31 @@ impl std::fmt::Debug for UserEvent {
                 .finish(),
             Self::NewTexture(arg0, _) => f.debug_tuple("NewTexture").field(arg0).finish(),
             Self::PopApp => write!(f, "PopApp"),
+            Self::Quit => write!(f, "Quit"),
             Self::LoadRom { file, game_boy } => f
                 .debug_struct("LoadRom")
                 .field("file", file)
                 .field("game_boy", game_boy)
                 .finish(),
+            Self::LoadFailed(arg0) => f.debug_tuple("LoadFailed").field(arg0).finish(),
             Self::SpawnTask(arg0) => f.debug_tuple("SpawnTask").field(arg0).finish(),
             Self::UpdateRomList => write!(f, "UpdateRomList"),
             Self::UpdatedRomList => write!(f, "UpdatedRomList"),
+            Self::ReloadStyle => write!(f, "ReloadStyle"),
+            Self::Notify(arg0) => f.debug_tuple("Notify").field(arg0).finish(),
+            Self::Annotation(arg0) => f.debug_tuple("Annotation").field(arg0).finish(),
+            Self::RamSaved => write!(f, "RamSaved"),
+            Self::ImportSession(arg0) => f.debug_tuple("ImportSession").field(arg0).finish(),
+            Self::EmulatorCrashed {
+                message,
+                pc,
+                clock_count,
+            } => f
+                .debug_struct("EmulatorCrashed")
+                .field("message", message)
+                .field("pc", pc)
+                .field("clock_count", clock_count)
+                .finish(),
         }
     }
 }
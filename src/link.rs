@@ -0,0 +1,198 @@
+//! Local multiplayer through a emulated DMG-07, the 4-player adapter, over TCP.
+//!
+//! One process runs the hub (`gameroy --dmg07-hub <address>`), which emulates the adapter, and each
+//! player runs a instance with `link` set in the config, or `--link <address>`, which connects to
+//! the hub. The players are numbered in the order they connect. See `gameroy::dmg07`.
+//!
+//! For each transfer, the hub sends one byte to each player, and each player answers with two
+//! bytes: 1 and the byte sent by the game, or 0 and 0 if the game did not start a transfer in time.
+//! The hub waits for the answers of all players before the next transfer, so the games run in
+//! lockstep while they are exchanging data.
+//!
+//! The bytes are exchanged by the emulator thread, between two runs of the emulator, through
+//! `EmulatorEvent::LinkTransfer`. Still, when they arrive depends on the network, so the link is
+//! refused while playing a movie or in deterministic mode.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use gameroy::dmg07::{Dmg07, Phase, PLAYERS};
+use parking_lot::Mutex;
+
+use crate::emulator::EmulatorEvent;
+
+/// How long a player waits for the emulator to exchange a byte, before answering that the game
+/// is not ready. The emulator answers after a frame of emulation, so this is only reached while it
+/// is paused.
+const EXCHANGE_TIMEOUT: Duration = Duration::from_millis(200);
+/// How long the hub waits for the answer of a player, before disconnecting it.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A connection to a DMG-07 hub, that exchanges the bytes of the serial port of the GameBoy.
+pub struct LinkClient {
+    closed: Arc<AtomicBool>,
+}
+impl LinkClient {
+    /// Connect to the hub at `address`, and exchange the bytes with the emulator in a new thread.
+    pub fn start(address: &str, emu_channel: flume::Sender<EmulatorEvent>) -> Result<Self, String> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|e| format!("failed to connect to the hub at '{}': {}", address, e))?;
+        let _ = stream.set_nodelay(true);
+        log::info!("connected to the DMG-07 hub at '{}'", address);
+
+        let closed = Arc::new(AtomicBool::new(false));
+        std::thread::Builder::new()
+            .name("link".to_string())
+            .spawn({
+                let closed = closed.clone();
+                move || {
+                    if let Err(e) = run_client(&mut stream, &emu_channel, &closed) {
+                        log::error!("link to the hub closed: {}", e);
+                    }
+                    log::info!("exiting link thread");
+                }
+            })
+            .map_err(|e| format!("failed to spawn link thread: {}", e))?;
+
+        Ok(Self { closed })
+    }
+}
+impl Drop for LinkClient {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run_client(
+    stream: &mut TcpStream,
+    emu_channel: &flume::Sender<EmulatorEvent>,
+    closed: &AtomicBool,
+) -> std::io::Result<()> {
+    // wake up from time to time to check if the client was closed.
+    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+    while !closed.load(Ordering::Relaxed) {
+        let mut byte = [0];
+        match stream.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        }
+
+        let (answer, recv) = flume::bounded(1);
+        if emu_channel
+            .send(EmulatorEvent::LinkTransfer(byte[0], answer))
+            .is_err()
+        {
+            // the emulator was closed.
+            break;
+        }
+        let answer = match recv.recv_timeout(EXCHANGE_TIMEOUT) {
+            Ok(Some(sent)) => [1, sent],
+            Ok(None) | Err(_) => [0, 0],
+        };
+        stream.write_all(&answer)?;
+    }
+    Ok(())
+}
+
+/// Run the hub, listening for players at `address`, until the process is killed.
+pub fn run_hub(address: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("failed to listen on '{}': {}", address, e))?;
+    log::info!("DMG-07 hub listening on '{}'", address);
+    println!("DMG-07 hub listening on '{}'", address);
+
+    let ports: Arc<Mutex<[Option<TcpStream>; PLAYERS]>> = Arc::default();
+    std::thread::Builder::new()
+        .name("hub listener".to_string())
+        .spawn({
+            let ports = ports.clone();
+            move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(x) => x,
+                        Err(e) => {
+                            log::error!("player connection failed: {}", e);
+                            continue;
+                        }
+                    };
+                    let mut ports = ports.lock();
+                    let Some(i) = ports.iter().position(|x| x.is_none()) else {
+                        log::warn!("hub is full, refusing {:?}", stream.peer_addr());
+                        continue;
+                    };
+                    let _ = stream.set_nodelay(true);
+                    let _ = stream.set_read_timeout(Some(ANSWER_TIMEOUT));
+                    println!("player {} connected from {:?}", i + 1, stream.peer_addr());
+                    ports[i] = Some(stream);
+                }
+            }
+        })
+        .map_err(|e| format!("failed to spawn hub listener thread: {}", e))?;
+
+    let mut dmg07 = Dmg07::new();
+    let mut last_phase = dmg07.phase();
+    loop {
+        let mut ports = ports.lock();
+        if ports.iter().all(|x| x.is_none()) {
+            drop(ports);
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        // send the bytes to all players first, so they wait for their games at the same time.
+        let output = dmg07.output();
+        for (i, port) in ports.iter_mut().enumerate() {
+            if let Some(stream) = port {
+                if let Err(e) = stream.write_all(&[output[i]]) {
+                    println!("player {} disconnected: {}", i + 1, e);
+                    *port = None;
+                }
+            }
+        }
+        let mut received = [None; PLAYERS];
+        for (i, port) in ports.iter_mut().enumerate() {
+            if let Some(stream) = port {
+                let mut answer = [0; 2];
+                match stream.read_exact(&mut answer) {
+                    Ok(()) => received[i] = (answer[0] != 0).then_some(answer[1]),
+                    Err(e) => {
+                        println!("player {} disconnected: {}", i + 1, e);
+                        *port = None;
+                    }
+                }
+            }
+        }
+        drop(ports);
+        dmg07.input(received);
+
+        if dmg07.phase() != last_phase {
+            last_phase = dmg07.phase();
+            let (rate, size) = dmg07.rate_and_size();
+            log::info!(
+                "DMG-07 {:?} phase, players {:04b}, rate {:02x}, size {:02x}",
+                last_phase,
+                dmg07.connected(),
+                rate,
+                size
+            );
+            if last_phase == Phase::Transmission {
+                println!("transmission started, players {:04b}", dmg07.connected());
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Translation of the UI strings.
+//!
+//! Each string is identified by a key, and each language is a file in `assets/lang/`, containing a
+//! map from keys to strings. The English file is always embedded in the binary, and is used as a
+//! fallback for keys missing in the selected language.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+
+type Strings = HashMap<String, String>;
+
+const ENGLISH: &str = include_str!("../assets/lang/en.ron");
+
+#[cfg(feature = "static")]
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", ENGLISH),
+    ("pt-BR", include_str!("../assets/lang/pt-BR.ron")),
+];
+
+static STRINGS: OnceCell<Strings> = OnceCell::new();
+static FALLBACK: OnceCell<Strings> = OnceCell::new();
+
+fn parse(language: &str, file: &str) -> Option<Strings> {
+    ron::from_str(file)
+        .map_err(|err| log::error!("failed to parse language '{}': {}", language, err))
+        .ok()
+}
+
+#[cfg(not(feature = "static"))]
+fn read_language(language: &str) -> Option<String> {
+    let mut path = crate::config::base_folder()?;
+    path.push("assets/lang");
+    path.push(format!("{}.ron", language));
+    std::fs::read_to_string(&path)
+        .map_err(|err| log::error!("failed reading '{}': {}", path.display(), err))
+        .ok()
+}
+
+#[cfg(feature = "static")]
+fn read_language(language: &str) -> Option<String> {
+    match LANGUAGES.iter().find(|(name, _)| *name == language) {
+        Some((_, file)) => Some(file.to_string()),
+        None => {
+            log::error!("unknown language '{}'", language);
+            None
+        }
+    }
+}
+
+/// Load the strings of the given language. Should be called before building any UI, and only the
+/// first call has effect.
+pub fn init(language: &str) {
+    log::info!("using language '{}'", language);
+    let strings = read_language(language)
+        .and_then(|file| parse(language, &file))
+        .unwrap_or_default();
+    if STRINGS.set(strings).is_err() {
+        log::warn!("language was already loaded");
+    }
+}
+
+/// Get the string identified by `key`, in the current language.
+pub fn tr(key: &'static str) -> &'static str {
+    if let Some(string) = STRINGS.get().and_then(|x| x.get(key)) {
+        return string;
+    }
+    let fallback = FALLBACK.get_or_init(|| parse("en", ENGLISH).unwrap_or_default());
+    match fallback.get(key) {
+        Some(string) => string,
+        None => {
+            log::warn!("missing string for '{}'", key);
+            key
+        }
+    }
+}
+
+/// Same as `tr`, but replaces `{}` in the string by `arg`.
+pub fn tr_arg(key: &'static str, arg: impl std::fmt::Display) -> String {
+    tr(key).replace("{}", &arg.to_string())
+}
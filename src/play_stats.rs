@@ -0,0 +1,154 @@
+//! The play time, the number of launches and the last played date of each rom.
+//!
+//! The statistics are kept in '<data folder>/play_stats.toml' (see `dirs::data_folder`), indexed
+//! by the CRC32 of the rom, so they follow the game when its file is renamed or moved. The launches
+//! are counted when a rom is loaded, and the play time is added by the emulator thread each time
+//! the emulation pauses or stops.
+//!
+//! On the web there is no data folder, and nothing is recorded.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use std::collections::BTreeMap;
+
+use instant::{Duration, SystemTime};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// The file, relative to the data folder, that contains the statistics.
+const PLAY_STATS_FILE: &str = "play_stats.toml";
+
+/// Held while the file is read and written back, so concurrent updates are not lost.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayStats {
+    /// The file name of the rom, the last time it was launched.
+    pub name: String,
+    /// The total time the game was running, in seconds. The time paused is not counted.
+    pub play_time: u64,
+    /// How many times the rom was loaded.
+    pub launches: u32,
+    /// When the rom was last launched, in milliseconds since the epoch.
+    pub last_played: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayStatsStore {
+    /// The statistics of each rom, indexed by its CRC32 in hexadecimal, like "B3A86164".
+    pub roms: BTreeMap<String, PlayStats>,
+}
+impl PlayStatsStore {
+    /// Load the statistics from the data folder. If there is no file yet, or it fails to load, a
+    /// empty store is returned.
+    pub fn load() -> Self {
+        let path = crate::dirs::data_folder().join(PLAY_STATS_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    log::error!("failed reading '{}': {}", path.display(), err);
+                }
+                return Self::default();
+            }
+        };
+        toml::from_str(&text)
+            .map_err(|err| log::error!("failed parsing '{}': {}", path.display(), err))
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = crate::dirs::data_path(PLAY_STATS_FILE);
+        let text = toml::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(&path, text)
+            .map_err(|err| format!("failed writing '{}': {}", path.display(), err))
+    }
+
+    /// The statistics of the rom with the given CRC32, if it was ever launched.
+    pub fn get(&self, hash: u32) -> Option<&PlayStats> {
+        self.roms.get(&key(hash))
+    }
+
+    /// The statistics of all roms, from the most played to the least played.
+    pub fn by_play_time(&self) -> Vec<&PlayStats> {
+        let mut roms: Vec<&PlayStats> = self.roms.values().collect();
+        roms.sort_by(|a, b| b.play_time.cmp(&a.play_time).then(a.name.cmp(&b.name)));
+        roms
+    }
+}
+
+fn key(hash: u32) -> String {
+    format!("{:08X}", hash)
+}
+
+/// Load the store, apply `f` to the statistics of the rom with the given CRC32, and save it back.
+fn update(hash: u32, f: impl FnOnce(&mut PlayStats)) {
+    let _lock = LOCK.lock();
+    let mut store = PlayStatsStore::load();
+    f(store.roms.entry(key(hash)).or_default());
+    if let Err(err) = store.save() {
+        log::error!("error saving play stats: {}", err);
+    }
+}
+
+/// Count a new launch of the rom with the given CRC32 and file name.
+pub fn record_launch(hash: u32, name: &str) {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_millis() as u64);
+    update(hash, |stats| {
+        stats.name = name.to_string();
+        stats.launches += 1;
+        stats.last_played = Some(now);
+    });
+}
+
+/// Add `time` to the play time of the rom with the given CRC32. Fractions of a second are
+/// discarded.
+pub fn add_play_time(hash: u32, time: Duration) {
+    if time.as_secs() == 0 {
+        return;
+    }
+    log::debug!("adding {}s of play time to {}", time.as_secs(), key(hash));
+    update(hash, |stats| stats.play_time += time.as_secs());
+}
+
+/// A play time in seconds as a short text, like "12h 05m", or "-" if it is zero.
+pub fn format_play_time(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    match minutes {
+        _ if seconds == 0 => "-".to_string(),
+        0 => "<1m".to_string(),
+        1..=59 => format!("{}m", minutes),
+        _ => format!("{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut store = PlayStatsStore::default();
+        store.roms.insert(
+            key(0xB3A86164),
+            PlayStats {
+                name: "game.gb".to_string(),
+                play_time: 3725,
+                launches: 3,
+                last_played: Some(1_700_000_000_000),
+            },
+        );
+        let text = toml::to_string(&store).unwrap();
+        assert_eq!(toml::from_str::<PlayStatsStore>(&text).unwrap(), store);
+        assert_eq!(store.get(0xB3A86164).unwrap().launches, 3);
+        assert!(store.get(0x12345678).is_none());
+
+        assert_eq!(format_play_time(0), "-");
+        assert_eq!(format_play_time(59), "<1m");
+        assert_eq!(format_play_time(600), "10m");
+        assert_eq!(format_play_time(3725), "1h 02m");
+    }
+}
@@ -0,0 +1,23 @@
+//! Creation of the renderer used to draw the interface and the Game Boy screen.
+//!
+//! The rest of the frontend only sees the `SpriteRender` trait, so the renderer is only created
+//! here.
+
+use sprite_render::SpriteRender;
+use winit::window::Window;
+
+/// Create the renderer of the platform, falling back to a renderer that draws nothing if it fails.
+pub fn create_render(window: &Window) -> Box<dyn SpriteRender> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            Box::new(sprite_render::WebGLSpriteRender::new(window))
+        } else {
+            sprite_render::GlSpriteRender::new(window, true)
+                .map(|x| Box::new(x) as Box<dyn SpriteRender>)
+                .unwrap_or_else(|err| {
+                    log::error!("failed to create GlSpriteRender: {:?}", err);
+                    Box::new(sprite_render::NoopSpriteRender)
+                })
+        }
+    }
+}
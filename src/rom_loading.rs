@@ -1,10 +1,21 @@
-use std::io::Read;
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
-use gameroy::gameboy::{cartridge::Cartridge, GameBoy};
-use image::codecs::png::PngEncoder;
-use image::ImageEncoder;
+use gameroy::{
+    consts::{FRAME_CYCLES, SCREEN_HEIGHT, SCREEN_WIDTH},
+    gameboy::{accuracy::AccuracyConfig, cartridge::Cartridge, ram_fill::RamFill, GameBoy},
+    interpreter::Interpreter,
+    parser::{Vbm, VbmStart},
+};
+use serde::{Deserialize, Serialize};
 
-use crate::config::{config, normalize_config_path};
+use crate::{
+    config::{config, InitialRam},
+    game_database::crc32,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "android")] {
@@ -19,18 +30,81 @@ cfg_if::cfg_if! {
     }
 }
 
-pub fn load_gameboy(rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Box<GameBoy>, String> {
-    load_gameboy_with_spec(rom, ram, None)
+/// Load the rom into a new GameBoy. `file_name` is the name of the rom file, used to select the
+/// accuracy profile of the game.
+pub fn load_gameboy(
+    rom: Vec<u8>,
+    ram: Option<Vec<u8>>,
+    file_name: &str,
+) -> Result<Box<GameBoy>, String> {
+    load_gameboy_with_spec(rom, ram, None, file_name)
 }
 
 pub fn load_gameboy_with_spec(
     rom: Vec<u8>,
     ram: Option<Vec<u8>>,
     spec: Option<&str>,
+    file_name: &str,
 ) -> Result<Box<GameBoy>, String> {
     let boot_rom = load_boot_rom();
 
-    let mut cartridge = match Cartridge::new_with_spec_str(rom, spec) {
+    let mut cartridge = prepare_cartridge(rom, spec)?;
+
+    if let Some(ram) = ram {
+        if config().deterministic {
+            log::info!("deterministic mode: ignoring saved cartridge ram");
+        } else {
+            cartridge
+                .load_ram(ram)
+                .map_err(|err| format!("invalid save file: {}", err))?;
+        }
+    }
+
+    for warning in cartridge.validation().warnings() {
+        log::warn!("rom validation: {}", warning);
+    }
+
+    let accuracy = config().accuracy_for(file_name);
+    log::info!("accuracy profile: {:?}", accuracy);
+    let game_boy = GameBoy::builder(cartridge)
+        .boot_rom(boot_rom)
+        .accuracy(accuracy.config())
+        .predict_interrupt(config().interrupt_prediction)
+        .ram_fill(ram_fill(None))
+        .unusable_area(config().model.unusable_area());
+    // the serial output is only printed to stderr when asked for.
+    let game_boy = if config().serial_stderr {
+        game_boy
+    } else {
+        game_boy.serial_sink(None)
+    }
+    .build();
+    trace_entry_points(&game_boy);
+    // GameBoy is too big to live on the stack.
+    Ok(Box::new(game_boy))
+}
+
+/// Create the cartridge of a rom file, with the MBC `spec`, if given, or the one in its header. The
+/// game is looked up in the game database.
+pub fn prepare_cartridge(rom: Vec<u8>, spec: Option<&str>) -> Result<Cartridge, String> {
+    let crc32 = crate::game_database::crc32(&rom);
+    match crate::game_database::lookup_hash(crc32) {
+        Some(game) => {
+            log::info!("rom {:08X}: {}", crc32, game.full_title());
+            for note in &game.notes {
+                log::info!("note: {}", note);
+            }
+        }
+        None => log::info!("rom {:08X}: not in the game database", crc32),
+    }
+
+    load_cartridge(rom, spec)
+}
+
+/// Create a cartridge from the given rom. Problems that still allow the rom to be loaded are only
+/// logged.
+pub fn load_cartridge(rom: Vec<u8>, spec: Option<&str>) -> Result<Cartridge, String> {
+    let cartridge = match Cartridge::new_with_spec_str(rom, spec) {
         Ok(rom) => Ok(rom),
         Err((warn, Some(rom))) => {
             println!("Warning: {}", warn.strip_suffix('\n').unwrap_or(&warn));
@@ -40,26 +114,267 @@ pub fn load_gameboy_with_spec(
         Err((err, None)) => Err(err),
     }?;
     log::info!("Cartridge type: {}", cartridge.kind_name());
+    Ok(cartridge)
+}
 
-    if let Some(ram) = ram {
-        cartridge.ram = ram;
+/// Disassemble the code reachable from the entry point and from the interrupt handlers.
+pub fn trace_entry_points(game_boy: &GameBoy) {
+    game_boy.trace.borrow_mut().trace_entry_points(game_boy);
+}
+
+/// The initial RAM content configured by the user. The seed of the random fill is taken from the
+/// config, or from `movie_seed`, or from the current time, in this order.
+pub fn ram_fill(movie_seed: Option<u64>) -> RamFill {
+    let config = config();
+    match config.initial_ram {
+        InitialRam::Zeros => RamFill::Zeros,
+        InitialRam::Ones => RamFill::Ones,
+        InitialRam::Dmg => RamFill::DmgPattern,
+        InitialRam::Random => {
+            let seed = config.initial_ram_seed.or(movie_seed).unwrap_or_else(|| {
+                if config.deterministic {
+                    return 0;
+                }
+                instant::SystemTime::now()
+                    .duration_since(instant::SystemTime::UNIX_EPOCH)
+                    .map_or(0, |x| x.as_nanos() as u64)
+            });
+            log::info!("initial ram seed: {}", seed);
+            RamFill::Random(seed)
+        }
     }
+}
 
-    let mut game_boy = GameBoy::new(boot_rom, cartridge);
-    game_boy.predict_interrupt = config().interrupt_prediction;
-    {
-        let mut trace = game_boy.trace.borrow_mut();
+/// The RAM content after a power cycle: random bytes, from a new seed. In deterministic mode the
+/// seed is derived from the previous one, so the sequence of power cycles is still reproducible.
+pub fn power_cycle_ram_fill(previous: RamFill) -> RamFill {
+    let seed = if config().deterministic {
+        match previous {
+            RamFill::Random(seed) => seed.wrapping_add(1),
+            _ => 0,
+        }
+    } else {
+        instant::SystemTime::now()
+            .duration_since(instant::SystemTime::UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos() as u64)
+    };
+    log::info!("power cycle ram seed: {}", seed);
+    RamFill::Random(seed)
+}
 
-        let banks = game_boy.cartridge.curr_bank();
-        trace.trace_starting_at(&game_boy, banks, 0x100, Some("entry point".into()));
-        trace.trace_starting_at(&game_boy, banks, 0x40, Some("RST_0x40".into()));
-        trace.trace_starting_at(&game_boy, banks, 0x48, Some("RST_0x48".into()));
-        trace.trace_starting_at(&game_boy, banks, 0x50, Some("RST_0x50".into()));
-        trace.trace_starting_at(&game_boy, banks, 0x58, Some("RST_0x58".into()));
-        trace.trace_starting_at(&game_boy, banks, 0x60, Some("RST_0x60".into()));
+/// The number of frames emulated before the boot rom finishes. Movies only contain the inputs after
+/// it.
+pub const MOVIE_BOOT_FRAMES: u64 = 23_384_580 / FRAME_CYCLES;
+
+/// Changes to the initial conditions of a movie, for repairing movies that desync. Loaded from a
+/// '<movie>.sync.toml' file next to the movie, which `gameroy --sync-movie` can generate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MovieSync {
+    /// Added to the DIV counter after reset, shifting the phase of the timer, in clock cycles.
+    pub div_offset: u16,
+    /// The seed of a random initial RAM. If not set, the RAM is filled as configured, using the
+    /// movie uid as seed.
+    pub ram_seed: Option<u64>,
+}
+impl MovieSync {
+    /// The path of the sync file of the movie at `movie_path`.
+    pub fn path(movie_path: &Path) -> PathBuf {
+        movie_path.with_extension("sync.toml")
     }
-    // GameBoy is too big to live on the stack.
-    Ok(Box::new(game_boy))
+
+    /// Load the sync file of the movie at `movie_path`, if there is one.
+    pub fn load(movie_path: &Path) -> Result<Option<Self>, String> {
+        let path = Self::path(movie_path);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("error reading '{}': {}", path.display(), err)),
+        };
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|err| format!("error parsing '{}': {}", path.display(), err))
+    }
+
+    pub fn save(&self, movie_path: &Path) -> Result<(), String> {
+        let path = Self::path(movie_path);
+        let text = toml::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(&path, text)
+            .map_err(|err| format!("error writing '{}': {}", path.display(), err))
+    }
+}
+
+/// Texts shown over the screen while a movie is played, each from a frame of the movie on, like
+/// subtitles, for publishing the commentary of a TAS. Loaded from a '<movie>.annotations.txt' file
+/// next to the movie, or the one given by `--annotations`.
+///
+/// Each line of the file is the frame, counted from the start of the movie, and the text. A frame
+/// without text hides the previous annotation. Empty lines and lines starting with '#' are
+/// ignored:
+///
+/// ```text
+/// # commentary of the any% TAS
+/// 120 skipping the intro
+/// 600
+/// 1520 this jump clips through the wall
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MovieAnnotations {
+    /// The text of each annotated frame.
+    entries: BTreeMap<u32, String>,
+}
+impl MovieAnnotations {
+    /// The default path of the annotations of the movie at `movie_path`.
+    pub fn path(movie_path: &Path) -> PathBuf {
+        movie_path.with_extension("annotations.txt")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (frame, text) = line.split_once(' ').unwrap_or((line, ""));
+            let frame = frame
+                .parse()
+                .map_err(|_| format!("line {}: invalid frame '{}'", i + 1, frame))?;
+            entries.insert(frame, text.trim().to_string());
+        }
+        Ok(Self { entries })
+    }
+
+    /// Load the annotations in the file at `path`. If there is no file, there are no annotations.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("error reading '{}': {}", path.display(), err)),
+        };
+        Self::parse(&text).map_err(|err| format!("error parsing '{}': {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_string())
+            .map_err(|err| format!("error writing '{}': {}", path.display(), err))
+    }
+
+    /// The text shown at the given frame of the movie: the one of the last annotation at or before
+    /// it. Empty if there is none.
+    pub fn text_at(&self, frame: u32) -> &str {
+        self.entries
+            .range(..=frame)
+            .next_back()
+            .map_or("", |(_, text)| text.as_str())
+    }
+
+    /// Show `text` from the given frame on, replacing the annotation of the frame, if any. A empty
+    /// text hides the previous annotation.
+    pub fn set(&mut self, frame: u32, text: String) {
+        self.entries.insert(frame, text);
+    }
+
+    /// Remove the annotation of the given frame. Returns false if there was none.
+    pub fn remove(&mut self, frame: u32) -> bool {
+        self.entries.remove(&frame).is_some()
+    }
+}
+impl std::fmt::Display for MovieAnnotations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (frame, text) in &self.entries {
+            if text.is_empty() {
+                writeln!(f, "{}", frame)?;
+            } else {
+                writeln!(f, "{} {}", frame, text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reset the GameBoy to the initial conditions of the movie, or load the save state it starts
+/// from. A save state referenced by its CRC32 is searched in the folder of the movie, between the
+/// files with `.save_state` in their name. The sync of the movie only applies to movies that start
+/// from a reset.
+pub fn start_movie(
+    gb: &mut GameBoy,
+    movie: &Vbm,
+    movie_path: &Path,
+    sync: MovieSync,
+) -> Result<(), String> {
+    let state = match movie.start() {
+        VbmStart::PowerOn => None,
+        VbmStart::Sram(ram) => {
+            gb.cartridge.load_ram(ram.to_vec())?;
+            None
+        }
+        VbmStart::SaveState(state) => Some(state.to_vec()),
+        VbmStart::SaveStateReference(hash) => Some(find_save_state(movie_path, hash)?),
+    };
+    if let Some(state) = state {
+        return gb
+            .load_state(&mut &state[..])
+            .map_err(|err| format!("failed to load the start state of the movie: {:?}", err));
+    }
+
+    // the movie uid is used as the seed of the initial ram, so the movie can be reproduced.
+    gb.ram_fill = match sync.ram_seed {
+        Some(seed) => RamFill::Random(seed),
+        None => ram_fill(Some(movie.uid as u64)),
+    };
+    gb.reset();
+    let timer = gb.timer.get_mut();
+    timer.div = timer.div.wrapping_add(sync.div_offset);
+    gb.update_next_interrupt();
+    Ok(())
+}
+
+/// Find the save state file, in the folder of the movie at `movie_path`, with the given CRC32.
+fn find_save_state(movie_path: &Path, hash: u32) -> Result<Vec<u8>, String> {
+    let folder = movie_path.parent().unwrap_or(Path::new(""));
+    let entries = std::fs::read_dir(if folder.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        folder
+    })
+    .map_err(|err| format!("error reading '{}': {}", folder.display(), err))?;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().contains(".save_state") {
+            continue;
+        }
+        let Ok(data) = std::fs::read(entry.path()) else {
+            continue;
+        };
+        if crc32(&data) == hash {
+            log::info!("movie starts from '{}'", entry.path().display());
+            return Ok(data);
+        }
+    }
+    Err(format!(
+        "the movie starts from a save state with CRC32 {:08x}, but it was not found in '{}'",
+        hash,
+        folder.display()
+    ))
+}
+
+/// The joypad of each frame of the movie, in the format of `GameBoy::joypad`, indexed by the frame
+/// count since the power on. The movie is started at the end of the boot rom, or at the frame of
+/// the save state it starts from, which must already be loaded in `gb`. The frames before it have
+/// no button pressed.
+pub fn movie_timeline(movie: &Vbm, gb: &GameBoy) -> Vec<u8> {
+    let start_frame = match movie.start() {
+        VbmStart::SaveState(_) | VbmStart::SaveStateReference(_) => gb.clock_count / FRAME_CYCLES,
+        VbmStart::PowerOn | VbmStart::Sram(_) => MOVIE_BOOT_FRAMES,
+    };
+    (0..start_frame)
+        .map(|_| 0)
+        .chain(movie.controller_data.iter().copied())
+        .map(|x| {
+            let joy = !(x as u8);
+            ((joy & 0x0F) << 4) | (joy >> 4)
+        })
+        .collect()
 }
 
 /// Returns a PNG encoded image.
@@ -98,15 +413,9 @@ pub fn get_thumb(file_name: &str) -> Result<Vec<u8>, String> {
     let image = image::load_from_memory(&bytes).unwrap();
     let image = image::imageops::resize(&image, 96, 96, image::imageops::FilterType::Lanczos3);
 
-    let mut buffer = Vec::new();
-    PngEncoder::new(&mut buffer)
-        .write_image(
-            image.as_raw().as_slice(),
-            image.width(),
-            image.height(),
-            image::ExtendedColorType::Rgba8,
-        )
-        .map_err(|err| format!("failed to png encode thumbnail: {}", err))?;
+    let (width, height) = (image.width(), image.height());
+    let buffer = crate::image_pool::encode_png_blocking(image.into_raw(), width, height)
+        .map_err(|err| format!("failed to encode thumbnail: {}", err))?;
 
     if let Err(err) = crate::rom_loading::save_thumb(&buffer, file_name) {
         log::error!("failed to save thumbnail for '{file_name}': {err}");
@@ -115,9 +424,106 @@ pub fn get_thumb(file_name: &str) -> Result<Vec<u8>, String> {
     Ok(buffer)
 }
 
+/// The maximum number of frames emulated by `capture_title_screen`.
+const CAPTURE_MAX_FRAMES: u32 = 300;
+/// The number of identical frames after which the screen is considered to be waiting for input.
+const CAPTURE_STABLE_FRAMES: u32 = 30;
+
+/// Run the given rom headlessly, without boot rom, until it shows a representative image (usually
+/// the title screen), and return that image as a PNG, at the native resolution.
+///
+/// The rom runs until its screen stops changing for half a second, or up to 5 seconds of emulation.
+/// Blank screens are never captured, and identical frames are skipped without conversion.
+pub fn capture_title_screen(rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    let cartridge = match Cartridge::new(rom) {
+        Ok(x) | Err((_, Some(x))) => x,
+        Err((err, None)) => return Err(err),
+    };
+    let mut gb = GameBoy::builder(cartridge)
+        .accuracy(AccuracyConfig::FAST)
+        .serial_sink(None)
+        .build();
+    gb.reset();
+
+    let mut last_screen = gb.ppu.borrow().screen.packed();
+    let mut captured = None;
+    let mut stable_frames = 0;
+    for _ in 0..CAPTURE_MAX_FRAMES {
+        let target_clock = gb.clock_count + FRAME_CYCLES;
+        while gb.clock_count < target_clock {
+            Interpreter(&mut gb).interpret_op();
+        }
+
+        let screen = gb.ppu.borrow().screen.packed();
+        if screen == last_screen {
+            stable_frames += 1;
+        } else {
+            stable_frames = 0;
+            last_screen = screen;
+        }
+
+        let blank = screen.iter().all(|&x| x == screen[0]);
+        if !blank {
+            captured = Some(screen);
+            if stable_frames >= CAPTURE_STABLE_FRAMES {
+                break;
+            }
+        }
+    }
+
+    let screen = captured.ok_or_else(|| "the screen was always blank".to_string())?;
+    let rgba: Vec<u8> = screen
+        .iter()
+        .flat_map(|&x| {
+            let [r, g, b] = crate::DMG_PALETTE[x as usize % 4];
+            [r, g, b, 255]
+        })
+        .collect();
+
+    crate::image_pool::encode_png_blocking(rgba, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|err| format!("failed to encode title screen: {}", err))
+}
+
+/// Capture the title screen of the given rom with `capture_title_screen`, and save it as the
+/// thumbnail of `file_name`. Returns the PNG encoded image.
+///
+/// A failed capture is recorded in the thumbnails folder, as '<file_name>.failed', so the rom is
+/// not emulated again each time the rom list is loaded. Delete that file to retry.
+pub fn generate_thumb(file_name: &str, rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    let failed_path = crate::dirs::data_path("thumbnails")
+        .join(file_name)
+        .with_extension("failed");
+    if failed_path.exists() {
+        return Err("title screen capture failed before".to_string());
+    }
+
+    let start = instant::Instant::now();
+    let thumb = match capture_title_screen(rom) {
+        Ok(x) => x,
+        Err(err) => {
+            if let Err(err) = std::fs::create_dir_all(failed_path.parent().unwrap())
+                .and_then(|_| std::fs::write(&failed_path, &err))
+            {
+                log::error!("failed to record thumbnail failure for '{file_name}': {err}");
+            }
+            return Err(err);
+        }
+    };
+    log::info!(
+        "captured title screen of '{}' in {:?}",
+        file_name,
+        start.elapsed()
+    );
+
+    if let Err(err) = save_thumb(&thumb, file_name) {
+        log::error!("failed to save thumbnail for '{file_name}': {err}");
+    }
+    Ok(thumb)
+}
+
 /// Returns a PNG encoded image.
 pub fn load_thumb(file_name: &str) -> Result<Vec<u8>, String> {
-    let thumbs_folder = normalize_config_path("thumbnails");
+    let thumbs_folder = crate::dirs::data_folder().join("thumbnails");
     let save_path = thumbs_folder.join(file_name).with_extension("png");
 
     let mut file = match std::fs::File::open(save_path) {
@@ -136,7 +542,7 @@ pub fn load_thumb(file_name: &str) -> Result<Vec<u8>, String> {
 
 /// Receives a PNG encoded image
 pub fn save_thumb(thumb: &Vec<u8>, file_name: &str) -> Result<(), String> {
-    let thumbs_folder = normalize_config_path("thumbnails");
+    let thumbs_folder = crate::dirs::data_path("thumbnails");
     let save_path = thumbs_folder.join(file_name).with_extension("png");
 
     log::debug!("save thumbnail path: {}", save_path.display());
@@ -150,3 +556,36 @@ pub fn save_thumb(thumb: &Vec<u8>, file_name: &str) -> Result<(), String> {
     }
     std::fs::write(save_path, thumb).map_err(|x| x.to_string())
 }
+
+#[cfg(test)]
+mod test {
+    use super::MovieAnnotations;
+
+    #[test]
+    fn annotations() {
+        let text = "# commentary\n\n120 skipping the intro\n600\n1520  clip  \n";
+        let mut annotations = MovieAnnotations::parse(text).unwrap();
+        assert_eq!(annotations.text_at(0), "");
+        assert_eq!(annotations.text_at(120), "skipping the intro");
+        assert_eq!(annotations.text_at(599), "skipping the intro");
+        assert_eq!(annotations.text_at(600), "");
+        assert_eq!(annotations.text_at(9999), "clip");
+
+        annotations.set(300, "first level".to_string());
+        assert!(annotations.remove(600));
+        assert!(!annotations.remove(601));
+        assert_eq!(
+            annotations.to_string(),
+            "120 skipping the intro\n300 first level\n1520 clip\n"
+        );
+        assert_eq!(
+            MovieAnnotations::parse(&annotations.to_string()).unwrap(),
+            annotations
+        );
+
+        assert_eq!(
+            MovieAnnotations::parse("12 a\nxyz b\n"),
+            Err("line 2: invalid frame 'xyz'".to_string())
+        );
+    }
+}
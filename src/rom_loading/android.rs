@@ -75,7 +75,7 @@ pub fn load_roms(roms_path: &str) -> Result<Vec<RomFile>, String> {
         .collect::<Vec<RomFile>>())
 }
 
-pub fn load_boot_rom() -> Option<[u8; 256]> {
+pub fn load_boot_rom() -> Option<gameroy::gameboy::boot_rom::BootRom> {
     None
 }
 
@@ -200,6 +200,17 @@ impl RomFile {
         }
     }
 
+    /// Read the entire rom, blocking the current thread.
+    pub fn read_blocking(&self) -> Result<Vec<u8>, String> {
+        read_uri(self.uri.as_str(), 0)
+    }
+
+    /// The CRC32 of the entire rom, used for looking up the game database.
+    pub fn get_hash(&self) -> Result<u32, String> {
+        let rom = self.read_blocking()?;
+        Ok(crate::game_database::crc32(&rom))
+    }
+
     pub fn file_name(&self) -> Cow<str> {
         urlencoding::decode(&self.uri)
             .unwrap()
@@ -232,6 +243,10 @@ impl RomFile {
         file_date(&file_name).ok_or_else(|| "file date failed".to_string())
     }
 
+    pub fn rollback_ram_data(&self) -> Result<Vec<u8>, String> {
+        Err("save backups are not supported on Android".to_string())
+    }
+
     pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
         let file_name = self.file_name().to_owned() + ".save_state";
 
@@ -239,11 +254,52 @@ impl RomFile {
         Ok(())
     }
 
+    pub fn save_crash_state(&self, state: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_owned() + ".crash_state";
+
+        save_file(&file_name, state);
+        Ok(())
+    }
+
+    pub fn save_serial_log(&self, data: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_owned() + ".serial.log";
+
+        save_file(&file_name, data);
+        Ok(())
+    }
+
     pub fn load_state(&self) -> Result<Vec<u8>, String> {
         let file_name = self.file_name().to_owned() + ".save_state";
 
         load_file(&file_name).ok_or_else(|| "load save state failed".to_string())
     }
+
+    pub fn load_state_tree(&self) -> Result<Vec<u8>, String> {
+        let file_name = self.file_name().to_owned() + ".state_tree.toml";
+
+        // a missing tree is the same as a empty one.
+        Ok(load_file(&file_name).unwrap_or_default())
+    }
+
+    pub fn save_state_tree(&self, data: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_owned() + ".state_tree.toml";
+
+        save_file(&file_name, data);
+        Ok(())
+    }
+
+    pub fn save_branch_state(&self, id: u32, state: &[u8]) -> Result<(), String> {
+        let file_name = format!("{}.save_state.{}", self.file_name(), id);
+
+        save_file(&file_name, state);
+        Ok(())
+    }
+
+    pub fn load_branch_state(&self, id: u32) -> Result<Vec<u8>, String> {
+        let file_name = format!("{}.save_state.{}", self.file_name(), id);
+
+        load_file(&file_name).ok_or_else(|| "load branch state failed".to_string())
+    }
 }
 #[cfg(feature = "rfd")]
 impl From<rfd::FileHandle> for RomFile {
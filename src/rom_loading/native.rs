@@ -1,6 +1,10 @@
-use std::{borrow::Cow, path::PathBuf};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
-use gameroy::gameboy::cartridge::CartridgeHeader;
+use gameroy::gameboy::{boot_rom::BootRom, cartridge::CartridgeHeader};
+use parking_lot::Mutex;
 
 use crate::config::config;
 
@@ -32,30 +36,104 @@ fn open_and_read(
         .map_err(|x| format!("error reading '{}': {}", rom_path.display(), x))? as usize)
 }
 
-pub fn load_boot_rom() -> Option<[u8; 256]> {
-    let boot_rom_path = if let Some(x) = &config().boot_rom {
-        PathBuf::from(x)
-    } else {
-        return None;
+pub fn load_boot_rom() -> Option<BootRom> {
+    let boot_rom_path = {
+        let config = config();
+        let path = config
+            .boot_rom
+            .as_ref()
+            .or(config.boot_roms.get(config.model));
+        PathBuf::from(path?)
     };
 
-    let mut boot_rom = [0; 0x100];
-    match open_and_read(&boot_rom_path, &mut &mut boot_rom[..]) {
+    let mut boot_rom = Vec::new();
+    let result = open_and_read(&boot_rom_path, &mut boot_rom).and_then(|_| {
+        BootRom::new(boot_rom)
+            .map_err(|x| format!("error loading '{}': {}", boot_rom_path.display(), x))
+    });
+    match result {
         Err(e) => {
             eprintln!("{}", e);
             None
         }
-        Ok(_) => Some(boot_rom),
+        Ok(boot_rom) => Some(boot_rom),
     }
 }
 
+/// The file, relative to the data folder, that lists the recently opened roms.
+const RECENT_ROMS_FILE: &str = "recent_roms.txt";
+
+/// The maximum number of roms kept in the recent roms list.
+const MAX_RECENT_ROMS: usize = 10;
+
+/// Returns the paths of the recently opened roms, from the most recent to the least recent.
+pub fn recent_roms() -> Vec<PathBuf> {
+    let path = crate::dirs::data_folder().join(RECENT_ROMS_FILE);
+    let list = match std::fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(err) => {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                log::error!("failed reading '{}': {}", path.display(), err);
+            }
+            return Vec::new();
+        }
+    };
+    list.lines()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// The folder of the most recently opened rom, where the open rom dialog starts.
+pub fn last_rom_folder() -> Option<PathBuf> {
+    recent_roms().first()?.parent().map(Path::to_path_buf)
+}
+
+/// Move the given rom to the top of the recent roms list, and save the list to disk.
+pub fn add_recent_rom(rom: &RomFile) {
+    let rom_path = rom.path.canonicalize().unwrap_or_else(|_| rom.path.clone());
+
+    let mut recent = recent_roms();
+    recent.retain(|x| x != &rom_path);
+    recent.insert(0, rom_path);
+    recent.truncate(MAX_RECENT_ROMS);
+
+    let list = recent
+        .iter()
+        .fold(String::new(), |list, x| list + &x.to_string_lossy() + "\n");
+    let path = crate::dirs::data_path(RECENT_ROMS_FILE);
+    if let Err(err) = std::fs::write(&path, list) {
+        log::error!("failed writing '{}': {}", path.display(), err);
+    }
+}
+
+/// The save files that were already backed up since the emulator started. See
+/// `RomFile::backup_ram_data`.
+static BACKED_UP_SAVES: Mutex<Vec<PathBuf>> = parking_lot::const_mutex(Vec::new());
+
 #[derive(Clone, Debug)]
 pub struct RomFile {
     path: PathBuf,
+    /// The MBC specification given in the command line, if any, used when the rom is reloaded.
+    mbc: Option<String>,
 }
 impl RomFile {
     pub fn from_path(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, mbc: None }
+    }
+
+    /// Set the MBC specification the rom was loaded with. See `Cartridge::new_with_spec_str`.
+    pub fn with_mbc(mut self, mbc: Option<String>) -> Self {
+        self.mbc = mbc;
+        self
+    }
+
+    pub fn mbc(&self) -> Option<&str> {
+        self.mbc.as_deref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn get_header(&self) -> Result<CartridgeHeader, String> {
@@ -67,6 +145,17 @@ impl RomFile {
         }
     }
 
+    /// Read the entire rom, blocking the current thread.
+    pub fn read_blocking(&self) -> Result<Vec<u8>, String> {
+        std::fs::read(&self.path).map_err(|e| format!("io error: {}", e))
+    }
+
+    /// The CRC32 of the entire rom, used for looking up the game database.
+    pub fn get_hash(&self) -> Result<u32, String> {
+        let rom = self.read_blocking()?;
+        Ok(crate::game_database::crc32(&rom))
+    }
+
     pub fn file_name(&self) -> Cow<str> {
         self.path
             .file_name()
@@ -86,34 +175,173 @@ impl RomFile {
     }
 
     pub async fn load_ram_data(&self) -> Result<Vec<u8>, String> {
+        self.load_ram_data_blocking()
+    }
+
+    /// Same as `load_ram_data`, for the callers outside of the async executor.
+    pub fn load_ram_data_blocking(&self) -> Result<Vec<u8>, String> {
         let save_path = self.save_path();
         log::info!("loading save at {}", save_path.display());
         std::fs::read(&save_path).map_err(|x| format!("load save failed: {}", x))
     }
 
+    /// The path of a file in `folder` with the same name of the rom, but with the given extension.
+    fn rom_file_path(&self, folder: PathBuf, extension: &str) -> PathBuf {
+        let file_name = self.path.file_name().unwrap_or_default();
+        folder.join(file_name).with_extension(extension)
+    }
+
     fn save_path(&self) -> PathBuf {
-        self.path.with_extension("sav")
+        self.rom_file_path(crate::dirs::save_folder(&self.path), "sav")
     }
 
     fn save_state_path(&self) -> PathBuf {
-        self.path.with_extension("save_state")
+        self.rom_file_path(crate::dirs::state_folder(&self.path), "save_state")
+    }
+
+    fn crash_state_path(&self) -> PathBuf {
+        self.rom_file_path(crate::dirs::state_folder(&self.path), "crash_state")
+    }
+
+    fn state_tree_path(&self) -> PathBuf {
+        self.rom_file_path(crate::dirs::state_folder(&self.path), "state_tree.toml")
+    }
+
+    /// The path of the state of the node `id` of the state tree.
+    fn branch_state_path(&self, id: u32) -> PathBuf {
+        self.rom_file_path(
+            crate::dirs::state_folder(&self.path),
+            &format!("save_state.{}", id),
+        )
+    }
+
+    /// The path of the `n`th backup of the save file, where 1 is the newest one.
+    fn save_backup_path(&self, n: u32) -> PathBuf {
+        self.rom_file_path(crate::dirs::save_folder(&self.path), &format!("sav.{}", n))
     }
 
     pub fn save_ram_data(&self, data: &[u8]) -> Result<(), String> {
+        if let Err(err) = self.backup_ram_data(data) {
+            log::error!("failed to backup save: {}", err);
+        }
         let save_path = self.save_path();
         std::fs::write(save_path, data).map_err(|x| x.to_string())
     }
 
+    /// Before the save file is overwritten by `data`, keep a copy of it as the newest backup, and
+    /// shift the older ones, deleting the oldest. Nothing is done if the save did not change. See
+    /// `Config::save_backups`.
+    ///
+    /// Only the first change in each session is backed up, so the later writes, like the
+    /// autosaves, don't push the saves of the previous sessions out of the backups.
+    fn backup_ram_data(&self, data: &[u8]) -> Result<(), String> {
+        let backups = config().save_backups;
+        if backups == 0 {
+            return Ok(());
+        }
+        let save_path = self.save_path();
+        let mut backed_up = BACKED_UP_SAVES.lock();
+        if backed_up.contains(&save_path) {
+            return Ok(());
+        }
+        match std::fs::read(&save_path) {
+            Ok(old) if old != data => {}
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                backed_up.push(save_path);
+                return Ok(());
+            }
+            Err(err) => return Err(format!("error reading '{}': {}", save_path.display(), err)),
+        }
+        backed_up.push(save_path.clone());
+        for n in (1..backups).rev() {
+            let from = self.save_backup_path(n);
+            if from.exists() {
+                let to = self.save_backup_path(n + 1);
+                std::fs::rename(&from, &to)
+                    .map_err(|x| format!("error renaming '{}': {}", from.display(), x))?;
+            }
+        }
+        let to = self.save_backup_path(1);
+        std::fs::copy(&save_path, &to)
+            .map_err(|x| format!("error copying to '{}': {}", to.display(), x))?;
+        Ok(())
+    }
+
+    /// Replace the save file by its newest backup, and return its data. The older backups are
+    /// shifted to take its place, so each call goes one save further back. The current save is
+    /// discarded.
+    pub fn rollback_ram_data(&self) -> Result<Vec<u8>, String> {
+        let backup_path = self.save_backup_path(1);
+        log::info!("rolling back save to {}", backup_path.display());
+        let data =
+            std::fs::read(&backup_path).map_err(|x| format!("load save backup failed: {}", x))?;
+        std::fs::write(self.save_path(), &data).map_err(|x| x.to_string())?;
+
+        let mut n = 1;
+        loop {
+            let from = self.save_backup_path(n + 1);
+            let to = self.save_backup_path(n);
+            if !from.exists() {
+                // if a older backup was moved, `to` was already moved out.
+                if n == 1 {
+                    std::fs::remove_file(&to)
+                        .map_err(|x| format!("error removing '{}': {}", to.display(), x))?;
+                }
+                break;
+            }
+            std::fs::rename(&from, &to)
+                .map_err(|x| format!("error renaming '{}': {}", from.display(), x))?;
+            n += 1;
+        }
+        Ok(data)
+    }
+
     pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
         let save_path = self.save_state_path();
         std::fs::write(save_path, state).map_err(|x| x.to_string())
     }
 
+    /// Save a state taken after the emulator crashed. It is kept separated from the regular save
+    /// state, to not overwrite it with a possible corrupted state.
+    pub fn save_crash_state(&self, state: &[u8]) -> Result<(), String> {
+        let save_path = self.crash_state_path();
+        std::fs::write(save_path, state).map_err(|x| x.to_string())
+    }
+
+    /// Save the bytes sent through the serial port to '<rom_file_name>.serial.log', in the save
+    /// folder.
+    pub fn save_serial_log(&self, data: &[u8]) -> Result<(), String> {
+        let save_path = self.rom_file_path(crate::dirs::save_folder(&self.path), "serial.log");
+        std::fs::write(save_path, data).map_err(|x| x.to_string())
+    }
+
     pub fn load_state(&self) -> Result<Vec<u8>, String> {
         let save_path = self.save_state_path();
         std::fs::read(save_path).map_err(|x| x.to_string())
     }
 
+    /// Load the state tree of the rom. Returns no data if there is no tree yet.
+    pub fn load_state_tree(&self) -> Result<Vec<u8>, String> {
+        match std::fs::read(self.state_tree_path()) {
+            Ok(data) => Ok(data),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn save_state_tree(&self, data: &[u8]) -> Result<(), String> {
+        std::fs::write(self.state_tree_path(), data).map_err(|x| x.to_string())
+    }
+
+    pub fn save_branch_state(&self, id: u32, state: &[u8]) -> Result<(), String> {
+        std::fs::write(self.branch_state_path(id), state).map_err(|x| x.to_string())
+    }
+
+    pub fn load_branch_state(&self, id: u32) -> Result<Vec<u8>, String> {
+        std::fs::read(self.branch_state_path(id)).map_err(|x| x.to_string())
+    }
+
     pub fn get_save_time(&self) -> Result<u64, String> {
         let save_path = self.save_path();
         let data = std::fs::metadata(&save_path)
@@ -136,6 +364,61 @@ impl From<rfd::FileHandle> for RomFile {
     fn from(handle: rfd::FileHandle) -> Self {
         Self {
             path: handle.inner().to_path_buf(),
+            mbc: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RomFile;
+
+    #[test]
+    fn rollback_ram_data() {
+        let folder = std::env::temp_dir().join("gameroy_rollback_test");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        let rom = RomFile::from_path(folder.join("game.gb"));
+        let backups = |rom: &RomFile| -> Vec<Vec<u8>> {
+            (1..=4)
+                .map_while(|n| std::fs::read(rom.save_backup_path(n)).ok())
+                .collect()
+        };
+
+        for count in 1..=3u8 {
+            std::fs::write(rom.save_path(), [0]).unwrap();
+            for n in 1..=count {
+                std::fs::write(rom.save_backup_path(n as u32), [n]).unwrap();
+            }
+            for n in 1..=count {
+                assert_eq!(rom.rollback_ram_data().unwrap(), [n]);
+                assert_eq!(std::fs::read(rom.save_path()).unwrap(), [n]);
+                let rest: Vec<Vec<u8>> = (n + 1..=count).map(|x| vec![x]).collect();
+                assert_eq!(backups(&rom), rest);
+            }
+            assert!(rom.rollback_ram_data().is_err());
+            assert_eq!(std::fs::read(rom.save_path()).unwrap(), [count]);
         }
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn backup_once_per_session() {
+        let folder = std::env::temp_dir().join("gameroy_backup_test");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        let rom = RomFile::from_path(folder.join("game.gb"));
+
+        std::fs::write(rom.save_path(), [0]).unwrap();
+        rom.save_ram_data(&[0]).unwrap();
+        assert!(!rom.save_backup_path(1).exists());
+        rom.save_ram_data(&[1]).unwrap();
+        rom.save_ram_data(&[2]).unwrap();
+        assert_eq!(std::fs::read(rom.save_path()).unwrap(), [2]);
+        assert_eq!(std::fs::read(rom.save_backup_path(1)).unwrap(), [0]);
+        assert!(!rom.save_backup_path(2).exists());
+
+        let _ = std::fs::remove_dir_all(&folder);
     }
 }
@@ -7,7 +7,7 @@ pub fn load_roms(_roms_path: &str) -> Result<Vec<RomFile>, String> {
     Ok(Vec::new())
 }
 
-pub fn load_boot_rom() -> Option<[u8; 256]> {
+pub fn load_boot_rom() -> Option<gameroy::gameboy::boot_rom::BootRom> {
     None
 }
 pub fn load_file(file_name: &str) -> Result<Vec<u8>, String> {
@@ -92,17 +92,58 @@ impl RomFile {
         load_file(&file_name)
     }
 
+    pub fn rollback_ram_data(&self) -> Result<Vec<u8>, String> {
+        Err("save backups are not supported on the web".to_string())
+    }
+
     pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
         let file_name = self.file_name().to_string() + ".save_state";
 
         save_file(&file_name, state)
     }
 
+    pub fn save_crash_state(&self, state: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_string() + ".crash_state";
+
+        save_file(&file_name, state)
+    }
+
+    pub fn save_serial_log(&self, data: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_string() + ".serial.log";
+
+        save_file(&file_name, data)
+    }
+
     pub fn load_state(&self) -> Result<Vec<u8>, String> {
         let file_name = self.file_name().to_string() + ".save_state";
 
         load_file(&file_name)
     }
+
+    pub fn load_state_tree(&self) -> Result<Vec<u8>, String> {
+        let file_name = self.file_name().to_string() + ".state_tree.toml";
+
+        // a missing tree is the same as a empty one.
+        Ok(load_file(&file_name).unwrap_or_default())
+    }
+
+    pub fn save_state_tree(&self, data: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_string() + ".state_tree.toml";
+
+        save_file(&file_name, data)
+    }
+
+    pub fn save_branch_state(&self, id: u32, state: &[u8]) -> Result<(), String> {
+        let file_name = format!("{}.save_state.{}", self.file_name(), id);
+
+        save_file(&file_name, state)
+    }
+
+    pub fn load_branch_state(&self, id: u32) -> Result<Vec<u8>, String> {
+        let file_name = format!("{}.save_state.{}", self.file_name(), id);
+
+        load_file(&file_name)
+    }
 }
 #[cfg(feature = "rfd")]
 impl From<rfd::FileHandle> for RomFile {
@@ -0,0 +1,66 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{emulator::EmulatorEvent, rom_loading::prepare_cartridge};
+
+/// How often the modification time of the rom file is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// Spawn a thread that watches the rom file at `path`, and sends a `EmulatorEvent::ReloadRom` each
+/// time it is rebuilt. See `Config::watch_rom`. The new rom is loaded like the first one, with the
+/// MBC specification `mbc`, if any.
+///
+/// The file is polled, instead of using the OS file notifications, to also work on network and
+/// removable drives. The thread exits when the emulator is closed.
+pub fn watch_rom(path: PathBuf, mbc: Option<String>, emu_channel: flume::Sender<EmulatorEvent>) {
+    log::info!("watching '{}' for changes", path.display());
+    thread::Builder::new()
+        .name("rom watcher".to_string())
+        .spawn(move || {
+            let mut last_modified = modified_time(&path);
+            let mut changed = false;
+            while !emu_channel.is_disconnected() {
+                thread::sleep(POLL_INTERVAL);
+                let modified = modified_time(&path);
+                if modified.is_none() {
+                    continue;
+                }
+                if modified != last_modified {
+                    // the build may still be writing the file, wait until it stops changing.
+                    last_modified = modified;
+                    changed = true;
+                    continue;
+                }
+                if !changed {
+                    continue;
+                }
+                changed = false;
+
+                let rom = match std::fs::read(&path) {
+                    Ok(x) => x,
+                    Err(err) => {
+                        log::error!("failed to read '{}': {}", path.display(), err);
+                        continue;
+                    }
+                };
+                match prepare_cartridge(rom, mbc.as_deref()) {
+                    Ok(cartridge) => {
+                        log::info!("'{}' changed, reloading", path.display());
+                        let event = EmulatorEvent::ReloadRom(Box::new(cartridge));
+                        if emu_channel.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => log::error!("failed to reload rom: {}", err),
+                }
+            }
+        })
+        .unwrap();
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+/// If a termination signal was already received.
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Handle Ctrl+C, SIGTERM and SIGHUP on Unix, and the console control events on Windows, by
+/// sending a `UserEvent::Quit`, so gameroy exits the same way as when the window is closed: the
+/// emulator thread is joined, and the save data is written.
+///
+/// The handler runs in its own thread, so it can send the event safely. If a second signal arrives
+/// before the shutdown finishes, the process exits immediately.
+pub fn handle_termination(proxy: EventLoopProxy<UserEvent>) {
+    let result = ctrlc::set_handler(move || {
+        if RECEIVED.swap(true, Ordering::SeqCst) {
+            log::warn!("received a second termination signal, exiting without saving");
+            std::process::exit(130);
+        }
+        log::info!("received a termination signal, exiting");
+        if proxy.send_event(UserEvent::Quit).is_err() {
+            std::process::exit(130);
+        }
+    });
+    if let Err(err) = result {
+        log::error!("could not set the termination signal handler: {}", err);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{ops::Range, sync::Arc};
+
+use gameroy::gameboy::{cpu::Cpu, GameBoy};
+use parking_lot::Mutex;
+
+/// The number of sound samples copied to each snapshot.
+pub const OSCILLOSCOPE_SAMPLES: usize = 512;
+
+/// The registers of the PPU, and its internal state, as shown in the debugger.
+#[derive(Debug, Default, Clone)]
+pub struct PpuRegisters {
+    pub lcdc: u8,
+    pub stat: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub lyc: u8,
+    pub ly: u8,
+    /// The number of cycles since the start of the current line.
+    pub lx: u64,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wyc: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub state: u8,
+    pub next_clock_count: u64,
+}
+
+/// A immutable copy of part of the state of the GameBoy, taken by the emulator after each frame
+/// and each pause.
+#[derive(Debug, Default)]
+pub struct GameBoySnapshot {
+    pub clock_count: u64,
+    pub cpu: Cpu,
+    pub div: u16,
+    pub ppu: PpuRegisters,
+    /// The current lower and upper rom banks.
+    pub bank: (u16, u16),
+    /// FF00-FF7F: The I/O registers.
+    pub io: [u8; 0x80],
+    /// FFFF: Interrupt Enabled (IE).
+    pub interrupt_enabled: u8,
+    /// The amplitude of each sound channel in the last `OSCILLOSCOPE_SAMPLES` samples, from the
+    /// oldest to the newest. Empty if the sound is not being sampled.
+    pub channels: Vec<[u8; 4]>,
+    /// A copy of each memory window requested through `Snapshot::request_window`.
+    memory: Vec<(Range<u16>, Vec<u8>)>,
+}
+impl GameBoySnapshot {
+    fn new(gb: &GameBoy, windows: &[Range<u16>]) -> Self {
+        let ppu = gb.ppu.borrow();
+        let ppu_registers = PpuRegisters {
+            lcdc: ppu.lcdc,
+            stat: ppu.stat,
+            scy: ppu.scy,
+            scx: ppu.scx,
+            lyc: ppu.lyc,
+            ly: ppu.ly,
+            lx: (gb.clock_count - ppu.line_start_clock_count) % 456,
+            bgp: ppu.bgp,
+            obp0: ppu.obp0,
+            obp1: ppu.obp1,
+            wyc: ppu.wyc,
+            wy: ppu.wy,
+            wx: ppu.wx,
+            state: ppu.state,
+            next_clock_count: ppu.next_clock_count,
+        };
+        drop(ppu);
+
+        let mut io = [0; 0x80];
+        for (i, x) in io.iter_mut().enumerate() {
+            *x = gb.peek(0xFF00 + i as u16);
+        }
+
+        let channels = {
+            let sound = gb.sound.borrow();
+            let history = sound.channels_history();
+            let start = history.len().saturating_sub(OSCILLOSCOPE_SAMPLES);
+            history.range(start..).copied().collect()
+        };
+
+        let memory = windows
+            .iter()
+            .map(|window| (window.clone(), window.clone().map(|x| gb.peek(x)).collect()))
+            .collect();
+
+        Self {
+            clock_count: gb.clock_count,
+            cpu: gb.cpu.clone(),
+            div: gb.timer.borrow().div,
+            ppu: ppu_registers,
+            bank: gb.cartridge.curr_bank(),
+            io,
+            interrupt_enabled: gb.interrupt_enabled,
+            channels,
+            memory,
+        }
+    }
+
+    /// Read a byte from the I/O registers or from the copied memory windows. Returns None if the
+    /// address is not in any of them.
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0xFF00..=0xFF7F => Some(self.io[address as usize - 0xFF00]),
+            0xFFFF => Some(self.interrupt_enabled),
+            _ => self
+                .memory
+                .iter()
+                .find(|(window, _)| window.contains(&address))
+                .map(|(window, data)| data[(address - window.start) as usize]),
+        }
+    }
+}
+
+/// The last snapshot of the GameBoy, published by the emulator.
+///
+/// UI panels should read from here, instead of locking the `Arc<Mutex<GameBoy>>`, to not contend
+/// with the emulation thread.
+#[derive(Debug, Default, Clone)]
+pub struct Snapshot {
+    current: Arc<Mutex<Arc<GameBoySnapshot>>>,
+    windows: Arc<Mutex<Vec<Range<u16>>>>,
+}
+impl Snapshot {
+    /// The last published snapshot.
+    pub fn get(&self) -> Arc<GameBoySnapshot> {
+        self.current.lock().clone()
+    }
+
+    /// Include the given memory range in the next snapshots.
+    pub fn request_window(&self, window: Range<u16>) {
+        let mut windows = self.windows.lock();
+        if !windows.contains(&window) {
+            windows.push(window);
+        }
+    }
+
+    /// Take a new snapshot of the GameBoy.
+    pub fn publish(&self, gb: &GameBoy) {
+        let snapshot = GameBoySnapshot::new(gb, &self.windows.lock());
+        *self.current.lock() = Arc::new(snapshot);
+    }
+}
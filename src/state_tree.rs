@@ -0,0 +1,250 @@
+//! A tree of save states, for exploring alternative timelines of a game (for example, when making
+//! a tool-assisted speedrun).
+//!
+//! Each state saved records the node it descends from: the last one saved or loaded. Loading an
+//! older node and saving again creates a new branch. The tree is kept next to the save state of
+//! the rom, as '<rom>.state_tree.toml', and the state of each node as '<rom>.save_state.<id>'.
+//!
+//! The tree keeps at most `MAX_NODES` nodes. Past that, each new node takes the place, and the
+//! state file, of the oldest node without a name that is not the current or the saved one.
+
+use gameroy::consts::CLOCK_SPEED;
+use serde::{Deserialize, Serialize};
+
+use crate::RomFile;
+
+/// The maximum number of nodes in the tree, before unnamed nodes start to be replaced.
+const MAX_NODES: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateNode {
+    pub id: u32,
+    pub parent: Option<u32>,
+    /// A name given by the user. May be empty.
+    pub name: String,
+    /// The clock count of the GameBoy in the state.
+    pub clock_count: u64,
+}
+impl StateNode {
+    /// A description of the node, like "#3 boss fight (02:15.40)".
+    pub fn label(&self) -> String {
+        let centis = self.clock_count * 100 / CLOCK_SPEED;
+        let time = format!(
+            "{:02}:{:02}.{:02}",
+            centis / 6000,
+            centis / 100 % 60,
+            centis % 100
+        );
+        if self.name.is_empty() {
+            format!("#{} ({})", self.id, time)
+        } else {
+            format!("#{} {} ({})", self.id, self.name, time)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateTree {
+    /// The nodes, in the order they were saved.
+    pub nodes: Vec<StateNode>,
+    /// The node that the current emulation descends from, if any.
+    pub current: Option<u32>,
+    /// The node whose state is also in the regular save state of the rom, which is loaded by
+    /// `EmulatorEvent::LoadState`.
+    pub saved: Option<u32>,
+}
+impl StateTree {
+    /// Load the tree of `rom`. If it has no tree yet, or it fails to load, a empty one is returned.
+    pub fn load(rom: &RomFile) -> Self {
+        let data = match rom.load_state_tree() {
+            Ok(x) => x,
+            Err(err) => {
+                log::warn!("failed to load state tree: {}", err);
+                return Self::default();
+            }
+        };
+        let text = String::from_utf8_lossy(&data);
+        toml::from_str(&text).unwrap_or_else(|err| {
+            log::error!("failed to parse state tree: {}", err);
+            Self::default()
+        })
+    }
+
+    fn save(&self, rom: &RomFile) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|err| err.to_string())?;
+        rom.save_state_tree(text.as_bytes())
+    }
+
+    pub fn get(&self, id: u32) -> Option<&StateNode> {
+        self.nodes.iter().find(|x| x.id == id)
+    }
+
+    /// Save `state` as a new child of the current node, and make it the current one. Returns the id
+    /// of the new node.
+    pub fn branch(
+        &mut self,
+        rom: &RomFile,
+        name: String,
+        clock_count: u64,
+        state: &[u8],
+    ) -> Result<u32, String> {
+        let replaced = self.replaceable_node();
+        let id = match replaced {
+            Some(i) => self.nodes[i].id,
+            None => self.nodes.iter().map(|x| x.id + 1).max().unwrap_or(1),
+        };
+        rom.save_branch_state(id, state)?;
+        if let Some(i) = replaced {
+            self.remove_node(i);
+        }
+        self.nodes.push(StateNode {
+            id,
+            parent: self.current,
+            name,
+            clock_count,
+        });
+        self.current = Some(id);
+        self.save(rom)?;
+        Ok(id)
+    }
+
+    /// If the tree is full, the index of the node that a new node replaces: the oldest one without
+    /// a name, that is not the current or the saved node.
+    fn replaceable_node(&self) -> Option<usize> {
+        if self.nodes.len() < MAX_NODES {
+            return None;
+        }
+        self.nodes.iter().position(|x| {
+            x.name.is_empty() && Some(x.id) != self.current && Some(x.id) != self.saved
+        })
+    }
+
+    /// Remove the node at index `i`. Its children become children of its parent.
+    fn remove_node(&mut self, i: usize) {
+        let node = self.nodes.remove(i);
+        for child in self.nodes.iter_mut().filter(|x| x.parent == Some(node.id)) {
+            child.parent = node.parent;
+        }
+    }
+
+    /// Load the state of the node `id`, and make it the current one.
+    pub fn checkout(&mut self, rom: &RomFile, id: u32) -> Result<Vec<u8>, String> {
+        if self.get(id).is_none() {
+            return Err(format!("there is no state #{}", id));
+        }
+        let state = rom.load_branch_state(id)?;
+        self.current = Some(id);
+        self.save(rom)?;
+        Ok(state)
+    }
+
+    /// Make the node of the regular save state the current one, after it is loaded.
+    pub fn checkout_saved(&mut self, rom: &RomFile) -> Result<(), String> {
+        if self.current == self.saved {
+            return Ok(());
+        }
+        self.current = self.saved;
+        self.save(rom)
+    }
+
+    /// Record that the current node is also in the regular save state.
+    pub fn set_saved(&mut self, rom: &RomFile) -> Result<(), String> {
+        self.saved = self.current;
+        self.save(rom)
+    }
+
+    pub fn rename(&mut self, rom: &RomFile, id: u32, name: String) -> Result<(), String> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|x| x.id == id)
+            .ok_or_else(|| format!("there is no state #{}", id))?;
+        node.name = name;
+        self.save(rom)
+    }
+
+    /// The nodes in depth-first order, with their depth. Children come in the order they were
+    /// saved.
+    pub fn walk(&self) -> Vec<(usize, &StateNode)> {
+        let mut list = Vec::with_capacity(self.nodes.len());
+        // nodes whose parent is missing are shown as roots.
+        let mut stack: Vec<(usize, &StateNode)> = self
+            .nodes
+            .iter()
+            .rev()
+            .filter(|x| x.parent.map_or(true, |p| self.get(p).is_none()))
+            .map(|x| (0, x))
+            .collect();
+        while let Some((depth, node)) = stack.pop() {
+            list.push((depth, node));
+            stack.extend(
+                self.nodes
+                    .iter()
+                    .rev()
+                    .filter(|x| x.parent == Some(node.id))
+                    .map(|x| (depth + 1, x)),
+            );
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StateNode, StateTree, MAX_NODES};
+
+    fn node(id: u32, parent: Option<u32>) -> StateNode {
+        StateNode {
+            id,
+            parent,
+            name: String::new(),
+            clock_count: 0,
+        }
+    }
+
+    #[test]
+    fn walk_depth_first() {
+        let tree = StateTree {
+            nodes: vec![
+                node(1, None),
+                node(2, Some(1)),
+                node(3, Some(2)),
+                node(4, Some(1)),
+                node(5, None),
+                node(6, Some(9)),
+            ],
+            current: None,
+            saved: None,
+        };
+        let walk: Vec<(usize, u32)> = tree.walk().iter().map(|(d, x)| (*d, x.id)).collect();
+        assert_eq!(walk, [(0, 1), (1, 2), (2, 3), (1, 4), (0, 5), (0, 6)]);
+    }
+
+    #[test]
+    fn replace_oldest_unnamed_node() {
+        let mut tree = StateTree {
+            nodes: (1..=MAX_NODES as u32)
+                .map(|id| node(id, id.checked_sub(1).filter(|&x| x != 0)))
+                .collect(),
+            current: Some(MAX_NODES as u32),
+            saved: Some(1),
+        };
+        tree.nodes[1].name = "boss fight".to_string();
+        // 1 is saved, and 2 is named.
+        let i = tree.replaceable_node().unwrap();
+        assert_eq!(tree.nodes[i].id, 3);
+        tree.remove_node(i);
+        assert_eq!(tree.get(4).unwrap().parent, Some(2));
+        assert_eq!(tree.replaceable_node(), None);
+    }
+
+    #[test]
+    fn label() {
+        let mut node = node(3, None);
+        node.clock_count = gameroy::consts::CLOCK_SPEED * 135 + gameroy::consts::CLOCK_SPEED / 2;
+        assert_eq!(node.label(), "#3 (02:15.50)");
+        node.name = "boss fight".to_string();
+        assert_eq!(node.label(), "#3 boss fight (02:15.50)");
+    }
+}
@@ -10,7 +10,7 @@ use sprite_render::SpriteRender;
 
 #[cfg(not(feature = "static"))]
 use crate::config;
-use crate::widget::fold_view::FoldIcon;
+use crate::{config::Theme, widget::fold_view::FoldIcon};
 
 /// Avaliable scales for bitmaps.
 ///
@@ -171,11 +171,15 @@ mod static_files {
     pub struct StaticFiles {
         pub font: &'static [u8],
         pub style: &'static str,
+        pub style_light: &'static str,
+        pub style_high_contrast: &'static str,
         pub icons_texture: &'static [&'static [u8]],
     }
     pub static FILES: StaticFiles = StaticFiles {
         font: include_bytes!("../assets/NotoSansMono.ttf"),
         style: include_str!("../assets/style.ron"),
+        style_light: include_str!("../assets/style_light.ron"),
+        style_high_contrast: include_str!("../assets/style_high_contrast.ron"),
         icons_texture: &[
             include_bytes!("../assets/icons0.75x.png"),
             include_bytes!("../assets/icons1x.png"),
@@ -312,6 +316,7 @@ impl Style {
         fonts: &mut Fonts,
         render: &mut dyn SpriteRender,
         scale_factor: f64,
+        theme: Theme,
     ) -> Option<Self> {
         let loader = Loader {
             fonts,
@@ -322,13 +327,16 @@ impl Style {
 
         #[cfg(not(feature = "static"))]
         let file = &{
-            let mut path = config::base_folder().unwrap();
-            path.push("assets/style.ron");
-            std::fs::read_to_string(path)
-                .unwrap_or_else(|err| panic!("failed reading 'assets/style.ron': {}", err))
+            let path = style_path(theme);
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("failed reading '{}': {}", path.display(), err))
         };
         #[cfg(feature = "static")]
-        let file = static_files::FILES.style;
+        let file = match theme {
+            Theme::Dark => static_files::FILES.style,
+            Theme::Light => static_files::FILES.style_light,
+            Theme::HighContrast => static_files::FILES.style_high_contrast,
+        };
 
         let mut deser = ron::Deserializer::from_str(file).unwrap();
         let style: Result<Self, _> = load_style(&mut deser, loader);
@@ -337,6 +345,41 @@ impl Style {
     }
 }
 
+#[cfg(not(feature = "static"))]
+fn style_path(theme: Theme) -> std::path::PathBuf {
+    let mut path = config::base_folder().unwrap();
+    path.push("assets");
+    path.push(theme.style_file());
+    path
+}
+
+/// Spawn a thread that polls the style file of the given theme, and sends a
+/// `UserEvent::ReloadStyle` every time it is modified.
+#[cfg(not(any(feature = "static", target_arch = "wasm32", target_os = "android")))]
+pub fn watch_style_file(theme: Theme, proxy: winit::event_loop::EventLoopProxy<crate::UserEvent>) {
+    let path = style_path(theme);
+    let modified = move || std::fs::metadata(&path).and_then(|x| x.modified()).ok();
+    std::thread::Builder::new()
+        .name("style_watcher".to_string())
+        .spawn(move || {
+            let mut last_modified = modified();
+            loop {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                let current = modified();
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                log::info!("style file was modified");
+                if proxy.send_event(crate::UserEvent::ReloadStyle).is_err() {
+                    // event loop is closed
+                    break;
+                }
+            }
+        })
+        .unwrap();
+}
+
 // From https://stackoverflow.com/a/7666577 or http://www.cse.yorku.ca/~oz/hash.html
 pub fn hash(s: &[u8]) -> u64 {
     use std::num::Wrapping as w;
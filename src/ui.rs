@@ -18,10 +18,20 @@ use winit::{
 use crate::{event_table::EventTable, style::Style, UserEvent, SCREEN_HEIGHT, SCREEN_WIDTH};
 
 mod emulator_ui;
-pub use emulator_ui::create_emulator_ui;
+pub use emulator_ui::{
+    create_emulator_ui, import_session, open_crash_menu, open_rom_warnings, save_debug_layout,
+};
 
 mod rom_loading_ui;
-pub use rom_loading_ui::{create_rom_loading_ui, RomEntries};
+#[cfg(feature = "rfd")]
+pub use rom_loading_ui::open_rom_dialog;
+pub use rom_loading_ui::{create_rom_loading_ui, open_load_error, RomEntries};
+
+#[cfg(all(
+    feature = "threads",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+mod spectator;
 
 struct Render<'a>(&'a mut dyn SpriteRender);
 impl GuiRenderer for Render<'_> {
@@ -54,6 +64,8 @@ pub struct Textures {
     pub tilemap: u32,
     pub background: u32,
     pub window: u32,
+    pub oscilloscope: u32,
+    pub spectator: u32,
 }
 
 pub struct Ui {
@@ -65,6 +77,22 @@ pub struct Ui {
     pub textures: Textures,
     pub is_animating: bool,
     pub force_render: bool,
+    /// The scale factor used in the last time the style was loaded.
+    style_scale_factor: f64,
+    /// If the screen texture was recreated, and need to be entirely uploaded again.
+    screen_outdated: bool,
+    /// The spectator window, if open. See `config::Config::spectator_window`.
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    spectator: Option<spectator::Spectator>,
+    /// If the screen is colorized, so it is not in the DMG palette. See `set_screen_colorized`.
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    screen_colorized: bool,
 
     #[cfg(target_os = "android")]
     pub textures_to_reload: Vec<(u32, Box<dyn Fn() -> (u32, u32, Vec<u8>) + Send + 'static>)>,
@@ -74,15 +102,7 @@ impl Ui {
         let fonts = Fonts::new();
 
         // create the render and camera, and a texture for the glyphs rendering
-        #[cfg(not(any(target_arch = "wasm32")))]
-        let render = sprite_render::GlSpriteRender::new(window, true)
-            .map(|x| Box::new(x) as Box<dyn SpriteRender>)
-            .unwrap_or_else(|err| {
-                log::error!("failed to create GlSpriteRender: {:?}", err);
-                Box::new(sprite_render::NoopSpriteRender)
-            });
-        #[cfg(target_arch = "wasm32")]
-        let render = Box::new(sprite_render::WebGLSpriteRender::new(window));
+        let render = crate::render::create_render(window);
 
         log::info!("loading graphics");
 
@@ -93,6 +113,8 @@ impl Ui {
             tilemap: 2,
             background: 3,
             window: 4,
+            oscilloscope: 6,
+            spectator: 7,
         };
 
         // create the gui, and the gui_render
@@ -119,6 +141,18 @@ impl Ui {
             event_table: Rc::new(RefCell::new(EventTable::new())),
             is_animating: false,
             force_render: true,
+            style_scale_factor: window.scale_factor(),
+            screen_outdated: true,
+            #[cfg(all(
+                feature = "threads",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            spectator: None,
+            #[cfg(all(
+                feature = "threads",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            screen_colorized: false,
             #[cfg(target_os = "android")]
             textures_to_reload: Vec::new(),
         };
@@ -200,8 +234,13 @@ impl Ui {
         self.update_window(window);
     }
 
+    /// Returns true if the gui changed, or is animating, and need to be rendered again.
+    pub fn render_is_dirty(&mut self) -> bool {
+        self.gui.render_is_dirty() || self.is_animating
+    }
+
     fn update_window(&mut self, window: &Window) {
-        if self.gui.render_is_dirty() || self.is_animating {
+        if self.render_is_dirty() {
             window.request_redraw();
         }
         if let Some(cursor) = self.gui.cursor_change() {
@@ -209,11 +248,101 @@ impl Ui {
         }
     }
 
+    /// Returns true if the screen texture was recreated since the last call to
+    /// `update_screen_texture`.
     #[cfg(feature = "threads")]
-    pub fn update_screen_texture(&mut self, img_data: &[u8]) {
+    pub fn screen_texture_outdated(&self) -> bool {
+        self.screen_outdated
+    }
+
+    /// Upload the given rows of the screen. `img_data` is the entire screen in RGBA. If the
+    /// texture is outdated, all rows are uploaded.
+    #[cfg(feature = "threads")]
+    pub fn update_screen_texture(&mut self, img_data: &[u8], rows: std::ops::Range<usize>) {
+        let rows = if std::mem::take(&mut self.screen_outdated) {
+            0..SCREEN_HEIGHT
+        } else {
+            rows
+        };
+        if rows.is_empty() {
+            return;
+        }
+        let stride = SCREEN_WIDTH * 4;
         self.render
-            .update_texture(TextureId(self.textures.screen), Some(img_data), None)
+            .update_texture(
+                TextureId(self.textures.screen),
+                Some(&img_data[rows.start * stride..rows.end * stride]),
+                Some([0, rows.start as u32, SCREEN_WIDTH as u32, rows.len() as u32]),
+            )
             .unwrap();
+        #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+        if let Some(spectator) = &mut self.spectator {
+            spectator.update(self.render.as_mut(), img_data, rows, self.screen_colorized);
+        }
+    }
+
+    /// Set if the screen is colorized. The spectator palette only applies to screens in the DMG
+    /// palette.
+    pub fn set_screen_colorized(&mut self, colorized: bool) {
+        #[cfg(all(
+            feature = "threads",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        ))]
+        if self.screen_colorized != colorized {
+            self.screen_colorized = colorized;
+            self.screen_outdated = true;
+        }
+        #[cfg(not(all(
+            feature = "threads",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        )))]
+        let _ = colorized;
+    }
+
+    /// Open the spectator window, a second window that only shows the game screen.
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    pub fn open_spectator(
+        &mut self,
+        event_loop: &winit::event_loop::EventLoopWindowTarget<UserEvent>,
+    ) {
+        let config = crate::config::config();
+        match spectator::Spectator::new(
+            event_loop,
+            self.render.as_mut(),
+            self.textures.spectator,
+            config.spectator_scale,
+            config.spectator_palette,
+        ) {
+            Ok(spectator) => {
+                self.spectator = Some(spectator);
+                self.screen_outdated = true;
+            }
+            Err(err) => log::error!("failed to open spectator window: {}", err),
+        }
+    }
+
+    /// Handle the event if it belongs to the spectator window. Returns false if the event is for
+    /// other window.
+    #[cfg(all(
+        feature = "threads",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    pub fn spectator_window_event(&mut self, window_id: WindowId, event: &WindowEvent) -> bool {
+        let Some(spectator) = &mut self.spectator else {
+            return false;
+        };
+        if spectator.id() != window_id {
+            return false;
+        }
+        if !spectator.window_event(self.render.as_mut(), event) {
+            log::info!("closing spectator window");
+            let spectator = self.spectator.take().unwrap();
+            spectator.close(self.render.as_mut());
+        }
+        true
     }
 
     pub fn update_texture(&mut self, texture: u32, img_data: &[u8]) {
@@ -222,11 +351,20 @@ impl Ui {
             .unwrap();
     }
 
+    /// Returns true if the scale factor of the window is not the one used to load the current
+    /// style, meaning that the graphics need to be reloaded.
+    pub fn style_is_outdated(&self, window: &Window) -> bool {
+        self.style_scale_factor != window.scale_factor()
+    }
+
     fn load_graphics(&mut self, window: &Window) -> Style {
+        self.style_scale_factor = window.scale_factor();
+        let theme = crate::config::config().theme;
         let style = Style::load(
             self.gui.fonts_mut(),
             self.render.as_mut(),
-            window.scale_factor(),
+            self.style_scale_factor,
+            theme,
         )
         .unwrap();
 
@@ -246,6 +384,7 @@ impl Ui {
             .filter(sprite_render::TextureFilter::Nearest)
             .create(render)
             .unwrap();
+        self.screen_outdated = true;
         Texture::new(128, 192)
             .id(TextureId(self.textures.tilemap))
             .filter(sprite_render::TextureFilter::Nearest)
@@ -261,6 +400,19 @@ impl Ui {
             .filter(sprite_render::TextureFilter::Nearest)
             .create(render)
             .unwrap();
+        Texture::new(
+            emulator_ui::OSCILLOSCOPE_WIDTH,
+            emulator_ui::OSCILLOSCOPE_HEIGHT,
+        )
+        .id(TextureId(self.textures.oscilloscope))
+        .filter(sprite_render::TextureFilter::Nearest)
+        .create(render)
+        .unwrap();
+        Texture::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+            .id(TextureId(self.textures.spectator))
+            .filter(sprite_render::TextureFilter::Nearest)
+            .create(render)
+            .unwrap();
 
         #[cfg(target_os = "android")]
         for (id, texture) in self.textures_to_reload.iter() {
@@ -276,6 +428,16 @@ impl Ui {
     }
 
     pub fn render(&mut self, window_id: WindowId) {
+        #[cfg(all(
+            feature = "threads",
+            not(any(target_arch = "wasm32", target_os = "android"))
+        ))]
+        if let Some(spectator) = &mut self.spectator {
+            if spectator.id() == window_id {
+                spectator.render(self.render.as_mut());
+                return;
+            }
+        }
         let mut ctx = self.gui.get_render_context();
         let (sprites, is_anim) = self
             .gui_render
@@ -1,5 +1,9 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
+use gameroy::debugger::session::Session;
 use giui::{
     graphics::{Graphic, Icon, Texture},
     layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
@@ -11,7 +15,12 @@ use giui::{
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
-    event_table::EventTable,
+    debug_panel::DebugTab,
+    emulator::ResetOptions,
+    event_table::{DisassemblyNavigation, EventTable},
+    input_latency::InputLatency,
+    localization::tr,
+    state_tree::StateTree,
     style::Style,
     ui::{Textures, Ui},
     widget::{
@@ -21,9 +30,23 @@ use crate::{
     EmulatorEvent, UserEvent,
 };
 
+mod annotation_overlay;
+mod audio_viewer;
+mod cartridge_viewer;
+mod console;
 mod disassembler_viewer;
+mod encoder_overlay;
 mod game_pad;
+mod latency_overlay;
+mod notification_overlay;
 mod ppu_viewer;
+mod save_indicator;
+mod screen_view;
+mod serial_viewer;
+mod session;
+mod state_tree_viewer;
+
+pub use audio_viewer::{OSCILLOSCOPE_HEIGHT, OSCILLOSCOPE_WIDTH};
 
 pub fn create_emulator_ui(ui: &mut Ui, debug: bool) {
     let style = &ui.gui.get::<Style>().clone();
@@ -43,25 +66,50 @@ pub fn create_gui(
     style: &Style,
     debug: bool,
 ) {
+    gui.set(DebugLayout::new());
     let root = gui.reserve_id();
-    let mut screen_id = gui.reserve_id();
-    let mut split_view = gui.reserve_id();
+    let panels = Panels {
+        root,
+        screen_id: Rc::new(Cell::new(gui.reserve_id())),
+        split_view: Rc::new(Cell::new(gui.reserve_id())),
+        command_field: Rc::new(Cell::new(None)),
+        event_table,
+    };
 
-    let sty = style.clone();
-    let event_table_clone = event_table.clone();
+    let panels_ = panels.clone();
     gui.create_control_reserved(root)
         .behaviour(OnKeyboardEvent::new(move |event, _, ctx| {
             use giui::KeyboardEvent::*;
             use winit::event::VirtualKeyCode::*;
+            let panels = &panels_;
             let sender = ctx.get::<flume::Sender<EmulatorEvent>>().clone();
             let debug = ctx.get::<crate::AppState>().debug;
             let app_state = ctx.get_mut::<crate::AppState>();
+            if app_state.binding_macro {
+                if let Pressed(x) = event {
+                    app_state.binding_macro = false;
+                    sender.send(EmulatorEvent::BindMacro(x)).unwrap();
+                    return true;
+                }
+            }
+            let ctrl = app_state.ctrl;
+            let old_joypad = app_state.joypad;
             let mut set_key = |key: u8, value: bool| {
                 app_state.joypad = (app_state.joypad & !(1 << key)) | ((!value as u8) << key)
             };
             let km = &crate::config::config().keymap;
             match event {
+                Pressed(P) if ctrl => open_command_palette(ctx, panels),
+                Pressed(Tab) if ctrl && debug => panels.cycle_focus(ctx),
                 Pressed(M) => open_menu(ctx, root),
+                Pressed(Left) if ctrl && debug => panels
+                    .event_table
+                    .borrow_mut()
+                    .notify(DisassemblyNavigation::Back, ctx),
+                Pressed(Right) if ctrl && debug => panels
+                    .event_table
+                    .borrow_mut()
+                    .notify(DisassemblyNavigation::Forward, ctx),
                 Pressed(x) if x == km.right => set_key(0, true), // Left
                 Release(x) if x == km.right => set_key(0, false),
                 Pressed(x) if x == km.left => set_key(1, true), // Right
@@ -96,17 +144,10 @@ pub fn create_gui(
                             Pressed(x) if x == km.debug_run => {
                                 sender.send(EmulatorEvent::Run).unwrap();
                             }
-                            Pressed(x) if x == km.open_debugger => {
-                                let textures = ctx.get::<Textures>().clone();
-                                close_debug_panel(
-                                    ctx,
-                                    &textures,
-                                    &mut split_view,
-                                    &mut screen_id,
-                                    root,
-                                    &sty,
-                                );
+                            Pressed(x) if x == km.toggle_jit => {
+                                sender.send(EmulatorEvent::SetJit(None)).unwrap();
                             }
+                            Pressed(x) if x == km.open_debugger => panels.toggle_debug(ctx),
                             _ => {}
                         }
                     } else {
@@ -117,18 +158,9 @@ pub fn create_gui(
                             Pressed(x) if x == km.load_state => {
                                 sender.send(EmulatorEvent::LoadState).unwrap();
                             }
-                            Pressed(x) if x == km.open_debugger => {
-                                let textures = ctx.get::<Textures>().clone();
-                                // Debug
-                                open_debug_panel(
-                                    ctx,
-                                    &textures,
-                                    split_view,
-                                    root,
-                                    &sty,
-                                    &mut screen_id,
-                                    event_table.clone(),
-                                );
+                            Pressed(x) if x == km.open_debugger => panels.toggle_debug(ctx),
+                            Pressed(x) if x == km.toggle_jit => {
+                                sender.send(EmulatorEvent::SetJit(None)).unwrap();
                             }
                             Pressed(x) | Release(x) if x == km.speed => sender
                                 .send(EmulatorEvent::FrameLimit(!matches!(event, Pressed(_))))
@@ -136,35 +168,167 @@ pub fn create_gui(
                             Pressed(x) | Release(x) if x == km.rewind => sender
                                 .send(EmulatorEvent::Rewind(matches!(event, Pressed(_))))
                                 .unwrap(),
-
+                            Pressed(x) if x == km.record_macro => {
+                                let app_state = ctx.get_mut::<crate::AppState>();
+                                app_state.recording_macro = !app_state.recording_macro;
+                                // after the recording stops, the next key pressed is bound to it.
+                                app_state.binding_macro = !app_state.recording_macro;
+                                sender
+                                    .send(EmulatorEvent::RecordMacro(app_state.recording_macro))
+                                    .unwrap();
+                            }
+                            Pressed(x) => sender.send(EmulatorEvent::PlayMacro(x)).unwrap(),
                             _ => {}
                         }
                     }
                 }
             }
+            let joypad = ctx.get::<crate::AppState>().joypad;
+            if joypad != old_joypad {
+                ctx.get::<InputLatency>().key_event(joypad);
+            }
             true
         }))
         .build(gui);
 
+    let ctx = &mut gui.get_context();
     if debug {
-        open_debug_panel(
-            &mut gui.get_context(),
+        panels.open_debug(ctx, textures, style);
+    } else {
+        let mut screen_id = panels.screen_id.get();
+        let event_table = &mut *panels.event_table.borrow_mut();
+        create_screen(ctx, textures, &mut screen_id, root, style, event_table);
+        panels.screen_id.set(screen_id);
+        ctx.set_focus(screen_id);
+    }
+}
+
+/// The positions of the divisions of the debug panel, and its selected tab, kept while it is
+/// closed, and saved between sessions (see `debug_panel`) and in the debugger sessions.
+#[derive(Clone)]
+struct DebugLayout {
+    /// The splits between the screen and the debug panel, between the tabs and the side panel,
+    /// and between the tab page and the console.
+    splits: [Rc<Cell<f32>>; 3],
+    tab: Rc<Cell<DebugTab>>,
+    /// The SplitViews of the splits, while the debug panel is open.
+    split_views: Rc<Cell<Option<[Id; 3]>>>,
+}
+impl DebugLayout {
+    /// The keys of the splits in the layout of a session.
+    const KEYS: [&'static str; 3] = ["screen_split", "side_panel_split", "console_split"];
+
+    /// The layout of the last session.
+    fn new() -> Self {
+        let saved = crate::debug_panel::layout();
+        Self {
+            splits: saved.splits.map(|x| Rc::new(Cell::new(x.clamp(0.0, 1.0)))),
+            tab: Rc::new(Cell::new(saved.tab)),
+            split_views: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Keep the layout for the next session. It is written to disk by `save_debug_layout`.
+    fn update(&self) {
+        crate::debug_panel::update(|layout| {
+            layout.splits = self.splits.each_ref().map(|x| x.get());
+            layout.tab = self.tab.get();
+        });
+    }
+
+    fn to_session(&self) -> Vec<(String, String)> {
+        Self::KEYS
+            .iter()
+            .zip(&self.splits)
+            .map(|(key, split)| (key.to_string(), split.get().to_string()))
+            .collect()
+    }
+
+    /// Set the splits in the layout of the session. Missing or invalid ones are kept.
+    fn apply_session(&self, session: &Session, ctx: &mut Context) {
+        for (key, split) in Self::KEYS.iter().zip(&self.splits) {
+            let value = session
+                .layout_value(key)
+                .and_then(|x| x.parse::<f32>().ok());
+            if let Some(value) = value.filter(|x| (0.0..=1.0).contains(x)) {
+                split.set(value);
+            }
+        }
+        if let Some(split_views) = self.split_views.get() {
+            for id in split_views {
+                ctx.dirty_layout(id);
+            }
+        }
+    }
+}
+
+/// The controls that are replaced when the debug panel is opened or closed.
+#[derive(Clone)]
+struct Panels {
+    root: Id,
+    screen_id: Rc<Cell<Id>>,
+    split_view: Rc<Cell<Id>>,
+    /// The command field of the debug panel, and if it has the keyboard focus, while the debug
+    /// panel is open.
+    command_field: Rc<Cell<Option<(Id, bool)>>>,
+    event_table: Rc<RefCell<EventTable>>,
+}
+impl Panels {
+    fn open_debug(&self, ctx: &mut Context, textures: &Textures, style: &Style) {
+        let mut screen_id = self.screen_id.get();
+        let command_field = open_debug_panel(
+            ctx,
             textures,
-            split_view,
-            root,
+            self.split_view.get(),
+            self.root,
             style,
             &mut screen_id,
-            event_table_clone,
+            self.event_table.clone(),
         );
-    } else {
-        create_screen(
-            &mut gui.get_context(),
+        self.screen_id.set(screen_id);
+        self.command_field.set(Some((command_field, false)));
+    }
+
+    fn close_debug(&self, ctx: &mut Context, textures: &Textures, style: &Style) {
+        let mut screen_id = self.screen_id.get();
+        let mut split_view = self.split_view.get();
+        close_debug_panel(
+            ctx,
             textures,
+            &mut split_view,
             &mut screen_id,
-            root,
+            self.root,
             style,
+            &mut self.event_table.borrow_mut(),
         );
-        gui.set_focus(Some(screen_id));
+        self.screen_id.set(screen_id);
+        self.split_view.set(split_view);
+        self.command_field.set(None);
+    }
+
+    /// Move the keyboard focus between the screen, which receives the game input, and the command
+    /// field of the debug panel.
+    fn cycle_focus(&self, ctx: &mut Context) {
+        let Some((command_field, focused)) = self.command_field.get() else {
+            return;
+        };
+        if focused {
+            ctx.set_focus(self.screen_id.get());
+        } else {
+            ctx.set_focus(command_field);
+        }
+        self.command_field.set(Some((command_field, !focused)));
+    }
+
+    /// Open the debug panel if it is closed, or close it otherwise.
+    fn toggle_debug(&self, ctx: &mut Context) {
+        let textures = ctx.get::<Textures>().clone();
+        let style = ctx.get::<Style>().clone();
+        if ctx.get::<crate::AppState>().debug {
+            self.close_debug(ctx, &textures, &style);
+        } else {
+            self.open_debug(ctx, &textures, &style);
+        }
     }
 }
 
@@ -175,11 +339,13 @@ fn close_debug_panel(
     screen_id: &mut Id,
     root: Id,
     style: &Style,
+    event_table: &mut EventTable,
 ) {
     ctx.remove(*split_view);
     *split_view = ctx.reserve();
+    ctx.get::<DebugLayout>().split_views.set(None);
 
-    create_screen(ctx, textures, screen_id, root, style);
+    create_screen(ctx, textures, screen_id, root, style, event_table);
     ctx.set_focus(*screen_id);
     let proxy = ctx.get::<EventLoopProxy<UserEvent>>();
     proxy.send_event(UserEvent::Debug(false)).unwrap();
@@ -193,16 +359,23 @@ fn open_debug_panel(
     style: &Style,
     screen_id: &mut Id,
     event_table: Rc<RefCell<EventTable>>,
-) {
+) -> Id {
     let event_table = &mut *event_table.borrow_mut();
+    let layout = ctx.get::<DebugLayout>().clone();
+    let [screen_split, side_panel_split, console_split] = layout.splits.clone();
+    let update_layout = |_, ctx: &mut Context| ctx.get::<DebugLayout>().update();
     ctx.create_control_reserved(split_view)
         .parent(root)
         .graphic(style.split_background.clone())
-        .behaviour_and_layout(SplitView::new(0.333, 4.0, [2.0; 4], false))
+        .behaviour_and_layout(
+            SplitView::new(0.333, 4.0, [2.0; 4], false)
+                .shared(screen_split)
+                .on_change(update_layout),
+        )
         .build(ctx);
     ctx.remove(*screen_id);
 
-    create_screen(ctx, textures, screen_id, split_view, style);
+    create_screen(ctx, textures, screen_id, split_view, style, event_table);
 
     // create debug panel
     let debug_panel = ctx
@@ -214,7 +387,11 @@ fn open_debug_panel(
     let h_box = ctx
         .create_control()
         .parent(debug_panel)
-        .behaviour_and_layout(SplitView::new(1.0, 2.0, [2.0; 4], false))
+        .behaviour_and_layout(
+            SplitView::new(1.0, 2.0, [2.0; 4], false)
+                .shared(side_panel_split)
+                .on_change(update_layout),
+        )
         .expand_y(true)
         .build(ctx);
 
@@ -240,7 +417,11 @@ fn open_debug_panel(
         .parent(vbox)
         // .graphic(style.split_background.clone())
         .expand_y(true)
-        .behaviour_and_layout(SplitView::new(0.9, 2.0, [0.0; 4], true))
+        .behaviour_and_layout(
+            SplitView::new(0.9, 2.0, [0.0; 4], true)
+                .shared(console_split)
+                .on_change(update_layout),
+        )
         .build(ctx);
 
     let tab_page = ctx
@@ -253,34 +434,33 @@ fn open_debug_panel(
     let ppu_id = ctx.reserve();
     disassembler_viewer::side_panel(ctx, style, h_box, cpu_id, ppu_id, event_table);
 
-    let scroll_log = ctx.reserve();
-    let content = ctx.reserve();
-    super::scroll_viewer(ctx, scroll_log, content, style, (true, true))
-        .parent(v_split)
-        .graphic(style.terminal_background.clone())
-        .build(ctx);
-    let log = ctx
-        .create_control()
-        .graphic(Text::new(
-            String::new(),
-            (-1, -1),
-            style.terminal_text_style.clone(),
-        ))
-        .layout(FitGraphic)
-        .parent(content)
-        .build(ctx);
-    disassembler_viewer::command_field(ctx, vbox, style, scroll_log, log);
+    let (scroll_log, log) = console::build(v_split, ctx, event_table, style);
+    let command_field = disassembler_viewer::command_field(ctx, vbox, style, scroll_log, log);
 
-    let tab_group = ButtonGroup::new(|_, _| ());
+    // the buttons of the tabs, in the order of `DebugTab::ALL`.
+    let tab_buttons = [(); 6].map(|_| ctx.reserve());
+    let tab_group = {
+        let layout = layout.clone();
+        ButtonGroup::new(move |id, _| {
+            let Some(i) = tab_buttons.iter().position(|&x| x == id) else {
+                return;
+            };
+            if layout.tab.get() != DebugTab::ALL[i] {
+                layout.tab.set(DebugTab::ALL[i]);
+                layout.update();
+            }
+        })
+    };
+    let selected = |tab: DebugTab| layout.tab.get() == tab;
 
     let disas_page = ctx.create_control().parent(tab_page).build(ctx);
     disassembler_viewer::build(disas_page, ctx, event_table, style, cpu_id, ppu_id);
     let _disas_tab = ctx
-        .create_control()
+        .create_control_reserved(tab_buttons[0])
         .parent(tab_header)
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "disassembly".to_string(),
+                tr("debugger.disassembly").to_string(),
                 (0, 0),
                 style.text_style.clone(),
             ))
@@ -290,7 +470,7 @@ fn open_debug_panel(
         .behaviour(TabButton::new(
             tab_group.clone(),
             disas_page,
-            true,
+            selected(DebugTab::Disassembly),
             style.tab_style.clone(),
         ))
         .build(ctx);
@@ -298,11 +478,11 @@ fn open_debug_panel(
     let ppu_page = ctx.create_control().parent(tab_page).build(ctx);
     ppu_viewer::build(ppu_page, ctx, event_table, style, textures);
     let _ppu_tab = ctx
-        .create_control()
+        .create_control_reserved(tab_buttons[1])
         .parent(tab_header)
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "ppu".to_string(),
+                tr("debugger.ppu").to_string(),
                 (0, 0),
                 style.text_style.clone(),
             ))
@@ -310,15 +490,107 @@ fn open_debug_panel(
         })
         .layout(MarginLayout::default())
         .behaviour(TabButton::new(
-            tab_group,
+            tab_group.clone(),
             ppu_page,
-            false,
+            selected(DebugTab::Ppu),
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
+    let serial_page = ctx.create_control().parent(tab_page).build(ctx);
+    serial_viewer::build(serial_page, ctx, event_table, style);
+    let _serial_tab = ctx
+        .create_control_reserved(tab_buttons[2])
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("debugger.serial").to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group.clone(),
+            serial_page,
+            selected(DebugTab::Serial),
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
+    let states_page = ctx.create_control().parent(tab_page).build(ctx);
+    state_tree_viewer::build(states_page, ctx, event_table, style);
+    let _states_tab = ctx
+        .create_control_reserved(tab_buttons[3])
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("debugger.states").to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group.clone(),
+            states_page,
+            selected(DebugTab::States),
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
+    let audio_page = ctx.create_control().parent(tab_page).build(ctx);
+    audio_viewer::build(audio_page, ctx, event_table, style, textures);
+    let _audio_tab = ctx
+        .create_control_reserved(tab_buttons[4])
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("debugger.audio").to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group.clone(),
+            audio_page,
+            selected(DebugTab::Audio),
             style.tab_style.clone(),
         ))
         .build(ctx);
 
+    let cartridge_page = ctx.create_control().parent(tab_page).build(ctx);
+    cartridge_viewer::build(cartridge_page, ctx, event_table, style);
+    let _cartridge_tab = ctx
+        .create_control_reserved(tab_buttons[5])
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("debugger.cartridge").to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group,
+            cartridge_page,
+            selected(DebugTab::Cartridge),
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
+    layout.split_views.set(Some([split_view, h_box, v_split]));
+
     let proxy = ctx.get::<EventLoopProxy<UserEvent>>();
     proxy.send_event(UserEvent::Debug(true)).unwrap();
+
+    command_field
 }
 
 fn send_emu(ctx: &mut Context, event: EmulatorEvent) {
@@ -333,12 +605,13 @@ fn create_screen(
     screen_id: &mut Id,
     parent: Id,
     style: &Style,
+    event_table: &mut EventTable,
 ) {
     *screen_id = ctx.reserve();
     let screen = ctx.reserve();
     let mut layout = ScreenLayout::new((160, 144));
 
-    let gamepad = cfg!(target_os = "android");
+    let gamepad = cfg!(target_os = "android") || crate::config::config().gamepad_overlay;
     if gamepad {
         let scale_factor = ctx.scale_factor() as f32;
         let mut create_control = |graphic, [ax, ay]: [f32; 2], [x, y]: [f32; 2]| -> Id {
@@ -475,7 +748,10 @@ fn create_screen(
         ctx.create_control_reserved(*screen_id)
             .parent(parent)
             .graphic(style.background.clone())
-            .behaviour(game_pad::GamePad::new(joypad, other, sprites))
+            .behaviour(
+                game_pad::GamePad::new(joypad, other, sprites)
+                    .with_frame_tick(event_table.register(*screen_id)),
+            )
             .build(ctx);
     } else {
         ctx.create_control_reserved(*screen_id)
@@ -484,17 +760,32 @@ fn create_screen(
             .build(ctx);
     }
 
+    let texture = ctx.reserve();
     let _screen = ctx
         .create_control_reserved(screen)
         .parent(*screen_id)
         .layout(layout)
-        .child(ctx, |cb, _| {
-            cb.graphic(Texture::new(textures.screen, [0.0, 0.0, 1.0, 1.0]))
-        })
+        .build(ctx);
+    ctx.create_control_reserved(texture)
+        .parent(screen)
+        .graphic(Texture::new(textures.screen, [0.0, 0.0, 1.0, 1.0]))
         .build(ctx);
 
     ctx.move_to_back(screen);
 
+    // with the game pad, the screen is covered by it.
+    if !gamepad {
+        screen_view::build(*screen_id, texture, ctx, event_table, style);
+    }
+
+    if ctx.get::<InputLatency>().enabled() {
+        latency_overlay::build(*screen_id, ctx, event_table, style);
+    }
+    notification_overlay::build(*screen_id, ctx, event_table, style);
+    annotation_overlay::build(*screen_id, ctx, event_table, style);
+    save_indicator::build(*screen_id, ctx, event_table, style);
+    encoder_overlay::build(*screen_id, ctx, event_table, style);
+
     let menu = cfg!(target_os = "android");
     if menu {
         let _open_menu = ctx
@@ -527,19 +818,250 @@ fn open_menu(ctx: &mut Context, root: Id) {
     }
     send_emu(ctx, EmulatorEvent::Pause);
     let options = vec![
-        option("Save State", |ctx| send_emu(ctx, EmulatorEvent::SaveState)),
-        option("Load State", |ctx| send_emu(ctx, EmulatorEvent::LoadState)),
-        option("Reset", |ctx| send_emu(ctx, EmulatorEvent::Reset)),
-        option("Exit Game", |ctx| {
+        option(tr("menu.save_state"), |ctx| {
+            send_emu(ctx, EmulatorEvent::SaveState)
+        }),
+        option(tr("menu.load_state"), |ctx| {
+            send_emu(ctx, EmulatorEvent::LoadState)
+        }),
+        option(tr("menu.reset"), |ctx| {
+            send_emu(ctx, EmulatorEvent::Reset(ResetOptions::from_config(false)))
+        }),
+        option(tr("menu.power_cycle"), |ctx| {
+            send_emu(ctx, EmulatorEvent::Reset(ResetOptions::from_config(true)))
+        }),
+        option(tr("menu.exit_game"), |ctx| {
+            ctx.get::<EventLoopProxy<UserEvent>>()
+                .send_event(UserEvent::PopApp)
+                .unwrap();
+        }),
+    ];
+    let on_close = move |ctx: &mut Context| {
+        ctx.set_focus(root);
+        if !ctx.get::<crate::AppState>().paused {
+            send_emu(ctx, EmulatorEvent::Resume)
+        }
+    };
+    let menu = create_menu(options, on_close, ctx, &style);
+    ctx.set_focus(menu);
+}
+
+/// Open a menu listing every action available to the emulator, grouped by category.
+fn open_command_palette(ctx: &mut Context, panels: &Panels) {
+    let style = ctx.get::<Style>().clone();
+    fn option(a: &str, b: impl FnMut(&mut Context) + 'static) -> MenuOption {
+        (a, Box::new(b))
+    }
+    let app_state = ctx.get::<crate::AppState>();
+    let debug = app_state.debug;
+    let paused = app_state.paused;
+    let fast_forward = app_state.fast_forward;
+
+    // the debugger already controls the execution.
+    if !debug {
+        send_emu(ctx, EmulatorEvent::Pause);
+    }
+
+    let mut options = vec![
+        option(tr("palette.save_state"), |ctx| {
+            send_emu(ctx, EmulatorEvent::SaveState)
+        }),
+        option(tr("palette.load_state"), |ctx| {
+            send_emu(ctx, EmulatorEvent::LoadState)
+        }),
+        option(tr("palette.load_branch"), open_state_tree_menu),
+        option(tr("palette.rollback_save"), |ctx| {
+            send_emu(ctx, EmulatorEvent::RollbackRam)
+        }),
+        option(tr("palette.close_rom"), |ctx| {
             ctx.get::<EventLoopProxy<UserEvent>>()
                 .send_event(UserEvent::PopApp)
                 .unwrap();
         }),
+        option(tr("palette.reset"), |ctx| {
+            send_emu(ctx, EmulatorEvent::Reset(ResetOptions::from_config(false)))
+        }),
+        option(tr("palette.power_cycle"), |ctx| {
+            send_emu(ctx, EmulatorEvent::Reset(ResetOptions::from_config(true)))
+        }),
+        option(tr("palette.toggle_jit"), |ctx| {
+            send_emu(ctx, EmulatorEvent::SetJit(None))
+        }),
     ];
+    #[cfg(feature = "rfd")]
+    options.insert(
+        0,
+        option(tr("palette.open_rom"), crate::ui::open_rom_dialog),
+    );
+    #[cfg(all(feature = "rfd", not(target_arch = "wasm32")))]
+    if debug {
+        options.push(option(tr("palette.export_session"), session::export_dialog));
+        options.push(option(tr("palette.import_session"), session::import_dialog));
+    }
+    if !debug {
+        let (text, pause) = if paused {
+            (tr("palette.resume"), false)
+        } else {
+            (tr("palette.pause"), true)
+        };
+        options.push(option(text, move |ctx| {
+            ctx.get_mut::<crate::AppState>().paused = pause;
+        }));
+
+        let text = if fast_forward {
+            tr("palette.disable_fast_forward")
+        } else {
+            tr("palette.enable_fast_forward")
+        };
+        options.push(option(text, move |ctx| {
+            ctx.get_mut::<crate::AppState>().fast_forward = !fast_forward;
+            send_emu(ctx, EmulatorEvent::FrameLimit(fast_forward));
+        }));
+    }
+    let text = if debug {
+        tr("palette.close_debugger")
+    } else {
+        tr("palette.open_debugger")
+    };
+    let panels_ = panels.clone();
+    options.push(option(text, move |ctx| {
+        // Undo the pause, otherwise the emulator would ignore entering debug mode.
+        if !debug {
+            ctx.get_mut::<crate::AppState>().paused = false;
+            send_emu(ctx, EmulatorEvent::Resume);
+        }
+        panels_.toggle_debug(ctx);
+    }));
+
+    let root = panels.root;
     let on_close = move |ctx: &mut Context| {
         ctx.set_focus(root);
-        send_emu(ctx, EmulatorEvent::Resume)
+        let app_state = ctx.get::<crate::AppState>();
+        if !app_state.debug && !app_state.paused {
+            send_emu(ctx, EmulatorEvent::Resume)
+        }
     };
     let menu = create_menu(options, on_close, ctx, &style);
     ctx.set_focus(menu);
 }
+
+/// Open a menu listing the nodes of the state tree, for loading one of them.
+fn open_state_tree_menu(ctx: &mut Context) {
+    let style = ctx.get::<Style>().clone();
+    let tree = ctx.get::<StateTree>();
+    let items: Vec<(u32, String)> = tree
+        .walk()
+        .iter()
+        .map(|(depth, node)| {
+            let marker = if tree.current == Some(node.id) {
+                "> "
+            } else {
+                ""
+            };
+            let text = format!("{}{}{}", "  ".repeat(*depth), marker, node.label());
+            (node.id, text)
+        })
+        .collect();
+    if items.is_empty() {
+        log::warn!("there is no state in the state tree");
+        return;
+    }
+    let options = items
+        .iter()
+        .map(|(id, text)| {
+            let id = *id;
+            let load: Box<dyn FnMut(&mut Context)> =
+                Box::new(move |ctx| send_emu(ctx, EmulatorEvent::LoadBranch(id)));
+            (text.as_str(), load)
+        })
+        .collect();
+    let menu = create_menu(options, |_| {}, ctx, &style);
+    ctx.set_focus(menu);
+}
+
+/// Write the layout of the debug panel, and if the emulator is in debug mode, to disk, to be
+/// restored in the next session.
+pub fn save_debug_layout(ui: &mut Ui) {
+    let debug = ui.get::<crate::AppState>().debug;
+    ui.get::<DebugLayout>().update();
+    crate::debug_panel::update(|layout| layout.debug = debug);
+    crate::debug_panel::save();
+}
+
+/// Import the debugger session in the file at `path`, chosen in a file dialog.
+pub fn import_session(ui: &mut Ui, path: &std::path::Path) {
+    let result = {
+        let ctx = &mut ui.gui.get_context();
+        session::load(ctx, path)
+    };
+    let message = match result {
+        Ok(()) => tr("notification.session_imported").to_string(),
+        Err(err) => {
+            log::error!("error importing session: {}", err);
+            err
+        }
+    };
+    ui.notify(crate::event_table::Notification(message));
+}
+
+/// Show the problems found when validating the loaded ROM. The user can continue playing anyway.
+pub fn open_rom_warnings(ui: &mut Ui, warnings: &[String]) {
+    let style = ui.gui.get::<Style>().clone();
+    let ctx = &mut ui.gui.get_context();
+    fn option(a: &str, b: impl FnMut(&mut Context) + 'static) -> MenuOption {
+        (a, Box::new(b))
+    }
+
+    let mut options = vec![option(tr("rom_warning.title"), |_| {})];
+    for warning in warnings {
+        options.push(option(warning, |_| {}));
+    }
+    options.push(option(tr("rom_warning.continue"), |_| {}));
+    options.push(option(tr("menu.exit_game"), |ctx| {
+        ctx.get::<EventLoopProxy<UserEvent>>()
+            .send_event(UserEvent::PopApp)
+            .unwrap();
+    }));
+    let menu = create_menu(options, |_| {}, ctx, &style);
+    ctx.set_focus(menu);
+}
+
+/// Open a menu reporting that the emulator thread has crashed, offering to save the game data and
+/// to restart the emulator.
+pub fn open_crash_menu(ui: &mut Ui, message: &str, pc: u16, clock_count: u64) {
+    let style = ui.gui.get::<Style>().clone();
+    let ctx = &mut ui.gui.get_context();
+    fn option(a: &str, b: impl FnMut(&mut Context) + 'static) -> MenuOption {
+        (a, Box::new(b))
+    }
+
+    let title = format!(
+        "{} {} (pc {:04x}, clock {})",
+        tr("crash.title"),
+        message,
+        pc,
+        clock_count
+    );
+    let options = vec![
+        option(&title, |_| {}),
+        option(tr("crash.save"), |ctx| {
+            send_emu(ctx, EmulatorEvent::SaveRam);
+            send_emu(ctx, EmulatorEvent::SaveState);
+        }),
+        option(tr("crash.restart"), |ctx| {
+            let debug = ctx.get::<crate::AppState>().debug;
+            send_emu(ctx, EmulatorEvent::Restart);
+            send_emu(ctx, EmulatorEvent::Debug(debug));
+            if !debug {
+                send_emu(ctx, EmulatorEvent::RunFrame);
+            }
+        }),
+        option(tr("menu.exit_game"), |ctx| {
+            ctx.get::<EventLoopProxy<UserEvent>>()
+                .send_event(UserEvent::PopApp)
+                .unwrap();
+        }),
+    ];
+    let menu = create_menu(options, |_| {}, ctx, &style);
+    ctx.set_focus(menu);
+}
@@ -0,0 +1,40 @@
+use giui::{graphics::Text, layouts::FitGraphic, Behaviour, BuilderContext, Context, Id, RectFill};
+
+use crate::{
+    event_table::{Annotation, EventTable, Handle},
+    style::Style,
+};
+
+/// Show the annotation of the movie being played at the top of the screen, like a subtitle. See
+/// `rom_loading::MovieAnnotations`.
+struct AnnotationOverlay {
+    _annotation_event: Handle<Annotation>,
+}
+impl Behaviour for AnnotationOverlay {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if let Some(Annotation(text)) = event.downcast_ref::<Annotation>() {
+            ctx.get_graphic_mut(this).set_text(text);
+        }
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    // the screen is rebuilt when the debugger is opened or closed, so start with the current one.
+    let text = ctx.get::<crate::AppState>().annotation.clone();
+    let id = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .graphic(Text::new(text, (0, -1), style.terminal_text_style.clone()))
+        .layout(FitGraphic)
+        .behaviour(AnnotationOverlay {
+            _annotation_event: event_table.register(id),
+        })
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkStart)
+        .build(ctx);
+}
@@ -0,0 +1,142 @@
+use giui::{
+    graphics::{Text, Texture},
+    layouts::{FitGraphic, HBoxLayout, VBoxLayout},
+    Behaviour, BuilderContext, Context, Id,
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, FrameTick, Handle},
+    snapshot::Snapshot,
+    style::Style,
+    ui::Textures,
+    UserEvent,
+};
+
+/// The number of samples shown in the oscilloscope, one per column.
+pub const OSCILLOSCOPE_WIDTH: u32 = 256;
+/// The height of each waveform in the oscilloscope.
+const LANE_HEIGHT: u32 = 32;
+/// The four channels and the mix.
+const LANES: u32 = 5;
+pub const OSCILLOSCOPE_HEIGHT: u32 = LANE_HEIGHT * LANES;
+
+const LANE_NAMES: [&str; LANES as usize] = ["CH1", "CH2", "CH3", "CH4", "MIX"];
+const LANE_COLORS: [[u8; 3]; LANES as usize] = [
+    [255, 96, 96],
+    [96, 255, 96],
+    [96, 160, 255],
+    [255, 224, 96],
+    [255, 255, 255],
+];
+
+/// Find the first rising edge of the mix in the first half of the samples, so the waveform of a
+/// steady note don't move between frames.
+fn trigger_point(mix: &[u8]) -> usize {
+    let half = mix.len().saturating_sub(OSCILLOSCOPE_WIDTH as usize);
+    (1..half).find(|&i| mix[i - 1] < mix[i]).unwrap_or(half)
+}
+
+/// Plot the last waveform of each channel, and of their mix, in a RGBA image of
+/// `OSCILLOSCOPE_WIDTH`x`OSCILLOSCOPE_HEIGHT` pixels.
+pub fn render_oscilloscope(channels: &[[u8; 4]]) -> Vec<u8> {
+    let width = OSCILLOSCOPE_WIDTH as usize;
+    let mut image = vec![0; width * OSCILLOSCOPE_HEIGHT as usize * 4];
+    for (i, pixel) in image.chunks_exact_mut(4).enumerate() {
+        // separate the lanes with a lighter line.
+        let y = i / width;
+        let gray = if y % LANE_HEIGHT as usize == 0 {
+            48
+        } else {
+            16
+        };
+        pixel.copy_from_slice(&[gray, gray, gray, 255]);
+    }
+
+    let mix: Vec<u8> = channels.iter().map(|x| x.iter().sum()).collect();
+    let start = trigger_point(&mix);
+    let end = (start + width).min(channels.len());
+
+    for (x, i) in (start..end).enumerate() {
+        for lane in 0..LANES as usize {
+            // channels are in 0..=15, and the mix in 0..=60.
+            let (value, max) = match lane {
+                4 => (mix[i] as u32, 60),
+                _ => (channels[i][lane] as u32, 15),
+            };
+            let height = LANE_HEIGHT - 2;
+            let y = lane as u32 * LANE_HEIGHT + 1 + height - value * height / max;
+            let j = (y as usize * width + x) * 4;
+            image[j..j + 3].copy_from_slice(&LANE_COLORS[lane]);
+        }
+    }
+    image
+}
+
+/// Show the waveforms of the sound channels, updated each frame from the last snapshot.
+struct AudioViewer {
+    _frame_tick_event: Handle<FrameTick>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl Behaviour for AudioViewer {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if !(event.is::<FrameTick>() || event.is::<EmulatorUpdated>()) {
+            return;
+        }
+        let snapshot = ctx.get::<Snapshot>().get();
+        let image = render_oscilloscope(&snapshot.channels);
+        let texture = ctx.get::<Textures>().oscilloscope;
+        ctx.get::<EventLoopProxy<UserEvent>>()
+            .send_event(UserEvent::UpdateTexture(texture, image.into_boxed_slice()))
+            .unwrap();
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+    textures: &Textures,
+) {
+    let hbox = ctx
+        .create_control()
+        .parent(parent)
+        .layout(HBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+
+    let names = ctx
+        .create_control()
+        .parent(hbox)
+        .layout(VBoxLayout::new(0.0, [0.0; 4], -1))
+        .build(ctx);
+    for name in LANE_NAMES {
+        ctx.create_control()
+            .parent(names)
+            .graphic(Text::new(
+                name.to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+            .min_size([0.0, 2.0 * LANE_HEIGHT as f32])
+            .build(ctx);
+    }
+
+    let view = ctx.reserve();
+    ctx.create_control_reserved(view)
+        .parent(hbox)
+        .graphic(Texture::new(textures.oscilloscope, [0.0, 0.0, 1.0, 1.0]))
+        .min_size([
+            2.0 * OSCILLOSCOPE_WIDTH as f32,
+            2.0 * OSCILLOSCOPE_HEIGHT as f32,
+        ])
+        .expand_x(true)
+        .fill_x(giui::RectFill::ShrinkStart)
+        .fill_y(giui::RectFill::ShrinkStart)
+        .behaviour(AudioViewer {
+            _frame_tick_event: event_table.register(view),
+            _emulator_updated_event: event_table.register(view),
+        })
+        .build(ctx);
+}
@@ -0,0 +1,239 @@
+use std::{cell::Cell, fmt::Write, rc::Rc, sync::Arc};
+
+use gameroy::gameboy::{cartridge::Cartridge, GameBoy};
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, VBoxLayout},
+    text::Text,
+    widgets::Button,
+    Behaviour, BuilderContext, Context, Id,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, FrameTick, Handle},
+    localization::tr,
+    style::Style,
+    ui::scroll_viewer,
+};
+
+/// Sent to the viewer when the browsed bank changes.
+struct BankChanged;
+
+/// Show the registers of the MBC, and the content of a RAM bank of the cartridge.
+struct CartridgeViewer {
+    state: Id,
+    bank_label: Id,
+    dump: Id,
+    /// The RAM bank being shown, or None to show the bank mapped in A000..=BFFF.
+    bank: Rc<Cell<Option<usize>>>,
+    /// The bank and the content last shown in the dump, to only rebuild its text when they change.
+    shown: Option<(usize, Vec<u8>)>,
+    _frame_tick_event: Handle<FrameTick>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl CartridgeViewer {
+    fn update(&mut self, ctx: &mut Context) {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
+        let gb = gb.lock();
+        let cartridge = &gb.cartridge;
+        let state = cartridge.mbc_state();
+
+        ctx.get_graphic_mut(self.state)
+            .set_text(&describe_mbc(cartridge));
+
+        let num_banks = cartridge.num_ram_banks();
+        if num_banks == 0 {
+            ctx.get_graphic_mut(self.bank_label)
+                .set_text(tr("cartridge_viewer.no_ram"));
+            if self.shown.take().is_some() {
+                ctx.get_graphic_mut(self.dump).set_text("");
+            }
+            return;
+        }
+
+        let bank = match self.bank.get() {
+            Some(bank) => bank.min(num_banks - 1),
+            None => state.ram_bank.unwrap_or(0),
+        };
+        let mapped = if state.ram_bank == Some(bank) {
+            " (A000-BFFF)"
+        } else {
+            ""
+        };
+        ctx.get_graphic_mut(self.bank_label)
+            .set_text(&format!("bank {:02x}/{:02x}{}", bank, num_banks, mapped));
+
+        let content = cartridge.ram_bank(bank).unwrap_or_default();
+        if matches!(&self.shown, Some((x, y)) if *x == bank && y == content) {
+            return;
+        }
+        ctx.get_graphic_mut(self.dump).set_text(&hex_dump(content));
+        self.shown = Some((bank, content.to_vec()));
+    }
+}
+impl Behaviour for CartridgeViewer {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if event.is::<FrameTick>() || event.is::<EmulatorUpdated>() || event.is::<BankChanged>() {
+            self.update(ctx);
+        }
+    }
+}
+
+fn describe_mbc(cartridge: &Cartridge) -> String {
+    let state = cartridge.mbc_state();
+    let mut text = format!("MBC: {}\n", cartridge.kind_name());
+    let _ = writeln!(
+        text,
+        "ROM banks: {:02x}/{:02x} of {:02x}",
+        state.lower_rom_bank,
+        state.upper_rom_bank,
+        cartridge.num_banks()
+    );
+    match state.ram_bank {
+        Some(bank) => {
+            let _ = write!(text, "RAM bank: {:02x}", bank);
+        }
+        None => text.push_str("RAM bank: -"),
+    }
+    let enabled = if state.ram_enabled {
+        "enabled"
+    } else {
+        "disabled"
+    };
+    let _ = write!(text, ", {}", enabled);
+    if let Some(mode) = state.banking_mode {
+        let _ = write!(text, "\nbanking mode: {}", mode);
+    }
+    if let Some(rtc) = state.rtc {
+        let [s, m, h, dl, dh] = rtc.registers;
+        let _ = write!(
+            text,
+            "\nRTC: {:02x}:{:02x}:{:02x}, DL={:02x} DH={:02x}",
+            h, m, s, dl, dh
+        );
+        if let Some(register) = rtc.selected {
+            let _ = write!(text, "\nRTC register {:02x} mapped", register);
+        }
+        if rtc.latch_armed {
+            text.push_str("\nlatch armed");
+        }
+    }
+    text
+}
+
+/// Format the bank as lines of 16 bytes, prefixed with their address in A000..=BFFF.
+fn hex_dump(bank: &[u8]) -> String {
+    let mut text = String::with_capacity(bank.len() * 4);
+    for (i, line) in bank.chunks(16).enumerate() {
+        let _ = write!(text, "{:04x}:", 0xA000 + i * 16);
+        for x in line {
+            let _ = write!(text, " {:02x}", x);
+        }
+        text.push('\n');
+    }
+    text.pop();
+    text
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let vbox = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(2.0, [2.0; 4], -1))
+        .build(ctx);
+
+    let viewer = ctx.reserve();
+    let state = ctx.reserve();
+    let bank_label = ctx.reserve();
+    let dump = ctx.reserve();
+    let bank = Rc::new(Cell::new(None));
+
+    ctx.create_control_reserved(state)
+        .parent(vbox)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let hbox = ctx
+        .create_control()
+        .parent(vbox)
+        .layout(HBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+    // the button selects the bank returned by `select`, given the bank being shown.
+    let mut bank_button = |label: &str, select: fn(usize) -> Option<usize>| {
+        let bank = bank.clone();
+        ctx.create_control()
+            .parent(hbox)
+            .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+            .behaviour(Button::new(
+                style.delete_button.clone(),
+                true,
+                move |_, ctx| {
+                    {
+                        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+                        let cartridge = &gb.cartridge;
+                        let last = cartridge.num_ram_banks().saturating_sub(1);
+                        let shown = bank
+                            .get()
+                            .unwrap_or_else(|| cartridge.mbc_state().ram_bank.unwrap_or(0));
+                        bank.set(select(shown.min(last)).map(|x| x.min(last)));
+                    }
+                    ctx.send_event_to(viewer, BankChanged);
+                },
+            ))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(
+                    label.to_string(),
+                    (-1, 0),
+                    style.text_style.clone(),
+                ))
+                .layout(FitGraphic)
+            })
+            .build(ctx);
+    };
+    bank_button(tr("cartridge_viewer.previous_bank"), |x| {
+        Some(x.saturating_sub(1))
+    });
+    bank_button(tr("cartridge_viewer.next_bank"), |x| Some(x + 1));
+    bank_button(tr("cartridge_viewer.mapped_bank"), |_| None);
+    ctx.create_control_reserved(bank_label)
+        .parent(hbox)
+        .graphic(Text::new(String::new(), (-1, 0), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let scroll = ctx.reserve();
+    let content = ctx.reserve();
+    scroll_viewer(ctx, scroll, content, style, (true, true))
+        .parent(vbox)
+        .graphic(style.terminal_background.clone())
+        .expand_y(true)
+        .build(ctx);
+    ctx.create_control_reserved(dump)
+        .parent(content)
+        .graphic(Text::new(
+            String::new(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    ctx.create_control_reserved(viewer)
+        .parent(vbox)
+        .behaviour(CartridgeViewer {
+            state,
+            bank_label,
+            dump,
+            bank,
+            shown: None,
+            _frame_tick_event: event_table.register(viewer),
+            _emulator_updated_event: event_table.register(viewer),
+        })
+        .build(ctx);
+}
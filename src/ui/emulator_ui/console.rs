@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use gameroy::debugger::Debugger;
+use giui::{
+    graphics::Text, layouts::FitGraphic, widgets::SetScrollPosition, Behaviour, BuilderContext,
+    Context, Id,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, FrameTick, Handle},
+    style::Style,
+    ui::scroll_viewer,
+    SerialOutput,
+};
+
+/// The maximum number of bytes of text kept in the console. The oldest lines are dropped.
+const CONSOLE_CAPACITY: usize = 0x10000;
+
+/// Sent to the console text, to append the text to it.
+struct AppendLog(String);
+
+/// Append the text to the end of the console, and scroll to the bottom.
+pub fn append_log(ctx: &mut Context, scroll: Id, log: Id, text: &str) {
+    ctx.send_event_to(log, AppendLog(text.to_string()));
+    // scroll to bottom
+    ctx.send_event_to(
+        scroll,
+        SetScrollPosition {
+            vertical: true,
+            value: 1.0,
+        },
+    );
+}
+
+/// Show the messages printed by tracepoints and the bytes sent through the serial port, in
+/// addition to the commands and its errors.
+struct Console {
+    scroll: Id,
+    log: Id,
+    /// The text shown in the console, up to `CONSOLE_CAPACITY` bytes.
+    text: String,
+    /// How many bytes of the serial output were already shown.
+    serial_read: usize,
+    _frame_tick_event: Handle<FrameTick>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl Console {
+    /// Append the text, dropping the oldest lines if it goes over `CONSOLE_CAPACITY`.
+    fn append(&mut self, text: &str, this: Id, ctx: &mut Context) {
+        self.text += text;
+        if self.text.len() > CONSOLE_CAPACITY {
+            let mut start = self.text.len() - CONSOLE_CAPACITY;
+            while !self.text.is_char_boundary(start) {
+                start += 1;
+            }
+            // drop whole lines, if there is a line break left.
+            if let Some(i) = self.text[start..].find('\n') {
+                start += i + 1;
+            }
+            self.text.drain(..start);
+        }
+        ctx.get_graphic_mut(this).set_text(&self.text);
+    }
+}
+impl Behaviour for Console {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if let Some(AppendLog(text)) = event.downcast_ref::<AppendLog>() {
+            self.append(text, this, ctx);
+            return;
+        }
+        if !(event.is::<FrameTick>() || event.is::<EmulatorUpdated>()) {
+            return;
+        }
+        let mut text = String::new();
+        for message in ctx.get::<Arc<Mutex<Debugger>>>().lock().take_trace_log() {
+            text += &message;
+            text += "\n";
+        }
+        {
+            let serial = ctx.get::<SerialOutput>().0.lock();
+            text.extend(serial[self.serial_read..].iter().map(|&c| c as char));
+            self.serial_read = serial.len();
+        }
+        if !text.is_empty() {
+            append_log(ctx, self.scroll, self.log, &text);
+        }
+    }
+}
+
+/// Build the console. Returns the ids of its scroll view and of its text.
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) -> (Id, Id) {
+    let scroll = ctx.reserve();
+    let content = ctx.reserve();
+    let log = ctx.reserve();
+    scroll_viewer(ctx, scroll, content, style, (true, true))
+        .parent(parent)
+        .graphic(style.terminal_background.clone())
+        .build(ctx);
+    ctx.create_control_reserved(log)
+        .graphic(Text::new(
+            String::new(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(Console {
+            scroll,
+            log,
+            text: String::new(),
+            serial_read: 0,
+            _frame_tick_event: event_table.register(log),
+            _emulator_updated_event: event_table.register(log),
+        })
+        .parent(content)
+        .build(ctx);
+
+    (scroll, log)
+}
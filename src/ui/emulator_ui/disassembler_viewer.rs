@@ -3,7 +3,7 @@ use std::{any::Any, ops::Range, sync::Arc};
 use gameroy::{
     debugger::{break_flags, Debugger},
     disassembler::{Address, Directive},
-    gameboy::GameBoy,
+    gameboy::{memory_map::Region, GameBoy},
 };
 use giui::{
     event::SetValue,
@@ -11,8 +11,7 @@ use giui::{
     layouts::{FitGraphic, HBoxLayout},
     text::{Span, TextStyle},
     widgets::{
-        Button, FocusItem, InteractiveText, ListBuilder, SetScrollPosition, TextField,
-        TextFieldCallback, UpdateItems,
+        Button, FocusItem, InteractiveText, ListBuilder, TextField, TextFieldCallback, UpdateItems,
     },
     BuilderContext, Color, Context, ControlBuilder, Id, MouseEvent, MouseInfo,
 };
@@ -20,7 +19,12 @@ use parking_lot::Mutex;
 use winit::event::VirtualKeyCode;
 
 use crate::{
-    event_table::{self, BreakpointsUpdated, EmulatorUpdated, EventTable, Handle, WatchsUpdated},
+    event_table::{
+        self, BreakpointsUpdated, DisassemblyNavigation, EmulatorUpdated, EventTable, Handle,
+        LabelsUpdated, WatchsUpdated,
+    },
+    localization::tr,
+    snapshot::Snapshot,
     style::Style,
     ui,
     widget::fold_view,
@@ -32,6 +36,8 @@ struct Callback {
     /// A list of past submitted texts, that allow to be reused by pressing `UpArrow`.
     history: Vec<String>,
     curr: usize,
+    /// The current text in the text field, used for tab completion.
+    text: String,
 }
 impl Callback {
     fn new(log_scroll: Id, log: Id) -> Self {
@@ -40,39 +46,128 @@ impl Callback {
             log,
             history: Vec::new(),
             curr: 0,
+            text: String::new(),
+        }
+    }
+
+    /// Complete the last word of the text with the name of a label. If there is more than one
+    /// candidate, complete their common prefix, and list them in the console.
+    fn complete(&mut self, this: Id, ctx: &mut Context) {
+        let start = self.text.rfind(' ').map_or(0, |x| x + 1);
+        let prefix = &self.text[start..];
+        let candidates: Vec<String> = {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            let trace = gb.trace.borrow();
+            let mut names: Vec<String> = debugger
+                .labels()
+                .values()
+                .chain(trace.labels.values().map(|x| &x.name))
+                .filter(|x| x.starts_with(prefix))
+                .cloned()
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common = candidates.iter().fold(first.as_str(), |common, x| {
+            let len = common
+                .char_indices()
+                .zip(x.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(x.len()), |((i, _), _)| i);
+            &common[..len]
+        });
+        let mut text = self.text[..start].to_string() + common;
+        if candidates.len() == 1 {
+            text.push(' ');
+        } else {
+            let list = candidates.join(" ") + "\n";
+            super::console::append_log(ctx, self.log_scroll, self.log, &list);
         }
+        self.text = text.clone();
+        ctx.send_event_to(this, SetValue(text));
     }
 }
+/// Handle the `jit [on|off]` command: run the game with the JIT compiler or the interpreter, or
+/// switch between them.
+fn jit_command(sender: &flume::Sender<crate::EmulatorEvent>, args: &[&str]) -> Result<(), String> {
+    let enable = match args[1..] {
+        [] => None,
+        ["on"] => Some(true),
+        ["off"] => Some(false),
+        _ => return Err("'jit' expect 'on', 'off' or nothing".to_string()),
+    };
+    sender.send(crate::EmulatorEvent::SetJit(enable)).unwrap();
+    Ok(())
+}
+
 impl TextFieldCallback for Callback {
     fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
-        let mut args: Vec<&str> = text.split_ascii_whitespace().collect();
-        {
+        super::console::append_log(ctx, self.log_scroll, self.log, &format!("> {}\n", text));
+        let mut args: Vec<String> = text.split_ascii_whitespace().map(String::from).collect();
+        let result = if args.first().map(String::as_str) == Some("session") {
+            // it locks the GameBoy and the debugger by itself.
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            super::session::command(ctx, &args)
+        } else {
             let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
             let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
             if args.is_empty() {
-                args.push("");
+                args.push(String::new());
             }
 
-            match debugger.execute_command(&gb, &args) {
-                Ok(_) => {}
-                Err(m) => {
-                    drop((gb, debugger));
-                    eprintln!("{}", m);
-                    let fonts = ctx.get_fonts();
-                    if let (rect, Graphic::Text(x)) = ctx.get_rect_and_graphic(self.log) {
-                        let text_layout = &mut x.get_layout(fonts, rect);
-                        text_layout.append(&(m + "\n"), fonts);
-                    };
-                    // scroll to bottom
-                    ctx.send_event_to(
-                        self.log_scroll,
-                        SetScrollPosition {
-                            vertical: true,
-                            value: 1.0,
-                        },
-                    );
+            // labels can be used in place of addresses, but not in the name of a label, or in the
+            // text of a comment or annotation.
+            {
+                let trace = gb.trace.borrow();
+                let len = match args[0].as_str() {
+                    "label" | "comment" => 1,
+                    "annotate" => 0,
+                    _ => args.len(),
+                };
+                for arg in args.iter_mut().skip(1).take(len) {
+                    if let Some((&address, _)) =
+                        debugger.labels().iter().find(|(_, name)| **name == *arg)
+                    {
+                        *arg = format!("{:04x}", address);
+                    } else if let Some(label) = trace.labels.values().find(|x| x.name == *arg) {
+                        *arg = format!("{:04x}", label.address.to_pc());
+                    }
+                }
+            }
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+            // commands that need the frontend are handled here, the rest by the debugger.
+            match args[0] {
+                "dumptiles" => super::ppu_viewer::dump_tiles(&gb, &args),
+                "branch" | "loadbranch" | "namebranch" => {
+                    let sender = ctx.get::<flume::Sender<crate::EmulatorEvent>>();
+                    super::state_tree_viewer::command(sender, &args)
+                }
+                "jit" => jit_command(ctx.get::<flume::Sender<crate::EmulatorEvent>>(), &args),
+                "annotate" | "unannotate" => {
+                    let text = (args[0] == "annotate").then(|| args[1..].join(" "));
+                    ctx.get::<flume::Sender<crate::EmulatorEvent>>()
+                        .send(crate::EmulatorEvent::Annotate(text))
+                        .unwrap();
+                    Ok(())
                 }
+                _ => debugger.execute_command(&gb, &args),
             }
+        };
+        if let Err(m) = result {
+            eprintln!("{}", m);
+            super::console::append_log(ctx, self.log_scroll, self.log, &(m + "\n"));
+        }
+        // show the output of commands like `stats` now, even if the emulation is paused.
+        let output = ctx.get::<Arc<Mutex<Debugger>>>().lock().take_trace_log();
+        if !output.is_empty() {
+            let output = output.join("\n") + "\n";
+            super::console::append_log(ctx, self.log_scroll, self.log, &output);
         }
         if !text.trim().is_empty() {
             // don't add to history if it is the same text again and again
@@ -83,9 +178,12 @@ impl TextFieldCallback for Callback {
         self.curr = self.history.len();
 
         text.clear();
+        self.text.clear();
     }
 
-    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, text: &str) {
+        self.text = text.to_string();
+    }
 
     fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
 
@@ -103,6 +201,7 @@ impl TextFieldCallback for Callback {
                     Some(x) => x.clone(),
                     None => String::new(),
                 };
+                self.text = text.clone();
                 ctx.send_event_to(this, SetValue(text));
                 true
             }
@@ -112,11 +211,18 @@ impl TextFieldCallback for Callback {
                     Some(x) => x.clone(),
                     None => String::new(),
                 };
+                self.text = text.clone();
                 ctx.send_event_to(this, SetValue(text));
                 true
             }
+            // Ctrl+Tab moves the focus out of the command field.
+            Pressed(VirtualKeyCode::Tab) if !ctx.get::<crate::AppState>().ctrl => {
+                self.complete(this, ctx);
+                true
+            }
             Pressed(VirtualKeyCode::Escape) => {
                 self.curr = self.history.len();
+                self.text.clear();
                 ctx.send_event_to(this, SetValue(String::new()));
                 true
             }
@@ -125,8 +231,10 @@ impl TextFieldCallback for Callback {
     }
 }
 
+/// Sent when a jump target in the disassembly is clicked.
 struct JumpToAddress {
     from_address: Address,
+    to_address: Address,
 }
 
 struct DissasemblerList {
@@ -134,41 +242,90 @@ struct DissasemblerList {
     cpu: Id,
     ppu: Id,
     pc: Option<Address>,
+    /// The ROM banks mapped in the last update, for resolving the targets of jumps to the upper
+    /// bank from the lower bank.
+    banks: (u16, u16),
     directives: Vec<Directive>,
     items_are_dirty: bool,
+    /// The addresses navigated away from, by clicking a jump target, or by going forward.
+    back_history: Vec<Address>,
+    /// The addresses navigated away from by going back.
+    forward_history: Vec<Address>,
+    /// The address navigated to last, if any.
+    location: Option<Address>,
     _emulator_updated_event: Handle<EmulatorUpdated>,
+    _navigation_event: Handle<DisassemblyNavigation>,
+    _labels_updated_event: Handle<LabelsUpdated>,
 }
 impl DissasemblerList {
+    /// Scroll the list to the directive at `address`, if it was disassembled.
+    fn focus(&self, address: Address, ctx: &mut Context) -> bool {
+        let Ok(pos) = self
+            .directives
+            .binary_search_by(|x| x.address.cmp(&address))
+        else {
+            return false;
+        };
+        ctx.send_event_to(
+            self.list,
+            FocusItem {
+                index: pos,
+                margin: 30.0,
+            },
+        );
+        true
+    }
+
+    /// The address a jump of the directive at `from` to `pc` goes to.
+    fn jump_target(&self, from: Address, pc: u16) -> Option<Address> {
+        // code in a switchable bank usually jumps inside its own bank.
+        let upper = if from.bank != 0 {
+            from.bank
+        } else {
+            self.banks.1
+        };
+        Address::from_pc((0, upper), pc)
+    }
+
     fn graphic(
         &mut self,
         style: TextStyle,
         direc: Directive,
         trace: std::cell::Ref<gameroy::disassembler::Trace>,
+        debugger: &Debugger,
         pc: Option<Address>,
-    ) -> (Graphic, Option<Range<usize>>) {
+    ) -> (Graphic, Option<Range<usize>>, Option<(Range<usize>, u16)>) {
         let curr = direc.address;
+        let cpu_address = {
+            let mut address = curr.address;
+            if address < 0x4000 && curr.bank != 0 {
+                address += 0x4000;
+            }
+            address
+        };
+        // the labels of the user take the place of the generated ones.
         let mut text = format!(
             "{:04x} {:16} ",
-            {
-                let mut address = curr.address;
-                if address < 0x4000 && curr.bank != 0 {
-                    address += 0x4000;
-                }
-                address
-            },
-            trace
-                .labels
-                .get(&curr)
-                .map(|x| x.name.as_str())
+            cpu_address,
+            debugger
+                .labels()
+                .get(&cpu_address)
+                .map(String::as_str)
+                .or_else(|| trace.labels.get(&curr).map(|x| x.name.as_str()))
                 .unwrap_or("")
         );
+        let operand = std::cell::Cell::new(None);
         let label = |pc, x| {
             if let Some(address) = trace.jumps.get(&pc) {
-                let mut name = trace.labels.get(address).unwrap().name.clone();
+                let mut name = match debugger.labels().get(&address.to_pc()) {
+                    Some(name) => name.clone(),
+                    None => trace.labels.get(address).unwrap().name.clone(),
+                };
                 name.insert_str(0, "<l>");
                 name += "</l>";
                 return name;
             }
+            operand.set(Some(x));
             format!("<a>${:04x}</a>", x)
         };
         gameroy::disassembler::disassembly_opcode(
@@ -195,6 +352,11 @@ impl DissasemblerList {
             None
         };
         let op_len = text[22..].find(' ').unwrap();
+        let comment_start = text.len();
+        if let Some(comment) = debugger.comments().get(&cpu_address) {
+            text += "  ; ";
+            text += comment;
+        }
 
         let mut text = Text::new(text, (-1, 0), style);
 
@@ -209,7 +371,12 @@ impl DissasemblerList {
             .as_ref()
             .map(|r| text.add_span(r.clone(), Span::Color(label)));
         text.add_span(22..22 + op_len, Span::Color(op));
-        address_range.map(|r| text.add_span(r, Span::Color(number)));
+        address_range
+            .as_ref()
+            .map(|r| text.add_span(r.clone(), Span::Color(number)));
+        if comment_start < text.len() {
+            text.add_span(comment_start..text.len(), Span::Color(address));
+        }
         if Some(curr) == pc {
             text.add_span(
                 0..text.len(),
@@ -219,14 +386,14 @@ impl DissasemblerList {
                 },
             );
         }
-        (text.into(), label_range)
+        let address_range = address_range.zip(operand.get());
+        (text.into(), label_range, address_range)
     }
 }
 impl ListBuilder for DissasemblerList {
     fn on_event(&mut self, event: Box<dyn Any>, _this: Id, ctx: &mut Context) {
         if event.is::<EmulatorUpdated>() {
-            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
-            let gb = gb.lock();
+            let snapshot = ctx.get::<Snapshot>().get();
 
             fn decimal_mark(n: u64) -> String {
                 let s = n.to_string();
@@ -242,7 +409,7 @@ impl ListBuilder for DissasemblerList {
                 result
             }
 
-            let cpu = &gb.cpu;
+            let cpu = &snapshot.cpu;
 
             let flags = format!(
                 "{} {} {} {}",
@@ -261,7 +428,7 @@ impl ListBuilder for DissasemblerList {
  SP: {:04x}
  PC: {:04x}
  DIV:{:04x}",
-                decimal_mark(gb.clock_count),
+                decimal_mark(snapshot.clock_count),
                 cpu.a,
                 cpu.f.0,
                 flags,
@@ -273,14 +440,14 @@ impl ListBuilder for DissasemblerList {
                 cpu.l,
                 cpu.sp,
                 cpu.pc,
-                gb.timer.borrow().div,
+                snapshot.div,
             );
 
             if let Graphic::Text(text) = ctx.get_graphic_mut(self.cpu) {
                 text.set_string(&cpu_text);
             }
 
-            let ppu = gb.ppu.borrow();
+            let ppu = &snapshot.ppu;
             let ppu_text = format!(
                 " LCDC:{:02x}
  STAT:{:02x}
@@ -303,7 +470,7 @@ impl ListBuilder for DissasemblerList {
                 ppu.scx,
                 ppu.lyc,
                 ppu.ly,
-                (gb.clock_count - ppu.line_start_clock_count) % 456,
+                ppu.lx,
                 ppu.bgp,
                 ppu.obp0,
                 ppu.obp1,
@@ -318,15 +485,20 @@ impl ListBuilder for DissasemblerList {
                 text.set_string(&ppu_text);
             }
 
-            let trace = gb.trace.borrow();
+            // the disassembly is not part of the snapshot, because it is too big to be copied.
+            {
+                let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+                let trace = gb.trace.borrow();
 
-            self.items_are_dirty = true;
-            self.directives.clear();
-            self.directives.extend(trace.directives.iter().cloned());
-            debug_assert!(self.directives.windows(2).all(|x| x[0] <= x[1]));
+                self.items_are_dirty = true;
+                self.directives.clear();
+                self.directives.extend(trace.directives.iter().cloned());
+                debug_assert!(self.directives.windows(2).all(|x| x[0] <= x[1]));
+            }
 
             let pc = cpu.pc;
-            let bank = gb.cartridge.curr_bank();
+            let bank = snapshot.bank;
+            self.banks = bank;
             self.pc = Some(Address::from_pc(bank, pc).unwrap_or(Address {
                 address: pc,
                 bank: 0xFF,
@@ -343,21 +515,32 @@ impl ListBuilder for DissasemblerList {
                     },
                 );
             };
-        } else if let Some(JumpToAddress { from_address }) = event.downcast_ref::<JumpToAddress>() {
-            let mut gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
-            let trace = gb.trace.get_mut();
-            let jump_to = trace.jumps.get(from_address).unwrap();
-            let pos = self.directives.binary_search_by(|x| x.address.cmp(jump_to));
-            drop(gb);
-            if let Ok(pos) = pos {
-                ctx.send_event_to(
-                    self.list,
-                    FocusItem {
-                        index: pos,
-                        margin: 30.0,
-                    },
-                );
+        } else if let Some(&JumpToAddress {
+            from_address,
+            to_address,
+        }) = event.downcast_ref::<JumpToAddress>()
+        {
+            if self.focus(to_address, ctx) {
+                self.back_history.push(from_address);
+                self.forward_history.clear();
+                self.location = Some(to_address);
+            }
+        } else if let Some(&navigation) = event.downcast_ref::<DisassemblyNavigation>() {
+            let (from, to) = match navigation {
+                DisassemblyNavigation::Back => (&mut self.back_history, &mut self.forward_history),
+                DisassemblyNavigation::Forward => {
+                    (&mut self.forward_history, &mut self.back_history)
+                }
+            };
+            let Some(address) = from.pop() else {
+                return;
             };
+            to.extend(self.location);
+            self.location = Some(address);
+            self.focus(address, ctx);
+        } else if event.is::<LabelsUpdated>() {
+            self.items_are_dirty = true;
+            ctx.send_event_to(self.list, UpdateItems);
         }
     }
 
@@ -374,43 +557,60 @@ impl ListBuilder for DissasemblerList {
     ) -> giui::ControlBuilder {
         cb.min_size([0.0, 15.0]).child(ctx, |cb, ctx| {
             let inter = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
 
             let trace = inter.trace.borrow();
             let directive = self.directives[index].clone();
+            let from_address = directive.address;
+            let label_target = trace.jumps.get(&from_address).copied();
             let style = ctx.get::<Style>().text_style.clone();
-            let (graphic, label_range) = self.graphic(style, directive.clone(), trace, self.pc);
+            let (graphic, label_range, address_range) =
+                self.graphic(style, directive, trace, &debugger, self.pc);
             let cb = cb.graphic(graphic).layout(FitGraphic);
-            let mut span = 0;
-            if let Some(label_range) = label_range {
-                cb.behaviour(InteractiveText::new(vec![(
-                    label_range.clone(),
-                    Box::new(move |mouse: MouseInfo, this: Id, ctx: &mut Context| {
-                        let text = match ctx.get_graphic_mut(this) {
-                            Graphic::Text(x) => x,
-                            _ => return,
-                        };
-                        match mouse.event {
-                            MouseEvent::Enter => {
-                                let label = 0x2e8bb2ff.into();
-                                span = text
-                                    .add_span(label_range.clone(), Span::Underline(Some(label)));
-                            }
-                            MouseEvent::Exit => {
-                                text.remove_span(span);
-                            }
-                            _ if mouse.click() => ctx.send_event_to(
-                                _list_id,
-                                JumpToAddress {
-                                    from_address: dbg!(directive.address),
-                                },
-                            ),
-                            _ => {}
+
+            // the jump targets, and the addresses in the operands, can be clicked to navigate to.
+            let targets: Vec<(Range<usize>, Address)> = label_range
+                .zip(label_target)
+                .into_iter()
+                .chain(
+                    address_range
+                        .and_then(|(range, pc)| Some((range, self.jump_target(from_address, pc)?))),
+                )
+                .collect();
+            if targets.is_empty() {
+                return cb;
+            }
+            let list_id = self.list;
+            let mut callbacks = Vec::new();
+            for (range, to_address) in targets {
+                let mut span = 0;
+                let underline = range.clone();
+                let callback = move |mouse: MouseInfo, this: Id, ctx: &mut Context| {
+                    let text = match ctx.get_graphic_mut(this) {
+                        Graphic::Text(x) => x,
+                        _ => return,
+                    };
+                    match mouse.event {
+                        MouseEvent::Enter => {
+                            let label = 0x2e8bb2ff.into();
+                            span = text.add_span(underline.clone(), Span::Underline(Some(label)));
+                        }
+                        MouseEvent::Exit => {
+                            text.remove_span(span);
                         }
-                    }),
-                )]))
-            } else {
-                cb
+                        _ if mouse.click() => ctx.send_event_to(
+                            list_id,
+                            JumpToAddress {
+                                from_address,
+                                to_address,
+                            },
+                        ),
+                        _ => {}
+                    }
+                };
+                callbacks.push((range, Box::new(callback) as Box<_>));
             }
+            cb.behaviour(InteractiveText::new(callbacks))
         })
     }
 
@@ -495,8 +695,14 @@ impl WatchsList {
             .iter()
             .nth(index)
             .unwrap();
-        let value = ctx.get::<Arc<Mutex<GameBoy>>>().lock().read(address);
-        let text = format!("{:04x} = {:02x}", address, value);
+        let snapshot = ctx.get::<Snapshot>().clone();
+        let value = snapshot.get().read(address).unwrap_or_else(|| {
+            // the watch was added after the last snapshot.
+            snapshot.request_window(address..address.saturating_add(1));
+            ctx.get::<Arc<Mutex<GameBoy>>>().lock().peek(address)
+        });
+        let region = Region::of(address, snapshot.get().bank);
+        let text = format!("{:04x} = {:02x} ({})", address, value, region);
         (address, text)
     }
 }
@@ -535,6 +741,112 @@ impl ListBuilder for WatchsList {
     }
 }
 
+/// The watch expressions, re-evaluated on each update of the emulator. The values that changed
+/// since the last update are highlighted.
+struct WatchExpressionList {
+    /// The values of the expressions in the last update.
+    values: Vec<Option<u16>>,
+    _watchs_updated_event: Handle<WatchsUpdated>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl WatchExpressionList {
+    fn evaluate(ctx: &mut dyn BuilderContext, index: usize) -> (String, Option<u16>) {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+        let (text, expr) = &debugger.watch_expressions()[index];
+        match expr.eval(&gb) {
+            Ok(value) if expr.is_8bit() => (format!("{} = {:02x}", text, value), Some(value)),
+            Ok(value) => (format!("{} = {:04x}", text, value), Some(value)),
+            Err(err) => (format!("{}: {}", text, err), None),
+        }
+    }
+}
+impl ListBuilder for WatchExpressionList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<event_table::WatchsUpdated>() {
+            // the indices may have changed.
+            self.values.clear();
+        }
+        if event.is::<event_table::WatchsUpdated>() || event.is::<event_table::EmulatorUpdated>() {
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, ctx: &mut dyn BuilderContext) -> usize {
+        let len = ctx
+            .get::<Arc<Mutex<Debugger>>>()
+            .lock()
+            .watch_expressions()
+            .len();
+        self.values.resize(len, None);
+        len
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let (text, value) = Self::evaluate(ctx, index);
+        self.values[index] = value;
+        list_item(ctx, cb, text, move |_: Id, ctx| {
+            let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            if index < debugger.watch_expressions().len() {
+                debugger.remove_watch_expression(index);
+            }
+        })
+    }
+
+    fn update_item(&mut self, index: usize, item_id: Id, ctx: &mut dyn BuilderContext) -> bool {
+        let (text, value) = Self::evaluate(ctx, index);
+        let changed = self.values[index].is_some() && self.values[index] != value;
+        self.values[index] = value;
+        let text_id = ctx.get_active_children(item_id)[0];
+        if let Graphic::Text(x) = ctx.get_graphic_mut(text_id) {
+            x.set_string(&text);
+            if changed {
+                let len = x.len();
+                x.add_span(0..len, Span::Color(0xd79314ff.into()));
+            }
+        }
+        true
+    }
+}
+
+/// Add the submitted expression to the watch expressions.
+struct WatchExpressionCallback;
+impl TextFieldCallback for WatchExpressionCallback {
+    fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let result = ctx
+            .get::<Arc<Mutex<Debugger>>>()
+            .lock()
+            .add_watch_expression(text);
+        match result {
+            Ok(()) => text.clear(),
+            // keep the text, so it can be fixed.
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        _event: giui::KeyboardEvent,
+        _this: Id,
+        _ctx: &mut Context,
+    ) -> bool {
+        false
+    }
+}
+
 fn list_item(
     ctx: &mut dyn BuilderContext,
     cb: ControlBuilder,
@@ -580,9 +892,15 @@ pub fn build(
             cpu: cpu_id,
             ppu: ppu_id,
             pc: None,
+            banks: (0, 1),
             directives: Vec::new(),
             items_are_dirty: true,
+            back_history: Vec::new(),
+            forward_history: Vec::new(),
+            location: None,
             _emulator_updated_event: event_table.register(list_id),
+            _navigation_event: event_table.register(list_id),
+            _labels_updated_event: event_table.register(list_id),
         },
     )
     .parent(parent)
@@ -604,7 +922,7 @@ pub fn side_panel(
         .min_size([100.0, 0.0])
         .graphic(style.split_background.clone())
         .build(ctx);
-    let cpu = fold_view::folder(ctx, "cpu".to_string(), style)
+    let cpu = fold_view::folder(ctx, tr("debugger.cpu").to_string(), style)
         .parent(right_panel)
         .build(ctx);
     let _reg_view = ctx
@@ -613,7 +931,7 @@ pub fn side_panel(
         .graphic(Text::new(String::new(), (-1, 0), style.text_style.clone()))
         .layout(FitGraphic)
         .build(ctx);
-    let ppu = fold_view::folder(ctx, "ppu".to_string(), style)
+    let ppu = fold_view::folder(ctx, tr("debugger.ppu").to_string(), style)
         .parent(right_panel)
         .build(ctx);
     let _ppu_view = ctx
@@ -622,7 +940,7 @@ pub fn side_panel(
         .graphic(Text::new(String::new(), (-1, 0), style.text_style.clone()))
         .layout(FitGraphic)
         .build(ctx);
-    let breaks = fold_view::folder(ctx, "breaks".to_string(), style)
+    let breaks = fold_view::folder(ctx, tr("debugger.breaks").to_string(), style)
         .parent(right_panel)
         .build(ctx);
     let break_list = ctx.reserve();
@@ -638,7 +956,7 @@ pub fn side_panel(
         },
     )
     .build(ctx);
-    let watchs = fold_view::folder(ctx, "watchs".to_string(), style)
+    let watchs = fold_view::folder(ctx, tr("debugger.watchs").to_string(), style)
         .parent(right_panel)
         .build(ctx);
     let watchs_list = ctx.reserve();
@@ -655,6 +973,44 @@ pub fn side_panel(
         },
     )
     .build(ctx);
+    let caret = ctx.reserve();
+    let label = ctx.reserve();
+    let expression_field = ctx
+        .create_control()
+        .parent(watchs)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            WatchExpressionCallback,
+        ))
+        .min_size([20.0; 2])
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(expression_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(expression_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+    let expressions_list = ctx.reserve();
+    ui::list(
+        ctx.create_control_reserved(expressions_list)
+            .parent(watchs)
+            .min_size([50.0, 100.0]),
+        ctx,
+        style,
+        [10.0, 0.0, 0.0, 0.0],
+        WatchExpressionList {
+            values: Vec::new(),
+            _watchs_updated_event: event_table.register(expressions_list),
+            _emulator_updated_event: event_table.register(expressions_list),
+        },
+    )
+    .build(ctx);
     ctx.create_control()
         .expand_y(true)
         .parent(right_panel)
@@ -662,13 +1018,14 @@ pub fn side_panel(
         .build(ctx);
 }
 
+/// Build the text field where debugger commands are typed, and return its Id.
 pub fn command_field(
     ctx: &mut dyn BuilderContext,
     vbox: Id,
     style: &Style,
     scroll_log: Id,
     log: Id,
-) {
+) -> Id {
     let caret = ctx.reserve();
     let label = ctx.reserve();
     let text_field = ctx
@@ -693,4 +1050,5 @@ pub fn command_field(
         .parent(text_field)
         .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
         .build(ctx);
+    text_field
 }
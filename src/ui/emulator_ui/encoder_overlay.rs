@@ -0,0 +1,51 @@
+use giui::{graphics::Text, layouts::FitGraphic, Behaviour, BuilderContext, Context, Id, RectFill};
+
+use crate::{
+    event_table::{EventTable, FrameTick, Handle},
+    image_pool,
+    localization::tr,
+    style::Style,
+};
+
+/// Show over the screen when the image encoder is busy, and new images would not be saved.
+struct EncoderOverlay {
+    saturated: bool,
+    _frame_tick_event: Handle<FrameTick>,
+}
+impl Behaviour for EncoderOverlay {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if !event.is::<FrameTick>() {
+            return;
+        }
+        let saturated = image_pool::is_saturated();
+        if saturated != self.saturated {
+            self.saturated = saturated;
+            let text = if saturated { tr("image_pool.busy") } else { "" };
+            ctx.get_graphic_mut(this).set_text(text);
+        }
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let id = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .graphic(Text::new(
+            String::new(),
+            (1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(EncoderOverlay {
+            saturated: false,
+            _frame_tick_event: event_table.register(id),
+        })
+        .fill_x(RectFill::ShrinkEnd)
+        .fill_y(RectFill::ShrinkStart)
+        .build(ctx);
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use giui::{Context, Id};
 
+use crate::event_table::{FrameTick, Handle};
+
 pub type OtherButton = (Id, Box<dyn FnMut(bool, &mut Context)>);
 
 pub struct GamePad {
@@ -14,6 +16,10 @@ pub struct GamePad {
     /// opacity.
     sprites: [Id; 9],
     pressed: HashMap<giui::MouseId, u8>,
+    /// The buttons currently pressed through the game pad, with 1 meaning pressed.
+    pad_joypad: u8,
+    /// Keeps the sprites in sync with buttons pressed by other means, like the keyboard.
+    _frame_tick_event: Option<Handle<FrameTick>>,
 }
 
 impl GamePad {
@@ -24,9 +30,30 @@ impl GamePad {
             other_buttons: other,
             sprites,
             pressed: HashMap::default(),
+            pad_joypad: 0,
+            _frame_tick_event: None,
         }
     }
 
+    /// Update the pressed state of the sprites on each frame, so they also show the buttons
+    /// pressed through the keyboard.
+    pub fn with_frame_tick(mut self, handle: Handle<FrameTick>) -> Self {
+        self._frame_tick_event = Some(handle);
+        self
+    }
+
+    /// Make the sprites of the pressed buttons opaque.
+    fn update_sprites(&self, ctx: &mut Context) {
+        let joypad = !ctx.get::<crate::AppState>().joypad;
+        for i in 0..8 {
+            let alpha = if (joypad >> i) & 1 != 0 { 255 } else { 128 };
+            ctx.get_graphic_mut(self.sprites[i as usize])
+                .set_alpha(alpha);
+        }
+        ctx.get_graphic_mut(self.sprites[8])
+            .set_alpha([128, 255][((joypad & 0xf) != 0) as usize]);
+    }
+
     fn on_change(&mut self, ctx: &mut Context) {
         let mut joypad = 0;
         let joys = self.joy_buttons.len();
@@ -47,16 +74,13 @@ impl GamePad {
             (b.1)(v, ctx);
         }
 
-        for i in 0..8 {
-            let alpha = if (joypad >> i) & 1 != 0 { 255 } else { 128 };
-            ctx.get_graphic_mut(self.sprites[i as usize])
-                .set_alpha(alpha);
-        }
-        ctx.get_graphic_mut(self.sprites[8])
-            .set_alpha([128, 255][((joypad & 0xf) != 0) as usize]);
-
+        // only the buttons pressed through the game pad change, so it can be used together with
+        // the keyboard.
         let app_state = ctx.get_mut::<crate::AppState>();
-        app_state.joypad = !joypad;
+        app_state.joypad = (app_state.joypad | self.pad_joypad) & !joypad;
+        self.pad_joypad = joypad;
+
+        self.update_sprites(ctx);
     }
 }
 
@@ -66,6 +90,12 @@ impl giui::Behaviour for GamePad {
         self.on_change(ctx);
     }
 
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if event.is::<FrameTick>() {
+            self.update_sprites(ctx);
+        }
+    }
+
     fn input_flags(&self) -> giui::InputFlags {
         giui::InputFlags::MOUSE
     }
@@ -126,10 +156,7 @@ mod test {
     fn ctx_and_game_pad() -> giui::Gui {
         let size = 900.0;
         let mut gui = giui::Gui::new(size, size, 1.0, giui::font::Fonts::new());
-        gui.set(AppState {
-            joypad: 0xFF,
-            debug: false,
-        });
+        gui.set(AppState::new(false));
         {
             let ctx = &mut gui.get_context();
 
@@ -235,5 +262,15 @@ mod test {
 
         gui.mouse_exit(1);
         assert_eq!(gui.get::<AppState>().joypad, !0x00);
+
+        // buttons pressed by the keyboard are kept
+        log::info!("test keyboard");
+
+        gui.get_mut::<AppState>().joypad = !0x10;
+        gui.mouse_moved(0, 150.0, 150.0);
+        gui.mouse_down(0, giui::MouseButton::Left);
+        assert_eq!(gui.get::<AppState>().joypad, !0x11);
+        gui.mouse_exit(0);
+        assert_eq!(gui.get::<AppState>().joypad, !0x10);
     }
 }
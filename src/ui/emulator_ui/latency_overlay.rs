@@ -0,0 +1,58 @@
+use giui::{graphics::Text, layouts::FitGraphic, Behaviour, BuilderContext, Context, Id, RectFill};
+use instant::Duration;
+
+use crate::{
+    event_table::{EventTable, FrameTick, Handle},
+    input_latency::InputLatency,
+    localization::tr,
+    style::Style,
+};
+
+/// Show the last input latency measured, over the screen.
+struct LatencyOverlay {
+    _frame_tick_event: Handle<FrameTick>,
+}
+impl Behaviour for LatencyOverlay {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if !event.is::<FrameTick>() {
+            return;
+        }
+        let ms = |x: Duration| x.as_secs_f64() * 1000.0;
+        let text = match ctx.get::<InputLatency>().stats() {
+            Some((last, average)) => format!(
+                "{}{:.1} ms\n{}{:.1} ms\n{}{:.1} ms",
+                tr("latency_overlay.to_joypad"),
+                ms(last.to_joypad),
+                tr("latency_overlay.to_present"),
+                ms(last.to_present),
+                tr("latency_overlay.average"),
+                ms(average),
+            ),
+            None => tr("latency_overlay.waiting").to_string(),
+        };
+        ctx.get_graphic_mut(this).set_text(&text);
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let id = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .graphic(Text::new(
+            tr("latency_overlay.waiting").to_string(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(LatencyOverlay {
+            _frame_tick_event: event_table.register(id),
+        })
+        .fill_x(RectFill::ShrinkStart)
+        .fill_y(RectFill::ShrinkStart)
+        .build(ctx);
+}
@@ -0,0 +1,54 @@
+use giui::{graphics::Text, layouts::FitGraphic, Behaviour, BuilderContext, Context, Id, RectFill};
+use instant::{Duration, Instant};
+
+use crate::{
+    event_table::{EventTable, FrameTick, Handle, Notification},
+    style::Style,
+};
+
+/// For how long a notification stays on the screen.
+const DURATION: Duration = Duration::from_secs(3);
+
+/// Show the last notification over the screen, for a few seconds.
+struct NotificationOverlay {
+    /// The moment the current notification was shown, if any.
+    shown_at: Option<Instant>,
+    _notification_event: Handle<Notification>,
+    _frame_tick_event: Handle<FrameTick>,
+}
+impl Behaviour for NotificationOverlay {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if let Some(Notification(text)) = event.downcast_ref::<Notification>() {
+            self.shown_at = Some(Instant::now());
+            ctx.get_graphic_mut(this).set_text(text);
+        } else if event.is::<FrameTick>() && self.shown_at.is_some_and(|x| x.elapsed() > DURATION) {
+            self.shown_at = None;
+            ctx.get_graphic_mut(this).set_text("");
+        }
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let id = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .graphic(Text::new(
+            String::new(),
+            (-1, 1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(NotificationOverlay {
+            shown_at: None,
+            _notification_event: event_table.register(id),
+            _frame_tick_event: event_table.register(id),
+        })
+        .fill_x(RectFill::ShrinkStart)
+        .fill_y(RectFill::ShrinkEnd)
+        .build(ctx);
+}
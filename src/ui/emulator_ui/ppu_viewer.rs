@@ -1,17 +1,20 @@
-use std::sync::Arc;
+use std::{cell::RefCell, path::PathBuf, rc::Rc, sync::Arc};
 
 use gameroy::gameboy::{ppu::Ppu, GameBoy};
 use giui::{
     graphics::{Graphic, Texture},
     layouts::{FitGraphic, GridLayout, HBoxLayout, VBoxLayout},
     text::Text,
+    widgets::Button,
     Behaviour, BuilderContext, Color, Context, Id, InputFlags, MouseEvent,
 };
 use parking_lot::Mutex;
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
-    event_table::{EmulatorUpdated, EventTable, FrameUpdated, Handle},
+    event_table::{EmulatorUpdated, EventTable, FrameTick, Handle},
+    image_pool,
+    localization::tr,
     style::Style,
     ui::{scroll_viewer, Textures},
     UserEvent,
@@ -19,12 +22,201 @@ use crate::{
 
 type InfoText = Box<dyn FnMut(u8, u8, &mut Context) -> String>;
 
+const COLOR: [[u8; 3]; 5] = [
+    [255, 255, 255],
+    [170, 170, 170],
+    [85, 85, 85],
+    [0, 0, 0],
+    [255, 0, 255], // invalid color
+];
+
+/// Rasterize all the 384 tiles in VRAM, 16 tiles per row, using the background palette. Returns
+/// a 128x192 RGBA image.
+pub fn render_tiles(ppu: &Ppu) -> Vec<u8> {
+    let mut tiles = vec![255; 128 * 192 * 4];
+    gameroy::gameboy::ppu::draw_tiles(
+        ppu,
+        &mut |x, y, c| {
+            let i = (x + y * 128) as usize * 4;
+            tiles[i..i + 3].copy_from_slice(&COLOR[c as usize]);
+        },
+        ppu.bgp,
+    );
+    tiles
+}
+
+/// Rasterize the whole 32x32 tiles background map. Returns a 256x256 RGBA image.
+pub fn render_background(ppu: &Ppu) -> Vec<u8> {
+    let mut background = vec![255; 256 * 256 * 4];
+    gameroy::gameboy::ppu::draw_background(ppu, &mut |x, y, c| {
+        let i = (x + y * 256) as usize * 4;
+        background[i..i + 3].copy_from_slice(&COLOR[c as usize]);
+    });
+    background
+}
+
+/// Rasterize the whole 32x32 tiles window map. Returns a 256x256 RGBA image.
+pub fn render_window(ppu: &Ppu) -> Vec<u8> {
+    let mut window = vec![255; 256 * 256 * 4];
+    gameroy::gameboy::ppu::draw_window(ppu, &mut |x, y, c| {
+        let i = (x + y * 256) as usize * 4;
+        window[i..i + 3].copy_from_slice(&COLOR[c as usize]);
+    });
+    window
+}
+
+/// Rasterize the sprite at the given OAM index, with its palette and flips. Returns a RGBA image,
+/// with a height of 8 or 16, depending on the sprite size in LCDC, and a width of 8. Transparent
+/// pixels have alpha 0.
+pub fn render_sprite(ppu: &Ppu, index: usize) -> (Vec<u8>, u32) {
+    let data = &ppu.oam[index * 4..index * 4 + 4];
+    let tile = data[2] as usize;
+    let flags = data[3];
+    let palette = if flags & 0x10 != 0 {
+        ppu.obp1
+    } else {
+        ppu.obp0
+    };
+
+    let height: i32 = if ppu.lcdc & 0x04 != 0 { 16 } else { 8 };
+    let mut sprite = vec![0; 8 * height as usize * 4];
+    let mut draw_pixel = |x: i32, y: i32, c: u8| {
+        let x = if flags & 0x20 != 0 { 7 - x } else { x };
+        let y = if flags & 0x40 != 0 { height - 1 - y } else { y };
+        let i = (x + y * 8) as usize * 4;
+        sprite[i..i + 3].copy_from_slice(&COLOR[c as usize]);
+        sprite[i + 3] = 255;
+    };
+    if height == 16 {
+        gameroy::gameboy::ppu::draw_tile(ppu, &mut draw_pixel, 0, 0, tile & !1, palette, true);
+        gameroy::gameboy::ppu::draw_tile(ppu, &mut draw_pixel, 0, 8, tile | 1, palette, true);
+    } else {
+        gameroy::gameboy::ppu::draw_tile(ppu, &mut draw_pixel, 0, 0, tile, palette, true);
+    }
+    (sprite, height as u32)
+}
+
+/// Save the tile sheet to a PNG file. Used by the `dumptiles` debugger command.
+///
+/// The image is small, so it is encoded and written in place, to report any error to the command.
+pub fn dump_tiles(gb: &GameBoy, args: &[&str]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err(format!(
+            "'dumptiles' expect 1 argument, receive {}",
+            args.len() - 1
+        ));
+    }
+    let tiles = render_tiles(&gb.ppu.borrow());
+    // relative paths are relative to the screenshot folder.
+    let path = crate::dirs::screenshot_folder().join(args[1]);
+    let png = image_pool::encode_png(&tiles, 128, 192)?;
+    std::fs::write(&path, png)
+        .map_err(|err| format!("failed to save '{}': {}", path.display(), err))?;
+    log::info!("saved '{}'", path.display());
+    Ok(())
+}
+
+/// Ask the user where to save the image, and save it as a PNG file. If there is no file dialog
+/// available, save it in the screenshot folder, with the given file name.
+///
+/// The image is encoded in the background, by the `image_pool`. If it is busy, the image is not
+/// saved, and the user is notified.
+fn export_png(ctx: &mut Context, file_name: String, rgba: Vec<u8>, width: u32, height: u32) {
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let save = move |path: PathBuf| {
+        if let Err(err) = image_pool::save_png(path, rgba, width, height) {
+            log::error!("{}", err);
+            let _ = proxy.send_event(UserEvent::Notify(err));
+        }
+    };
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "rfd", not(target_arch = "wasm32")))] {
+            let handle = ctx.get::<std::rc::Rc<winit::window::Window>>().clone();
+            let task = async move {
+                let handle = &*handle;
+                let file = rfd::AsyncFileDialog::new()
+                    .set_title(tr("dialog.export_png"))
+                    .add_filter(tr("dialog.png_filter"), &["png"])
+                    .set_file_name(&file_name)
+                    .set_directory(crate::dirs::screenshot_folder())
+                    .set_parent(handle)
+                    .save_file()
+                    .await;
+
+                if let Some(file) = file {
+                    save(file.path().to_path_buf());
+                }
+            };
+            crate::executor::Executor::spawn_task(task, ctx);
+        } else {
+            save(crate::dirs::screenshot_folder().join(&file_name));
+        }
+    }
+}
+
+/// Clone the current PPU state, boxed to avoid stack overflow.
+fn clone_ppu(ctx: &mut Context) -> Box<Ppu> {
+    let gb = ctx.get::<Arc<Mutex<GameBoy>>>();
+    let mut ppu: Box<Ppu> = Box::default();
+    (*ppu).clone_from(&*gb.lock().ppu.borrow());
+    ppu
+}
+
+/// Edit the tile data in VRAM by clicking in the pixels of the tile viewer, while the emulator is
+/// paused. Each click cycles the color of the pixel.
+#[derive(Clone)]
+struct TileEditor {
+    /// The PpuViewer, that is updated after each edit.
+    ppu_viewer: Id,
+    /// The address and the previous value of each edited tile row.
+    undo: Rc<RefCell<Vec<(usize, [u8; 2])>>>,
+}
+impl TileEditor {
+    /// Cycle the color of the pixel at the given position of the tile sheet.
+    fn edit(&self, x: u32, y: u32, ctx: &mut Context) {
+        if ctx.get::<crate::AppState>().running {
+            return;
+        }
+        let tile = (y / 8 * 16 + x / 8) as usize;
+        let address = tile * 0x10 + (y % 8) as usize * 2;
+        let bit = 7 - (x % 8);
+        {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let mut ppu = gb.ppu.borrow_mut();
+            let [a, b] = [ppu.vram[address], ppu.vram[address + 1]];
+            self.undo.borrow_mut().push((address, [a, b]));
+
+            let color = ((((b >> bit) & 1) << 1) | ((a >> bit) & 1)) + 1;
+            ppu.vram[address] = (a & !(1 << bit)) | ((color & 1) << bit);
+            ppu.vram[address + 1] = (b & !(1 << bit)) | (((color >> 1) & 1) << bit);
+        }
+        ctx.send_event_to(self.ppu_viewer, EmulatorUpdated);
+    }
+
+    /// Revert the last edit.
+    fn undo(&self, ctx: &mut Context) {
+        if ctx.get::<crate::AppState>().running {
+            return;
+        }
+        let Some((address, value)) = self.undo.borrow_mut().pop() else {
+            return;
+        };
+        {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let mut ppu = gb.ppu.borrow_mut();
+            ppu.vram[address..address + 2].copy_from_slice(&value);
+        }
+        ctx.send_event_to(self.ppu_viewer, EmulatorUpdated);
+    }
+}
+
 struct TilemapViewer {
     info_text: Id,
     tilemap: Id,
     width: u8,
     height: u8,
     info: InfoText,
+    editor: Option<TileEditor>,
 }
 impl Behaviour for TilemapViewer {
     fn on_start(&mut self, _this: Id, ctx: &mut Context) {
@@ -40,7 +232,20 @@ impl Behaviour for TilemapViewer {
         match mouse.event {
             MouseEvent::Enter => {}
             MouseEvent::Exit => {}
-            MouseEvent::Down(_) => {}
+            MouseEvent::Down(_) => {
+                let Some(editor) = &self.editor else {
+                    return;
+                };
+                let tilemap = ctx.get_rect(self.tilemap);
+                let rel_x = (mouse.pos[0] - tilemap[0]) / (tilemap[2] - tilemap[0]);
+                let rel_y = (mouse.pos[1] - tilemap[1]) / (tilemap[3] - tilemap[1]);
+
+                if (0.0..1.0).contains(&rel_x) && (0.0..1.0).contains(&rel_y) {
+                    let x = (rel_x * 8.0 * self.width as f32) as u32;
+                    let y = (rel_y * 8.0 * self.height as f32) as u32;
+                    editor.edit(x, y, ctx);
+                }
+            }
             MouseEvent::Up(_) => {}
             MouseEvent::Moved => {
                 let tilemap = ctx.get_rect(self.tilemap);
@@ -65,20 +270,17 @@ struct PpuViewer {
     buffer_sprites: [[Id; 2]; 10],
     background_fifo: Id,
     sprite_fifo: Id,
-    _frame_updated_event: Handle<FrameUpdated>,
+    pipeline_info: Id,
+    /// The cells that show each pixel of the background and of the sprite FIFOs.
+    background_cells: [Id; FIFO_CELLS],
+    sprite_cells: [Id; FIFO_CELLS],
+    _frame_tick_event: Handle<FrameTick>,
     _emulator_updated_event: Handle<EmulatorUpdated>,
 }
 impl PpuViewer {
     #[allow(clippy::needless_range_loop)]
     fn update(&mut self, ctx: &mut Context, emulator_updated: bool) {
         let textures = ctx.get::<Textures>();
-        const COLOR: [[u8; 3]; 5] = [
-            [255, 255, 255],
-            [170, 170, 170],
-            [85, 85, 85],
-            [0, 0, 0],
-            [255, 0, 255], // invalid color
-        ];
         let gb = ctx.get::<Arc<Mutex<GameBoy>>>();
         let proxy = &ctx.get::<EventLoopProxy<UserEvent>>();
 
@@ -93,9 +295,12 @@ impl PpuViewer {
             let mut debug_screen = vec![255; 160 * 144 * 4];
             let screen = &ppu.screen.packed();
             let curr_i = (ppu.ly as usize * 160 + ppu.screen_x.min(160) as usize).min(160 * 144);
+            // colorized pixels also hold their source, above the shade.
+            let colorization = ppu.colorization_enabled();
+            let shade = |x: u8| if colorization { x & 0b11 } else { x.min(4) };
 
             for i in 0..curr_i {
-                let c = screen[i].min(4);
+                let c = shade(screen[i]);
                 let i = i * 4;
                 debug_screen[i..i + 3].copy_from_slice(&COLOR[c as usize]);
             }
@@ -108,7 +313,7 @@ impl PpuViewer {
                 [255, 0, 255], // invalid color
             ];
             for i in curr_i..screen.len() {
-                let c = screen[i].min(4);
+                let c = shade(screen[i]);
                 let i = i * 4;
                 debug_screen[i..i + 3].copy_from_slice(&OLD_COLOR[c as usize]);
             }
@@ -120,38 +325,21 @@ impl PpuViewer {
                 ))
                 .unwrap();
         }
-        let mut tiles = vec![255; 128 * 192 * 4];
-        gameroy::gameboy::ppu::draw_tiles(
-            &ppu,
-            &mut |x, y, c| {
-                let i = (x + y * 128) as usize * 4;
-                let color = COLOR[c as usize];
-                tiles[i..i + 3].copy_from_slice(&color);
-            },
-            ppu.bgp,
-        );
+        let tiles = render_tiles(&ppu);
         proxy
             .send_event(UserEvent::UpdateTexture(
                 textures.tilemap,
                 tiles.into_boxed_slice(),
             ))
             .unwrap();
-        let mut background = vec![255; 256 * 256 * 4];
-        gameroy::gameboy::ppu::draw_background(&ppu, &mut |x, y, c| {
-            let i = (x + y * 256) as usize * 4;
-            background[i..i + 3].copy_from_slice(&COLOR[c as usize]);
-        });
+        let background = render_background(&ppu);
         proxy
             .send_event(UserEvent::UpdateTexture(
                 textures.background,
                 background.into_boxed_slice(),
             ))
             .unwrap();
-        let mut window = vec![255; 256 * 256 * 4];
-        gameroy::gameboy::ppu::draw_window(&ppu, &mut |x, y, c| {
-            let i = (x + y * 256) as usize * 4;
-            window[i..i + 3].copy_from_slice(&COLOR[c as usize]);
-        });
+        let window = render_window(&ppu);
         proxy
             .send_event(UserEvent::UpdateTexture(
                 textures.window,
@@ -228,7 +416,7 @@ impl PpuViewer {
         let background_pixels = ppu
             .background_fifo
             .iter()
-            .fold("Background pixels: ".to_string(), |t, p| {
+            .fold(tr("ppu_viewer.background_pixels").to_string(), |t, p| {
                 t + &format!("{:02x}", p) + " "
             });
         ctx.get_graphic_mut(self.background_fifo)
@@ -237,16 +425,72 @@ impl PpuViewer {
         let sprite_pixels = ppu
             .sprite_fifo
             .iter()
-            .fold("Sprite pixels:     ".to_string(), |t, p| {
+            .fold(tr("ppu_viewer.sprite_pixels").to_string(), |t, p| {
                 t + &format!("{:02x}", p) + " "
             });
         ctx.get_graphic_mut(self.sprite_fifo)
             .set_text(&sprite_pixels);
+
+        let pipeline = ppu.pipeline();
+        let yes_no = |x: bool| if x { "yes" } else { "no" };
+        ctx.get_graphic_mut(self.pipeline_info).set_text(&format!(
+            "fetcher step: {} tile: {:02x} data: {:02x} {:02x}\n\
+             screen x: {} scanline x: {} discarding: {}\n\
+             window: reached {}, active {}, starting {}, tile x: {:02x}",
+            pipeline.fetcher_step,
+            pipeline.tile_number,
+            pipeline.tile_data_low,
+            pipeline.tile_data_high,
+            ppu.screen_x,
+            ppu.scanline_x as i8,
+            pipeline.discarding,
+            yes_no(pipeline.reach_window),
+            yes_no(pipeline.is_in_window),
+            yes_no(pipeline.is_window_being_fetched),
+            pipeline.fetcher_x,
+        ));
+
+        let mut background = ppu.background_fifo.iter();
+        for &cell in &self.background_cells {
+            let color = background
+                .next()
+                .map(|x| COLOR[((ppu.bgp >> (x * 2)) & 0b11) as usize]);
+            set_cell_color(ctx, cell, color);
+        }
+        let mut sprite = ppu.sprite_fifo.iter();
+        for &cell in &self.sprite_cells {
+            let color = sprite.next().map(|x| {
+                let color = x & 0b11;
+                if color == 0 {
+                    TRANSPARENT_COLOR
+                } else {
+                    let palette = if x & 0x10 != 0 { ppu.obp1 } else { ppu.obp0 };
+                    COLOR[((palette >> (color * 2)) & 0b11) as usize]
+                }
+            });
+            set_cell_color(ctx, cell, color);
+        }
+    }
+}
+
+/// The number of pixels that fit in a FIFO.
+const FIFO_CELLS: usize = 16;
+/// The color of the transparent pixels of the sprite FIFO.
+const TRANSPARENT_COLOR: [u8; 3] = [0, 160, 160];
+
+/// Paint a cell of the FIFO viewer with the color of its pixel, or make it dim if the FIFO has no
+/// pixel at that position.
+fn set_cell_color(ctx: &mut Context, cell: Id, color: Option<[u8; 3]>) {
+    if let Graphic::Texture(t) = ctx.get_graphic_mut(cell) {
+        t.color = match color {
+            Some([r, g, b]) => [r, g, b, 255].into(),
+            None => [60, 60, 60, 255].into(),
+        };
     }
 }
 impl Behaviour for PpuViewer {
     fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut giui::Context) {
-        if event.is::<FrameUpdated>() || event.is::<EmulatorUpdated>() {
+        if event.is::<FrameTick>() || event.is::<EmulatorUpdated>() {
             let emulator_updated = event.is::<EmulatorUpdated>();
             self.update(ctx, emulator_updated);
         }
@@ -268,14 +512,28 @@ pub fn build(
         .parent(ppu_viewer)
         .build(ctx);
 
-    build_tilemap_viewer(ctx, textures.tilemap, style, content, 16, 24, |x, y, _| {
-        format!(
-            "tilemap:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
-            y as u16 * 16 + x as u16,
-            x,
-            y
-        )
-    });
+    build_tilemap_viewer(
+        ctx,
+        textures.tilemap,
+        style,
+        content,
+        16,
+        24,
+        "tiles.png",
+        render_tiles,
+        Some(TileEditor {
+            ppu_viewer,
+            undo: Rc::default(),
+        }),
+        |x, y, _| {
+            format!(
+                "tilemap:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
+                y as u16 * 16 + x as u16,
+                x,
+                y
+            )
+        },
+    );
 
     build_tilemap_viewer(
         ctx,
@@ -284,6 +542,9 @@ pub fn build(
         content,
         32,
         32,
+        "background.png",
+        render_background,
+        None,
         |x, y, ctx| {
             let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
             let ppu = gb.ppu.borrow();
@@ -306,26 +567,37 @@ pub fn build(
         },
     );
 
-    build_tilemap_viewer(ctx, textures.window, style, content, 32, 32, |x, y, ctx| {
-        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
-        let ppu = gb.ppu.borrow();
+    build_tilemap_viewer(
+        ctx,
+        textures.window,
+        style,
+        content,
+        32,
+        32,
+        "window.png",
+        render_window,
+        None,
+        |x, y, ctx| {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let ppu = gb.ppu.borrow();
 
-        let i = y as u16 * 32 + x as u16;
-        let address = if ppu.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
-        let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
+            let i = y as u16 * 32 + x as u16;
+            let address = if ppu.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+            let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
 
-        // if is using 8800 method
-        if ppu.lcdc & 0x10 == 0 {
-            tile += 0x100;
-            if tile >= 0x180 {
-                tile -= 0x100;
+            // if is using 8800 method
+            if ppu.lcdc & 0x10 == 0 {
+                tile += 0x100;
+                if tile >= 0x180 {
+                    tile -= 0x100;
+                }
             }
-        }
-        format!(
-            "window:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
-            tile, x, y
-        )
-    });
+            format!(
+                "window:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
+                tile, x, y
+            )
+        },
+    );
 
     let oam_viewer = ctx.reserve();
     ctx.create_control()
@@ -333,7 +605,7 @@ pub fn build(
         .layout(VBoxLayout::default())
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "Sprites in OAM".to_string(),
+                tr("ppu_viewer.sprites_in_oam").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -346,7 +618,7 @@ pub fn build(
 
     let oam_sprites: [[Id; 2]; 40] = [(); 40].map(|_| [ctx.reserve(), ctx.reserve()]);
 
-    for &[view, text] in &oam_sprites {
+    for (i, &[view, text]) in oam_sprites.iter().enumerate() {
         let tile = ctx
             .create_control()
             .parent(oam_viewer)
@@ -369,6 +641,11 @@ pub fn build(
             ))
             .layout(FitGraphic)
             .build(ctx);
+        build_text_button(ctx, style, tile, tr("ppu_viewer.export_png"), move |ctx| {
+            let ppu = clone_ppu(ctx);
+            let (rgba, height) = render_sprite(&ppu, i);
+            export_png(ctx, format!("sprite_{:02}.png", i), rgba, 8, height);
+        });
     }
 
     let buffer_viewer = ctx.reserve();
@@ -377,7 +654,7 @@ pub fn build(
         .layout(VBoxLayout::default())
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "Sprites in Buffer".to_string(),
+                tr("ppu_viewer.sprites_in_buffer").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -417,26 +694,26 @@ pub fn build(
 
     let background_fifo = ctx.reserve();
     let sprite_fifo = ctx.reserve();
-    ctx.create_control()
+    let pipeline_info = ctx.reserve();
+    let pipeline_viewer = ctx
+        .create_control()
         .parent(content)
         .layout(VBoxLayout::default())
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "Fifo Viewer".to_string(),
+                tr("ppu_viewer.fifo_viewer").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
             .layout(FitGraphic)
         })
-        .child_reserved(background_fifo, ctx, |cb, _| {
-            cb.graphic(Text::new("".to_string(), (-1, 0), style.text_style.clone()))
-                .layout(FitGraphic)
-        })
-        .child_reserved(sprite_fifo, ctx, |cb, _| {
+        .child_reserved(pipeline_info, ctx, |cb, _| {
             cb.graphic(Text::new("".to_string(), (-1, 0), style.text_style.clone()))
                 .layout(FitGraphic)
         })
         .build(ctx);
+    let background_cells = build_fifo_row(ctx, style, textures, pipeline_viewer, background_fifo);
+    let sprite_cells = build_fifo_row(ctx, style, textures, pipeline_viewer, sprite_fifo);
 
     ctx.create_control_reserved(ppu_viewer)
         .parent(parent)
@@ -445,12 +722,46 @@ pub fn build(
             buffer_sprites,
             background_fifo,
             sprite_fifo,
-            _frame_updated_event: event_table.register(ppu_viewer),
+            pipeline_info,
+            background_cells,
+            sprite_cells,
+            _frame_tick_event: event_table.register(ppu_viewer),
             _emulator_updated_event: event_table.register(ppu_viewer),
         })
         .build(ctx);
 }
 
+/// Build a row with a cell for each pixel of a FIFO, followed by the text `fifo_text`, that lists
+/// the values of the pixels. Returns the cells.
+fn build_fifo_row(
+    ctx: &mut dyn BuilderContext,
+    style: &Style,
+    textures: &Textures,
+    parent: Id,
+    fifo_text: Id,
+) -> [Id; FIFO_CELLS] {
+    let row = ctx
+        .create_control()
+        .parent(parent)
+        .layout(HBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+    let cells = [(); FIFO_CELLS].map(|_| {
+        ctx.create_control()
+            .parent(row)
+            .graphic(Texture::new(textures.white, [0.0, 0.0, 1.0, 1.0]))
+            .min_size([12.0, 12.0])
+            .fill_y(giui::RectFill::ShrinkCenter)
+            .build(ctx)
+    });
+    ctx.create_control_reserved(fifo_text)
+        .parent(row)
+        .graphic(Text::new("".to_string(), (-1, 0), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+    cells
+}
+
+#[allow(clippy::too_many_arguments)]
 fn build_tilemap_viewer(
     ctx: &mut dyn BuilderContext,
     texture: u32,
@@ -458,15 +769,45 @@ fn build_tilemap_viewer(
     parent: Id,
     width: u8,
     height: u8,
+    file_name: &'static str,
+    render: fn(&Ppu) -> Vec<u8>,
+    editor: Option<TileEditor>,
     info: impl Fn(u8, u8, &mut Context) -> String + 'static,
 ) {
     let tilemap_viewer = ctx.reserve();
-    let tilemap_text = ctx
+    let left_panel = ctx
         .create_control()
         .parent(tilemap_viewer)
+        .layout(VBoxLayout::default())
+        .build(ctx);
+    let tilemap_text = ctx
+        .create_control()
+        .parent(left_panel)
         .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
         .min_size([140.0, 16.0])
         .build(ctx);
+    build_text_button(
+        ctx,
+        style,
+        left_panel,
+        tr("ppu_viewer.export_png"),
+        move |ctx| {
+            let ppu = clone_ppu(ctx);
+            let rgba = render(&ppu);
+            export_png(
+                ctx,
+                file_name.to_string(),
+                rgba,
+                8 * width as u32,
+                8 * height as u32,
+            );
+        },
+    );
+    if let Some(editor) = editor.clone() {
+        build_text_button(ctx, style, left_panel, tr("ppu_viewer.undo"), move |ctx| {
+            editor.undo(ctx)
+        });
+    }
     let tilemap = ctx
         .create_control()
         .parent(tilemap_viewer)
@@ -485,6 +826,34 @@ fn build_tilemap_viewer(
             tilemap,
             info_text: tilemap_text,
             info: Box::new(info),
+            editor,
         })
         .build(ctx);
 }
+
+fn build_text_button(
+    ctx: &mut dyn BuilderContext,
+    style: &Style,
+    parent: Id,
+    label: &str,
+    on_click: impl Fn(&mut Context) + 'static,
+) {
+    ctx.create_control()
+        .parent(parent)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| on_click(ctx),
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                label.to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .fill_x(giui::RectFill::ShrinkStart)
+        .build(ctx);
+}
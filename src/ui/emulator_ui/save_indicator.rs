@@ -0,0 +1,56 @@
+use giui::{graphics::Text, layouts::FitGraphic, Behaviour, BuilderContext, Context, Id, RectFill};
+use instant::{Duration, Instant};
+
+use crate::{
+    event_table::{EventTable, FrameTick, Handle, RamSaved},
+    localization::tr,
+    style::Style,
+};
+
+/// For how long the indicator stays on the screen.
+const DURATION: Duration = Duration::from_secs(1);
+
+/// Show a small indicator in the corner of the screen, when the save file is written.
+struct SaveIndicator {
+    /// The moment the save file was last written, while the indicator is shown.
+    shown_at: Option<Instant>,
+    _ram_saved_event: Handle<RamSaved>,
+    _frame_tick_event: Handle<FrameTick>,
+}
+impl Behaviour for SaveIndicator {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, this: Id, ctx: &mut Context) {
+        if event.is::<RamSaved>() {
+            self.shown_at = Some(Instant::now());
+            ctx.get_graphic_mut(this)
+                .set_text(tr("save_indicator.saved"));
+        } else if event.is::<FrameTick>() && self.shown_at.is_some_and(|x| x.elapsed() > DURATION) {
+            self.shown_at = None;
+            ctx.get_graphic_mut(this).set_text("");
+        }
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let id = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .graphic(Text::new(
+            String::new(),
+            (1, 1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(SaveIndicator {
+            shown_at: None,
+            _ram_saved_event: event_table.register(id),
+            _frame_tick_event: event_table.register(id),
+        })
+        .fill_x(RectFill::ShrinkEnd)
+        .fill_y(RectFill::ShrinkEnd)
+        .build(ctx);
+}
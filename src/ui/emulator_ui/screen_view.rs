@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use gameroy::{
+    consts::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    gameboy::GameBoy,
+};
+use giui::{
+    graphics::{Graphic, Text},
+    layouts::FitGraphic,
+    Behaviour, BuilderContext, Context, Id, InputFlags, MouseButton, MouseEvent, RectFill,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EventTable, FrameUpdated, Handle},
+    localization::tr,
+    style::Style,
+    AppState,
+};
+
+/// The maximum zoom, in multiples of the fitted size of the screen.
+const MAX_ZOOM: f32 = 16.0;
+
+/// Zoom into the game screen with the mouse wheel, and pan it by dragging, while the emulation is
+/// paused. The pixel under the cursor and its color are shown over the screen, and clicking a pixel
+/// shows where it came from, as recorded by the PPU debug pass. The view is reset by a right click,
+/// or when the emulation resumes.
+struct ScreenView {
+    /// The control with the screen texture.
+    texture: Id,
+    /// The text with the pixel under the cursor.
+    info_text: Id,
+    zoom: f32,
+    /// The screen pixel shown in the center of the view.
+    center: [f32; 2],
+    /// The last position of the mouse over the screen.
+    mouse: Option<[f32; 2]>,
+    /// If the screen is being dragged by the left button.
+    dragging: bool,
+    /// Where the left button was pressed, while it is not dragged away, to detect clicks.
+    click_start: Option<[f32; 2]>,
+    /// The pixel clicked last, whose source is shown.
+    selected: Option<[u8; 2]>,
+    _frame_updated_event: Handle<FrameUpdated>,
+}
+impl ScreenView {
+    fn size() -> [f32; 2] {
+        [SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32]
+    }
+
+    /// The size of the visible part of the screen, in screen pixels.
+    fn visible_size(&self) -> [f32; 2] {
+        Self::size().map(|x| x / self.zoom)
+    }
+
+    /// The screen pixel at the given window position, if it is over the screen. Fractional.
+    fn pixel_at(&self, pos: [f32; 2], ctx: &mut Context) -> Option<[f32; 2]> {
+        let rect = ctx.get_rect(self.texture);
+        let rel_x = (pos[0] - rect[0]) / (rect[2] - rect[0]);
+        let rel_y = (pos[1] - rect[1]) / (rect[3] - rect[1]);
+        if !(0.0..1.0).contains(&rel_x) || !(0.0..1.0).contains(&rel_y) {
+            return None;
+        }
+        let [w, h] = self.visible_size();
+        Some([
+            self.center[0] - w / 2.0 + rel_x * w,
+            self.center[1] - h / 2.0 + rel_y * h,
+        ])
+    }
+
+    /// Keep the view inside the screen, and update the texture to show it.
+    fn update_view(&mut self, ctx: &mut Context) {
+        let size = Self::size();
+        let visible = self.visible_size();
+        for i in 0..2 {
+            let half = visible[i] / 2.0;
+            self.center[i] = self.center[i].clamp(half, size[i] - half);
+        }
+        let uv_rect = [
+            (self.center[0] - visible[0] / 2.0) / size[0],
+            (self.center[1] - visible[1] / 2.0) / size[1],
+            1.0 / self.zoom,
+            1.0 / self.zoom,
+        ];
+        if let Graphic::Texture(t) = ctx.get_graphic_mut(self.texture) {
+            t.uv_rect = uv_rect;
+        }
+    }
+
+    fn reset_view(&mut self, ctx: &mut Context) {
+        self.zoom = 1.0;
+        self.center = Self::size().map(|x| x / 2.0);
+        self.dragging = false;
+        self.click_start = None;
+        self.selected = None;
+        self.update_view(ctx);
+    }
+
+    /// Show the position and the color of the pixel under the mouse, and the source of the
+    /// selected pixel.
+    fn update_info(&mut self, ctx: &mut Context) {
+        let pixel = self.mouse.and_then(|pos| self.pixel_at(pos, ctx));
+        let mut text = String::new();
+        {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let ppu = gb.ppu.borrow();
+            if let Some([x, y]) = self.selected {
+                let source = match ppu.pixel_source(x, y) {
+                    Some(source) => source.to_string(),
+                    None => tr("screen_view.no_source").to_string(),
+                };
+                text += &format!("({}, {}) {}\n", x, y, source);
+            }
+            if let Some([x, y]) = pixel {
+                let (x, y) = (x as usize, y as usize);
+                // without the colorization source, if any.
+                let color = ppu.screen.row_iter().nth(y).map_or(0, |row| row[x] & 0b11);
+                text += &format!("({}, {}) {}{}", x, y, tr("screen_view.color"), color);
+            }
+        }
+        ctx.get_graphic_mut(self.info_text)
+            .set_text(text.trim_end());
+    }
+}
+impl Behaviour for ScreenView {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if !event.is::<FrameUpdated>() || !ctx.get::<AppState>().running {
+            return;
+        }
+        let selected = self.selected.is_some();
+        if self.zoom != 1.0 || selected {
+            self.reset_view(ctx);
+        }
+        if self.mouse.take().is_some() || selected {
+            self.update_info(ctx);
+        }
+    }
+
+    fn input_flags(&self) -> InputFlags {
+        InputFlags::MOUSE | InputFlags::SCROLL
+    }
+
+    fn on_scroll_event(&mut self, delta: [f32; 2], _this: Id, ctx: &mut Context) {
+        if ctx.get::<AppState>().running || delta[1] == 0.0 {
+            return;
+        }
+        let old_zoom = self.zoom;
+        let factor = if delta[1] > 0.0 { 1.25 } else { 0.8 };
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+
+        // keep the pixel under the cursor in place.
+        if let Some(pixel) = self.mouse.and_then(|pos| self.pixel_at(pos, ctx)) {
+            for i in 0..2 {
+                self.center[i] = pixel[i] - (pixel[i] - self.center[i]) * old_zoom / self.zoom;
+            }
+        }
+        self.update_view(ctx);
+        self.update_info(ctx);
+    }
+
+    fn on_mouse_event(&mut self, mouse: giui::MouseInfo, _this: Id, ctx: &mut Context) {
+        if ctx.get::<AppState>().running {
+            return;
+        }
+        match mouse.event {
+            MouseEvent::Down(MouseButton::Left) => {
+                self.dragging = true;
+                self.click_start = self.mouse;
+            }
+            MouseEvent::Up(MouseButton::Left) => {
+                self.dragging = false;
+                if self.click_start.take().is_some() {
+                    let pixel = self.mouse.and_then(|pos| self.pixel_at(pos, ctx));
+                    self.selected = pixel.map(|[x, y]| [x as u8, y as u8]);
+                }
+            }
+            MouseEvent::Down(MouseButton::Right) => self.reset_view(ctx),
+            MouseEvent::Exit => {
+                self.mouse = None;
+                self.dragging = false;
+                self.click_start = None;
+            }
+            MouseEvent::Moved => {
+                // a click that moves more than a few pixels is a drag.
+                if let Some(start) = self.click_start {
+                    let [dx, dy] = [mouse.pos[0] - start[0], mouse.pos[1] - start[1]];
+                    if dx * dx + dy * dy > 16.0 {
+                        self.click_start = None;
+                    }
+                }
+                if let (true, Some(last)) = (self.dragging, self.mouse) {
+                    let rect = ctx.get_rect(self.texture);
+                    let [w, h] = self.visible_size();
+                    self.center[0] -= (mouse.pos[0] - last[0]) * w / (rect[2] - rect[0]);
+                    self.center[1] -= (mouse.pos[1] - last[1]) * h / (rect[3] - rect[1]);
+                    self.update_view(ctx);
+                }
+                self.mouse = Some(mouse.pos);
+            }
+            _ => {}
+        }
+        self.update_info(ctx);
+    }
+}
+
+/// Add the zoom, pan and pixel inspection over the game screen. `texture` is the control that
+/// shows the screen texture.
+pub fn build(
+    parent: Id,
+    texture: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let id = ctx.reserve();
+    let info_text = ctx.reserve();
+    ctx.create_control_reserved(id)
+        .parent(parent)
+        .behaviour(ScreenView {
+            texture,
+            info_text,
+            zoom: 1.0,
+            center: ScreenView::size().map(|x| x / 2.0),
+            mouse: None,
+            dragging: false,
+            click_start: None,
+            selected: None,
+            _frame_updated_event: event_table.register(id),
+        })
+        .build(ctx);
+    ctx.create_control_reserved(info_text)
+        .parent(id)
+        .graphic(Text::new(
+            String::new(),
+            (-1, 1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .fill_x(RectFill::ShrinkStart)
+        .fill_y(RectFill::ShrinkEnd)
+        .build(ctx);
+}
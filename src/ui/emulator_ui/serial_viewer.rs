@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use gameroy::gameboy::GameBoy;
+use giui::{
+    graphics::Text,
+    layouts::{FitGraphic, VBoxLayout},
+    widgets::{TextField, TextFieldCallback},
+    Behaviour, BuilderContext, Context, Id,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, FrameTick, Handle},
+    localization::tr,
+    style::Style,
+    ui::scroll_viewer,
+    SerialOutput,
+};
+
+use super::console::append_log;
+
+/// Show the bytes sent through the serial port.
+struct SerialViewer {
+    scroll: Id,
+    log: Id,
+    /// How many bytes of the serial output were already shown.
+    serial_read: usize,
+    _frame_tick_event: Handle<FrameTick>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl Behaviour for SerialViewer {
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if !(event.is::<FrameTick>() || event.is::<EmulatorUpdated>()) {
+            return;
+        }
+        let text: String = {
+            let serial = ctx.get::<SerialOutput>().0.lock();
+            let text = serial[self.serial_read..]
+                .iter()
+                .map(|&c| c as char)
+                .collect();
+            self.serial_read = serial.len();
+            text
+        };
+        if !text.is_empty() {
+            append_log(ctx, self.scroll, self.log, &text);
+        }
+    }
+}
+
+/// Queue the submitted bytes in the stubbed link partner, to be received by the game.
+struct LinkPartnerCallback {
+    scroll: Id,
+    log: Id,
+}
+impl TextFieldCallback for LinkPartnerCallback {
+    fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
+        let bytes: Result<Vec<u8>, _> = text
+            .split_ascii_whitespace()
+            .map(|x| u8::from_str_radix(x, 16))
+            .collect();
+        match bytes {
+            Ok(bytes) => {
+                let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+                gb.serial.borrow_mut().link_partner.extend(bytes);
+            }
+            Err(_) => {
+                let message = format!("'{}' is not a list of hexadecimal bytes\n", text);
+                append_log(ctx, self.scroll, self.log, &message);
+                return;
+            }
+        }
+        text.clear();
+    }
+
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        _event: giui::KeyboardEvent,
+        _this: Id,
+        _ctx: &mut Context,
+    ) -> bool {
+        false
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let vbox = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+
+    let scroll = ctx.reserve();
+    let content = ctx.reserve();
+    let log = ctx.reserve();
+    scroll_viewer(ctx, scroll, content, style, (true, true))
+        .parent(vbox)
+        .graphic(style.terminal_background.clone())
+        .expand_y(true)
+        .build(ctx);
+    ctx.create_control_reserved(log)
+        .graphic(Text::new(
+            String::new(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .behaviour(SerialViewer {
+            scroll,
+            log,
+            serial_read: 0,
+            _frame_tick_event: event_table.register(log),
+            _emulator_updated_event: event_table.register(log),
+        })
+        .parent(content)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(vbox)
+        .graphic(Text::new(
+            tr("serial_viewer.link_partner").to_string(),
+            (-1, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let caret = ctx.reserve();
+    let label = ctx.reserve();
+    let text_field = ctx
+        .create_control()
+        .parent(vbox)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            LinkPartnerCallback { scroll, log },
+        ))
+        .min_size([20.0; 2])
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(text_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(text_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+}
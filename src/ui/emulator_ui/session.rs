@@ -0,0 +1,148 @@
+//! Export and import of debugger sessions, from the command palette or with the `session`
+//! command. See `gameroy::debugger::session`.
+
+use std::{path::Path, sync::Arc};
+
+use gameroy::{
+    debugger::{session::Session, Debugger},
+    gameboy::GameBoy,
+};
+use giui::Context;
+use parking_lot::Mutex;
+
+use super::DebugLayout;
+use crate::EmulatorEvent;
+
+/// Take a session of the current state, debugger and layout.
+fn take(ctx: &mut Context) -> Result<Session, String> {
+    let mut state = Vec::new();
+    let rom_crc32 = {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        gb.save_state(crate::emulator::timestamp(), &mut state)
+            .map_err(|x| x.to_string())?;
+        crate::game_database::crc32(&gb.cartridge.rom)
+    };
+    let commands = ctx.get::<Arc<Mutex<Debugger>>>().lock().session_commands();
+    let layout = ctx.get::<DebugLayout>().to_session();
+    Ok(Session {
+        rom_crc32,
+        commands,
+        layout,
+        state,
+    })
+}
+
+fn write(session: &Session, path: &Path) -> Result<(), String> {
+    let mut data = Vec::new();
+    session.write(&mut data).map_err(|x| x.to_string())?;
+    std::fs::write(path, data).map_err(|x| format!("'{}': {}", path.display(), x))
+}
+
+/// Save the session in the file at `path`.
+pub fn save(ctx: &mut Context, path: &Path) -> Result<(), String> {
+    write(&take(ctx)?, path)
+}
+
+/// Load the session in the file at `path`: the layout and the debugger are restored immediately,
+/// and the state is loaded by the emulator thread. If some of the debugger commands fail, the
+/// rest of the session is still loaded. A session of another rom is not loaded.
+pub fn load(ctx: &mut Context, path: &Path) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|x| format!("'{}': {}", path.display(), x))?;
+    let session = Session::read(&data).map_err(|x| format!("'{}': {}", path.display(), x))?;
+    let rom_crc32 =
+        crate::game_database::crc32(&ctx.get::<Arc<Mutex<GameBoy>>>().lock().cartridge.rom);
+    session
+        .check_rom(rom_crc32)
+        .map_err(|x| format!("'{}': {}", path.display(), x))?;
+
+    ctx.get::<DebugLayout>()
+        .clone()
+        .apply_session(&session, ctx);
+    let result = {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+        debugger.restore_session(&gb, &session.commands)
+    };
+    ctx.get::<flume::Sender<EmulatorEvent>>()
+        .send(EmulatorEvent::LoadStateData(session.state))
+        .unwrap();
+    result
+}
+
+/// Handle the `session save <file>` and `session load <file>` commands of the debugger.
+pub fn command(ctx: &mut Context, args: &[&str]) -> Result<(), String> {
+    match args {
+        ["session", "save", path] => save(ctx, Path::new(path)),
+        ["session", "load", path] => load(ctx, Path::new(path)),
+        _ => Err("'session' expect 'save' or 'load', and a file".to_string()),
+    }
+}
+
+/// Ask the user where to save the session, and save it. The session is taken before the dialog
+/// is shown.
+#[cfg(all(feature = "rfd", not(target_arch = "wasm32")))]
+pub fn export_dialog(ctx: &mut Context) {
+    use winit::event_loop::EventLoopProxy;
+
+    use crate::{localization::tr, UserEvent};
+
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let session = match take(ctx) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("error exporting session: {}", err);
+            let _ = proxy.send_event(UserEvent::Notify(err));
+            return;
+        }
+    };
+    let handle = ctx.get::<std::rc::Rc<winit::window::Window>>().clone();
+    let task = async move {
+        let handle = &*handle;
+        let file = rfd::AsyncFileDialog::new()
+            .set_title(tr("dialog.export_session"))
+            .add_filter(tr("dialog.session_filter"), &["session"])
+            .set_file_name("debugger.session")
+            .set_parent(handle)
+            .save_file()
+            .await;
+
+        let Some(file) = file else {
+            return;
+        };
+        let message = match write(&session, file.path()) {
+            Ok(()) => tr("notification.session_exported").to_string(),
+            Err(err) => {
+                log::error!("error exporting session: {}", err);
+                err
+            }
+        };
+        let _ = proxy.send_event(UserEvent::Notify(message));
+    };
+    crate::executor::Executor::spawn_task(task, ctx);
+}
+
+/// Ask the user for a session file, and load it. See `super::import_session`.
+#[cfg(all(feature = "rfd", not(target_arch = "wasm32")))]
+pub fn import_dialog(ctx: &mut Context) {
+    use winit::event_loop::EventLoopProxy;
+
+    use crate::{localization::tr, UserEvent};
+
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let handle = ctx.get::<std::rc::Rc<winit::window::Window>>().clone();
+    let task = async move {
+        let handle = &*handle;
+        let file = rfd::AsyncFileDialog::new()
+            .set_title(tr("dialog.import_session"))
+            .add_filter(tr("dialog.session_filter"), &["session"])
+            .set_parent(handle)
+            .pick_file()
+            .await;
+
+        if let Some(file) = file {
+            let path = file.path().to_path_buf();
+            let _ = proxy.send_event(UserEvent::ImportSession(path));
+        }
+    };
+    crate::executor::Executor::spawn_task(task, ctx);
+}
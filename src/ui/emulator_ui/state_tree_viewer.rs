@@ -0,0 +1,183 @@
+use std::any::Any;
+
+use giui::{
+    graphics::{Graphic, Text},
+    layouts::{FitGraphic, VBoxLayout},
+    widgets::{Button, ListBuilder, TextField, TextFieldCallback, UpdateItems},
+    BuilderContext, Context, ControlBuilder, Id,
+};
+
+use crate::{
+    event_table::{EventTable, Handle, StateTreeUpdated},
+    localization::tr,
+    state_tree::StateTree,
+    style::Style,
+    ui, EmulatorEvent,
+};
+
+/// List the nodes of the state tree. Clicking a node loads its state.
+struct StateList {
+    _state_tree_updated_event: Handle<StateTreeUpdated>,
+}
+impl StateList {
+    fn get_text(ctx: &mut dyn BuilderContext, index: usize) -> String {
+        let tree = ctx.get::<StateTree>();
+        let (depth, node) = tree.walk()[index];
+        let marker = if tree.current == Some(node.id) {
+            "> "
+        } else {
+            "  "
+        };
+        format!("{}{}{}", "  ".repeat(depth), marker, node.label())
+    }
+}
+impl ListBuilder for StateList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<StateTreeUpdated>() {
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, ctx: &mut dyn BuilderContext) -> usize {
+        ctx.get::<StateTree>().nodes.len()
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let text = Self::get_text(ctx, index);
+        let style = ctx.get::<Style>().clone();
+        cb.behaviour(Button::new(style.delete_button, true, move |_, ctx| {
+            let id = ctx.get::<StateTree>().walk()[index].1.id;
+            ctx.get::<flume::Sender<EmulatorEvent>>()
+                .send(EmulatorEvent::LoadBranch(id))
+                .unwrap();
+        }))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(text, (-1, 0), style.text_style))
+                .layout(FitGraphic)
+        })
+    }
+
+    fn update_item(&mut self, index: usize, item_id: Id, ctx: &mut dyn BuilderContext) -> bool {
+        let text = Self::get_text(ctx, index);
+        let text_id = ctx.get_active_children(item_id)[0];
+        if let Graphic::Text(x) = ctx.get_graphic_mut(text_id) {
+            x.set_string(&text);
+        }
+        true
+    }
+}
+
+/// Save a new branch, with the submitted name.
+struct BranchCallback;
+impl TextFieldCallback for BranchCallback {
+    fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
+        let name = text.trim().to_string();
+        ctx.get::<flume::Sender<EmulatorEvent>>()
+            .send(EmulatorEvent::BranchState(name))
+            .unwrap();
+        text.clear();
+    }
+
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        _event: giui::KeyboardEvent,
+        _this: Id,
+        _ctx: &mut Context,
+    ) -> bool {
+        false
+    }
+}
+
+/// Handle the state tree commands of the debugger: `branch [name]`, `loadbranch <id>` and
+/// `namebranch <id> <name>`.
+pub fn command(sender: &flume::Sender<EmulatorEvent>, args: &[&str]) -> Result<(), String> {
+    let parse_id = |arg: Option<&&str>| -> Result<u32, String> {
+        let arg = arg.ok_or_else(|| format!("'{}' expect a state id", args[0]))?;
+        arg.trim_start_matches('#')
+            .parse()
+            .map_err(|_| format!("'{}' is not a state id", arg))
+    };
+    let event = match args[0] {
+        "branch" => EmulatorEvent::BranchState(args[1..].join(" ")),
+        "loadbranch" => EmulatorEvent::LoadBranch(parse_id(args.get(1))?),
+        "namebranch" => {
+            let id = parse_id(args.get(1))?;
+            EmulatorEvent::RenameBranch(id, args.get(2..).unwrap_or_default().join(" "))
+        }
+        _ => return Err(format!("unknown command '{}'", args[0])),
+    };
+    sender.send(event).unwrap();
+    Ok(())
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let vbox = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+
+    let list = ctx.reserve();
+    ui::list(
+        ctx.create_control_reserved(list)
+            .parent(vbox)
+            .expand_y(true)
+            .min_size([50.0, 100.0]),
+        ctx,
+        style,
+        [10.0, 0.0, 0.0, 0.0],
+        StateList {
+            _state_tree_updated_event: event_table.register(list),
+        },
+    )
+    .build(ctx);
+
+    ctx.create_control()
+        .parent(vbox)
+        .graphic(Text::new(
+            tr("state_tree.branch_name").to_string(),
+            (-1, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let caret = ctx.reserve();
+    let label = ctx.reserve();
+    let text_field = ctx
+        .create_control()
+        .parent(vbox)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            BranchCallback,
+        ))
+        .min_size([20.0; 2])
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(text_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(text_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+}
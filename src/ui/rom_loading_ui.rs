@@ -18,17 +18,20 @@ use crate::{
     config::config,
     event_table::{self, EventTable},
     executor,
+    localization::{tr, tr_arg},
     rom_loading::{load_gameboy, RomFile},
     style::Style,
     widget::table_item::{TableGroup, TableItem},
     UserEvent,
 };
 
-const COLLUMNS: &[(&str, f32)] = &[
-    ("File", 490.0),
-    ("Header Name", 129.0),
-    ("Size", 60.0),
-    ("Last played", 100.0),
+/// The name, the translation key and the width of each collumn. The name is used in the config.
+const COLLUMNS: &[(&str, &str, f32)] = &[
+    ("File", "rom_list.file", 490.0),
+    ("Header Name", "rom_list.header_name", 129.0),
+    ("Size", "rom_list.size", 60.0),
+    ("Last played", "rom_list.last_played", 100.0),
+    ("Play Time", "rom_list.play_time", 80.0),
 ];
 
 struct SortBy(usize);
@@ -70,7 +73,7 @@ impl RomEntries {
 
                 let col = COLLUMNS
                     .iter()
-                    .position(|&(title, _)| sort_config.contains(&title.to_lowercase()))
+                    .position(|&(title, _, _)| sort_config.contains(&title.to_lowercase()))
                     .unwrap_or_else(|| {
                         log::error!("Unkown collumn name '{}'", sort_config);
                         0
@@ -104,7 +107,7 @@ impl RomEntries {
         self.sort_collumn = collumn_index;
 
         {
-            if let Some((title, _)) = COLLUMNS.get(collumn_index) {
+            if let Some((title, _, _)) = COLLUMNS.get(collumn_index) {
                 let dir = match self.sort_direction {
                     SortDirection::Ascending => "+".to_string(),
                     SortDirection::Descending => "-".to_string(),
@@ -134,7 +137,7 @@ impl RomEntries {
             let a = entries[*a].read().unwrap();
             let b = entries[*b].read().unwrap();
 
-            fn some_first<T, U: Ord>(a: &T, b: &T, map: impl Fn(&T) -> &Option<U>) -> Ordering {
+            fn some_first<T, U: Ord>(a: &T, b: &T, map: impl Fn(&T) -> Option<&U>) -> Ordering {
                 let a = map(a);
                 let b = map(b);
                 a.is_some()
@@ -145,9 +148,10 @@ impl RomEntries {
 
             let ord = match sort_collumn {
                 0 => a.file.file_name().cmp(&b.file.file_name()),
-                1 => some_first(&*a, &*b, |x| &x.header_name),
-                2 => some_first(&*a, &*b, |x| &x.size),
-                3 => a.save_time.cmp(&b.save_time).reverse(),
+                1 => some_first(&*a, &*b, |x| x.title.as_ref().or(x.header_name.as_ref())),
+                2 => some_first(&*a, &*b, |x| x.size.as_ref()),
+                3 => a.last_played().cmp(&b.last_played()).reverse(),
+                4 => a.play_time.cmp(&b.play_time).reverse(),
                 _ => {
                     log::error!("Unknown collumn index: {}", sort_collumn);
                     Ordering::Equal
@@ -196,6 +200,7 @@ impl RomEntries {
 
         let f = move || {
             let start = instant::Instant::now();
+            let play_stats = crate::play_stats::PlayStatsStore::load();
 
             let roms = crate::rom_loading::load_roms(&roms_path)
                 .map_err(|e: String| log::error!("error reading roms: {}", e))
@@ -210,8 +215,11 @@ impl RomEntries {
                     RwLock::new(RomEntry {
                         file: x,
                         header_name: None,
+                        title: None,
                         size: None,
                         save_time: save_time.ok(),
+                        last_played: None,
+                        play_time: None,
                         thumbnail: None,
                     })
                 })
@@ -229,6 +237,7 @@ impl RomEntries {
                     let proxy = proxy.clone();
                     let entries = entries.clone();
                     let index = &index;
+                    let play_stats = &play_stats;
                     s.spawn(move || loop {
                         let entries = entries.read().unwrap();
                         let index = index.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -236,16 +245,32 @@ impl RomEntries {
                             break;
                         };
 
-                        let (header, file_name) = {
+                        let (header, game, stats, file_name, rom_file) = {
                             let rom_file = entry.read().unwrap().file.clone();
                             let file_name =
                                 rom_file.file_name().trim_end_matches(".gb").to_string();
                             let header = rom_file.get_header();
-                            (header, file_name)
+                            let hash = rom_file
+                                .get_hash()
+                                .map_err(|err| {
+                                    log::error!("error hashing '{}': {}", file_name, err)
+                                })
+                                .ok();
+                            let game = hash.and_then(crate::game_database::lookup_hash);
+                            let stats = hash.and_then(|x| play_stats.get(x)).cloned();
+                            (header, game, stats, file_name, rom_file)
                         };
 
+                        // if there is no thumbnail, use the title screen of the game.
+                        let thumb = crate::rom_loading::get_thumb(&file_name).or_else(|err| {
+                            log::info!("get thumbnail failed: {err}");
+                            rom_file
+                                .read_blocking()
+                                .and_then(|rom| crate::rom_loading::generate_thumb(&file_name, rom))
+                        });
+
                         let mut thumbnail = None;
-                        match crate::rom_loading::get_thumb(&file_name) {
+                        match thumb {
                             Ok(buffer) => {
                                 let texture_id =
                                     (crate::style::hash(file_name.as_bytes()) & 0x7fff_ffff) as u32;
@@ -268,13 +293,18 @@ impl RomEntries {
                                 thumbnail = Some(texture_id);
                             }
                             Err(err) => {
-                                log::info!("get thumbnail failed: {err}");
+                                log::info!("generate thumbnail failed: {err}");
                             }
                         }
 
                         {
                             let mut entry = entry.write().unwrap();
                             entry.thumbnail = thumbnail;
+                            entry.title = game.map(|x| x.full_title());
+                            if let Some(stats) = stats {
+                                entry.last_played = stats.last_played;
+                                entry.play_time = Some(stats.play_time);
+                            }
                             match header {
                                 Ok(header) => {
                                     entry.header_name = Some(header.title_as_string());
@@ -282,7 +312,8 @@ impl RomEntries {
                                         Some(header.rom_size_in_bytes().unwrap_or(0) as u64);
                                 }
                                 Err(err) => {
-                                    entry.header_name = Some("Error reading header...".to_string());
+                                    entry.header_name =
+                                        Some(tr("rom_list.error_reading_header").to_string());
                                     entry.size = None;
                                     log::error!(
                                         "error reading '{}' header: {}",
@@ -328,10 +359,17 @@ impl RomEntries {
 pub struct RomEntry {
     /// The name of the game as write in the rom header.
     header_name: Option<String>,
+    /// The canonical name of the game, if it is in the game database.
+    title: Option<String>,
     /// The size of the rom file in bytes
     size: Option<u64>,
     /// The instant in millisenconds since epoch of this rom's ram save file
     save_time: Option<u64>,
+    /// The instant in millisenconds since epoch of the last launch of this rom, from the play
+    /// stats.
+    last_played: Option<u64>,
+    /// The total time this rom was played, in seconds, from the play stats.
+    play_time: Option<u64>,
     /// The path to the rom
     pub file: RomFile,
     /// The index of the texture that contains this Rom thumbnail
@@ -339,9 +377,10 @@ pub struct RomEntry {
 }
 impl RomEntry {
     pub fn name(&self) -> String {
-        self.header_name
+        self.title
             .clone()
-            .unwrap_or_else(|| "Loading...".to_string())
+            .or_else(|| self.header_name.clone())
+            .unwrap_or_else(|| tr("rom_list.loading").to_string())
     }
 
     fn size(&self) -> String {
@@ -356,40 +395,51 @@ impl RomEntry {
         }
     }
 
-    fn save_age(&self) -> String {
-        use instant::{Duration, SystemTime};
+    /// The last time the rom was launched, or its game was saved, whichever is later. The roms
+    /// played before the play stats existed only have the time of the save.
+    fn last_played(&self) -> Option<u64> {
+        self.last_played.max(self.save_time)
+    }
 
-        let last_played = match self.save_time {
-            Some(x) => x,
-            None => return " - ".to_string(),
-        };
-        let then = SystemTime::UNIX_EPOCH.add(Duration::from_millis(last_played));
-        let now = SystemTime::now();
+    fn play_time(&self) -> String {
+        crate::play_stats::format_play_time(self.play_time.unwrap_or(0))
+    }
+}
 
-        let delta = now.duration_since(then);
+/// How long ago the given instant, in milliseconds since epoch, was, like "3d ago".
+fn format_age(time: Option<u64>) -> String {
+    use instant::{Duration, SystemTime};
 
-        let delta = match delta {
-            Ok(x) => x.as_secs(),
-            Err(_) => return " - ".to_string(),
-        };
+    let last_played = match time {
+        Some(x) => x,
+        None => return " - ".to_string(),
+    };
+    let then = SystemTime::UNIX_EPOCH.add(Duration::from_millis(last_played));
+    let now = SystemTime::now();
 
-        const SECOND: u64 = 1;
-        const MINUTE: u64 = 60 * SECOND;
-        const HOUR: u64 = 60 * MINUTE;
-        const DAY: u64 = 24 * HOUR;
-        // const WEEK: u64 = 7 * DAY;
-        const MONTH: u64 = 30 * DAY;
-        const YEAR: u64 = 365 * DAY;
-
-        match delta {
-            x if x < SECOND => "Just Now".to_string(),
-            x if x < MINUTE => format!("{}s agi", x / SECOND),
-            x if x < HOUR => format!("{}min ago", x / MINUTE),
-            x if x < DAY => format!("{}h ago", x / HOUR),
-            x if x < MONTH => format!("{}d ago", x / DAY),
-            x if x < YEAR => format!("{}m ago", x / MONTH),
-            x => format!("{} years", x / YEAR),
-        }
+    let delta = now.duration_since(then);
+
+    let delta = match delta {
+        Ok(x) => x.as_secs(),
+        Err(_) => return " - ".to_string(),
+    };
+
+    const SECOND: u64 = 1;
+    const MINUTE: u64 = 60 * SECOND;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    // const WEEK: u64 = 7 * DAY;
+    const MONTH: u64 = 30 * DAY;
+    const YEAR: u64 = 365 * DAY;
+
+    match delta {
+        x if x < SECOND => tr("age.just_now").to_string(),
+        x if x < MINUTE => tr_arg("age.seconds", x / SECOND),
+        x if x < HOUR => tr_arg("age.minutes", x / MINUTE),
+        x if x < DAY => tr_arg("age.hours", x / HOUR),
+        x if x < MONTH => tr_arg("age.days", x / DAY),
+        x if x < YEAR => tr_arg("age.months", x / MONTH),
+        x => tr_arg("age.years", x / YEAR),
     }
 }
 
@@ -473,23 +523,26 @@ impl ListBuilder for RomList {
     ) -> giui::ControlBuilder {
         let style = &ctx.get::<Style>().clone();
         let header = index == 0;
-        let (file, name, size, age, entry) = if !header {
+        let (file, name, size, age, play_time, entry) = if !header {
             let entry = ctx.get::<RomEntries>().get_rom(index - 1).unwrap();
             let size = entry.size();
-            let age = entry.save_age();
+            let age = format_age(entry.last_played());
+            let play_time = entry.play_time();
             (
                 entry.file.file_name().into_owned(),
                 entry.name(),
                 size,
                 age,
+                play_time,
                 Some(entry),
             )
         } else {
             (
-                COLLUMNS[0].0.to_string(),
-                COLLUMNS[1].0.to_string(),
-                COLLUMNS[2].0.to_string(),
-                COLLUMNS[3].0.to_string(),
+                tr(COLLUMNS[0].1).to_string(),
+                tr(COLLUMNS[1].1).to_string(),
+                tr(COLLUMNS[2].1).to_string(),
+                tr(COLLUMNS[3].1).to_string(),
+                tr(COLLUMNS[4].1).to_string(),
                 None,
             )
         };
@@ -515,7 +568,7 @@ impl ListBuilder for RomList {
                 .build(ctx);
         }
 
-        for (collumn_index, text) in [file, name, size, age].into_iter().enumerate() {
+        for (collumn_index, text) in [file, name, size, age, play_time].into_iter().enumerate() {
             let cb = ctx
                 .create_control()
                 .parent(parent)
@@ -576,10 +629,11 @@ impl ListBuilder for RomList {
                                     None
                                 }
                             };
-                            let game_boy = match load_gameboy(rom, ram) {
+                            let game_boy = match load_gameboy(rom, ram, &file.file_name()) {
                                 Ok(x) => x,
                                 Err(err) => {
                                     log::error!("failed to load rom: {}", err);
+                                    p.send_event(UserEvent::LoadFailed(err)).unwrap();
                                     return;
                                 }
                             };
@@ -595,6 +649,51 @@ impl ListBuilder for RomList {
     }
 }
 
+/// Open a native file dialog for picking a rom, and load the chosen one. The dialog starts in the
+/// folder of the last opened rom.
+#[cfg(feature = "rfd")]
+pub fn open_rom_dialog(ctx: &mut giui::Context) {
+    let handle = ctx.get::<Rc<winit::window::Window>>().clone();
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let task = async move {
+        let handle = &*handle;
+        let dialog = rfd::AsyncFileDialog::new()
+            .set_title(tr("dialog.open_rom"))
+            .add_filter(tr("dialog.rom_filter"), &["gb"])
+            .set_parent(handle);
+        #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+        let dialog = match crate::rom_loading::last_rom_folder() {
+            Some(folder) => dialog.set_directory(folder),
+            None => dialog,
+        };
+        let file = dialog.pick_file().await;
+
+        if let Some(file) = file {
+            let file: RomFile = file.into();
+            let rom = file.read().await.unwrap();
+            let ram = match file.load_ram_data().await {
+                Ok(x) => Some(x),
+                Err(err) => {
+                    log::error!("{}", err);
+                    None
+                }
+            };
+            let game_boy = match load_gameboy(rom, ram, &file.file_name()) {
+                Ok(x) => x,
+                Err(err) => {
+                    log::error!("failed to load rom: {}", err);
+                    proxy.send_event(UserEvent::LoadFailed(err)).unwrap();
+                    return;
+                }
+            };
+            proxy
+                .send_event(UserEvent::LoadRom { file, game_boy })
+                .unwrap();
+        }
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
+
 pub fn create_rom_loading_ui(
     ctx: &mut giui::Gui,
     style: &Style,
@@ -621,45 +720,14 @@ pub fn create_rom_loading_ui(
         .behaviour(Button::new(
             style.delete_button.clone(),
             true,
-            move |_, ctx| {
-                let handle = ctx.get::<std::rc::Rc<winit::window::Window>>().clone();
-                let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
-                let task = async move {
-                    let handle = &*handle;
-                    let file = rfd::AsyncFileDialog::new()
-                        .set_title("Open GameBoy Rom file")
-                        .add_filter("GameBoy roms", &["gb"])
-                        .set_parent(handle)
-                        .pick_file()
-                        .await;
-
-                    if let Some(file) = file {
-                        let file: RomFile = file.into();
-                        let rom = file.read().await.unwrap();
-                        let ram = match file.load_ram_data().await {
-                            Ok(x) => Some(x),
-                            Err(err) => {
-                                log::error!("{}", err);
-                                None
-                            }
-                        };
-                        proxy
-                            .send_event(UserEvent::LoadRom {
-                                file,
-                                game_boy: load_gameboy(rom, ram).unwrap(),
-                            })
-                            .unwrap();
-                    }
-                };
-                executor::Executor::spawn_task(task, ctx);
-            },
+            move |_, ctx| open_rom_dialog(ctx),
         ))
         .child(ctx, |cb, _| {
             cb.graphic(style.file_icon.clone()).layout(FitGraphic)
         })
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "open rom".to_string(),
+                tr("rom_list.open_rom").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -681,8 +749,8 @@ pub fn create_rom_loading_ui(
                 let task = async move {
                     let handle = &*handle;
                     let folder = rfd::AsyncFileDialog::new()
-                        .set_title("Open GameBoy Rom file")
-                        .add_filter("GameBoy roms", &["gb"])
+                        .set_title(tr("dialog.choose_rom_folder"))
+                        .add_filter(tr("dialog.rom_filter"), &["gb"])
                         .set_parent(handle)
                         .pick_folder()
                         .await;
@@ -708,7 +776,53 @@ pub fn create_rom_loading_ui(
         })
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "choose folder".to_string(),
+                tr("rom_list.choose_folder").to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    let _recent_button = ctx
+        .create_control()
+        .parent(h_box)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| open_recent_roms_menu(ctx, h_box),
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(style.file_icon.clone()).layout(FitGraphic)
+        })
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("rom_list.recent").to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _stats_button = ctx
+        .create_control()
+        .parent(h_box)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| open_play_stats(ctx, h_box),
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(style.file_icon.clone()).layout(FitGraphic)
+        })
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                tr("rom_list.stats").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -738,7 +852,7 @@ pub fn create_rom_loading_ui(
         })
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "licenses".to_string(),
+                tr("rom_list.licenses").to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -751,7 +865,7 @@ pub fn create_rom_loading_ui(
 
         // Thumbnail
         tg.add_column(60.0, false);
-        for &(_, width) in COLLUMNS.iter() {
+        for &(_, _, width) in COLLUMNS.iter() {
             tg.add_column(width, false)
         }
         tg
@@ -770,3 +884,127 @@ pub fn create_rom_loading_ui(
     .expand_y(true)
     .build(ctx);
 }
+
+/// Open a menu listing the recently opened roms.
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+fn open_recent_roms_menu(ctx: &mut giui::Context, focus: Id) {
+    use crate::widget::menu::{create_menu, MenuOption};
+
+    let style = ctx.get::<Style>().clone();
+    let recent: Vec<(String, RomFile)> = crate::rom_loading::recent_roms()
+        .into_iter()
+        .map(|path| {
+            let file = RomFile::from_path(path);
+            (file.file_name().into_owned(), file)
+        })
+        .collect();
+
+    if recent.is_empty() {
+        log::info!("there are no recent roms");
+        return;
+    }
+
+    let options: Vec<MenuOption> = recent
+        .iter()
+        .map(|(name, file)| {
+            let file = file.clone();
+            let load: Box<dyn FnMut(&mut giui::Context)> = Box::new(move |ctx| {
+                let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+                let file = file.clone();
+                let task = async move {
+                    let rom = match file.read().await {
+                        Ok(x) => x,
+                        Err(err) => {
+                            log::error!("{}", err);
+                            return;
+                        }
+                    };
+                    let ram = match file.load_ram_data().await {
+                        Ok(x) => Some(x),
+                        Err(err) => {
+                            log::error!("{}", err);
+                            None
+                        }
+                    };
+                    let game_boy = match load_gameboy(rom, ram, &file.file_name()) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            log::error!("failed to load rom: {}", err);
+                            proxy.send_event(UserEvent::LoadFailed(err)).unwrap();
+                            return;
+                        }
+                    };
+                    proxy
+                        .send_event(UserEvent::LoadRom { file, game_boy })
+                        .unwrap();
+                };
+                executor::Executor::spawn_task(task, ctx);
+            });
+            (name.as_str(), load)
+        })
+        .collect();
+
+    let menu = create_menu(options, move |ctx| ctx.set_focus(focus), ctx, &style);
+    ctx.set_focus(menu);
+}
+
+/// Open a dialog with the play time, launches and last played date of each rom ever played, from
+/// the most played to the least played.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_play_stats(ctx: &mut giui::Context, focus: Id) {
+    use crate::{
+        play_stats::{format_play_time, PlayStatsStore},
+        widget::menu::{create_menu, MenuOption},
+    };
+
+    let style = ctx.get::<Style>().clone();
+    let store = PlayStatsStore::load();
+    let roms = store.by_play_time();
+    let total: u64 = roms.iter().map(|x| x.play_time).sum();
+
+    let mut lines = vec![
+        tr("play_stats.title").to_string(),
+        tr_arg("play_stats.total", format_play_time(total)),
+    ];
+    if roms.is_empty() {
+        lines.push(tr("play_stats.empty").to_string());
+    }
+    for stats in roms {
+        lines.push(format!(
+            "{}: {}, {}, {}",
+            stats.name,
+            format_play_time(stats.play_time),
+            tr_arg("play_stats.launches", stats.launches),
+            format_age(stats.last_played),
+        ));
+    }
+    lines.push(tr("load_error.ok").to_string());
+
+    let options: Vec<MenuOption> = lines
+        .iter()
+        .map(|line| {
+            let close: Box<dyn FnMut(&mut giui::Context)> = Box::new(|_| {});
+            (line.as_str(), close)
+        })
+        .collect();
+    let menu = create_menu(options, move |ctx| ctx.set_focus(focus), ctx, &style);
+    ctx.set_focus(menu);
+}
+
+/// Show why a ROM failed to load.
+pub fn open_load_error(ctx: &mut giui::Context, message: &str) {
+    use crate::widget::menu::{create_menu, MenuOption};
+
+    let style = ctx.get::<Style>().clone();
+    fn option(a: &str, b: impl FnMut(&mut giui::Context) + 'static) -> MenuOption {
+        (a, Box::new(b))
+    }
+
+    let options = vec![
+        option(tr("load_error.title"), |_| {}),
+        option(message, |_| {}),
+        option(tr("load_error.ok"), |_| {}),
+    ];
+    let menu = create_menu(options, |_| {}, ctx, &style);
+    ctx.set_focus(menu);
+}
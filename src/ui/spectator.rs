@@ -0,0 +1,168 @@
+//! A borderless window that only shows the game screen, meant to be captured by streaming
+//! software. It shares the frames of the main screen, but has its own scale and palette.
+
+use sprite_render::{Camera, SpriteInstance, SpriteRender, TextureId};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, MouseButton, WindowEvent},
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
+};
+
+use crate::{UserEvent, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub struct Spectator {
+    window: Window,
+    camera: Camera,
+    texture: u32,
+    /// Maps the gray level of a pixel of the main screen to its color in the spectator palette. If
+    /// there is no spectator palette, or the game is colorized, the colors of the main screen are
+    /// kept.
+    lut: Option<Box<[[u8; 4]; 256]>>,
+    /// The screen in the spectator palette.
+    rgba: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4]>,
+}
+impl Spectator {
+    pub fn new(
+        event_loop: &EventLoopWindowTarget<UserEvent>,
+        render: &mut dyn SpriteRender,
+        texture: u32,
+        scale: u32,
+        palette: Option<[[u8; 3]; 4]>,
+    ) -> Result<Self, winit::error::OsError> {
+        let scale = scale.max(1);
+        let window = WindowBuilder::new()
+            .with_title("gameroy - spectator")
+            .with_decorations(false)
+            .with_inner_size(PhysicalSize::new(
+                SCREEN_WIDTH as u32 * scale,
+                SCREEN_HEIGHT as u32 * scale,
+            ))
+            .build(event_loop)?;
+        render.add_window(&window);
+
+        let size = window.inner_size();
+        let camera = Camera::new(size.width, size.height, size.height as f32);
+
+        let mut this = Self {
+            window,
+            camera,
+            texture,
+            lut: palette.map(|x| Box::new(palette_lut(x))),
+            rgba: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT * 4]),
+        };
+        this.resize(render, size);
+        Ok(this)
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Convert the given rows of the screen to the spectator palette, if any, and upload them.
+    /// `img_data` is the entire screen in RGBA, in the DMG palette unless `colorized` is true.
+    pub fn update(
+        &mut self,
+        render: &mut dyn SpriteRender,
+        img_data: &[u8],
+        rows: std::ops::Range<usize>,
+        colorized: bool,
+    ) {
+        let stride = SCREEN_WIDTH * 4;
+        let range = rows.start * stride..rows.end * stride;
+        // the colors of a colorized screen are not shades of gray, and can't be remapped.
+        match self.lut.as_ref().filter(|_| !colorized) {
+            Some(lut) => {
+                for (color, src) in self.rgba[range.clone()]
+                    .chunks_exact_mut(4)
+                    .zip(img_data[range.clone()].chunks_exact(4))
+                {
+                    color.copy_from_slice(&lut[src[0] as usize]);
+                }
+            }
+            None => self.rgba[range.clone()].copy_from_slice(&img_data[range.clone()]),
+        }
+        render
+            .update_texture(
+                TextureId(self.texture),
+                Some(&self.rgba[range]),
+                Some([0, rows.start as u32, SCREEN_WIDTH as u32, rows.len() as u32]),
+            )
+            .unwrap();
+        self.window.request_redraw();
+    }
+
+    /// Handle a event of the spectator window. Returns false if the window should be closed.
+    pub fn window_event(&mut self, render: &mut dyn SpriteRender, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CloseRequested | WindowEvent::Destroyed => return false,
+            WindowEvent::Resized(size) => self.resize(render, *size),
+            // there is no title bar, so the window is moved by dragging it anywhere.
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let _ = self.window.drag_window();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn resize(&mut self, render: &mut dyn SpriteRender, size: PhysicalSize<u32>) {
+        render.resize(self.window.id(), size.width, size.height);
+        self.camera.resize(size.width, size.height);
+        self.camera.set_width(size.width as f32);
+        self.camera.set_height(size.height as f32);
+        self.camera
+            .set_position((size.width as f32) / 2.0, (size.height as f32) / 2.0);
+    }
+
+    pub fn render(&mut self, render: &mut dyn SpriteRender) {
+        let size = self.window.inner_size();
+        // the largest integer scale that fits in the window, so pixels keep the same size.
+        let scale = (size.width / SCREEN_WIDTH as u32)
+            .min(size.height / SCREEN_HEIGHT as u32)
+            .max(1);
+        let screen = SpriteInstance {
+            scale: [
+                (SCREEN_WIDTH as u32 * scale) as f32,
+                (SCREEN_HEIGHT as u32 * scale) as f32,
+            ],
+            angle: 0.0,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: [255, 255, 255, 255],
+            pos: [(size.width / 2) as f32, (size.height / 2) as f32],
+            texture: TextureId(self.texture),
+        };
+        let mut renderer = render.render(self.window.id());
+        renderer.clear_screen(&[0.0, 0.0, 0.0, 1.0]);
+        renderer.draw_sprites(&mut self.camera, &[screen]);
+        renderer.finish();
+    }
+
+    /// Remove the window from the render. The window is closed when dropped.
+    pub fn close(self, render: &mut dyn SpriteRender) {
+        render.remove_window(self.window.id());
+    }
+}
+
+/// Build a lookup table from the gray levels of `DMG_PALETTE` to `palette`. Gray levels between two
+/// shades, produced by the LCD ghosting, are interpolated.
+fn palette_lut(palette: [[u8; 3]; 4]) -> [[u8; 4]; 256] {
+    let mut lut = [[0, 0, 0, 255]; 256];
+    for (gray, color) in lut.iter_mut().enumerate() {
+        // DMG_PALETTE goes from 255 to 0 in steps of 85.
+        let darkness = 255 - gray as u32;
+        let shade = (darkness / 85) as usize;
+        let t = darkness % 85;
+        let next = (shade + 1).min(3);
+        for (c, channel) in color[..3].iter_mut().enumerate() {
+            let a = palette[shade][c] as u32;
+            let b = palette[next][c] as u32;
+            *channel = ((a * (85 - t) + b * t) / 85) as u8;
+        }
+    }
+    lut
+}
@@ -0,0 +1,340 @@
+//! The wall mode, that runs several roms at the same time, each one in its own emulator thread,
+//! and shows their screens in a grid. Only the selected game receives the input of the keyboard
+//! and is heard, and the selection is changed with `Tab` or by clicking a screen.
+//!
+//! Each game is run by its own `Emulator`, the same as a game played alone, so it keeps the pace,
+//! the colors and the cartridge RAM saves of the normal mode.
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use gameroy::{
+    consts::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    debugger::Debugger,
+    gameboy::GameBoy,
+};
+use parking_lot::Mutex;
+use sprite_render::{Camera, SpriteInstance, SpriteRender, Texture, TextureId};
+use winit::{
+    dpi::{LogicalSize, PhysicalSize},
+    event::{ElementState, Event, KeyboardInput, MouseButton, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
+    window::{Window, WindowBuilder},
+};
+
+use crate::{
+    config::config,
+    emulator::{Emulator, EmulatorEvent},
+    input_latency::InputLatency,
+    snapshot::Snapshot,
+    Frame, FrameSlot, LcdScreen, RomFile, UserEvent,
+};
+
+/// The texture of a single white pixel, drawn behind the selected screen as its border.
+const WHITE_TEXTURE: u32 = 0;
+/// The width of the border around the selected screen, in physical pixels.
+const BORDER: f32 = 3.0;
+/// The interval between each redraw of the window, and each frame requested to the emulators.
+const REDRAW_INTERVAL: Duration = Duration::from_micros(16_667);
+
+/// A game of the wall, and its emulator thread.
+struct Instance {
+    title: String,
+    texture: TextureId,
+    emu_channel: flume::Sender<EmulatorEvent>,
+    emu_thread: Option<thread::JoinHandle<()>>,
+    /// Where the emulator publishes its frames.
+    frame_slot: Arc<Mutex<FrameSlot>>,
+    /// The frame being shown.
+    front_frame: Frame,
+}
+impl Instance {
+    fn new(
+        index: usize,
+        file: RomFile,
+        mut gb: Box<GameBoy>,
+        render: &mut dyn SpriteRender,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> Self {
+        let texture = TextureId(index as u32 + 1);
+        Texture::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+            .id(texture)
+            .filter(sprite_render::TextureFilter::Nearest)
+            .create(render)
+            .unwrap();
+
+        let (mut lcd_screen, front_frame) = LcdScreen::for_game(&mut gb);
+        let frame_slot = lcd_screen.slot.clone();
+        // the window is redrawn at a fixed interval, so the frames are not notified.
+        gb.v_blank = Some(Box::new(move |gb| {
+            lcd_screen.update(&gb.ppu.borrow().screen);
+        }));
+
+        let (emu_channel, recv) = flume::bounded(8);
+        emu_channel.send(EmulatorEvent::Mute(index != 0)).unwrap();
+        let title = file.file_name().into_owned();
+        let emu_thread = thread::Builder::new()
+            .name(format!("wall {}", index))
+            .spawn(move || {
+                Emulator::run(
+                    Arc::new(Mutex::new(*gb)),
+                    Arc::new(Mutex::new(Debugger::default())),
+                    Snapshot::default(),
+                    InputLatency::new(false),
+                    proxy,
+                    None,
+                    file,
+                    recv,
+                );
+            })
+            .unwrap();
+
+        Self {
+            title,
+            texture,
+            emu_channel,
+            emu_thread: Some(emu_thread),
+            frame_slot,
+            front_frame,
+        }
+    }
+
+    fn send(&self, event: EmulatorEvent) {
+        // the emulator is already killed, when the window is closing.
+        let _ = self.emu_channel.send(event);
+    }
+
+    /// Upload the last frame of the game to its texture, if there is a new one.
+    fn update_texture(&mut self, render: &mut dyn SpriteRender) {
+        let rows = self.frame_slot.lock().take(&mut self.front_frame);
+        if !rows.is_empty() {
+            render
+                .update_texture(self.texture, Some(&self.front_frame.rgba[..]), None)
+                .unwrap();
+        }
+    }
+
+    /// Kill the emulator, and wait for it to save the cartridge RAM.
+    fn kill(&mut self) {
+        self.send(EmulatorEvent::Kill);
+        if let Some(emu_thread) = self.emu_thread.take() {
+            if emu_thread.join().is_err() {
+                log::error!("emulator thread of '{}' panicked", self.title);
+            }
+        }
+    }
+}
+
+/// The placement of the screens in the window: a grid with the same number of columns and rows,
+/// or one more column.
+struct Grid {
+    count: usize,
+    columns: usize,
+    /// The size of each cell of the grid.
+    cell: [f32; 2],
+}
+impl Grid {
+    fn new(count: usize, size: PhysicalSize<u32>) -> Self {
+        let columns = ((count as f64).sqrt().ceil() as usize).max(1);
+        let rows = count.div_ceil(columns).max(1);
+        Self {
+            count,
+            columns,
+            cell: [
+                size.width as f32 / columns as f32,
+                size.height as f32 / rows as f32,
+            ],
+        }
+    }
+
+    /// The center and the size of the screen of the given game, the largest that fits in its cell
+    /// with room for the border.
+    fn screen(&self, index: usize) -> ([f32; 2], [f32; 2]) {
+        let [width, height] = self.cell;
+        let column = (index % self.columns) as f32;
+        let row = (index / self.columns) as f32;
+        let center = [(column + 0.5) * width, (row + 0.5) * height];
+        let scale = ((width - 2.0 * BORDER) / SCREEN_WIDTH as f32)
+            .min((height - 2.0 * BORDER) / SCREEN_HEIGHT as f32)
+            .max(0.0);
+        let size = [SCREEN_WIDTH as f32 * scale, SCREEN_HEIGHT as f32 * scale];
+        (center, size)
+    }
+
+    /// The game whose cell contains the given position of the window, if any.
+    fn game_at(&self, [x, y]: [f32; 2]) -> Option<usize> {
+        let column = (x / self.cell[0]) as usize;
+        let row = (y / self.cell[1]) as usize;
+        let index = row * self.columns + column;
+        (x >= 0.0 && y >= 0.0 && column < self.columns && index < self.count).then_some(index)
+    }
+}
+
+fn resize(
+    window: &Window,
+    render: &mut dyn SpriteRender,
+    camera: &mut Camera,
+    size: PhysicalSize<u32>,
+) {
+    let PhysicalSize { width, height } = size;
+    render.resize(window.id(), width, height);
+    camera.resize(width, height);
+    camera.set_width(width as f32);
+    camera.set_height(height as f32);
+    camera.set_position(width as f32 / 2.0, height as f32 / 2.0);
+}
+
+fn set_title(window: &Window, instances: &[Instance], selected: usize) {
+    window.set_title(&format!(
+        "{} ({}/{}) - gameroy wall",
+        instances[selected].title,
+        selected + 1,
+        instances.len()
+    ));
+}
+
+/// Select the game at `index`, releasing the buttons held in the previous one and muting it.
+fn select(window: &Window, instances: &[Instance], selected: &mut usize, index: usize) {
+    instances[*selected].send(EmulatorEvent::SetJoypad(0xFF));
+    instances[*selected].send(EmulatorEvent::Mute(true));
+    *selected = index;
+    instances[*selected].send(EmulatorEvent::Mute(false));
+    set_title(window, instances, *selected);
+}
+
+/// Run the given games in the wall mode. Never returns.
+pub fn main(games: Vec<(RomFile, Box<GameBoy>)>) -> ! {
+    assert!(!games.is_empty(), "the wall needs at least one game");
+
+    let event_loop = EventLoopBuilder::with_user_event().build();
+    let window = WindowBuilder::new()
+        .with_inner_size(LogicalSize::new(768, 576))
+        .with_title("gameroy wall")
+        .build(&event_loop)
+        .unwrap();
+    let mut render = crate::render::create_render(&window);
+    Texture::new(1, 1)
+        .id(TextureId(WHITE_TEXTURE))
+        .data(&[255, 255, 255, 255])
+        .create(render.as_mut())
+        .unwrap();
+
+    crate::signal::handle_termination(event_loop.create_proxy());
+
+    let mut instances: Vec<Instance> = games
+        .into_iter()
+        .enumerate()
+        .map(|(i, (file, gb))| {
+            Instance::new(i, file, gb, render.as_mut(), event_loop.create_proxy())
+        })
+        .collect();
+
+    let mut selected = 0;
+    set_title(&window, &instances, selected);
+    let mut joypad = 0xFF;
+    let mut cursor = [0.0; 2];
+    let size = window.inner_size();
+    let mut camera = Camera::new(size.width, size.height, size.height as f32);
+    let mut grid = Grid::new(instances.len(), size);
+    resize(&window, render.as_mut(), &mut camera, size);
+
+    event_loop.run(move |event, _, control| {
+        let quit = |instances: &mut [Instance], control: &mut ControlFlow| {
+            for instance in instances {
+                instance.kill();
+            }
+            *control = ControlFlow::Exit;
+        };
+        match event {
+            Event::NewEvents(StartCause::Init | StartCause::ResumeTimeReached { .. }) => {
+                window.request_redraw();
+                *control = ControlFlow::WaitUntil(Instant::now() + REDRAW_INTERVAL);
+            }
+            Event::UserEvent(UserEvent::Quit) => quit(&mut instances, control),
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => quit(&mut instances, control),
+                WindowEvent::Resized(size) => {
+                    resize(&window, render.as_mut(), &mut camera, size);
+                    grid = Grid::new(instances.len(), size);
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    cursor = [position.x as f32, position.y as f32];
+                }
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(index) = grid.game_at(cursor) {
+                        select(&window, &instances, &mut selected, index);
+                        joypad = 0xFF;
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    use winit::event::VirtualKeyCode::Tab;
+                    let pressed = state == ElementState::Pressed;
+                    if key == Tab {
+                        if pressed {
+                            let next = (selected + 1) % instances.len();
+                            select(&window, &instances, &mut selected, next);
+                            joypad = 0xFF;
+                        }
+                        return;
+                    }
+                    let km = &config().keymap;
+                    let buttons = [
+                        km.right, km.left, km.up, km.down, km.a, km.b, km.select, km.start,
+                    ];
+                    if let Some(bit) = buttons.iter().position(|&x| x == key) {
+                        joypad = (joypad & !(1 << bit)) | ((!pressed as u8) << bit);
+                        instances[selected].send(EmulatorEvent::SetJoypad(joypad));
+                    }
+                }
+                _ => {}
+            },
+            Event::RedrawRequested(_) => {
+                let mut sprites = Vec::with_capacity(instances.len() + 1);
+                for (i, instance) in instances.iter_mut().enumerate() {
+                    instance.update_texture(render.as_mut());
+                    instance.send(EmulatorEvent::RunFrame);
+
+                    let (pos, [width, height]) = grid.screen(i);
+                    if i == selected {
+                        sprites.push(SpriteInstance {
+                            scale: [width + 2.0 * BORDER, height + 2.0 * BORDER],
+                            angle: 0.0,
+                            uv_rect: [0.0, 0.0, 1.0, 1.0],
+                            color: [255, 200, 0, 255],
+                            pos,
+                            texture: TextureId(WHITE_TEXTURE),
+                        });
+                    }
+                    sprites.push(SpriteInstance {
+                        scale: [width, height],
+                        angle: 0.0,
+                        uv_rect: [0.0, 0.0, 1.0, 1.0],
+                        color: [255, 255, 255, 255],
+                        pos,
+                        texture: instance.texture,
+                    });
+                }
+                let mut renderer = render.render(window.id());
+                renderer.clear_screen(&[0.0, 0.0, 0.0, 1.0]);
+                renderer.draw_sprites(&mut camera, &sprites);
+                renderer.finish();
+            }
+            _ => {}
+        }
+    })
+}
@@ -1,9 +1,12 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use giui::{
     layouts::{FitGraphic, MarginLayout, VBoxLayout},
     text::Text,
-    widgets::{Blocker, Button},
+    widgets::{Blocker, Button, OnKeyboardEvent},
     BuilderContext, Context, Id, RectFill,
 };
 
@@ -11,6 +14,9 @@ use crate::style::Style;
 
 pub type MenuOption<'a> = (&'a str, Box<dyn FnMut(&mut Context)>);
 
+/// Create a menu with the given options, and return its Id. When the menu has the keyboard focus,
+/// the options can be selected with Up/Down (or Tab) and activated with Enter or Space, and Escape
+/// closes it.
 pub fn create_menu(
     options: Vec<MenuOption>,
     on_close: impl Fn(&mut Context) + 'static,
@@ -81,31 +87,79 @@ pub fn create_menu(
         .graphic(style.blocker.clone())
         .build(ctx);
 
+    let buttons: Rc<[Id]> = options.iter().map(|_| ctx.reserve()).collect();
+    let mut functions = Vec::with_capacity(options.len());
+    let mut texts = Vec::with_capacity(options.len());
+    for (text, function) in options {
+        texts.push(text);
+        functions.push(function);
+    }
+
+    let functions = RefCell::new(functions);
+    let close_ = close.clone();
+    let activate = Rc::new(move |i: usize, ctx: &mut Context| {
+        // make sure to only call the function if `close` is taken, to avoid activating a option
+        // while the menu is closing.
+        if let Some(close) = close_.take() {
+            (functions.borrow_mut()[i])(ctx);
+            (close)(ctx);
+        }
+    });
+
+    // the option highlighted by the keyboard, if any.
+    let selected = Cell::new(None::<usize>);
+    let button_style = style.delete_button.clone();
+    let activate_ = activate.clone();
+    let buttons_ = buttons.clone();
     let _menu = ctx
         .create_control_reserved(menu)
         .parent(Id::ROOT_ID)
         .layout(VBoxLayout::new(1.0, [0.0, 10.0, 0.0, 10.0], -1))
         .graphic(style.split_background.clone())
+        .behaviour(OnKeyboardEvent::new(move |event, _, ctx| {
+            use giui::KeyboardEvent::*;
+            use winit::event::VirtualKeyCode::*;
+            let len = buttons_.len();
+            let next = match (event, selected.get()) {
+                (Pressed(Down | Tab), None) => 0,
+                (Pressed(Down | Tab), Some(i)) => (i + 1) % len.max(1),
+                (Pressed(Up), None) => len.saturating_sub(1),
+                (Pressed(Up), Some(i)) => (i + len - 1) % len.max(1),
+                (Pressed(Return | NumpadEnter | Space), Some(i)) => {
+                    activate_(i, ctx);
+                    return true;
+                }
+                (Pressed(Escape), _) => {
+                    if let Some(x) = close.take() {
+                        x(ctx)
+                    }
+                    return true;
+                }
+                _ => return false,
+            };
+            if len == 0 {
+                return true;
+            }
+            if let Some(i) = selected.get() {
+                ctx.set_graphic(buttons_[i], button_style.normal.clone());
+            }
+            ctx.set_graphic(buttons_[next], button_style.focus.clone());
+            selected.set(Some(next));
+            true
+        }))
         .fill_y(RectFill::ShrinkEnd)
         .fill_x(RectFill::ShrinkCenter)
         .build(ctx);
 
-    for (text, mut function) in options {
-        let close = close.clone();
-        ctx.create_control()
+    for (i, text) in texts.into_iter().enumerate() {
+        let activate = activate.clone();
+        ctx.create_control_reserved(buttons[i])
             .parent(menu)
             .layout(MarginLayout::new([10.0, 10.0, 10.0, 10.0]))
             .behaviour(Button::new(
                 style.delete_button.clone(),
                 false,
-                move |_, ctx| {
-                    // make sure to only call `function` if `close` is taken, to avoid clicking on
-                    // the button while the menu is closing.
-                    if let Some(close) = close.take() {
-                        function(ctx);
-                        (close)(ctx);
-                    }
-                },
+                move |_, ctx| activate(i, ctx),
             ))
             .child(ctx, |cb, _| {
                 cb.graphic(Text::new(
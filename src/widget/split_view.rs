@@ -1,3 +1,5 @@
+use std::{cell::Cell, rc::Rc};
+
 use giui::{Behaviour, Id, InputFlags, Layout, LayoutContext, MinSizeContext, MouseEvent};
 use winit::window::CursorIcon;
 
@@ -12,6 +14,10 @@ pub struct SplitView {
     right_min_size: f32,
     dragging: bool,
     dragging_delta: f32,
+    /// A copy of `split` that can be read and changed from outside. See `SplitView::shared`.
+    shared: Option<Rc<Cell<f32>>>,
+    /// Called with the new split when the user stops dragging it.
+    on_change: Option<Box<dyn Fn(f32, &mut giui::Context)>>,
 }
 impl SplitView {
     pub fn new(split: f32, spacing: f32, margins: [f32; 4], vertical: bool) -> Self {
@@ -24,9 +30,25 @@ impl SplitView {
             right_min_size: 0.0,
             dragging: false,
             dragging_delta: 0.0,
+            shared: None,
+            on_change: None,
         }
     }
 
+    /// Call `on_change` with the new split each time the user finishes dragging it.
+    pub fn on_change(mut self, on_change: impl Fn(f32, &mut giui::Context) + 'static) -> Self {
+        self.on_change = Some(Box::new(on_change));
+        self
+    }
+
+    /// Start with the split in `split`, and keep it updated when the split is dragged. A change to
+    /// it is applied in the next layout of the control.
+    pub fn shared(mut self, split: Rc<Cell<f32>>) -> Self {
+        self.split = split.get();
+        self.shared = Some(split);
+        self
+    }
+
     fn free_space(&self, rect: [f32; 4]) -> f32 {
         if self.vertical {
             rect[3] - rect[1] - self.left_min_size - self.right_min_size - self.spacing
@@ -91,6 +113,9 @@ impl Layout for SplitView {
     }
 
     fn update_layouts(&mut self, this: Id, ctx: &mut LayoutContext) {
+        if let Some(shared) = &self.shared {
+            self.split = shared.get();
+        }
         let children = ctx.get_active_children(this);
         if children.is_empty() {
             return;
@@ -154,6 +179,11 @@ impl Behaviour for SplitView {
                 }
             }
             MouseEvent::Up(giui::MouseButton::Left) => {
+                if self.dragging {
+                    if let Some(on_change) = &self.on_change {
+                        on_change(self.split, ctx);
+                    }
+                }
                 self.dragging = false;
                 ctx.lock_cursor(false, mouse.id);
             }
@@ -173,6 +203,9 @@ impl Behaviour for SplitView {
                     let split = self.dragging_delta + self.mouse_to_split_pos(mouse.pos, rect);
 
                     self.split = split.clamp(0.0, 1.0);
+                    if let Some(shared) = &self.shared {
+                        shared.set(self.split);
+                    }
                     ctx.dirty_layout(this);
                 }
             }